@@ -6,9 +6,10 @@ use anyhow::{Context, Result, bail};
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 
+use crate::activation_stack;
 use crate::config::BulkerConfig;
 use crate::imports;
-use crate::manifest::CrateVars;
+use crate::manifest::{CommandsFilter, CrateVars};
 use crate::shimlink;
 
 /// Result of building a new PATH: the full PATH string and the shimdir path.
@@ -17,44 +18,674 @@ pub struct ActivationResult {
     pub path: String,
     /// The shimlink directory path (for cleanup on deactivation).
     pub shimdir: String,
+    /// Imports that could not be fetched; their commands are not available.
+    pub missing_imports: Vec<String>,
+    /// True for a `shared_shimdir`, which outlives the activation and must not
+    /// be removed on deactivation.
+    pub shared: bool,
+}
+
+/// Exit status of `activate --echo/--format/--write-rc` when the activation
+/// was printed but some imports could not be fetched.
+pub(crate) const EXIT_PARTIAL_ACTIVATION: i32 = 3;
+
+/// Shimdirs hold only symlinks and tiny scripts; require at least this much free space.
+const MIN_SHIMDIR_FREE_BYTES: u64 = 1024 * 1024;
+
+/// Bytes available to unprivileged users on a filesystem.
+// statvfs field types differ across platforms (u32 on macOS, u64 on Linux).
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn available_bytes(stat: &nix::sys::statvfs::Statvfs) -> u64 {
+    stat.blocks_available() as u64 * stat.fragment_size() as u64
+}
+
+/// Why `dir` cannot host shimdirs (noexec, read-only, full), or None if it can.
+pub(crate) fn shimdir_base_problem(dir: &Path) -> Option<String> {
+    use nix::sys::statvfs::{FsFlags, statvfs};
+    let stat = match statvfs(dir) {
+        Ok(s) => s,
+        Err(e) => return Some(format!("cannot stat: {}", e)),
+    };
+    if stat.flags().contains(FsFlags::ST_NOEXEC) {
+        return Some("filesystem is mounted noexec".to_string());
+    }
+    if stat.flags().contains(FsFlags::ST_RDONLY) {
+        return Some("filesystem is read-only".to_string());
+    }
+    let free = available_bytes(&stat);
+    if free < MIN_SHIMDIR_FREE_BYTES {
+        return Some(format!("only {} free", crate::humanize::format_size(free)));
+    }
+    None
+}
+
+/// Directory in which shimdirs are created: the `shimdir_base` config value if
+/// set, otherwise the first usable of $XDG_RUNTIME_DIR, $TMPDIR and /tmp.
+pub(crate) fn shimdir_base(config: &BulkerConfig) -> Result<PathBuf> {
+    if let Some(ref base) = config.bulker.shimdir_base {
+        let dir = PathBuf::from(crate::config::expand_path(base));
+        crate::cache_permissions::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create shimdir_base: {}", dir.display()))?;
+        if let Some(problem) = shimdir_base_problem(&dir) {
+            bail!(
+                "shimdir_base {} is unusable: {}. Set `shimdir_base` to a writable directory on a \
+                 filesystem that allows execution.",
+                dir.display(),
+                problem
+            );
+        }
+        return Ok(dir);
+    }
+
+    let mut candidates = Vec::new();
+    for var in ["XDG_RUNTIME_DIR", "TMPDIR"] {
+        if let Some(dir) = std::env::var(var).ok().filter(|d| !d.is_empty()) {
+            candidates.push((PathBuf::from(dir), format!("${}", var)));
+        }
+    }
+    candidates.push((PathBuf::from("/tmp"), "default".to_string()));
+
+    let mut rejected = Vec::new();
+    for (dir, source) in candidates {
+        if !dir.is_dir() {
+            continue;
+        }
+        match shimdir_base_problem(&dir) {
+            None => return Ok(dir),
+            Some(problem) => {
+                log::debug!("Skipping shimdir base {} ({}): {}", dir.display(), source, problem);
+                rejected.push(format!("{} ({}): {}", dir.display(), source, problem));
+            }
+        }
+    }
+    bail!(
+        "No usable directory for shimlinks:\n  {}\nSet `shimdir_base` to a writable directory on a \
+         filesystem that allows execution, e.g.:\n  bulker config set shimdir_base=$HOME/.cache/bulker/shims",
+        rejected.join("\n  ")
+    )
+}
+
+/// Marker file identifying a directory as a bulker shared shimdir.
+pub(crate) const SHARED_SHIMDIR_MARKER: &str = ".bulker-shimdir";
+
+/// Create (or reuse) the shared shimdir at `dir`. Refuses to adopt an existing
+/// non-empty directory that bulker did not create, since stale shimlinks in it
+/// are pruned on every activation.
+pub(crate) fn prepare_shared_shimdir(dir: &Path) -> Result<()> {
+    crate::cache_permissions::create_dir_all(dir)
+        .with_context(|| format!("Failed to create shared shimdir: {}", dir.display()))?;
+    if let Some(problem) = shimdir_base_problem(dir) {
+        bail!("shared shimdir {} is unusable: {}", dir.display(), problem);
+    }
+    let marker = dir.join(SHARED_SHIMDIR_MARKER);
+    if !marker.exists() {
+        let foreign = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read shared shimdir: {}", dir.display()))?
+            .flatten()
+            .any(|e| !e.file_name().to_string_lossy().starts_with('.'));
+        if foreign {
+            bail!(
+                "{} is not empty and is not a bulker shimdir. Choose an empty or new directory for --shimdir.",
+                dir.display()
+            );
+        }
+        crate::cache_permissions::write(&marker, "")
+            .with_context(|| format!("Failed to write {}", marker.display()))?;
+    }
+    Ok(())
+}
+
+/// Directory in a shared shimdir holding one file per live activation (named
+/// after its owner pid) that lists the shimlinks the activation uses.
+const SHARED_SHIMDIR_OWNERS: &str = ".owners";
+
+/// Record that `owner` uses `names` in the shared shimdir at `dir`, replacing
+/// its previous record, and drop records of owners that have exited. Returns
+/// every name still used by a live owner. Call with the shimdir lock held.
+fn update_shared_shimdir_owners(
+    dir: &Path,
+    owner: u32,
+    names: &std::collections::HashSet<String>,
+) -> Result<std::collections::HashSet<String>> {
+    let owners = dir.join(SHARED_SHIMDIR_OWNERS);
+    crate::cache_permissions::create_dir_all(&owners)
+        .with_context(|| format!("Failed to create {}", owners.display()))?;
+    let mut listing: Vec<&str> = names.iter().map(String::as_str).collect();
+    listing.sort_unstable();
+    let record = owners.join(owner.to_string());
+    crate::cache_permissions::write(&record, listing.join("\n"))
+        .with_context(|| format!("Failed to write {}", record.display()))?;
+
+    let mut live = std::collections::HashSet::new();
+    for entry in std::fs::read_dir(&owners)?.flatten() {
+        let pid = entry.file_name().to_string_lossy().parse::<i32>().ok();
+        if !pid.is_some_and(crate::containers::pid_alive) {
+            log::debug!("Dropping shared shimdir owner record {}", entry.path().display());
+            let _ = std::fs::remove_file(entry.path());
+            continue;
+        }
+        let content = std::fs::read_to_string(entry.path()).unwrap_or_default();
+        live.extend(content.lines().filter(|l| !l.is_empty()).map(String::from));
+    }
+    Ok(live)
+}
+
+/// Remove shimlinks in a shared shimdir that no live activation uses.
+fn prune_shared_shimdir(dir: &Path, keep: &std::collections::HashSet<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || keep.contains(&name) {
+            continue;
+        }
+        if entry.file_type().map(|t| t.is_symlink()).unwrap_or(false) || is_host_command_script(&entry.path()) {
+            log::debug!("Pruning stale shimlink {}", entry.path().display());
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// ─── engine health ───────────────────────────────────────────────────────────
+
+/// Set to skip the engine health check (e.g. for crates with only host commands).
+pub(crate) const SKIP_ENGINE_CHECK_ENV: &str = "BULKER_SKIP_ENGINE_CHECK";
+
+/// How long the engine gets to answer `info`/`--version`. A hung daemon
+/// socket otherwise blocks activation indefinitely.
+const ENGINE_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long a passed engine check is trusted within one session, so
+/// back-to-back `bulker exec` runs don't each wait for `<engine> info`.
+const ENGINE_CHECK_TTL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Stamp recording a passed engine check, keyed by the engine setup and the
+/// session id of this process; its mtime is the check time.
+fn engine_check_stamp(config: &BulkerConfig) -> PathBuf {
+    let session = nix::unistd::getsid(None).map(|sid| sid.as_raw()).unwrap_or(0);
+    let key = format!(
+        "{}\0{}\0{}\0{}",
+        config.bulker.container_engine,
+        config.engine_path(),
+        config.bulker.apptainer_image_folder.as_deref().unwrap_or(""),
+        session
+    );
+    crate::manifest_cache::cache_root_dir().join("engine-checks").join(crate::digest::sha512t24u(key))
+}
+
+/// Run `cmd` to completion, or kill it after `timeout`. `None` on timeout.
+/// Output is drained on reader threads while waiting, so a child writing
+/// more than a pipe buffer doesn't block before it exits.
+fn output_with_timeout(cmd: &mut std::process::Command, timeout: std::time::Duration) -> std::io::Result<Option<std::process::Output>> {
+    use std::io::Read;
+    use std::process::Stdio;
+    fn drain(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    }
+    let mut child = cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let start = std::time::Instant::now();
+    let status = loop {
+        match child.try_wait()? {
+            Some(status) => break status,
+            None if start.elapsed() < timeout => std::thread::sleep(std::time::Duration::from_millis(20)),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+        }
+    };
+    Ok(Some(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+/// What to try when the engine's health probe fails.
+fn engine_guidance(engine: &str) -> &'static str {
+    match engine {
+        "docker" => "Is the Docker daemon running? Start it (`sudo systemctl start docker`, or open Docker Desktop) \
+                     and make sure your user may use it (member of the `docker` group).",
+        "podman" => "Check `podman info` directly; rootless podman may need `podman system migrate` after an upgrade.",
+        _ => "Check that the engine works outside bulker.",
+    }
+}
+
+/// Verify the configured engine responds before an activation starts
+/// fetching crates: `<engine> info` for docker-compatible engines,
+/// `apptainer --version` plus a writable `apptainer_image_folder` for
+/// apptainer. Fails with specific guidance instead of leaving the first tool
+/// call to discover a dead daemon. A pass is reused for `ENGINE_CHECK_TTL`
+/// within the same session.
+pub(crate) fn check_engine_health(config: &BulkerConfig) -> Result<()> {
+    if std::env::var_os(SKIP_ENGINE_CHECK_ENV).is_some() {
+        return Ok(());
+    }
+    let stamp = engine_check_stamp(config);
+    let age = std::fs::metadata(&stamp).and_then(|m| m.modified()).ok().and_then(|t| t.elapsed().ok());
+    if age.is_some_and(|age| age < ENGINE_CHECK_TTL) {
+        log::debug!("Engine check passed recently in this session; skipping");
+        return Ok(());
+    }
+    let engine = &config.bulker.container_engine;
+    let engine_path = config.engine_path();
+    let _span = crate::timings::span("engine_check");
+    let probe = if config.is_apptainer() { vec!["--version"] } else { vec!["info", "--format", "{{.ServerVersion}}"] };
+    let output = match output_with_timeout(std::process::Command::new(engine_path).args(&probe), ENGINE_PROBE_TIMEOUT) {
+        Ok(Some(output)) => output,
+        Ok(None) => bail!(
+            "Container engine '{}' did not respond within {}s (`{} {}`). {}",
+            engine, ENGINE_PROBE_TIMEOUT.as_secs(), engine_path, probe.join(" "), engine_guidance(engine)
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => bail!(
+            "Container engine '{}' not found ({}). Install it, point `engine_path` at it, or pick another \
+             engine with `bulker config set container_engine=...`.",
+            engine, engine_path
+        ),
+        Err(e) => return Err(e).with_context(|| format!("Failed to run {}", engine_path)),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("no error output");
+        bail!("Container engine '{}' is not working: {}. {}", engine, reason, engine_guidance(engine));
+    }
+
+    if let Some(folder) = config.bulker.apptainer_image_folder.as_ref().filter(|_| config.is_apptainer()) {
+        let folder = PathBuf::from(crate::config::expand_path(folder));
+        std::fs::create_dir_all(&folder)
+            .and_then(|_| tempfile::tempfile_in(&folder))
+            .with_context(|| format!(
+                "apptainer_image_folder {} is not writable, so images cannot be pulled. \
+                 Set `apptainer_image_folder` to a writable directory.",
+                folder.display()
+            ))?;
+    }
+    let written = stamp
+        .parent()
+        .map(crate::cache_permissions::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| crate::cache_permissions::write(&stamp, ""));
+    if let Err(e) = written {
+        log::debug!("Could not record engine check at {}: {}", stamp.display(), e);
+    }
+    Ok(())
+}
+
+// ─── shadowed host tools ─────────────────────────────────────────────────────
+
+/// How long to wait for a host tool's `--version` before giving up.
+const VERSION_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// First dotted version number in a string (e.g. "samtools 1.19.2" -> [1, 19, 2]).
+pub(crate) fn extract_version(s: &str) -> Option<Vec<u64>> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_digit() && (i == 0 || bytes[i - 1] == b'v' || !bytes[i - 1].is_ascii_alphanumeric()) {
+            let end = s[i..]
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .map(|n| i + n)
+                .unwrap_or(s.len());
+            let parts: Vec<u64> = s[i..end]
+                .split('.')
+                .take_while(|p| !p.is_empty())
+                .filter_map(|p| p.parse().ok())
+                .collect();
+            if parts.len() >= 2 {
+                return Some(parts);
+            }
+            i = end;
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Version a command's image provides, taken from the image tag
+/// (e.g. `quay.io/biocontainers/samtools:1.9--h91753b0_8` -> [1, 9]).
+fn image_tag_version(image: &str) -> Option<Vec<u64>> {
+    let (_, tag, _) = crate::manifest::split_image_tag_digest(image);
+    extract_version(tag?)
+}
+
+/// True if two versions differ in their major or minor component.
+pub(crate) fn versions_differ_significantly(a: &[u64], b: &[u64]) -> bool {
+    let key = |v: &[u64]| (v.first().copied().unwrap_or(0), v.get(1).copied().unwrap_or(0));
+    key(a) != key(b)
+}
+
+fn format_version(v: &[u64]) -> String {
+    v.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// Find a host executable named `name` on `path`, skipping bulker shimlinks.
+fn find_host_tool(name: &str, path: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let bulker = std::env::current_exe().ok().and_then(|p| std::fs::canonicalize(p).ok());
+    path.split(':').filter(|d| !d.is_empty()).find_map(|dir| {
+        let candidate = Path::new(dir).join(name);
+        let meta = std::fs::metadata(&candidate).ok()?;
+        if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+            return None;
+        }
+        if bulker.is_some() && std::fs::canonicalize(&candidate).ok() == bulker {
+            return None;
+        }
+        Some(candidate)
+    })
+}
+
+/// Run `<tool> --version` (bounded by a timeout) and extract its version.
+fn probe_host_version(tool: &Path) -> Option<Vec<u64>> {
+    let Some(output) = output_with_timeout(std::process::Command::new(tool).arg("--version"), VERSION_PROBE_TIMEOUT).ok()? else {
+        log::debug!("Version probe of {} timed out", tool.display());
+        return None;
+    };
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    extract_version(&text)
+}
+
+/// Warning for a crate whose images were pulled with engine `built` when the
+/// configured engine is `current`. Only a switch between apptainer and an
+/// OCI engine matters: docker and podman pull what they lack on first use,
+/// but SIF files and OCI image stores don't stand in for each other.
+fn engine_mismatch_message(crate_name: &str, built: &str, current: &str) -> Option<String> {
+    let is_apptainer = |engine: &str| matches!(engine, "apptainer" | "singularity");
+    if is_apptainer(built) == is_apptainer(current) {
+        return None;
+    }
+    Some(format!(
+        "Images of '{}' were pulled with {}, but the configured engine is {}; its commands may fail \
+         or stall on first use. Run `bulker crate install --images-only {}` to pull them for {}.",
+        crate_name, built, current, crate_name, current
+    ))
+}
+
+/// Sidecar holding the digest of the `activation_message` last shown for a crate.
+const ACTIVATION_MESSAGE_SIDECAR: &str = "activation-message-shown";
+
+/// `activation_message`s of the crates (imports included) that have not been
+/// shown yet, or changed since, as (crate, message). Marks them as shown.
+fn unseen_activation_messages(config: &BulkerConfig, cratelist: &[CrateVars]) -> Result<Vec<(String, String)>> {
+    let (all, _missing) = imports::resolve_cratevars_allow_missing_imports(config, cratelist)?;
+    let mut unseen = Vec::new();
+    for cv in &all {
+        let Some(message) = crate::manifest_cache::load_cached(cv)?.and_then(|m| m.manifest.activation_message) else {
+            continue;
+        };
+        let digest = crate::digest::sha512t24u(&message);
+        if crate::manifest_cache::read_digest_sidecar(cv, ACTIVATION_MESSAGE_SIDECAR).as_deref() == Some(digest.as_str()) {
+            continue;
+        }
+        if let Err(e) = crate::manifest_cache::write_digest_sidecar(cv, ACTIVATION_MESSAGE_SIDECAR, &digest) {
+            log::debug!("{:#}", e);
+        }
+        unseen.push((cv.display_name(), message));
+    }
+    Ok(unseen)
+}
+
+/// Warn when a crate command shadows a host tool whose version differs in
+/// major or minor version (enabled with `warn_on_shadow: true`).
+fn warn_on_shadowed_tools(manifests: &[crate::manifest::Manifest], host_path: &str) {
+    for pkg in manifests.iter().flat_map(|m| &m.manifest.commands) {
+        let Some(crate_version) = image_tag_version(&pkg.docker_image) else {
+            continue;
+        };
+        let Some(host_tool) = find_host_tool(&pkg.command, host_path) else {
+            continue;
+        };
+        let Some(host_version) = probe_host_version(&host_tool) else {
+            continue;
+        };
+        if versions_differ_significantly(&crate_version, &host_version) {
+            let downgrade = if crate_version < host_version { " (older than the host version)" } else { "" };
+            log::warn!(
+                "'{}' {} from {} shadows host {} {}{}",
+                pkg.command,
+                format_version(&crate_version),
+                pkg.docker_image,
+                host_tool.display(),
+                format_version(&host_version),
+                downgrade
+            );
+        }
+    }
+}
+
+/// Start of the scripts strict shimdirs get for host commands, which tells
+/// them apart from the host binaries they run.
+const HOST_COMMAND_SCRIPT_HEADER: &str = "#!/bin/sh\n# bulker host command\n";
+
+fn is_host_command_script(path: &Path) -> bool {
+    use std::io::Read;
+    let mut head = [0u8; HOST_COMMAND_SCRIPT_HEADER.len()];
+    std::fs::File::open(path).and_then(|mut f| f.read_exact(&mut head)).is_ok()
+        && head == HOST_COMMAND_SCRIPT_HEADER.as_bytes()
+}
+
+/// Host commands of an activation: the union of `host_commands` across the
+/// crates and their imports, in crate order, then the config's
+/// `host_commands`. A name that is also a crate command is left out; the
+/// crate command is the one on PATH.
+pub(crate) fn effective_host_commands(config: &BulkerConfig, manifests: &[crate::manifest::Manifest]) -> Vec<String> {
+    let crate_commands: std::collections::HashSet<&str> = manifests
+        .iter()
+        .flat_map(|m| &m.manifest.commands)
+        .map(|pkg| pkg.command.as_str())
+        .collect();
+    let mut seen = std::collections::HashSet::new();
+    manifests
+        .iter()
+        .flat_map(|m| &m.manifest.host_commands)
+        .chain(&config.bulker.host_commands)
+        .filter(|name| !crate_commands.contains(name.as_str()) && seen.insert(name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Give a strict shimdir a script per host command that runs the binary
+/// found on `host_path` through `bulker host-exec`. The binary keeps the
+/// path it was found under, so a venv's `python3` still finds its venv
+/// (a symlink would be resolved past it). Returns the commands not found.
+fn write_host_command_scripts(shimdir: &Path, host_commands: &[String], host_path: &str) -> Result<Vec<String>> {
+    use std::os::unix::fs::PermissionsExt;
+    let bulker = std::env::current_exe().context("Failed to determine bulker binary path")?;
+    let mut missing = Vec::new();
+    for name in host_commands {
+        // Skip the scripts of an enclosing strict activation
+        let search_path: Vec<&str> = host_path
+            .split(':')
+            .filter(|dir| !is_host_command_script(&Path::new(dir).join(name)))
+            .collect();
+        let Some(binary) = find_host_tool(name, &search_path.join(":")) else {
+            missing.push(name.clone());
+            continue;
+        };
+        let script = format!(
+            "{}exec {} host-exec {} \"$@\"\n",
+            HOST_COMMAND_SCRIPT_HEADER,
+            shell_escape::escape(bulker.to_string_lossy()),
+            shell_escape::escape(binary.to_string_lossy())
+        );
+        let path = shimdir.join(name);
+        // Replaces whatever an earlier activation of a shared shimdir left
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(&path, script).with_context(|| format!("Failed to write {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        crate::cache_permissions::fix(&path);
+    }
+    Ok(missing)
+}
+
+/// Commands an activation links without `--commands-filter` before it warns.
+/// Full biocontainers dumps have many more, and linking them all makes
+/// activation slow and the shimdir enormous.
+pub(crate) const MAX_ACTIVATION_COMMANDS: usize = 5_000;
+
+/// Check the commands an activation is about to link: a filter has to
+/// match something, and without one a huge crate gets a warning.
+fn check_linked_commands(manifests: &[crate::manifest::Manifest], cratelist: &[CrateVars], filter: &CommandsFilter) -> Result<()> {
+    let names: std::collections::HashSet<&str> = manifests
+        .iter()
+        .flat_map(|m| &m.manifest.commands)
+        .map(|pkg| pkg.command.as_str())
+        .collect();
+    let crates = cratelist.iter().map(|cv| cv.display_name()).collect::<Vec<_>>().join(", ");
+    if !filter.is_empty() && names.is_empty() {
+        bail!("--commands-filter '{}' matches no command of {} or its imports", filter, crates);
+    }
+    if filter.is_empty() && names.len() > MAX_ACTIVATION_COMMANDS {
+        log::warn!(
+            "{} and its imports have {} commands; linking them all may be slow. \
+             Pick the tools you need with --commands-filter (e.g. --commands-filter 'samtools,bcf*').",
+            crates,
+            names.len()
+        );
+    }
+    Ok(())
 }
 
 /// Build the new PATH using shimlink directories.
 /// Creates a temp directory with symlinks to the bulker binary for each command,
 /// then returns the PATH string with the shimlink dir prepended.
-/// Auto-fetches manifests from the registry if not cached locally.
-pub fn get_new_path(config: &BulkerConfig, cratelist: &[CrateVars], strict: bool, force: bool) -> Result<ActivationResult> {
+/// Auto-fetches manifests from the registry if not cached locally. Only the
+/// commands `commands_filter` matches are linked.
+pub fn get_new_path(
+    config: &BulkerConfig,
+    cratelist: &[CrateVars],
+    commands_filter: &CommandsFilter,
+    strict: bool,
+    force: bool,
+) -> Result<ActivationResult> {
     // Each activation gets its own shimdir. Sharing a shimdir between shells
-    // is a correctness bug: re-activation nukes a live shell's PATH.
-    let shimdir = tempfile::Builder::new()
-        .prefix("bulker_")
-        .tempdir()
-        .context("Failed to create shimlink temp directory")?
-        .keep();
-
-    // Auto-fetch: ensure all manifests (and their imports) are cached
+    // is a correctness bug: re-activation nukes a live shell's PATH. The
+    // exception is an explicit `shared_shimdir`, which is updated in place
+    // (entry by entry, under a lock) so live shells keep working.
+    let shimdir_span = crate::timings::span("shimdir_create");
+    let shared = config.bulker.shared_shimdir.is_some();
+    let shimdir = match config.bulker.shared_shimdir {
+        Some(ref dir) => {
+            let dir = PathBuf::from(crate::config::expand_path(dir));
+            prepare_shared_shimdir(&dir)?;
+            dir
+        }
+        None => {
+            let base = shimdir_base(config)?;
+            tempfile::Builder::new()
+                .prefix("bulker_")
+                .tempdir_in(&base)
+                .with_context(|| format!(
+                    "Failed to create shimlink directory in {}. Set `shimdir_base` to a writable directory \
+                     (e.g. `bulker config set shimdir_base=$HOME/.cache/bulker/shims`).",
+                    base.display()
+                ))?
+                .keep()
+        }
+    };
+    crate::cache_permissions::fix(&shimdir);
+    drop(shimdir_span);
+
+    // Auto-fetch: ensure all manifests (and their imports) are cached. The
+    // requested crates must be fetched; a failing import only drops its commands.
+    let fetch_span = crate::timings::span("import_resolution");
     for cv in cratelist {
-        let mut visited = std::collections::HashSet::new();
-        crate::manifest_cache::ensure_cached_with_imports(config, cv, force, false, &mut visited, 0)?;
+        let manifest = crate::manifest_cache::ensure_cached(config, cv, force, false)?;
+        let mut visited = std::collections::HashSet::from([cv.display_name()]);
+        for import_path in &manifest.manifest.imports {
+            let import_cv = imports::resolve_import(config, import_path)?;
+            if let Err(e) = crate::manifest_cache::ensure_cached_with_imports(config, &import_cv, force, false, &mut visited, 1) {
+                log::warn!("Failed to fetch import '{}' of '{}': {:#}", import_path, cv.display_name(), e);
+            }
+        }
     }
 
     // Resolve all crates including imports (reads from manifest cache, not config)
-    let all_cratevars = imports::resolve_cratevars_with_imports(config, cratelist)?;
+    let (all_cratevars, missing_imports) = imports::resolve_cratevars_allow_missing_imports(config, cratelist)?;
+    // Imported crates count as used too, so `crate clean --unused` keeps them
+    for cv in &all_cratevars {
+        if let Err(e) = crate::manifest_cache::touch_last_used(cv) {
+            log::debug!("Failed to record last-used time for {}: {}", cv.display_name(), e);
+        }
+    }
+    for cv in &all_cratevars {
+        let built = crate::manifest_cache::read_digest_sidecar(cv, crate::manifest_cache::BUILD_ENGINE_SIDECAR);
+        if let Some(message) = built.and_then(|b| engine_mismatch_message(&cv.display_name(), &b, &config.bulker.container_engine)) {
+            log::warn!("{}", message);
+        }
+    }
+    drop(fetch_span);
 
-    let mut has_host_commands = false;
+    let mut manifests = Vec::with_capacity(all_cratevars.len());
     for cv in &all_cratevars {
-        let manifest = shimlink::load_cached_manifest(config, cv)?;
-        if !manifest.manifest.host_commands.is_empty() {
-            has_host_commands = true;
+        let mut manifest = shimlink::load_cached_manifest(config, cv)?;
+        commands_filter.apply(&mut manifest);
+        manifests.push(manifest);
+    }
+    if let Err(e) = check_linked_commands(&manifests, cratelist, commands_filter) {
+        if !shared {
+            let _ = std::fs::remove_dir(&shimdir);
         }
-        shimlink::create_shimlink_dir(&manifest, &shimdir)?;
+        return Err(e);
+    }
+
+    let populate_span = crate::timings::span("shimdir_populate");
+    // Serialize concurrent activations of the same shared shimdir (e.g. every
+    // task of an array job activating at once)
+    let _lock = if shared {
+        let mut lock_path = shimdir.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock = crate::filelock::FileLock::acquire(Path::new(&lock_path))?;
+        crate::cache_permissions::fix(Path::new(&lock_path));
+        Some(lock)
+    } else {
+        None
+    };
+    for manifest in &manifests {
+        shimlink::create_shimlink_dir(manifest, &shimdir)?;
+    }
+    // Without the host PATH, host commands need entries in the shimdir
+    let host_commands = if strict { effective_host_commands(config, &manifests) } else { Vec::new() };
+    let missing_host = write_host_command_scripts(&shimdir, &host_commands, &std::env::var("PATH").unwrap_or_default())?;
+    if !missing_host.is_empty() {
+        log::warn!("host_commands not found on PATH, left out of the strict PATH: {}", missing_host.join(", "));
+    }
+    if shared {
+        let linked = manifests
+            .iter()
+            .flat_map(|m| &m.manifest.commands)
+            .flat_map(|pkg| [pkg.command.clone(), format!("_{}", pkg.command)])
+            .chain(host_commands.iter().cloned())
+            .collect();
+        // Owned by the shell that evals (or spawned) this activation; other
+        // live shells on the same shimdir keep their shimlinks
+        let keep = update_shared_shimdir_owners(&shimdir, std::os::unix::process::parent_id(), &linked)?;
+        prune_shared_shimdir(&shimdir, &keep)?;
+    }
+    drop(_lock);
+    drop(populate_span);
+
+    if config.bulker.warn_on_shadow && !strict {
+        warn_on_shadowed_tools(&manifests, &std::env::var("PATH").unwrap_or_default());
     }
 
     let shimdir_str = shimdir.to_string_lossy().to_string();
 
     let path = if strict {
-        if !has_host_commands {
-            eprintln!("Note: Strict mode active with no host_commands. Only crate commands are on PATH.");
+        if host_commands.is_empty() {
+            log::info!("Strict mode active with no host_commands. Only crate commands are on PATH.");
         }
         shimdir_str.clone()
     } else {
@@ -62,8 +693,333 @@ pub fn get_new_path(config: &BulkerConfig, cratelist: &[CrateVars], strict: bool
         format!("{}:{}", shimdir_str, current_path)
     };
 
-    Ok(ActivationResult { path, shimdir: shimdir_str })
+    Ok(ActivationResult { path, shimdir: shimdir_str, missing_imports, shared })
+}
+
+/// How `activate --prefetch` pulls the crate's images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Prefetch {
+    /// Pull before activating, reporting progress on stderr.
+    Foreground,
+    /// Pull in a detached process that logs to a status file.
+    Background,
+}
+
+impl Prefetch {
+    pub const NAMES: [&'static str; 2] = ["foreground", "background"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "foreground" => Some(Self::Foreground),
+            "background" => Some(Self::Background),
+            _ => None,
+        }
+    }
+}
+
+/// One command per distinct image across the crates and their imports.
+fn prefetch_commands(config: &BulkerConfig, cratelist: &[CrateVars]) -> Result<Vec<crate::manifest::PackageCommand>> {
+    let (all_cratevars, _) = imports::resolve_cratevars_allow_missing_imports(config, cratelist)?;
+    let mut seen = std::collections::HashSet::new();
+    let mut commands = Vec::new();
+    for cv in &all_cratevars {
+        let manifest = shimlink::load_cached_manifest(config, cv)?;
+        for pkg in manifest.manifest.commands {
+            if !pkg.docker_image.is_empty() && seen.insert(pkg.docker_image.clone()) {
+                commands.push(pkg);
+            }
+        }
+    }
+    Ok(commands)
+}
+
+/// Pull the images of all commands in the (cached) crates and their imports,
+/// one at a time with a progress line each. Failures are reported at the end.
+pub(crate) fn prefetch_images(config: &BulkerConfig, cratelist: &[CrateVars]) -> Result<()> {
+    let commands = prefetch_commands(config, cratelist)?;
+    let start = std::time::Instant::now();
+    let mut failed = Vec::new();
+    for (i, pkg) in commands.iter().enumerate() {
+        eprintln!("bulker: prefetching [{}/{}] {}", i + 1, commands.len(), pkg.docker_image);
+        let manifest = crate::manifest::Manifest {
+            manifest: crate::manifest::ManifestInner {
+                commands: vec![pkg.clone()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        if let Err(e) = crate::manifest_cache::pull_images(config, &manifest) {
+            log::warn!("Failed to prefetch {}: {:#}", pkg.docker_image, e);
+            failed.push(pkg.docker_image.clone());
+        }
+    }
+    if !failed.is_empty() {
+        bail!("Failed to prefetch: {}", failed.join(", "));
+    }
+    eprintln!(
+        "bulker: prefetched {} image(s) in {}",
+        commands.len(),
+        crate::humanize::format_duration(start.elapsed())
+    );
+    Ok(())
+}
+
+/// Status file for a background prefetch of `cratelist`.
+fn prefetch_status_path(cratelist: &[CrateVars]) -> PathBuf {
+    let id: String = cratelist
+        .iter()
+        .map(|cv| cv.display_name())
+        .collect::<Vec<_>>()
+        .join(",")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    crate::manifest_cache::cache_root_dir().join("prefetch").join(format!("{}.log", id))
+}
+
+/// Start `bulker prefetch` in a new session, detached from this terminal, with
+/// its output going to a status file. Returns the status file path.
+pub(crate) fn spawn_background_prefetch(config_path: Option<&Path>, cratelist: &[CrateVars]) -> Result<PathBuf> {
+    let status_path = prefetch_status_path(cratelist);
+    if let Some(parent) = status_path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let log = std::fs::File::create(&status_path)
+        .with_context(|| format!("Failed to create prefetch status file {}", status_path.display()))?;
+    let exe = std::env::current_exe().context("Failed to locate the bulker binary")?;
+    let mut cmd = std::process::Command::new(exe);
+    cmd.arg("prefetch");
+    if let Some(p) = config_path {
+        cmd.arg("-c").arg(p);
+    }
+    cmd.arg(cratelist.iter().map(|cv| cv.display_name()).collect::<Vec<_>>().join(","))
+        .stdin(std::process::Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            nix::unistd::setsid().map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+            Ok(())
+        });
+    }
+    cmd.spawn().context("Failed to start background prefetch")?;
+    Ok(status_path)
+}
+
+// ─── engine pre-warm ─────────────────────────────────────────────────────────
+
+/// Engine command that starts a throwaway container of `image` and exits at
+/// once, never pulling: docker-compatible engines run `true` as entrypoint
+/// (an image without it still wakes the daemon and sets up networking),
+/// apptainer execs `true` in the SIF. None when apptainer has no SIF yet.
+pub(crate) fn prewarm_command(config: &BulkerConfig, image: &str) -> Option<Vec<String>> {
+    let engine_path = config.engine_path().to_string();
+    if config.is_apptainer() {
+        let (_, fullpath) = crate::manifest::apptainer_image_paths(image, config.bulker.apptainer_image_folder.as_deref());
+        let sif = crate::config::expand_path(&fullpath);
+        if !Path::new(&sif).exists() {
+            return None;
+        }
+        return Some(vec![engine_path, "exec".to_string(), sif, "true".to_string()]);
+    }
+    Some(
+        [engine_path.as_str(), "run", "--rm", "--pull=never", "--entrypoint", "true", image]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+    )
+}
+
+/// With `prewarm: true`, start a throwaway container of the crate's first
+/// image in a detached session, so the engine has woken up (daemon, network
+/// setup, SIF in the page cache) by the first real tool call. Activation
+/// doesn't wait for it.
+pub(crate) fn spawn_prewarm(config: &BulkerConfig, cratelist: &[CrateVars]) -> Result<()> {
+    let commands = prefetch_commands(config, cratelist)?;
+    let Some(argv) = commands.first().and_then(|pkg| prewarm_command(config, &pkg.docker_image)) else {
+        log::debug!("Nothing to pre-warm");
+        return Ok(());
+    };
+    let mut cmd = std::process::Command::new(&argv[0]);
+    cmd.args(&argv[1..])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // Double fork: the spawned child exits at once and the pre-warm runs in
+    // its own session as an orphan, reparented to init, so nothing has to
+    // wait for it (an interactive activation execs its shell).
+    // SAFETY: fork, setsid and _exit are async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            if let nix::unistd::ForkResult::Parent { .. } = nix::unistd::fork()? {
+                nix::libc::_exit(0);
+            }
+            nix::unistd::setsid()?;
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn().with_context(|| format!("Failed to start pre-warm container ({})", argv[0]))?;
+    log::debug!("Pre-warming: {}", argv.join(" "));
+    let _ = child.wait();
+    Ok(())
+}
+
+/// Output format for printed (non-interactive) activation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ActivationFormat {
+    /// POSIX `export` statements, for `eval` in bash/zsh (`--echo`).
+    Shell,
+    /// Environment Modules / Lmod TCL modulefile.
+    Modulefile,
+    /// direnv `.envrc`.
+    Envrc,
+    /// Shell commands appending to `$GITHUB_ENV` and `$GITHUB_PATH`.
+    GithubEnv,
+    /// Nushell record for `from nuon | load-env` (`--echo --shell nu`).
+    Nu,
+    /// Xonsh assignments for `execx` (`--echo --shell xonsh`).
+    Xonsh,
+}
+
+impl ActivationFormat {
+    pub const NAMES: [&'static str; 4] = ["shell", "modulefile", "envrc", "github-env"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shell" => Some(Self::Shell),
+            "modulefile" => Some(Self::Modulefile),
+            "envrc" => Some(Self::Envrc),
+            "github-env" => Some(Self::GithubEnv),
+            _ => None,
+        }
+    }
+
+    /// Shells `--echo --shell` can print for.
+    pub const SHELL_NAMES: [&'static str; 4] = ["bash", "zsh", "nu", "xonsh"];
+
+    pub fn for_shell(name: &str) -> Option<Self> {
+        match name {
+            "bash" | "zsh" => Some(Self::Shell),
+            "nu" => Some(Self::Nu),
+            "xonsh" => Some(Self::Xonsh),
+            _ => None,
+        }
+    }
+}
+
+/// Render activation variables for an integration target other than an
+/// interactive shell. `vars` are exported as-is; the shimdir is prepended to
+/// PATH (or replaces it in strict mode).
+pub(crate) fn render_activation(
+    format: ActivationFormat,
+    vars: &[(&str, String)],
+    shimdir: &str,
+    path: &str,
+    strict: bool,
+) -> Result<String> {
+    let mut out = String::new();
+    match format {
+        ActivationFormat::Shell => {
+            for (k, v) in vars {
+                out.push_str(&format!("export {}=\"{}\"\n", k, v));
+            }
+            out.push_str(&format!("export PATH=\"{}\"\n", path));
+        }
+        ActivationFormat::Modulefile => {
+            out.push_str("#%Module1.0\n");
+            for (k, v) in vars {
+                out.push_str(&format!("setenv {} \"{}\"\n", k, v));
+            }
+            if strict {
+                out.push_str(&format!("setenv PATH \"{}\"\n", path));
+            } else {
+                out.push_str(&format!("prepend-path PATH \"{}\"\n", shimdir));
+            }
+        }
+        ActivationFormat::Envrc => {
+            for (k, v) in vars {
+                out.push_str(&format!("export {}=\"{}\"\n", k, v));
+            }
+            if strict {
+                out.push_str(&format!("export PATH=\"{}\"\n", path));
+            } else {
+                out.push_str(&format!("PATH_add \"{}\"\n", shimdir));
+            }
+        }
+        ActivationFormat::GithubEnv => {
+            if strict {
+                bail!("Strict mode cannot be expressed with GITHUB_PATH, which only prepends to PATH.");
+            }
+            for (k, v) in vars {
+                out.push_str(&format!("echo \"{}={}\" >> \"$GITHUB_ENV\"\n", k, v));
+            }
+            out.push_str(&format!("echo \"{}\" >> \"$GITHUB_PATH\"\n", shimdir));
+        }
+        ActivationFormat::Nu => {
+            // JSON strings are valid NUON; PATH is a list in nushell
+            out.push_str("{\n");
+            for (k, v) in vars {
+                out.push_str(&format!("  {}: {},\n", k, serde_json::to_string(v)?));
+            }
+            let entries: Vec<&str> = path.split(':').filter(|p| !p.is_empty()).collect();
+            out.push_str(&format!("  PATH: {}\n}}\n", serde_json::to_string(&entries)?));
+        }
+        ActivationFormat::Xonsh => {
+            // JSON strings are valid Python string literals
+            for (k, v) in vars {
+                out.push_str(&format!("${} = {}\n", k, serde_json::to_string(v)?));
+            }
+            if strict {
+                let entries: Vec<&str> = path.split(':').filter(|p| !p.is_empty()).collect();
+                out.push_str(&format!("$PATH = {}\n", serde_json::to_string(&entries)?));
+            } else {
+                out.push_str(&format!("$PATH.insert(0, {})\n", serde_json::to_string(shimdir)?));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Wrap shell activation exports into a file meant to be `source`d. Unlike
+/// `--echo` output, the file may be sourced long after it was written, so the
+/// original PATH and prompt are captured at source time. Uses the same
+/// session variables as the init-shell function, so `bulker deactivate` works.
+fn render_rc_file(exports: &str, crate_id: &str, prompt: bool) -> String {
+    let mut out = format!("# bulker activation for {}\n# Load with: source <this file>\n", crate_id);
+    out.push_str("if [ -n \"$BULKERCRATE\" ]; then\n");
+    out.push_str("  echo \"bulker: already activated ($BULKERCRATE). Run 'bulker deactivate' first.\" >&2\n");
+    out.push_str("  return 1 2>/dev/null || exit 1\n");
+    out.push_str("fi\n");
+    out.push_str("export BULKER_ORIG_PATH=\"$PATH\"\n");
+    out.push_str(exports);
+    if prompt {
+        out.push_str("_BULKER_OLD_PS1=\"$PS1\"\n");
+        out.push_str(&format!("PS1=\"({}) $PS1\"\n", crate_id));
+    }
+    // Without the init-shell function, `bulker deactivate` needs one here
+    out.push_str(&RC_DEACTIVATE_FUNCTION.replace("@ACTIVATION_VARS@", &activation_stack::ACTIVATION_VARS.join(" ")));
+    out
+}
+
+/// `bulker deactivate` for a sourced activation file, defined unless the
+/// init-shell function (whose deactivate handles the file too) is loaded.
+/// Removes itself once it has run.
+const RC_DEACTIVATE_FUNCTION: &str = r#"if ! typeset -f bulker >/dev/null 2>&1; then
+bulker() {
+  if [ "$1" = deactivate ]; then
+    export PATH="$BULKER_ORIG_PATH"
+    [ -n "${_BULKER_OLD_PS1+x}" ] && PS1="$_BULKER_OLD_PS1"
+    [ -z "$BULKER_SHIMDIR_SHARED" ] && [ -d "$BULKER_SHIMDIR" ] && rm -rf "$BULKER_SHIMDIR"
+    unset @ACTIVATION_VARS@ BULKER_ORIG_PATH _BULKER_OLD_PS1
+    unset -f bulker
+  else
+    command bulker "$@"
+  fi
 }
+fi
+"#;
 
 /// Determine the shell type from a shell path.
 fn shell_type(shell_path: &str) -> &str {
@@ -107,31 +1063,130 @@ fn build_prompt(shell: &str, crate_name: &str, custom_prompt: Option<&str>) -> S
         .replace("%b", crate_name)
 }
 
+/// How [`activate`] activates, beyond which crates. The defaults are a plain
+/// interactive activation.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ActivateOptions {
+    /// Link only the matching commands (`--commands-filter`).
+    pub commands_filter: CommandsFilter,
+    /// Print the activation in this format instead of starting a shell.
+    pub format: Option<ActivationFormat>,
+    pub strict: bool,
+    pub host_env: bool,
+    pub hide_prompt: bool,
+    /// Re-fetch manifests even if cached.
+    pub force: bool,
+    pub timings: bool,
+    /// Write the (shell-format) activation to this file and print a `source` line.
+    pub write_rc: Option<PathBuf>,
+    pub prefetch: Option<Prefetch>,
+    /// Start the shell with the minimal startup files, skipping the user's rc.
+    pub no_rc: bool,
+    /// A saved session for `bulker attach`, written to `write_rc`.
+    pub session: bool,
+    /// Take the current activation's place instead of stacking on it.
+    pub replace: bool,
+    /// Check the crates against this lockfile and pin images from it.
+    pub lockfile: Option<PathBuf>,
+    /// Exec the user's shell with PATH already set, reading only its own
+    /// startup files: no eval and no bulker rcfile.
+    pub exec_shell: bool,
+}
+
 /// Activate a crate environment by replacing the current process with a new shell.
-pub fn activate(
+/// A `session` activation (written with `write_rc` for `bulker attach`) puts
+/// the shimdir in front of the PATH of whichever shell sources it, rather
+/// than of this one.
+///
+/// Inside an activation, an interactive or `--echo` activation is pushed onto
+/// the activation stack (see `activation_stack.rs`) on top of the current
+/// one; with `replace` it takes the current one's place instead.
+pub(crate) fn activate(
     config: &BulkerConfig,
     config_path: Option<&Path>,
     cratelist: &[CrateVars],
-    echo: bool,
-    strict: bool,
-    host_env: bool,
-    prompt: bool,
-    force: bool,
+    opts: &ActivateOptions,
 ) -> Result<()> {
-    // Guard against double activation
-    if let Ok(active) = std::env::var("BULKERCRATE") {
-        bail!("bulker: already activated ({}). Run 'bulker deactivate' first.", active);
+    let ActivateOptions { ref commands_filter, format, strict, host_env, force, timings, prefetch, no_rc, session, replace, exec_shell, .. } = *opts;
+    let prompt = !opts.hide_prompt;
+    let write_rc = opts.write_rc.as_deref();
+    let lockfile = opts.lockfile.as_deref();
+    let active = std::env::var("BULKERCRATE").ok().filter(|c| !c.is_empty());
+    let mut stack = activation_stack::read()?;
+    // The activation this one sits on: pushed, or what a replaced one sat on
+    let mut below = None;
+    let mut replaced_shimdir = None;
+    if let Some(ref active) = active {
+        // Only activations of this shell can stack; the others are files
+        // sourced later or elsewhere
+        let stackable = format.is_none() || (format == Some(ActivationFormat::Shell) && write_rc.is_none());
+        if replace {
+            // Build on the PATH from before the current activation
+            let base = stack.last().and_then(|f| f.get("PATH").cloned()).or_else(|| std::env::var("BULKER_ORIG_PATH").ok());
+            if let Some(base) = base {
+                // SAFETY: called before any threads are spawned
+                unsafe { std::env::set_var("PATH", base); }
+            }
+            if std::env::var_os("BULKER_SHIMDIR_SHARED").is_none() {
+                replaced_shimdir = std::env::var("BULKER_SHIMDIR").ok();
+            }
+            below = activation_stack::stack_on(&mut stack, activation_stack::current_frame(), true);
+        } else if stackable {
+            below = activation_stack::stack_on(&mut stack, activation_stack::current_frame(), false);
+        } else {
+            bail!("bulker: already activated ({}). Run 'bulker deactivate' first, or pass --replace.", active);
+        }
     }
 
-    let result = get_new_path(config, cratelist, strict, force)?;
-    let newpath = &result.path;
+    check_engine_health(config)?;
+    // Before the shimdir exists, so a refused activation leaves nothing behind
+    if let Some(lockfile) = lockfile {
+        crate::lockfile::check_locked(config, cratelist, lockfile, force)?;
+    }
+    // The check fetched what --force asked for; re-fetching could undo it
+    let result = get_new_path(config, cratelist, commands_filter, strict, force && lockfile.is_none())?;
+    // Lets every shimlink dispatch skip loading the YAML config
+    if let Err(e) = crate::config::write_config_snapshot(Path::new(&result.shimdir), config, config_path) {
+        log::debug!("{:#}", e);
+    }
+    // A failed prefetch only costs latency later, so it never blocks activation
+    let prefetch_status = match prefetch {
+        Some(Prefetch::Foreground) => {
+            if let Err(e) = prefetch_images(config, cratelist) {
+                log::warn!("{:#}", e);
+            }
+            None
+        }
+        Some(Prefetch::Background) => match spawn_background_prefetch(config_path, cratelist) {
+            Ok(status) => {
+                log::info!("Prefetching images in the background; progress in {}", status.display());
+                Some(status.display().to_string())
+            }
+            Err(e) => {
+                log::warn!("{:#}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    // A saved session is only sourced later, so there is nothing to warm up yet
+    if config.bulker.prewarm && !session {
+        spawn_prewarm(config, cratelist).unwrap_or_else(|e| log::warn!("{:#}", e));
+    }
+    let session_path = format!("{}:$PATH", result.shimdir);
+    let newpath = if session && !strict { &session_path } else { &result.path };
     let shimdir = &result.shimdir;
     // Record ALL activated crates so the shim resolver can search every one.
-    let crate_id = cratelist
+    let mut crate_id = cratelist
         .iter()
         .map(|cv| cv.display_name())
         .collect::<Vec<_>>()
         .join(",");
+    // A stacked activation keeps the shimdirs below it on PATH (unless
+    // strict), so their commands must still resolve
+    if let Some(crates) = activation_stack::crates_below(below.as_ref()).filter(|_| !strict) {
+        crate_id = format!("{},{}", crate_id, crates);
+    }
     let crate_name = crate_display_name(cratelist);
 
     // Resolve shell
@@ -169,38 +1224,108 @@ pub fn activate(
     let default_cfg = crate::config::default_config_path();
     let effective_config_path = config_path.unwrap_or(&default_cfg);
     let config_dir = config_templates_dir(effective_config_path);
-    let rcfile = if strict {
-        &config.bulker.rcfile_strict
-    } else {
-        &config.bulker.rcfile
-    };
-    let rcfile_path = config_dir.join(rcfile);
+    let startup = startup_file(config, &config_dir, shell, strict || no_rc);
 
-    // Echo mode: print export statements and return
-    if echo {
-        if std::env::var("BULKER_ORIG_PATH").is_err() {
-            println!("export BULKER_ORIG_PATH=\"$PATH\"");
-        }
-        println!("export BULKERCRATE=\"{}\"", crate_id);
+    // Print mode: emit environment for the requested target and return
+    if let Some(format) = format {
+        let mut vars: Vec<(&str, String)> = vec![("BULKERCRATE", crate_id.clone())];
         if let Some(cp) = config_path {
-            println!("export BULKERCFG=\"{}\"", cp.display());
+            vars.push(("BULKERCFG", cp.display().to_string()));
         }
         if host_env {
-            println!("export BULKER_HOST_ENV=1");
+            vars.push(("BULKER_HOST_ENV", "1".to_string()));
         }
-        println!("export BULKERPATH=\"{}\"", newpath);
-        println!("export BULKER_SHIMDIR=\"{}\"", shimdir);
-        if prompt {
-            println!("export BULKERPROMPT=\"{}\"", ps1);
+        vars.push(("BULKERPATH", newpath.clone()));
+        vars.push(("BULKER_SHIMDIR", shimdir.clone()));
+        if result.shared {
+            vars.push(("BULKER_SHIMDIR_SHARED", "1".to_string()));
+        }
+        if let Some(ref status) = prefetch_status {
+            vars.push(("BULKER_PREFETCH_STATUS", status.clone()));
+        }
+        if let Some(lockfile) = lockfile {
+            vars.push((crate::lockfile::LOCKFILE_ENV, lockfile.display().to_string()));
+        }
+
+        if format == ActivationFormat::Shell {
+            if prompt {
+                vars.push(("BULKERPROMPT", ps1.clone()));
+            }
+            vars.push(("BULKERSHELLRC", shell_rc.clone()));
+            if !stack.is_empty() {
+                vars.push((activation_stack::STACK_ENV, activation_stack::encode(&stack)?));
+            }
+        }
+
+        let mut rendered = render_activation(format, &vars, shimdir, newpath, strict)?;
+        if active.is_some() {
+            // Whatever the activation below set and this one doesn't
+            let stale: Vec<&str> = activation_stack::ACTIVATION_VARS
+                .iter()
+                .copied()
+                .filter(|var| !vars.iter().any(|(k, _)| k == var))
+                .collect();
+            if !stale.is_empty() {
+                rendered.insert_str(0, &format!("unset {}\n", stale.join(" ")));
+            }
+        }
+        if let Some(ref dir) = replaced_shimdir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        match write_rc {
+            Some(rc_path) => {
+                let contents = render_rc_file(&rendered, &crate_id, prompt);
+                std::fs::write(rc_path, contents)
+                    .with_context(|| format!("Failed to write activation file: {}", rc_path.display()))?;
+                println!("source {}", shell_escape::escape(rc_path.to_string_lossy()));
+            }
+            None => {
+                // Session-specific variables used by the init-shell deactivate function
+                if format == ActivationFormat::Shell && std::env::var("BULKER_ORIG_PATH").is_err() {
+                    println!("export BULKER_ORIG_PATH=\"$PATH\"");
+                }
+                print!("{}", rendered);
+            }
+        }
+        if timings {
+            crate::timings::print_report();
+        }
+        if !result.missing_imports.is_empty() {
+            // Exports are printed; signal the partial activation to wrappers
+            use std::io::Write;
+            std::io::stdout().flush()?;
+            log::error!(
+                "Activated without imports that could not be fetched: {}",
+                result.missing_imports.join(", ")
+            );
+            return Err(crate::process::ExitWith(EXIT_PARTIAL_ACTIVATION).into());
         }
-        println!("export BULKERSHELLRC=\"{}\"", shell_rc);
-        println!("export PATH=\"{}\"", newpath);
         return Ok(());
     }
 
+    // Crate maintainers' notes, once per message; --quiet suppresses them
+    if log::max_level() >= log::LevelFilter::Warn {
+        for (crate_name, message) in unseen_activation_messages(config, cratelist)? {
+            eprintln!("bulker: message from {}:", crate_name);
+            for line in message.trim_end().lines() {
+                eprintln!("  {}", line);
+            }
+        }
+    }
+
+    if let Some(ref dir) = replaced_shimdir {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
     // Set environment for the new shell
     // SAFETY: called in the main thread before exec replaces the process
     unsafe {
+        for var in activation_stack::ACTIVATION_VARS {
+            std::env::remove_var(var);
+        }
+        if !stack.is_empty() {
+            std::env::set_var(activation_stack::STACK_ENV, activation_stack::encode(&stack)?);
+        }
         std::env::set_var("BULKERCRATE", &crate_id);
         if let Some(cp) = config_path {
             std::env::set_var("BULKERCFG", cp.to_string_lossy().as_ref());
@@ -210,6 +1335,15 @@ pub fn activate(
         }
         std::env::set_var("BULKERPATH", newpath);
         std::env::set_var("BULKER_SHIMDIR", shimdir);
+        if result.shared {
+            std::env::set_var("BULKER_SHIMDIR_SHARED", "1");
+        }
+        if let Some(ref status) = prefetch_status {
+            std::env::set_var("BULKER_PREFETCH_STATUS", status);
+        }
+        if let Some(lockfile) = lockfile {
+            std::env::set_var(crate::lockfile::LOCKFILE_ENV, lockfile);
+        }
         if prompt {
             std::env::set_var("BULKERPROMPT", &ps1);
         }
@@ -220,19 +1354,26 @@ pub fn activate(
     // Build shell command
     let mut cmd = std::process::Command::new(&shell_path);
 
-    match shell {
-        "bash" => {
+    if exec_shell {
+        // No startup file of ours sets PATH, so it is exported ready-made;
+        // `exit` leaves the activation
+        // SAFETY: called before exec, single-threaded at this point
+        unsafe { std::env::set_var("PATH", newpath); }
+        if timings {
+            crate::timings::print_report();
+        }
+        let err = cmd.exec();
+        bail!("Failed to exec shell: {}", err);
+    }
+
+    match (shell, startup) {
+        ("bash", Some(rcfile_path)) => {
             cmd.arg("--noprofile");
             cmd.arg("--rcfile");
             cmd.arg(rcfile_path.to_string_lossy().as_ref());
         }
-        "zsh" => {
+        ("zsh", Some(zdotdir)) => {
             // Zsh uses ZDOTDIR to find .zshrc
-            let zdotdir = if strict {
-                config_dir.join("zsh_start_strict")
-            } else {
-                config_dir.join("zsh_start")
-            };
             // SAFETY: called before exec, single-threaded at this point
             unsafe { std::env::set_var("ZDOTDIR", zdotdir.to_string_lossy().as_ref()); }
         }
@@ -241,11 +1382,27 @@ pub fn activate(
         }
     }
 
+    if timings {
+        crate::timings::print_report();
+    }
+
     // Replace current process with the shell (never returns on success)
     let err = cmd.exec();
     bail!("Failed to exec shell: {}", err);
 }
 
+/// Startup file for the activated shell: the bash rcfile or the zsh ZDOTDIR.
+/// The strict startup files set only the prompt and PATH, so --no-rc reuses them.
+fn startup_file(config: &BulkerConfig, config_dir: &Path, shell: &str, minimal_rc: bool) -> Option<PathBuf> {
+    match shell {
+        "bash" if minimal_rc => Some(config_dir.join(&config.bulker.rcfile_strict)),
+        "bash" => Some(config_dir.join(&config.bulker.rcfile)),
+        "zsh" if minimal_rc => Some(config_dir.join("zsh_start_strict")),
+        "zsh" => Some(config_dir.join("zsh_start")),
+        _ => None,
+    }
+}
+
 /// Get the directory to resolve rcfile paths from (the config file's parent directory).
 pub(crate) fn config_templates_dir(config_path: &Path) -> PathBuf {
     config_path
@@ -258,6 +1415,383 @@ pub(crate) fn config_templates_dir(config_path: &Path) -> PathBuf {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_effective_host_commands_union_imports_and_config() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.host_commands = vec!["git".to_string(), "ls".to_string()];
+        let mut app = crate::test_util::make_manifest_with_imports("app", vec!["bulker/base:1".to_string()]);
+        app.manifest.host_commands = vec!["ls".to_string(), "base".to_string()];
+        let mut base = crate::test_util::make_manifest_with_imports("base", vec![]);
+        base.manifest.host_commands = vec!["cat".to_string(), "ls".to_string()];
+        // `base` is a crate command of the import, so it isn't taken from the host
+        assert_eq!(effective_host_commands(&config, &[app, base]), vec!["ls", "cat", "git"]);
+    }
+
+    #[test]
+    fn test_write_host_command_scripts() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let (bin, outer, shimdir) = (tmpdir.path().join("bin"), tmpdir.path().join("outer"), tmpdir.path().join("shims"));
+        for dir in [&bin, &outer, &shimdir] {
+            std::fs::create_dir(dir).unwrap();
+        }
+        std::fs::write(bin.join("tool"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(bin.join("tool"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        // A script of an enclosing strict activation is skipped for the binary
+        write_host_command_scripts(&outer, &["tool".to_string()], bin.to_str().unwrap()).unwrap();
+        let host_path = format!("{}:{}", outer.display(), bin.display());
+
+        let names = vec!["tool".to_string(), "no-such-tool".to_string()];
+        let missing = write_host_command_scripts(&shimdir, &names, &host_path).unwrap();
+        assert_eq!(missing, vec!["no-such-tool"]);
+        let script = std::fs::read_to_string(shimdir.join("tool")).unwrap();
+        assert!(script.starts_with(HOST_COMMAND_SCRIPT_HEADER), "{}", script);
+        assert!(script.contains(&format!(" host-exec {} \"$@\"", bin.join("tool").display())), "{}", script);
+        assert!(is_host_command_script(&shimdir.join("tool")));
+        assert!(!is_host_command_script(&bin.join("tool")));
+        assert!(!shimdir.join("no-such-tool").exists());
+    }
+
+    #[test]
+    fn test_engine_mismatch_message() {
+        assert!(engine_mismatch_message("bulker/demo:default", "docker", "docker").is_none());
+        assert!(engine_mismatch_message("bulker/demo:default", "docker", "podman").is_none());
+        assert!(engine_mismatch_message("bulker/demo:default", "singularity", "apptainer").is_none());
+        let msg = engine_mismatch_message("bulker/demo:default", "apptainer", "docker").unwrap();
+        assert!(msg.starts_with("Images of 'bulker/demo:default' were pulled with apptainer, but the configured engine is docker"), "{}", msg);
+        assert!(msg.contains("bulker crate install --images-only bulker/demo:default"), "{}", msg);
+    }
+
+    #[test]
+    fn test_unseen_activation_messages_shown_once_per_message() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let config = BulkerConfig::test_default();
+        let cratelist = crate::manifest::parse_registry_paths("bulker/app:1", "bulker").unwrap();
+        let mut app = crate::test_util::make_manifest_with_imports("app", vec!["bulker/base:1".to_string()]);
+        app.manifest.activation_message = Some("Reference data moved to /ref/v2".to_string());
+        let mut base = crate::test_util::make_manifest_with_imports("base", vec![]);
+        base.manifest.activation_message = Some("Please cite base".to_string());
+        crate::manifest_cache::save_to_cache(&cratelist[0], &app).unwrap();
+        let base_cv = crate::manifest::parse_registry_path("bulker/base:1", "bulker").unwrap();
+        crate::manifest_cache::save_to_cache(&base_cv, &base).unwrap();
+
+        let unseen = unseen_activation_messages(&config, &cratelist).unwrap();
+        assert_eq!(unseen.len(), 2, "{:?}", unseen);
+        assert!(unseen.contains(&("bulker/base:1".to_string(), "Please cite base".to_string())));
+        assert!(unseen_activation_messages(&config, &cratelist).unwrap().is_empty());
+
+        // A changed message is shown again
+        app.manifest.activation_message = Some("Reference data moved to /ref/v3".to_string());
+        crate::manifest_cache::save_to_cache(&cratelist[0], &app).unwrap();
+        let unseen = unseen_activation_messages(&config, &cratelist).unwrap();
+        assert_eq!(unseen, vec![("bulker/app:1".to_string(), "Reference data moved to /ref/v3".to_string())]);
+    }
+
+    #[test]
+    fn test_prefetch_status_path_is_one_file_per_crate_list() {
+        let cratelist = crate::manifest::parse_registry_paths("bulker/demo,databio/pepatac:1.0", "bulker").unwrap();
+        let path = prefetch_status_path(&cratelist);
+        assert_eq!(path.file_name().unwrap(), "bulker_demo_default_databio_pepatac_1.0.log");
+        assert_eq!(path.parent().unwrap().file_name().unwrap(), "prefetch");
+        assert_eq!(Prefetch::from_name("background"), Some(Prefetch::Background));
+    }
+
+    #[test]
+    fn test_check_linked_commands() {
+        let cratelist = crate::manifest::parse_registry_paths("bulker/big", "bulker").unwrap();
+        let mut manifest = crate::manifest::Manifest {
+            manifest: crate::manifest::ManifestInner {
+                commands: (0..=MAX_ACTIVATION_COMMANDS)
+                    .map(|i| crate::manifest::PackageCommand { command: format!("tool{}", i), ..Default::default() })
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let everything = CommandsFilter::parse(["*"]).unwrap();
+        // Over the limit only warns
+        assert!(check_linked_commands(std::slice::from_ref(&manifest), &cratelist, &CommandsFilter::default()).is_ok());
+        assert!(check_linked_commands(std::slice::from_ref(&manifest), &cratelist, &everything).is_ok());
+
+        let none = CommandsFilter::parse(["samtools"]).unwrap();
+        none.apply(&mut manifest);
+        let err = check_linked_commands(&[manifest], &cratelist, &none).unwrap_err();
+        assert!(err.to_string().contains("matches no command"), "{}", err);
+    }
+
+    #[test]
+    fn test_prewarm_command_never_pulls() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.container_engine = "podman".to_string();
+        assert_eq!(
+            prewarm_command(&config, "quay.io/org/tool:1").unwrap().join(" "),
+            "podman run --rm --pull=never --entrypoint true quay.io/org/tool:1"
+        );
+
+        let tmp = tempfile::tempdir().unwrap();
+        config.bulker.container_engine = "apptainer".to_string();
+        config.bulker.apptainer_image_folder = Some(tmp.path().display().to_string());
+        assert!(prewarm_command(&config, "quay.io/org/tool:1").is_none());
+        let sif = tmp.path().join("quay.io/org/tool-1.sif");
+        std::fs::create_dir_all(sif.parent().unwrap()).unwrap();
+        std::fs::write(&sif, "").unwrap();
+        assert_eq!(
+            prewarm_command(&config, "quay.io/org/tool:1").unwrap(),
+            vec!["apptainer".to_string(), "exec".to_string(), sif.display().to_string(), "true".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_output_with_timeout_drains_large_output() {
+        // Well past a pipe buffer: the child only exits once it is read
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", "head -c 1000000 /dev/zero; echo done >&2"]);
+        let output = output_with_timeout(&mut cmd, std::time::Duration::from_secs(10)).unwrap().expect("timed out");
+        assert_eq!(output.stdout.len(), 1_000_000);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "done\n");
+
+        let mut cmd = std::process::Command::new("sleep");
+        cmd.arg("5");
+        assert!(output_with_timeout(&mut cmd, std::time::Duration::from_millis(100)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_extract_version() {
+        assert_eq!(extract_version("samtools 1.19.2\nUsing htslib 1.19"), Some(vec![1, 19, 2]));
+        assert_eq!(extract_version("Python 3.12.1"), Some(vec![3, 12, 1]));
+        assert_eq!(extract_version("x86_64 tool v2.4"), Some(vec![2, 4]));
+        assert_eq!(extract_version("no version here"), None);
+        assert_eq!(image_tag_version("quay.io/biocontainers/samtools:1.9--h91753b0_8"), Some(vec![1, 9]));
+        assert_eq!(image_tag_version("localhost:5000/tool"), None);
+    }
+
+    #[test]
+    fn test_versions_differ_significantly() {
+        assert!(versions_differ_significantly(&[1, 9], &[1, 19, 2]));
+        assert!(versions_differ_significantly(&[2, 0], &[1, 0]));
+        assert!(!versions_differ_significantly(&[1, 19], &[1, 19, 2]));
+    }
+
+    #[test]
+    fn test_probe_host_version_reads_stdout_and_stderr() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tool = tmpdir.path().join("fake-tool");
+        std::fs::write(&tool, "#!/bin/sh\necho 'fake-tool version 1.19.2' >&2\n").unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = tmpdir.path().to_string_lossy().to_string();
+        assert_eq!(find_host_tool("fake-tool", &path), Some(tool.clone()));
+        assert_eq!(probe_host_version(&tool), Some(vec![1, 19, 2]));
+    }
+
+    #[test]
+    fn test_check_engine_health_reports_engine_failures() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::remove(SKIP_ENGINE_CHECK_ENV).and_set("XDG_CACHE_HOME", tmpdir.path());
+        let engine = tmpdir.path().join("fake-docker");
+        std::fs::write(&engine, "#!/bin/sh\necho 'Cannot connect to the Docker daemon' >&2\nexit 1\n").unwrap();
+        std::fs::set_permissions(&engine, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut config = BulkerConfig::test_default();
+        config.bulker.engine_path = Some(engine.to_string_lossy().to_string());
+        let err = format!("{:#}", check_engine_health(&config).unwrap_err());
+        assert!(err.contains("Cannot connect to the Docker daemon"), "{}", err);
+        assert!(err.contains("daemon running"), "{}", err);
+
+        config.bulker.engine_path = Some(tmpdir.path().join("missing").to_string_lossy().to_string());
+        let err = format!("{:#}", check_engine_health(&config).unwrap_err());
+        assert!(err.contains("not found"), "{}", err);
+
+        std::fs::write(&engine, "#!/bin/sh\necho 'apptainer version 1.3.0'\n").unwrap();
+        config.bulker.container_engine = "apptainer".to_string();
+        config.bulker.engine_path = Some(engine.to_string_lossy().to_string());
+        config.bulker.apptainer_image_folder = Some(tmpdir.path().join("sif").to_string_lossy().to_string());
+        check_engine_health(&config).unwrap();
+        assert!(tmpdir.path().join("sif").is_dir());
+    }
+
+    #[test]
+    fn test_check_engine_health_reuses_a_recent_pass() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::remove(SKIP_ENGINE_CHECK_ENV).and_set("XDG_CACHE_HOME", tmpdir.path());
+        let engine = tmpdir.path().join("fake-docker");
+        let calls = tmpdir.path().join("calls");
+        std::fs::write(&engine, format!("#!/bin/sh\necho x >> {}\necho 27.0\n", calls.display())).unwrap();
+        std::fs::set_permissions(&engine, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut config = BulkerConfig::test_default();
+        config.bulker.engine_path = Some(engine.to_string_lossy().to_string());
+        check_engine_health(&config).unwrap();
+        check_engine_health(&config).unwrap();
+        assert_eq!(std::fs::read_to_string(&calls).unwrap().lines().count(), 1);
+
+        // Another engine setup is probed on its own
+        config.bulker.container_engine = "podman".to_string();
+        check_engine_health(&config).unwrap();
+        assert_eq!(std::fs::read_to_string(&calls).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_shimdir_base_uses_configured_dir() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let base = tmpdir.path().join("shims");
+        let mut config = BulkerConfig::test_default();
+        config.bulker.shimdir_base = Some(base.to_string_lossy().to_string());
+        assert_eq!(shimdir_base(&config).unwrap(), base);
+        assert!(base.is_dir(), "configured shimdir_base should be created");
+    }
+
+    #[test]
+    fn test_shimdir_base_defaults_to_usable_dir() {
+        let config = BulkerConfig::test_default();
+        let base = shimdir_base(&config).unwrap();
+        assert!(shimdir_base_problem(&base).is_none());
+    }
+
+    #[test]
+    fn test_shared_shimdir_is_reused_and_pruned() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let cache = |name: &str, commands: &[&str]| {
+            let yaml = format!(
+                "manifest:\n  name: {}\n  commands:\n{}",
+                name,
+                commands
+                    .iter()
+                    .map(|c| format!("  - command: {}\n    docker_image: alpine\n", c))
+                    .collect::<String>()
+            );
+            let cv = crate::manifest::parse_registry_path(&format!("shared/{}", name), "bulker").unwrap();
+            crate::manifest_cache::save_to_cache(&cv, &crate::manifest::parse_manifest(&yaml).unwrap()).unwrap();
+            cv
+        };
+        let one = cache("one", &["cowsay", "fortune"]);
+        let two = cache("two", &["cowsay"]);
+
+        let shared = tmpdir.path().join("shared-shims");
+        let mut config = BulkerConfig::test_default();
+        config.bulker.shared_shimdir = Some(shared.to_string_lossy().to_string());
+
+        let first = get_new_path(&config, std::slice::from_ref(&one), &CommandsFilter::default(), false, false).unwrap();
+        assert!(first.shared);
+        assert_eq!(Path::new(&first.shimdir), shared);
+        assert!(shared.join("fortune").is_symlink());
+
+        // Re-activation reuses the directory and drops commands no longer activated
+        let second = get_new_path(&config, std::slice::from_ref(&two), &CommandsFilter::default(), false, false).unwrap();
+        assert_eq!(second.shimdir, first.shimdir);
+        assert!(shared.join("cowsay").is_symlink());
+        assert!(shared.join("_cowsay").is_symlink());
+        assert!(!shared.join("fortune").exists());
+        assert!(!shared.join("_fortune").exists());
+    }
+
+    #[test]
+    fn test_shared_shimdir_owners_keep_names_of_live_owners() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let names = |list: &[&str]| list.iter().map(|n| n.to_string()).collect::<std::collections::HashSet<_>>();
+        let parent = std::os::unix::process::parent_id();
+        update_shared_shimdir_owners(tmpdir.path(), parent, &names(&["cowsay", "_cowsay"])).unwrap();
+        // A record left by an exited activation is dropped
+        std::fs::write(tmpdir.path().join(SHARED_SHIMDIR_OWNERS).join(i32::MAX.to_string()), "stale").unwrap();
+
+        let live = update_shared_shimdir_owners(tmpdir.path(), std::process::id(), &names(&["fortune"])).unwrap();
+        assert_eq!(live, names(&["cowsay", "_cowsay", "fortune"]));
+        assert!(!tmpdir.path().join(SHARED_SHIMDIR_OWNERS).join(i32::MAX.to_string()).exists());
+
+        // Re-registering an owner replaces its names
+        let live = update_shared_shimdir_owners(tmpdir.path(), parent, &names(&[])).unwrap();
+        assert_eq!(live, names(&["fortune"]));
+    }
+
+    #[test]
+    fn test_prepare_shared_shimdir_refuses_foreign_dir() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        std::fs::write(tmpdir.path().join("notes.txt"), "mine").unwrap();
+        let err = prepare_shared_shimdir(tmpdir.path()).unwrap_err();
+        assert!(err.to_string().contains("not a bulker shimdir"), "{}", err);
+
+        let fresh = tmpdir.path().join("shims");
+        prepare_shared_shimdir(&fresh).unwrap();
+        std::fs::write(fresh.join("leftover"), "").unwrap();
+        prepare_shared_shimdir(&fresh).expect("a marked shimdir is reusable");
+    }
+
+    fn sample_vars() -> Vec<(&'static str, String)> {
+        vec![
+            ("BULKERCRATE", "bulker/demo:default".to_string()),
+            ("BULKER_SHIMDIR", "/tmp/bulker_x".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_render_activation_shell() {
+        let out = render_activation(ActivationFormat::Shell, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        assert!(out.contains("export BULKERCRATE=\"bulker/demo:default\"\n"));
+        assert!(out.ends_with("export PATH=\"/tmp/bulker_x:/usr/bin\"\n"));
+    }
+
+    #[test]
+    fn test_render_rc_file() {
+        let exports = render_activation(ActivationFormat::Shell, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        let rc = render_rc_file(&exports, "bulker/demo:default", true);
+        assert!(rc.contains("export BULKER_ORIG_PATH=\"$PATH\"\n"));
+        assert!(rc.contains(&exports));
+        assert!(rc.contains("PS1=\"(bulker/demo:default) $PS1\"\n"));
+        assert!(rc.contains("    unset BULKERCRATE BULKERCFG "), "{}", rc);
+        assert!(!render_rc_file(&exports, "bulker/demo:default", false).contains("PS1=\"("));
+    }
+
+    #[test]
+    fn test_render_activation_modulefile() {
+        let out = render_activation(ActivationFormat::Modulefile, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        assert!(out.starts_with("#%Module1.0\n"));
+        assert!(out.contains("setenv BULKERCRATE \"bulker/demo:default\"\n"));
+        assert!(out.contains("prepend-path PATH \"/tmp/bulker_x\"\n"));
+
+        let strict = render_activation(ActivationFormat::Modulefile, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x", true).unwrap();
+        assert!(strict.contains("setenv PATH \"/tmp/bulker_x\"\n"));
+    }
+
+    #[test]
+    fn test_render_activation_envrc() {
+        let out = render_activation(ActivationFormat::Envrc, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        assert!(out.contains("export BULKERCRATE=\"bulker/demo:default\"\n"));
+        assert!(out.contains("PATH_add \"/tmp/bulker_x\"\n"));
+    }
+
+    #[test]
+    fn test_render_activation_github_env() {
+        let out = render_activation(ActivationFormat::GithubEnv, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        assert!(out.contains("echo \"BULKERCRATE=bulker/demo:default\" >> \"$GITHUB_ENV\"\n"));
+        assert!(out.contains("echo \"/tmp/bulker_x\" >> \"$GITHUB_PATH\"\n"));
+
+        // GITHUB_PATH can only prepend, so strict mode is rejected
+        assert!(render_activation(ActivationFormat::GithubEnv, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x", true).is_err());
+    }
+
+    #[test]
+    fn test_render_activation_nu() {
+        let out = render_activation(ActivationFormat::Nu, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        assert_eq!(
+            out,
+            "{\n  BULKERCRATE: \"bulker/demo:default\",\n  BULKER_SHIMDIR: \"/tmp/bulker_x\",\n  PATH: [\"/tmp/bulker_x\",\"/usr/bin\"]\n}\n"
+        );
+        assert_eq!(ActivationFormat::for_shell("nu"), Some(ActivationFormat::Nu));
+        assert_eq!(ActivationFormat::for_shell("zsh"), Some(ActivationFormat::Shell));
+    }
+
+    #[test]
+    fn test_render_activation_xonsh() {
+        let out = render_activation(ActivationFormat::Xonsh, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x:/usr/bin", false).unwrap();
+        assert!(out.contains("$BULKERCRATE = \"bulker/demo:default\"\n"));
+        assert!(out.ends_with("$PATH.insert(0, \"/tmp/bulker_x\")\n"));
+
+        let strict = render_activation(ActivationFormat::Xonsh, &sample_vars(), "/tmp/bulker_x", "/tmp/bulker_x", true).unwrap();
+        assert!(strict.ends_with("$PATH = [\"/tmp/bulker_x\"]\n"));
+    }
+
     #[test]
     fn test_config_templates_dir_resolves_relative_to_config_file() {
         let config_path = Path::new("/some/custom/path/bulker_config.yaml");
@@ -275,4 +1809,15 @@ mod tests {
             PathBuf::from("/home/user/Dropbox/env/bulker_config/templates/start.sh")
         );
     }
+
+    #[test]
+    fn test_no_rc_uses_the_minimal_startup_files() {
+        let config = BulkerConfig::test_default();
+        let dir = Path::new("/cfg");
+        assert_eq!(startup_file(&config, dir, "bash", false), Some(dir.join(&config.bulker.rcfile)));
+        assert_eq!(startup_file(&config, dir, "bash", true), Some(dir.join("start_strict.sh")));
+        assert_eq!(startup_file(&config, dir, "zsh", false), Some(dir.join("zsh_start")));
+        assert_eq!(startup_file(&config, dir, "zsh", true), Some(dir.join("zsh_start_strict")));
+        assert_eq!(startup_file(&config, dir, "fish", true), None);
+    }
 }
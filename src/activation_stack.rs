@@ -0,0 +1,168 @@
+//! Nested activations. `bulker activate` inside an activated shell pushes the
+//! current activation onto `$BULKER_STACK` instead of failing, and `bulker
+//! deactivate` pops it again, restoring the outer activation's PATH and
+//! variables. `$BULKER_STACK` is a list of frames, outermost first, stored as
+//! base64url-encoded JSON so it survives any shell quoting.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+pub const STACK_ENV: &str = "BULKER_STACK";
+
+/// The variables an activation sets. A frame saves those set when it was
+/// pushed, plus PATH.
+pub const ACTIVATION_VARS: &[&str] = &[
+    "BULKERCRATE",
+    "BULKERCFG",
+    "BULKER_HOST_ENV",
+    "BULKERPATH",
+    "BULKER_SHIMDIR",
+    "BULKER_SHIMDIR_SHARED",
+    "BULKER_PREFETCH_STATUS",
+    "BULKER_LOCKFILE",
+    "BULKERPROMPT",
+    "BULKERSHELLRC",
+];
+
+/// One saved activation: variable name -> value, for the variables that were set.
+pub type Frame = BTreeMap<String, String>;
+
+/// The activation of this process's environment, as a frame to push.
+pub fn current_frame() -> Frame {
+    ACTIVATION_VARS
+        .iter()
+        .chain(&["PATH"])
+        .filter_map(|var| std::env::var(var).ok().map(|value| (var.to_string(), value)))
+        .collect()
+}
+
+/// The stack in `$BULKER_STACK`, outermost frame first; empty when unset.
+pub fn read() -> Result<Vec<Frame>> {
+    match std::env::var(STACK_ENV) {
+        Ok(encoded) if !encoded.is_empty() => decode(&encoded),
+        _ => Ok(Vec::new()),
+    }
+}
+
+pub fn encode(stack: &[Frame]) -> Result<String> {
+    Ok(base64_url::encode(&serde_json::to_vec(stack)?))
+}
+
+fn decode(encoded: &str) -> Result<Vec<Frame>> {
+    let json = base64_url::decode(encoded).map_err(|e| anyhow::anyhow!("{}", e));
+    json.and_then(|json| Ok(serde_json::from_slice(&json)?))
+        .with_context(|| format!("${} is not a bulker activation stack", STACK_ENV))
+}
+
+/// Stacks an activation on top of `current`, the one active now: it is pushed,
+/// or with `replace` the stack is left as is so the new activation takes its
+/// place. Returns the frame the new activation sits on.
+pub fn stack_on(stack: &mut Vec<Frame>, current: Frame, replace: bool) -> Option<Frame> {
+    if replace {
+        return stack.last().cloned();
+    }
+    stack.push(current.clone());
+    Some(current)
+}
+
+/// Takes the activation to return to off the top of the stack.
+pub fn pop(stack: &mut Vec<Frame>) -> Result<Frame> {
+    stack.pop().ok_or_else(|| anyhow::anyhow!("No stacked activation to return to"))
+}
+
+/// The crates of the frame a nested activation is pushed onto (or replaces
+/// the top of), which stay resolvable while their shimdir is on PATH.
+pub fn crates_below(frame: Option<&Frame>) -> Option<&str> {
+    frame.and_then(|f| f.get("BULKERCRATE")).map(String::as_str).filter(|c| !c.is_empty())
+}
+
+/// POSIX shell code returning to `frame`, leaving `rest` on the stack: its
+/// variables are restored and the ones it didn't have are unset.
+pub fn render_pop(frame: &Frame, rest: &[Frame]) -> Result<String> {
+    let mut out = String::new();
+    for var in ACTIVATION_VARS.iter().chain(&["PATH"]) {
+        match frame.get(*var) {
+            Some(value) => out.push_str(&format!("export {}={}\n", var, shell_escape::escape(value.into()))),
+            None => out.push_str(&format!("unset {}\n", var)),
+        }
+    }
+    if rest.is_empty() {
+        out.push_str(&format!("unset {}\n", STACK_ENV));
+    } else {
+        out.push_str(&format!("export {}={}\n", STACK_ENV, encode(rest)?));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(vars: &[(&str, &str)]) -> Frame {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_stack_round_trip() {
+        let stack = vec![frame(&[("BULKERCRATE", "bulker/demo:default"), ("PATH", "/tmp/bulker_a:/usr/bin")])];
+        let encoded = encode(&stack).unwrap();
+        assert!(encoded.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(decode(&encoded).unwrap(), stack);
+        assert!(decode("not a stack").is_err());
+    }
+
+    #[test]
+    fn test_render_pop_restores_and_unsets() {
+        let outer = frame(&[("BULKERCRATE", "bulker/demo:default"), ("PATH", "/tmp/bulker_a:/usr/my bin")]);
+        let out = render_pop(&outer, &[]).unwrap();
+        assert!(out.contains("export BULKERCRATE='bulker/demo:default'\n"), "{}", out);
+        assert!(out.contains("export PATH='/tmp/bulker_a:/usr/my bin'\n"), "{}", out);
+        assert!(out.contains("unset BULKER_HOST_ENV\n"), "{}", out);
+        assert!(out.ends_with("unset BULKER_STACK\n"), "{}", out);
+
+        let out = render_pop(&outer, std::slice::from_ref(&outer)).unwrap();
+        assert!(out.contains(&format!("export BULKER_STACK={}\n", encode(std::slice::from_ref(&outer)).unwrap())), "{}", out);
+    }
+
+    #[test]
+    fn test_stack_on_pushes_current_frame() {
+        let outer = frame(&[("BULKERCRATE", "bulker/a:default"), ("PATH", "/tmp/bulker_a:/usr/bin")]);
+        let mut stack = Vec::new();
+        assert_eq!(stack_on(&mut stack, outer.clone(), false), Some(outer.clone()));
+        assert_eq!(stack, vec![outer.clone()]);
+
+        let inner = frame(&[("BULKERCRATE", "bulker/b:default"), ("PATH", "/tmp/bulker_b:/tmp/bulker_a:/usr/bin")]);
+        assert_eq!(stack_on(&mut stack, inner.clone(), false), Some(inner.clone()));
+        assert_eq!(stack, vec![outer, inner]);
+    }
+
+    #[test]
+    fn test_stack_on_replace_keeps_stack() {
+        let current = frame(&[("BULKERCRATE", "bulker/a:default")]);
+        let mut stack = Vec::new();
+        assert_eq!(stack_on(&mut stack, current.clone(), true), None);
+        assert!(stack.is_empty());
+
+        // Replacing a nested activation sits on what it sat on
+        let outer = frame(&[("BULKERCRATE", "bulker/outer:default")]);
+        let mut stack = vec![outer.clone()];
+        assert_eq!(stack_on(&mut stack, current, true), Some(outer.clone()));
+        assert_eq!(stack, vec![outer]);
+    }
+
+    #[test]
+    fn test_pop_returns_outer_frame_and_leaves_rest() {
+        let a = frame(&[("BULKERCRATE", "bulker/a:default")]);
+        let b = frame(&[("BULKERCRATE", "bulker/b:default")]);
+        let mut stack = Vec::new();
+        stack_on(&mut stack, a.clone(), false);
+        stack_on(&mut stack, b.clone(), false);
+        stack_on(&mut stack, frame(&[("BULKERCRATE", "bulker/c:default")]), true);
+
+        assert_eq!(pop(&mut stack).unwrap(), b);
+        assert_eq!(stack, vec![a.clone()]);
+        assert_eq!(pop(&mut stack).unwrap(), a);
+        assert!(stack.is_empty());
+        assert!(pop(&mut stack).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Crate bundles for air-gapped systems: `bulker crate export` packs cached
+//! manifests (with their digest sidecars) and optionally the crates' images
+//! into a `.tar.gz`, and `bulker crate import` restores them offline.
+//!
+//! ```text
+//! bulker-bundle.yaml                      # BundleIndex
+//! manifests/<ns>/<crate>/<tag>/manifest.yaml
+//! manifests/<ns>/<crate>/<tag>/crate-manifest-digest ...
+//! images/<image>.tar                      # `docker save`, or
+//! images/<image>.sif                      # apptainer SIF files
+//! ```
+//!
+//! Packing and unpacking use the system `tar`.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::cache_permissions;
+use crate::config::BulkerConfig;
+use crate::manifest::{CrateVars, Manifest};
+use crate::manifest_cache::{self, BUILD_ENGINE_SIDECAR, SOURCE_SIDECAR};
+
+/// Default bundle name, in the current directory.
+pub const BUNDLE_NAME: &str = "bulker-bundle.tar.gz";
+
+/// Index at the top of every bundle.
+const INDEX_FILE: &str = "bulker-bundle.yaml";
+
+const BUNDLE_VERSION: u32 = 1;
+
+/// Sidecars that describe this machine's cache rather than the crate; they
+/// are not exported.
+const LOCAL_SIDECARS: &[&str] = &["installed-at", "last-used", BUILD_ENGINE_SIDECAR];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    /// `docker save` archive; loaded with `load`, or built into a SIF by apptainer.
+    Oci,
+    /// Apptainer SIF file.
+    Sif,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundledImage {
+    pub image: String,
+    /// File name under `images/`.
+    pub file: String,
+    pub format: ImageFormat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BundleIndex {
+    pub version: u32,
+    /// `namespace/crate:tag` of every bundled crate, imports included.
+    pub crates: Vec<String>,
+    #[serde(default)]
+    pub images: Vec<BundledImage>,
+}
+
+impl BundleIndex {
+    fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(INDEX_FILE);
+        let text = std::fs::read_to_string(&path).with_context(|| format!("Not a bulker bundle: {} is missing", INDEX_FILE))?;
+        let index: BundleIndex = serde_yml::from_str(&text).with_context(|| format!("Failed to parse {}", INDEX_FILE))?;
+        if index.version != BUNDLE_VERSION {
+            bail!("Unsupported bundle version {} (expected {})", index.version, BUNDLE_VERSION);
+        }
+        // Image files are joined onto the unpacked bundle
+        if let Some(bad) = index.images.iter().find(|i| i.file.is_empty() || i.file.contains('/') || i.file.starts_with('.')) {
+            bail!("Bundle image file name '{}' is not a plain file name", bad.file);
+        }
+        Ok(index)
+    }
+}
+
+/// What `import` did with each bundled crate.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    /// Already cached with the same manifest digest.
+    pub unchanged: Vec<String>,
+    pub images: usize,
+}
+
+fn crate_bundle_dir(root: &Path, cv: &CrateVars) -> PathBuf {
+    root.join("manifests").join(&cv.namespace).join(&cv.crate_name).join(&cv.tag)
+}
+
+/// File name an image is bundled under: the reference with anything but
+/// `[A-Za-z0-9._-]` replaced by `_`.
+fn image_file_name(image: &str, format: ImageFormat) -> String {
+    let stem: String = image
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '_' })
+        .collect();
+    let ext = match format {
+        ImageFormat::Oci => "tar",
+        ImageFormat::Sif => "sif",
+    };
+    format!("{}.{}", stem.trim_start_matches('.'), ext)
+}
+
+/// Run the system `tar` with `args`.
+fn run_tar(args: &[&std::ffi::OsStr]) -> Result<()> {
+    let status = std::process::Command::new("tar").args(args).status().context("Failed to run tar")?;
+    if !status.success() {
+        bail!("tar failed ({})", status);
+    }
+    Ok(())
+}
+
+/// Run `engine args`, failing with `what` in the message.
+fn run_engine(engine: &str, args: &[&std::ffi::OsStr], what: &str) -> Result<()> {
+    let status = std::process::Command::new(engine)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run {}", engine))?;
+    if !status.success() {
+        bail!("{} failed ({})", what, status);
+    }
+    Ok(())
+}
+
+/// Copy the regular files of `from` into `to`, except those named in `skip`.
+fn copy_files(from: &Path, to: &Path, skip: &[&str]) -> Result<()> {
+    cache_permissions::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    for entry in std::fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !entry.file_type()?.is_file() || skip.iter().any(|s| name == *s) {
+            continue;
+        }
+        std::fs::copy(entry.path(), to.join(&name))
+            .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+    }
+    Ok(())
+}
+
+/// Unique images of `manifests`, in order.
+fn manifest_images(manifests: &[Manifest]) -> Vec<String> {
+    let mut images: Vec<String> = Vec::new();
+    for pkg in manifests.iter().flat_map(|m| &m.manifest.commands) {
+        if !images.contains(&pkg.docker_image) {
+            images.push(pkg.docker_image.clone());
+        }
+    }
+    images
+}
+
+/// Write a bundle of `cratelist` and their imports, which must be cached, to
+/// `output`. With `with_images`, the images are saved into it too: `docker
+/// save` archives, or the pulled SIF files under apptainer.
+pub fn export(config: &BulkerConfig, cratelist: &[CrateVars], output: &Path, with_images: bool) -> Result<BundleIndex> {
+    let staging = tempfile::tempdir().context("Failed to create a staging directory")?;
+    let mut index = BundleIndex { version: BUNDLE_VERSION, crates: Vec::new(), images: Vec::new() };
+    let mut manifests = Vec::new();
+
+    for cv in crate::imports::resolve_cratevars_with_imports(config, cratelist)? {
+        let manifest = manifest_cache::load_cached(&cv)?
+            .ok_or_else(|| anyhow::anyhow!("Crate '{}' is not cached", cv.display_name()))?;
+        manifest_cache::ensure_crate_manifest_digest(&cv)?;
+        let cache_dir = manifest_cache::manifest_path(&cv).parent().unwrap().to_path_buf();
+        copy_files(&cache_dir, &crate_bundle_dir(staging.path(), &cv), LOCAL_SIDECARS)?;
+        index.crates.push(cv.display_name());
+        manifests.push(manifest);
+    }
+
+    if with_images {
+        let images_dir = staging.path().join("images");
+        std::fs::create_dir_all(&images_dir)?;
+        for image in manifest_images(&manifests) {
+            let format = if config.is_apptainer() { ImageFormat::Sif } else { ImageFormat::Oci };
+            let file = image_file_name(&image, format);
+            let dest = images_dir.join(&file);
+            log::info!("Saving image {}...", image);
+            match format {
+                ImageFormat::Sif => {
+                    let sif = crate::manifest::migrate_legacy_sif(&image, config.bulker.apptainer_image_folder.as_deref());
+                    if !Path::new(&sif).is_file() {
+                        bail!("Image '{}' has not been pulled ({} is missing). Run 'bulker crate install' first.", image, sif);
+                    }
+                    std::fs::copy(&sif, &dest).with_context(|| format!("Failed to copy {}", sif))?;
+                }
+                ImageFormat::Oci => {
+                    run_engine(config.engine_path(), &["save".as_ref(), "-o".as_ref(), dest.as_os_str(), image.as_ref()], &format!("Saving image '{}'", image))?;
+                }
+            }
+            index.images.push(BundledImage { image, file, format });
+        }
+    }
+
+    let yaml = serde_yml::to_string(&index)?;
+    std::fs::write(staging.path().join(INDEX_FILE), format!("# Written by `bulker crate export`\n{}", yaml))?;
+    run_tar(&["-czf".as_ref(), output.as_os_str(), "-C".as_ref(), staging.path().as_os_str(), ".".as_ref()])
+        .with_context(|| format!("Failed to write bundle: {}", output.display()))?;
+    Ok(index)
+}
+
+/// The unpacked file of a bundled image, which must be a regular file: a
+/// symlink would make the import copy or load whatever it points to.
+fn bundled_image_file(root: &Path, bundled: &BundledImage) -> Result<PathBuf> {
+    let file = root.join("images").join(&bundled.file);
+    let meta = std::fs::symlink_metadata(&file)
+        .with_context(|| format!("Bundle is missing the image of '{}'", bundled.image))?;
+    if !meta.file_type().is_file() {
+        bail!("Bundled image of '{}' is not a regular file; the bundle is corrupt", bundled.image);
+    }
+    Ok(file)
+}
+
+/// Restore a bundle written by [`export`] into the manifest cache. Crates
+/// already cached with a different manifest are refused unless `force`.
+/// With `load_images`, bundled images are loaded into the engine (`load`),
+/// or placed in the apptainer image folder (`docker save` archives are
+/// built into SIFs).
+pub fn import(config: &BulkerConfig, bundle: &Path, load_images: bool, force: bool) -> Result<ImportReport> {
+    let staging = tempfile::tempdir().context("Failed to create a staging directory")?;
+    run_tar(&["-xzf".as_ref(), bundle.as_os_str(), "-C".as_ref(), staging.path().as_os_str()])
+        .with_context(|| format!("Failed to unpack bundle: {}", bundle.display()))?;
+    let index = BundleIndex::load(staging.path()).with_context(|| bundle.display().to_string())?;
+
+    // Check everything before touching the cache
+    let mut crates = Vec::new();
+    for name in &index.crates {
+        let cv = crate::manifest::parse_registry_path(name, &config.bulker.default_namespace)?;
+        let dir = crate_bundle_dir(staging.path(), &cv);
+        let text = crate::manifest::read_manifest_file(&dir.join("manifest.yaml"))
+            .with_context(|| format!("Bundle is missing the manifest of {}", name))?;
+        let mut manifest = crate::manifest::parse_manifest(&text).with_context(|| format!("Bundled manifest of {}", name))?;
+        manifest.source = std::fs::read_to_string(dir.join(SOURCE_SIDECAR)).ok();
+        let digest = crate::digest::crate_manifest_digest(&manifest).digest;
+        let recorded = std::fs::read_to_string(dir.join("crate-manifest-digest"))
+            .with_context(|| format!("Bundle is missing the manifest digest of {}", name))?;
+        if recorded.trim() != digest {
+            bail!("Bundled manifest of {} does not match its digest; the bundle is corrupt", name);
+        }
+        let unchanged = match manifest_cache::load_cached(&cv)? {
+            Some(cached) if crate::digest::crate_manifest_digest(&cached).digest == digest => true,
+            Some(_) if !force => bail!("'{}' is already cached with a different manifest. Use --force to replace it.", name),
+            _ => false,
+        };
+        crates.push((cv, dir, manifest, unchanged));
+    }
+    let mut images = Vec::new();
+    if load_images {
+        for bundled in &index.images {
+            images.push((bundled, bundled_image_file(staging.path(), bundled)?));
+        }
+    }
+
+    let mut report = ImportReport::default();
+    for (cv, dir, manifest, unchanged) in &crates {
+        if *unchanged {
+            report.unchanged.push(cv.display_name());
+            continue;
+        }
+        manifest_cache::save_to_cache(cv, manifest)?;
+        let cache_dir = manifest_cache::manifest_path(cv).parent().unwrap().to_path_buf();
+        let written = ["manifest.yaml", SOURCE_SIDECAR, "crate-manifest-digest"];
+        copy_files(dir, &cache_dir, &[LOCAL_SIDECARS, &written[..]].concat())?;
+        report.imported.push(cv.display_name());
+    }
+
+    if !images.is_empty() {
+        for (bundled, file) in images {
+            log::info!("Loading image {}...", bundled.image);
+            if config.is_apptainer() {
+                let (_, sif) = crate::manifest::apptainer_image_paths(&bundled.image, config.bulker.apptainer_image_folder.as_deref());
+                let sif = PathBuf::from(crate::config::expand_path(&sif));
+                if let Some(parent) = sif.parent() {
+                    cache_permissions::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create image directory: {}", parent.display()))?;
+                }
+                match bundled.format {
+                    ImageFormat::Sif => {
+                        std::fs::copy(&file, &sif).with_context(|| format!("Failed to copy image to {}", sif.display()))?;
+                    }
+                    ImageFormat::Oci => {
+                        let archive = format!("docker-archive:{}", file.display());
+                        run_engine(config.engine_path(), &["build".as_ref(), sif.as_os_str(), archive.as_ref()], &format!("Building {}", sif.display()))?;
+                    }
+                }
+                cache_permissions::fix(&sif);
+            } else {
+                if bundled.format == ImageFormat::Sif {
+                    bail!("Image '{}' was bundled as a SIF file, which only apptainer can use. Export the bundle with docker or podman.", bundled.image);
+                }
+                run_engine(config.engine_path(), &["load".as_ref(), "-i".as_ref(), file.as_os_str()], &format!("Loading image '{}'", bundled.image))?;
+            }
+            report.images += 1;
+        }
+        for (cv, ..) in &crates {
+            manifest_cache::write_digest_sidecar(cv, BUILD_ENGINE_SIDECAR, &config.bulker.container_engine)?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_file_name_is_a_plain_file_name() {
+        assert_eq!(image_file_name("quay.io/biocontainers/samtools:1.9", ImageFormat::Oci), "quay.io_biocontainers_samtools_1.9.tar");
+        assert_eq!(image_file_name("../evil", ImageFormat::Sif), "_evil.sif");
+    }
+
+    #[test]
+    fn test_index_rejects_unsafe_image_files_and_unknown_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |yaml: &str| std::fs::write(dir.path().join(INDEX_FILE), yaml).unwrap();
+
+        write("version: 1\ncrates: [bulker/demo:1.0]\nimages:\n- {image: 'a:1', file: a_1.tar, format: oci}\n");
+        let index = BundleIndex::load(dir.path()).unwrap();
+        assert_eq!(index.images[0].format, ImageFormat::Oci);
+
+        write("version: 1\ncrates: []\nimages:\n- {image: 'a:1', file: ../../a.tar, format: oci}\n");
+        assert!(BundleIndex::load(dir.path()).unwrap_err().to_string().contains("not a plain file name"));
+
+        write("version: 2\ncrates: []\n");
+        assert!(BundleIndex::load(dir.path()).unwrap_err().to_string().contains("version 2"));
+    }
+
+    #[test]
+    fn test_bundled_image_file_must_be_regular() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("images")).unwrap();
+        let bundled = |file: &str| BundledImage { image: "a:1".to_string(), file: file.to_string(), format: ImageFormat::Oci };
+
+        std::fs::write(dir.path().join("images/a_1.tar"), "archive").unwrap();
+        assert_eq!(bundled_image_file(dir.path(), &bundled("a_1.tar")).unwrap(), dir.path().join("images/a_1.tar"));
+
+        std::os::unix::fs::symlink("/etc/passwd", dir.path().join("images/link.tar")).unwrap();
+        let err = bundled_image_file(dir.path(), &bundled("link.tar")).unwrap_err();
+        assert!(err.to_string().contains("not a regular file"), "{}", err);
+        std::fs::create_dir(dir.path().join("images/dir.tar")).unwrap();
+        assert!(bundled_image_file(dir.path(), &bundled("dir.tar")).is_err());
+        assert!(bundled_image_file(dir.path(), &bundled("missing.tar")).is_err());
+    }
+}
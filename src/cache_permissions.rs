@@ -0,0 +1,219 @@
+//! Group-shared caches (`cache_permissions` config section).
+//!
+//! A lab sharing one cache, templates directory or shared shimdir between
+//! several accounts needs what bulker creates there to be writable by the
+//! group. With `cache_permissions`, files bulker creates get `mode` and
+//! directories the matching search bits, and both are handed to `group`;
+//! directories also get setgid so entries other tools create inherit the
+//! group. Failures are logged, never fatal: another member's files can't be
+//! changed, and don't need to be.
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The `cache_permissions` config section.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachePermissions {
+    /// Octal mode for files (e.g. `0664`); directories get the matching
+    /// search bits (`0775`). Unset keeps the modes the umask gives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Group name or id to give created files and directories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// Permissions validated from a `CachePermissions`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Resolved {
+    file_mode: Option<u32>,
+    gid: Option<nix::unistd::Gid>,
+}
+
+static RESOLVED: RwLock<Option<Resolved>> = RwLock::new(None);
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+fn parse_mode(mode: &str) -> Result<u32> {
+    let mode = mode.trim();
+    if mode.is_empty() || mode.len() > 4 || !mode.chars().all(|c| ('0'..='7').contains(&c)) {
+        bail!("Invalid cache_permissions mode '{}'. Use an octal file mode such as 0664.", mode);
+    }
+    Ok(u32::from_str_radix(mode, 8)?)
+}
+
+fn resolve_group(group: &str) -> Result<nix::unistd::Gid> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(nix::unistd::Gid::from_raw(gid));
+    }
+    match nix::unistd::Group::from_name(group) {
+        Ok(Some(g)) => Ok(g.gid),
+        _ => bail!("Unknown cache_permissions group '{}'", group),
+    }
+}
+
+impl CachePermissions {
+    fn resolve(&self) -> Result<Resolved> {
+        Ok(Resolved {
+            file_mode: self.mode.as_deref().filter(|m| !m.is_empty()).map(parse_mode).transpose()?,
+            gid: self.group.as_deref().filter(|g| !g.is_empty()).map(resolve_group).transpose()?,
+        })
+    }
+
+    /// Parse the `MODE[:GROUP]` form `config set cache_permissions=` takes
+    /// (`0664:lab`, `0664`, or `:lab`).
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let (mode, group) = spec.split_once(':').unwrap_or((spec, ""));
+        let permissions = CachePermissions {
+            mode: Some(mode.trim().to_string()).filter(|m| !m.is_empty()),
+            group: Some(group.trim().to_string()).filter(|g| !g.is_empty()),
+        };
+        if permissions.mode.is_none() && permissions.group.is_none() {
+            bail!("Invalid cache_permissions '{}'. Use MODE[:GROUP], e.g. 0664:lab.", spec);
+        }
+        permissions.resolve()?;
+        Ok(permissions)
+    }
+
+    /// The `MODE[:GROUP]` form shown by `config get cache_permissions`.
+    pub fn spec(&self) -> String {
+        match self.group {
+            Some(ref group) => format!("{}:{}", self.mode.as_deref().unwrap_or(""), group),
+            None => self.mode.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Set the permissions `fix` applies, from the loaded config.
+pub fn set_cache_permissions(permissions: Option<&CachePermissions>) {
+    let resolved = permissions.and_then(|p| match p.resolve() {
+        Ok(resolved) => Some(resolved),
+        Err(e) => {
+            log::warn!("Ignoring cache_permissions: {:#}", e);
+            None
+        }
+    });
+    *RESOLVED.write().unwrap_or_else(|e| e.into_inner()) = resolved;
+}
+
+/// Mode for an entry with current mode `current` under `file_mode`. Read
+/// bits imply search bits for directories and for executable files.
+fn target_mode(file_mode: Option<u32>, current: u32, is_dir: bool, setgid: bool) -> u32 {
+    let executable = is_dir || current & 0o100 != 0;
+    let mut mode = match file_mode {
+        Some(m) if executable => m | ((m & 0o444) >> 2),
+        Some(m) => m,
+        None => current & 0o7777,
+    };
+    if is_dir && setgid {
+        mode |= 0o2000;
+    }
+    mode
+}
+
+fn configured() -> Option<Resolved> {
+    *RESOLVED.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Apply the configured permissions to `path` (not following symlinks).
+pub fn fix(path: &Path) {
+    fix_with(path, configured());
+}
+
+fn fix_with(path: &Path, resolved: Option<Resolved>) {
+    let Some(resolved) = resolved else { return };
+    let Ok(meta) = std::fs::symlink_metadata(path) else { return };
+    if meta.file_type().is_symlink() {
+        return;
+    }
+    let mut result = Ok(());
+    if let Some(gid) = resolved.gid {
+        result = nix::unistd::chown(path, None, Some(gid)).map_err(std::io::Error::from);
+    }
+    let mode = target_mode(resolved.file_mode, meta.permissions().mode(), meta.is_dir(), resolved.gid.is_some());
+    if result.is_ok() && mode != meta.permissions().mode() & 0o7777 {
+        result = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode));
+    }
+    if let Err(e) = result {
+        // Entries of other group members are theirs to fix
+        use std::os::unix::fs::MetadataExt;
+        if meta.uid() == nix::unistd::getuid().as_raw() && !WARNED.swap(true, Ordering::Relaxed) {
+            log::warn!("Failed to apply cache_permissions to {}: {}", path.display(), e);
+        } else {
+            log::debug!("Failed to apply cache_permissions to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// `std::fs::create_dir_all`, applying the permissions to every directory
+/// it creates.
+pub fn create_dir_all(path: &Path) -> std::io::Result<()> {
+    create_dir_all_with(path, configured())
+}
+
+fn create_dir_all_with(path: &Path, resolved: Option<Resolved>) -> std::io::Result<()> {
+    let missing: Vec<&Path> = path.ancestors().take_while(|p| !p.as_os_str().is_empty() && !p.exists()).collect();
+    std::fs::create_dir_all(path)?;
+    for dir in missing.into_iter().rev() {
+        fix_with(dir, resolved);
+    }
+    Ok(())
+}
+
+/// `std::fs::write`, applying the permissions to the file.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    write_with(path, contents, configured())
+}
+
+fn write_with(path: &Path, contents: impl AsRef<[u8]>, resolved: Option<Resolved>) -> std::io::Result<()> {
+    std::fs::write(path, contents)?;
+    fix_with(path, resolved);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_round_trip() {
+        let p = CachePermissions::from_spec("0664:0").unwrap();
+        assert_eq!(p, CachePermissions { mode: Some("0664".to_string()), group: Some("0".to_string()) });
+        assert_eq!(p.spec(), "0664:0");
+        assert_eq!(CachePermissions::from_spec("0664").unwrap().spec(), "0664");
+        assert_eq!(CachePermissions::from_spec(":0").unwrap().spec(), ":0");
+        assert!(CachePermissions::from_spec("0999").is_err());
+        assert!(CachePermissions::from_spec(":no-such-group-here").is_err());
+        assert!(CachePermissions::from_spec(":").is_err());
+    }
+
+    #[test]
+    fn test_target_mode() {
+        assert_eq!(target_mode(Some(0o664), 0o600, false, true), 0o664);
+        // Executable files and directories get search bits where readable
+        assert_eq!(target_mode(Some(0o664), 0o700, false, true), 0o775);
+        assert_eq!(target_mode(Some(0o664), 0o700, true, true), 0o2775);
+        // A group alone keeps the mode but makes directories setgid
+        assert_eq!(target_mode(None, 0o40755, true, true), 0o2755);
+        assert_eq!(target_mode(Some(0o640), 0o755, true, false), 0o750);
+    }
+
+    #[test]
+    fn test_create_dir_all_and_write_apply_mode() {
+        // Passed explicitly: setting the global would leak into concurrent tests
+        let tmpdir = tempfile::tempdir().unwrap();
+        let resolved = CachePermissions { mode: Some("0660".to_string()), group: None }.resolve().ok();
+        let nested = tmpdir.path().join("a/b");
+        create_dir_all_with(&nested, resolved).unwrap();
+        write_with(&nested.join("f"), "x", resolved).unwrap();
+        let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&tmpdir.path().join("a")), 0o770);
+        assert_eq!(mode(&nested), 0o770);
+        assert_eq!(mode(&nested.join("f")), 0o660);
+        // The existing parent is left alone
+        assert_ne!(mode(tmpdir.path()), 0o770);
+    }
+}
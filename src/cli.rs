@@ -0,0 +1,128 @@
+//! Entry point of the `bulker` binary. Not part of the library API.
+
+use anyhow::Result;
+use std::os::unix::process::CommandExt;
+
+use crate::{build_parser, commands, manifest_cache, plugins, shimlink};
+
+/// The `bulker` binary: shimlink dispatch, `host-exec`, or the CLI. Errors
+/// carrying an exit status (`process::ExitWith`) end the process with it.
+pub fn run() -> Result<()> {
+    run_command().or_else(|e| match e.downcast::<crate::process::ExitWith>() {
+        Ok(crate::process::ExitWith(code)) => std::process::exit(code),
+        Err(e) => Err(e),
+    })
+}
+
+fn run_command() -> Result<()> {
+    // Shimlink dispatch: if invoked as a symlink (argv[0] != "bulker"),
+    // dispatch directly to the container command without clap parsing.
+    if let Some(cmd_name) = shimlink::detect_shimlink_invocation() {
+        // SAFETY: called before any threads are spawned, single-threaded context
+        unsafe {
+            if std::env::var("RUST_LOG").is_err() {
+                std::env::set_var("RUST_LOG", "info");
+            }
+        }
+        let _ = env_logger::try_init();
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        return shimlink::shimlink_exec(&cmd_name, &args);
+    }
+
+    // Host-exec dispatch: exec scripts for host commands route through here.
+    // `bulker host-exec /path/to/binary [args...]` replaces bulker with the binary.
+    //
+    // Security note: this is intentionally not guarded. It runs as the current user
+    // with no privilege escalation. The user can already run any binary directly;
+    // this subcommand exists only so shimlink scripts can dispatch host_commands
+    // through the bulker binary. It is hidden from `--help` since it is an internal
+    // implementation detail, not a user-facing command.
+    if std::env::args().nth(1).as_deref() == Some("host-exec") {
+        let Some(binary) = std::env::args().nth(2) else {
+            eprintln!("Usage: bulker host-exec <path> [args...]");
+            std::process::exit(1);
+        };
+        let args: Vec<String> = std::env::args().skip(3).collect();
+        let err = std::process::Command::new(&binary).args(&args).exec();
+        eprintln!("Failed to exec {}: {}", binary, err);
+        std::process::exit(1);
+    }
+
+    let app = build_parser();
+    let matches = app.get_matches();
+
+    // Initialize logging
+    // SAFETY: called before any threads are spawned, single-threaded context
+    unsafe {
+        if matches.get_flag("verbose") {
+            std::env::set_var("RUST_LOG", "debug");
+        } else if std::env::var("RUST_LOG").is_err() {
+            std::env::set_var("RUST_LOG", "info");
+        }
+        // Exported so shimlinks in an activated shell use the same caches
+        match matches.get_one::<String>("cache-scope").map(|s| s.as_str()) {
+            Some("project") => {
+                let cwd = std::env::current_dir()?;
+                std::env::set_var(manifest_cache::PROJECT_CACHE_ENV, manifest_cache::find_project_cache(&cwd));
+            }
+            Some(_) => std::env::remove_var(manifest_cache::PROJECT_CACHE_ENV),
+            None => {}
+        }
+    }
+    env_logger::init();
+
+    manifest_cache::migrate_legacy_cache();
+
+    match matches.subcommand() {
+        Some(("activate", sub_m)) => commands::activate::run(sub_m),
+        Some(("deactivate", sub_m)) => commands::deactivate::run(sub_m),
+        Some(("attach", sub_m)) => commands::attach::run(sub_m),
+        Some(("exec", sub_m)) => commands::exec::run(sub_m),
+        Some(("crate", sub_m)) => commands::crate_cmd::dispatch(sub_m),
+        Some(("ci", sub_m)) => commands::ci_cmd::dispatch(sub_m),
+        Some(("config", sub_m)) => commands::config_cmd::dispatch(sub_m),
+        Some(("env", sub_m)) => commands::env_cmd::dispatch(sub_m),
+        Some(("registry", sub_m)) => commands::registry_cmd::dispatch(sub_m),
+        Some(("init-shell", sub_m)) => commands::init_shell::run(sub_m),
+        Some(("mock", sub_m)) => commands::mock_cmd::dispatch(sub_m),
+        Some(("ps", sub_m)) => commands::ps::run(sub_m),
+        Some(("clean", sub_m)) => commands::clean::run(sub_m),
+        Some(("status", sub_m)) => commands::status::run(sub_m),
+        Some(("prefetch", sub_m)) => commands::prefetch::run(sub_m),
+        Some(("selftest", sub_m)) => commands::selftest::run(sub_m),
+        Some(("demo", sub_m)) => commands::demo::run(sub_m),
+        Some(("version", sub_m)) => commands::version::run(sub_m),
+        Some(("bugreport", sub_m)) => commands::bugreport::run(sub_m),
+        Some(("doctor", sub_m)) => commands::doctor::run(sub_m),
+        Some(("completions", sub_m)) => commands::completions::run(sub_m),
+        Some((name, sub_m)) => run_plugin(name, sub_m),
+        None if matches.get_flag("list-plugins") => {
+            let app = build_parser();
+            let builtins: Vec<&str> = app.get_subcommands().map(|c| c.get_name()).collect();
+            plugins::print_plugins(&builtins);
+            Ok(())
+        }
+        None => build_parser()
+            .error(clap::error::ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit(),
+    }
+}
+
+/// Run an unknown subcommand as a `bulker-<name>` plugin from PATH. Without a
+/// matching plugin, re-parse without external subcommands so clap reports the
+/// usual "unrecognized subcommand" error (with its suggestions).
+fn run_plugin(name: &str, matches: &clap::ArgMatches) -> Result<()> {
+    let path = std::env::var("PATH").unwrap_or_default();
+    let Some(plugin) = plugins::find_plugin(name, &path) else {
+        build_parser()
+            .allow_external_subcommands(false)
+            .get_matches_from(std::env::args_os());
+        anyhow::bail!("unrecognized subcommand '{}'", name);
+    };
+    let args: Vec<std::ffi::OsString> = matches
+        .get_many::<std::ffi::OsString>("")
+        .map(|vals| vals.cloned().collect())
+        .unwrap_or_default();
+    let err = plugins::exec_plugin(&plugin, &args);
+    anyhow::bail!("Failed to exec plugin {}: {}", plugin.display(), err)
+}
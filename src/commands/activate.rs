@@ -1,8 +1,9 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use clap::{Arg, ArgAction, ArgMatches, Command};
 
-use crate::config::load_config;
-use crate::manifest::{is_local_path, is_url, load_local_manifest, load_url_manifest, parse_registry_paths};
+use crate::activate::{ActivateOptions, ActivationFormat, Prefetch};
+use crate::config::load_cli_config;
+use crate::manifest::{is_local_path, is_stdin_path, is_url, load_local_manifest, load_stdin_manifest, load_url_manifest, parse_registry_paths, CommandsFilter};
 
 pub fn create_cli() -> Command {
     Command::new("activate")
@@ -14,19 +15,45 @@ EXAMPLES:
   bulker activate bulker/demo,bulker/pi        # multiple crates
   bulker activate demo                          # uses default namespace
   bulker activate -s bulker/demo                # strict: only crate commands in PATH
+  bulker activate --no-rc bulker/demo           # skip ~/.bashrc / ~/.zshrc
+  bulker activate --echo --shimdir /shared/shims bulker/demo  # multi-node jobs
   bulker activate --echo bulker/demo            # print exports instead of launching shell
+  bulker activate --echo --timings bulker/demo  # report time spent activating
+  bulker activate --echo --shell nu bulker/demo | from nuon | load-env   # nushell
+  execx($(bulker activate --echo --shell xonsh bulker/demo))            # xonsh
+  bulker activate --prefetch bulker/demo        # pull all images before the shell starts
+  bulker activate --prefetch=background bulker/demo  # pull in the background
+  bulker activate --commands-filter 'samtools,bcf*' bulker/biocontainers  # link only these tools
+  bulker activate --write-rc demo.sh bulker/demo  # write a sourceable file, no eval needed
+  bulker activate --exec-shell bulker/demo      # exec $SHELL with PATH set: no eval, no rcfile
+  bulker activate --detach work bulker/demo     # save as session 'work' for `bulker attach work`
+  bulker activate bulker/pi                     # inside an activation: stack on top of it
+  bulker activate --replace bulker/pi           # inside an activation: take its place
+  bulker activate --locked databio/pepatac:1.0.13   # run the images pinned in ./bulker.lock
+  eval \"$(bulker activate --echo --quiet bulker/demo)\"   # scripted: only errors on stderr
   bulker activate ./my-pipeline.yaml            # activate from local manifest file
+  bulker activate --format envrc bulker/demo > .envrc       # direnv
+  bulker activate --format modulefile bulker/demo           # Environment Modules / Lmod
+  bulker activate --format github-env bulker/demo | sh      # GitHub Actions
+  gen-manifest | bulker activate --echo -n me/tools -      # manifest from stdin
 
 CRATE FORMAT:
   namespace/crate:tag    Full path (e.g., databio/pepatac:1.0.13)
   crate                  Uses default namespace \"bulker\", tag \"default\"
   crate1,crate2          Multiple crates
-  ./path/to/file.yaml    Local manifest file
-  https://url/file.yaml  Remote manifest")
+  ./path/to/file.yaml    Local manifest file (.yaml, .yml or .json)
+  https://url/file.yaml  Remote manifest
+  -                      Manifest read from stdin (requires --name)
+
+EXIT STATUS (with --echo, --format, --write-rc or --detach):
+  0  activation printed
+  1  error; nothing printed
+  3  activation printed, but some imports could not be fetched and their
+     commands are missing")
         .arg(
             Arg::new("crate_registry_paths")
                 .required(true)
-                .help("Crate(s) to activate (comma-separated for multiple, or a local .yaml file)"),
+                .help("Crate(s) to activate (comma-separated for multiple, a local .yaml/.json file, or - for stdin)"),
         )
         .arg(
             Arg::new("config")
@@ -47,6 +74,91 @@ CRATE FORMAT:
                 .action(ArgAction::SetTrue)
                 .help("Echo export commands instead of launching shell"),
         )
+        .arg(
+            Arg::new("shell")
+                .long("shell")
+                .value_parser(ActivationFormat::SHELL_NAMES)
+                .requires("echo")
+                .help("Shell syntax for --echo (default: POSIX exports for bash/zsh)"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(ActivationFormat::NAMES)
+                .conflicts_with("echo")
+                .help("Print activation for an integration target instead of launching shell"),
+        )
+        .arg(
+            Arg::new("write-rc")
+                .long("write-rc")
+                .value_name("FILE")
+                .conflicts_with_all(["echo", "format"])
+                .help("Write the activation to a sourceable FILE and print the `source` line (no eval needed)"),
+        )
+        .arg(
+            Arg::new("exec-shell")
+                .long("exec-shell")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["echo", "format", "write-rc"])
+                .help("Exec $SHELL with the activation already exported; the shell reads only its own startup files (no eval, no bulker rcfile)"),
+        )
+        .arg(
+            Arg::new("detach")
+                .long("detach")
+                .value_name("NAME")
+                .conflicts_with_all(["echo", "format", "write-rc", "exec-shell"])
+                .help("Save the activation as session NAME for later shells to load with `bulker attach NAME`"),
+        )
+        .arg(
+            Arg::new("shimdir")
+                .long("shimdir")
+                .value_name("DIR")
+                .help("Use (and update in place) a fixed shimdir, e.g. on a shared filesystem for multi-node jobs"),
+        )
+        .arg(
+            Arg::new("prefetch")
+                .long("prefetch")
+                .value_name("MODE")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("foreground")
+                .value_parser(Prefetch::NAMES)
+                .help("Pull all images now (foreground, the default) or in a detached process (background); \
+                       background progress goes to $BULKER_PREFETCH_STATUS"),
+        )
+        .arg(
+            Arg::new("commands-filter")
+                .long("commands-filter")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("Only link the commands matching these globs (comma-separated or repeated, e.g. 'samtools,bcf*')"),
+        )
+        .arg(
+            Arg::new("locked")
+                .long("locked")
+                .action(ArgAction::SetTrue)
+                .help("Run the image digests pinned in ./bulker.lock (see `bulker crate lock`); refuse crates changed since"),
+        )
+        .arg(
+            Arg::new("replace")
+                .long("replace")
+                .action(ArgAction::SetTrue)
+                .help("Inside an activation, take its place instead of stacking on top of it"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::SetTrue)
+                .help("Only report errors on stderr"),
+        )
+        .arg(
+            Arg::new("no-rc")
+                .long("no-rc")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["echo", "format", "write-rc", "detach", "exec-shell"])
+                .help("Do not source your shell rc file (~/.bashrc, ~/.zshrc): only the prompt and PATH are set"),
+        )
         .arg(
             Arg::new("hide-prompt")
                 .long("hide-prompt")
@@ -67,6 +179,12 @@ CRATE FORMAT:
                 .action(ArgAction::SetTrue)
                 .help("Re-fetch manifests from registry even if cached"),
         )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .help("Print a breakdown of where bulker spent its time to stderr"),
+        )
         .arg(
             Arg::new("name")
                 .short('n')
@@ -76,18 +194,55 @@ CRATE FORMAT:
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    if matches.get_flag("quiet") {
+        log::set_max_level(log::LevelFilter::Error);
+    }
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+
+    if let Some(dir) = matches.get_one::<String>("shimdir") {
+        config.bulker.shared_shimdir = Some(crate::config::mkabs(dir, None).to_string_lossy().to_string());
+    }
+
+    let session = match matches.get_one::<String>("detach") {
+        Some(name) => {
+            let dir = crate::sessions::sessions_dir(config_path.as_deref());
+            let session = crate::sessions::Session::new(&dir, name)?;
+            std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            if config.bulker.shared_shimdir.is_none() {
+                config.bulker.shared_shimdir = Some(session.shimdir.to_string_lossy().to_string());
+            }
+            Some(session)
+        }
+        None => None,
+    };
 
     let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
-    let echo = matches.get_flag("echo");
-    let strict = matches.get_flag("strict");
-    let host_env = matches.get_flag("host_env");
-    let hide_prompt = matches.get_flag("hide-prompt");
-    let force = matches.get_flag("force");
+    let write_rc = match session {
+        Some(ref session) => Some(session.env_file.clone()),
+        None => matches.get_one::<String>("write-rc").map(|f| crate::config::mkabs(f, None)),
+    };
+    let format = match matches.get_one::<String>("format") {
+        Some(name) => ActivationFormat::from_name(name),
+        None if matches.get_flag("echo") => {
+            matches.get_one::<String>("shell").and_then(|s| ActivationFormat::for_shell(s)).or(Some(ActivationFormat::Shell))
+        }
+        None if matches.get_flag("echo") || write_rc.is_some() => Some(ActivationFormat::Shell),
+        None => None,
+    };
     let name_override = matches.get_one::<String>("name").map(|s| s.as_str());
+    let prefetch = matches.get_one::<String>("prefetch").and_then(|m| Prefetch::from_name(m));
+    let commands_filter = CommandsFilter::parse(matches.get_many::<String>("commands-filter").into_iter().flatten().map(String::as_str))?;
+    let lockfile = matches.get_flag("locked").then(|| crate::config::mkabs(crate::lockfile::LOCKFILE_NAME, None));
 
-    // Detect URL, local file path, or registry path
-    let cratelist = if is_url(registry_paths) {
+    // Detect stdin, URL, local file path, or registry path
+    let cratelist = if is_stdin_path(registry_paths) {
+        if format.is_none() {
+            bail!("Reading a manifest from stdin needs --echo, --format, --write-rc or --detach: an interactive shell cannot use the consumed stdin");
+        }
+        let (cv, manifest) = load_stdin_manifest(name_override, &config.bulker.default_namespace)?;
+        crate::manifest_cache::save_to_cache(&cv, &manifest)?;
+        vec![cv]
+    } else if is_url(registry_paths) {
         let (cv, manifest) = load_url_manifest(registry_paths, name_override, &config.bulker.default_namespace)?;
         crate::manifest_cache::save_to_cache(&cv, &manifest)?;
         vec![cv]
@@ -96,8 +251,28 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         crate::manifest_cache::save_to_cache(&cv, &manifest)?;
         vec![cv]
     } else {
-        parse_registry_paths(registry_paths, &config.bulker.default_namespace)?
+        crate::manifest_cache::resolve_tag_aliases(parse_registry_paths(registry_paths, &config.bulker.default_namespace)?)
     };
 
-    crate::activate::activate(&config, config_path.as_deref(), &cratelist, echo, strict, host_env, !hide_prompt, force)
+    let opts = ActivateOptions {
+        commands_filter,
+        format,
+        strict: matches.get_flag("strict"),
+        host_env: matches.get_flag("host_env"),
+        hide_prompt: matches.get_flag("hide-prompt"),
+        force: matches.get_flag("force"),
+        timings: matches.get_flag("timings"),
+        write_rc,
+        prefetch,
+        no_rc: matches.get_flag("no-rc"),
+        session: session.is_some(),
+        replace: matches.get_flag("replace"),
+        lockfile,
+        exec_shell: matches.get_flag("exec-shell"),
+    };
+    crate::activate::activate(&config, config_path.as_deref(), &cratelist, &opts)?;
+    if let Some(session) = session {
+        log::info!("Saved session '{}'. Load it in another shell with: eval \"$(bulker attach {})\"", session.name, session.name);
+    }
+    Ok(())
 }
@@ -0,0 +1,85 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+
+use crate::config::load_cli_config;
+use crate::sessions::{self, Session};
+
+pub fn create_cli() -> Command {
+    Command::new("attach")
+        .about("Load a session saved with `bulker activate --detach`")
+        .after_help("\
+EXAMPLES:
+  bulker activate --detach work bulker/demo   # once: build the session
+  eval \"$(bulker attach work)\"                # in any later shell
+  tmux new-session -d -s work && tmux send-keys -t work 'eval \"$(bulker attach work)\"' Enter
+  bulker attach --list
+  bulker attach --remove work
+
+A session is an activation saved to sessions/<name>.env next to the config
+file. Sourcing it puts the crate commands in front of the shell's PATH, like
+`bulker activate --echo`, without building the shimdir again; `bulker
+deactivate` restores the shell and leaves the session for other shells.
+Re-run `bulker activate --detach` to update a session.")
+        .arg(
+            Arg::new("name")
+                .required_unless_present("list")
+                .help("Session to load"),
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("list")
+                .short('l')
+                .long("list")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["name", "remove"])
+                .help("List saved sessions"),
+        )
+        .arg(
+            Arg::new("remove")
+                .long("remove")
+                .action(ArgAction::SetTrue)
+                .help("Delete the session and its shimdir"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (_config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let dir = sessions::sessions_dir(config_path.as_deref());
+
+    if matches.get_flag("list") {
+        for name in sessions::list(&dir) {
+            let contents = std::fs::read_to_string(dir.join(format!("{}.env", name))).unwrap_or_default();
+            let crates = sessions::exported(&contents, "BULKERCRATE").unwrap_or_default();
+            println!("{}\t{}", name, crates);
+        }
+        return Ok(());
+    }
+
+    let name = matches.get_one::<String>("name").unwrap();
+    let session = Session::find(&dir, name)?;
+    if matches.get_flag("remove") {
+        session.remove()?;
+        println!("Removed session '{}'", session.name);
+        return Ok(());
+    }
+
+    // The shimdir may have been cleaned away, e.g. from a scratch filesystem
+    let contents = std::fs::read_to_string(&session.env_file)?;
+    match sessions::exported(&contents, "BULKER_SHIMDIR") {
+        Some(shimdir) if !Path::new(&shimdir).is_dir() => bail!(
+            "The shimdir of session '{}' ({}) no longer exists. Recreate the session with `bulker activate --detach {} <crate>`.",
+            session.name,
+            shimdir,
+            session.name
+        ),
+        _ => {}
+    }
+    println!("source {}", shell_escape::escape(session.env_file.to_string_lossy()));
+    Ok(())
+}
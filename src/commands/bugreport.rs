@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use clap::{Arg, ArgMatches, Command};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::commands::version::{ENGINES, engine_version};
+use crate::config::load_cli_config;
+use crate::consts;
+use crate::manifest_cache;
+use crate::trace;
+
+/// Number of trace bundles included from the trace directory.
+const RECENT_TRACES: usize = 5;
+
+pub fn create_cli() -> Command {
+    Command::new("bugreport")
+        .about("Collect version, engine, config and recent failure details for a GitHub issue")
+        .after_help("\
+EXAMPLES:
+  bulker bugreport > report.md
+  bulker bugreport -o report.md
+  bulker bugreport --trace-dir /tmp/bulker-trace   # include recent BULKER_TRACE bundles
+
+The report is Markdown. Secrets in the config are redacted and your home
+directory is shown as ~, but review the report before posting it.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Write the report to FILE instead of stdout"),
+        )
+        .arg(
+            Arg::new("trace-dir")
+                .long("trace-dir")
+                .value_name("DIR")
+                .help("Trace directory to include bundles from (default: $BULKER_TRACE)"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let mut report = build_report(matches)?;
+    if !home.is_empty() && home != "/" {
+        report = report.replace(&home, "~");
+    }
+
+    match matches.get_one::<String>("output") {
+        Some(path) => {
+            std::fs::write(path, &report).with_context(|| format!("Failed to write {}", path))?;
+            eprintln!("Wrote {}. Review it before attaching it to an issue.", path);
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+fn build_report(matches: &ArgMatches) -> Result<String> {
+    let mut out = String::from("# bulker bug report\n\n");
+
+    writeln!(out, "## bulker\n")?;
+    writeln!(out, "- version: {}", consts::VERSION)?;
+    writeln!(out, "- commit: {}", consts::GIT_COMMIT)?;
+    writeln!(out, "- built: {}", consts::BUILD_DATE)?;
+    writeln!(out, "- target: {}", consts::TARGET)?;
+
+    writeln!(out, "\n## System\n")?;
+    writeln!(out, "- os: {}", os_description())?;
+    writeln!(out, "- shell: {}", std::env::var("SHELL").unwrap_or_else(|_| "unknown".to_string()))?;
+    for name in ["BULKERCRATE", "BULKERCFG", "BULKER_SHIMDIR", "XDG_CACHE_HOME", "TMPDIR"] {
+        if let Ok(value) = std::env::var(name) {
+            writeln!(out, "- {}: {}", name, value)?;
+        }
+    }
+
+    writeln!(out, "\n## Engines\n")?;
+    for engine in ENGINES {
+        writeln!(out, "- {}: {}", engine, engine_version(engine).as_deref().unwrap_or("not found"))?;
+    }
+
+    writeln!(out, "\n## Config\n")?;
+    match load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str())) {
+        Ok((config, path)) => {
+            writeln!(
+                out,
+                "File: {}\n",
+                path.map(|p| p.display().to_string()).unwrap_or_else(|| "(defaults, no config file)".to_string())
+            )?;
+            let yaml = serde_yml::to_string(&config).unwrap_or_else(|e| format!("# failed to serialize: {}\n", e));
+            writeln!(out, "```yaml\n{}```", redact_yaml(&yaml))?;
+        }
+        Err(e) => writeln!(out, "Failed to load config: {:#}", e)?,
+    }
+
+    writeln!(out, "\n## Cache\n")?;
+    writeln!(out, "Directory: {}\n", manifest_cache::cache_base_dir().display())?;
+    match manifest_cache::list_cached() {
+        Ok(cached) if cached.is_empty() => writeln!(out, "No cached crates.")?,
+        Ok(cached) => {
+            for (cv, _) in &cached {
+                writeln!(out, "- {}", cv.display_name())?;
+            }
+        }
+        Err(e) => writeln!(out, "Failed to list cache: {:#}", e)?,
+    }
+
+    writeln!(out, "\n## Last failing command\n")?;
+    match std::fs::read_to_string(trace::last_failure_path()) {
+        Ok(json) => writeln!(out, "```json\n{}```", json)?,
+        Err(_) => writeln!(out, "None recorded.")?,
+    }
+
+    let trace_dir = matches
+        .get_one::<String>("trace-dir")
+        .cloned()
+        .or_else(|| std::env::var(trace::TRACE_ENV).ok().filter(|d| !d.is_empty()));
+    if let Some(dir) = trace_dir {
+        writeln!(out, "\n## Recent traces\n")?;
+        let bundles = trace::recent_bundles(Path::new(&dir), RECENT_TRACES);
+        if bundles.is_empty() {
+            writeln!(out, "No trace bundles in {}.", dir)?;
+        }
+        for bundle in bundles {
+            let name = bundle.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let json = std::fs::read_to_string(bundle.join("trace.json")).unwrap_or_default();
+            writeln!(out, "### {}\n\n```json\n{}```\n", name, json)?;
+        }
+    }
+
+    Ok(out)
+}
+
+/// OS name and version from /etc/os-release (Linux) or `sw_vers` (macOS),
+/// plus the architecture.
+fn os_description() -> String {
+    let name = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|s| {
+            s.lines()
+                .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+                .map(|v| v.trim_matches('"').to_string())
+        })
+        .or_else(|| {
+            let output = std::process::Command::new("sw_vers").arg("-productVersion").output().ok()?;
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!version.is_empty()).then(|| format!("macOS {}", version))
+        })
+        .unwrap_or_else(|| std::env::consts::OS.to_string());
+    format!("{} ({})", name, std::env::consts::ARCH)
+}
+
+/// Replace values of secret-looking keys with `<redacted>`.
+fn redact_yaml(yaml: &str) -> String {
+    let mut out = String::new();
+    for line in yaml.lines() {
+        let redacted = line.split_once(':').and_then(|(key, value)| {
+            let k = key.trim().trim_start_matches("- ").to_lowercase();
+            let secret = trace::SECRET_KEY_HINTS.iter().any(|hint| k.contains(hint));
+            (secret && !value.trim().is_empty()).then(|| format!("{}: <redacted>", key))
+        });
+        out.push_str(redacted.as_deref().unwrap_or(line));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_yaml() {
+        let yaml = "bulker:\n  container_engine: docker\n  registry_token: abc123\n  auth:\n    password: hunter2\n";
+        let out = redact_yaml(yaml);
+        assert!(out.contains("  container_engine: docker\n"));
+        assert!(out.contains("  registry_token: <redacted>\n"));
+        assert!(out.contains("    password: <redacted>\n"));
+        assert!(out.contains("  auth:\n"));
+        assert!(!out.contains("abc123") && !out.contains("hunter2"));
+    }
+}
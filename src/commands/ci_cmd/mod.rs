@@ -0,0 +1,26 @@
+pub mod setup;
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+pub fn create_cli() -> Command {
+    Command::new("ci")
+        .about("Helpers for continuous integration")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .global(true)
+                .help("Bulker configuration file"),
+        )
+        .subcommand(setup::create_cli())
+}
+
+pub fn dispatch(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("setup", sub_m)) => setup::run(sub_m),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io::Write;
+use std::path::Path;
+
+use crate::activate::{ActivationFormat, get_new_path, render_activation};
+use crate::config::load_cli_config;
+use crate::manifest::{is_local_path, is_url, load_local_manifest, load_url_manifest, parse_registry_paths, CommandsFilter};
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("setup")
+        .about("Prepare a crate environment for later CI steps")
+        .after_help("\
+EXAMPLES:
+  bulker ci setup bulker/demo                  # in GitHub Actions: writes $GITHUB_ENV/$GITHUB_PATH
+  bulker ci setup -b databio/pepatac:1.0.13    # also pull container images
+  eval \"$(bulker ci setup bulker/demo)\"        # other CIs: prints export statements
+
+Inside GitHub Actions ($GITHUB_ENV and $GITHUB_PATH set), the crate's commands
+are added to PATH for all subsequent steps, output is grouped, and failures are
+reported as error annotations. Elsewhere, export statements are printed.")
+        .arg(
+            Arg::new("crate_registry_paths")
+                .required(true)
+                .help("Crate(s) to set up (comma-separated for multiple, or a local .yaml/.json file)"),
+        )
+        .arg(
+            Arg::new("build")
+                .short('b')
+                .long("build")
+                .action(ArgAction::SetTrue)
+                .help("Build/pull container images"),
+        )
+        .arg(
+            Arg::new("host_env")
+                .short('H')
+                .long("host-env")
+                .action(ArgAction::SetTrue)
+                .help("Forward all host environment variables (overrides allowlist)"),
+        )
+        .arg(
+            Arg::new("name")
+                .short('n')
+                .long("name")
+                .help("Override crate identity for local manifests (e.g., bulker/biobase:0.1.0)"),
+        )
+}
+
+/// Paths of the GitHub Actions environment files, if running under Actions.
+fn github_env_files() -> Option<(String, String)> {
+    let env = std::env::var("GITHUB_ENV").ok().filter(|s| !s.is_empty())?;
+    let path = std::env::var("GITHUB_PATH").ok().filter(|s| !s.is_empty())?;
+    Some((env, path))
+}
+
+/// `message` escaped for a GitHub Actions workflow command, which ends at the
+/// first newline.
+fn escape_workflow_data(message: &str) -> String {
+    message.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
+    let github = github_env_files();
+
+    if github.is_some() {
+        println!("::group::bulker ci setup {}", escape_workflow_data(registry_paths));
+    }
+    let result = setup(matches, registry_paths, github.as_ref());
+    if github.is_some() {
+        println!("::endgroup::");
+        if let Err(ref e) = result {
+            // Workflow commands are single-line; keep the full chain on one line.
+            println!("::error title=bulker ci setup::{}", escape_workflow_data(&format!("{:#}", e)));
+        }
+    }
+    result
+}
+
+fn setup(matches: &ArgMatches, registry_paths: &str, github: Option<&(String, String)>) -> Result<()> {
+    let (config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let name_override = matches.get_one::<String>("name").map(|s| s.as_str());
+
+    let cratelist = if is_url(registry_paths) {
+        let (cv, manifest) = load_url_manifest(registry_paths, name_override, &config.bulker.default_namespace)?;
+        manifest_cache::save_to_cache(&cv, &manifest)?;
+        vec![cv]
+    } else if is_local_path(registry_paths) {
+        let (cv, manifest) = load_local_manifest(registry_paths, name_override, &config.bulker.default_namespace)?;
+        manifest_cache::save_to_cache(&cv, &manifest)?;
+        vec![cv]
+    } else {
+        crate::manifest_cache::resolve_tag_aliases(parse_registry_paths(registry_paths, &config.bulker.default_namespace)?)
+    };
+
+    let result = get_new_path(&config, &cratelist, &CommandsFilter::default(), false, false)?;
+    if let Err(e) = crate::config::write_config_snapshot(Path::new(&result.shimdir), &config, config_path.as_deref()) {
+        log::debug!("{:#}", e);
+    }
+
+    if matches.get_flag("build") {
+        for cv in crate::imports::resolve_cratevars_with_imports(&config, &cratelist)? {
+            if let Some(manifest) = manifest_cache::load_cached(&cv)? {
+                manifest_cache::pull_crate_images(&config, &cv, &manifest)?;
+            }
+        }
+    }
+
+    let crate_id = cratelist
+        .iter()
+        .map(|cv| cv.display_name())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut vars: Vec<(&str, String)> = vec![("BULKERCRATE", crate_id.clone())];
+    if let Some(cp) = &config_path {
+        vars.push(("BULKERCFG", cp.display().to_string()));
+    }
+    if matches.get_flag("host_env") {
+        vars.push(("BULKER_HOST_ENV", "1".to_string()));
+    }
+    vars.push(("BULKER_SHIMDIR", result.shimdir.clone()));
+
+    match github {
+        Some((env_file, path_file)) => {
+            let env_lines: String = vars.iter().map(|(k, v)| format!("{}={}\n", k, v)).collect();
+            append_to_file(env_file, &env_lines)?;
+            append_to_file(path_file, &format!("{}\n", result.shimdir))?;
+            println!("Set up {} (commands on PATH for subsequent steps)", crate_id);
+        }
+        None => {
+            print!("{}", render_activation(ActivationFormat::Shell, &vars, &result.shimdir, &result.path, false)?);
+        }
+    }
+    Ok(())
+}
+
+/// Append text to a CI environment file (e.g., $GITHUB_ENV).
+fn append_to_file(path: &str, content: &str) -> Result<()> {
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path))?;
+    f.write_all(content.as_bytes())
+        .with_context(|| format!("Failed to write {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_workflow_data() {
+        assert_eq!(escape_workflow_data("plain"), "plain");
+        assert_eq!(
+            escape_workflow_data("not found in any registry:\n  a: 100%\r\n  b"),
+            "not found in any registry:%0A  a: 100%25%0D%0A  b"
+        );
+    }
+}
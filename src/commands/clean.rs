@@ -0,0 +1,75 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::load_cli_config;
+use crate::containers;
+
+pub fn create_cli() -> Command {
+    Command::new("clean")
+        .about("Remove containers left behind by bulker runs that were killed")
+        .after_help("\
+EXAMPLES:
+  bulker clean --containers --dry-run   # list orphaned containers
+  bulker clean --containers
+
+A container outlives a bulker process killed with SIGKILL (or an OOM kill),
+since only bulker would have stopped it. With `reap_containers: true` in the
+config, each run cleans up after killed earlier runs automatically, and bulker
+labels the containers it starts with its own pid and host, so containers from
+this host whose bulker process is gone can be found and removed here (docker
+and podman only). Cached crate manifests are removed with
+`bulker crate clean`.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("containers")
+                .long("containers")
+                .action(ArgAction::SetTrue)
+                .required(true)
+                .help("Remove containers whose bulker process is gone"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("List the containers without removing them"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    if config.is_apptainer() {
+        bail!("`bulker clean --containers` requires docker: apptainer runs commands as host processes, not managed containers.");
+    }
+    let engine_path = config.engine_path();
+    let dry_run = matches.get_flag("dry-run");
+
+    let orphans = containers::list_orphans(engine_path)?;
+    if orphans.is_empty() {
+        println!("No orphaned bulker containers.");
+    } else {
+        for orphan in &orphans {
+            println!(
+                "{}{} ({}, started by pid {})",
+                if dry_run { "Would remove " } else { "Removing " },
+                orphan.id,
+                orphan.command,
+                orphan.pid
+            );
+        }
+        if !dry_run {
+            let ids: Vec<String> = orphans.into_iter().map(|o| o.id).collect();
+            containers::remove_containers(engine_path, &ids)?;
+            println!("Removed {} container(s)", ids.len());
+        }
+    }
+    if !dry_run {
+        // Their containers are gone now; drop the stale registrations too
+        containers::reap_registered(engine_path);
+    }
+    Ok(())
+}
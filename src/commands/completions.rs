@@ -1,20 +1,206 @@
 use anyhow::Result;
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use clap_complete::{Shell, generate};
-use std::io;
+use std::io::{self, Write};
+
+use crate::config::load_cli_config;
+use crate::manifest::{is_local_path, load_local_manifest, parse_registry_paths};
 
 pub fn create_cli() -> Command {
     Command::new("completions")
         .about("Generate shell completions")
+        .after_help("\
+EXAMPLES:
+  source <(bulker completions bash)
+  bulker completions zsh > \"${fpath[1]}/_bulker\"
+
+The bash and zsh scripts also complete cached crates (bulker activate <TAB>,
+bulker crate inspect <TAB>) and a crate's commands (bulker exec <crate> -- <TAB>).")
         .arg(
             Arg::new("shell")
-                .required(true)
+                .required_unless_present_any(["crates", "exec-commands"])
                 .value_parser(["bash", "zsh", "fish"])
                 .help("Shell type"),
         )
+        .arg(
+            Arg::new("crates")
+                .long("crates")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+                .help("Print the cached crates, for the completion scripts"),
+        )
+        .arg(
+            Arg::new("exec-commands")
+                .long("exec-commands")
+                .num_args(0..)
+                .allow_hyphen_values(true)
+                .hide(true)
+                .help("Print the commands of the crates a `bulker exec` command line names, for the completion scripts"),
+        )
+}
+
+/// Subcommands whose first argument is a crate: `top-level,subcommand`
+/// pairs, as the shell functions match them.
+const CRATE_ARG_COMMANDS: &[&str] = &[
+    "activate,activate",
+    "exec,exec",
+    "prefetch,prefetch",
+    "crate,alias",
+    "crate,annotate",
+    "crate,clean",
+    "crate,compare",
+    "crate,digest",
+    "crate,export",
+    "crate,export-spack",
+    "crate,graph",
+    "crate,inspect",
+    "crate,lock",
+    "crate,scan",
+    "crate,wrappers",
+];
+
+/// Wraps clap's `_bulker`. Words are re-read from COMP_LINE because bash
+/// splits `namespace/crate:tag` at the colon.
+const BASH_DYNAMIC: &str = r#"
+# Set COMPREPLY to the lines the command "${@:2}" prints that start with $1,
+# trimmed to what follows the last ':' in $1 (bash splits words at ':').
+_bulker_complete_from() {
+    local cur="$1" candidate
+    shift
+    COMPREPLY=()
+    while IFS= read -r candidate; do
+        [[ "$candidate" == "$cur"* ]] && COMPREPLY+=("${candidate#"${cur%"${cur##*:}"}"}")
+    done < <("$@" 2>/dev/null)
+}
+
+_bulker_dynamic() {
+    local line="${COMP_LINE:0:COMP_POINT}" cur prev
+    local -a words
+    read -ra words <<< "$line"
+    if [[ "$line" =~ [[:space:]]$ ]]; then
+        cur=""
+    else
+        cur="${words[${#words[@]}-1]}"
+        unset 'words[${#words[@]}-1]'
+    fi
+    prev="${words[${#words[@]}-1]}"
+    if [[ "${words[1]}" == exec && "$prev" == -- ]]; then
+        _bulker_complete_from "$cur" "$1" completions --exec-commands "${words[@]:2:${#words[@]}-3}"
+        return 0
+    fi
+    if [[ "$cur" != -* ]]; then
+        case "${words[1]},$prev" in
+            @CRATE_ARG_COMMANDS@)
+                _bulker_complete_from "$cur" "$1" completions --crates
+                return 0
+                ;;
+        esac
+    fi
+    _bulker "$@"
+}
+
+"#;
+
+/// Replaces clap's `_bulker`, renamed `_bulker_clap`, so compsys keeps
+/// calling it after the autoloaded file has run once.
+const ZSH_DYNAMIC: &str = r#"_bulker() {
+    local -a candidates
+    if [[ ${words[2]} == exec && ${words[CURRENT-1]} == -- ]]; then
+        candidates=(${(f)"$(${words[1]} completions --exec-commands ${words[3,CURRENT-2]} 2>/dev/null)"})
+        compadd -a candidates
+        return
+    fi
+    if [[ ${words[CURRENT]} != -* ]]; then
+        case "${words[2]},${words[CURRENT-1]}" in
+            (@CRATE_ARG_COMMANDS@)
+                candidates=(${(f)"$(${words[1]} completions --crates 2>/dev/null)"})
+                compadd -a candidates
+                return
+                ;;
+        esac
+    fi
+    _bulker_clap "$@"
+}
+
+"#;
+
+/// Hook the dynamic completions into the script clap generated for `shell`.
+fn add_dynamic_completions(shell: Shell, script: String) -> String {
+    let patterns = CRATE_ARG_COMMANDS.join("|");
+    match shell {
+        Shell::Bash => {
+            let trailer = script.rfind("\nif [[ \"${BASH_VERSINFO[0]}\"").unwrap_or(script.len());
+            let (functions, registration) = script.split_at(trailer);
+            format!(
+                "{}{}{}",
+                functions,
+                BASH_DYNAMIC.replace("@CRATE_ARG_COMMANDS@", &patterns),
+                registration.replace("complete -F _bulker ", "complete -F _bulker_dynamic ")
+            )
+        }
+        Shell::Zsh => {
+            let trailer = script.rfind("if [ \"$funcstack[1]\" = \"_bulker\" ]").unwrap_or(script.len());
+            let (functions, registration) = script.split_at(trailer);
+            format!(
+                "{}{}{}",
+                functions.replacen("\n_bulker() {", "\n_bulker_clap() {", 1),
+                ZSH_DYNAMIC.replace("@CRATE_ARG_COMMANDS@", &patterns),
+                registration
+            )
+        }
+        _ => script,
+    }
+}
+
+/// Cached crates, one `namespace/crate:tag` per line.
+fn print_cached_crates() -> Result<()> {
+    for (cv, _) in crate::manifest_cache::list_cached()? {
+        println!("{}", cv.display_name());
+    }
+    Ok(())
+}
+
+/// Commands of the crates (and their imports) named by the `bulker exec`
+/// arguments `words`, the ones before `--`.
+fn print_exec_commands(words: &[String]) -> Result<()> {
+    let argv = ["bulker", "exec"].into_iter().map(String::from).chain(words.iter().cloned()).chain(["--".to_string(), "_".to_string()]);
+    let matches = crate::build_parser().try_get_matches_from(argv)?;
+    let Some(("exec", exec)) = matches.subcommand() else {
+        return Ok(());
+    };
+    let (config, _config_path) = load_cli_config(exec.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry_paths = exec.get_one::<String>("crate_registry_paths").unwrap();
+
+    let mut commands: Vec<String> = Vec::new();
+    if is_local_path(registry_paths) {
+        let (_, manifest) = load_local_manifest(registry_paths, None, &config.bulker.default_namespace)?;
+        commands.extend(manifest.manifest.commands.into_iter().map(|c| c.command));
+    } else {
+        let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
+        let (all_crates, _missing) = crate::imports::resolve_cratevars_allow_missing_imports(&config, &cratelist)?;
+        for cv in &all_crates {
+            if let Some(manifest) = crate::manifest_cache::load_cached(cv)? {
+                commands.extend(manifest.manifest.commands.into_iter().map(|c| c.command));
+                commands.extend(manifest.manifest.host_commands);
+            }
+        }
+    }
+    commands.sort();
+    commands.dedup();
+    for command in commands {
+        println!("{}", command);
+    }
+    Ok(())
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
+    if matches.get_flag("crates") {
+        return print_cached_crates();
+    }
+    if let Some(words) = matches.get_many::<String>("exec-commands") {
+        return print_exec_commands(&words.cloned().collect::<Vec<_>>());
+    }
+
     let shell_name = matches.get_one::<String>("shell").unwrap();
     let shell: Shell = match shell_name.as_str() {
         "bash" => Shell::Bash,
@@ -24,6 +210,35 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
     };
 
     let mut cmd = crate::build_parser();
-    generate(shell, &mut cmd, "bulker", &mut io::stdout());
+    let mut script = Vec::new();
+    generate(shell, &mut cmd, "bulker", &mut script);
+    let script = add_dynamic_completions(shell, String::from_utf8_lossy(&script).into_owned());
+    io::stdout().write_all(script.as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(shell: Shell) -> String {
+        let mut script = Vec::new();
+        generate(shell, &mut crate::build_parser(), "bulker", &mut script);
+        add_dynamic_completions(shell, String::from_utf8(script).unwrap())
+    }
+
+    #[test]
+    fn test_dynamic_completions_are_registered() {
+        let bash = script(Shell::Bash);
+        assert!(bash.contains("_bulker_dynamic() {"));
+        assert!(bash.contains("complete -F _bulker_dynamic ") && !bash.contains("complete -F _bulker "));
+        assert!(bash.contains("exec,exec|prefetch,prefetch|crate,alias"));
+        assert!(bash.find("_bulker_dynamic() {").unwrap() < bash.find("complete -F").unwrap());
+
+        let zsh = script(Shell::Zsh);
+        assert_eq!(zsh.matches("\n_bulker() {").count(), 1);
+        assert!(zsh.contains("\n_bulker_clap() {") && zsh.contains("    _bulker_clap \"$@\"\n"));
+        assert!(zsh.find("\n_bulker_clap() {").unwrap() < zsh.find("\n_bulker() {").unwrap());
+        assert!(zsh.contains("compdef _bulker bulker"));
+    }
+}
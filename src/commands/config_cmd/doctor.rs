@@ -0,0 +1,200 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde_yml::Value;
+use std::path::Path;
+
+use crate::config::{BulkerConfig, expand_path, locate_config_file, resolve_engine_path, write_config_file};
+
+pub fn create_cli() -> Command {
+    Command::new("doctor")
+        .about("Find (and with --fix, repair) common problems in the config file")
+        .after_help("\
+EXAMPLES:
+  bulker config doctor          # list problems and the changes --fix would make
+  bulker config doctor --fix    # apply them
+
+Checks for an engine_path that no longer exists (re-resolved from
+container_engine), a missing or incomplete templates/ directory next to the
+config (rewritten from the templates built into bulker), \"null\" strings that
+should be empty values, and volumes entries with stray whitespace, trailing
+slashes or duplicates. The config is rewritten atomically and the previous
+version kept as a backup (see `bulker config restore`). Exits 1 if problems
+remain.")
+        .arg(
+            Arg::new("fix")
+                .long("fix")
+                .action(ArgAction::SetTrue)
+                .help("Apply the fixes"),
+        )
+}
+
+/// Fix the `bulker:` settings of a parsed config in place. Returns a note
+/// per problem fixed.
+fn fix_settings(settings: &mut serde_yml::Mapping) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    // Dropping the key lets the setting fall back to its default
+    settings.retain(|key, value| {
+        let null = value.as_str().is_some_and(|s| s == "null" || s == "~");
+        if null {
+            notes.push(format!("{}: the string \"null\" stands for no value; removing the key", key.as_str().unwrap_or("?")));
+        }
+        !null
+    });
+
+    let engine = settings.get("container_engine").and_then(Value::as_str).unwrap_or("docker").to_string();
+    if let Some(path) = settings.get("engine_path").and_then(Value::as_str).map(str::to_string) {
+        let expanded = expand_path(&path);
+        if !Path::new(&expanded).is_file() && resolve_engine_path(&expanded).is_none() {
+            let resolved = resolve_engine_path(&engine);
+            notes.push(format!(
+                "engine_path: {} does not exist; {}",
+                path,
+                resolved.as_ref().map_or_else(|| format!("{} is not on PATH either, unsetting it", engine), |r| format!("{} is at {}", engine, r))
+            ));
+            settings.insert(Value::from("engine_path"), resolved.map_or(Value::Null, Value::from));
+        }
+    }
+
+    if let Some(volumes) = settings.get_mut("volumes") {
+        // A comma-separated string, as `config set volumes=a,b` would take
+        let entries: Vec<String> = match volumes {
+            Value::String(s) => s.split(',').map(str::to_string).collect(),
+            Value::Sequence(seq) => seq.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        };
+        let mut normalized: Vec<String> = Vec::new();
+        for entry in &entries {
+            let entry = entry.trim();
+            let entry = if entry.len() > 1 { entry.trim_end_matches('/') } else { entry };
+            if !entry.is_empty() && !normalized.iter().any(|v| v == entry) {
+                normalized.push(entry.to_string());
+            }
+        }
+        let fixed = Value::Sequence(normalized.into_iter().map(Value::from).collect());
+        if matches!(volumes, Value::String(_) | Value::Sequence(_)) && *volumes != fixed {
+            notes.push("volumes: trimmed whitespace and trailing slashes, dropped empty and duplicate entries".to_string());
+            *volumes = fixed;
+        }
+    }
+    notes
+}
+
+/// Line diff of `old` and `new` (longest common subsequence), with `-`/`+`
+/// marking removed and added lines.
+fn line_diff(old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+/// The config text with the fixes applied, keeping its leading comment
+/// block. None if nothing needs fixing.
+fn fixed_config(contents: &str) -> Result<Option<(String, Vec<String>)>> {
+    let mut value: Value = serde_yml::from_str(contents)?;
+    let Some(settings) = value.get_mut("bulker").and_then(Value::as_mapping_mut) else {
+        bail!("No `bulker:` section");
+    };
+    let notes = fix_settings(settings);
+    if notes.is_empty() {
+        return Ok(None);
+    }
+    let header: String = contents.lines().take_while(|l| l.starts_with('#')).map(|l| format!("{}\n", l)).collect();
+    Ok(Some((header + &serde_yml::to_string(&value)?, notes)))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let config_path = locate_config_file(matches.get_one::<String>("config").map(|s| s.as_str()));
+    let fix = matches.get_flag("fix");
+    if !config_path.exists() {
+        bail!("No config file at {}; create one with `bulker config init`", config_path.display());
+    }
+    let contents = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+    let fixed = fixed_config(&contents).with_context(|| format!("Failed to parse config: {}", config_path.display()))?;
+
+    let templates_dir = config_path.parent().unwrap_or_else(|| Path::new(".")).join("templates");
+    let missing_templates: Vec<&str> =
+        crate::templates::TEMPLATE_FILES.iter().map(|(f, _)| *f).filter(|f| !templates_dir.join(f).is_file()).collect();
+
+    if fixed.is_none() && missing_templates.is_empty() {
+        println!("No problems found in {}", config_path.display());
+        return Ok(());
+    }
+    println!("{}:", config_path.display());
+    if !missing_templates.is_empty() {
+        println!("  ✗ templates: missing {} in {}", missing_templates.join(", "), templates_dir.display());
+    }
+    if let Some((ref new_contents, ref notes)) = fixed {
+        for note in notes {
+            println!("  ✗ {}", note);
+        }
+        println!();
+        print!("{}", line_diff(&contents, new_contents));
+    }
+
+    if !fix {
+        println!();
+        println!("Run `bulker config doctor --fix` to apply these changes.");
+        return Err(crate::process::ExitWith(1).into());
+    }
+    if !missing_templates.is_empty() {
+        crate::templates::write_templates_to_dir(&templates_dir)?;
+        println!("Rewrote templates in {}", templates_dir.display());
+    }
+    if let Some((new_contents, _)) = fixed {
+        // Never write a config that bulker itself would fail to load
+        serde_yml::from_str::<BulkerConfig>(&new_contents).context("The fixed config does not parse; leaving the file as is")?;
+        write_config_file(&config_path, &new_contents)?;
+        println!("Fixed {}", config_path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_config() {
+        let contents = "# Auto-generated by bulker. Edit to customize.\nbulker:\n  container_engine: docker\n  \
+                        engine_path: /nonexistent/bin/docker\n  shell_prompt: 'null'\n  volumes:\n  - '$HOME/'\n  - ' /data '\n  - $HOME\n  - ''\n";
+        let (fixed, notes) = fixed_config(contents).unwrap().unwrap();
+        assert_eq!(notes.len(), 3, "{:?}", notes);
+        assert!(fixed.starts_with("# Auto-generated by bulker. Edit to customize.\nbulker:\n"), "{}", fixed);
+        let config: BulkerConfig = serde_yml::from_str(&fixed).unwrap();
+        assert_eq!(config.bulker.shell_prompt, None);
+        assert_eq!(config.bulker.volumes, vec!["$HOME", "/data"]);
+        assert_eq!(config.bulker.engine_path, resolve_engine_path("docker"));
+
+        // A clean config needs nothing
+        assert!(fixed_config(&fixed).unwrap().is_none());
+        assert!(fixed_config("other: 1\n").is_err());
+    }
+
+    #[test]
+    fn test_line_diff() {
+        assert_eq!(line_diff("a\nb\nc\n", "a\nB\nc\nd\n"), "  a\n- b\n+ B\n  c\n+ d\n");
+    }
+}
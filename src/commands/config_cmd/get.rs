@@ -1,20 +1,18 @@
-use anyhow::{Result, bail};
+use anyhow::Result;
 use clap::{Arg, ArgMatches, Command};
 
-use crate::config::load_config;
+use crate::config::load_cli_config;
 
 pub fn create_cli() -> Command {
     Command::new("get")
         .about("Get a configuration value")
-        .after_help("\
+        .after_help(format!("\
 EXAMPLES:
   bulker config get envvars
   bulker config get container_engine
   bulker config get shell_path
 
-SUPPORTED KEYS:
-  container_engine, default_namespace, registry_url,
-  shell_path, shell_rc, envvars, volumes, shell_prompt, apptainer_image_folder")
+{}", super::supported_keys_help()))
         .arg(
             Arg::new("key")
                 .required(true)
@@ -23,13 +21,18 @@ SUPPORTED KEYS:
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, _config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
     let key = matches.get_one::<String>("key").unwrap();
 
     match key.as_str() {
         "container_engine" => println!("{}", config.bulker.container_engine),
         "default_namespace" => println!("{}", config.bulker.default_namespace),
         "registry_url" => println!("{}", config.bulker.registry_url),
+        "registry_urls" => {
+            for url in &config.bulker.registry_urls {
+                println!("{}", url);
+            }
+        }
         "shell_path" => println!("{}", config.bulker.shell_path),
         "shell_rc" => println!("{}", config.bulker.shell_rc),
         "envvars" => {
@@ -42,6 +45,11 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
                 println!("{}", v);
             }
         }
+        "host_commands" => {
+            for v in &config.bulker.host_commands {
+                println!("{}", v);
+            }
+        }
         "shell_prompt" => {
             if let Some(ref p) = config.bulker.shell_prompt {
                 println!("{}", p);
@@ -52,7 +60,107 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
                 println!("{}", f);
             }
         }
-        _ => bail!("Unknown config key: '{}'. Supported keys: container_engine, default_namespace, registry_url, shell_path, shell_rc, envvars, volumes, shell_prompt, apptainer_image_folder", key),
+        "warn_on_shadow" => println!("{}", config.bulker.warn_on_shadow),
+        "no_init" => println!("{}", config.bulker.no_init),
+        "isolate_home" => println!("{}", config.bulker.isolate_home),
+        "umask" => {
+            if let Some(ref m) = config.bulker.umask {
+                println!("{}", m);
+            }
+        }
+        "cpu_binding" => {
+            if let Some(ref b) = config.bulker.cpu_binding {
+                println!("{}", b);
+            }
+        }
+        "cpus" | "memory" | "shm_size" => {
+            let setting = match key.as_str() {
+                "cpus" => &config.bulker.cpus,
+                "memory" => &config.bulker.memory,
+                _ => &config.bulker.shm_size,
+            };
+            if let Some(ref value) = *setting {
+                println!("{}", value);
+            }
+        }
+        "chown_outputs" => println!("{}", config.bulker.chown_outputs),
+        "memoize" => println!("{}", config.bulker.memoize),
+        "reap_containers" => println!("{}", config.bulker.reap_containers),
+        "prewarm" => println!("{}", config.bulker.prewarm),
+        "download_rate_limit" => {
+            if let Some(ref rate) = config.bulker.download_rate_limit {
+                println!("{}", rate);
+            }
+        }
+        "digest_cache_ttl" => {
+            if let Some(ref ttl) = config.bulker.digest_cache_ttl {
+                println!("{}", ttl);
+            }
+        }
+        "scan_api" => {
+            if let Some(ref url) = config.bulker.scan_api {
+                println!("{}", url);
+            }
+        }
+        "scan_api_token" => {
+            if let Some(ref reference) = config.bulker.scan_api_token {
+                println!("{}", reference);
+            }
+        }
+        "publish_url" => {
+            if let Some(ref url) = config.bulker.publish_url {
+                println!("{}", url);
+            }
+        }
+        "publish_token" => {
+            if let Some(ref reference) = config.bulker.publish_token {
+                println!("{}", reference);
+            }
+        }
+        "strict_manifests" => println!("{}", config.bulker.strict_manifests),
+        "cache_dir" => {
+            if let Some(ref d) = config.bulker.cache_dir {
+                println!("{}", d);
+            }
+        }
+        "shimdir_base" => {
+            if let Some(ref b) = config.bulker.shimdir_base {
+                println!("{}", b);
+            }
+        }
+        "shared_shimdir" => {
+            if let Some(ref d) = config.bulker.shared_shimdir {
+                println!("{}", d);
+            }
+        }
+        "import_remap" => {
+            for (from, to) in &config.bulker.import_remap {
+                println!("{} -> {}", from, to);
+            }
+        }
+        "path_map" => {
+            for (host, container) in &config.bulker.path_map {
+                println!("{} -> {}", host, container);
+            }
+        }
+        "registry_auth" => {
+            for (registry, reference) in &config.bulker.registry_auth {
+                println!("{} -> {}", registry, reference);
+            }
+        }
+        "cache_permissions" => {
+            if let Some(ref p) = config.bulker.cache_permissions {
+                println!("{}", p.spec());
+            }
+        }
+        "metrics" => {
+            if let Some(ref m) = config.bulker.metrics {
+                println!("{}", m.spec());
+            }
+        }
+        "auto_mount_mode" => println!("{}", config.bulker.auto_mount_mode),
+        "locale_policy" => println!("{}", config.bulker.locale_policy),
+        key => return Err(super::unknown_key(key)),
     }
 
     Ok(())
@@ -1,15 +1,61 @@
 pub mod add;
+pub mod doctor;
 pub mod get;
 pub mod init;
 pub mod remove;
+pub mod restore;
 pub mod set;
 pub mod show;
 
 use anyhow::Result;
 use clap::{Arg, ArgMatches, Command};
 
+/// Keys `config get` and `config set` accept, for their help and errors.
+const SUPPORTED_KEYS: &[&str] = &[
+    "container_engine", "default_namespace", "registry_url", "registry_urls", "shell_path",
+    "shell_rc", "envvars", "volumes", "host_commands", "shell_prompt", "apptainer_image_folder",
+    "auto_mount_mode", "locale_policy", "shimdir_base", "shared_shimdir", "cache_dir",
+    "cache_permissions", "warn_on_shadow", "no_init", "isolate_home", "umask", "cpu_binding",
+    "cpus", "memory", "shm_size", "chown_outputs", "strict_manifests", "memoize", "reap_containers",
+    "prewarm", "import_remap", "path_map", "registry_auth", "digest_cache_ttl",
+    "download_rate_limit", "scan_api", "scan_api_token", "publish_url", "publish_token", "metrics",
+];
+
+/// The SUPPORTED KEYS section of `config get`/`config set` help.
+fn supported_keys_help() -> String {
+    let mut help = String::from("SUPPORTED KEYS:\n");
+    let mut line = String::new();
+    for key in SUPPORTED_KEYS {
+        if !line.is_empty() && line.len() + 2 + key.len() > 77 {
+            help.push_str(&format!("  {},\n", line));
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push_str(", ");
+        }
+        line.push_str(key);
+    }
+    help.push_str(&format!("  {}", line));
+    help
+}
+
+fn unknown_key(key: &str) -> anyhow::Error {
+    anyhow::anyhow!("Unknown config key: '{}'. Supported keys: {}", key, SUPPORTED_KEYS.join(", "))
+}
+
 fn is_list_key(key: &str) -> bool {
-    matches!(key, "volumes")
+    matches!(key, "volumes" | "host_commands")
+}
+
+/// Reject a list entry that can't be used for `key`.
+fn check_list_entry(key: &str, value: &str) -> Result<()> {
+    if is_envvars_key(key) {
+        return crate::config::check_envvar_entry(value);
+    }
+    if key == "host_commands" && (value.is_empty() || value.contains('/') || value.starts_with('.')) {
+        anyhow::bail!("Invalid host command '{}'. Give a command name, not a path; it is looked up on PATH.", value);
+    }
+    Ok(())
 }
 
 fn is_envvars_key(key: &str) -> bool {
@@ -34,6 +80,8 @@ pub fn create_cli() -> Command {
         .subcommand(set::create_cli())
         .subcommand(add::create_cli())
         .subcommand(remove::create_cli())
+        .subcommand(restore::create_cli())
+        .subcommand(doctor::create_cli())
 }
 
 pub fn dispatch(matches: &ArgMatches) -> Result<()> {
@@ -44,6 +92,22 @@ pub fn dispatch(matches: &ArgMatches) -> Result<()> {
         Some(("set", sub_m)) => set::run(sub_m),
         Some(("add", sub_m)) => add::run(sub_m),
         Some(("remove", sub_m)) => remove::run(sub_m),
+        Some(("restore", sub_m)) => restore::run(sub_m),
+        Some(("doctor", sub_m)) => doctor::run(sub_m),
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_keys_help_lists_every_key() {
+        let help = supported_keys_help();
+        let listed: Vec<&str> = help.lines().skip(1).flat_map(|l| l.split(',')).map(str::trim).filter(|k| !k.is_empty()).collect();
+        assert_eq!(listed, SUPPORTED_KEYS);
+        assert!(help.lines().all(|l| l.len() <= 80), "{}", help);
+        assert!(unknown_key("nope").to_string().ends_with(&SUPPORTED_KEYS.join(", ")));
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::{Result, bail};
 use clap::{Arg, ArgMatches, Command};
 
 use anyhow::Context;
-use crate::config::load_config;
+use crate::config::load_cli_config;
 
 pub fn create_cli() -> Command {
     Command::new("remove")
@@ -14,7 +14,7 @@ EXAMPLES:
         .arg(
             Arg::new("key")
                 .required(true)
-                .help("List field name (envvars, volumes)"),
+                .help("List field name (envvars, volumes, host_commands)"),
         )
         .arg(
             Arg::new("value")
@@ -24,7 +24,7 @@ EXAMPLES:
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (mut config, config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
     let config_path = config_path.context("No config file to write to. Run `bulker config init` first.")?;
     let key = matches.get_one::<String>("key").unwrap();
     let value = matches.get_one::<String>("value").unwrap();
@@ -38,6 +38,7 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
 
     let list = match key.as_str() {
         "volumes" => &mut config.bulker.volumes,
+        "host_commands" => &mut config.bulker.host_commands,
         _ => unreachable!(),
     };
 
@@ -0,0 +1,70 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::{BulkerConfig, CONFIG_BACKUPS, config_backup_path, locate_config_file, write_config_file};
+
+pub fn create_cli() -> Command {
+    Command::new("restore")
+        .about("Restore the config file from one of its automatic backups")
+        .after_help("\
+EXAMPLES:
+  bulker config restore --list            # show the available backups
+  bulker config restore --from-backup     # restore the newest backup
+  bulker config restore --from-backup 3   # restore the third newest
+
+Every command that rewrites the config (config set/add/remove, env, registry
+login/logout) first keeps the previous version in backups/ next to the config
+file, up to 5. Restoring is itself a rewrite, so it can be undone the same way.")
+        .arg(
+            Arg::new("from-backup")
+                .long("from-backup")
+                .value_name("N")
+                .num_args(0..=1)
+                .default_missing_value("1")
+                .value_parser(clap::value_parser!(usize))
+                .required_unless_present("list")
+                .help("Backup to restore, 1 being the newest"),
+        )
+        .arg(
+            Arg::new("list")
+                .long("list")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("from-backup")
+                .help("List the available backups"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    // Not load_config: the point is to recover a config that may not parse
+    let config_path = locate_config_file(matches.get_one::<String>("config").map(|s| s.as_str()));
+
+    if matches.get_flag("list") {
+        let mut found = false;
+        for n in 1..=CONFIG_BACKUPS {
+            let backup = config_backup_path(&config_path, n);
+            if let Ok(meta) = std::fs::metadata(&backup) {
+                let age = meta.modified().ok().and_then(|m| m.elapsed().ok()).unwrap_or_default();
+                println!("{}  {} ago  {}", n, crate::humanize::format_duration(age), backup.display());
+                found = true;
+            }
+        }
+        if !found {
+            println!("No backups of {}", config_path.display());
+        }
+        return Ok(());
+    }
+
+    let n = *matches.get_one::<usize>("from-backup").unwrap();
+    if n == 0 || n > CONFIG_BACKUPS {
+        bail!("Backups are numbered 1 (newest) to {}", CONFIG_BACKUPS);
+    }
+    let backup = config_backup_path(&config_path, n);
+    if !backup.exists() {
+        bail!("No backup {} of {} (see `bulker config restore --list`)", n, config_path.display());
+    }
+    BulkerConfig::from_file(&backup).context("Refusing to restore a backup that is not a valid config")?;
+    let contents = std::fs::read_to_string(&backup).with_context(|| format!("Failed to read {}", backup.display()))?;
+    write_config_file(&config_path, &contents)?;
+    println!("Restored {} from {}", config_path.display(), backup.display());
+    Ok(())
+}
@@ -2,18 +2,44 @@ use anyhow::{Result, bail};
 use clap::{Arg, ArgMatches, Command};
 
 use anyhow::Context;
-use crate::config::load_config;
+use crate::config::{BulkerConfig, load_cli_config};
+use std::collections::BTreeMap;
 
 pub fn create_cli() -> Command {
     Command::new("set")
         .about("Set a configuration value")
-        .after_help("\
+        .after_help(format!("\
 EXAMPLES:
   bulker config set container_engine=apptainer
   bulker config set envvars=HOME,DISPLAY,LANG
   bulker config set shell_path=/bin/zsh
+  bulker config set auto_mount_mode=ro-inputs
+  bulker config set locale_policy=c.utf8     # LANG=C.UTF-8 in containers
+  bulker config set 'import_remap=bulker/coreutils->myorg/coreutils-arm:1.2'
+  bulker config set 'path_map+=/mnt/gpfs->/gpfs'   # storage mounted elsewhere on this node
+  bulker config set download_rate_limit=5M      # cap downloads at 5 MiB/s
+  bulker config set metrics=textfile:/var/lib/node_exporter/textfile/bulker.prom
+  bulker config set volumes+=/scratch          # add to a list (no-op if present)
+  bulker config set volumes-=/scratch          # remove from a list (no-op if absent)
+  bulker config set host_commands+=git,make    # host tools strict activations keep
+  bulker config set registry_urls=http://mirror.example.org/,http://hub.bulker.io/
+  bulker config set 'import_remap+=bulker/coreutils->myorg/coreutils'
+  bulker config set import_remap-=bulker/coreutils
 
-For list fields (envvars, volumes), use comma-separated values.")
+For list fields (envvars, volumes, host_commands, registry_urls), use comma-separated
+values; registry_urls are tried in order and replace registry_url. import_remap
+takes comma-separated FROM->TO pairs and replaces the whole map (empty clears it);
+path_map takes HOST_PREFIX->CONTAINER_PREFIX pairs the same way.
+registry_auth takes REGISTRY->env:VAR or REGISTRY->file:PATH pairs (optionally USER@env:VAR) the same way;
+`bulker registry login` is the usual way to set it. metrics takes
+textfile:PATH or statsd:HOST:PORT (empty turns metrics off). cache_permissions
+takes MODE[:GROUP], e.g. 0664:lab. scan_api_token and publish_token take env:VAR or file:PATH.
+
+KEY+=VALUE and KEY-=VALUE add or remove entries of a list (envvars, volumes, host_commands,
+registry_urls) or map (import_remap, path_map, registry_auth) without touching the others, so running the
+same command twice leaves the config unchanged. For maps, -= takes the keys.
+
+{}", super::supported_keys_help()))
         .arg(
             Arg::new("key_value")
                 .required(true)
@@ -22,12 +48,21 @@ For list fields (envvars, volumes), use comma-separated values.")
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (mut config, config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
     let config_path = config_path.context("No config file to write to. Run `bulker config init` first.")?;
     let kv = matches.get_one::<String>("key_value").unwrap();
 
-    let (key, value) = kv.split_once('=')
-        .ok_or_else(|| anyhow::anyhow!("Expected key=value format, got: '{}'", kv))?;
+    let (key, op, value) = split_assignment(kv)?;
+    if op != Op::Set {
+        let (messages, changed) = update_collection(&mut config, key, op, value)?;
+        if changed {
+            config.write(&config_path)?;
+        }
+        for message in messages {
+            println!("{}", message);
+        }
+        return Ok(());
+    }
 
     match key {
         "container_engine" => {
@@ -43,24 +78,407 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         }
         "default_namespace" => config.bulker.default_namespace = value.to_string(),
         "registry_url" => config.bulker.registry_url = value.to_string(),
+        "registry_urls" => config.bulker.registry_urls = entries(value).map(str::to_string).collect(),
         "shell_path" => config.bulker.shell_path = value.to_string(),
         "shell_rc" => config.bulker.shell_rc = value.to_string(),
         "envvars" => {
-            config.bulker.envvars = value.split(',').map(|s| s.trim().to_string()).collect();
+            let names: Vec<String> = entries(value).map(str::to_string).collect();
+            for name in &names {
+                super::check_list_entry(key, name)?;
+            }
+            config.bulker.envvars = names;
         }
         "volumes" => {
             config.bulker.volumes = value.split(',').map(|s| s.trim().to_string()).collect();
         }
+        "host_commands" => {
+            let names: Vec<String> = entries(value).map(str::to_string).collect();
+            for name in &names {
+                super::check_list_entry(key, name)?;
+            }
+            config.bulker.host_commands = names;
+        }
         "shell_prompt" => {
             config.bulker.shell_prompt = if value.is_empty() { None } else { Some(value.to_string()) };
         }
         "apptainer_image_folder" => {
             config.bulker.apptainer_image_folder = if value.is_empty() { None } else { Some(value.to_string()) };
         }
-        _ => bail!("Unknown config key: '{}'. Supported keys: container_engine, default_namespace, registry_url, shell_path, shell_rc, envvars, volumes, shell_prompt, apptainer_image_folder", key),
+        "warn_on_shadow" => {
+            config.bulker.warn_on_shadow = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid warn_on_shadow '{}'. Use true or false.", value))?;
+        }
+        "no_init" => {
+            config.bulker.no_init = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid no_init '{}'. Use true or false.", value))?;
+        }
+        "isolate_home" => {
+            config.bulker.isolate_home = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid isolate_home '{}'. Use true or false.", value))?;
+        }
+        "umask" => {
+            if !value.is_empty() && crate::ownership::parse_umask(value).is_none() {
+                bail!("Invalid umask '{}'. Use an octal mask such as 0022.", value);
+            }
+            config.bulker.umask = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "cpu_binding" => {
+            crate::cpu_binding::CpuBinding::parse(value)?;
+            config.bulker.cpu_binding = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "cpus" => {
+            if !value.is_empty() {
+                crate::manifest::check_cpus(value)?;
+            }
+            config.bulker.cpus = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "memory" | "shm_size" => {
+            if !value.is_empty() {
+                crate::manifest::check_memory_size(value)?;
+            }
+            let setting = if key == "memory" { &mut config.bulker.memory } else { &mut config.bulker.shm_size };
+            *setting = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "digest_cache_ttl" => {
+            if !value.is_empty() {
+                crate::humanize::parse_duration(value)?;
+            }
+            config.bulker.digest_cache_ttl = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "download_rate_limit" => {
+            if !value.is_empty() {
+                crate::humanize::parse_size(value)?;
+            }
+            config.bulker.download_rate_limit = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "scan_api" => {
+            if !value.is_empty() && !crate::manifest::is_url(value) {
+                bail!("Invalid scan_api '{}'. Use an http:// or https:// URL.", value);
+            }
+            config.bulker.scan_api = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "scan_api_token" => {
+            if !value.is_empty() {
+                crate::config::check_credential_ref(value)?;
+            }
+            config.bulker.scan_api_token = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "publish_url" => {
+            if !value.is_empty() && !crate::manifest::is_url(value) {
+                bail!("Invalid publish_url '{}'. Use an http:// or https:// URL.", value);
+            }
+            config.bulker.publish_url = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "publish_token" => {
+            if !value.is_empty() {
+                crate::config::check_credential_ref(value)?;
+            }
+            config.bulker.publish_token = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "chown_outputs" => {
+            config.bulker.chown_outputs = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid chown_outputs '{}'. Use true or false.", value))?;
+        }
+        "strict_manifests" => {
+            config.bulker.strict_manifests = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid strict_manifests '{}'. Use true or false.", value))?;
+        }
+        "memoize" => {
+            config.bulker.memoize = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid memoize '{}'. Use true or false.", value))?;
+        }
+        "reap_containers" => {
+            config.bulker.reap_containers = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid reap_containers '{}'. Use true or false.", value))?;
+        }
+        "prewarm" => {
+            config.bulker.prewarm = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid prewarm '{}'. Use true or false.", value))?;
+        }
+        "cache_dir" => {
+            config.bulker.cache_dir = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "shimdir_base" => {
+            config.bulker.shimdir_base = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "shared_shimdir" => {
+            config.bulker.shared_shimdir = if value.is_empty() { None } else { Some(value.to_string()) };
+        }
+        "path_map" => {
+            config.bulker.path_map = parse_path_map(value)?;
+        }
+        "import_remap" => {
+            config.bulker.import_remap = parse_import_remap(value, &config.bulker.default_namespace)?;
+        }
+        "registry_auth" => {
+            config.bulker.registry_auth = parse_registry_auth(value)?;
+        }
+        "cache_permissions" => {
+            config.bulker.cache_permissions = if value.is_empty() {
+                None
+            } else {
+                Some(crate::cache_permissions::CachePermissions::from_spec(value)?)
+            };
+        }
+        "metrics" => {
+            config.bulker.metrics = if value.is_empty() {
+                None
+            } else {
+                Some(crate::metrics::MetricsConfig::from_spec(value)?)
+            };
+        }
+        "auto_mount_mode" => {
+            if crate::config::AutoMountMode::from_name(value).is_none() {
+                bail!(
+                    "Invalid auto_mount_mode '{}'. Valid values: {}",
+                    value,
+                    crate::config::AutoMountMode::NAMES.join(", ")
+                );
+            }
+            config.bulker.auto_mount_mode = value.to_string();
+        }
+        "locale_policy" => {
+            if crate::config::LocalePolicy::from_name(value).is_none() {
+                bail!(
+                    "Invalid locale_policy '{}'. Valid values: {}",
+                    value,
+                    crate::config::LocalePolicy::NAMES.join(", ")
+                );
+            }
+            config.bulker.locale_policy = value.to_string();
+        }
+        key => return Err(super::unknown_key(key)),
     }
 
     config.write(&config_path)?;
     println!("Set {}={}", key, value);
     Ok(())
 }
+
+/// How `config set` changes a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// `key=value`: replace the value.
+    Set,
+    /// `key+=value`: add entries to a list or map.
+    Add,
+    /// `key-=value`: remove entries from a list or map.
+    Remove,
+}
+
+/// Split `key=value`, `key+=value` or `key-=value`.
+fn split_assignment(kv: &str) -> Result<(&str, Op, &str)> {
+    let (key, value) = kv.split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Expected key=value format, got: '{}'", kv))?;
+    let (key, op) = if let Some(key) = key.strip_suffix('+') {
+        (key, Op::Add)
+    } else if let Some(key) = key.strip_suffix('-') {
+        (key, Op::Remove)
+    } else {
+        (key, Op::Set)
+    };
+    Ok((key, op, value))
+}
+
+fn entries(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+/// Apply `key+=value` or `key-=value` to a list or map key. Returns a message
+/// per entry and whether the config changed.
+fn update_collection(config: &mut BulkerConfig, key: &str, op: Op, value: &str) -> Result<(Vec<String>, bool)> {
+    match key {
+        "volumes" => Ok(update_list(&mut config.bulker.volumes, key, op, value)),
+        "registry_urls" => Ok(update_list(&mut config.bulker.registry_urls, key, op, value)),
+        "envvars" | "host_commands" => {
+            if op == Op::Add {
+                entries(value).try_for_each(|name| super::check_list_entry(key, name))?;
+            }
+            let list = if key == "envvars" { &mut config.bulker.envvars } else { &mut config.bulker.host_commands };
+            Ok(update_list(list, key, op, value))
+        }
+        "import_remap" => {
+            let default_namespace = config.bulker.default_namespace.clone();
+            let entries = match op {
+                Op::Add => parse_import_remap(value, &default_namespace)?,
+                _ => map_keys(value, str::to_string),
+            };
+            Ok(update_map(&mut config.bulker.import_remap, key, op, entries))
+        }
+        "path_map" => {
+            let entries = match op {
+                Op::Add => parse_path_map(value)?,
+                _ => map_keys(value, normalize_prefix),
+            };
+            Ok(update_map(&mut config.bulker.path_map, key, op, entries))
+        }
+        "registry_auth" => {
+            let entries = match op {
+                Op::Add => parse_registry_auth(value)?,
+                _ => map_keys(value, crate::config::normalize_registry),
+            };
+            Ok(update_map(&mut config.bulker.registry_auth, key, op, entries))
+        }
+        _ => bail!(
+            "'{}' is not a list or map key. `+=` and `-=` work with envvars, volumes, host_commands, registry_urls, import_remap, path_map and registry_auth.",
+            key
+        ),
+    }
+}
+
+fn update_list(list: &mut Vec<String>, key: &str, op: Op, value: &str) -> (Vec<String>, bool) {
+    let mut messages = Vec::new();
+    let mut changed = false;
+    for entry in entries(value) {
+        let pos = list.iter().position(|v| v == entry);
+        match (op, pos) {
+            (Op::Remove, Some(pos)) => {
+                list.remove(pos);
+                messages.push(format!("Removed '{}' from {}", entry, key));
+                changed = true;
+            }
+            (Op::Remove, None) => messages.push(format!("'{}' not found in {}", entry, key)),
+            (_, Some(_)) => messages.push(format!("'{}' already in {}", entry, key)),
+            (_, None) => {
+                list.push(entry.to_string());
+                messages.push(format!("Added '{}' to {}", entry, key));
+                changed = true;
+            }
+        }
+    }
+    (messages, changed)
+}
+
+/// Keys named by a `-=` value; `FROM->TO` pairs are accepted too.
+fn map_keys(value: &str, normalize: impl Fn(&str) -> String) -> BTreeMap<String, String> {
+    entries(value)
+        .map(|e| (normalize(e.split_once("->").map_or(e, |(k, _)| k).trim()), String::new()))
+        .collect()
+}
+
+fn update_map(map: &mut BTreeMap<String, String>, key: &str, op: Op, entries: BTreeMap<String, String>) -> (Vec<String>, bool) {
+    let mut messages = Vec::new();
+    let mut changed = false;
+    for (k, v) in entries {
+        if op == Op::Remove {
+            if map.remove(&k).is_some() {
+                messages.push(format!("Removed '{}' from {}", k, key));
+                changed = true;
+            } else {
+                messages.push(format!("'{}' not found in {}", k, key));
+            }
+        } else if map.get(&k) == Some(&v) {
+            messages.push(format!("'{}->{}' already in {}", k, v, key));
+        } else {
+            messages.push(format!("Set '{}->{}' in {}", k, v, key));
+            map.insert(k, v);
+            changed = true;
+        }
+    }
+    (messages, changed)
+}
+
+/// A path prefix without trailing slashes (`/` stays `/`).
+fn normalize_prefix(path: &str) -> String {
+    let trimmed = path.trim().trim_end_matches('/');
+    if trimmed.is_empty() { "/".to_string() } else { trimmed.to_string() }
+}
+
+/// Parse `HOST->CONTAINER` path prefix pairs; both must be absolute.
+fn parse_path_map(value: &str) -> Result<BTreeMap<String, String>> {
+    let mut map = BTreeMap::new();
+    for pair in entries(value) {
+        let (host, container) = pair
+            .split_once("->")
+            .ok_or_else(|| anyhow::anyhow!("Invalid path_map entry '{}'. Use HOST_PREFIX->CONTAINER_PREFIX.", pair))?;
+        let (host, container) = (normalize_prefix(host), normalize_prefix(container));
+        if !host.starts_with('/') || !container.starts_with('/') {
+            bail!("Invalid path_map entry '{}': both prefixes must be absolute paths.", pair);
+        }
+        map.insert(host, container);
+    }
+    Ok(map)
+}
+
+/// Parse `REGISTRY->env:VAR` / `REGISTRY->file:PATH` pairs, validating the references.
+fn parse_registry_auth(value: &str) -> Result<BTreeMap<String, String>> {
+    let mut auth = BTreeMap::new();
+    for pair in entries(value) {
+        let (registry, reference) = pair
+            .split_once("->")
+            .ok_or_else(|| anyhow::anyhow!("Invalid registry_auth entry '{}'. Use REGISTRY->env:VAR or REGISTRY->file:PATH.", pair))?;
+        crate::config::check_credential_ref(reference.trim())?;
+        auth.insert(crate::config::normalize_registry(registry), reference.trim().to_string());
+    }
+    Ok(auth)
+}
+
+/// Parse `FROM->TO[,FROM->TO...]` import remap pairs, validating both sides.
+fn parse_import_remap(value: &str, default_namespace: &str) -> Result<BTreeMap<String, String>> {
+    let mut remap = BTreeMap::new();
+    for pair in entries(value) {
+        let (from, to) = pair
+            .split_once("->")
+            .ok_or_else(|| anyhow::anyhow!("Invalid import_remap entry '{}'. Use FROM->TO.", pair))?;
+        let (from, to) = (from.trim(), to.trim());
+        crate::manifest::parse_registry_path(from, default_namespace)?;
+        crate::manifest::parse_registry_path(to, default_namespace)?;
+        remap.insert(from.to_string(), to.to_string());
+    }
+    Ok(remap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("volumes+=/scratch").unwrap(), ("volumes", Op::Add, "/scratch"));
+        assert_eq!(split_assignment("volumes-=/a=b").unwrap(), ("volumes", Op::Remove, "/a=b"));
+        assert_eq!(split_assignment("umask=0002").unwrap(), ("umask", Op::Set, "0002"));
+        assert!(split_assignment("umask").is_err());
+    }
+
+    #[test]
+    fn test_update_collection_is_idempotent() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.volumes = vec!["/data".to_string()];
+        let (_, changed) = update_collection(&mut config, "volumes", Op::Add, "/data,/scratch").unwrap();
+        assert!(changed);
+        assert_eq!(config.bulker.volumes, vec!["/data", "/scratch"]);
+        let (messages, changed) = update_collection(&mut config, "volumes", Op::Add, "/scratch").unwrap();
+        assert!(!changed);
+        assert_eq!(messages, vec!["'/scratch' already in volumes"]);
+        update_collection(&mut config, "volumes", Op::Remove, "/data").unwrap();
+        assert_eq!(config.bulker.volumes, vec!["/scratch"]);
+
+        update_collection(&mut config, "import_remap", Op::Add, "bulker/coreutils->myorg/coreutils:1").unwrap();
+        assert_eq!(config.bulker.import_remap.get("bulker/coreutils").map(String::as_str), Some("myorg/coreutils:1"));
+        let (_, changed) = update_collection(&mut config, "import_remap", Op::Remove, "bulker/coreutils").unwrap();
+        assert!(changed && config.bulker.import_remap.is_empty());
+
+        update_collection(&mut config, "path_map", Op::Add, "/mnt/gpfs/->/gpfs").unwrap();
+        assert_eq!(config.bulker.path_map.get("/mnt/gpfs").map(String::as_str), Some("/gpfs"));
+        let (_, changed) = update_collection(&mut config, "path_map", Op::Remove, "/mnt/gpfs/").unwrap();
+        assert!(changed && config.bulker.path_map.is_empty());
+        assert!(update_collection(&mut config, "path_map", Op::Add, "gpfs->/gpfs").is_err());
+
+        assert!(update_collection(&mut config, "umask", Op::Add, "0002").is_err());
+
+        // The same entries `bulker env add` accepts or refuses
+        update_collection(&mut config, "envvars", Op::Add, "AWS_*,LANG=C").unwrap();
+        assert!(config.bulker.envvars.ends_with(&["AWS_*".to_string(), "LANG=C".to_string()]));
+        for bad in ["MY VAR", "1X", "=x", "A*=1"] {
+            assert!(update_collection(&mut config, "envvars", Op::Add, bad).is_err(), "{} accepted", bad);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::{Context, Result};
 use clap::{Arg, ArgAction, ArgMatches, Command};
 
-use crate::config::load_config;
+use crate::config::load_cli_config;
 
 pub fn create_cli() -> Command {
     Command::new("show")
@@ -19,7 +19,7 @@ EXAMPLES:
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
 
     if matches.get_flag("effective") {
         let yaml = serde_yml::to_string(&config)
@@ -0,0 +1,148 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::BTreeMap;
+
+use crate::config::load_cli_config;
+use crate::manifest::{parse_registry_path, validate_crate_component};
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("alias")
+        .about("Point tag aliases such as stable or latest at a crate's tags")
+        .after_help("\
+EXAMPLES:
+  bulker crate alias databio/pepatac stable=1.0.14
+  bulker crate alias databio/pepatac stable=1.0.15 latest=1.0.16-dev
+  bulker crate alias databio/pepatac                  # list the crate's aliases
+  bulker crate alias databio/pepatac --remove latest
+
+Wherever a crate is named (activate, exec, crate install, imports ...),
+databio/pepatac:stable then means databio/pepatac:1.0.14, so pipelines can
+refer to stable while whoever maintains the cache decides what it points at.
+Aliases are stored in the manifest cache (the project cache with
+--cache-scope project) and point at tags, not at other aliases. An
+activation keeps the tag the alias pointed at when it started.")
+        .arg(
+            Arg::new("crate")
+                .required(true)
+                .help("Crate to alias tags of (namespace/crate, without a tag)"),
+        )
+        .arg(
+            Arg::new("aliases")
+                .num_args(0..)
+                .value_name("ALIAS=TAG")
+                .help("Aliases to set"),
+        )
+        .arg(
+            Arg::new("remove")
+                .long("remove")
+                .value_name("ALIAS")
+                .action(ArgAction::Append)
+                .help("Remove an alias (repeatable)"),
+        )
+}
+
+/// Apply `ALIAS=TAG` assignments and removals to `aliases`. `cached_tags`
+/// are real tags, which can't be shadowed by an alias.
+fn update_aliases(
+    aliases: &mut BTreeMap<String, String>,
+    assignments: &[String],
+    removals: &[String],
+    cached_tags: &[String],
+) -> Result<()> {
+    for alias in removals {
+        if aliases.remove(alias).is_none() {
+            bail!("No alias '{}'", alias);
+        }
+    }
+    for assignment in assignments {
+        let Some((alias, tag)) = assignment.split_once('=') else {
+            bail!("Invalid alias '{}'. Use ALIAS=TAG, e.g. stable=1.0.14.", assignment);
+        };
+        let (alias, tag) = (alias.trim(), tag.trim());
+        validate_crate_component(alias, "alias")?;
+        validate_crate_component(tag, "tag")?;
+        if alias == "default" {
+            bail!("'default' is the tag used when none is given and can't be an alias");
+        }
+        if alias == tag {
+            bail!("Alias '{}' can't point at itself", alias);
+        }
+        if cached_tags.iter().any(|t| t == alias) {
+            bail!("'{}' is a cached tag of this crate; choose another alias name", alias);
+        }
+        aliases.insert(alias.to_string(), tag.to_string());
+    }
+    if let Some((alias, tag)) = aliases.iter().find(|(_, tag)| aliases.contains_key(*tag)) {
+        bail!("Alias '{}' points at '{}', which is an alias itself; point it at a tag", alias, tag);
+    }
+    Ok(())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let path = matches.get_one::<String>("crate").unwrap();
+    if path.contains(':') {
+        bail!("Name the crate without a tag, e.g. `bulker crate alias databio/pepatac stable=1.0.14`");
+    }
+    let cv = parse_registry_path(path, &config.bulker.default_namespace)?;
+    let name = format!("{}/{}", cv.namespace, cv.crate_name);
+    let assignments: Vec<String> = matches.get_many::<String>("aliases").into_iter().flatten().cloned().collect();
+    let removals: Vec<String> = matches.get_many::<String>("remove").into_iter().flatten().cloned().collect();
+    let mut aliases = manifest_cache::read_aliases(&cv.namespace, &cv.crate_name);
+
+    if assignments.is_empty() && removals.is_empty() {
+        if aliases.is_empty() {
+            println!("No tag aliases for {}.", name);
+        }
+        for (alias, tag) in &aliases {
+            println!("{}:{} -> {}", name, alias, tag);
+        }
+        return Ok(());
+    }
+
+    let cached_tags: Vec<String> = manifest_cache::list_cached()?
+        .into_iter()
+        .filter(|(c, _)| c.namespace == cv.namespace && c.crate_name == cv.crate_name)
+        .map(|(c, _)| c.tag)
+        .collect();
+    update_aliases(&mut aliases, &assignments, &removals, &cached_tags)?;
+    manifest_cache::write_aliases(&cv.namespace, &cv.crate_name, &aliases)?;
+    for alias in &removals {
+        println!("Removed {}:{}", name, alias);
+    }
+    for assignment in &assignments {
+        let (alias, tag) = assignment.split_once('=').unwrap();
+        let (alias, tag) = (alias.trim(), tag.trim());
+        println!("{}:{} -> {}", name, alias, tag);
+        if !cached_tags.iter().any(|t| t == tag) {
+            log::info!("{}:{} is not cached yet; it is fetched on first use", name, tag);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_update_aliases() {
+        let cached = strings(&["1.0.14", "1.0.15"]);
+        let mut aliases = BTreeMap::new();
+        update_aliases(&mut aliases, &strings(&["stable=1.0.14", "latest = 1.0.15"]), &[], &cached).unwrap();
+        assert_eq!(aliases.get("latest").map(String::as_str), Some("1.0.15"));
+        update_aliases(&mut aliases, &strings(&["stable=1.0.15"]), &strings(&["latest"]), &cached).unwrap();
+        assert_eq!(aliases, [("stable".to_string(), "1.0.15".to_string())].into());
+
+        for bad in ["stable", "default=1.0.14", "1.0.14=1.0.15", "x=x", "a/b=1", "next=stable"] {
+            let mut copy = aliases.clone();
+            assert!(update_aliases(&mut copy, &strings(&[bad]), &[], &cached).is_err(), "{} accepted", bad);
+        }
+        assert!(update_aliases(&mut aliases, &[], &strings(&["nope"]), &cached).is_err());
+    }
+}
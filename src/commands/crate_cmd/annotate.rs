@@ -0,0 +1,169 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::BTreeMap;
+
+use crate::config::load_cli_config;
+use crate::manifest::parse_registry_path;
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("annotate")
+        .about("Attach notes such as a project or validation status to a cached crate")
+        .after_help("\
+EXAMPLES:
+  bulker crate annotate databio/pepatac:1.0.13 project=encode-rerun
+  bulker crate annotate databio/pepatac:1.0.13 'validated-by=QA 2024-06-01'
+  bulker crate annotate databio/pepatac:1.0.13                 # show its annotations
+  bulker crate annotate databio/pepatac:1.0.13 --remove project
+  bulker crate list --filter project=encode-rerun
+
+Annotations are freeform KEY=VALUE notes on one cached tag of a crate.
+`crate inspect` and `crate list --long` show them, and `crate list --filter`
+lists the crates that carry them. They stay in this cache (moving with
+`crate rename-namespace`, gone with the crate) and never change what the
+crate runs.")
+        .arg(
+            Arg::new("crate")
+                .required(true)
+                .help("Cached crate to annotate (namespace/crate:tag)"),
+        )
+        .arg(
+            Arg::new("annotations")
+                .num_args(0..)
+                .value_name("KEY=VALUE")
+                .help("Annotations to set"),
+        )
+        .arg(
+            Arg::new("remove")
+                .long("remove")
+                .value_name("KEY")
+                .action(ArgAction::Append)
+                .help("Remove an annotation (repeatable)"),
+        )
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!("Invalid annotation key '{}'. Use letters, digits, '-', '_' and '.'.", key);
+    }
+    Ok(())
+}
+
+/// Split `KEY=VALUE`, trimming both.
+fn split_annotation(assignment: &str) -> Result<(&str, &str)> {
+    let Some((key, value)) = assignment.split_once('=') else {
+        bail!("Invalid annotation '{}'. Use KEY=VALUE, e.g. project=encode-rerun.", assignment);
+    };
+    let (key, value) = (key.trim(), value.trim());
+    validate_key(key)?;
+    Ok((key, value))
+}
+
+/// Apply `KEY=VALUE` assignments and removals to `annotations`.
+fn update_annotations(annotations: &mut BTreeMap<String, String>, assignments: &[String], removals: &[String]) -> Result<()> {
+    for key in removals {
+        if annotations.remove(key).is_none() {
+            bail!("No annotation '{}'", key);
+        }
+    }
+    for assignment in assignments {
+        let (key, value) = split_annotation(assignment)?;
+        if value.is_empty() {
+            bail!("Annotation '{}' has no value. Use --remove {} to drop it.", key, key);
+        }
+        annotations.insert(key.to_string(), value.to_string());
+    }
+    Ok(())
+}
+
+/// A `crate list --filter`: `KEY=VALUE` matches that value, `KEY` any.
+pub(super) struct Filter {
+    key: String,
+    value: Option<String>,
+}
+
+impl Filter {
+    pub(super) fn parse(filter: &str) -> Result<Self> {
+        let (key, value) = match filter.split_once('=') {
+            Some(_) => split_annotation(filter).map(|(k, v)| (k, Some(v.to_string())))?,
+            None => (filter.trim(), None),
+        };
+        validate_key(key)?;
+        Ok(Filter { key: key.to_string(), value })
+    }
+
+    pub(super) fn matches(&self, annotations: &BTreeMap<String, String>) -> bool {
+        match (annotations.get(&self.key), &self.value) {
+            (Some(actual), Some(wanted)) => actual == wanted,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let path = matches.get_one::<String>("crate").unwrap();
+    let cv = parse_registry_path(path, &config.bulker.default_namespace)?;
+    if manifest_cache::load_cached(&cv)?.is_none() {
+        bail!("Crate '{}' is not cached. Install it with `bulker crate install {}` first.", cv.display_name(), cv.display_name());
+    }
+    let assignments: Vec<String> = matches.get_many::<String>("annotations").into_iter().flatten().cloned().collect();
+    let removals: Vec<String> = matches.get_many::<String>("remove").into_iter().flatten().cloned().collect();
+    let mut annotations = manifest_cache::read_annotations(&cv);
+
+    if assignments.is_empty() && removals.is_empty() {
+        if annotations.is_empty() {
+            println!("No annotations for {}.", cv.display_name());
+        }
+        for (key, value) in &annotations {
+            println!("{}={}", key, value);
+        }
+        return Ok(());
+    }
+
+    update_annotations(&mut annotations, &assignments, &removals)?;
+    manifest_cache::write_annotations(&cv, &annotations)?;
+    for key in &removals {
+        println!("Removed {} from {}", key, cv.display_name());
+    }
+    for assignment in &assignments {
+        let (key, value) = split_annotation(assignment)?;
+        println!("{}: {}={}", cv.display_name(), key, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_update_annotations() {
+        let mut annotations = BTreeMap::new();
+        update_annotations(&mut annotations, &strings(&["project=encode-rerun", "validated-by = QA 2024-06-01"]), &[]).unwrap();
+        assert_eq!(annotations.get("validated-by").map(String::as_str), Some("QA 2024-06-01"));
+        update_annotations(&mut annotations, &strings(&["project=other"]), &strings(&["validated-by"])).unwrap();
+        assert_eq!(annotations, [("project".to_string(), "other".to_string())].into());
+
+        for bad in ["project", "=x", "a b=c", "project="] {
+            assert!(update_annotations(&mut annotations.clone(), &strings(&[bad]), &[]).is_err(), "{} accepted", bad);
+        }
+        assert!(update_annotations(&mut annotations, &[], &strings(&["nope"])).is_err());
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let annotations: BTreeMap<String, String> = [("project".to_string(), "encode-rerun".to_string())].into();
+        assert!(Filter::parse("project=encode-rerun").unwrap().matches(&annotations));
+        assert!(Filter::parse("project").unwrap().matches(&annotations));
+        assert!(!Filter::parse("project=other").unwrap().matches(&annotations));
+        assert!(!Filter::parse("owner").unwrap().matches(&annotations));
+        assert!(Filter::parse("bad key=x").is_err());
+    }
+}
@@ -1,8 +1,10 @@
 use anyhow::{Result, bail};
 use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::HashSet;
+use std::io::{BufRead, IsTerminal, Write};
 
-use crate::config::load_config;
-use crate::manifest::parse_registry_paths;
+use crate::config::{BulkerConfig, load_cli_config};
+use crate::manifest::{CrateVars, Manifest, parse_registry_paths};
 use crate::manifest_cache;
 
 pub fn create_cli() -> Command {
@@ -11,7 +13,11 @@ pub fn create_cli() -> Command {
         .after_help("\
 EXAMPLES:
   bulker crate clean databio/pepatac:1.0.13    # remove a specific cached manifest
-  bulker crate clean --all                     # clear entire manifest cache")
+  bulker crate clean --all                     # clear entire manifest cache
+  bulker crate clean --unused 90d              # remove crates not used in 90 days
+  bulker crate clean --unused 12w --images --yes   # also remove their images, no prompt
+
+DURATION: a number with a unit: m (minutes), h (hours), d (days), w (weeks).")
         .arg(
             Arg::new("crate_registry_paths")
                 .help("Crate(s) to clean (comma-separated for multiple)"),
@@ -20,8 +26,30 @@ EXAMPLES:
             Arg::new("all")
                 .long("all")
                 .action(ArgAction::SetTrue)
+                .conflicts_with("unused")
                 .help("Clear the entire manifest cache"),
         )
+        .arg(
+            Arg::new("unused")
+                .long("unused")
+                .value_name("DURATION")
+                .conflicts_with("crate_registry_paths")
+                .help("Remove crates not activated or run within DURATION (e.g. 90d)"),
+        )
+        .arg(
+            Arg::new("images")
+                .long("images")
+                .action(ArgAction::SetTrue)
+                .requires("unused")
+                .help("With --unused, also remove the crates' images (docker) or SIF files (apptainer)"),
+        )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .action(ArgAction::SetTrue)
+                .help("Do not ask for confirmation"),
+        )
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
@@ -33,15 +61,136 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         } else {
             println!("Manifest cache is already empty.");
         }
+    } else if let Some(duration) = matches.get_one::<String>("unused") {
+        let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+        let max_age = crate::humanize::parse_duration(duration)?.as_secs();
+        clean_unused(&config, max_age, matches.get_flag("images"), matches.get_flag("yes"))?;
     } else if let Some(registry_paths) = matches.get_one::<String>("crate_registry_paths") {
-        let (config, _config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+        let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
         let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
         for cv in &cratelist {
             manifest_cache::remove_cached(cv)?;
             println!("Removed: {}", cv.display_name());
         }
     } else {
-        bail!("Specify a crate to clean, or use --all to clear the entire cache.");
+        bail!("Specify a crate to clean, --unused DURATION, or --all to clear the entire cache.");
     }
     Ok(())
 }
+
+/// When a cached crate was last used: the `last-used` sidecar, else when it
+/// was installed, else the manifest's modification time.
+fn last_activity(cv: &CrateVars, manifest_path: &std::path::Path) -> u64 {
+    manifest_cache::read_timestamp_sidecar(cv, "last-used")
+        .or_else(|| manifest_cache::read_timestamp_sidecar(cv, "installed-at"))
+        .or_else(|| {
+            std::fs::metadata(manifest_path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+        })
+        .unwrap_or(0)
+}
+
+fn clean_unused(config: &BulkerConfig, max_age: u64, images: bool, yes: bool) -> Result<()> {
+    let now = manifest_cache::now_secs();
+    let mut stale: Vec<(CrateVars, u64)> = Vec::new();
+    let mut kept: Vec<CrateVars> = Vec::new();
+    for (cv, path) in manifest_cache::list_cached()? {
+        let last = last_activity(&cv, &path);
+        if now.saturating_sub(last) > max_age {
+            stale.push((cv, last));
+        } else {
+            kept.push(cv);
+        }
+    }
+
+    if stale.is_empty() {
+        println!("No cached crates unused for that long.");
+        return Ok(());
+    }
+
+    println!("Crates not used within the given period:");
+    for (cv, last) in &stale {
+        let days = now.saturating_sub(*last) / 86400;
+        println!("  {:<40} last used {} days ago", cv.display_name(), days);
+    }
+    if images {
+        println!("Their images will be removed too, unless a remaining crate uses them.");
+    }
+
+    if !yes && !confirm(&format!("Remove {} crate(s)?", stale.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    // Load manifests before removal so their images can be cleaned afterwards
+    let stale_manifests: Vec<Manifest> = if images {
+        stale.iter().filter_map(|(cv, _)| manifest_cache::load_cached(cv).ok().flatten()).collect()
+    } else {
+        Vec::new()
+    };
+
+    for (cv, _) in &stale {
+        manifest_cache::remove_cached(cv)?;
+        println!("Removed: {}", cv.display_name());
+    }
+
+    if images {
+        let in_use: HashSet<String> = kept
+            .iter()
+            .filter_map(|cv| manifest_cache::load_cached(cv).ok().flatten())
+            .flat_map(|m| m.manifest.commands.into_iter().map(|c| c.docker_image))
+            .collect();
+        remove_images(config, &stale_manifests, &in_use);
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on stderr. Without a terminal, requires `--yes`.
+fn confirm(prompt: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        bail!("Not a terminal; pass --yes to remove without confirmation.");
+    }
+    eprint!("{} [y/N] ", prompt);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Remove docker images or apptainer SIF files used only by removed crates.
+/// Failures are reported and skipped.
+fn remove_images(config: &BulkerConfig, manifests: &[Manifest], in_use: &HashSet<String>) {
+    let mut seen = HashSet::new();
+    for pkg in manifests.iter().flat_map(|m| &m.manifest.commands) {
+        let image = &pkg.docker_image;
+        if image.is_empty() || in_use.contains(image) || !seen.insert(image.clone()) {
+            continue;
+        }
+        if config.is_apptainer() {
+            // Images still in the old flat layout are moved first, then removed
+            let fullpath = crate::manifest::migrate_legacy_sif(
+                image,
+                config.bulker.apptainer_image_folder.as_deref(),
+            );
+            let size = std::fs::metadata(&fullpath).map(|m| m.len()).unwrap_or(0);
+            match std::fs::remove_file(&fullpath) {
+                Ok(()) => println!("Removed image: {} ({})", fullpath, crate::humanize::format_size(size)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => eprintln!("Failed to remove {}: {}", fullpath, e),
+            }
+        } else {
+            let status = std::process::Command::new(config.engine_path())
+                .args(["rmi", image])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status();
+            match status {
+                Ok(s) if s.success() => println!("Removed image: {}", image),
+                _ => log::info!("Image not removed (not present or in use): {}", image),
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::PathBuf;
+
+use crate::bundle::{self, BUNDLE_NAME};
+use crate::config::load_cli_config;
+use crate::manifest::parse_registry_paths;
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("export")
+        .about("Pack crates (and optionally their images) into a bundle for offline systems")
+        .after_help("\
+EXAMPLES:
+  bulker crate export databio/pepatac:1.0.13 -o pepatac.tar.gz
+  bulker crate export --images databio/pepatac:1.0.13 -o pepatac.tar.gz
+  bulker crate import pepatac.tar.gz            # on the offline system
+
+The bundle holds the cached manifests of the crates and their imports, with
+their digest sidecars. With --images it also holds the images: `docker save`
+archives with docker or podman, the pulled SIF files with apptainer.")
+        .arg(
+            Arg::new("crate_registry_paths")
+                .required(true)
+                .help("Crate(s) to export (comma-separated for multiple)"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .default_value(BUNDLE_NAME)
+                .help("Bundle to write (.tar.gz)"),
+        )
+        .arg(
+            Arg::new("images")
+                .long("images")
+                .action(ArgAction::SetTrue)
+                .help("Also save the crates' container images into the bundle"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
+    let output = PathBuf::from(matches.get_one::<String>("output").unwrap());
+
+    let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
+    for cv in &cratelist {
+        let mut visited = std::collections::HashSet::new();
+        manifest_cache::ensure_cached_with_imports(&config, cv, false, false, &mut visited, 0)?;
+    }
+    let index = bundle::export(&config, &cratelist, &output, matches.get_flag("images"))?;
+
+    println!("Exported {} crate(s), {} image(s) to {}", index.crates.len(), index.images.len(), output.display());
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use anyhow::{Context, Result};
+use clap::{Arg, ArgMatches, Command};
+use std::collections::{BTreeSet, HashSet};
+
+use crate::config::load_cli_config;
+use crate::manifest::{CrateVars, parse_registry_path};
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("export-spack")
+        .about("Generate a Spack package (or EasyBuild easyconfig) stub that wraps a crate")
+        .after_help("\
+EXAMPLES:
+  bulker crate export-spack databio/pepatac:1.0.13 -o package.py
+  bulker crate export-spack databio/pepatac:1.0.13 --format easybuild -o bulker-pepatac-1.0.13.eb
+
+The recipe installs one wrapper per container command (imports included) that
+runs `bulker exec <crate> -- <command>`, so sites that require software to be
+installable through Spack or EasyBuild can offer bulker crates as modules.
+bulker and a container engine must be available where the package is used.")
+        .arg(
+            Arg::new("crate_registry_path")
+                .required(true)
+                .help("Crate to export (fetched if not cached)"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["spack", "easybuild"])
+                .default_value("spack")
+                .help("Recipe format"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Write the recipe to FILE instead of stdout"),
+        )
+}
+
+/// What a recipe needs to know about the crate.
+#[derive(Debug)]
+struct Recipe {
+    /// `namespace/crate:tag`, as passed to `bulker exec`.
+    crate_path: String,
+    /// Package name, `bulker-<crate>`.
+    name: String,
+    version: String,
+    digest: Option<String>,
+    commands: Vec<String>,
+}
+
+impl Recipe {
+    /// The wrapper every command links to; it runs the command named by `$0`.
+    fn wrapper(&self) -> String {
+        format!("#!/bin/sh\nexec bulker exec {} -- \"$(basename \"$0\")\" \"$@\"\n", self.crate_path)
+    }
+
+    fn header(&self, kind: &str, format: &str) -> String {
+        let mut out = format!("# {} stub for the bulker crate {}.\n", kind, self.crate_path);
+        out.push_str(&format!("# Generated by `bulker crate export-spack {} --format {}`", self.crate_path, format));
+        match self.digest {
+            Some(ref digest) => out.push_str(&format!("; crate-manifest-digest {}.\n", digest)),
+            None => out.push_str(".\n"),
+        }
+        out.push_str("# Each command runs through `bulker exec`, so bulker and a container engine\n");
+        out.push_str("# must be available where the package is used.\n");
+        out
+    }
+}
+
+/// Spack class name for a package name: `bulker-pep_atac` -> `BulkerPepAtac`.
+fn spack_class_name(name: &str) -> String {
+    name.split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// `s` as a double-quoted Python string literal. Python source is UTF-8, so
+/// only quotes, backslashes and control characters need escaping.
+fn python_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn shell_quote(s: &str) -> String {
+    shell_escape::escape(std::borrow::Cow::Borrowed(s)).to_string()
+}
+
+fn render_spack(recipe: &Recipe) -> String {
+    let mut out = recipe.header("Spack package", "spack");
+    out.push_str("\nfrom spack.package import *\n\n\n");
+    out.push_str(&format!("class {}(Package):\n", spack_class_name(&recipe.name)));
+    out.push_str(&format!("    \"\"\"Containerized commands of the bulker crate {}.\"\"\"\n\n", recipe.crate_path));
+    out.push_str("    homepage = \"https://bulker.io\"\n");
+    out.push_str("    has_code = False\n\n");
+    out.push_str(&format!("    version({})\n\n", python_str(&recipe.version)));
+    out.push_str("    commands = [\n");
+    for command in &recipe.commands {
+        out.push_str(&format!("        {},\n", python_str(command)));
+    }
+    out.push_str("    ]\n\n");
+    out.push_str("    def install(self, spec, prefix):\n");
+    out.push_str("        mkdirp(prefix.bin)\n");
+    out.push_str("        wrapper = join_path(prefix.bin, \".bulker-exec\")\n");
+    out.push_str("        with open(wrapper, \"w\") as f:\n");
+    out.push_str(&format!("            f.write({})\n", python_str(&recipe.wrapper())));
+    out.push_str("        set_executable(wrapper)\n");
+    out.push_str("        with working_dir(prefix.bin):\n");
+    out.push_str("            for command in self.commands:\n");
+    out.push_str("                symlink(\".bulker-exec\", command)\n");
+    out
+}
+
+fn render_easybuild(recipe: &Recipe) -> String {
+    let mut out = recipe.header("EasyBuild easyconfig", "easybuild");
+    out.push_str("\neasyblock = 'Bundle'\n\n");
+    out.push_str(&format!("name = '{}'\n", recipe.name));
+    out.push_str(&format!("version = '{}'\n\n", recipe.version));
+    out.push_str("homepage = 'https://bulker.io'\n");
+    out.push_str(&format!(
+        "description = \"\"\"Containerized commands of the bulker crate {}, run through `bulker exec`.\"\"\"\n\n",
+        recipe.crate_path
+    ));
+    out.push_str("toolchain = SYSTEM\n\n");
+    // printf turns the escaped newlines back into the wrapper script
+    let wrapper = recipe.wrapper().replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\\\n");
+    let links: Vec<String> = recipe.commands.iter().map(|c| format!("ln -s .bulker-exec {}", shell_quote(c))).collect();
+    out.push_str("postinstallcmds = [\n");
+    out.push_str("    \"mkdir -p %(installdir)s/bin\",\n");
+    out.push_str(&format!("    \"printf '{}' > %(installdir)s/bin/.bulker-exec\",\n", wrapper));
+    out.push_str("    \"chmod +x %(installdir)s/bin/.bulker-exec\",\n");
+    out.push_str(&format!("    {},\n", python_str(&format!("cd %(installdir)s/bin && {}", links.join(" && ")))));
+    out.push_str("]\n\n");
+    out.push_str("sanity_check_paths = {\n");
+    let files: Vec<String> = recipe.commands.iter().map(|c| python_str(&format!("bin/{}", c))).collect();
+    out.push_str(&format!("    'files': [{}],\n", files.join(", ")));
+    out.push_str("    'dirs': [],\n");
+    out.push_str("}\n\n");
+    out.push_str("moduleclass = 'tools'\n");
+    out
+}
+
+/// Container commands of a crate and its imports, sorted and deduplicated.
+/// Host commands are left out: they already exist on the host.
+pub(crate) fn crate_commands(config: &crate::config::BulkerConfig, cv: &CrateVars) -> Result<Vec<String>> {
+    let mut visited = HashSet::new();
+    manifest_cache::ensure_cached_with_imports(config, cv, false, false, &mut visited, 0)?;
+    let mut commands = BTreeSet::new();
+    for import_cv in crate::imports::resolve_cratevars_with_imports(config, std::slice::from_ref(cv))? {
+        if let Some(manifest) = manifest_cache::load_cached(&import_cv)? {
+            commands.extend(manifest.manifest.commands.into_iter().map(|c| c.command));
+        }
+    }
+    Ok(commands.into_iter().collect())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let path = matches.get_one::<String>("crate_registry_path").unwrap();
+    let cv = parse_registry_path(path, &config.bulker.default_namespace)?;
+    let commands = crate_commands(&config, &cv)?;
+    for command in &commands {
+        crate::manifest::check_command_name(command).with_context(|| format!("Cannot export {}", cv.display_name()))?;
+    }
+    let recipe = Recipe {
+        crate_path: cv.display_name(),
+        name: format!("bulker-{}", cv.crate_name.to_lowercase()),
+        version: cv.tag.clone(),
+        commands,
+        digest: manifest_cache::ensure_crate_manifest_digest(&cv)?,
+    };
+    let rendered = match matches.get_one::<String>("format").map(|s| s.as_str()) {
+        Some("easybuild") => render_easybuild(&recipe),
+        _ => render_spack(&recipe),
+    };
+    match matches.get_one::<String>("output") {
+        Some(file) => {
+            std::fs::write(file, rendered).with_context(|| format!("Failed to write {}", file))?;
+            println!("Wrote {}", file);
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Recipe {
+        Recipe {
+            crate_path: "databio/pepatac:1.0.13".to_string(),
+            name: "bulker-pepatac".to_string(),
+            version: "1.0.13".to_string(),
+            digest: Some("abc".to_string()),
+            commands: vec!["bowtie2".to_string(), "samtools".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_spack_class_name() {
+        assert_eq!(spack_class_name("bulker-pepatac"), "BulkerPepatac");
+        assert_eq!(spack_class_name("bulker-pep_atac.v2"), "BulkerPepAtacV2");
+    }
+
+    #[test]
+    fn test_render_spack() {
+        let out = render_spack(&sample());
+        assert!(out.contains("class BulkerPepatac(Package):"), "{}", out);
+        assert!(out.contains("    version(\"1.0.13\")"), "{}", out);
+        assert!(out.contains("        \"samtools\",\n"), "{}", out);
+        assert!(out.contains(r##"f.write("#!/bin/sh\nexec bulker exec databio/pepatac:1.0.13 -- \"$(basename \"$0\")\" \"$@\"\n")"##), "{}", out);
+        assert!(out.contains("crate-manifest-digest abc"), "{}", out);
+    }
+
+    #[test]
+    fn test_python_str() {
+        assert_eq!(python_str("samtools"), "\"samtools\"");
+        assert_eq!(python_str("a \"b\" \\ c\n"), r#""a \"b\" \\ c\n""#);
+        // Python has no \u{..} escape; UTF-8 source takes the character as is
+        assert_eq!(python_str("café\u{1}"), "\"café\\x01\"");
+    }
+
+    #[test]
+    fn test_render_easybuild_quotes_commands() {
+        let mut recipe = sample();
+        recipe.commands = vec!["my tool".to_string()];
+        let out = render_easybuild(&recipe);
+        assert!(out.contains(r#""cd %(installdir)s/bin && ln -s .bulker-exec 'my tool'","#), "{}", out);
+    }
+
+    #[test]
+    fn test_render_easybuild_wrapper_survives_printf() {
+        let out = render_easybuild(&sample());
+        assert!(out.contains("name = 'bulker-pepatac'"), "{}", out);
+        assert!(out.contains("'files': [\"bin/bowtie2\", \"bin/samtools\"]"), "{}", out);
+        assert!(out.contains("ln -s .bulker-exec bowtie2 && ln -s .bulker-exec samtools"), "{}", out);
+        // The postinstallcmd, once the easyconfig string is unescaped, prints the wrapper
+        let line = out.lines().find(|l| l.contains("printf")).unwrap().trim().trim_end_matches(',');
+        let command = line.trim_matches('"').replace("\\\"", "\"").replace("\\\\", "\\");
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command.replace(" > %(installdir)s/bin/.bulker-exec", ""))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), sample().wrapper());
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::manifest::{Manifest, parse_manifest, to_canonical_yaml};
+
+pub fn create_cli() -> Command {
+    Command::new("fmt")
+        .about("Rewrite manifest files in canonical form")
+        .after_help("\
+EXAMPLES:
+  bulker crate fmt manifest.yaml
+  bulker crate fmt --check crates/*.yaml       # CI gate: exit 1 if any file would change
+  bulker crate fmt --sort manifest.yaml        # also sort commands by name
+  bulker crate fmt --expand-defaults manifest.yaml
+
+Keys are written in canonical order, unset fields are dropped, duplicate list
+entries are removed and deprecated spellings are normalized (`dockerargs` is
+merged into `docker_args`). Comments are not preserved.")
+        .arg(
+            Arg::new("manifests")
+                .required(true)
+                .num_args(1..)
+                .help("Manifest file(s) to format (.yaml, .yml or .json)"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .help("Don't write; exit 1 if any file is not formatted"),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .action(ArgAction::SetTrue)
+                .help("Sort commands and host_commands by name"),
+        )
+        .arg(
+            Arg::new("expand-defaults")
+                .long("expand-defaults")
+                .action(ArgAction::SetTrue)
+                .help("Write default values (flags, lists, pull_policy, shell_in_container) explicitly"),
+        )
+}
+
+/// Formatting options beyond the canonical form.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct FmtOptions {
+    pub sort: bool,
+    pub expand_defaults: bool,
+}
+
+/// Command fields in canonical (struct) order. Kept in sync with
+/// `PackageCommand` by `test_command_field_order_matches_struct`.
+const COMMAND_FIELDS: &[&str] = &[
+    "command", "docker_image", "entrypoint", "docker_command", "docker_args", "dockerargs",
+    "apptainer_args", "apptainer_command", "volumes", "envvars", "no_user", "no_network",
+    "no_init", "no_default_volumes", "no_default_envvars", "workdir", "ports", "devices",
+    "sockets", "gpu", "platform", "pull_policy", "shell_in_container", "umask", "chown_outputs",
+    "cpu_binding", "cpus", "memory", "shm_size",
+];
+
+/// Values written for omitted fields with `--expand-defaults`.
+const COMMAND_DEFAULTS: &[(&str, &str)] = &[
+    ("volumes", "[]"),
+    ("envvars", "[]"),
+    ("no_user", "false"),
+    ("no_network", "false"),
+    ("no_init", "false"),
+    ("no_default_volumes", "false"),
+    ("no_default_envvars", "false"),
+    ("ports", "[]"),
+    ("devices", "[]"),
+    ("sockets", "[]"),
+    ("gpu", "false"),
+    ("pull_policy", "missing"),
+    ("shell_in_container", "auto"),
+    ("chown_outputs", "false"),
+];
+
+/// Remove repeated entries, keeping the first occurrence.
+fn dedup(list: &mut Vec<String>) -> bool {
+    let before = list.len();
+    let mut seen = std::collections::HashSet::new();
+    list.retain(|item| seen.insert(item.clone()));
+    list.len() != before
+}
+
+/// Normalize a manifest in place. Returns a note per change that is more than
+/// reordering.
+pub(crate) fn normalize(manifest: &mut Manifest, opts: FmtOptions) -> Vec<String> {
+    let mut notes = Vec::new();
+    for pkg in &mut manifest.manifest.commands {
+        if pkg.normalize_docker_args() {
+            notes.push(format!("'{}': merged `dockerargs` into `docker_args`", pkg.command));
+        }
+        for (field, list) in [("volumes", &mut pkg.volumes), ("envvars", &mut pkg.envvars), ("ports", &mut pkg.ports), ("devices", &mut pkg.devices), ("sockets", &mut pkg.sockets)] {
+            if dedup(list) {
+                notes.push(format!("'{}': removed duplicate {}", pkg.command, field));
+            }
+        }
+    }
+    if dedup(&mut manifest.manifest.host_commands) {
+        notes.push("removed duplicate host_commands".to_string());
+    }
+    if dedup(&mut manifest.manifest.imports) {
+        notes.push("removed duplicate imports".to_string());
+    }
+    if dedup(&mut manifest.manifest.env_defaults) {
+        notes.push("removed duplicate env_defaults".to_string());
+    }
+    if opts.sort {
+        manifest.manifest.commands.sort_by(|a, b| a.command.cmp(&b.command));
+        manifest.manifest.host_commands.sort();
+    }
+    notes
+}
+
+/// Add omitted command fields that have a default value, in canonical order.
+fn expand_defaults(value: &mut serde_yml::Value) -> Result<()> {
+    let Some(commands) = value
+        .get_mut("manifest")
+        .and_then(|m| m.get_mut("commands"))
+        .and_then(|c| c.as_sequence_mut())
+    else {
+        return Ok(());
+    };
+    for command in commands {
+        let Some(present) = command.as_mapping() else {
+            continue;
+        };
+        let mut expanded = serde_yml::Mapping::new();
+        for field in COMMAND_FIELDS {
+            if let Some(v) = present.get(*field) {
+                expanded.insert((*field).into(), v.clone());
+            } else if let Some((_, default)) = COMMAND_DEFAULTS.iter().find(|(k, _)| k == field) {
+                expanded.insert((*field).into(), serde_yml::from_str(default)?);
+            }
+        }
+        *command = serde_yml::Value::Mapping(expanded);
+    }
+    Ok(())
+}
+
+/// Serialize a manifest canonically, as JSON if `as_json`, else YAML.
+fn render(manifest: &Manifest, opts: FmtOptions, as_json: bool) -> Result<String> {
+    if !opts.expand_defaults {
+        if as_json {
+            let mut out = serde_json::to_string_pretty(manifest).context("Failed to serialize manifest")?;
+            out.push('\n');
+            return Ok(out);
+        }
+        return to_canonical_yaml(manifest);
+    }
+    let mut value = serde_yml::to_value(manifest).context("Failed to serialize manifest")?;
+    expand_defaults(&mut value)?;
+    if as_json {
+        let mut out = serde_json::to_string_pretty(&value).context("Failed to serialize manifest")?;
+        out.push('\n');
+        Ok(out)
+    } else {
+        serde_yml::to_string(&value).context("Failed to serialize manifest")
+    }
+}
+
+/// Format manifest text. Returns the formatted text and notes on what changed.
+pub(crate) fn format_manifest(original: &str, opts: FmtOptions) -> Result<(String, Vec<String>)> {
+    let mut manifest = parse_manifest(original)?;
+    let notes = normalize(&mut manifest, opts);
+    let formatted = render(&manifest, opts, original.trim_start().starts_with('{'))?;
+    Ok((formatted, notes))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let check = matches.get_flag("check");
+    let opts = FmtOptions {
+        sort: matches.get_flag("sort"),
+        expand_defaults: matches.get_flag("expand-defaults"),
+    };
+
+    let mut unformatted = 0;
+    for path in matches.get_many::<String>("manifests").unwrap() {
+        let original = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {}", path))?;
+        let (formatted, notes) = format_manifest(&original, opts)
+            .with_context(|| format!("Failed to parse manifest: {}", path))?;
+
+        for note in &notes {
+            println!("{}: {}", path, note);
+        }
+        if let Ok(manifest) = parse_manifest(&formatted) {
+            for d in manifest.deprecations() {
+                println!("{}: {} (not fixed automatically)", path, d);
+            }
+        }
+
+        if formatted == original {
+            if !check {
+                println!("{} is already formatted", path);
+            }
+        } else if check {
+            println!("Would reformat {}", path);
+            unformatted += 1;
+        } else {
+            std::fs::write(path, &formatted)
+                .with_context(|| format!("Failed to write manifest: {}", path))?;
+            println!("Formatted {}", path);
+        }
+    }
+
+    if unformatted > 0 {
+        bail!("{} file(s) would be reformatted; run `bulker crate fmt` on them", unformatted);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::PackageCommand;
+
+    const MESSY: &str = "\
+manifest:
+  version: 1.0.0
+  name: t
+  host_commands: [ls, cat, ls]
+  commands:
+  - docker_image: alpine
+    command: zeta
+    dockerargs: -p 80
+    docker_args: --privileged
+    volumes: [/data, /ref, /data]
+  - command: alpha
+    docker_image: alpine
+    dockerargs: -e X=1
+";
+
+    #[test]
+    fn test_normalize_merges_dockerargs_and_dedups() {
+        let mut manifest = parse_manifest(MESSY).unwrap();
+        let notes = normalize(&mut manifest, FmtOptions::default());
+        assert_eq!(notes.len(), 4, "{:?}", notes);
+        let zeta = &manifest.manifest.commands[0];
+        assert_eq!(zeta.dockerargs, None);
+        assert_eq!(zeta.docker_args.as_deref(), Some("-p 80 --privileged"));
+        assert_eq!(zeta.volumes, vec!["/data", "/ref"]);
+        assert_eq!(manifest.manifest.commands[1].docker_args.as_deref(), Some("-e X=1"));
+        assert_eq!(manifest.manifest.host_commands, vec!["ls", "cat"]);
+        assert!(manifest.deprecations().is_empty());
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        for opts in [
+            FmtOptions::default(),
+            FmtOptions { sort: true, expand_defaults: true },
+        ] {
+            let (once, _) = format_manifest(MESSY, opts).unwrap();
+            let (twice, notes) = format_manifest(&once, opts).unwrap();
+            assert_eq!(once, twice);
+            assert!(notes.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_sort_and_expand_defaults() {
+        let opts = FmtOptions { sort: true, expand_defaults: true };
+        let (formatted, _) = format_manifest(MESSY, opts).unwrap();
+        let manifest = parse_manifest(&formatted).unwrap();
+        let names: Vec<&str> = manifest.manifest.commands.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(names, vec!["alpha", "zeta"]);
+        assert_eq!(manifest.manifest.host_commands, vec!["cat", "ls"]);
+        assert!(formatted.contains("no_user: false"), "{}", formatted);
+        assert!(formatted.contains("pull_policy: missing"), "{}", formatted);
+        assert!(formatted.contains("shell_in_container: auto"), "{}", formatted);
+        assert!(!formatted.contains("entrypoint"), "{}", formatted);
+        // Expanded defaults parse back to the same behavior
+        assert_eq!(manifest.manifest.commands[0].container_shell(), PackageCommand::default().container_shell());
+    }
+
+    #[test]
+    fn test_format_preserves_json() {
+        let (formatted, _) = format_manifest(
+            r#"{"manifest": {"name": "t", "commands": [{"docker_image": "alpine", "command": "a"}]}}"#,
+            FmtOptions::default(),
+        )
+        .unwrap();
+        assert!(formatted.starts_with('{'));
+        assert!(formatted.find("\"command\"").unwrap() < formatted.find("\"docker_image\"").unwrap());
+    }
+
+    #[test]
+    fn test_command_field_order_matches_struct() {
+        let full = PackageCommand {
+            command: "c".into(),
+            docker_image: "i".into(),
+            entrypoint: Some("e".into()),
+            docker_command: Some("d".into()),
+            docker_args: Some("a".into()),
+            dockerargs: Some("a".into()),
+            apptainer_args: Some("a".into()),
+            apptainer_command: Some("a".into()),
+            volumes: vec!["/v".into()],
+            envvars: vec!["E".into()],
+            no_user: true,
+            no_network: true,
+            no_init: true,
+            no_default_volumes: true,
+            no_default_envvars: true,
+            workdir: Some("/w".into()),
+            ports: vec!["80".into()],
+            devices: vec!["/dev/fuse".into()],
+            sockets: vec!["/run/s.sock".into()],
+            gpu: true,
+            platform: Some("linux/amd64".into()),
+            pull_policy: Some("never".into()),
+            shell_in_container: Some("none".into()),
+            umask: Some("0002".into()),
+            chown_outputs: true,
+            cpu_binding: Some("0-3".into()),
+            cpus: Some("2".into()),
+            memory: Some("8G".into()),
+            shm_size: Some("1G".into()),
+        };
+        let value = serde_yml::to_value(&full).unwrap();
+        let keys: Vec<&str> = value.as_mapping().unwrap().keys().map(|k| k.as_str().unwrap()).collect();
+        assert_eq!(keys, COMMAND_FIELDS);
+    }
+}
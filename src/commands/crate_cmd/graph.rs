@@ -0,0 +1,282 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::HashSet;
+
+use crate::config::{BulkerConfig, load_cli_config};
+use crate::manifest::{CrateVars, parse_registry_paths};
+use crate::manifest_cache::{self, MAX_IMPORT_DEPTH};
+
+pub fn create_cli() -> Command {
+    Command::new("graph")
+        .about("Export a crate's import graph and command images (DOT or JSON)")
+        .after_help("\
+EXAMPLES:
+  bulker crate graph databio/pepatac:1.0.13 | dot -Tsvg > pepatac.svg
+  bulker crate graph databio/pepatac --format json | jq '.crates[].name'
+  bulker crate graph databio/pepatac --imports-only
+
+Nodes are crates, their commands and the images the commands run; edges are
+imports (labelled when `import_remap` replaced the import) and command->image.
+Imports that are not cached are drawn dashed and have `cached: false` in JSON.")
+        .arg(
+            Arg::new("crate_registry_paths")
+                .help("Crate(s) to graph (comma-separated; defaults to the active crate)"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["dot", "json"])
+                .default_value("dot")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::new("imports-only")
+                .long("imports-only")
+                .action(ArgAction::SetTrue)
+                .help("Only show crates and imports, not commands and images"),
+        )
+}
+
+/// One crate in the graph.
+#[derive(Debug)]
+struct CrateNode {
+    name: String,
+    /// False for imports missing from the cache; their imports are unknown.
+    cached: bool,
+    /// `(import as written, resolved crate, remapped)` triples.
+    imports: Vec<(String, String, bool)>,
+    /// `(command, image)` pairs.
+    commands: Vec<(String, String)>,
+    host_commands: Vec<String>,
+}
+
+/// Walk the crates and their imports depth-first, each crate once.
+fn collect(config: &BulkerConfig, cratelist: &[CrateVars]) -> Result<Vec<CrateNode>> {
+    let mut nodes = Vec::new();
+    let mut visited = HashSet::new();
+    for cv in cratelist {
+        if manifest_cache::load_cached(cv)?.is_none() {
+            bail!(
+                "Crate '{}' is not cached. Run 'bulker crate install {}' first.",
+                cv.display_name(),
+                cv.display_name()
+            );
+        }
+        visit(config, cv, &mut nodes, &mut visited, 0)?;
+    }
+    Ok(nodes)
+}
+
+fn visit(
+    config: &BulkerConfig,
+    cv: &CrateVars,
+    nodes: &mut Vec<CrateNode>,
+    visited: &mut HashSet<String>,
+    depth: usize,
+) -> Result<()> {
+    if depth >= MAX_IMPORT_DEPTH {
+        bail!("Import depth exceeded {} at crate '{}'", MAX_IMPORT_DEPTH, cv.display_name());
+    }
+    if !visited.insert(cv.display_name()) {
+        return Ok(());
+    }
+    let Some(manifest) = manifest_cache::load_cached(cv)? else {
+        nodes.push(CrateNode {
+            name: cv.display_name(),
+            cached: false,
+            imports: Vec::new(),
+            commands: Vec::new(),
+            host_commands: Vec::new(),
+        });
+        return Ok(());
+    };
+    let mut imports = Vec::new();
+    for import in &manifest.manifest.imports {
+        imports.push((import.clone(), crate::imports::resolve_import(config, import)?));
+    }
+    nodes.push(CrateNode {
+        name: cv.display_name(),
+        cached: true,
+        imports: imports
+            .iter()
+            .map(|(raw, resolved)| {
+                let written = crate::manifest::parse_registry_path(raw, &config.bulker.default_namespace)?;
+                Ok((raw.clone(), resolved.display_name(), written.display_name() != resolved.display_name()))
+            })
+            .collect::<Result<_>>()?,
+        commands: manifest
+            .manifest
+            .commands
+            .iter()
+            .map(|c| (c.command.clone(), c.docker_image.clone()))
+            .collect(),
+        host_commands: manifest.manifest.host_commands.clone(),
+    });
+    for (_, import_cv) in &imports {
+        visit(config, import_cv, nodes, visited, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn render_dot(roots: &[String], nodes: &[CrateNode], imports_only: bool) -> String {
+    let mut out = String::from("digraph bulker {\n  rankdir=LR;\n  node [fontname=\"Helvetica\"];\n");
+    let mut images: Vec<&str> = Vec::new();
+    for node in nodes {
+        let id = dot_quote(&format!("crate:{}", node.name));
+        let mut attrs = format!("label={}, shape=box", dot_quote(&node.name));
+        if roots.contains(&node.name) {
+            attrs.push_str(", style=bold");
+        } else if !node.cached {
+            attrs.push_str(", style=dashed");
+        }
+        out.push_str(&format!("  {} [{}];\n", id, attrs));
+        for (raw, resolved, remapped) in &node.imports {
+            let target = dot_quote(&format!("crate:{}", resolved));
+            if *remapped {
+                out.push_str(&format!("  {} -> {} [label={}];\n", id, target, dot_quote(&format!("remapped from {}", raw))));
+            } else {
+                out.push_str(&format!("  {} -> {};\n", id, target));
+            }
+        }
+        if imports_only {
+            continue;
+        }
+        for (command, image) in &node.commands {
+            let cmd_id = dot_quote(&format!("cmd:{}:{}", node.name, command));
+            out.push_str(&format!("  {} [label={}, shape=ellipse];\n", cmd_id, dot_quote(command)));
+            out.push_str(&format!("  {} -> {} [style=dotted, arrowhead=none];\n", id, cmd_id));
+            out.push_str(&format!("  {} -> {};\n", cmd_id, dot_quote(&format!("image:{}", image))));
+            if !images.contains(&image.as_str()) {
+                images.push(image);
+            }
+        }
+    }
+    for image in images {
+        out.push_str(&format!(
+            "  {} [label={}, shape=cylinder];\n",
+            dot_quote(&format!("image:{}", image)),
+            dot_quote(image)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn render_json(roots: &[String], nodes: &[CrateNode], imports_only: bool) -> serde_json::Value {
+    let crates: Vec<serde_json::Value> = nodes
+        .iter()
+        .map(|node| {
+            let mut value = serde_json::json!({
+                "name": node.name,
+                "cached": node.cached,
+                "imports": node.imports.iter().map(|(raw, resolved, remapped)| serde_json::json!({
+                    "import": raw,
+                    "crate": resolved,
+                    "remapped": remapped,
+                })).collect::<Vec<_>>(),
+            });
+            if !imports_only {
+                value["commands"] = node
+                    .commands
+                    .iter()
+                    .map(|(command, image)| serde_json::json!({"command": command, "image": image}))
+                    .collect();
+                value["host_commands"] = serde_json::json!(node.host_commands);
+            }
+            value
+        })
+        .collect();
+    let mut graph = serde_json::json!({ "roots": roots, "crates": crates });
+    if !imports_only {
+        let mut images: Vec<&str> = nodes.iter().flat_map(|n| n.commands.iter().map(|(_, i)| i.as_str())).collect();
+        images.sort();
+        images.dedup();
+        graph["images"] = serde_json::json!(images);
+    }
+    graph
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry_path = match matches.get_one::<String>("crate_registry_paths") {
+        Some(p) => p.clone(),
+        None => std::env::var("BULKERCRATE")
+            .map_err(|_| anyhow::anyhow!("No crate specified and no active crate (BULKERCRATE not set)"))?,
+    };
+    let cratelist = parse_registry_paths(&registry_path, &config.bulker.default_namespace)?;
+    let nodes = collect(&config, &cratelist)?;
+    let roots: Vec<String> = cratelist.iter().map(|cv| cv.display_name()).collect();
+    let imports_only = matches.get_flag("imports-only");
+
+    match matches.get_one::<String>("format").map(|s| s.as_str()) {
+        Some("json") => println!("{}", serde_json::to_string_pretty(&render_json(&roots, &nodes, imports_only))?),
+        _ => print!("{}", render_dot(&roots, &nodes, imports_only)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Manifest, ManifestInner, PackageCommand};
+
+    fn save(cv: &CrateVars, commands: &[(&str, &str)], imports: &[&str]) {
+        let manifest = Manifest {
+            manifest: ManifestInner {
+                name: Some(cv.crate_name.clone()),
+                commands: commands
+                    .iter()
+                    .map(|(command, image)| PackageCommand {
+                        command: command.to_string(),
+                        docker_image: image.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                imports: imports.iter().map(|i| i.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        manifest_cache::save_to_cache(cv, &manifest).unwrap();
+    }
+
+    #[test]
+    fn test_graph_follows_imports_and_remaps() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let mut config = BulkerConfig::test_default();
+        config.bulker.import_remap.insert("bulker/base".into(), "myorg/base-arm:1".into());
+
+        let top = parse_registry_paths("bulker/top_graphtest:1", "bulker").unwrap();
+        save(&top[0], &[("samtools", "quay.io/samtools:1.17"), ("bgzip", "quay.io/samtools:1.17")], &["bulker/base", "bulker/gone"]);
+        let base = parse_registry_paths("myorg/base-arm:1", "bulker").unwrap();
+        save(&base[0], &[("ls", "alpine")], &["bulker/top_graphtest:1"]);
+
+        let nodes = collect(&config, &top).unwrap();
+        let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+        // The cycle back to the top crate is visited once
+        assert_eq!(names, vec!["bulker/top_graphtest:1", "myorg/base-arm:1", "bulker/gone:default"]);
+        assert!(!nodes[2].cached);
+
+        let roots = vec!["bulker/top_graphtest:1".to_string()];
+        let dot = render_dot(&roots, &nodes, false);
+        assert!(dot.contains("\"crate:bulker/top_graphtest:1\" -> \"crate:myorg/base-arm:1\" [label=\"remapped from bulker/base\"];"), "{}", dot);
+        assert!(dot.contains("\"crate:bulker/gone:default\" [label=\"bulker/gone:default\", shape=box, style=dashed];"), "{}", dot);
+        assert_eq!(dot.matches("[label=\"quay.io/samtools:1.17\", shape=cylinder]").count(), 1, "{}", dot);
+
+        let json = render_json(&roots, &nodes, false);
+        assert_eq!(json["crates"][0]["commands"][0]["image"], "quay.io/samtools:1.17");
+        assert_eq!(json["crates"][0]["imports"][0]["crate"], "myorg/base-arm:1");
+        assert_eq!(json["crates"][0]["imports"][0]["remapped"], true);
+        assert_eq!(json["crates"][0]["imports"][1]["remapped"], false);
+        assert_eq!(json["images"], serde_json::json!(["alpine", "quay.io/samtools:1.17"]));
+
+        let imports_only = render_dot(&roots, &nodes, true);
+        assert!(!imports_only.contains("cylinder"));
+        assert!(render_json(&roots, &nodes, true).get("images").is_none());
+    }
+}
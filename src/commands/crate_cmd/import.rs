@@ -0,0 +1,55 @@
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::PathBuf;
+
+use crate::bundle;
+use crate::config::load_cli_config;
+
+pub fn create_cli() -> Command {
+    Command::new("import")
+        .about("Restore crates and images from a bundle written by `crate export`")
+        .after_help("\
+EXAMPLES:
+  bulker crate import pepatac.tar.gz
+  bulker crate import --no-images pepatac.tar.gz
+  bulker crate import --force pepatac.tar.gz    # replace differing cached crates
+
+Needs no network. Manifests go into the manifest cache; bundled images are
+loaded into docker or podman (`load`), or placed in the apptainer image
+folder (`docker save` archives are built into SIF files).")
+        .arg(
+            Arg::new("bundle")
+                .required(true)
+                .help("Bundle file (.tar.gz)"),
+        )
+        .arg(
+            Arg::new("no-images")
+                .long("no-images")
+                .action(ArgAction::SetTrue)
+                .help("Restore only the manifests, not the bundled images"),
+        )
+        .arg(
+            Arg::new("force")
+                .short('f')
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Replace cached crates whose manifest differs from the bundled one"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let bundle_path = PathBuf::from(matches.get_one::<String>("bundle").unwrap());
+
+    let report = bundle::import(&config, &bundle_path, !matches.get_flag("no-images"), matches.get_flag("force"))?;
+    for name in &report.imported {
+        println!("Imported: {}", name);
+    }
+    for name in &report.unchanged {
+        println!("Already cached: {}", name);
+    }
+    if report.images > 0 {
+        println!("Loaded {} image(s)", report.images);
+    }
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use anyhow::Result;
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 
-use crate::config::load_config;
-use crate::manifest::parse_registry_paths;
+use crate::config::{BulkerConfig, load_cli_config};
+use crate::manifest::{CrateVars, Manifest, PackageCommand, parse_registry_paths};
 use crate::manifest_cache;
 
 pub fn create_cli() -> Command {
@@ -12,15 +12,181 @@ pub fn create_cli() -> Command {
 EXAMPLES:
   bulker crate inspect                         # inspect the currently active crate
   bulker crate inspect bulker/demo
-  bulker crate inspect databio/pepatac:1.0.13")
+  bulker crate inspect databio/pepatac:1.0.13
+  bulker crate inspect --raw bulker/demo       # the manifest as authored, comments included
+  bulker crate inspect --effective databio/pepatac,bulker/coreutils
+
+--effective shows what the shimlinks will run: the crates and imports in the
+order commands are looked up, which crate provides each command (the first
+one defining it) and which it shadows, each command's volumes, forwarded
+variables and engine arguments after the config is merged in, tag aliases and
+import_remap entries that applied, and the host commands a strict activation
+keeps. Volumes and variables from a run's arguments and environment
+(auto-mounts, $TMPDIR, BULKER_EXTRA_*) come on top.")
         .arg(
             Arg::new("crate_registry_paths")
                 .help("Crate to inspect (defaults to active crate from BULKERCRATE)"),
         )
+        .arg(
+            Arg::new("raw")
+                .long("raw")
+                .action(ArgAction::SetTrue)
+                .help("Print the manifest as it was authored (canonical form if the original was not kept)"),
+        )
+        .arg(
+            Arg::new("effective")
+                .long("effective")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("raw")
+                .help("Show the merged view of the crates, their imports and the config that commands run with"),
+        )
+}
+
+/// A command as the dispatcher resolves it.
+struct EffectiveCommand<'a> {
+    pkg: &'a PackageCommand,
+    /// Crate providing the command, and its `env_defaults`.
+    provider: String,
+    env_defaults: &'a [String],
+    /// Later crates defining the same command, which lose.
+    shadowed: Vec<String>,
+}
+
+/// Resolve every command name across `manifests` (crates and imports in
+/// search order) the way `find_command_in_crates_with_imports` does: the
+/// first crate defining a name provides it.
+fn effective_commands(manifests: &[(CrateVars, Manifest)]) -> Vec<EffectiveCommand<'_>> {
+    let mut commands: Vec<EffectiveCommand> = Vec::new();
+    for (cv, manifest) in manifests {
+        for pkg in &manifest.manifest.commands {
+            match commands.iter_mut().find(|c| c.pkg.command == pkg.command) {
+                Some(existing) => existing.shadowed.push(cv.display_name()),
+                None => commands.push(EffectiveCommand {
+                    pkg,
+                    provider: cv.display_name(),
+                    env_defaults: &manifest.manifest.env_defaults,
+                    shadowed: Vec::new(),
+                }),
+            }
+        }
+    }
+    commands.sort_by(|a, b| a.pkg.command.cmp(&b.pkg.command));
+    commands
+}
+
+/// Tag aliases and import remaps that changed what `registry_path` and the
+/// crates' imports resolve to.
+fn applied_overrides(config: &BulkerConfig, registry_path: &str, cratelist: &[CrateVars], manifests: &[(CrateVars, Manifest)]) -> Vec<String> {
+    let mut overrides = Vec::new();
+    for (raw, cv) in registry_path.split(',').zip(cratelist) {
+        let raw_tag = raw.trim().rsplit_once(':').map(|(_, tag)| tag).filter(|tag| !tag.contains('/'));
+        if let Some(tag) = raw_tag.filter(|tag| *tag != cv.tag) {
+            overrides.push(format!("{}/{}:{} -> {} (tag alias)", cv.namespace, cv.crate_name, tag, cv.tag));
+        }
+    }
+    for (cv, manifest) in manifests {
+        for import in &manifest.manifest.imports {
+            let (Ok(original), Ok(resolved)) = (
+                crate::manifest::parse_registry_path(import, &config.bulker.default_namespace),
+                crate::imports::resolve_import(config, import),
+            ) else {
+                continue;
+            };
+            if original.display_name() != resolved.display_name() {
+                overrides.push(format!(
+                    "import {} of {} -> {} (import_remap)",
+                    original.display_name(),
+                    cv.display_name(),
+                    resolved.display_name()
+                ));
+            }
+        }
+    }
+    overrides
+}
+
+/// `defaults + A, B` for an allowlist that extends the default one.
+fn describe_allowlist(patterns: &[String]) -> String {
+    let defaults = crate::shimlink::DEFAULT_ENVVARS;
+    let has_defaults = defaults.iter().all(|d| patterns.iter().any(|p| p == d));
+    let extra: Vec<&str> = patterns
+        .iter()
+        .map(String::as_str)
+        .filter(|p| !has_defaults || !defaults.contains(p))
+        .collect();
+    match (has_defaults, extra.is_empty()) {
+        (true, true) => "defaults".to_string(),
+        (true, false) => format!("defaults + {}", extra.join(", ")),
+        (false, true) => "none".to_string(),
+        (false, false) => extra.join(", "),
+    }
+}
+
+fn print_effective(config: &BulkerConfig, registry_path: &str, cratelist: &[CrateVars]) -> Result<()> {
+    let (all, missing) = crate::imports::resolve_cratevars_allow_missing_imports(config, cratelist)?;
+    let manifests: Vec<(CrateVars, Manifest)> = all
+        .iter()
+        .map(|cv| Ok((cv.clone(), crate::shimlink::load_cached_manifest(config, cv)?)))
+        .collect::<Result<_>>()?;
+    let names: Vec<String> = cratelist.iter().map(|cv| cv.display_name()).collect();
+    println!("Effective view of {}", names.join(","));
+
+    println!("Search order:");
+    for (cv, _) in &manifests {
+        let imported = !names.contains(&cv.display_name());
+        println!("  {}{}", cv.display_name(), if imported { " (import)" } else { "" });
+    }
+    for name in &missing {
+        println!("  {} (import not fetched; its commands are unavailable)", name);
+    }
+
+    let overrides = applied_overrides(config, registry_path, cratelist, &manifests);
+    if !overrides.is_empty() {
+        println!("Overrides:");
+        for line in &overrides {
+            println!("  {}", line);
+        }
+    }
+
+    let commands = effective_commands(&manifests);
+    println!("Commands:");
+    for c in &commands {
+        println!("  {}  {}  [{}]", c.pkg.command, c.pkg.docker_image, c.provider);
+        if !c.shadowed.is_empty() {
+            println!("    shadows:      {}", c.shadowed.join(", "));
+        }
+        let volumes = crate::shimlink::configured_volumes(config, c.pkg);
+        println!("    volumes:      {}", if volumes.is_empty() { "none".to_string() } else { volumes.join(", ") });
+        println!("    envvars:      {}", describe_allowlist(&crate::shimlink::envvar_allowlist(config, c.pkg)));
+        if !c.env_defaults.is_empty() {
+            println!("    env_defaults: {}", c.env_defaults.join(", "));
+        }
+        let tool_args = config.host_tool_specific_args(c.pkg, "docker_args");
+        let docker_args = c.pkg.merged_docker_args(&[&tool_args]);
+        if !docker_args.is_empty() {
+            let source = if tool_args.is_empty() { "" } else { " (with tool_args from the config)" };
+            println!("    docker_args:  {}{}", docker_args, source);
+        }
+        if let Some(ref args) = c.pkg.apptainer_args {
+            println!("    apptainer_args: {}", args);
+        }
+        if let Some(ref workdir) = c.pkg.workdir {
+            println!("    workdir:      {}", workdir);
+        }
+        if let Some(mask) = crate::shimlink::effective_umask(config, c.pkg) {
+            println!("    umask:        {:04o}", mask);
+        }
+    }
+
+    let just_manifests: Vec<Manifest> = manifests.iter().map(|(_, m)| m.clone()).collect();
+    let host_commands = crate::activate::effective_host_commands(config, &just_manifests);
+    println!("Host commands (strict mode): {}", if host_commands.is_empty() { "none".to_string() } else { host_commands.join(", ") });
+    println!("\n{} commands available", commands.len());
+    Ok(())
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, _config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
 
     let registry_path = match matches.get_one::<String>("crate_registry_paths") {
         Some(p) => p.clone(),
@@ -28,6 +194,9 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
             .map_err(|_| anyhow::anyhow!("No crate specified and no active crate (BULKERCRATE not set)"))?,
     };
     let cratelist = parse_registry_paths(&registry_path, &config.bulker.default_namespace)?;
+    if matches.get_flag("effective") {
+        return print_effective(&config, &registry_path, &cratelist);
+    }
 
     for cratevars in &cratelist {
         let manifest = manifest_cache::load_cached(cratevars)?
@@ -36,6 +205,15 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
                 cratevars.display_name(), cratevars.display_name()
             ))?;
 
+        if matches.get_flag("raw") {
+            // Crates cached before sources were kept only have the canonical form
+            match manifest.source {
+                Some(ref text) => print!("{}", text),
+                None => print!("{}", crate::manifest::to_canonical_yaml(&manifest)?),
+            }
+            continue;
+        }
+
         println!("Crate: {}", cratevars.display_name());
 
         // Show digests
@@ -68,17 +246,45 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
             }
         }
 
+        if !manifest.manifest.env_defaults.is_empty() {
+            println!("Env defaults:");
+            for entry in &manifest.manifest.env_defaults {
+                println!("  {}", entry);
+            }
+        }
+
+        let annotations = manifest_cache::read_annotations(cratevars);
+        if !annotations.is_empty() {
+            println!("Annotations:");
+            for (key, value) in &annotations {
+                println!("  {}={}", key, value);
+            }
+        }
+
+        if let Some(ref message) = manifest.manifest.activation_message {
+            println!("Activation message:");
+            for line in message.trim_end().lines() {
+                println!("  {}", line);
+            }
+        }
+
         // Show imports from the manifest itself
         if !manifest.manifest.imports.is_empty() {
             println!("Imports:");
             for import in &manifest.manifest.imports {
-                let import_cv = crate::manifest::parse_registry_path(import, &config.bulker.default_namespace)?;
+                let import_cv = crate::imports::resolve_import(&config, import)?;
+                let original = crate::manifest::parse_registry_path(import, &config.bulker.default_namespace)?;
+                let label = if import_cv.display_name() == original.display_name() {
+                    import.to_string()
+                } else {
+                    format!("{} -> {} (remapped)", import, import_cv.display_name())
+                };
                 match manifest_cache::load_cached(&import_cv) {
                     Ok(Some(m)) => {
                         let count = m.manifest.commands.len() + m.manifest.host_commands.len();
-                        println!("  {} ({} commands)", import, count);
+                        println!("  {} ({} commands)", label, count);
                     }
-                    _ => println!("  {} (not cached)", import),
+                    _ => println!("  {} (not cached)", label),
                 }
             }
         }
@@ -90,3 +296,37 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_commands_first_crate_wins() {
+        let cv = |name: &str| crate::manifest::parse_registry_path(name, "bulker").unwrap();
+        let mut app = crate::test_util::make_manifest_with_imports("app", vec!["bulker/base:1".to_string()]);
+        app.manifest.commands.push(PackageCommand { command: "samtools".to_string(), docker_image: "app/samtools".to_string(), ..Default::default() });
+        let mut base = crate::test_util::make_manifest_with_imports("base", vec![]);
+        base.manifest.commands.push(PackageCommand { command: "samtools".to_string(), docker_image: "base/samtools".to_string(), ..Default::default() });
+        base.manifest.env_defaults = vec!["A=1".to_string()];
+        let manifests = vec![(cv("bulker/app:1"), app), (cv("bulker/base:1"), base)];
+
+        let commands = effective_commands(&manifests);
+        let names: Vec<&str> = commands.iter().map(|c| c.pkg.command.as_str()).collect();
+        assert_eq!(names, vec!["app", "base", "samtools"]);
+        assert_eq!(commands[2].pkg.docker_image, "app/samtools");
+        assert_eq!(commands[2].shadowed, vec!["bulker/base:1"]);
+        assert!(commands[2].env_defaults.is_empty());
+        assert_eq!(commands[1].env_defaults, ["A=1"]);
+    }
+
+    #[test]
+    fn test_describe_allowlist() {
+        let mut patterns: Vec<String> = crate::shimlink::DEFAULT_ENVVARS.iter().map(|s| s.to_string()).collect();
+        assert_eq!(describe_allowlist(&patterns), "defaults");
+        patterns.push("MYVAR".to_string());
+        assert_eq!(describe_allowlist(&patterns), "defaults + MYVAR");
+        assert_eq!(describe_allowlist(&["MYVAR".to_string()]), "MYVAR");
+        assert_eq!(describe_allowlist(&[]), "none");
+    }
+}
@@ -1,9 +1,9 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::{Arg, ArgAction, ArgMatches, Command};
 
-use crate::config::load_config;
+use crate::config::{BulkerConfig, load_cli_config};
 use crate::digest;
-use crate::manifest::{is_local_path, is_url, load_local_manifest, load_url_manifest, parse_registry_paths, CrateVars, Manifest};
+use crate::manifest::{is_local_path, is_stdin_path, is_url, load_local_manifest, load_stdin_manifest, load_url_manifest, parse_registry_paths, CommandsFilter, CrateVars, Manifest};
 use crate::manifest_cache;
 
 pub fn create_cli() -> Command {
@@ -14,17 +14,21 @@ EXAMPLES:
   bulker crate install bulker/demo
   bulker crate install databio/pepatac:1.0.13
   bulker crate install -b bulker/demo             # also pull container images
+  bulker crate install --images-only bulker/demo  # pull images of an already-cached crate
+  bulker crate install -b --commands-filter 'samtools,bcf*' bulker/biocontainers  # pull only these tools' images
   bulker crate install ./manifest.yaml            # cache from local file
+  gen-manifest | bulker crate install -n me/tools:1.0 -   # read from stdin
 
 CRATEFILE FORMAT:
   namespace/crate:tag    Registry shorthand (e.g., databio/pepatac:1.0.13)
   crate                  Uses default namespace \"bulker\", tag \"default\"
-  ./path/to/file.yaml    Local cratefile
-  https://url/file.yaml  Remote cratefile")
+  ./path/to/file.yaml    Local cratefile (.yaml, .yml or .json)
+  https://url/file.yaml  Remote cratefile
+  -                      Cratefile read from stdin (requires --name)")
         .arg(
             Arg::new("cratefile")
                 .required(true)
-                .help("Cratefile: registry shorthand, URL, or local file path"),
+                .help("Cratefile: registry shorthand, URL, local file path, or - for stdin (requires --name)"),
         )
         .arg(
             Arg::new("build")
@@ -33,12 +37,27 @@ CRATEFILE FORMAT:
                 .action(ArgAction::SetTrue)
                 .help("Build/pull container images"),
         )
+        .arg(
+            Arg::new("images-only")
+                .long("images-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["build", "name", "no-overwrite"])
+                .help("Pull/build images of already-cached crates (and their imports) without re-fetching manifests"),
+        )
         .arg(
             Arg::new("name")
                 .short('n')
                 .long("name")
                 .help("Override crate identity (e.g., bulker/biobase or bulker/biobase:0.1.0)"),
         )
+        .arg(
+            Arg::new("commands-filter")
+                .long("commands-filter")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("With --build or --images-only, only pull the images of commands matching these globs \
+                       (comma-separated or repeated); the whole manifest is still cached"),
+        )
         .arg(
             Arg::new("no-overwrite")
                 .long("no-overwrite")
@@ -48,20 +67,35 @@ CRATEFILE FORMAT:
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, _config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
 
     let cratefile = matches.get_one::<String>("cratefile").unwrap();
     let build = matches.get_flag("build");
     let name_override = matches.get_one::<String>("name").map(|s| s.as_str());
     let no_overwrite = matches.get_flag("no-overwrite");
+    let filter = CommandsFilter::parse(matches.get_many::<String>("commands-filter").into_iter().flatten().map(String::as_str))?;
+
+    if matches.get_flag("images-only") {
+        return pull_cached_images(&config, cratefile, &filter);
+    }
+    if !filter.is_empty() && !build {
+        bail!("--commands-filter selects the images to pull; use it with --build or --images-only");
+    }
 
-    if is_url(cratefile) {
+    if is_stdin_path(cratefile) {
+        // Manifest piped on stdin (identity from --name)
+        let (cv, manifest) = load_stdin_manifest(name_override, &config.bulker.default_namespace)?;
+        manifest_cache::save_to_cache(&cv, &manifest)?;
+        if build {
+            pull_matching_images(&config, &cv, &manifest, &filter)?;
+        }
+        println!("Cached: {}", cv.display_name());
+    } else if is_url(cratefile) {
         // Remote manifest URL
         let (cv, manifest) = load_url_manifest(cratefile, name_override, &config.bulker.default_namespace)?;
         manifest_cache::save_to_cache(&cv, &manifest)?;
         if build {
-            manifest_cache::pull_images(&config, &manifest)?;
-            attempt_image_digest(&cv, &manifest);
+            pull_matching_images(&config, &cv, &manifest, &filter)?;
         }
         println!("Cached: {}", cv.display_name());
     } else if is_local_path(cratefile) {
@@ -69,20 +103,18 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         let (cv, manifest) = load_local_manifest(cratefile, name_override, &config.bulker.default_namespace)?;
         manifest_cache::save_to_cache(&cv, &manifest)?;
         if build {
-            manifest_cache::pull_images(&config, &manifest)?;
-            attempt_image_digest(&cv, &manifest);
+            pull_matching_images(&config, &cv, &manifest, &filter)?;
         }
         println!("Cached: {}", cv.display_name());
     } else {
         // Registry path(s)
-        let cratelist = parse_registry_paths(cratefile, &config.bulker.default_namespace)?;
+        let cratelist = manifest_cache::resolve_tag_aliases(parse_registry_paths(cratefile, &config.bulker.default_namespace)?);
         for cv in &cratelist {
             let mut visited = std::collections::HashSet::new();
             manifest_cache::ensure_cached_with_imports(&config, cv, true, no_overwrite, &mut visited, 0)?;  // always fetch fresh on explicit install
             if build {
                 let manifest = manifest_cache::load_cached(cv)?.unwrap();
-                manifest_cache::pull_images(&config, &manifest)?;
-                attempt_image_digest(cv, &manifest);
+                pull_matching_images(&config, cv, &manifest, &filter)?;
             }
             println!("Cached: {}", cv.display_name());
         }
@@ -91,6 +123,40 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// `--images-only`: pull the images of cached crates and their imports from
+/// the cached manifests, e.g. on compute nodes that share the manifest cache
+/// but keep images locally. The registry is never asked for manifests.
+fn pull_cached_images(config: &BulkerConfig, cratefile: &str, filter: &CommandsFilter) -> Result<()> {
+    if is_stdin_path(cratefile) || is_url(cratefile) || is_local_path(cratefile) {
+        bail!("--images-only works on cached crates; pass registry paths, not a cratefile");
+    }
+    for cv in manifest_cache::resolve_tag_aliases(parse_registry_paths(cratefile, &config.bulker.default_namespace)?) {
+        if manifest_cache::load_cached(&cv)?.is_none() {
+            bail!("Crate '{}' is not cached. Run 'bulker crate install {}' first.", cv.display_name(), cv.display_name());
+        }
+        for dep in crate::imports::resolve_cratevars_with_imports(config, std::slice::from_ref(&cv))? {
+            if let Some(manifest) = manifest_cache::load_cached(&dep)? {
+                pull_matching_images(config, &dep, &manifest, filter)?;
+            }
+        }
+        println!("Pulled images: {}", cv.display_name());
+    }
+    Ok(())
+}
+
+/// Pull the images of the commands of `manifest` that `filter` matches.
+fn pull_matching_images(config: &BulkerConfig, cv: &CrateVars, manifest: &Manifest, filter: &CommandsFilter) -> Result<()> {
+    if filter.is_empty() {
+        manifest_cache::pull_crate_images(config, cv, manifest)?;
+        attempt_image_digest(cv, manifest);
+        return Ok(());
+    }
+    let mut selected = manifest.clone();
+    filter.apply(&mut selected);
+    // The crate-image-digest covers every image, so a partial pull leaves it alone
+    manifest_cache::pull_crate_images(config, cv, &selected)
+}
+
 /// Best-effort: resolve OCI digests and store the crate-image-digest sidecar.
 fn attempt_image_digest(cv: &CrateVars, manifest: &Manifest) {
     let oci_digests = digest::resolve_oci_digests(manifest);
@@ -101,3 +167,42 @@ fn attempt_image_digest(cv: &CrateVars, manifest: &Manifest) {
         log::debug!("Could not compute crate-image-digest (some images not resolved)");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::parse_registry_path;
+
+    #[test]
+    fn test_images_only_pulls_from_cached_manifests_and_imports() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let config = BulkerConfig::test_default();
+        let filter = CommandsFilter::parse(["app,base"]).unwrap();
+
+        let err = pull_cached_images(&config, "bulker/app:1", &filter).unwrap_err();
+        assert!(err.to_string().contains("is not cached"), "{}", err);
+        assert!(pull_cached_images(&config, "./manifest.yaml", &filter).is_err());
+
+        let app_cv = parse_registry_path("bulker/app:1", "bulker").unwrap();
+        let base_cv = parse_registry_path("bulker/base:1", "bulker").unwrap();
+        for (cv, mut manifest) in [
+            (&app_cv, crate::test_util::make_manifest_with_imports("app", vec!["bulker/base:1".to_string()])),
+            (&base_cv, crate::test_util::make_manifest_with_imports("base", vec![])),
+        ] {
+            // Nothing reaches the engine, but the pull is still recorded
+            manifest.manifest.commands[0].pull_policy = Some("never".to_string());
+            manifest_cache::save_to_cache(cv, &manifest).unwrap();
+        }
+
+        pull_cached_images(&config, "bulker/app:1", &filter).unwrap();
+        for cv in [&app_cv, &base_cv] {
+            assert_eq!(
+                manifest_cache::read_digest_sidecar(cv, manifest_cache::BUILD_ENGINE_SIDECAR).as_deref(),
+                Some(config.bulker.container_engine.as_str()),
+                "{}",
+                cv.display_name()
+            );
+        }
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use clap::{ArgAction, ArgMatches, Command};
 use std::collections::BTreeMap;
 
-use crate::manifest::{Manifest};
+use crate::manifest::parse_manifest;
 use crate::manifest_cache;
 
 pub fn create_cli() -> Command {
@@ -12,6 +12,8 @@ pub fn create_cli() -> Command {
 EXAMPLES:
   bulker crate list
   bulker crate list --versions                 # show all cached versions
+  bulker crate list --long                     # include install and last-used times, annotations
+  bulker crate list --filter project=encode-rerun   # crates annotated so (repeatable)
   bulker crate list --simple                   # simple format for scripting")
         .arg(
             clap::Arg::new("simple")
@@ -26,6 +28,20 @@ EXAMPLES:
                 .action(ArgAction::SetTrue)
                 .help("Show all cached versions for each crate"),
         )
+        .arg(
+            clap::Arg::new("long")
+                .long("long")
+                .short('l')
+                .action(ArgAction::SetTrue)
+                .help("Show install and last-used times and annotations for each crate"),
+        )
+        .arg(
+            clap::Arg::new("filter")
+                .long("filter")
+                .value_name("KEY[=VALUE]")
+                .action(ArgAction::Append)
+                .help("Only crates with this annotation (see `crate annotate`); repeatable, all must match"),
+        )
 }
 
 /// Parse a version tag into comparable parts for semver-aware sorting.
@@ -52,7 +68,7 @@ fn version_sort_key(tag: &str) -> (u8, Vec<i64>, String) {
 }
 
 /// Sort version tags descending: newest semver first, "default" last.
-fn sort_versions_desc(tags: &mut Vec<String>) {
+pub(super) fn sort_versions_desc(tags: &mut Vec<String>) {
     tags.sort_by(|a, b| {
         let ka = version_sort_key(a);
         let kb = version_sort_key(b);
@@ -78,7 +94,24 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
     let simple = matches.get_flag("simple");
     let show_versions = matches.get_flag("versions");
 
-    let cached = manifest_cache::list_cached()?;
+    let filters = matches
+        .get_many::<String>("filter")
+        .into_iter()
+        .flatten()
+        .map(|f| super::annotate::Filter::parse(f))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut cached = manifest_cache::list_cached()?;
+    if !filters.is_empty() {
+        cached.retain(|(cv, _)| {
+            let annotations = manifest_cache::read_annotations(cv);
+            filters.iter().all(|f| f.matches(&annotations))
+        });
+        if cached.is_empty() {
+            println!("No cached crates match the filter.");
+            return Ok(());
+        }
+    }
 
     if cached.is_empty() {
         println!("No cached crates.");
@@ -94,34 +127,85 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         return Ok(());
     }
 
+    let long = matches.get_flag("long");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
     // Group by namespace/crate_name -> Vec<(tag, version, digest)>
     let mut grouped: BTreeMap<String, Vec<(String, String, Option<String>)>> = BTreeMap::new();
+    // Per display name -> (installed, last used), only filled in --long mode
+    let mut times: std::collections::HashMap<String, (String, String)> = std::collections::HashMap::new();
+    // Per display name -> annotations as `key=value, ...`, only in --long mode
+    let mut notes: std::collections::HashMap<String, String> = std::collections::HashMap::new();
     for (cv, manifest_path) in &cached {
         let key = format!("{}/{}", cv.namespace, cv.crate_name);
         let digest = manifest_cache::read_digest_sidecar(cv, "crate-manifest-digest");
         let version = std::fs::read_to_string(manifest_path)
             .ok()
-            .and_then(|contents| serde_yml::from_str::<Manifest>(&contents).ok())
+            .and_then(|contents| parse_manifest(&contents).ok())
             .and_then(|m| m.manifest.version)
             .unwrap_or_default();
+        if long {
+            times.insert(cv.display_name(), (
+                crate::humanize::format_age(now, manifest_cache::read_timestamp_sidecar(cv, "installed-at")),
+                crate::humanize::format_age(now, manifest_cache::read_timestamp_sidecar(cv, "last-used")),
+            ));
+            let annotations: Vec<String> = manifest_cache::read_annotations(cv)
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            if !annotations.is_empty() {
+                notes.insert(cv.display_name(), annotations.join(", "));
+            }
+        }
         grouped.entry(key).or_default().push((cv.tag.clone(), version, digest));
     }
 
+    // Extra columns appended in --long mode
+    let time_width = 10;
+    let time_cols = |full_name: &str, tag: &str| -> String {
+        if !long {
+            return String::new();
+        }
+        let (installed, used) = times
+            .get(&format!("{}:{}", full_name, tag))
+            .cloned()
+            .unwrap_or_else(|| ("never".to_string(), "never".to_string()));
+        format!("  {:<w$}  {:<w$}", installed, used, w = time_width)
+    };
+
     // Calculate column widths
     let max_crate_width = grouped.keys().map(|k| k.len()).max().unwrap_or(20);
+    // Annotations go on a line of their own under the tag they belong to
+    let print_notes = |full_name: &str, tag: &str| {
+        if let Some(line) = notes.get(&format!("{}:{}", full_name, tag)) {
+            println!("  {:<cw$}  {}", "", line, cw = max_crate_width);
+        }
+    };
     let tag_width = 10;
     let version_width = 10;
     let digest_width = 12;
 
+    let (time_header, time_rule) = if long {
+        (
+            format!("  {:<w$}  {:<w$}", "Installed", "Last used", w = time_width),
+            format!("  {}  {}", "─".repeat(time_width), "─".repeat(time_width)),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+
     println!();
     println!(
-        "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}",
-        "Crate", "Tag", "Version", "Digest",
+        "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}{}",
+        "Crate", "Tag", "Version", "Digest", time_header,
         cw = max_crate_width, tw = tag_width, vw = version_width, dw = digest_width
     );
     println!(
-        "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}",
-        "─".repeat(max_crate_width), "─".repeat(tag_width), "─".repeat(version_width), "─".repeat(digest_width),
+        "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}{}",
+        "─".repeat(max_crate_width), "─".repeat(tag_width), "─".repeat(version_width), "─".repeat(digest_width), time_rule,
         cw = max_crate_width, tw = tag_width, vw = version_width, dw = digest_width
     );
 
@@ -144,18 +228,19 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
                 let version_str = if version.is_empty() { "" } else { version };
                 if first {
                     println!(
-                        "  {:<cw$}  {:<tw$}  {:<vw$}  {}",
-                        full_name, tag, version_str, digest_str,
-                        cw = max_crate_width, tw = tag_width, vw = version_width
+                        "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}{}",
+                        full_name, tag, version_str, digest_str, time_cols(&full_name, tag),
+                        cw = max_crate_width, tw = tag_width, vw = version_width, dw = digest_width
                     );
                     first = false;
                 } else {
                     println!(
-                        "  {:<cw$}  {:<tw$}  {:<vw$}  {}",
-                        "", tag, version_str, digest_str,
-                        cw = max_crate_width, tw = tag_width, vw = version_width
+                        "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}{}",
+                        "", tag, version_str, digest_str, time_cols(&full_name, tag),
+                        cw = max_crate_width, tw = tag_width, vw = version_width, dw = digest_width
                     );
                 }
+                print_notes(&full_name, tag);
             }
         } else {
             let latest = tag_list.first().map(|s| s.as_str()).unwrap_or("default");
@@ -169,10 +254,18 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
                 String::new()
             };
             println!(
-                "  {:<cw$}  {:<tw$}  {:<vw$}  {}{}",
-                full_name, latest, version_str, digest_str, extra_str,
-                cw = max_crate_width, tw = tag_width, vw = version_width
+                "  {:<cw$}  {:<tw$}  {:<vw$}  {:<dw$}{}{}",
+                full_name, latest, version_str, digest_str, time_cols(&full_name, latest), extra_str,
+                cw = max_crate_width, tw = tag_width, vw = version_width, dw = digest_width
             );
+            print_notes(&full_name, latest);
+        }
+
+        let (namespace, crate_name) = full_name.split_once('/').unwrap_or(("", &full_name));
+        let aliases = manifest_cache::read_aliases(namespace, crate_name);
+        if !aliases.is_empty() {
+            let aliases: Vec<String> = aliases.iter().map(|(alias, tag)| format!("{} -> {}", alias, tag)).collect();
+            println!("  {:<cw$}  aliases: {}", "", aliases.join(", "), cw = max_crate_width);
         }
     }
 
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+use std::path::PathBuf;
+
+use crate::config::load_cli_config;
+use crate::lockfile::{self, LOCKFILE_NAME};
+use crate::manifest::parse_registry_paths;
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("lock")
+        .about("Pin a crate's images to their content digests in bulker.lock")
+        .after_help("\
+EXAMPLES:
+  bulker crate lock databio/pepatac:1.0.13
+  bulker crate lock databio/pepatac:1.0.13,bulker/demo
+  bulker crate lock -o envs/rnaseq.lock my/rnaseq
+  bulker activate --locked databio/pepatac:1.0.13   # run the locked images
+
+Resolves every image of the crates and their imports to its OCI content
+digest (this needs access to the image registries) and writes them, with
+each manifest's digest, to bulker.lock in the current directory. With
+`activate --locked` or `exec --locked`, commands run `image@digest`, and
+crates whose manifest changed since they were locked are refused.")
+        .arg(
+            Arg::new("crate_registry_paths")
+                .required(true)
+                .help("Crate(s) to lock (comma-separated for multiple)"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .default_value(LOCKFILE_NAME)
+                .help("Lockfile to write"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
+    let output = PathBuf::from(matches.get_one::<String>("output").unwrap());
+
+    let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
+    for cv in &cratelist {
+        let mut visited = std::collections::HashSet::new();
+        manifest_cache::ensure_cached_with_imports(&config, cv, false, false, &mut visited, 0)?;
+    }
+    let lock = lockfile::lock_crates(&config, &cratelist)?;
+    lock.write(&output)?;
+
+    let images: usize = lock.crates.values().map(|c| c.images.len()).sum();
+    println!("Locked {} crate(s), {} image(s) in {}", lock.crates.len(), images, output.display());
+    Ok(())
+}
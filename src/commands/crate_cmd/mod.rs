@@ -1,9 +1,23 @@
+pub mod alias;
+pub mod annotate;
 pub mod clean;
 pub mod compare;
 pub mod digest;
+pub mod export;
+pub mod export_spack;
+pub mod fmt;
+pub mod graph;
+pub mod import;
 pub mod install;
 pub mod inspect;
 pub mod list;
+pub mod lock;
+pub mod publish;
+pub mod rename_namespace;
+pub mod scan;
+pub mod search;
+pub mod sync;
+pub mod wrappers;
 
 use anyhow::Result;
 use clap::{Arg, ArgMatches, Command};
@@ -22,20 +36,48 @@ pub fn create_cli() -> Command {
         )
         .subcommand(install::create_cli())
         .subcommand(list::create_cli())
+        .subcommand(search::create_cli())
         .subcommand(inspect::create_cli())
         .subcommand(clean::create_cli())
         .subcommand(digest::create_cli())
+        .subcommand(lock::create_cli())
         .subcommand(compare::create_cli())
+        .subcommand(fmt::create_cli())
+        .subcommand(graph::create_cli())
+        .subcommand(sync::create_cli())
+        .subcommand(rename_namespace::create_cli())
+        .subcommand(export::create_cli())
+        .subcommand(import::create_cli())
+        .subcommand(publish::create_cli())
+        .subcommand(export_spack::create_cli())
+        .subcommand(wrappers::create_cli())
+        .subcommand(scan::create_cli())
+        .subcommand(alias::create_cli())
+        .subcommand(annotate::create_cli())
 }
 
 pub fn dispatch(matches: &ArgMatches) -> Result<()> {
     match matches.subcommand() {
         Some(("install", sub_m)) => install::run(sub_m),
         Some(("list", sub_m)) => list::run(sub_m),
+        Some(("search", sub_m)) => search::run(sub_m),
         Some(("inspect", sub_m)) => inspect::run(sub_m),
         Some(("clean", sub_m)) => clean::run(sub_m),
         Some(("digest", sub_m)) => digest::run(sub_m),
+        Some(("lock", sub_m)) => lock::run(sub_m),
         Some(("compare", sub_m)) => compare::run(sub_m),
+        Some(("fmt", sub_m)) => fmt::run(sub_m),
+        Some(("graph", sub_m)) => graph::run(sub_m),
+        Some(("sync", sub_m)) => sync::run(sub_m),
+        Some(("rename-namespace", sub_m)) => rename_namespace::run(sub_m),
+        Some(("export", sub_m)) => export::run(sub_m),
+        Some(("import", sub_m)) => import::run(sub_m),
+        Some(("publish", sub_m)) => publish::run(sub_m),
+        Some(("export-spack", sub_m)) => export_spack::run(sub_m),
+        Some(("wrappers", sub_m)) => wrappers::run(sub_m),
+        Some(("scan", sub_m)) => scan::run(sub_m),
+        Some(("alias", sub_m)) => alias::run(sub_m),
+        Some(("annotate", sub_m)) => annotate::run(sub_m),
         _ => unreachable!(),
     }
 }
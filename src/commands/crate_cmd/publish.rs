@@ -0,0 +1,175 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::load_cli_config;
+use crate::digest;
+use crate::manifest::{CrateVars, Manifest, build_manifest_url, is_local_path, parse_manifest, parse_registry_path, read_manifest_file};
+
+pub fn create_cli() -> Command {
+    Command::new("publish")
+        .about("Validate a cratefile and upload it to a writable registry")
+        .after_help("\
+EXAMPLES:
+  bulker config set publish_url=https://registry.example.org/
+  bulker config set publish_token=env:BULKER_PUBLISH_TOKEN
+  bulker crate publish pepatac.yaml databio/pepatac:1.0.14
+  bulker crate publish --dry-run pepatac.yaml databio/pepatac:1.0.14
+  bulker crate publish --resolve pepatac.yaml databio/pepatac:1.0.14
+
+The cratefile is validated first: its name (if any) must match the target,
+imports must be registry paths (not files), image digests must be
+well-formed and deprecated fields must be fixed (`bulker crate fmt`). It is
+then POSTed as written to <publish_url>/<namespace>/<crate>_<tag>.yaml, the
+path registries serve it from, with its crate-manifest-digest (and, with
+--resolve, its crate-image-digest) in X-Bulker-* headers and publish_token
+as a bearer token. The token is only sent over https:// (or to localhost).")
+        .arg(
+            Arg::new("cratefile")
+                .required(true)
+                .help("Cratefile to publish"),
+        )
+        .arg(
+            Arg::new("crate_registry_path")
+                .required(true)
+                .help("Registry path to publish it as (namespace/crate:tag)"),
+        )
+        .arg(
+            Arg::new("url")
+                .long("url")
+                .value_name("URL")
+                .help("Writable registry to publish to (default: publish_url from the config)"),
+        )
+        .arg(
+            Arg::new("resolve")
+                .long("resolve")
+                .action(ArgAction::SetTrue)
+                .help("Also compute the crate-image-digest by resolving OCI image digests (requires network)"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Validate and print the digests without uploading"),
+        )
+}
+
+/// Refuse manifests that would not work, or not be what the target says,
+/// once in the registry.
+fn validate(manifest: &Manifest, cv: &CrateVars) -> Result<()> {
+    let target = cv.display_name();
+    // A bare name (the usual `name: pepatac`) only has to match the crate
+    if let Some(ref name) = manifest.manifest.name {
+        let matches = match name.split_once('/') {
+            Some((namespace, crate_name)) => namespace == cv.namespace && crate_name == cv.crate_name,
+            None => *name == cv.crate_name,
+        };
+        if !matches {
+            bail!("The cratefile is named '{}' but would be published as '{}'. Fix its name field or the target.", name, target);
+        }
+    }
+    if let Some(version) = manifest.manifest.version.as_deref().filter(|v| *v != cv.tag) {
+        log::warn!("Publishing version '{}' of the cratefile as tag '{}'", version, cv.tag);
+    }
+    if let Some(import) = manifest.manifest.imports.iter().find(|i| is_local_path(i)) {
+        bail!("Import '{}' is a file; published crates can only import registry crates (namespace/crate:tag).", import);
+    }
+    let deprecations = manifest.deprecations();
+    if !deprecations.is_empty() {
+        bail!(
+            "The cratefile uses deprecated fields:\n  {}\nRun `bulker crate fmt` before publishing.",
+            deprecations.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n  ")
+        );
+    }
+    crate::manifest::check_image_references(manifest, &target)?;
+    crate::manifest::check_host_access(manifest, &target);
+    Ok(())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let cratefile = matches.get_one::<String>("cratefile").unwrap();
+    let cv = parse_registry_path(matches.get_one::<String>("crate_registry_path").unwrap(), &config.bulker.default_namespace)?;
+
+    let text = read_manifest_file(std::path::Path::new(cratefile))
+        .with_context(|| format!("Failed to read cratefile: {}", cratefile))?;
+    let manifest = parse_manifest(&text).with_context(|| format!("Failed to parse cratefile: {}", cratefile))?;
+    validate(&manifest, &cv)?;
+
+    let manifest_digest = digest::crate_manifest_digest(&manifest).digest;
+    let image_digest = if matches.get_flag("resolve") {
+        log::info!("Resolving OCI digests from registries...");
+        let oci_digests = digest::resolve_oci_digests(&manifest);
+        let result = digest::crate_image_digest(&manifest, &oci_digests);
+        if result.is_none() {
+            log::warn!("crate-image-digest not available: some images could not be resolved");
+        }
+        result.map(|r| r.digest)
+    } else {
+        None
+    };
+    println!("Crate: {}", cv.display_name());
+    println!("crate-manifest-digest:  {}", manifest_digest);
+    if let Some(ref d) = image_digest {
+        println!("crate-image-digest:     {}", d);
+    }
+
+    let registry = match matches.get_one::<String>("url").or(config.bulker.publish_url.as_ref()) {
+        Some(url) => url,
+        None if matches.get_flag("dry-run") => return Ok(()),
+        None => bail!("No registry to publish to. Set one with `bulker config set publish_url=<url>` or pass --url."),
+    };
+    let url = build_manifest_url(registry, &cv);
+    if matches.get_flag("dry-run") {
+        println!("Would publish to {}", url);
+        return Ok(());
+    }
+
+    let mut req = ureq::post(&url)
+        .set("Content-Type", "application/yaml")
+        .set("X-Bulker-Crate", &cv.display_name())
+        .set("X-Bulker-Crate-Manifest-Digest", &manifest_digest);
+    if let Some(ref d) = image_digest {
+        req = req.set("X-Bulker-Crate-Image-Digest", d);
+    }
+    if let Some(ref reference) = config.bulker.publish_token {
+        if crate::download::is_cleartext(&url) {
+            bail!("Refusing to send publish_token over plain HTTP to {}. Use an https:// publish_url.", url);
+        }
+        req = req.set("Authorization", &format!("Bearer {}", crate::config::resolve_credential(reference)?));
+    }
+    match req.send_string(&text) {
+        Ok(_) => {}
+        Err(ureq::Error::Status(status @ (401 | 403), _)) => {
+            bail!("{} refused the upload ({}). Check publish_token.", url, status)
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to publish to {}", url)),
+    }
+    println!("Published {} to {}", cv.display_name(), url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cv() -> CrateVars {
+        parse_registry_path("databio/pepatac:1.0.14", "bulker").unwrap()
+    }
+
+    #[test]
+    fn test_validate_checks_name_imports_and_deprecations() {
+        let bare = parse_manifest("manifest:\n  name: pepatac\n  commands: []\n").unwrap();
+        validate(&bare, &cv()).unwrap();
+        let ok = parse_manifest("manifest:\n  name: databio/pepatac\n  imports: [bulker/base:1]\n  commands:\n  - {command: samtools, docker_image: 'samtools:1'}\n").unwrap();
+        validate(&ok, &cv()).unwrap();
+
+        let renamed = parse_manifest("manifest:\n  name: databio/peppro\n  commands: []\n").unwrap();
+        assert!(validate(&renamed, &cv()).unwrap_err().to_string().contains("named 'databio/peppro'"));
+
+        let file_import = parse_manifest("manifest:\n  name: databio/pepatac\n  imports: [./base.yaml]\n  commands: []\n").unwrap();
+        assert!(validate(&file_import, &cv()).unwrap_err().to_string().contains("is a file"));
+
+        let bad_digest = parse_manifest("manifest:\n  commands:\n  - {command: samtools, docker_image: 'samtools@sha256:abc'}\n").unwrap();
+        assert!(validate(&bad_digest, &cv()).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::load_cli_config;
+use crate::manifest::{CrateVars, parse_registry_path};
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("rename-namespace")
+        .about("Move cached crates to a new namespace and rewrite imports that reference them")
+        .after_help("\
+EXAMPLES:
+  bulker crate rename-namespace oldorg neworg --dry-run   # report affected crates
+  bulker crate rename-namespace oldorg neworg
+
+Every cached oldorg/<crate>:<tag> moves to neworg/<crate>:<tag> with its install
+and last-used times, and every cached manifest importing an oldorg crate is
+rewritten to import the neworg one (in its authored text too, comments kept).
+Manifests fetched later still carry the old imports; add `import_remap` entries
+to the config for those.")
+        .arg(Arg::new("old").required(true).help("Namespace to rename"))
+        .arg(Arg::new("new").required(true).help("New namespace"))
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Print the affected crates without changing the cache"),
+        )
+}
+
+/// `import` pointed at namespace `new` if it resolves into `old`, keeping
+/// its tag as written. None if the import is elsewhere.
+fn rename_import(import: &str, old: &str, new: &str, default_namespace: &str) -> Option<String> {
+    let cv = parse_registry_path(import, default_namespace).ok()?;
+    if cv.namespace != old {
+        return None;
+    }
+    let import = import.trim();
+    let rest = import.split_once('/').map_or(import, |(_, rest)| rest);
+    Some(format!("{}/{}", new, rest))
+}
+
+/// `source` with the import list entry `from` replaced by `to`. None if the
+/// entry isn't written as a plain list item, e.g. in a flow sequence or quoted.
+fn rename_in_source(source: &str, from: &str, to: &str) -> Option<String> {
+    let mut found = false;
+    let lines: Vec<String> = source
+        .lines()
+        .map(|line| {
+            let item = line.trim_start().strip_prefix("- ").map(|rest| rest.split(" #").next().unwrap_or(rest).trim());
+            if item == Some(from) {
+                found = true;
+                line.replacen(from, to, 1)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    let trailing_newline = if source.ends_with('\n') { "\n" } else { "" };
+    found.then(|| lines.join("\n") + trailing_newline)
+}
+
+fn renamed(cv: &CrateVars, old: &str, new: &str) -> CrateVars {
+    if cv.namespace == old {
+        CrateVars { namespace: new.to_string(), ..cv.clone() }
+    } else {
+        cv.clone()
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let old = matches.get_one::<String>("old").unwrap();
+    let new = matches.get_one::<String>("new").unwrap();
+    let dry_run = matches.get_flag("dry-run");
+    let default_ns = &config.bulker.default_namespace;
+    if old == new {
+        bail!("Old and new namespace are both '{}'", old);
+    }
+    // Reuse the crate path validation for the namespace
+    parse_registry_path(&format!("{}/x", new), default_ns)?;
+
+    let cached = manifest_cache::list_cached()?;
+    let moves: Vec<(CrateVars, CrateVars)> = cached
+        .iter()
+        .filter(|(cv, _)| cv.namespace == *old)
+        .map(|(cv, _)| (cv.clone(), renamed(cv, old, new)))
+        .collect();
+    let conflicts: Vec<String> = moves
+        .iter()
+        .filter(|(_, to)| manifest_cache::manifest_path(to).exists())
+        .map(|(_, to)| to.display_name())
+        .collect();
+    if !conflicts.is_empty() {
+        bail!("Already cached under '{}': {}. Remove them first with `bulker crate clean`.", new, conflicts.join(", "));
+    }
+
+    // Manifests whose imports change, under their post-move identity
+    let mut rewrites = Vec::new();
+    for (cv, _) in &cached {
+        let Some(mut manifest) = manifest_cache::load_cached(cv)? else { continue };
+        let mut changed = Vec::new();
+        for import in manifest.manifest.imports.iter_mut() {
+            if let Some(updated) = rename_import(import, old, new, default_ns) {
+                // Keep the authored text (and its comments) in step where possible
+                manifest.source = manifest.source.take().and_then(|text| rename_in_source(&text, import, &updated));
+                changed.push(format!("{} -> {}", import, updated));
+                *import = updated;
+            }
+        }
+        if !changed.is_empty() {
+            rewrites.push((renamed(cv, old, new), manifest, changed));
+        }
+    }
+
+    if moves.is_empty() && rewrites.is_empty() {
+        println!("No cached crates in or importing namespace '{}'.", old);
+        return Ok(());
+    }
+
+    let prefix = if dry_run { "Would " } else { "" };
+    for (from, to) in &moves {
+        if !dry_run {
+            manifest_cache::move_cached(from, to)?;
+        }
+        println!("{}{}: {} -> {}", prefix, if dry_run { "move" } else { "Moved" }, from.display_name(), to.display_name());
+    }
+    for (cv, manifest, changed) in &rewrites {
+        if !dry_run {
+            manifest_cache::write_manifest(cv, manifest)?;
+        }
+        println!(
+            "{}{} imports of {}: {}",
+            prefix,
+            if dry_run { "rewrite" } else { "Rewrote" },
+            cv.display_name(),
+            changed.join(", ")
+        );
+    }
+    if !dry_run {
+        println!(
+            "Renamed namespace '{}' to '{}': {} crate(s) moved, {} manifest(s) rewritten",
+            old, new, moves.len(), rewrites.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_import_keeps_tag_form() {
+        assert_eq!(rename_import("oldorg/tools:1.0", "oldorg", "neworg", "bulker").as_deref(), Some("neworg/tools:1.0"));
+        assert_eq!(rename_import("oldorg/tools", "oldorg", "neworg", "bulker").as_deref(), Some("neworg/tools"));
+        assert_eq!(rename_import("other/tools", "oldorg", "neworg", "bulker"), None);
+        // Bare imports resolve through the default namespace
+        assert_eq!(rename_import("coreutils", "bulker", "neworg", "bulker").as_deref(), Some("neworg/coreutils"));
+        assert_eq!(rename_import("coreutils", "oldorg", "neworg", "bulker"), None);
+    }
+
+    #[test]
+    fn test_rename_in_source_keeps_comments() {
+        let source = "manifest:\n  name: app\n  imports:\n  - oldorg/base:1  # shared tools\n  commands: []\n";
+        assert_eq!(
+            rename_in_source(source, "oldorg/base:1", "neworg/base:1").unwrap(),
+            "manifest:\n  name: app\n  imports:\n  - neworg/base:1  # shared tools\n  commands: []\n"
+        );
+        assert_eq!(rename_in_source("manifest:\n  imports: [oldorg/base:1]\n", "oldorg/base:1", "neworg/base:1"), None);
+    }
+}
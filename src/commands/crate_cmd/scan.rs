@@ -0,0 +1,491 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::config::{BulkerConfig, load_cli_config};
+use crate::manifest::{CrateVars, parse_registry_path};
+use crate::manifest_cache;
+
+/// Exit status when findings reach the `--fail-on` severity.
+pub const EXIT_FINDINGS: i32 = 3;
+
+pub fn create_cli() -> Command {
+    Command::new("scan")
+        .about("Scan a crate's images for known vulnerabilities (trivy, grype or a scanning service)")
+        .after_help("\
+EXAMPLES:
+  bulker crate scan databio/pepatac:1.0.13
+  bulker crate scan databio/pepatac:1.0.13 --severity high
+  bulker crate scan databio/pepatac:1.0.13 --fail-on critical    # gate a deployment
+  bulker crate scan databio/pepatac:1.0.13 --json | jq '.commands[] | select(.counts.critical > 0)'
+
+Each image of the crate and its imports is scanned once, and findings are
+reported for every command that runs the image. The scanner is the first
+available of trivy and grype on PATH, then the `scan_api` service:
+
+  bulker config set scan_api=https://scanner.example.org/v1/scan
+  bulker config set scan_api_token=env:SCAN_TOKEN
+
+which gets a POST of {\"image\": \"<image>\"} per image (with the token as a
+bearer token) and must answer with a trivy or grype JSON report. The images
+are scanned as the registry serves them; the scanner pulls them itself.
+
+EXIT STATUS:
+  0  no findings at or above --fail-on (or no --fail-on)
+  1  error, or some images could not be scanned
+  3  findings at or above --fail-on")
+        .arg(
+            Arg::new("crate_registry_path")
+                .required(true)
+                .help("Crate to scan (fetched if not cached)"),
+        )
+        .arg(
+            Arg::new("scanner")
+                .long("scanner")
+                .value_parser(["trivy", "grype", "api"])
+                .help("Scanner to use instead of the first available"),
+        )
+        .arg(
+            Arg::new("severity")
+                .long("severity")
+                .value_name("LEVEL")
+                .value_parser(Severity::NAMES)
+                .default_value("unknown")
+                .help("Only count findings of at least this severity"),
+        )
+        .arg(
+            Arg::new("fail-on")
+                .long("fail-on")
+                .value_name("LEVEL")
+                .value_parser(Severity::NAMES)
+                .help("Exit with status 3 if any finding has at least this severity"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON, including the individual findings"),
+        )
+}
+
+/// Severity of a finding, lowest first. trivy's UNKNOWN and grype's
+/// Negligible are below Low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Severity {
+    Unknown,
+    Negligible,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    const NAMES: [&'static str; 6] = ["unknown", "negligible", "low", "medium", "high", "critical"];
+    const ALL: [Severity; 6] = [
+        Severity::Unknown,
+        Severity::Negligible,
+        Severity::Low,
+        Severity::Medium,
+        Severity::High,
+        Severity::Critical,
+    ];
+
+    /// Severity as trivy or grype spell it; anything else is Unknown.
+    fn parse(name: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|s| s.name().eq_ignore_ascii_case(name.trim()))
+            .unwrap_or(Severity::Unknown)
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+}
+
+/// One vulnerability in one package of an image.
+#[derive(Debug, Clone, PartialEq)]
+struct Finding {
+    id: String,
+    package: String,
+    installed_version: String,
+    fixed_version: Option<String>,
+    severity: Severity,
+}
+
+impl Finding {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "package": self.package,
+            "installed_version": self.installed_version,
+            "fixed_version": self.fixed_version,
+            "severity": self.severity.name(),
+        })
+    }
+}
+
+fn json_str(value: &serde_json::Value, key: &str) -> String {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string()
+}
+
+/// Findings of a trivy (`Results`) or grype (`matches`) JSON report, each
+/// vulnerability of a package version once.
+fn parse_report(report: &str) -> Result<Vec<Finding>> {
+    let value: serde_json::Value = serde_json::from_str(report).context("Scanner output is not JSON")?;
+    let mut findings = Vec::new();
+    if let Some(results) = value.get("Results") {
+        let vulnerabilities = results
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.get("Vulnerabilities")?.as_array())
+            .flatten();
+        for v in vulnerabilities {
+            findings.push(Finding {
+                id: json_str(v, "VulnerabilityID"),
+                package: json_str(v, "PkgName"),
+                installed_version: json_str(v, "InstalledVersion"),
+                fixed_version: Some(json_str(v, "FixedVersion")).filter(|f| !f.is_empty()),
+                severity: Severity::parse(&json_str(v, "Severity")),
+            });
+        }
+    } else if let Some(matches) = value.get("matches") {
+        for m in matches.as_array().into_iter().flatten() {
+            let (vulnerability, artifact) = (&m["vulnerability"], &m["artifact"]);
+            let fixed: Vec<&str> = vulnerability["fix"]["versions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str())
+                .collect();
+            findings.push(Finding {
+                id: json_str(vulnerability, "id"),
+                package: json_str(artifact, "name"),
+                installed_version: json_str(artifact, "version"),
+                fixed_version: Some(fixed.join(", ")).filter(|f| !f.is_empty()),
+                severity: Severity::parse(&json_str(vulnerability, "severity")),
+            });
+        }
+    } else if value.is_object() {
+        // trivy leaves out Results for images it finds nothing to scan in
+        log::debug!("Scanner report has no Results or matches");
+    } else {
+        bail!("Unrecognized scanner report: expected a trivy or grype JSON report");
+    }
+    let mut seen = HashSet::new();
+    findings.retain(|f| seen.insert((f.id.clone(), f.package.clone(), f.installed_version.clone())));
+    Ok(findings)
+}
+
+/// Where images are scanned.
+#[derive(Debug)]
+enum Scanner {
+    Trivy(String),
+    Grype(String),
+    Api { url: String, token_ref: Option<String> },
+}
+
+impl Scanner {
+    /// The scanner named by `--scanner`, or the first available.
+    fn choose(config: &BulkerConfig, name: Option<&str>) -> Result<Self> {
+        let api = || match config.bulker.scan_api {
+            Some(ref url) => Ok(Scanner::Api { url: url.clone(), token_ref: config.bulker.scan_api_token.clone() }),
+            None => bail!("No scan_api configured. Set one with `bulker config set scan_api=<url>`."),
+        };
+        let program = |program: &str| {
+            crate::config::resolve_engine_path(program).ok_or_else(|| anyhow::anyhow!("{} is not on PATH", program))
+        };
+        match name {
+            Some("trivy") => Ok(Scanner::Trivy(program("trivy")?)),
+            Some("grype") => Ok(Scanner::Grype(program("grype")?)),
+            Some(_) => api(),
+            None => {
+                if let Ok(path) = program("trivy") {
+                    return Ok(Scanner::Trivy(path));
+                }
+                if let Ok(path) = program("grype") {
+                    return Ok(Scanner::Grype(path));
+                }
+                api().map_err(|_| {
+                    anyhow::anyhow!(
+                        "No image scanner available. Install trivy or grype, or configure a scanning service \
+                         with `bulker config set scan_api=<url>`."
+                    )
+                })
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scanner::Trivy(_) => "trivy",
+            Scanner::Grype(_) => "grype",
+            Scanner::Api { .. } => "api",
+        }
+    }
+
+    fn scan(&self, image: &str) -> Result<Vec<Finding>> {
+        let report = match self {
+            Scanner::Trivy(path) => run_scanner(path, &["image", "--quiet", "--format", "json", image])?,
+            Scanner::Grype(path) => run_scanner(path, &[image, "--output", "json", "--quiet"])?,
+            Scanner::Api { url, token_ref } => {
+                let mut req = ureq::post(url).set("Content-Type", "application/json");
+                if let Some(reference) = token_ref {
+                    req = req.set("Authorization", &format!("Bearer {}", crate::config::resolve_credential(reference)?));
+                }
+                req.send_string(&serde_json::json!({ "image": image }).to_string())
+                    .with_context(|| format!("Scanning service {} failed", url))?
+                    .into_string()
+                    .with_context(|| format!("Failed to read response from {}", url))?
+            }
+        };
+        parse_report(&report)
+    }
+}
+
+fn run_scanner(program: &str, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Images of a crate and its imports with the commands that run them. The
+/// first crate providing a command wins, as in an activation.
+fn crate_images(config: &BulkerConfig, cv: &CrateVars) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut visited = HashSet::new();
+    manifest_cache::ensure_cached_with_imports(config, cv, false, false, &mut visited, 0)?;
+    let mut seen = HashSet::new();
+    let mut images: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for import_cv in crate::imports::resolve_cratevars_with_imports(config, std::slice::from_ref(cv))? {
+        if let Some(manifest) = manifest_cache::load_cached(&import_cv)? {
+            for command in manifest.manifest.commands {
+                if seen.insert(command.command.clone()) {
+                    images.entry(command.docker_image).or_default().push(command.command);
+                }
+            }
+        }
+    }
+    for commands in images.values_mut() {
+        commands.sort();
+    }
+    Ok(images)
+}
+
+/// Scan result of one image.
+struct ImageScan {
+    image: String,
+    commands: Vec<String>,
+    findings: Result<Vec<Finding>>,
+}
+
+impl ImageScan {
+    /// Findings of at least `threshold` per severity.
+    fn counts(&self, threshold: Severity) -> BTreeMap<Severity, usize> {
+        let mut counts: BTreeMap<Severity, usize> = Severity::ALL.into_iter().map(|s| (s, 0)).collect();
+        for finding in self.findings.as_deref().unwrap_or_default() {
+            if finding.severity >= threshold {
+                *counts.entry(finding.severity).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    fn worst(&self) -> Option<Severity> {
+        self.findings.as_deref().unwrap_or_default().iter().map(|f| f.severity).max()
+    }
+}
+
+fn counts_json(counts: &BTreeMap<Severity, usize>) -> serde_json::Value {
+    counts.iter().map(|(s, n)| (s.name().to_string(), serde_json::json!(n))).collect::<serde_json::Map<_, _>>().into()
+}
+
+fn render_json(crate_name: &str, scanner: &str, threshold: Severity, scans: &[ImageScan]) -> serde_json::Value {
+    let mut commands: Vec<serde_json::Value> = Vec::new();
+    let mut images = Vec::new();
+    for scan in scans {
+        let counts = counts_json(&scan.counts(threshold));
+        for command in &scan.commands {
+            commands.push(serde_json::json!({ "command": command, "image": scan.image, "counts": counts }));
+        }
+        let mut value = serde_json::json!({ "image": scan.image, "commands": scan.commands });
+        match scan.findings {
+            Ok(ref findings) => {
+                value["findings"] = findings.iter().filter(|f| f.severity >= threshold).map(Finding::to_json).collect();
+            }
+            Err(ref e) => value["error"] = serde_json::json!(format!("{:#}", e)),
+        }
+        images.push(value);
+    }
+    commands.sort_by(|a, b| a["command"].as_str().cmp(&b["command"].as_str()));
+    serde_json::json!({
+        "crate": crate_name,
+        "scanner": scanner,
+        "severity": threshold.name(),
+        "commands": commands,
+        "images": images,
+    })
+}
+
+fn render_table(scans: &[ImageScan], threshold: Severity) -> String {
+    let mut rows: Vec<(&str, &ImageScan)> =
+        scans.iter().flat_map(|scan| scan.commands.iter().map(move |c| (c.as_str(), scan))).collect();
+    rows.sort_by_key(|(command, _)| *command);
+    let width = rows.iter().map(|(c, _)| c.len()).max().unwrap_or(0).max("COMMAND".len());
+    let mut out = format!("{:<width$}  CRITICAL  HIGH  MEDIUM  LOW  OTHER  IMAGE\n", "COMMAND", width = width);
+    for (command, scan) in rows {
+        if let Err(ref e) = scan.findings {
+            out.push_str(&format!("{:<width$}  scan failed: {:#}  {}\n", command, e, scan.image, width = width));
+            continue;
+        }
+        let counts = scan.counts(threshold);
+        let other = counts[&Severity::Unknown] + counts[&Severity::Negligible];
+        out.push_str(&format!(
+            "{:<width$}  {:>8}  {:>4}  {:>6}  {:>3}  {:>5}  {}\n",
+            command,
+            counts[&Severity::Critical],
+            counts[&Severity::High],
+            counts[&Severity::Medium],
+            counts[&Severity::Low],
+            other,
+            scan.image,
+            width = width
+        ));
+    }
+    out
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let path = matches.get_one::<String>("crate_registry_path").unwrap();
+    let cv = parse_registry_path(path, &config.bulker.default_namespace)?;
+    let scanner = Scanner::choose(&config, matches.get_one::<String>("scanner").map(|s| s.as_str()))?;
+    let threshold = Severity::parse(matches.get_one::<String>("severity").unwrap());
+    let fail_on = matches.get_one::<String>("fail-on").map(|s| Severity::parse(s));
+
+    let mut scans = Vec::new();
+    for (image, commands) in crate_images(&config, &cv)? {
+        log::info!("Scanning {} with {}", image, scanner.name());
+        let findings = scanner.scan(&image);
+        scans.push(ImageScan { image, commands, findings });
+    }
+
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&render_json(&cv.display_name(), scanner.name(), threshold, &scans))?);
+    } else {
+        print!("{}", render_table(&scans, threshold));
+    }
+
+    let failed: Vec<&str> = scans.iter().filter(|s| s.findings.is_err()).map(|s| s.image.as_str()).collect();
+    if !failed.is_empty() {
+        bail!("Could not scan {} image(s): {}", failed.len(), failed.join(", "));
+    }
+    if let Some(fail_on) = fail_on {
+        let hits: Vec<&str> =
+            scans.iter().filter(|s| s.worst().is_some_and(|w| w >= fail_on)).map(|s| s.image.as_str()).collect();
+        if !hits.is_empty() {
+            use std::io::Write;
+            std::io::stdout().flush()?;
+            log::error!("Findings of severity {} or higher in: {}", fail_on.name(), hits.join(", "));
+            return Err(crate::process::ExitWith(EXIT_FINDINGS).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIVY: &str = r#"{"Results": [
+        {"Target": "img (debian 12)", "Vulnerabilities": [
+            {"VulnerabilityID": "CVE-1", "PkgName": "openssl", "InstalledVersion": "3.0.1", "FixedVersion": "3.0.2", "Severity": "CRITICAL"},
+            {"VulnerabilityID": "CVE-2", "PkgName": "zlib", "InstalledVersion": "1.2", "Severity": "LOW"}
+        ]},
+        {"Target": "python-pkg", "Vulnerabilities": null},
+        {"Target": "dup", "Vulnerabilities": [
+            {"VulnerabilityID": "CVE-1", "PkgName": "openssl", "InstalledVersion": "3.0.1", "Severity": "CRITICAL"}
+        ]}
+    ]}"#;
+
+    const GRYPE: &str = r#"{"matches": [
+        {"vulnerability": {"id": "GHSA-x", "severity": "Negligible", "fix": {"versions": [], "state": "not-fixed"}},
+         "artifact": {"name": "bash", "version": "5.2"}},
+        {"vulnerability": {"id": "CVE-3", "severity": "High", "fix": {"versions": ["2.0", "1.9.9"]}},
+         "artifact": {"name": "curl", "version": "1.9"}}
+    ]}"#;
+
+    #[test]
+    fn test_parse_trivy_report() {
+        let findings = parse_report(TRIVY).unwrap();
+        assert_eq!(findings.len(), 2);
+        assert_eq!(
+            findings[0],
+            Finding {
+                id: "CVE-1".to_string(),
+                package: "openssl".to_string(),
+                installed_version: "3.0.1".to_string(),
+                fixed_version: Some("3.0.2".to_string()),
+                severity: Severity::Critical,
+            }
+        );
+        assert_eq!(findings[1].fixed_version, None);
+        assert!(parse_report("{}").unwrap().is_empty());
+        assert!(parse_report("[]").is_err());
+    }
+
+    #[test]
+    fn test_parse_grype_report() {
+        let findings = parse_report(GRYPE).unwrap();
+        assert_eq!(findings[0].severity, Severity::Negligible);
+        assert_eq!(findings[0].fixed_version, None);
+        assert_eq!(findings[1].package, "curl");
+        assert_eq!(findings[1].fixed_version.as_deref(), Some("2.0, 1.9.9"));
+        assert_eq!(findings[1].severity, Severity::High);
+    }
+
+    #[test]
+    fn test_severity_order_and_threshold() {
+        assert!(Severity::parse("CRITICAL") > Severity::parse("high"));
+        assert!(Severity::parse("Negligible") < Severity::Low);
+        assert_eq!(Severity::parse("bogus"), Severity::Unknown);
+        let scan = ImageScan {
+            image: "img".to_string(),
+            commands: vec!["a".to_string()],
+            findings: parse_report(TRIVY),
+        };
+        let counts = scan.counts(Severity::Medium);
+        assert_eq!((counts[&Severity::Critical], counts[&Severity::Low]), (1, 0));
+        assert_eq!(scan.worst(), Some(Severity::Critical));
+    }
+
+    #[test]
+    fn test_render_per_command() {
+        let scans = vec![
+            ImageScan {
+                image: "img:1".to_string(),
+                commands: vec!["samtools".to_string(), "bgzip".to_string()],
+                findings: parse_report(GRYPE),
+            },
+            ImageScan { image: "gone:1".to_string(), commands: vec!["fastqc".to_string()], findings: Err(anyhow::anyhow!("pull denied")) },
+        ];
+        let table = render_table(&scans, Severity::Unknown);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[1].starts_with("bgzip"), "{}", table);
+        assert!(lines[1].contains("        0     1       0    0      1  img:1"), "{}", table);
+        assert!(lines[2].contains("scan failed: pull denied  gone:1"), "{}", table);
+
+        let json = render_json("bulker/demo:default", "grype", Severity::High, &scans);
+        assert_eq!(json["commands"][0]["command"], "bgzip");
+        assert_eq!(json["commands"][0]["counts"]["high"], 1);
+        assert_eq!(json["commands"][0]["counts"]["negligible"], 0);
+        assert_eq!(json["images"][0]["findings"].as_array().unwrap().len(), 1);
+        assert_eq!(json["images"][1]["error"], "pull denied");
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::load_cli_config;
+use crate::registry::{self, IndexEntry};
+
+use super::list::sort_versions_desc;
+
+pub fn create_cli() -> Command {
+    Command::new("search")
+        .about("Search the registry for crates by name or namespace")
+        .after_help("\
+EXAMPLES:
+  bulker crate search pepatac
+  bulker crate search databio/               # everything in a namespace
+  bulker crate search --simple bowtie        # registry paths only, for scripting
+
+Searches the index the configured registry (`registry_url`, or the first of
+`registry_urls`) publishes at index.yaml. A crate matches when its namespace/crate contains the term,
+ignoring case. Install a match with `bulker crate install namespace/crate:tag`.")
+        .arg(
+            Arg::new("term")
+                .required(true)
+                .help("Text to look for in namespace/crate"),
+        )
+        .arg(
+            Arg::new("simple")
+                .long("simple")
+                .short('s')
+                .action(ArgAction::SetTrue)
+                .help("Print only the registry paths (namespace/crate), one per line"),
+        )
+}
+
+/// One search hit: its registry path and description, then its tags,
+/// newest first.
+fn format_entry(entry: &IndexEntry) -> String {
+    let mut out = entry.path();
+    if !entry.description.is_empty() {
+        out.push_str(&format!("  {}", entry.description));
+    }
+    if !entry.tags.is_empty() {
+        let mut tags = entry.tags.clone();
+        sort_versions_desc(&mut tags);
+        out.push_str(&format!("\n  tags: {}", tags.join(", ")));
+    }
+    out
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let term = matches.get_one::<String>("term").unwrap();
+    let index = registry::fetch_index(&config)?;
+    let found = index.search(term);
+
+    if found.is_empty() {
+        println!("No crates matching '{}' in {}.", term, config.registry_urls()[0]);
+        return Ok(());
+    }
+    for entry in found {
+        if matches.get_flag("simple") {
+            println!("{}", entry.path());
+        } else {
+            println!("{}", format_entry(entry));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_entry() {
+        let entry = IndexEntry {
+            namespace: "databio".to_string(),
+            crate_name: "pepatac".to_string(),
+            description: "ATAC-seq tools".to_string(),
+            tags: vec!["1.0.9".to_string(), "default".to_string(), "1.0.13".to_string()],
+        };
+        assert_eq!(format_entry(&entry), "databio/pepatac  ATAC-seq tools\n  tags: 1.0.13, 1.0.9, default");
+        let bare = IndexEntry { description: String::new(), tags: vec![], ..entry };
+        assert_eq!(format_entry(&bare), "databio/pepatac");
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+use crate::config::{BulkerConfig, load_cli_config};
+use crate::digest;
+use crate::manifest::{CrateVars, load_remote_manifest, parse_registry_path};
+use crate::manifest_cache::{self, MAX_IMPORT_DEPTH};
+
+pub fn create_cli() -> Command {
+    Command::new("sync")
+        .about("Make the crate cache match a declared set of crates")
+        .after_help("\
+EXAMPLES:
+  bulker crate sync crates.yaml               # install missing, re-fetch pinned crates that differ
+  bulker crate sync crates.yaml --dry-run     # show what would change
+  bulker crate sync crates.yaml --prune       # also remove crates not declared
+
+CRATE SET FORMAT:
+  update: missing        # missing (default): fetch only absent crates; always: re-fetch all
+  prune: false           # remove cached crates that are neither declared nor imported
+  crates:
+  - bulker/demo
+  - crate: databio/pepatac:1.0.13
+    digest: 4c1Mr...     # crate-manifest-digest the cache must hold (see `bulker crate digest`)
+    update: always       # per-crate policy
+
+A crate whose cached digest differs from its pin is re-fetched; if the registry
+serves a different digest too, sync fails for that crate and leaves it as is.")
+        .arg(
+            Arg::new("crate_set")
+                .required(true)
+                .value_name("FILE")
+                .help("YAML file declaring the crates to keep cached"),
+        )
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .action(ArgAction::SetTrue)
+                .help("Remove cached crates that are neither declared nor imported by a declared crate"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Print the changes without making them"),
+        )
+}
+
+/// When a declared crate is fetched from the registry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum UpdatePolicy {
+    /// Only when it is not cached (or does not match its pin).
+    #[default]
+    Missing,
+    /// On every sync.
+    Always,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CrateSet {
+    #[serde(default)]
+    update: UpdatePolicy,
+    #[serde(default)]
+    prune: bool,
+    crates: Vec<CrateEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CrateEntry {
+    Path(String),
+    Pinned(PinnedCrate),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PinnedCrate {
+    #[serde(rename = "crate")]
+    path: String,
+    digest: Option<String>,
+    update: Option<UpdatePolicy>,
+}
+
+/// A declared crate with its effective policy.
+#[derive(Debug)]
+struct Declared {
+    cv: CrateVars,
+    digest: Option<String>,
+    update: UpdatePolicy,
+}
+
+fn parse_crate_set(contents: &str, config: &BulkerConfig) -> Result<(Vec<Declared>, bool)> {
+    let set: CrateSet = serde_yml::from_str(contents)?;
+    let mut declared = Vec::new();
+    for entry in set.crates {
+        let (path, digest, update) = match entry {
+            CrateEntry::Path(path) => (path, None, set.update),
+            CrateEntry::Pinned(p) => (p.path, p.digest, p.update.unwrap_or(set.update)),
+        };
+        let cv = parse_registry_path(&path, &config.bulker.default_namespace)?;
+        declared.push(Declared { cv, digest, update });
+    }
+    Ok((declared, set.prune))
+}
+
+/// What sync does to one declared crate.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Install,
+    /// Cached, but its digest differs from the pin.
+    Repin,
+    /// Cached; re-fetched because the policy is `always`.
+    Refresh,
+    Keep,
+}
+
+fn plan(declared: &Declared, cached_digest: Option<&str>) -> Action {
+    match (cached_digest, declared.digest.as_deref()) {
+        (None, _) => Action::Install,
+        (Some(have), Some(want)) if have != want => Action::Repin,
+        _ if declared.update == UpdatePolicy::Always => Action::Refresh,
+        _ => Action::Keep,
+    }
+}
+
+/// Fetch a declared crate from the registry and cache it (and any missing
+/// imports) unless it violates its pin. Returns the new manifest digest.
+fn fetch(config: &BulkerConfig, declared: &Declared) -> Result<String> {
+    let name = declared.cv.display_name();
+    let (manifest, _) = load_remote_manifest(config, &name, None)?;
+    let new = digest::crate_manifest_digest(&manifest).digest;
+    if let Some(want) = declared.digest.as_ref().filter(|want| **want != new) {
+        bail!("'{}': registry serves digest {}, but the crate set pins {}", name, new, want);
+    }
+    manifest_cache::save_to_cache(&declared.cv, &manifest)?;
+    let mut visited = HashSet::new();
+    manifest_cache::ensure_cached_with_imports(config, &declared.cv, false, false, &mut visited, 0)?;
+    Ok(new)
+}
+
+/// Display names of the declared crates and everything they import.
+fn keep_set(config: &BulkerConfig, declared: &[Declared]) -> Result<HashSet<String>> {
+    let mut keep = HashSet::new();
+    let mut stack: Vec<(CrateVars, usize)> = declared.iter().map(|d| (d.cv.clone(), 0)).collect();
+    while let Some((cv, depth)) = stack.pop() {
+        if depth >= MAX_IMPORT_DEPTH || !keep.insert(cv.display_name()) {
+            continue;
+        }
+        if let Some(manifest) = manifest_cache::load_cached(&cv)? {
+            for import in &manifest.manifest.imports {
+                stack.push((crate::imports::resolve_import(config, import)?, depth + 1));
+            }
+        }
+    }
+    Ok(keep)
+}
+
+fn short(digest: &str) -> &str {
+    &digest[..digest.len().min(8)]
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let path = matches.get_one::<String>("crate_set").unwrap();
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read crate set: {}", path))?;
+    let (declared, prune_in_file) =
+        parse_crate_set(&contents, &config).with_context(|| format!("Invalid crate set: {}", path))?;
+    let prune = prune_in_file || matches.get_flag("prune");
+    let dry_run = matches.get_flag("dry-run");
+
+    let mut failures = 0;
+    for d in &declared {
+        let name = d.cv.display_name();
+        let cached = manifest_cache::ensure_crate_manifest_digest(&d.cv)?;
+        let action = plan(d, cached.as_deref());
+        if action == Action::Keep {
+            println!("Unchanged: {}", name);
+            continue;
+        }
+        if dry_run {
+            let verb = match action {
+                Action::Install => "Would install",
+                Action::Repin => "Would re-fetch (digest differs from pin)",
+                _ => "Would re-fetch",
+            };
+            println!("{}: {}", verb, name);
+            continue;
+        }
+        match fetch(&config, d) {
+            Ok(new) => match cached {
+                None => println!("Installed: {}", name),
+                Some(ref old) if old != &new => println!("Updated: {} ({}… → {}…)", name, short(old), short(&new)),
+                Some(_) => println!("Unchanged: {}", name),
+            },
+            Err(e) => {
+                log::error!("{:#}", e);
+                failures += 1;
+            }
+        }
+    }
+
+    if prune {
+        let keep = keep_set(&config, &declared)?;
+        for (cv, _) in manifest_cache::list_cached()? {
+            if keep.contains(&cv.display_name()) {
+                continue;
+            }
+            if dry_run {
+                println!("Would remove: {}", cv.display_name());
+            } else {
+                manifest_cache::remove_cached(&cv)?;
+                println!("Removed: {}", cv.display_name());
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} crate(s) could not be synced", failures);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_crate_set_applies_default_policy() {
+        let config = BulkerConfig::test_default();
+        let yaml = "update: always\ncrates:\n- demo\n- crate: databio/pepatac:1.0.13\n  digest: abc\n  update: missing\n";
+        let (declared, prune) = parse_crate_set(yaml, &config).unwrap();
+        assert!(!prune);
+        assert_eq!(declared[0].cv.display_name(), "bulker/demo:default");
+        assert_eq!(declared[0].update, UpdatePolicy::Always);
+        assert_eq!(declared[1].digest.as_deref(), Some("abc"));
+        assert_eq!(declared[1].update, UpdatePolicy::Missing);
+
+        assert!(parse_crate_set("crates:\n- crate: demo\n  tag: 1\n", &config).is_err());
+        assert!(parse_crate_set("update: sometimes\ncrates: []\n", &config).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let config = BulkerConfig::test_default();
+        let yaml = "crates:\n- demo\n- crate: pinned\n  digest: abc\n- crate: fresh\n  update: always\n";
+        let (declared, _) = parse_crate_set(yaml, &config).unwrap();
+        assert_eq!(plan(&declared[0], None), Action::Install);
+        assert_eq!(plan(&declared[0], Some("xyz")), Action::Keep);
+        assert_eq!(plan(&declared[1], Some("abc")), Action::Keep);
+        assert_eq!(plan(&declared[1], Some("xyz")), Action::Repin);
+        assert_eq!(plan(&declared[2], Some("xyz")), Action::Refresh);
+    }
+}
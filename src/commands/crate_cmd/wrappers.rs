@@ -0,0 +1,130 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::load_cli_config;
+use crate::manifest::parse_registry_path;
+
+pub fn create_cli() -> Command {
+    Command::new("wrappers")
+        .about("Write standalone wrapper scripts that run a crate's commands by absolute path")
+        .after_help("\
+EXAMPLES:
+  bulker crate wrappers databio/pepatac:1.0.13 --out ~/pepatac-bin
+  ~/pepatac-bin/samtools --version            # no activation or PATH needed
+
+Each wrapper is a small shell script (not a symlink) that runs
+`<abs path to bulker> exec [-c <config>] <crate> -- <command> \"$@\"`, so it works
+where PATH and the environment are minimal: crontabs, systemd units, and
+schedulers that want absolute executable paths. Container commands of imported
+crates get wrappers too. Rerun after moving the bulker binary or the config.")
+        .arg(
+            Arg::new("crate_registry_path")
+                .required(true)
+                .help("Crate to wrap (fetched if not cached)"),
+        )
+        .arg(
+            Arg::new("out")
+                .long("out")
+                .short('o')
+                .value_name("DIR")
+                .required(true)
+                .help("Directory to write the wrappers to (created if missing)"),
+        )
+        .arg(
+            Arg::new("force")
+                .short('f')
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Overwrite existing files that are not bulker wrappers"),
+        )
+}
+
+/// First lines of every wrapper; also how an existing wrapper is recognized.
+const WRAPPER_MARKER: &str = "# Generated by `bulker crate wrappers`";
+
+fn quote(s: &str) -> String {
+    shell_escape::escape(std::borrow::Cow::Borrowed(s)).to_string()
+}
+
+/// Wrapper script running `command` of `crate_path` through `bulker exec`.
+/// Both only appear shell-quoted, never in the comment header.
+fn wrapper_script(bulker: &Path, config: Option<&Path>, crate_path: &str, command: &str) -> String {
+    let mut exec = format!("exec {} exec", quote(&bulker.to_string_lossy()));
+    if let Some(config) = config {
+        exec.push_str(&format!(" -c {}", quote(&config.to_string_lossy())));
+    }
+    exec.push_str(&format!(" {} -- {} \"$@\"", quote(crate_path), quote(command)));
+    format!("#!/bin/sh\n{}.\n{}\n", WRAPPER_MARKER, exec)
+}
+
+fn is_wrapper(path: &Path) -> bool {
+    std::fs::read_to_string(path).is_ok_and(|text| text.lines().nth(1).is_some_and(|l| l.starts_with(WRAPPER_MARKER)))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let path = matches.get_one::<String>("crate_registry_path").unwrap();
+    let out = crate::config::mkabs(matches.get_one::<String>("out").unwrap(), None);
+    let force = matches.get_flag("force");
+    let cv = parse_registry_path(path, &config.bulker.default_namespace)?;
+    let commands = super::export_spack::crate_commands(&config, &cv)?;
+    if commands.is_empty() {
+        bail!("'{}' has no container commands to wrap", cv.display_name());
+    }
+    for command in &commands {
+        crate::manifest::check_command_name(command).with_context(|| format!("Cannot write a wrapper for {}", cv.display_name()))?;
+    }
+
+    let bulker = std::env::current_exe()
+        .and_then(std::fs::canonicalize)
+        .context("Failed to locate the bulker binary")?;
+    let config_path: Option<PathBuf> = config_path.map(|p| std::fs::canonicalize(&p).unwrap_or(p));
+
+    std::fs::create_dir_all(&out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let clashes: Vec<&str> = commands
+        .iter()
+        .filter(|c| {
+            let target = out.join(c);
+            target.symlink_metadata().is_ok() && !is_wrapper(&target)
+        })
+        .map(|c| c.as_str())
+        .collect();
+    if !clashes.is_empty() && !force {
+        bail!("{} already has files that are not bulker wrappers: {} (use --force to overwrite)", out.display(), clashes.join(", "));
+    }
+
+    for command in &commands {
+        let target = out.join(command);
+        // Replace rather than write through a symlink
+        let _ = std::fs::remove_file(&target);
+        std::fs::write(&target, wrapper_script(&bulker, config_path.as_deref(), &cv.display_name(), command))
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o755))?;
+    }
+    println!("Wrote {} wrapper(s) for {} to {}", commands.len(), cv.display_name(), out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapper_script() {
+        let script = wrapper_script(
+            Path::new("/opt/bulker/bin/bulker"),
+            Some(Path::new("/home/me/my config.yaml")),
+            "databio/pepatac:1.0.13",
+            "samtools",
+        );
+        assert_eq!(
+            script,
+            "#!/bin/sh\n# Generated by `bulker crate wrappers`.\n\
+             exec /opt/bulker/bin/bulker exec -c '/home/me/my config.yaml' 'databio/pepatac:1.0.13' -- samtools \"$@\"\n"
+        );
+        let script = wrapper_script(Path::new("/usr/bin/bulker"), None, "bulker/demo:default", "cowsay");
+        assert!(script.ends_with("exec /usr/bin/bulker exec 'bulker/demo:default' -- cowsay \"$@\"\n"), "{}", script);
+    }
+}
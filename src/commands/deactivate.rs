@@ -0,0 +1,37 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+
+use crate::activation_stack;
+
+/// Internal: the pop behind the shell function's `bulker deactivate` when
+/// activations are stacked. Prints shell code returning to the activation
+/// below, after removing the innermost shimdir.
+pub fn create_cli() -> Command {
+    Command::new("deactivate")
+        .about("Return to the activation below a stacked one (used by the shell function)")
+        .hide(true)
+        .arg(
+            Arg::new("echo")
+                .long("echo")
+                .action(ArgAction::SetTrue)
+                .help("Print shell code to eval"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    if !matches.get_flag("echo") {
+        bail!(
+            "`bulker deactivate` is a shell function. Load it with `eval \"$(bulker init-shell bash)\"` (or zsh), \
+             or `exit` the shell that `bulker activate` started."
+        );
+    }
+    let mut stack = activation_stack::read()?;
+    let frame = activation_stack::pop(&mut stack)?;
+    let shared = std::env::var_os("BULKER_SHIMDIR_SHARED").is_some();
+    if let Some(shimdir) = std::env::var_os("BULKER_SHIMDIR").filter(|_| !shared) {
+        let _ = std::fs::remove_dir_all(Path::new(&shimdir));
+    }
+    print!("{}", activation_stack::render_pop(&frame, &stack)?);
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io::IsTerminal;
+
+use crate::activate::{get_new_path, ActivateOptions};
+use crate::config::load_cli_config;
+use crate::manifest::{parse_manifest, parse_registry_path, CommandsFilter};
+use crate::manifest_cache;
+
+/// Embedded demo crate, so the demo works without reaching the registry.
+const DEMO_MANIFEST: &str = "\
+manifest:
+  name: bulker/demo
+  version: embedded
+  commands:
+  - command: cowsay
+    docker_image: nsheff/cowsay
+  - command: fortune
+    docker_image: nsheff/fortune
+";
+
+const DEMO_CRATE: &str = "bulker/demo:embedded";
+
+/// The command the walkthrough runs through the shimlink.
+const DEMO_COMMAND: &str = "cowsay 'Hello from a container!'";
+
+pub fn create_cli() -> Command {
+    Command::new("demo")
+        .about("Install a tiny demo crate and try it out")
+        .after_help("\
+EXAMPLES:
+  bulker demo                 # walkthrough, then a shell with the demo crate active
+  bulker demo --no-shell      # walkthrough only
+
+Installs an embedded crate (cowsay and fortune) without contacting the
+registry, runs cowsay through its shimlink, then opens a subshell where the
+crate is active. Type `exit` to leave it. The first run pulls the images. The
+crate stays installed as bulker/demo:embedded.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("no-shell")
+                .long("no-shell")
+                .action(ArgAction::SetTrue)
+                .help("Don't open a shell with the demo crate active afterwards"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    // The walkthrough shimdir is removed afterwards, so never use the shared one
+    config.bulker.shared_shimdir = None;
+
+    let engine_path = config.engine_path();
+    if !std::path::Path::new(engine_path).is_file() && crate::config::resolve_engine_path(engine_path).is_none() {
+        bail!(
+            "'{}' not found. Install {} or run `bulker config init`, then try again.",
+            engine_path,
+            config.bulker.container_engine
+        );
+    }
+
+    println!("Step 1: install a crate");
+    let cv = parse_registry_path(DEMO_CRATE, &config.bulker.default_namespace)?;
+    let manifest = parse_manifest(DEMO_MANIFEST)?;
+    manifest_cache::save_to_cache(&cv, &manifest)?;
+    println!("  Installed {} with commands: cowsay, fortune", cv.display_name());
+    println!("  (normally: bulker crate install bulker/demo)");
+    println!();
+
+    println!("Step 2: run a containerized command");
+    println!("  Activation puts a shimlink for each command on PATH; running one starts");
+    println!("  the container. The first run pulls the image.");
+    println!("  $ {}", DEMO_COMMAND);
+    run_through_shimlink(&config, config_path.as_deref(), &cv)?;
+    println!();
+
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    if matches.get_flag("no-shell") || !interactive || std::env::var("BULKERCRATE").is_ok() {
+        println!("Next: bulker activate {}", cv.display_name());
+        return Ok(());
+    }
+
+    println!("Step 3: use the crate in a shell");
+    println!("  Try `fortune | cowsay`, or `which cowsay` to see the shimlink.");
+    println!("  Type `exit` to leave the demo shell.");
+    println!();
+    crate::activate::activate(&config, config_path.as_deref(), &[cv], &ActivateOptions::default())
+}
+
+/// Activate the demo crate in a subshell and run `DEMO_COMMAND` through it.
+fn run_through_shimlink(
+    config: &crate::config::BulkerConfig,
+    config_path: Option<&std::path::Path>,
+    cv: &crate::manifest::CrateVars,
+) -> Result<()> {
+    let result = get_new_path(config, std::slice::from_ref(cv), &CommandsFilter::default(), false, false)
+        .context("Failed to activate the demo crate")?;
+    let mut cmd = std::process::Command::new("/bin/sh");
+    cmd.arg("-c")
+        .arg(DEMO_COMMAND)
+        .env("PATH", &result.path)
+        .env("BULKERCRATE", cv.display_name())
+        .stdin(std::process::Stdio::null());
+    if let Some(p) = config_path {
+        cmd.env("BULKERCFG", p);
+    }
+    let status = cmd.status().context("Failed to start subshell");
+    let _ = std::fs::remove_dir_all(&result.shimdir);
+    let status = status?;
+    if !status.success() {
+        bail!(
+            "cowsay exited with {}; run `bulker doctor` to check your setup",
+            status.code().map(|c| c.to_string()).unwrap_or_else(|| "signal".to_string())
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demo_manifest_is_valid() {
+        let manifest = parse_manifest(DEMO_MANIFEST).unwrap();
+        let commands: Vec<&str> = manifest.manifest.commands.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, vec!["cowsay", "fortune"]);
+        assert!(manifest.deprecations().is_empty());
+        assert!(DEMO_COMMAND.starts_with(commands[0]));
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+
+use crate::config::load_cli_config;
+
+pub fn create_cli() -> Command {
+    Command::new("doctor")
+        .about("Diagnose common setup and activation problems")
+        .after_help("\
+EXAMPLES:
+  bulker doctor               # config, engine, cache and shimdir location
+  bulker doctor --active      # inside an activated shell: is the shimdir first on PATH?
+
+Unlike `bulker selftest`, doctor runs no containers. It exits 1 if any check
+fails.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("active")
+                .long("active")
+                .action(ArgAction::SetTrue)
+                .help("Check the currently activated environment (shimdir, PATH ordering)"),
+        )
+}
+
+fn pass(check: &str, detail: &str) {
+    println!("  ✓ {:<10} {}", check, detail);
+}
+
+fn fail(check: &str, detail: &str) {
+    println!("  ✗ {:<10} {}", check, detail);
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let ok = if matches.get_flag("active") {
+        check_active()
+    } else {
+        check_setup(matches.get_one::<String>("config").map(|s| s.as_str()))
+    };
+    if !ok {
+        return Err(crate::process::ExitWith(1).into());
+    }
+    Ok(())
+}
+
+/// Config, engine, cache and shimdir base checks.
+fn check_setup(config_arg: Option<&str>) -> bool {
+    println!("bulker doctor");
+    let (config, config_path) = match load_cli_config(config_arg) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            fail("config", &format!("{:#}", e));
+            return false;
+        }
+    };
+    pass(
+        "config",
+        &config_path
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "(defaults, no config file)".to_string()),
+    );
+
+    let mut ok = true;
+    let engine_path = config.engine_path();
+    if Path::new(engine_path).is_file() || crate::config::resolve_engine_path(engine_path).is_some() {
+        pass("engine", &format!("{} ({})", config.bulker.container_engine, engine_path));
+    } else {
+        fail("engine", &format!("'{}' not found; install {} or run `bulker config init`", engine_path, config.bulker.container_engine));
+        ok = false;
+    }
+
+    let cache = crate::manifest_cache::cache_root_dir();
+    match std::fs::create_dir_all(&cache).and_then(|_| tempfile::tempfile_in(&cache)) {
+        Ok(_) => pass("cache", &cache.display().to_string()),
+        Err(e) => {
+            fail("cache", &format!("{} is not writable: {}", cache.display(), e));
+            ok = false;
+        }
+    }
+
+    match crate::activate::shimdir_base(&config) {
+        Ok(base) => pass("shimdirs", &base.display().to_string()),
+        Err(e) => {
+            fail("shimdirs", &format!("{:#}", e));
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Where the shimdir sits on PATH relative to the other entries.
+#[derive(Debug, PartialEq)]
+pub(crate) enum PathOrder {
+    /// The shimdir is the first PATH entry.
+    First,
+    /// These entries come before the shimdir.
+    Buried(Vec<String>),
+    /// The shimdir is not on PATH at all.
+    Missing,
+}
+
+/// Check where `shimdir` appears in `path`.
+pub(crate) fn path_order(path: &str, shimdir: &str) -> PathOrder {
+    let shimdir = shimdir.trim_end_matches('/');
+    let entries: Vec<&str> = path.split(':').filter(|e| !e.is_empty()).collect();
+    match entries.iter().position(|e| e.trim_end_matches('/') == shimdir) {
+        Some(0) => PathOrder::First,
+        Some(i) => PathOrder::Buried(entries[..i].iter().map(|e| e.to_string()).collect()),
+        None => PathOrder::Missing,
+    }
+}
+
+/// Commands in `shimdir` that an executable in `dir` shadows.
+fn shadowed_commands(dir: &str, shimdir: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(shimdir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.') && Path::new(dir).join(name).is_file())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Checks for the environment of an activated shell.
+fn check_active() -> bool {
+    println!("bulker doctor --active");
+    let Ok(crate_id) = std::env::var("BULKERCRATE") else {
+        fail("crate", "no crate is active ($BULKERCRATE is not set)");
+        return false;
+    };
+    pass("crate", &crate_id);
+
+    let Some(shimdir) = std::env::var("BULKER_SHIMDIR").ok().filter(|d| !d.is_empty()) else {
+        fail("shimdir", "$BULKER_SHIMDIR is not set; re-activate with this bulker version");
+        return false;
+    };
+    if !Path::new(&shimdir).is_dir() {
+        fail("shimdir", &format!("{} no longer exists (cleaned up?); re-activate", shimdir));
+        return false;
+    }
+    pass("shimdir", &shimdir);
+
+    let path = std::env::var("PATH").unwrap_or_default();
+    match path_order(&path, &shimdir) {
+        PathOrder::First => {
+            pass("PATH", "shimdir is first");
+            true
+        }
+        PathOrder::Missing => {
+            fail("PATH", "shimdir is not on PATH; something in your shell startup reset PATH");
+            false
+        }
+        PathOrder::Buried(before) => {
+            fail("PATH", "shimdir is not first; these entries come before it:");
+            for entry in &before {
+                let shadowed = shadowed_commands(entry, &shimdir);
+                if shadowed.is_empty() {
+                    println!("                 {}", entry);
+                } else {
+                    println!("                 {}  (shadows: {})", entry, shadowed.join(", "));
+                }
+            }
+            println!(
+                "               Something in your shell startup (e.g. a PROMPT_COMMAND hook or a\n               \
+                 version manager) re-prepends them after activation. Disable it in\n               \
+                 activated shells, or run `export PATH=\"$BULKER_SHIMDIR:$PATH\"`."
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_order() {
+        assert_eq!(path_order("/tmp/bulker_x:/usr/bin", "/tmp/bulker_x"), PathOrder::First);
+        assert_eq!(path_order("/tmp/bulker_x/:/usr/bin", "/tmp/bulker_x"), PathOrder::First);
+        assert_eq!(
+            path_order("/opt/conda/bin:/home/u/.pyenv/shims:/tmp/bulker_x:/usr/bin", "/tmp/bulker_x"),
+            PathOrder::Buried(vec!["/opt/conda/bin".to_string(), "/home/u/.pyenv/shims".to_string()])
+        );
+        assert_eq!(path_order("/usr/bin", "/tmp/bulker_x"), PathOrder::Missing);
+    }
+
+    #[test]
+    fn test_shadowed_commands() {
+        let shimdir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        for name in ["python", "samtools"] {
+            std::os::unix::fs::symlink("/bin/true", shimdir.path().join(name)).unwrap();
+        }
+        std::fs::write(other.path().join("python"), "").unwrap();
+        let shadowed = shadowed_commands(&other.path().to_string_lossy(), &shimdir.path().to_string_lossy());
+        assert_eq!(shadowed, vec!["python"]);
+    }
+}
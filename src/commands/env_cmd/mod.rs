@@ -1,8 +1,8 @@
 use anyhow::{Context, Result};
 use clap::{Arg, ArgMatches, Command};
 
-use crate::config::load_config;
-use crate::shimlink::{DEFAULT_ENVVARS, expand_envvar_patterns};
+use crate::config::load_cli_config;
+use crate::shimlink::{DEFAULT_ENVVARS, apply_env_defaults, expand_envvar_patterns};
 
 pub fn create_cli() -> Command {
     Command::new("env")
@@ -13,7 +13,11 @@ EXAMPLES:
   bulker env add MY_CUSTOM_DB     Forward a host var by name
   bulker env add \"AWS_*\"          Forward all vars matching a prefix
   bulker env set LANG=C           Set a hardcoded value
-  bulker env remove MY_CUSTOM_DB  Stop forwarding a var")
+  bulker env remove MY_CUSTOM_DB  Stop forwarding a var
+  bulker env --crate databio/pepatac   Also show a crate's env_defaults
+
+Inside an activated shell, `bulker env` also shows the env_defaults that the
+active crates set in their containers.")
         .subcommand(
             Command::new("add")
                 .about("Add a name or pattern to forwarded envvars")
@@ -36,6 +40,11 @@ EXAMPLES:
                 .global(true)
                 .help("Bulker configuration file"),
         )
+        .arg(
+            Arg::new("crate")
+                .long("crate")
+                .help("Crate(s) whose env_defaults to show (default: $BULKERCRATE)"),
+        )
 }
 
 pub fn dispatch(matches: &ArgMatches) -> Result<()> {
@@ -49,7 +58,7 @@ pub fn dispatch(matches: &ArgMatches) -> Result<()> {
 }
 
 fn run_show(matches: &ArgMatches) -> Result<()> {
-    let (config, _) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, _) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
 
     // Show default allowlist
     let defaults: Vec<&str> = DEFAULT_ENVVARS.to_vec();
@@ -87,13 +96,54 @@ fn run_show(matches: &ArgMatches) -> Result<()> {
         }
     }
 
+    let crate_id = matches
+        .get_one::<String>("crate")
+        .cloned()
+        .or_else(|| std::env::var("BULKERCRATE").ok());
+    if let Some(crate_id) = crate_id {
+        show_env_defaults(&config, &crate_id)?;
+    }
+
+    Ok(())
+}
+
+/// Show the `env_defaults` of crates and their imports, as set in containers.
+fn show_env_defaults(config: &crate::config::BulkerConfig, crate_id: &str) -> Result<()> {
+    let cratelist = crate::manifest::parse_registry_paths(crate_id, &config.bulker.default_namespace)?;
+    let (all, _missing) = crate::imports::resolve_cratevars_allow_missing_imports(config, &cratelist)?;
+    // Explicit KEY=value config entries win over crate defaults
+    let explicit: Vec<String> = config.bulker.envvars.iter().filter(|e| e.contains('=')).cloned().collect();
+    for cv in &all {
+        let Some(manifest) = crate::manifest_cache::load_cached(cv)? else {
+            continue;
+        };
+        let defaults = &manifest.manifest.env_defaults;
+        if defaults.is_empty() {
+            continue;
+        }
+        println!();
+        println!("Crate defaults (env_defaults of {}):", cv.display_name());
+        let applied = apply_env_defaults(&mut explicit.clone(), defaults);
+        for default in defaults {
+            let Some((key, _)) = default.split_once('=') else {
+                println!("  {}  (ignored: expected KEY=value)", default);
+                continue;
+            };
+            let prefix = format!("{}=", key);
+            match applied.iter().find(|a| a.starts_with(&prefix)) {
+                Some(entry) => println!("  {}", entry),
+                None => println!("  {}  (overridden by config envvars)", default),
+            }
+        }
+    }
     Ok(())
 }
 
 fn run_add(sub_m: &ArgMatches, parent_m: &ArgMatches) -> Result<()> {
-    let (mut config, config_path) = load_config(parent_m.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (mut config, config_path) = load_cli_config(parent_m.get_one::<String>("config").map(|s| s.as_str()))?;
     let config_path = config_path.context("No config file to write to. Run `bulker config init` first.")?;
     let pattern = sub_m.get_one::<String>("pattern").unwrap();
+    crate::config::check_envvar_entry(pattern)?;
 
     if config.bulker.envvars.contains(pattern) {
         println!("'{}' already in envvars", pattern);
@@ -107,13 +157,14 @@ fn run_add(sub_m: &ArgMatches, parent_m: &ArgMatches) -> Result<()> {
 }
 
 fn run_set(sub_m: &ArgMatches, parent_m: &ArgMatches) -> Result<()> {
-    let (mut config, config_path) = load_config(parent_m.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (mut config, config_path) = load_cli_config(parent_m.get_one::<String>("config").map(|s| s.as_str()))?;
     let config_path = config_path.context("No config file to write to. Run `bulker config init` first.")?;
     let keyvalue = sub_m.get_one::<String>("keyvalue").unwrap();
 
     if !keyvalue.contains('=') {
         anyhow::bail!("Expected KEY=VALUE format, got '{}'", keyvalue);
     }
+    crate::config::check_envvar_entry(keyvalue)?;
 
     if config.bulker.envvars.contains(keyvalue) {
         println!("'{}' already in envvars", keyvalue);
@@ -127,7 +178,7 @@ fn run_set(sub_m: &ArgMatches, parent_m: &ArgMatches) -> Result<()> {
 }
 
 fn run_remove(sub_m: &ArgMatches, parent_m: &ArgMatches) -> Result<()> {
-    let (mut config, config_path) = load_config(parent_m.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (mut config, config_path) = load_cli_config(parent_m.get_one::<String>("config").map(|s| s.as_str()))?;
     let config_path = config_path.context("No config file to write to. Run `bulker config init` first.")?;
     let pattern = sub_m.get_one::<String>("pattern").unwrap();
 
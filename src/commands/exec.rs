@@ -1,9 +1,10 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::os::unix::fs::PermissionsExt;
 
 use crate::activate::get_new_path;
-use crate::config::load_config;
-use crate::manifest::{is_local_path, is_url, load_local_manifest, load_url_manifest, parse_registry_paths};
+use crate::config::load_cli_config;
+use crate::manifest::{is_local_path, is_url, load_local_manifest, load_url_manifest, parse_registry_paths, CommandsFilter};
 use crate::process;
 
 pub fn create_cli() -> Command {
@@ -14,12 +15,25 @@ EXAMPLES:
   bulker exec bulker/demo -- cowsay hello
   bulker exec databio/pepatac:1.0.13 -- samtools --version
   bulker exec -s bulker/demo -- cowsay hi    # strict: only crate commands in PATH
+  bulker exec bulker/demo --script steps.sh  # run a whole script in the environment
+  bulker exec bulker/demo --script steps.sh -- sample1   # script with arguments
+  bulker exec --record run1/ bulker/demo -- cowsay hi   # write a provenance bundle
+  bulker exec --timings bulker/demo -- cowsay hi        # report time spent in bulker
+  bulker exec --isolate-home bulker/demo -- cowsay hi   # throwaway HOME in the container
+  bulker exec --cwd /data/run1 my/pipeline -- align in.fq   # run in (and mount) /data/run1
+  bulker exec --var sample=NA12878 my/pipeline -- align in.fq   # fills {sample} in workdir/volumes
+  bulker exec --memoize my/pipeline --script steps.sh   # skip steps already run with the same inputs
+  bulker exec --prefix-output my/pipeline --script steps.sh   # \"bwa | ...\" on every output line
+  bulker exec --summary --fail-fast a/tools,b/more --script steps.sh   # stop at the first failing tool
+  bulker exec --commands-filter 'samtools*' bulker/biocontainers -- samtools view in.bam   # link only these tools
+  bulker exec --locked databio/pepatac:1.0.13 -- samtools --version   # images pinned in ./bulker.lock
+  bulker exec --gpus my/ml -- python train.py   # --gpus=all (docker/podman) or --nv (apptainer)
 
 CRATE FORMAT:
   namespace/crate:tag    Full path (e.g., databio/pepatac:1.0.13)
   crate                  Uses default namespace \"bulker\", tag \"default\"
   crate1,crate2          Multiple crates
-  ./path/to/file.yaml    Local manifest file
+  ./path/to/file.yaml    Local manifest file (.yaml, .yml or .json)
   https://url/file.yaml  Remote manifest")
         .arg(
             Arg::new("crate_registry_paths")
@@ -28,10 +42,16 @@ CRATE FORMAT:
         )
         .arg(
             Arg::new("cmd")
-                .required(true)
+                .required_unless_present("script")
                 .num_args(1..)
                 .trailing_var_arg(true)
-                .help("Command and arguments to run"),
+                .help("Command and arguments to run (arguments to the script with --script)"),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .value_name("FILE")
+                .help("Run a script file inside the crate environment"),
         )
         .arg(
             Arg::new("config")
@@ -60,6 +80,80 @@ CRATE FORMAT:
                 .action(ArgAction::SetTrue)
                 .help("Print the generated docker/apptainer command instead of running it"),
         )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("DIR")
+                .help("Record images, digests, args, exit codes and wall times of every container command into DIR"),
+        )
+        .arg(
+            Arg::new("isolate_home")
+                .long("isolate-home")
+                .action(ArgAction::SetTrue)
+                .help("Give containers a throwaway HOME instead of mounting the real one"),
+        )
+        .arg(
+            Arg::new("gpus")
+                .long("gpus")
+                .action(ArgAction::SetTrue)
+                .help("Give every container command the host's GPUs, as if the manifest set `gpu: true`"),
+        )
+        .arg(
+            Arg::new("cwd")
+                .long("cwd")
+                .value_name("DIR")
+                .help("Run the command in DIR, which containers use as their working directory (mounted if needed)"),
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .value_name("NAME=VALUE")
+                .action(ArgAction::Append)
+                .help("Fill {NAME} in the commands' workdir and volumes (repeatable)"),
+        )
+        .arg(
+            Arg::new("memoize")
+                .long("memoize")
+                .action(ArgAction::SetTrue)
+                .help("Replay the output of identical successful non-interactive runs instead of rerunning them"),
+        )
+        .arg(
+            Arg::new("prefix_output")
+                .long("prefix-output")
+                .action(ArgAction::SetTrue)
+                .help("Prefix each line a container command prints with the command name"),
+        )
+        .arg(
+            Arg::new("summary")
+                .long("summary")
+                .action(ArgAction::SetTrue)
+                .help("Print the container commands run, their failures and durations to stderr at the end"),
+        )
+        .arg(
+            Arg::new("fail_fast")
+                .long("fail-fast")
+                .action(ArgAction::SetTrue)
+                .help("Stop the whole command at the first failing container command, exiting with its code"),
+        )
+        .arg(
+            Arg::new("commands-filter")
+                .long("commands-filter")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("Only link the commands matching these globs (comma-separated or repeated, e.g. 'samtools,bcf*')"),
+        )
+        .arg(
+            Arg::new("locked")
+                .long("locked")
+                .action(ArgAction::SetTrue)
+                .help("Run the image digests pinned in ./bulker.lock (see `bulker crate lock`); refuse crates changed since"),
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .help("Print a breakdown of where bulker spent its time to stderr"),
+        )
         .arg(
             Arg::new("name")
                 .short('n')
@@ -69,11 +163,14 @@ CRATE FORMAT:
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    // exec removes its shimdir afterwards, so it never uses the shared one
+    config.bulker.shared_shimdir = None;
 
     let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
     let name_override = matches.get_one::<String>("name").map(|s| s.as_str());
     let strict = matches.get_flag("strict");
+    let commands_filter = CommandsFilter::parse(matches.get_many::<String>("commands-filter").into_iter().flatten().map(String::as_str))?;
 
     let cratelist = if is_url(registry_paths) {
         let (cv, manifest) = load_url_manifest(registry_paths, name_override, &config.bulker.default_namespace)?;
@@ -84,17 +181,72 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         crate::manifest_cache::save_to_cache(&cv, &manifest)?;
         vec![cv]
     } else {
-        parse_registry_paths(registry_paths, &config.bulker.default_namespace)?
+        crate::manifest_cache::resolve_tag_aliases(parse_registry_paths(registry_paths, &config.bulker.default_namespace)?)
+    };
+
+    let cwd = match matches.get_one::<String>("cwd") {
+        Some(dir) => {
+            let dir = std::fs::canonicalize(dir).with_context(|| format!("Working directory not found: {}", dir))?;
+            if !dir.is_dir() {
+                anyhow::bail!("Working directory is not a directory: {}", dir.display());
+            }
+            Some(dir)
+        }
+        None => None,
     };
 
-    let cmd_args: Vec<&String> = matches.get_many::<String>("cmd").unwrap().collect();
+    let cmd_args: Vec<&String> = matches
+        .get_many::<String>("cmd")
+        .map(|v| v.collect())
+        .unwrap_or_default();
 
     if matches.get_flag("print_command") {
         // SAFETY: called before any threads are spawned
         unsafe { std::env::set_var("BULKER_PRINT_COMMAND", "1"); }
     }
 
-    let result = get_new_path(&config, &cratelist, strict, false)?;
+    if matches.get_flag("isolate_home") {
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(crate::shimlink::ISOLATE_HOME_ENV, "1"); }
+    }
+
+    if matches.get_flag("gpus") {
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(crate::shimlink::GPUS_ENV, "1"); }
+    }
+
+    if matches.get_flag("memoize") {
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(crate::memoize::MEMOIZE_ENV, "1"); }
+    }
+
+    if matches.get_flag("prefix_output") {
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(crate::shimlink::PREFIX_OUTPUT_ENV, "1"); }
+    }
+
+    if let Some(ref dir) = cwd {
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(crate::shimlink::EXEC_CWD_ENV, dir); }
+    }
+
+    for var in matches.get_many::<String>("var").into_iter().flatten() {
+        let (name, value) = parse_var(var)?;
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(format!("{}{}", crate::shimlink::VAR_ENV_PREFIX, name), value); }
+    }
+
+    if !matches.get_flag("print_command") {
+        crate::activate::check_engine_health(&config)?;
+    }
+    // Before the shimdir exists, so a refused run leaves nothing behind
+    if matches.get_flag("locked") {
+        let lockfile = crate::config::mkabs(crate::lockfile::LOCKFILE_NAME, None);
+        crate::lockfile::check_locked(&config, &cratelist, &lockfile, false)?;
+        // SAFETY: called before any threads are spawned
+        unsafe { std::env::set_var(crate::lockfile::LOCKFILE_ENV, &lockfile); }
+    }
+    let result = get_new_path(&config, &cratelist, &commands_filter, strict, false)?;
 
     // Quote arguments with shell-escape
     let quoted_args: Vec<String> = cmd_args
@@ -110,27 +262,163 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         .join(",");
 
     let bulkercfg_export = match &config_path {
-        Some(p) => format!("export BULKERCFG=\"{}\"; ", p.display()),
+        // Absolute, so shimlinks find it after --cwd
+        Some(p) => format!("export BULKERCFG=\"{}\"; ", crate::config::mkabs(&p.to_string_lossy(), None).display()),
         None => String::new(),
     };
+    let timings = matches.get_flag("timings");
+    let timings_export = if timings {
+        format!("export {}=1; ", crate::timings::TIMINGS_ENV)
+    } else {
+        String::new()
+    };
     let host_env_export = if matches.get_flag("host_env") {
         "export BULKER_HOST_ENV=1; "
     } else {
         ""
     };
+    let record_dir = match matches.get_one::<String>("record") {
+        Some(dir) if !matches.get_flag("print_command") => {
+            let dir = crate::config::mkabs(dir, None);
+            crate::provenance::init_bundle(&dir)?;
+            Some(dir)
+        }
+        _ => None,
+    };
+    // --summary and --fail-fast read the per-command records; without
+    // --record they go to a throwaway bundle
+    let summary = matches.get_flag("summary");
+    let fail_fast = matches.get_flag("fail_fast");
+    let scratch_dir = if record_dir.is_none() && (summary || fail_fast) && !matches.get_flag("print_command") {
+        Some(tempfile::Builder::new().prefix("bulker_run_").tempdir().context("Failed to create run record directory")?)
+    } else {
+        None
+    };
+    let commands_dir = record_dir.clone().or_else(|| scratch_dir.as_ref().map(|d| d.path().to_path_buf()));
+    let record_export = match &commands_dir {
+        Some(dir) => format!(
+            "export {}={}; ",
+            crate::provenance::RECORD_ENV,
+            shell_escape::escape(dir.to_string_lossy())
+        ),
+        None => String::new(),
+    };
+    // $$ is the wrapped shell, which leads the process group to stop
+    let fail_fast_export = if fail_fast {
+        format!("export {}=$$; ", crate::shimlink::FAIL_FAST_ENV)
+    } else {
+        String::new()
+    };
+    let invocation = match matches.get_one::<String>("script") {
+        Some(script) => {
+            let mut parts = vec![script_runner(script)?];
+            parts.extend(quoted_args);
+            parts.join(" ")
+        }
+        None => quoted_args.join(" "),
+    };
     let merged_command = format!(
-        "export PATH=\"{}\"; export BULKERCRATE=\"{}\"; {}{}{}",
+        "export PATH=\"{}\"; export BULKERCRATE=\"{}\"; {}{}{}{}{}{}",
         result.path,
         crate_id,
         bulkercfg_export,
         host_env_export,
-        quoted_args.join(" ")
+        record_export,
+        fail_fast_export,
+        timings_export,
+        invocation
     );
+    // Paths given to bulker itself (crate files, --script, --record) are
+    // resolved by now; the command's own arguments are relative to DIR
+    if let Some(ref dir) = cwd {
+        std::env::set_current_dir(dir).with_context(|| format!("Failed to change directory to {}", dir.display()))?;
+    }
+
+    let started_at = crate::manifest_cache::now_secs();
+    let start = std::time::Instant::now();
+    let mut exit_code = process::spawn_shell_and_wait(&merged_command)?;
+    crate::timings::record("command_run", start.elapsed());
 
-    let exit_code = process::spawn_shell_and_wait(&merged_command)?;
+    if let Some(dir) = commands_dir.as_ref().filter(|_| summary || fail_fast) {
+        let records = crate::provenance::read_command_records(dir).unwrap_or_else(|e| {
+            log::warn!("Failed to read the run's command records: {:#}", e);
+            Vec::new()
+        });
+        if summary {
+            eprint!("{}", crate::provenance::summarize(&records));
+        }
+        // The shell was stopped by the failing command; report that command's code
+        if let Some(failed) = records.iter().find(|r| r.exit_code != 0).filter(|_| fail_fast) {
+            exit_code = failed.exit_code;
+        }
+    }
+    drop(scratch_dir);
+
+    if let Some(dir) = &record_dir {
+        let mut command: Vec<String> = Vec::new();
+        if let Some(script) = matches.get_one::<String>("script") {
+            command.push(script.clone());
+        }
+        command.extend(cmd_args.iter().map(|a| a.to_string()));
+        let record = crate::provenance::InvocationRecord {
+            bulker_version: crate::consts::VERSION.to_string(),
+            crates: cratelist.iter().map(|cv| cv.display_name()).collect(),
+            command,
+            strict,
+            env_mode: crate::provenance::env_mode(matches.get_flag("host_env")).to_string(),
+            cwd: std::env::current_dir()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            pid: std::process::id(),
+            started_at,
+            wall_time_secs: start.elapsed().as_secs_f64(),
+            exit_code,
+        };
+        match crate::provenance::write_invocation_record(dir, &record) {
+            Ok(()) => log::info!("Recorded provenance bundle: {}", dir.display()),
+            Err(e) => log::warn!("Failed to write provenance bundle: {:#}", e),
+        }
+    }
 
     // Clean up the ephemeral shimdir
     let _ = std::fs::remove_dir_all(&result.shimdir);
 
+    if timings {
+        crate::timings::print_report();
+    }
+
     std::process::exit(exit_code);
 }
+
+/// Build the shell-quoted invocation for a `--script` file. Executable scripts
+/// run directly (so their shebang is honored); others are run with `/bin/sh`.
+fn script_runner(script: &str) -> Result<String> {
+    let path = std::fs::canonicalize(script)
+        .with_context(|| format!("Script not found: {}", script))?;
+    let metadata = std::fs::metadata(&path)
+        .with_context(|| format!("Failed to read script: {}", path.display()))?;
+    if !metadata.is_file() {
+        anyhow::bail!("Script is not a file: {}", path.display());
+    }
+    let quoted = shell_escape::escape(path.to_string_lossy()).to_string();
+    if metadata.permissions().mode() & 0o111 != 0 {
+        Ok(quoted)
+    } else {
+        Ok(format!("/bin/sh {}", quoted))
+    }
+}
+
+/// Split a `--var NAME=VALUE` argument. Names are identifiers, like the
+/// `{name}` placeholders they fill.
+fn parse_var(var: &str) -> Result<(&str, &str)> {
+    let (name, value) = var
+        .split_once('=')
+        .with_context(|| format!("Invalid --var '{}': expected NAME=VALUE", var))?;
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        anyhow::bail!("Invalid --var name '{}': use letters, digits and underscores", name);
+    }
+    Ok((name, value))
+}
@@ -3,18 +3,23 @@ use clap::{Arg, ArgMatches, Command};
 
 pub fn create_cli() -> Command {
     Command::new("init-shell")
-        .about("Print shell function for ~/.bashrc or ~/.zshrc")
+        .about("Print shell function for ~/.bashrc or ~/.zshrc (or nushell/xonsh helpers)")
         .hide(true)
         .after_help("\
 EXAMPLES:
   eval \"$(bulker init-shell bash)\"     # add to ~/.bashrc
   eval \"$(bulker init-shell zsh)\"      # add to ~/.zshrc
-  bulker init-shell bash                # print the function to stdout")
+  bulker init-shell bash                # print the function to stdout
+  bulker init-shell nu | save -f ~/.config/nushell/bulker.nu   # then `source` it in config.nu
+  execx($(bulker init-shell xonsh))     # add to ~/.xonshrc
+
+nushell and xonsh get `bulker-activate` and `bulker-deactivate` commands
+instead of a `bulker` function.")
         .arg(
             Arg::new("shell")
                 .required(true)
-                .value_parser(["bash", "zsh"])
-                .help("Shell type (bash or zsh)"),
+                .value_parser(["bash", "zsh", "nu", "xonsh"])
+                .help("Shell type (bash, zsh, nu or xonsh)"),
         )
 }
 
@@ -23,6 +28,8 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
 
     let function = match shell.as_str() {
         "zsh" => SHELL_FUNCTION_ZSH,
+        "nu" => SHELL_FUNCTION_NU,
+        "xonsh" => SHELL_FUNCTION_XONSH,
         _ => SHELL_FUNCTION_BASH,
     };
 
@@ -35,24 +42,29 @@ bulker() {
   case "$1" in
     activate)
       shift
-      if [ -n "$BULKERCRATE" ]; then
-        echo "bulker: already activated ($BULKERCRATE). Run 'bulker deactivate' first." >&2
-        return 1
-      fi
-      _BULKER_OLD_PS1="$PS1"
+      # --no-rc asks for a fresh subshell, which only the binary can start
+      case " $* " in
+        *" --no-rc "*) \command bulker activate "$@"; return ;;
+      esac
+      # Inside an activation this stacks on top of it (or --replace's it)
+      [ -z "$BULKERCRATE" ] && _BULKER_OLD_PS1="$PS1"
       eval "$(\command bulker activate --echo "$@")"
       if [ -n "$BULKERCRATE" ]; then
         PS1="(\[\033[01;93m\]${BULKERCRATE}\[\033[00m\]) ${_BULKER_OLD_PS1}"
       fi
       ;;
     deactivate)
-      if [ -n "$BULKER_ORIG_PATH" ]; then
+      if [ -n "$BULKER_STACK" ]; then
+        # Back to the activation below
+        eval "$(\command bulker deactivate --echo)"
+        PS1="(\[\033[01;93m\]${BULKERCRATE}\[\033[00m\]) ${_BULKER_OLD_PS1}"
+      elif [ -n "$BULKER_ORIG_PATH" ]; then
         export PATH="$BULKER_ORIG_PATH"
         if [ -n "$_BULKER_OLD_PS1" ]; then
           PS1="$_BULKER_OLD_PS1"
         fi
-        [ -d "$BULKER_SHIMDIR" ] && rm -rf "$BULKER_SHIMDIR"
-        unset BULKERCRATE BULKERPATH BULKER_SHIMDIR BULKERPROMPT BULKERSHELLRC BULKER_ORIG_PATH _BULKER_OLD_PS1
+        [ -z "$BULKER_SHIMDIR_SHARED" ] && [ -d "$BULKER_SHIMDIR" ] && rm -rf "$BULKER_SHIMDIR"
+        unset BULKERCRATE BULKERCFG BULKER_HOST_ENV BULKERPATH BULKER_SHIMDIR BULKER_SHIMDIR_SHARED BULKER_PREFETCH_STATUS BULKER_LOCKFILE BULKERPROMPT BULKERSHELLRC BULKER_ORIG_PATH _BULKER_OLD_PS1
       fi
       ;;
     *)
@@ -69,24 +81,29 @@ bulker() {
   case "$1" in
     activate)
       shift
-      if [ -n "$BULKERCRATE" ]; then
-        echo "bulker: already activated ($BULKERCRATE). Run 'bulker deactivate' first." >&2
-        return 1
-      fi
-      _BULKER_OLD_PS1="$PS1"
+      # --no-rc asks for a fresh subshell, which only the binary can start
+      case " $* " in
+        *" --no-rc "*) \command bulker activate "$@"; return ;;
+      esac
+      # Inside an activation this stacks on top of it (or --replace's it)
+      [ -z "$BULKERCRATE" ] && _BULKER_OLD_PS1="$PS1"
       eval "$(\command bulker activate --echo "$@")"
       if [ -n "$BULKERCRATE" ]; then
         PS1="(%F{226}${BULKERCRATE}%f) ${_BULKER_OLD_PS1}"
       fi
       ;;
     deactivate)
-      if [ -n "$BULKER_ORIG_PATH" ]; then
+      if [ -n "$BULKER_STACK" ]; then
+        # Back to the activation below
+        eval "$(\command bulker deactivate --echo)"
+        PS1="(%F{226}${BULKERCRATE}%f) ${_BULKER_OLD_PS1}"
+      elif [ -n "$BULKER_ORIG_PATH" ]; then
         export PATH="$BULKER_ORIG_PATH"
         if [ -n "$_BULKER_OLD_PS1" ]; then
           PS1="$_BULKER_OLD_PS1"
         fi
-        [ -d "$BULKER_SHIMDIR" ] && rm -rf "$BULKER_SHIMDIR"
-        unset BULKERCRATE BULKERPATH BULKER_SHIMDIR BULKERPROMPT BULKERSHELLRC BULKER_ORIG_PATH _BULKER_OLD_PS1
+        [ -z "$BULKER_SHIMDIR_SHARED" ] && [ -d "$BULKER_SHIMDIR" ] && rm -rf "$BULKER_SHIMDIR"
+        unset BULKERCRATE BULKERCFG BULKER_HOST_ENV BULKERPATH BULKER_SHIMDIR BULKER_SHIMDIR_SHARED BULKER_PREFETCH_STATUS BULKER_LOCKFILE BULKERPROMPT BULKERSHELLRC BULKER_ORIG_PATH _BULKER_OLD_PS1
       fi
       ;;
     *)
@@ -97,3 +114,65 @@ bulker() {
 eval "$(\command bulker completions zsh)"
 # <<< bulker initialize <<<
 "#;
+
+const SHELL_FUNCTION_NU: &str = r#"# >>> bulker initialize >>>
+def --env bulker-activate [...args: string] {
+  if ($env.BULKERCRATE? | is-not-empty) {
+    error make {msg: $"bulker: already activated \(($env.BULKERCRATE)\). Run 'bulker-deactivate' first."}
+  }
+  let orig_path = $env.PATH
+  ^bulker activate --echo --shell nu ...$args | from nuon | load-env
+  $env.BULKER_ORIG_PATH = ($orig_path | str join (char esep))
+}
+
+def --env bulker-deactivate [] {
+  if ($env.BULKER_ORIG_PATH? | is-not-empty) {
+    $env.PATH = ($env.BULKER_ORIG_PATH | split row (char esep))
+    if ($env.BULKER_SHIMDIR_SHARED? | is-empty) and ($env.BULKER_SHIMDIR? | is-not-empty) {
+      rm -rf $env.BULKER_SHIMDIR
+    }
+    hide-env -i BULKERCRATE BULKERCFG BULKERPATH BULKER_SHIMDIR BULKER_SHIMDIR_SHARED BULKER_HOST_ENV BULKER_PREFETCH_STATUS BULKER_LOCKFILE BULKER_ORIG_PATH
+  }
+}
+# <<< bulker initialize <<<
+"#;
+
+const SHELL_FUNCTION_XONSH: &str = r#"# >>> bulker initialize >>>
+def _bulker_activate(args):
+    if ${...}.get("BULKERCRATE"):
+        print("bulker: already activated (" + $BULKERCRATE + "). Run 'bulker-deactivate' first.", file=__import__("sys").stderr)
+        return 1
+    exports = $(bulker activate --echo --shell xonsh @(args))
+    if exports:
+        $BULKER_ORIG_PATH = list($PATH)
+        execx(exports)
+
+def _bulker_deactivate(args):
+    if "BULKER_ORIG_PATH" in ${...}:
+        $PATH = $BULKER_ORIG_PATH
+        if not ${...}.get("BULKER_SHIMDIR_SHARED") and ${...}.get("BULKER_SHIMDIR"):
+            __import__("shutil").rmtree($BULKER_SHIMDIR, ignore_errors=True)
+        for var in ["BULKERCRATE", "BULKERCFG", "BULKERPATH", "BULKER_SHIMDIR", "BULKER_SHIMDIR_SHARED",
+                    "BULKER_HOST_ENV", "BULKER_PREFETCH_STATUS", "BULKER_LOCKFILE", "BULKER_ORIG_PATH"]:
+            ${...}.pop(var, None)
+
+aliases["bulker-activate"] = _bulker_activate
+aliases["bulker-deactivate"] = _bulker_deactivate
+# <<< bulker initialize <<<
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deactivate_unsets_every_activation_var() {
+        for function in [SHELL_FUNCTION_BASH, SHELL_FUNCTION_ZSH] {
+            let unset = function.lines().find(|l| l.trim_start().starts_with("unset ")).unwrap();
+            let unset: Vec<&str> = unset.split_whitespace().collect();
+            for var in crate::activation_stack::ACTIVATION_VARS {
+                assert!(unset.contains(var), "{} is not unset by deactivate", var);
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::load_cli_config;
+use crate::manifest::parse_registry_paths;
+use crate::manifest_cache;
+use crate::mock;
+
+pub fn create_cli() -> Command {
+    Command::new("generate")
+        .about("Create placeholder outputs.json recordings from a crate's manifest")
+        .after_help("\
+EXAMPLES:
+  bulker mock generate databio/pepatac:1.0.13
+  bulker mock generate bulker/demo -o ci/outputs.json
+  bulker mock generate databio/pepatac --creates 'samtools={-o}' --creates 'bowtie2={-S}'
+
+Every command of the crate gets a record that matches any arguments, prints
+nothing and exits 0, so pipeline CI can run `bulker mock run` before real
+recordings exist. --creates COMMAND=PATH declares a file the placeholder
+creates: `{N}` in PATH is the Nth argument and `{-o}` the argument after -o.
+
+If the output file exists, placeholders are only added for commands without
+recordings (use --force to start over). Placeholders go last, so recordings
+added later with `bulker mock record` still match first.")
+        .arg(
+            Arg::new("crate_registry_paths")
+                .required(true)
+                .help("Crate(s) to generate placeholders for (comma-separated for multiple)"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .default_value("outputs.json")
+                .help("outputs.json file to write"),
+        )
+        .arg(
+            Arg::new("creates")
+                .long("creates")
+                .value_name("COMMAND=PATH")
+                .action(ArgAction::Append)
+                .help("Output file template a command's placeholder creates (repeatable)"),
+        )
+        .arg(
+            Arg::new("force")
+                .short('f')
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Replace an existing output file instead of merging into it"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
+    let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
+    let output = Path::new(matches.get_one::<String>("output").unwrap());
+
+    let mut creates: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for spec in matches.get_many::<String>("creates").into_iter().flatten() {
+        let Some((command, path)) = spec.split_once('=').filter(|(c, p)| !c.is_empty() && !p.is_empty()) else {
+            bail!("Invalid --creates '{}'. Use COMMAND=PATH.", spec);
+        };
+        creates.entry(command.to_string()).or_default().push(path.to_string());
+    }
+
+    let mut commands = Vec::new();
+    for cv in &cratelist {
+        let manifest = manifest_cache::ensure_cached(&config, cv, false, false)?;
+        commands.extend(manifest.manifest.commands.iter().map(|c| c.command.clone()));
+        commands.extend(manifest.manifest.host_commands.iter().cloned());
+    }
+    for command in creates.keys().filter(|c| !commands.contains(*c)) {
+        log::warn!("--creates: '{}' is not a command of {}", command, registry_paths);
+    }
+
+    let mut outputs = if output.exists() && !matches.get_flag("force") {
+        let content = std::fs::read_to_string(output)
+            .with_context(|| format!("Failed to read {}", output.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("{} is not a valid outputs.json (use --force to replace it)", output.display()))?
+    } else {
+        serde_json::Map::new()
+    };
+    let added = mock::add_placeholder_records(&mut outputs, &commands, &creates);
+
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::write(output, serde_json::to_string_pretty(&outputs)? + "\n")
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    println!(
+        "Wrote {} placeholder recording(s) to {} ({} command(s) already recorded)",
+        added.len(),
+        output.display(),
+        commands.len() - added.len()
+    );
+    Ok(())
+}
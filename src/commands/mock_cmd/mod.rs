@@ -1,3 +1,4 @@
+pub mod generate;
 pub mod record;
 pub mod run;
 
@@ -18,12 +19,14 @@ pub fn create_cli() -> Command {
         )
         .subcommand(run::create_cli())
         .subcommand(record::create_cli())
+        .subcommand(generate::create_cli())
 }
 
 pub fn dispatch(matches: &ArgMatches) -> Result<()> {
     match matches.subcommand() {
         Some(("run", sub_m)) => run::run(sub_m),
         Some(("record", sub_m)) => record::run(sub_m),
+        Some(("generate", sub_m)) => generate::run(sub_m),
         _ => unreachable!(),
     }
 }
@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use clap::{Arg, ArgMatches, Command};
 use std::path::PathBuf;
 
-use crate::config::load_config;
+use crate::config::load_cli_config;
 use crate::manifest::{load_remote_manifest, parse_registry_paths};
 use crate::mock;
 use crate::process;
@@ -39,7 +39,7 @@ The recordings are appended to the specified outputs.json file for later use wit
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, _config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
 
     let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
     let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
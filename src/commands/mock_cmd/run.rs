@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use std::path::PathBuf;
 
-use crate::config::load_config;
+use crate::config::load_cli_config;
 use crate::manifest::{load_remote_manifest, parse_registry_paths};
 use crate::mock;
 
@@ -14,9 +14,23 @@ EXAMPLES:
   bulker mock run databio/pepatac:1.0.13 outputs.json
   bulker mock run bulker/demo outputs.json --echo
   bulker mock run -s bulker/demo outputs.json    # strict: only mock commands in PATH
+  bulker mock run bulker/demo outputs.json --inject failures.yaml
 
 The run subcommand loads a crate using pre-recorded outputs from an outputs.json
-file instead of real containers. Use 'bulker mock record' to create the outputs.json.")
+file instead of real containers. Use 'bulker mock record' to create the outputs.json.
+
+FAILURE INJECTION:
+  --inject takes a YAML or JSON file mapping command names to failure
+  scenarios, checked before the recordings:
+
+    samtools:
+    - args_pattern: sort      # regex on the arguments (default: any)
+      returncode: 137         # default 1
+      stderr: \"Killed\\n\"
+      delay: 2.5              # seconds before failing
+      every: 3                # only every 3rd matching call fails
+
+  outputs.json records accept `delay` and `every` too.")
         .arg(
             Arg::new("crate_registry_paths")
                 .required(true)
@@ -34,6 +48,12 @@ file instead of real containers. Use 'bulker mock record' to create the outputs.
                 .action(ArgAction::SetTrue)
                 .help("Strict mode: only mock commands available in PATH"),
         )
+        .arg(
+            Arg::new("inject")
+                .long("inject")
+                .value_name("FILE")
+                .help("Failure scenarios (YAML or JSON) applied before the recorded outputs"),
+        )
         .arg(
             Arg::new("echo")
                 .long("echo")
@@ -43,7 +63,7 @@ file instead of real containers. Use 'bulker mock record' to create the outputs.
 }
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let (config, _config_path) = load_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
 
     let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
     let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
@@ -73,6 +93,7 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
 
     // Load all manifests and create mock shims
     let mut all_mock_paths = Vec::new();
+    let mut mocked_commands = std::collections::HashSet::new();
     for cv in &cratelist {
         let (manifest, _cratevars) = load_remote_manifest(&config, &cv.display_name(), None)?;
         let crate_subdir = mock_crate_path.join(format!(
@@ -81,8 +102,29 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
         ));
         mock::load_mock_crate(&manifest, &crate_subdir)?;
         all_mock_paths.push(crate_subdir.to_string_lossy().to_string());
+        mocked_commands.extend(manifest.manifest.commands.iter().map(|c| c.command.clone()));
+        mocked_commands.extend(manifest.manifest.host_commands.iter().cloned());
     }
 
+    // Call counters for intermittent (`every`) scenarios live with the mock
+    // crate, and start from zero in every session
+    let state_dir = mock_crate_path.join("state");
+    mock::reset_state(&outputs_abs, &state_dir)?;
+    let state_dir = state_dir.to_string_lossy().to_string();
+    let failures_path = match matches.get_one::<String>("inject") {
+        Some(inject) => {
+            let failures = mock::load_failures(std::path::Path::new(inject))?;
+            for command in failures.keys().filter(|c| !mocked_commands.contains(*c)) {
+                log::warn!("--inject: '{}' is not a command of {}", command, registry_paths);
+            }
+            let path = mock_crate_path.join("failures.json");
+            std::fs::write(&path, serde_json::to_string_pretty(&failures)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Some(path.to_string_lossy().to_string())
+        }
+        None => None,
+    };
+
     let mock_path_str = all_mock_paths.join(":");
     let newpath = if strict {
         mock_path_str.clone()
@@ -96,6 +138,10 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
     if echo {
         println!("export PATH=\"{}\"", newpath);
         println!("export BULKER_MOCK_OUTPUTS=\"{}\"", outputs_abs_str);
+        println!("export BULKER_MOCK_STATE=\"{}\"", state_dir);
+        if let Some(ref path) = failures_path {
+            println!("export BULKER_MOCK_FAILURES=\"{}\"", path);
+        }
         println!("export BULKERCRATE=\"mock:{}\"", registry_paths);
         // Keep the temp dir alive by leaking it (user is responsible for cleanup)
         let _ = mock_dir.keep();
@@ -107,6 +153,10 @@ pub fn run(matches: &ArgMatches) -> Result<()> {
     unsafe {
         std::env::set_var("PATH", &newpath);
         std::env::set_var("BULKER_MOCK_OUTPUTS", &outputs_abs_str);
+        std::env::set_var("BULKER_MOCK_STATE", &state_dir);
+        if let Some(ref path) = failures_path {
+            std::env::set_var("BULKER_MOCK_FAILURES", path);
+        }
         std::env::set_var("BULKERCRATE", format!("mock:{}", registry_paths));
     }
 
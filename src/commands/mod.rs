@@ -1,8 +1,21 @@
 pub mod activate;
+pub mod attach;
+pub mod bugreport;
+pub mod ci_cmd;
+pub mod clean;
 pub mod completions;
 pub mod config_cmd;
 pub mod crate_cmd;
+pub mod deactivate;
+pub mod demo;
+pub mod doctor;
 pub mod env_cmd;
 pub mod exec;
 pub mod init_shell;
 pub mod mock_cmd;
+pub mod prefetch;
+pub mod ps;
+pub mod registry_cmd;
+pub mod selftest;
+pub mod status;
+pub mod version;
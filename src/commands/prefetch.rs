@@ -0,0 +1,34 @@
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+use crate::config::load_cli_config;
+use crate::manifest::parse_registry_paths;
+
+/// Internal: the detached worker behind `activate --prefetch=background`.
+/// Its output is the prefetch status file.
+pub fn create_cli() -> Command {
+    Command::new("prefetch")
+        .about("Pull the images of cached crates (used by activate --prefetch=background)")
+        .hide(true)
+        .arg(
+            Arg::new("crate_registry_paths")
+                .required(true)
+                .help("Cached crate(s) to pull images for (comma-separated)"),
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry_paths = matches.get_one::<String>("crate_registry_paths").unwrap();
+    let cratelist = parse_registry_paths(registry_paths, &config.bulker.default_namespace)?;
+    println!("bulker: prefetching images for {}", registry_paths);
+    crate::activate::prefetch_images(&config, &cratelist)?;
+    println!("bulker: prefetch done");
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgMatches, Command};
+
+use crate::config::load_cli_config;
+
+pub fn create_cli() -> Command {
+    Command::new("ps")
+        .about("List running bulker containers and their published ports")
+        .after_help("\
+EXAMPLES:
+  bulker ps
+
+Shows containers started by bulker (docker only). Commands declare published
+ports with the `ports:` manifest field.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    if config.is_apptainer() {
+        bail!("`bulker ps` requires docker: apptainer runs commands as host processes, not managed containers.");
+    }
+
+    let output = std::process::Command::new(config.engine_path())
+        .args([
+            "ps",
+            "--filter", "label=bulker.command",
+            "--format", "{{.ID}}\t{{.Label \"bulker.command\"}}\t{{.Image}}\t{{.RunningFor}}\t{{.Ports}}",
+        ])
+        .output()
+        .with_context(|| format!("Failed to run '{} ps'", config.engine_path()))?;
+    if !output.status.success() {
+        bail!("'{} ps' failed: {}", config.engine_path(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let rows: Vec<Vec<String>> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.split('\t').map(|s| s.to_string()).collect())
+        .collect();
+
+    if rows.is_empty() {
+        println!("No running bulker containers.");
+        return Ok(());
+    }
+
+    let headers = ["Container", "Command", "Image", "Running", "Ports"];
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate().take(headers.len()) {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+
+    println!();
+    let header: Vec<String> = headers.iter().enumerate()
+        .map(|(i, h)| format!("{:<w$}", h, w = widths[i]))
+        .collect();
+    println!("  {}", header.join("  ").trim_end());
+    let rule: Vec<String> = widths.iter().map(|w| "─".repeat(*w)).collect();
+    println!("  {}", rule.join("  "));
+    for row in &rows {
+        let cells: Vec<String> = row.iter().enumerate().take(headers.len())
+            .map(|(i, c)| format!("{:<w$}", c, w = widths[i]))
+            .collect();
+        println!("  {}", cells.join("  ").trim_end());
+    }
+    Ok(())
+}
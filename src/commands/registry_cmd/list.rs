@@ -0,0 +1,32 @@
+use anyhow::Result;
+use clap::{ArgMatches, Command};
+
+use crate::config::{load_cli_config, resolve_credential};
+
+pub fn create_cli() -> Command {
+    Command::new("list")
+        .about("List registries with recorded credential references")
+        .after_help("\
+EXAMPLES:
+  bulker registry list
+
+Shows each reference and whether it currently resolves. Tokens are never printed.
+Logins kept only in the engine's credential store are not listed.")
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (config, _) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    if config.bulker.registry_auth.is_empty() {
+        println!("No registry credentials recorded. Add one with `bulker registry login <registry> --token-env VAR`.");
+        return Ok(());
+    }
+    let width = config.bulker.registry_auth.keys().map(|k| k.len()).max().unwrap_or(0);
+    for (registry, reference) in &config.bulker.registry_auth {
+        let state = match resolve_credential(reference) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("{:#}", e),
+        };
+        println!("  {:<w$}  {}  ({})", registry, reference, state, w = width);
+    }
+    Ok(())
+}
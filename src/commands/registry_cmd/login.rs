@@ -0,0 +1,108 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io::Write;
+
+use crate::config::{load_cli_config, mkabs, normalize_registry, resolve_credential};
+
+pub fn create_cli() -> Command {
+    Command::new("login")
+        .about("Log in to a registry with the container engine (and bulker's own registry client)")
+        .after_help("\
+EXAMPLES:
+  bulker registry login ghcr.io                               # engine prompts for credentials
+  bulker registry login ghcr.io -u me --token-env GHCR_TOKEN  # non-interactive
+  bulker registry login quay.io --token-file ~/.quay-token --no-engine
+
+Runs `docker login` or `apptainer registry login` (`singularity remote login`),
+which keep the credentials in the engine's own store. With --token-env or
+--token-file, the config records only the reference (env:VAR or file:PATH),
+never the token. bulker uses it for its own registry requests (image digest
+lookups): with -u it is exchanged for a registry token the way the engine does
+(Docker Hub, ghcr.io, quay.io), without -u it is sent as a pre-issued bearer
+token.")
+        .arg(
+            Arg::new("registry")
+                .required(true)
+                .help("Registry host (e.g. ghcr.io, quay.io, docker.io)"),
+        )
+        .arg(
+            Arg::new("username")
+                .short('u')
+                .long("username")
+                .help("Registry username"),
+        )
+        .arg(
+            Arg::new("token-env")
+                .long("token-env")
+                .value_name("VAR")
+                .conflicts_with("token-file")
+                .help("Environment variable holding the token or password"),
+        )
+        .arg(
+            Arg::new("token-file")
+                .long("token-file")
+                .value_name("PATH")
+                .help("File holding the token or password"),
+        )
+        .arg(
+            Arg::new("no-engine")
+                .long("no-engine")
+                .action(ArgAction::SetTrue)
+                .help("Only record the credential reference; don't run the engine's login"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry = normalize_registry(matches.get_one::<String>("registry").unwrap());
+    let username = matches.get_one::<String>("username").map(|s| s.as_str());
+    let reference = match (matches.get_one::<String>("token-env"), matches.get_one::<String>("token-file")) {
+        (Some(var), _) => Some(format!("env:{}", var)),
+        (_, Some(path)) => Some(format!("file:{}", mkabs(path, None).display())),
+        _ => None,
+    };
+    let no_engine = matches.get_flag("no-engine");
+    if no_engine && reference.is_none() {
+        bail!("--no-engine needs --token-env or --token-file: there is nothing to record");
+    }
+    // Fail before touching anything if the reference doesn't resolve
+    let secret = reference.as_deref().map(resolve_credential).transpose()?;
+
+    if !no_engine {
+        let engine = config.bulker.container_engine.clone();
+        // A token can only be passed non-interactively together with a username
+        let password = secret.as_deref().filter(|_| username.is_some());
+        let args = super::engine_auth_args(&engine, "login", &registry, username, password.is_some());
+        let mut cmd = std::process::Command::new(config.engine_path());
+        cmd.args(&args);
+        if password.is_some() {
+            cmd.stdin(std::process::Stdio::piped());
+        }
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to run '{} {}'", config.engine_path(), args.join(" ")))?;
+        if let (Some(password), Some(mut stdin)) = (password, child.stdin.take()) {
+            stdin.write_all(password.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} login to {} failed", engine, registry);
+        }
+    }
+
+    if let Some(reference) = reference {
+        let config_path = config_path.context("No config file to write to. Run `bulker config init` first.")?;
+        // The username lets bulker exchange the token at the registry's token endpoint
+        let reference = match username {
+            Some(user) => format!("{}@{}", user, reference),
+            None => reference,
+        };
+        config.bulker.registry_auth.insert(registry.clone(), reference.clone());
+        config.write(&config_path)?;
+        println!("Recorded credentials for {} as {} in {}", registry, reference, config_path.display());
+    }
+    if !no_engine {
+        println!("Logged in to {}", registry);
+    }
+    Ok(())
+}
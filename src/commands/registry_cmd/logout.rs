@@ -0,0 +1,47 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::{load_cli_config, normalize_registry};
+
+pub fn create_cli() -> Command {
+    Command::new("logout")
+        .about("Log out of a registry and forget its credential reference")
+        .after_help("\
+EXAMPLES:
+  bulker registry logout ghcr.io
+  bulker registry logout quay.io --no-engine   # only forget the config reference")
+        .arg(
+            Arg::new("registry")
+                .required(true)
+                .help("Registry host"),
+        )
+        .arg(
+            Arg::new("no-engine")
+                .long("no-engine")
+                .action(ArgAction::SetTrue)
+                .help("Don't run the engine's logout"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let registry = normalize_registry(matches.get_one::<String>("registry").unwrap());
+
+    if !matches.get_flag("no-engine") {
+        let args = super::engine_auth_args(&config.bulker.container_engine, "logout", &registry, None, false);
+        let status = std::process::Command::new(config.engine_path())
+            .args(&args)
+            .status()
+            .with_context(|| format!("Failed to run '{} {}'", config.engine_path(), args.join(" ")))?;
+        if !status.success() {
+            bail!("{} logout from {} failed", config.bulker.container_engine, registry);
+        }
+    }
+
+    if config.bulker.registry_auth.remove(&registry).is_some() {
+        let config_path = config_path.context("No config file to write to")?;
+        config.write(&config_path)?;
+        println!("Removed credential reference for {}", registry);
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+pub mod list;
+pub mod login;
+pub mod logout;
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+pub fn create_cli() -> Command {
+    Command::new("registry")
+        .about("Log in to container registries for private images")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .global(true)
+                .help("Bulker configuration file"),
+        )
+        .subcommand(login::create_cli())
+        .subcommand(logout::create_cli())
+        .subcommand(list::create_cli())
+}
+
+pub fn dispatch(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("login", sub_m)) => login::run(sub_m),
+        Some(("logout", sub_m)) => logout::run(sub_m),
+        Some(("list", sub_m)) => list::run(sub_m),
+        _ => unreachable!(),
+    }
+}
+
+/// Arguments for the engine's own registry login or logout. Docker and
+/// apptainer keep the credentials in their own stores.
+pub(crate) fn engine_auth_args(engine: &str, action: &str, registry: &str, username: Option<&str>, password_stdin: bool) -> Vec<String> {
+    let mut args: Vec<String> = match engine {
+        "apptainer" => vec!["registry".into(), action.into()],
+        "singularity" => vec!["remote".into(), action.into()],
+        _ => vec![action.into()],
+    };
+    if let Some(user) = username {
+        args.push("--username".into());
+        args.push(user.into());
+    }
+    if password_stdin {
+        args.push("--password-stdin".into());
+    }
+    if engine == "docker" || engine == "podman" {
+        args.push(registry.into());
+    } else {
+        args.push(format!("docker://{}", registry));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_auth_args() {
+        assert_eq!(
+            engine_auth_args("docker", "login", "ghcr.io", Some("me"), true),
+            vec!["login", "--username", "me", "--password-stdin", "ghcr.io"]
+        );
+        assert_eq!(
+            engine_auth_args("apptainer", "login", "ghcr.io", None, false),
+            vec!["registry", "login", "docker://ghcr.io"]
+        );
+        assert_eq!(
+            engine_auth_args("singularity", "logout", "quay.io", None, false),
+            vec!["remote", "logout", "docker://quay.io"]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::activate::get_new_path;
+use crate::config::load_cli_config;
+use crate::manifest::{parse_manifest, parse_registry_path, CommandsFilter};
+use crate::manifest_cache;
+
+/// Embedded micro-crate: one alpine-based echo tool.
+const SELFTEST_MANIFEST: &str = "\
+manifest:
+  name: bulker/selftest
+  version: embedded
+  commands:
+  - command: bulker-selftest-echo
+    docker_image: alpine:3
+    entrypoint: echo
+";
+
+const SELFTEST_CRATE: &str = "bulker/selftest:embedded";
+const SELFTEST_COMMAND: &str = "bulker-selftest-echo";
+
+pub fn create_cli() -> Command {
+    Command::new("selftest")
+        .about("Check that config, cache, shimlinks and the container engine work end to end")
+        .after_help("\
+EXAMPLES:
+  bulker selftest
+  bulker selftest --keep      # leave the selftest crate in the cache
+
+Installs an embedded alpine-based echo crate, activates it in a subshell, runs
+the tool through a shimlink, and verifies its output. The first run pulls the
+alpine image.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("keep")
+                .long("keep")
+                .action(ArgAction::SetTrue)
+                .help("Keep the selftest crate in the manifest cache afterwards"),
+        )
+}
+
+/// Print a pass line for a completed step.
+fn pass(step: &str, detail: &str) {
+    println!("  ✓ {:<10} {}", step, detail);
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    println!("bulker selftest");
+    let result = selftest(matches);
+    match &result {
+        Ok(()) => println!("All checks passed."),
+        Err(e) => println!("  ✗ {:#}", e),
+    }
+    result
+}
+
+fn selftest(matches: &ArgMatches) -> Result<()> {
+    // 1. Config
+    let (mut config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))
+        .context("config: failed to load")?;
+    // The selftest shimdir is removed afterwards, so never use the shared one
+    config.bulker.shared_shimdir = None;
+    pass(
+        "config",
+        &config_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "(defaults, no config file)".to_string()),
+    );
+
+    // 2. Engine
+    let engine_path = config.engine_path();
+    if !std::path::Path::new(engine_path).is_file() && crate::config::resolve_engine_path(engine_path).is_none() {
+        bail!(
+            "engine: '{}' not found. Install {} or run `bulker config init`.",
+            engine_path,
+            config.bulker.container_engine
+        );
+    }
+    pass("engine", &format!("{} ({})", config.bulker.container_engine, engine_path));
+
+    // 3. Cache
+    let cv = parse_registry_path(SELFTEST_CRATE, &config.bulker.default_namespace)?;
+    let manifest = parse_manifest(SELFTEST_MANIFEST)?;
+    manifest_cache::save_to_cache(&cv, &manifest).context("cache: failed to write manifest")?;
+    manifest_cache::load_cached(&cv)?
+        .context("cache: manifest missing after write")?;
+    pass("cache", &manifest_cache::manifest_path(&cv).display().to_string());
+
+    let outcome = run_through_shimlink(&config, config_path.as_deref(), &cv);
+
+    if !matches.get_flag("keep") {
+        let _ = manifest_cache::remove_cached(&cv);
+    }
+    outcome
+}
+
+/// Activate the selftest crate in a subshell and run its tool via the shimlink.
+fn run_through_shimlink(
+    config: &crate::config::BulkerConfig,
+    config_path: Option<&std::path::Path>,
+    cv: &crate::manifest::CrateVars,
+) -> Result<()> {
+    // 4. Shimdir
+    let result = get_new_path(config, std::slice::from_ref(cv), &CommandsFilter::default(), true, false)
+        .context("shimdir: failed to activate selftest crate")?;
+    let shim = std::path::Path::new(&result.shimdir).join(SELFTEST_COMMAND);
+    if !shim.exists() {
+        let _ = std::fs::remove_dir_all(&result.shimdir);
+        bail!("shimdir: {} was not created", shim.display());
+    }
+    pass("shimdir", &result.shimdir);
+
+    // 5. Run the tool through the shimlink in a subshell
+    let token = format!("bulker-selftest-{}", std::process::id());
+    let mut cmd = std::process::Command::new("/bin/sh");
+    cmd.arg("-c")
+        .arg(format!("{} {}", SELFTEST_COMMAND, token))
+        .env("PATH", &result.path)
+        .env("BULKERCRATE", cv.display_name())
+        .stdin(std::process::Stdio::null());
+    if let Some(p) = config_path {
+        cmd.env("BULKERCFG", p);
+    }
+    let output = cmd.output().context("run: failed to start subshell");
+    let _ = std::fs::remove_dir_all(&result.shimdir);
+    let output = output?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        bail!(
+            "run: {} exited with {}: {}",
+            SELFTEST_COMMAND,
+            output.status.code().map(|c| c.to_string()).unwrap_or_else(|| "signal".to_string()),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if !stdout.contains(&token) {
+        bail!("run: unexpected output from {}: {}", SELFTEST_COMMAND, stdout.trim());
+    }
+    pass("run", &format!("{} printed the expected token", SELFTEST_COMMAND));
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::Path;
+
+use crate::commands::doctor::{PathOrder, path_order};
+use crate::config::load_cli_config;
+use crate::manifest::{CrateVars, parse_registry_paths};
+use crate::manifest_cache;
+
+pub fn create_cli() -> Command {
+    Command::new("status")
+        .about("Summarize the current activation")
+        .after_help("\
+EXAMPLES:
+  bulker status               # inside an activated shell
+  bulker status --json
+
+Shows the active crates and their imports with command counts and digests,
+the shimdir and whether it is first on PATH, the PATH and environment modes,
+and the container engine. Strict activations also list the host commands
+they keep: the crates' and imports' host_commands plus the config's.
+Exits 1 if no crate is active.")
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Bulker configuration file"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+/// One activated crate or import.
+struct CrateStatus {
+    name: String,
+    imported: bool,
+    /// Number of commands, or None if the manifest is no longer cached.
+    commands: Option<usize>,
+    manifest_digest: Option<String>,
+    image_digest: Option<String>,
+}
+
+/// Health of the shimdir of the current activation.
+fn shimdir_health(shimdir: &str, path: &str, strict: bool) -> &'static str {
+    if !Path::new(shimdir).is_dir() {
+        return "missing (cleaned up?); re-activate";
+    }
+    match path_order(path, shimdir) {
+        PathOrder::First if strict => "ok",
+        PathOrder::First => "ok, first on PATH",
+        PathOrder::Buried(_) => "not first on PATH; run `bulker doctor --active`",
+        PathOrder::Missing => "not on PATH",
+    }
+}
+
+fn all_cratevars(config: &crate::config::BulkerConfig, cratelist: &[CrateVars]) -> Vec<CrateVars> {
+    crate::imports::resolve_cratevars_allow_missing_imports(config, cratelist)
+        .map(|(all, _)| all)
+        .unwrap_or_else(|_| cratelist.to_vec())
+}
+
+fn crate_statuses(all: &[CrateVars], cratelist: &[CrateVars]) -> Vec<CrateStatus> {
+    all.iter()
+        .map(|cv| CrateStatus {
+            name: cv.display_name(),
+            imported: !cratelist.iter().any(|c| c.display_name() == cv.display_name()),
+            commands: manifest_cache::load_cached(cv)
+                .ok()
+                .flatten()
+                .map(|m| m.manifest.commands.len()),
+            manifest_digest: manifest_cache::ensure_crate_manifest_digest(cv).ok().flatten(),
+            image_digest: manifest_cache::read_digest_sidecar(cv, "crate-image-digest"),
+        })
+        .collect()
+}
+
+/// Shorten a `sha256:...` digest for display.
+fn short_digest(digest: &str) -> String {
+    let (algo, hex) = digest.split_once(':').unwrap_or(("", digest));
+    let hex: String = hex.chars().take(12).collect();
+    if algo.is_empty() { hex } else { format!("{}:{}", algo, hex) }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let Ok(crate_id) = std::env::var("BULKERCRATE") else {
+        bail!("No crate is active. Activate one with `bulker activate <crate>`.");
+    };
+    let (config, config_path) = load_cli_config(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+    let cratelist = parse_registry_paths(&crate_id, &config.bulker.default_namespace)?;
+    let all = all_cratevars(&config, &cratelist);
+    let crates = crate_statuses(&all, &cratelist);
+    let manifests: Vec<_> = all.iter().filter_map(|cv| manifest_cache::load_cached(cv).ok().flatten()).collect();
+    let host_commands = crate::activate::effective_host_commands(&config, &manifests);
+
+    let shimdir = std::env::var("BULKER_SHIMDIR").unwrap_or_default();
+    let strict = std::env::var("BULKERPATH").is_ok_and(|p| !shimdir.is_empty() && p == shimdir);
+    let host_env = std::env::var("BULKER_HOST_ENV").is_ok();
+    let shared = std::env::var("BULKER_SHIMDIR_SHARED").is_ok();
+    let health = if shimdir.is_empty() {
+        "unknown ($BULKER_SHIMDIR is not set)"
+    } else {
+        shimdir_health(&shimdir, &std::env::var("PATH").unwrap_or_default(), strict)
+    };
+    let total_commands: usize = crates.iter().filter_map(|c| c.commands).sum();
+
+    if matches.get_flag("json") {
+        let crates_json: Vec<serde_json::Value> = crates
+            .iter()
+            .map(|c| serde_json::json!({
+                "name": c.name,
+                "imported": c.imported,
+                "commands": c.commands,
+                "manifest_digest": c.manifest_digest,
+                "image_digest": c.image_digest,
+            }))
+            .collect();
+        let info = serde_json::json!({
+            "crates": crates_json,
+            "commands": total_commands,
+            "shimdir": shimdir,
+            "shimdir_shared": shared,
+            "shimdir_health": health,
+            "strict": strict,
+            "host_commands": host_commands,
+            "host_env": host_env,
+            "engine": config.bulker.container_engine,
+            "engine_path": config.engine_path(),
+            "config": config_path.map(|p| p.display().to_string()),
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    println!("Active: {}", crate_id);
+    for c in &crates {
+        let commands = match c.commands {
+            Some(n) => format!("{} commands", n),
+            None => "not cached".to_string(),
+        };
+        let mut line = format!("  {}{}  {}", c.name, if c.imported { " (import)" } else { "" }, commands);
+        if let Some(ref d) = c.manifest_digest {
+            line.push_str(&format!("  manifest {}", short_digest(d)));
+        }
+        if let Some(ref d) = c.image_digest {
+            line.push_str(&format!("  images {}", short_digest(d)));
+        }
+        println!("{}", line);
+    }
+    println!("Commands: {}", total_commands);
+    println!("Shimdir:  {}{} ({})", shimdir, if shared { " [shared]" } else { "" }, health);
+    println!("PATH:     {}", if strict { "strict (crate commands and host_commands only)" } else { "crate commands first, then host PATH" });
+    if strict {
+        println!("Host:     {}", if host_commands.is_empty() { "none".to_string() } else { host_commands.join(", ") });
+    }
+    println!("Env:      {}", if host_env { "all host variables (--host-env)" } else { "allowlist only" });
+    println!("Engine:   {} ({})", config.bulker.container_engine, config.engine_path());
+    if let Some(p) = config_path {
+        println!("Config:   {}", p.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_digest() {
+        assert_eq!(short_digest("sha256:0123456789abcdef0123"), "sha256:0123456789ab");
+        assert_eq!(short_digest("abc"), "abc");
+    }
+
+    #[test]
+    fn test_shimdir_health() {
+        let shimdir = tempfile::tempdir().unwrap();
+        let dir = shimdir.path().to_str().unwrap();
+        assert_eq!(shimdir_health(dir, &format!("{}:/usr/bin", dir), false), "ok, first on PATH");
+        assert_eq!(shimdir_health(dir, dir, true), "ok");
+        assert!(shimdir_health(dir, &format!("/opt/bin:{}", dir), false).starts_with("not first"));
+        assert!(shimdir_health("/nonexistent/bulker_x", "/usr/bin", false).starts_with("missing"));
+    }
+}
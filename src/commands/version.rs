@@ -0,0 +1,71 @@
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::resolve_engine_path;
+use crate::consts;
+
+/// Container engines probed for `bulker version`.
+pub(crate) const ENGINES: &[&str] = &["docker", "apptainer"];
+
+pub fn create_cli() -> Command {
+    Command::new("version")
+        .about("Show version, build metadata and detected container engines")
+        .after_help("\
+EXAMPLES:
+  bulker version
+  bulker version --json       # machine-readable, for bug reports and support scripts")
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Output as JSON"),
+        )
+}
+
+/// Version string of an installed engine (first line of `<engine> --version`),
+/// or None if the engine is not in PATH.
+pub(crate) fn engine_version(engine: &str) -> Option<String> {
+    let path = resolve_engine_path(engine)?;
+    let output = std::process::Command::new(path).arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|l| l.trim().to_string()).filter(|l| !l.is_empty())
+}
+
+fn features() -> Vec<&'static str> {
+    consts::FEATURES.split(',').filter(|f| !f.is_empty()).collect()
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let engines: Vec<(&str, Option<String>)> = ENGINES
+        .iter()
+        .map(|e| (*e, engine_version(e)))
+        .collect();
+
+    if matches.get_flag("json") {
+        let engines_json: serde_json::Map<String, serde_json::Value> = engines
+            .iter()
+            .map(|(name, version)| (name.to_string(), serde_json::json!(version)))
+            .collect();
+        let info = serde_json::json!({
+            "version": consts::VERSION,
+            "git_commit": consts::GIT_COMMIT,
+            "build_date": consts::BUILD_DATE,
+            "target": consts::TARGET,
+            "features": features(),
+            "engines": engines_json,
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    let features = features();
+    println!("{} {}", consts::BIN_NAME, consts::VERSION);
+    println!("  commit:    {}", consts::GIT_COMMIT);
+    println!("  built:     {}", consts::BUILD_DATE);
+    println!("  target:    {}", consts::TARGET);
+    println!("  features:  {}", if features.is_empty() { "none".to_string() } else { features.join(", ") });
+    for (name, version) in &engines {
+        println!("  {:<10} {}", format!("{}:", name), version.as_deref().unwrap_or("not found"));
+    }
+    Ok(())
+}
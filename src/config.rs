@@ -22,6 +22,10 @@ pub struct BulkerSettings {
     pub default_namespace: String,
     #[serde(default = "default_registry_url")]
     pub registry_url: String,
+    /// Registries to fetch manifests from, tried in order (e.g. a mirror
+    /// inside the firewall, then hub.bulker.io). Replaces `registry_url` when set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registry_urls: Vec<String>,
     #[serde(default = "default_shell_path")]
     pub shell_path: String,
     #[serde(default = "default_shell_rc")]
@@ -34,6 +38,10 @@ pub struct BulkerSettings {
     pub volumes: Vec<String>,
     #[serde(default = "default_envvars")]
     pub envvars: Vec<String>,
+    /// Host commands strict activations keep, in addition to the
+    /// `host_commands` of the activated crates and their imports.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub host_commands: Vec<String>,
     #[serde(default = "default_host_network")]
     pub host_network: bool,
     #[serde(default = "default_system_volumes")]
@@ -48,6 +56,107 @@ pub struct BulkerSettings {
     pub apptainer_image_folder: Option<String>,
     #[serde(default)]
     pub engine_path: Option<String>,
+    #[serde(default = "default_auto_mount_mode")]
+    pub auto_mount_mode: String,
+    #[serde(default = "default_locale_policy")]
+    pub locale_policy: String,
+    #[serde(default)]
+    pub shimdir_base: Option<String>,
+    /// Fixed shimdir reused by every activation (e.g. on a shared filesystem
+    /// for multi-node jobs), instead of a fresh one under `shimdir_base`.
+    #[serde(default)]
+    pub shared_shimdir: Option<String>,
+    #[serde(default)]
+    pub cache_dir: Option<String>,
+    /// Mode and group for what bulker creates in the cache, the templates
+    /// directory and shimdirs, for caches shared by a group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_permissions: Option<crate::cache_permissions::CachePermissions>,
+    #[serde(default)]
+    pub warn_on_shadow: bool,
+    /// Default for the per-command `no_init` flag.
+    #[serde(default)]
+    pub no_init: bool,
+    /// Give containers a throwaway HOME instead of mounting the real one.
+    #[serde(default)]
+    pub isolate_home: bool,
+    /// Default octal umask for container processes (per-command `umask`
+    /// overrides it). Unset keeps the image's umask.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<String>,
+    /// Default CPU binding for container commands (per-command `cpu_binding`
+    /// overrides it). Unset means `none`; `auto` binds only inside a SLURM job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_binding: Option<String>,
+    /// Default CPU limit for docker and podman containers (per-command
+    /// `cpus` overrides it), e.g. `4`.
+    #[serde(default, deserialize_with = "crate::manifest::scalar_as_string", skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<String>,
+    /// Default memory limit (per-command `memory` overrides it), e.g. `16G`.
+    #[serde(default, deserialize_with = "crate::manifest::scalar_as_string", skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Default /dev/shm size (per-command `shm_size` overrides it), e.g. `2G`.
+    #[serde(default, deserialize_with = "crate::manifest::scalar_as_string", skip_serializing_if = "Option::is_none")]
+    pub shm_size: Option<String>,
+    /// Default for the per-command `chown_outputs` flag.
+    #[serde(default)]
+    pub chown_outputs: bool,
+    /// Reject manifests that use deprecated fields instead of warning.
+    #[serde(default)]
+    pub strict_manifests: bool,
+    /// Replay identical successful non-interactive runs (see `memoize.rs`).
+    #[serde(default)]
+    pub memoize: bool,
+    /// Register each docker container and remove those left behind by
+    /// killed runs (see `containers.rs`).
+    #[serde(default)]
+    pub reap_containers: bool,
+    /// Start a throwaway container in the background on activation, so the
+    /// engine is awake before the first tool call.
+    #[serde(default)]
+    pub prewarm: bool,
+    /// Crates substituted for imports: `namespace/crate[:tag]` -> replacement
+    /// registry path. Applied wherever imports are resolved or fetched.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub import_remap: std::collections::BTreeMap<String, String>,
+    /// Host path prefix -> the prefix containers see it under, for storage
+    /// mounted at different paths on different nodes (`/mnt/gpfs` -> `/gpfs`).
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub path_map: std::collections::BTreeMap<String, String>,
+    /// Credentials for bulker's own registry requests (OCI digest lookups):
+    /// registry host -> `env:VAR` or `file:PATH` holding a token, prefixed
+    /// with `USER@` when it is exchanged for a registry token (otherwise it is
+    /// sent as a bearer token). Only the reference is stored, never the token.
+    /// Managed by `bulker registry`.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub registry_auth: std::collections::BTreeMap<String, String>,
+    /// How long resolved OCI digests are reused (e.g. `24h`, `0` to always
+    /// ask the registry). Unset means 24 hours.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest_cache_ttl: Option<String>,
+    /// Bandwidth cap for bulker's own downloads (manifests and bundles), in
+    /// bytes per second with an optional K/M/G suffix, e.g. `10M`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_rate_limit: Option<String>,
+    /// Image scanning service `crate scan` uses when neither trivy nor grype
+    /// is installed: a URL that takes `{"image": ...}` POSTs and answers with
+    /// a trivy or grype JSON report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_api: Option<String>,
+    /// `env:VAR` or `file:PATH` holding a bearer token for `scan_api`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_api_token: Option<String>,
+    /// Writable registry `crate publish` POSTs manifests to, at the path
+    /// they are served from (`<publish_url>/<namespace>/<crate>_<tag>.yaml`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_url: Option<String>,
+    /// `env:VAR` or `file:PATH` holding a bearer token for `publish_url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_token: Option<String>,
+    /// Usage metrics sink for shimlink invocations (Prometheus textfile or
+    /// StatsD). Off when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<crate::metrics::MetricsConfig>,
 }
 
 fn default_container_engine() -> String {
@@ -86,6 +195,14 @@ fn default_system_volumes() -> bool {
     !cfg!(target_os = "macos") // true on Linux, false on macOS
 }
 
+fn default_auto_mount_mode() -> String {
+    AutoMountMode::Rw.name().to_string()
+}
+
+fn default_locale_policy() -> String {
+    LocalePolicy::Strip.name().to_string()
+}
+
 fn default_volumes() -> Vec<String> {
     vec!["$HOME".to_string()]
 }
@@ -94,6 +211,69 @@ fn default_envvars() -> Vec<String> {
     Vec::new()
 }
 
+/// How directories auto-mounted from command arguments are bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoMountMode {
+    /// All auto-mounted directories are writable (default).
+    Rw,
+    /// Parents of existing input files are read-only; the working directory
+    /// and parents of not-yet-existing output paths stay writable.
+    RoInputs,
+}
+
+impl AutoMountMode {
+    pub const NAMES: &'static [&'static str] = &["rw", "ro-inputs"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rw" => Some(AutoMountMode::Rw),
+            "ro-inputs" => Some(AutoMountMode::RoInputs),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutoMountMode::Rw => "rw",
+            AutoMountMode::RoInputs => "ro-inputs",
+        }
+    }
+}
+
+/// Which locale settings containers get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalePolicy {
+    /// Only locale variables the env allowlist names (by default just LANG)
+    /// are forwarded; LC_* are dropped (default).
+    Strip,
+    /// Host locale variables are dropped and `LANG=C.UTF-8` is set, which
+    /// every image supports and keeps Java and Python tools on UTF-8.
+    CUtf8,
+    /// LANG, LANGUAGE and every LC_* variable are forwarded from the host.
+    Forward,
+}
+
+impl LocalePolicy {
+    pub const NAMES: &'static [&'static str] = &["strip", "c.utf8", "forward"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "strip" => Some(LocalePolicy::Strip),
+            "c.utf8" => Some(LocalePolicy::CUtf8),
+            "forward" => Some(LocalePolicy::Forward),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LocalePolicy::Strip => "strip",
+            LocalePolicy::CUtf8 => "c.utf8",
+            LocalePolicy::Forward => "forward",
+        }
+    }
+}
+
 impl BulkerSettings {
     /// Fix serde_yml's behavior of deserializing YAML null as the string "null",
     /// and warn about deprecated config keys.
@@ -116,6 +296,59 @@ impl BulkerSettings {
         if self.apptainer_image_folder.as_deref() == Some("null") {
             self.apptainer_image_folder = None;
         }
+        if self.shimdir_base.as_deref() == Some("null") || self.shimdir_base.as_deref() == Some("") {
+            self.shimdir_base = None;
+        }
+        if self.shared_shimdir.as_deref() == Some("null") || self.shared_shimdir.as_deref() == Some("") {
+            self.shared_shimdir = None;
+        }
+        if self.cache_dir.as_deref() == Some("null") || self.cache_dir.as_deref() == Some("") {
+            self.cache_dir = None;
+        }
+        if AutoMountMode::from_name(&self.auto_mount_mode).is_none() {
+            log::warn!(
+                "Unknown auto_mount_mode '{}', using 'rw'. Valid values: {}",
+                self.auto_mount_mode,
+                AutoMountMode::NAMES.join(", ")
+            );
+            self.auto_mount_mode = default_auto_mount_mode();
+        }
+        if LocalePolicy::from_name(&self.locale_policy).is_none() {
+            log::warn!(
+                "Unknown locale_policy '{}', using 'strip'. Valid values: {}",
+                self.locale_policy,
+                LocalePolicy::NAMES.join(", ")
+            );
+            self.locale_policy = default_locale_policy();
+        }
+        if let Some(ref mask) = self.umask {
+            if mask == "null" || mask.is_empty() {
+                self.umask = None;
+            } else if crate::ownership::parse_umask(mask).is_none() {
+                log::warn!("Ignoring invalid umask '{}'. Use an octal mask such as 0022.", mask);
+                self.umask = None;
+            }
+        }
+        if let Some(ref ttl) = self.digest_cache_ttl {
+            if ttl == "null" || ttl.is_empty() {
+                self.digest_cache_ttl = None;
+            } else if let Err(e) = crate::humanize::parse_duration(ttl) {
+                log::warn!("Ignoring digest_cache_ttl: {:#}", e);
+                self.digest_cache_ttl = None;
+            }
+        }
+        if let Some(ref rate) = self.download_rate_limit {
+            if rate == "null" || rate.is_empty() {
+                self.download_rate_limit = None;
+            } else if let Err(e) = crate::humanize::parse_size(rate) {
+                log::warn!("Ignoring download_rate_limit: {:#}", e);
+                self.download_rate_limit = None;
+            }
+        }
+        if let Some(Err(e)) = self.metrics.as_ref().map(|m| m.sink()) {
+            log::warn!("Ignoring metrics config: {:#}", e);
+            self.metrics = None;
+        }
     }
 }
 
@@ -153,9 +386,17 @@ impl BulkerConfig {
     pub fn write(&self, path: &Path) -> Result<()> {
         let yaml = serde_yml::to_string(self)
             .context("Failed to serialize config")?;
-        std::fs::write(path, &yaml)
-            .with_context(|| format!("Failed to write config: {}", path.display()))?;
-        Ok(())
+        write_config_file(path, &yaml)
+    }
+
+    /// Registries manifests are fetched from, in order: `registry_urls`, or
+    /// `registry_url` when that is empty. Never empty.
+    pub fn registry_urls(&self) -> Vec<&str> {
+        if self.bulker.registry_urls.is_empty() {
+            vec![self.bulker.registry_url.as_str()]
+        } else {
+            self.bulker.registry_urls.iter().map(String::as_str).collect()
+        }
     }
 
     /// Get the resolved engine path. Returns the absolute path if set,
@@ -167,6 +408,23 @@ impl BulkerConfig {
 
     /// Returns true if the configured container engine is apptainer (or the
     /// deprecated "singularity" name, which is the same engine).
+    pub fn auto_mount_mode(&self) -> AutoMountMode {
+        AutoMountMode::from_name(&self.bulker.auto_mount_mode).unwrap_or(AutoMountMode::Rw)
+    }
+
+    pub fn locale_policy(&self) -> LocalePolicy {
+        LocalePolicy::from_name(&self.bulker.locale_policy).unwrap_or(LocalePolicy::Strip)
+    }
+
+    /// The config's `cpu_binding`; `none` when unset or invalid.
+    pub fn cpu_binding(&self) -> crate::cpu_binding::CpuBinding {
+        let value = self.bulker.cpu_binding.as_deref().unwrap_or_default();
+        crate::cpu_binding::CpuBinding::parse(value).unwrap_or_else(|e| {
+            log::warn!("Ignoring config cpu_binding: {:#}", e);
+            crate::cpu_binding::CpuBinding::None
+        })
+    }
+
     pub fn is_apptainer(&self) -> bool {
         matches!(self.bulker.container_engine.as_str(), "apptainer" | "singularity")
     }
@@ -206,12 +464,14 @@ impl BulkerConfig {
                 container_engine: "docker".to_string(),
                 default_namespace: "bulker".to_string(),
                 registry_url: "http://hub.bulker.io/".to_string(),
+                registry_urls: vec![],
                 shell_path: "/bin/bash".to_string(),
                 shell_rc: "$HOME/.bashrc".to_string(),
                 rcfile: "start.sh".to_string(),
                 rcfile_strict: "start_strict.sh".to_string(),
                 volumes: vec!["$HOME".to_string()],
                 envvars: vec![],
+                host_commands: vec![],
                 host_network: true,
                 system_volumes: true,
                 no_default_envvars: false,
@@ -219,6 +479,35 @@ impl BulkerConfig {
                 shell_prompt: None,
                 apptainer_image_folder: None,
                 engine_path: None,
+                auto_mount_mode: default_auto_mount_mode(),
+                locale_policy: default_locale_policy(),
+                shimdir_base: None,
+                shared_shimdir: None,
+                cache_dir: None,
+                cache_permissions: None,
+                warn_on_shadow: false,
+                no_init: false,
+                isolate_home: false,
+                umask: None,
+                cpu_binding: None,
+                cpus: None,
+                memory: None,
+                shm_size: None,
+                chown_outputs: false,
+                strict_manifests: false,
+                memoize: false,
+                reap_containers: false,
+                prewarm: false,
+                import_remap: Default::default(),
+                path_map: Default::default(),
+                registry_auth: Default::default(),
+                digest_cache_ttl: None,
+                download_rate_limit: None,
+                scan_api: None,
+                scan_api_token: None,
+                publish_url: None,
+                publish_token: None,
+                metrics: None,
             },
         }
     }
@@ -238,12 +527,14 @@ impl Default for BulkerSettings {
             container_engine: engine.clone(),
             default_namespace: default_namespace(),
             registry_url: default_registry_url(),
+            registry_urls: Vec::new(),
             shell_path: default_shell_path(),
             shell_rc: default_shell_rc(),
             rcfile: default_rcfile(),
             rcfile_strict: default_rcfile_strict(),
             volumes: default_volumes(),
             envvars: default_envvars(),
+            host_commands: Vec::new(),
             host_network: default_host_network(),
             system_volumes: default_system_volumes(),
             no_default_envvars: false,
@@ -251,6 +542,35 @@ impl Default for BulkerSettings {
             shell_prompt: None,
             apptainer_image_folder: None,
             engine_path: resolve_engine_path(&engine),
+            auto_mount_mode: default_auto_mount_mode(),
+            locale_policy: default_locale_policy(),
+            shimdir_base: None,
+            shared_shimdir: None,
+            cache_dir: None,
+            cache_permissions: None,
+            warn_on_shadow: false,
+            no_init: false,
+            isolate_home: false,
+            umask: None,
+            cpu_binding: None,
+            cpus: None,
+            memory: None,
+            shm_size: None,
+            chown_outputs: false,
+            strict_manifests: false,
+            memoize: false,
+            reap_containers: false,
+            prewarm: false,
+            import_remap: Default::default(),
+            path_map: Default::default(),
+            registry_auth: Default::default(),
+            digest_cache_ttl: None,
+            download_rate_limit: None,
+            scan_api: None,
+            scan_api_token: None,
+            publish_url: None,
+            publish_token: None,
+            metrics: None,
         }
     }
 }
@@ -300,7 +620,208 @@ fn is_in_path(cmd: &str) -> bool {
 
 /// Load config: explicit arg > $BULKERCFG > default path > built-in defaults with cache attempt.
 /// Returns (config, Option<config_path>). The path is None only when no file exists and caching failed.
+///
+/// Only reads the config: the process-wide state that follows from it (cache
+/// directory, permissions, registry auth, ...) is set by the CLI's
+/// [`load_cli_config`].
 pub fn load_config(arg: Option<&str>) -> Result<(BulkerConfig, Option<PathBuf>)> {
+    let _span = crate::timings::span("config_load");
+    find_and_load_config(arg)
+}
+
+/// [`load_config`] for a bulker command: also sets the process-wide state
+/// that follows from the config.
+pub(crate) fn load_cli_config(arg: Option<&str>) -> Result<(BulkerConfig, Option<PathBuf>)> {
+    let loaded = load_config(arg)?;
+    apply_config_globals(&loaded.0);
+    Ok(loaded)
+}
+
+/// Set the process-wide state that follows from a loaded config.
+fn apply_config_globals(config: &BulkerConfig) {
+    crate::manifest_cache::set_cache_dir_override(
+        config.bulker.cache_dir.as_deref().map(|d| PathBuf::from(expand_path(d))),
+    );
+    crate::cache_permissions::set_cache_permissions(config.bulker.cache_permissions.as_ref());
+    crate::manifest::set_strict_manifests(config.bulker.strict_manifests);
+    crate::digest::set_registry_auth(config.bulker.registry_auth.clone());
+    crate::digest::set_digest_cache_ttl(
+        config
+            .bulker
+            .digest_cache_ttl
+            .as_deref()
+            .and_then(|ttl| crate::humanize::parse_duration(ttl).ok())
+            .unwrap_or(crate::digest::DEFAULT_DIGEST_CACHE_TTL),
+    );
+    crate::download::set_rate_limit(
+        config.bulker.download_rate_limit.as_deref().and_then(|r| crate::humanize::parse_size(r).ok()),
+    );
+}
+
+/// File in a shimdir holding the config the activation resolved, so shimlink
+/// dispatch can skip finding and parsing the YAML config.
+pub const CONFIG_SNAPSHOT_FILE: &str = ".bulker_config.json";
+
+/// The effective config as of activation, and the config file it came from.
+#[derive(Debug, Deserialize)]
+struct ConfigSnapshot {
+    bulker_version: String,
+    config_path: PathBuf,
+    /// Modification time (ns since the epoch) and size of `config_path`.
+    mtime_ns: u64,
+    size: u64,
+    config: BulkerConfig,
+}
+
+/// Modification time (ns since the epoch) and size of a file.
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some((u64::try_from(mtime.as_nanos()).ok()?, meta.len()))
+}
+
+/// Write the config snapshot into `shimdir`. Activations without a config
+/// file get no snapshot; their shimlinks load the config as usual.
+pub fn write_config_snapshot(shimdir: &Path, config: &BulkerConfig, config_path: Option<&Path>) -> Result<()> {
+    let Some(config_path) = config_path else {
+        return Ok(());
+    };
+    let (mtime_ns, size) = file_stamp(config_path)
+        .with_context(|| format!("Failed to stat config: {}", config_path.display()))?;
+    // Same fields as ConfigSnapshot, without cloning the config
+    let snapshot = serde_json::json!({
+        "bulker_version": env!("CARGO_PKG_VERSION"),
+        "config_path": config_path,
+        "mtime_ns": mtime_ns,
+        "size": size,
+        "config": config,
+    });
+    // Write-then-rename: a shared shimdir may be read by running shims
+    let tmp = tempfile::NamedTempFile::new_in(shimdir)
+        .with_context(|| format!("Failed to create config snapshot in {}", shimdir.display()))?;
+    serde_json::to_writer(&tmp, &snapshot).context("Failed to serialize config snapshot")?;
+    tmp.persist(shimdir.join(CONFIG_SNAPSHOT_FILE))
+        .with_context(|| format!("Failed to write config snapshot in {}", shimdir.display()))?;
+    crate::cache_permissions::fix(&shimdir.join(CONFIG_SNAPSHOT_FILE));
+    Ok(())
+}
+
+/// Read the snapshot in `shimdir` if it still matches the config file that
+/// `$BULKERCFG` names: same bulker version, same path, same mtime and size.
+fn read_config_snapshot(shimdir: &Path) -> Option<(BulkerConfig, PathBuf)> {
+    let contents = std::fs::read(shimdir.join(CONFIG_SNAPSHOT_FILE)).ok()?;
+    let snapshot: ConfigSnapshot = match serde_json::from_slice(&contents) {
+        Ok(s) => s,
+        Err(e) => {
+            log::debug!("Ignoring unreadable config snapshot in {}: {}", shimdir.display(), e);
+            return None;
+        }
+    };
+    let env_path = PathBuf::from(expand_path(&std::env::var(BULKERCFG_ENV).ok()?));
+    if snapshot.bulker_version != env!("CARGO_PKG_VERSION") || snapshot.config_path != env_path {
+        return None;
+    }
+    if file_stamp(&env_path) != Some((snapshot.mtime_ns, snapshot.size)) {
+        log::debug!("Config {} changed since activation; reloading it", env_path.display());
+        return None;
+    }
+    Some((snapshot.config, snapshot.config_path))
+}
+
+/// Load config for shimlink dispatch: the activation's snapshot in
+/// `$BULKER_SHIMDIR` when it is still current, otherwise [`load_cli_config`].
+pub(crate) fn load_config_for_dispatch() -> Result<(BulkerConfig, Option<PathBuf>)> {
+    let snapshot = std::env::var("BULKER_SHIMDIR")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .and_then(|d| {
+            let _span = crate::timings::span("config_load");
+            read_config_snapshot(Path::new(&d))
+        });
+    match snapshot {
+        Some((config, path)) => {
+            apply_config_globals(&config);
+            Ok((config, Some(path)))
+        }
+        None => load_cli_config(None),
+    }
+}
+
+/// Canonical registry host name: Docker Hub's aliases become `docker.io`.
+pub fn normalize_registry(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/');
+    let host = host.strip_prefix("https://").or_else(|| host.strip_prefix("docker://")).unwrap_or(host);
+    match host {
+        "index.docker.io" | "registry-1.docker.io" | "registry.hub.docker.com" => "docker.io".to_string(),
+        _ => host.to_string(),
+    }
+}
+
+/// Split the optional `USER@` prefix off a credential reference.
+fn split_credential_user(reference: &str) -> (Option<&str>, &str) {
+    let head = reference.split_once(':').map_or(reference, |(head, _)| head);
+    match head.rfind('@') {
+        Some(at) => (Some(&reference[..at]), &reference[at + 1..]),
+        None => (None, reference),
+    }
+}
+
+/// Username recorded in a credential reference (`USER@env:VAR`), if any.
+pub fn credential_user(reference: &str) -> Option<&str> {
+    split_credential_user(reference).0.filter(|u| !u.is_empty())
+}
+
+/// Check that a credential reference has the form `env:VAR` or `file:PATH`,
+/// optionally prefixed with `USER@`.
+pub fn check_credential_ref(reference: &str) -> Result<()> {
+    match split_credential_user(reference).1.split_once(':') {
+        Some(("env", var)) if !var.is_empty() => Ok(()),
+        Some(("file", path)) if !path.is_empty() => Ok(()),
+        _ => bail!(
+            "Invalid credential reference '{}'. Use env:VAR or file:PATH (optionally USER@env:VAR); tokens themselves are never stored in the config.",
+            reference
+        ),
+    }
+}
+
+/// Check an `envvars` entry: a variable name, a glob pattern such as `AWS_*`,
+/// or `KEY=value`. `bulker env` and `config set envvars` both go through here.
+pub fn check_envvar_entry(entry: &str) -> Result<()> {
+    let (name, pattern_ok) = match entry.split_once('=') {
+        Some((key, _)) => (key, false),
+        None => (entry, true),
+    };
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || (pattern_ok && c == '*'));
+    if !valid {
+        bail!(
+            "Invalid envvars entry '{}'. Use a variable name, a pattern such as AWS_*, or KEY=value.",
+            entry
+        );
+    }
+    Ok(())
+}
+
+/// Read the secret a credential reference (`env:VAR` or `file:PATH`) points to.
+pub fn resolve_credential(reference: &str) -> Result<String> {
+    check_credential_ref(reference)?;
+    let secret = match split_credential_user(reference).1.split_once(':') {
+        Some(("env", var)) => std::env::var(var).with_context(|| format!("${} is not set", var))?,
+        Some((_, path)) => {
+            let path = expand_path(path);
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read token file {}", path))?
+        }
+        None => unreachable!(),
+    };
+    let secret = secret.trim().to_string();
+    if secret.is_empty() {
+        bail!("Credential '{}' is empty", reference);
+    }
+    Ok(secret)
+}
+
+fn find_and_load_config(arg: Option<&str>) -> Result<(BulkerConfig, Option<PathBuf>)> {
     // Step 1: explicit arg
     if let Some(path) = arg {
         let p = PathBuf::from(expand_path(path));
@@ -361,12 +882,80 @@ pub fn cache_config_to_disk(config: &BulkerConfig, config_path: &Path) -> Result
     let yaml = serde_yml::to_string(config)
         .context("Failed to serialize config")?;
     let contents = format!("# Auto-generated by bulker. Edit to customize.\n{}", yaml);
-    std::fs::write(config_path, &contents)
-        .with_context(|| format!("Failed to write config: {}", config_path.display()))?;
+    write_config_file(config_path, &contents)
+}
+
+/// Rotated backups kept of a config file that bulker rewrites.
+pub const CONFIG_BACKUPS: usize = 5;
 
+/// Backup `n` (1 is the newest) of `config_path`, in `backups/` next to it.
+pub fn config_backup_path(config_path: &Path, n: usize) -> PathBuf {
+    let name = config_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("backups")
+        .join(format!("{}.{}", name, n))
+}
+
+/// Shift the backups of `path` down one slot (dropping the oldest) and copy
+/// the current file into slot 1.
+fn rotate_config_backups(path: &Path) -> Result<()> {
+    let newest = config_backup_path(path, 1);
+    if let Some(dir) = newest.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    for n in (1..CONFIG_BACKUPS).rev() {
+        let from = config_backup_path(path, n);
+        if from.exists() {
+            std::fs::rename(&from, config_backup_path(path, n + 1))
+                .with_context(|| format!("Failed to rotate {}", from.display()))?;
+        }
+    }
+    std::fs::copy(path, &newest).with_context(|| format!("Failed to back up config to {}", newest.display()))?;
+    Ok(())
+}
+
+/// Replace the config file at `path` with `contents`. The text is written to
+/// a temporary file in the same directory and renamed over the old file, so
+/// a crash leaves either the old or the new config, never a partial one. The
+/// previous version is kept as backup 1 (see `config_backup_path`).
+pub fn write_config_file(path: &Path, contents: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    // Write through a symlinked config instead of replacing the link
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let existing = std::fs::metadata(&path).ok();
+    let backed_up = if existing.is_some() { rotate_config_backups(&path) } else { Ok(()) };
+    if let Err(e) = backed_up {
+        log::warn!("Could not back up {}: {:#}", path.display(), e);
+    }
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".bulker_config_")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to write config: {}", path.display()))?;
+    tmp.write_all(contents.as_bytes())
+        .and_then(|_| tmp.as_file().sync_all())
+        .with_context(|| format!("Failed to write config: {}", path.display()))?;
+    // Keep the old file's mode (it may hold credentials); new files get 0644
+    let mode = existing.map(|m| m.permissions().mode()).unwrap_or(0o644);
+    tmp.as_file().set_permissions(std::fs::Permissions::from_mode(mode))?;
+    tmp.persist(&path)
+        .with_context(|| format!("Failed to write config: {}", path.display()))?;
     Ok(())
 }
 
+/// The config file `load_config(arg)` would read, without reading it: `arg`,
+/// then `$BULKERCFG`, then the default location.
+pub fn locate_config_file(arg: Option<&str>) -> PathBuf {
+    match arg.map(str::to_string).or_else(|| std::env::var(BULKERCFG_ENV).ok()) {
+        Some(path) => PathBuf::from(expand_path(&path)),
+        None => default_config_path(),
+    }
+}
+
 /// Default config file location: ~/.config/bulker/bulker_config.yaml
 pub fn default_config_path() -> PathBuf {
     let config_dir = dirs::config_dir()
@@ -421,7 +1010,6 @@ pub fn expand_path(s: &str) -> String {
 }
 
 /// Make a path absolute, resolving relative to `rel_dir` if provided.
-#[cfg(test)]
 pub fn mkabs(path: &str, rel_dir: Option<&Path>) -> PathBuf {
     let expanded = expand_path(path);
     let p = PathBuf::from(&expanded);
@@ -440,6 +1028,63 @@ pub fn mkabs(path: &str, rel_dir: Option<&Path>) -> PathBuf {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_normalize_registry() {
+        assert_eq!(normalize_registry("registry-1.docker.io"), "docker.io");
+        assert_eq!(normalize_registry("https://ghcr.io/"), "ghcr.io");
+        assert_eq!(normalize_registry("docker://quay.io"), "quay.io");
+    }
+
+    #[test]
+    fn test_resolve_credential() {
+        let _guard = crate::test_util::EnvGuard::set("BULKER_TEST_TOKEN", "s3cret\n");
+        assert_eq!(resolve_credential("env:BULKER_TEST_TOKEN").unwrap(), "s3cret");
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "filetoken\n").unwrap();
+        assert_eq!(resolve_credential(&format!("file:{}", file.path().display())).unwrap(), "filetoken");
+        assert!(resolve_credential("env:BULKER_TEST_TOKEN_UNSET").is_err());
+        assert!(check_credential_ref("ghp_literaltoken").is_err());
+        assert!(check_credential_ref("env:").is_err());
+
+        // A username may prefix the reference, and may itself contain '@'
+        assert_eq!(resolve_credential("me@env:BULKER_TEST_TOKEN").unwrap(), "s3cret");
+        assert_eq!(credential_user("me@example.org@env:BULKER_TEST_TOKEN"), Some("me@example.org"));
+        assert_eq!(credential_user("env:BULKER_TEST_TOKEN"), None);
+        assert!(check_credential_ref("me@ghp_literaltoken").is_err());
+    }
+
+    #[test]
+    fn test_config_snapshot_is_used_until_config_changes() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let config_path = tmpdir.path().join("bulker_config.yaml");
+        std::fs::write(&config_path, "bulker:\n  container_engine: podman\n  volumes: [/data]\n").unwrap();
+        let config = BulkerConfig::from_file(&config_path).unwrap();
+        let shimdir = tmpdir.path().join("shims");
+        std::fs::create_dir(&shimdir).unwrap();
+        write_config_snapshot(&shimdir, &config, Some(&config_path)).unwrap();
+
+        let _guard = crate::test_util::EnvGuard::set(BULKERCFG_ENV, &config_path);
+        let (snap, path) = read_config_snapshot(&shimdir).unwrap();
+        assert_eq!(path, config_path);
+        assert_eq!(snap.bulker.container_engine, "podman");
+        assert_eq!(snap.bulker.volumes, vec!["/data"]);
+        assert_eq!(snap.bulker.envvars, config.bulker.envvars);
+
+        // Editing the config invalidates the snapshot
+        std::fs::write(&config_path, "bulker:\n  container_engine: docker\n").unwrap();
+        assert!(read_config_snapshot(&shimdir).is_none());
+        // A snapshot of a different config file is ignored too
+        let other = tmpdir.path().join("other.yaml");
+        std::fs::write(&other, "bulker: {}\n").unwrap();
+        write_config_snapshot(&shimdir, &config, Some(&other)).unwrap();
+        assert!(read_config_snapshot(&shimdir).is_none());
+        // No config file, no snapshot
+        let bare = tmpdir.path().join("bare");
+        std::fs::create_dir(&bare).unwrap();
+        write_config_snapshot(&bare, &config, None).unwrap();
+        assert!(!bare.join(CONFIG_SNAPSHOT_FILE).exists());
+    }
+
     #[test]
     fn test_default_host_network() {
         // On Linux (our CI), should be true
@@ -482,6 +1127,14 @@ mod tests {
         assert_eq!(result, PathBuf::from("/base/relative"));
     }
 
+    #[test]
+    fn test_registry_urls_replace_registry_url() {
+        let mut config = BulkerConfig::test_default();
+        assert_eq!(config.registry_urls(), vec!["http://hub.bulker.io/"]);
+        config.bulker.registry_urls = vec!["http://mirror/".to_string(), "http://hub.bulker.io/".to_string()];
+        assert_eq!(config.registry_urls(), vec!["http://mirror/", "http://hub.bulker.io/"]);
+    }
+
     #[test]
     fn test_bulker_config_default_has_sensible_values() {
         let config = BulkerConfig::default();
@@ -517,6 +1170,62 @@ mod tests {
         assert_eq!(path.unwrap(), config_path);
     }
 
+    #[test]
+    fn test_load_config_leaves_process_state_alone() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let config_path = tmpdir.path().join("test_config.yaml");
+        let cache_dir = tmpdir.path().join("library-cache");
+        let mut config = BulkerConfig::default();
+        config.bulker.cache_dir = Some(cache_dir.to_string_lossy().into_owned());
+        cache_config_to_disk(&config, &config_path).unwrap();
+
+        let (loaded, _) = load_config(Some(config_path.to_str().unwrap())).unwrap();
+        assert_eq!(loaded.bulker.cache_dir, config.bulker.cache_dir);
+        assert_ne!(crate::manifest_cache::cache_root_dir(), cache_dir);
+    }
+
+    #[test]
+    fn test_write_config_file_rotates_backups() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmpdir = tempfile::tempdir().unwrap();
+        let config_path = tmpdir.path().join("bulker_config.yaml");
+        write_config_file(&config_path, "v0\n").unwrap();
+        assert!(!config_backup_path(&config_path, 1).exists());
+        std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        for i in 1..=CONFIG_BACKUPS + 2 {
+            write_config_file(&config_path, &format!("v{}\n", i)).unwrap();
+        }
+        let last = CONFIG_BACKUPS + 2;
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), format!("v{}\n", last));
+        assert_eq!(std::fs::read_to_string(config_backup_path(&config_path, 1)).unwrap(), format!("v{}\n", last - 1));
+        assert_eq!(
+            std::fs::read_to_string(config_backup_path(&config_path, CONFIG_BACKUPS)).unwrap(),
+            format!("v{}\n", last - CONFIG_BACKUPS)
+        );
+        assert!(!config_backup_path(&config_path, CONFIG_BACKUPS + 1).exists());
+        assert_eq!(std::fs::metadata(&config_path).unwrap().permissions().mode() & 0o777, 0o600);
+        // No temp files left behind
+        let leftovers: Vec<_> = std::fs::read_dir(tmpdir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(".bulker_config_"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_write_config_file_follows_symlink() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let target = tmpdir.path().join("real.yaml");
+        std::fs::write(&target, "old\n").unwrap();
+        let link = tmpdir.path().join("bulker_config.yaml");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_config_file(&link, "new\n").unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new\n");
+    }
+
     #[test]
     fn test_cache_config_to_disk_writes_file_and_templates() {
         let tmpdir = tempfile::tempdir().unwrap();
@@ -0,0 +1,187 @@
+//! Cleanup of docker containers whose bulker process died. A `docker run
+//! --rm` outlives a SIGKILLed bulker. With the `reap_containers` config key,
+//! every container bulker starts is labelled with the pid and host of the
+//! bulker process that started it, and each run registers its container (a
+//! `--cidfile` under `containers/` in the cache) and reaps the registrations
+//! of dead runs before starting its own. `bulker clean --containers` removes
+//! labelled containers whose process is gone.
+//!
+//! A pid that has since been reused keeps its container alive; cleanup errs
+//! towards leaving a container running.
+
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+
+/// Label carrying the pid of the bulker process that started the container.
+pub(crate) const PID_LABEL: &str = "bulker.pid";
+/// Label carrying the host that bulker process ran on.
+pub(crate) const HOST_LABEL: &str = "bulker.host";
+
+/// This machine's hostname, as recorded in `bulker.host` labels.
+pub fn hostname() -> String {
+    nix::unistd::gethostname()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .unwrap_or_default()
+}
+
+/// Whether `pid` is a running process (one owned by another user counts).
+pub(crate) fn pid_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    match nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None) {
+        Ok(()) => true,
+        Err(e) => e == nix::errno::Errno::EPERM,
+    }
+}
+
+fn registrations_dir() -> PathBuf {
+    crate::manifest_cache::cache_root_dir().join("containers")
+}
+
+/// Register the container this process is about to start: returns the path
+/// to pass as `--cidfile`, named after this process's pid.
+pub fn register() -> Result<PathBuf> {
+    let dir = registrations_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.cid", std::process::id()));
+    // Left by an earlier process with the same pid; docker refuses an existing cidfile
+    let _ = std::fs::remove_file(&path);
+    Ok(path)
+}
+
+/// Drop a registration once its run has finished.
+pub fn unregister(path: &Path) {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => log::debug!("Failed to remove {}: {}", path.display(), e),
+        _ => {}
+    }
+}
+
+/// Registrations in `dir` whose bulker process is gone: (file, container id).
+/// The id is empty if docker never got as far as creating the container.
+fn dead_registrations(dir: &Path) -> Vec<(PathBuf, String)> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let pid = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".cid"))
+                .and_then(|n| n.parse::<i32>().ok());
+            pid.is_some_and(|pid| !pid_alive(pid))
+        })
+        .map(|path| {
+            let id = std::fs::read_to_string(&path).unwrap_or_default().trim().to_string();
+            (path, id)
+        })
+        .collect()
+}
+
+/// Remove the containers of registered runs that died, and their
+/// registrations. Returns the number of containers removed.
+pub fn reap_registered(engine_path: &str) -> usize {
+    let mut removed = 0;
+    for (path, id) in dead_registrations(&registrations_dir()) {
+        if !id.is_empty() {
+            match remove_containers(engine_path, std::slice::from_ref(&id)) {
+                Ok(()) => {
+                    log::info!("Removed container {} left by an earlier bulker run", id);
+                    removed += 1;
+                }
+                Err(e) => {
+                    // Keep the registration to retry next time
+                    log::warn!("{:#}", e);
+                    continue;
+                }
+            }
+        }
+        unregister(&path);
+    }
+    removed
+}
+
+/// An orphaned container: one whose bulker process is gone.
+#[derive(Debug, PartialEq)]
+pub struct Orphan {
+    pub id: String,
+    pub command: String,
+    pub pid: i32,
+}
+
+/// Orphans among `ps` rows of `ID\tcommand\tpid\thost`: containers started on
+/// `host` by a process `alive` says is gone. Containers from other hosts
+/// sharing the engine are left alone.
+fn select_orphans(rows: &str, host: &str, alive: impl Fn(i32) -> bool) -> Vec<Orphan> {
+    rows.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let id = fields.next()?.trim();
+            let command = fields.next()?.trim();
+            let pid = fields.next()?.trim().parse::<i32>().ok()?;
+            let row_host = fields.next().unwrap_or("").trim();
+            (!id.is_empty() && row_host == host && !alive(pid))
+                .then(|| Orphan { id: id.to_string(), command: command.to_string(), pid })
+        })
+        .collect()
+}
+
+/// Containers labelled by bulker on this host whose bulker process is gone,
+/// running or not.
+pub fn list_orphans(engine_path: &str) -> Result<Vec<Orphan>> {
+    let format = format!(
+        "{{{{.ID}}}}\t{{{{.Label \"bulker.command\"}}}}\t{{{{.Label \"{}\"}}}}\t{{{{.Label \"{}\"}}}}",
+        PID_LABEL, HOST_LABEL
+    );
+    let output = std::process::Command::new(engine_path)
+        .args(["ps", "--all", "--filter", &format!("label={}", PID_LABEL), "--format", &format])
+        .output()
+        .with_context(|| format!("Failed to run '{} ps'", engine_path))?;
+    if !output.status.success() {
+        bail!("'{} ps' failed: {}", engine_path, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(select_orphans(&String::from_utf8_lossy(&output.stdout), &hostname(), pid_alive))
+}
+
+/// Force-remove containers (stopping running ones).
+pub fn remove_containers(engine_path: &str, ids: &[String]) -> Result<()> {
+    let output = std::process::Command::new(engine_path)
+        .args(["rm", "--force"])
+        .args(ids)
+        .output()
+        .with_context(|| format!("Failed to run '{} rm'", engine_path))?;
+    if !output.status.success() {
+        bail!("Failed to remove containers {}: {}", ids.join(", "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_orphans() {
+        let rows = "abc\tsamtools\t100\tnode1\n\
+                    def\tbowtie2\t200\tnode1\n\
+                    ghi\tsamtools\t100\tnode2\n\
+                    jkl\tcowsay\tnotapid\tnode1\n";
+        let orphans = select_orphans(rows, "node1", |pid| pid == 200);
+        assert_eq!(orphans, vec![Orphan { id: "abc".to_string(), command: "samtools".to_string(), pid: 100 }]);
+    }
+
+    #[test]
+    fn test_dead_registrations() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let live = tmpdir.path().join(format!("{}.cid", std::process::id()));
+        std::fs::write(&live, "live\n").unwrap();
+        // pid_max is well below i32::MAX, so this process never exists
+        let dead = tmpdir.path().join(format!("{}.cid", i32::MAX));
+        std::fs::write(&dead, "c0ffee\n").unwrap();
+        std::fs::write(tmpdir.path().join("notes.txt"), "").unwrap();
+        assert_eq!(dead_registrations(tmpdir.path()), vec![(dead, "c0ffee".to_string())]);
+    }
+}
@@ -0,0 +1,233 @@
+//! CPU binding for container commands (`cpu_binding` config key and command
+//! field). A docker container doesn't inherit the CPU affinity of the
+//! process that starts it, so `taskset`/`numactl` around a command or a
+//! SLURM job's core allocation would be lost; bulker passes the binding to
+//! docker as `--cpuset-cpus`/`--cpuset-mems` instead. apptainer commands are
+//! host processes: they inherit affinity, and explicit bindings wrap the
+//! `apptainer exec` in `taskset` or `numactl`.
+
+use anyhow::{Result, bail};
+use std::collections::BTreeSet;
+
+/// Set by SLURM inside a job: the number of CPUs allocated on this node.
+const SLURM_CPUS_ENV: &str = "SLURM_CPUS_ON_NODE";
+
+/// How a command's CPUs are bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuBinding {
+    /// Inside a SLURM job, like `Inherit`; otherwise nothing.
+    Auto,
+    /// No binding (default).
+    None,
+    /// The CPUs this process may run on, as set by taskset, numactl or the
+    /// batch system.
+    Inherit,
+    /// A CPU list such as `0-3,8`.
+    Cpus(String),
+    /// `numactl` options such as `--cpunodebind=0 --membind=0`.
+    Numactl(Vec<String>),
+}
+
+impl CpuBinding {
+    /// Parse `auto`, `none`, `inherit`, a CPU list, or `numactl <options>`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        Ok(match value {
+            "auto" => CpuBinding::Auto,
+            "" | "none" => CpuBinding::None,
+            "inherit" => CpuBinding::Inherit,
+            _ if value == "numactl" || value.starts_with("numactl ") => {
+                let args: Vec<String> = value["numactl".len()..].split_whitespace().map(str::to_string).collect();
+                if args.is_empty() {
+                    bail!("cpu_binding 'numactl' needs options, e.g. 'numactl --cpunodebind=0 --membind=0'");
+                }
+                CpuBinding::Numactl(args)
+            }
+            _ if parse_cpu_list(value).is_some() => CpuBinding::Cpus(value.to_string()),
+            _ => bail!(
+                "Invalid cpu_binding '{}'. Use auto, none, inherit, a CPU list such as 0-3,8, or 'numactl <options>'.",
+                value
+            ),
+        })
+    }
+}
+
+/// CPUs in a list such as `0-3,8`. None if the list is empty or malformed.
+pub(crate) fn parse_cpu_list(list: &str) -> Option<BTreeSet<usize>> {
+    let mut cpus = BTreeSet::new();
+    for part in list.trim().split(',') {
+        match part.split_once('-') {
+            Some((lo, hi)) => {
+                let (lo, hi) = (lo.trim().parse::<usize>().ok()?, hi.trim().parse::<usize>().ok()?);
+                if lo > hi {
+                    return None;
+                }
+                cpus.extend(lo..=hi);
+            }
+            None => {
+                cpus.insert(part.trim().parse().ok()?);
+            }
+        }
+    }
+    Some(cpus)
+}
+
+/// `cpus` as a compact list, e.g. `0-3,8`.
+pub(crate) fn format_cpu_list(cpus: &BTreeSet<usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, hi)) if *hi + 1 == cpu => *hi = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(lo, hi)| if lo == hi { lo.to_string() } else { format!("{}-{}", lo, hi) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// CPUs this process may run on. None where affinity isn't available.
+#[cfg(target_os = "linux")]
+fn affinity() -> Option<BTreeSet<usize>> {
+    let set = nix::sched::sched_getaffinity(nix::unistd::Pid::from_raw(0)).ok()?;
+    Some((0..nix::sched::CpuSet::count()).filter(|&cpu| set.is_set(cpu).unwrap_or(false)).collect())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn affinity() -> Option<BTreeSet<usize>> {
+    None
+}
+
+/// CPUs of a sysfs `cpulist` file (`online`, `node<N>/cpulist`).
+fn sysfs_cpu_list(path: &str) -> Option<BTreeSet<usize>> {
+    parse_cpu_list(&std::fs::read_to_string(path).ok()?)
+}
+
+/// `--cpuset-cpus` for the CPUs this process is bound to, or under SLURM
+/// without a binding, `--cpus` for the job's CPU count.
+fn inherited_docker_args() -> Vec<String> {
+    let online = sysfs_cpu_list("/sys/devices/system/cpu/online");
+    match (affinity(), online) {
+        (Some(cpus), Some(online)) if !cpus.is_empty() && cpus.len() < online.len() => {
+            vec![format!("--cpuset-cpus={}", format_cpu_list(&cpus))]
+        }
+        _ => match std::env::var(SLURM_CPUS_ENV).ok().and_then(|n| n.trim().parse::<usize>().ok()) {
+            Some(n) if n > 0 => vec![format!("--cpus={}", n)],
+            _ => Vec::new(),
+        },
+    }
+}
+
+/// docker cpuset options for `numactl` options, reading node CPU lists with
+/// `node_cpus`. Options with no docker equivalent are returned separately.
+fn translate_numactl(args: &[String], node_cpus: impl Fn(usize) -> Option<BTreeSet<usize>>) -> (Vec<String>, Vec<String>) {
+    let mut docker = Vec::new();
+    let mut ignored = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let takes_value = matches!(name, "-C" | "--physcpubind" | "-m" | "--membind" | "-N" | "--cpunodebind");
+        let value = if takes_value { value.or_else(|| iter.next().cloned()) } else { value };
+        match (name, value) {
+            ("-C" | "--physcpubind", Some(cpus)) => docker.push(format!("--cpuset-cpus={}", cpus)),
+            ("-m" | "--membind", Some(nodes)) => docker.push(format!("--cpuset-mems={}", nodes)),
+            ("-N" | "--cpunodebind", Some(nodes)) => {
+                let cpus: Option<BTreeSet<usize>> = parse_cpu_list(&nodes)
+                    .and_then(|nodes| nodes.into_iter().map(&node_cpus).collect::<Option<Vec<_>>>())
+                    .map(|sets| sets.into_iter().flatten().collect());
+                match cpus {
+                    Some(cpus) if !cpus.is_empty() => docker.push(format!("--cpuset-cpus={}", format_cpu_list(&cpus))),
+                    _ => ignored.push(arg.clone()),
+                }
+            }
+            _ => ignored.push(arg.clone()),
+        }
+    }
+    (docker, ignored)
+}
+
+/// Engine options that apply `binding` to a docker or podman container.
+pub fn docker_args(binding: &CpuBinding, command: &str) -> Vec<String> {
+    match binding {
+        CpuBinding::None => Vec::new(),
+        CpuBinding::Auto if std::env::var_os(SLURM_CPUS_ENV).is_none() => Vec::new(),
+        CpuBinding::Auto | CpuBinding::Inherit => inherited_docker_args(),
+        CpuBinding::Cpus(list) => vec![format!("--cpuset-cpus={}", list)],
+        CpuBinding::Numactl(args) => {
+            let (docker, ignored) = translate_numactl(args, |node| {
+                sysfs_cpu_list(&format!("/sys/devices/system/node/node{}/cpulist", node))
+            });
+            if !ignored.is_empty() {
+                log::warn!("'{}': ignoring numactl options docker cannot apply: {}", command, ignored.join(" "));
+            }
+            docker
+        }
+    }
+}
+
+/// Program and options to run the `apptainer exec` under for `binding`,
+/// empty if it needs none (the process already inherits its affinity).
+pub fn apptainer_wrapper(binding: &CpuBinding, command: &str) -> Vec<String> {
+    let wrapper: Vec<String> = match binding {
+        CpuBinding::Cpus(list) => vec!["taskset".to_string(), "-c".to_string(), list.clone()],
+        CpuBinding::Numactl(args) => std::iter::once("numactl".to_string()).chain(args.iter().cloned()).collect(),
+        _ => return Vec::new(),
+    };
+    if crate::config::resolve_engine_path(&wrapper[0]).is_none() {
+        log::warn!("'{}': {} is not on PATH; running without cpu_binding", command, wrapper[0]);
+        return Vec::new();
+    }
+    wrapper
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CpuBinding::parse("").unwrap(), CpuBinding::None);
+        assert_eq!(CpuBinding::parse("auto").unwrap(), CpuBinding::Auto);
+        assert_eq!(CpuBinding::parse("inherit").unwrap(), CpuBinding::Inherit);
+        assert_eq!(CpuBinding::parse("0-3,8").unwrap(), CpuBinding::Cpus("0-3,8".to_string()));
+        assert_eq!(
+            CpuBinding::parse("numactl --cpunodebind=0 --membind=0").unwrap(),
+            CpuBinding::Numactl(vec!["--cpunodebind=0".to_string(), "--membind=0".to_string()])
+        );
+        assert!(CpuBinding::parse("numactl").is_err());
+        assert!(CpuBinding::parse("3-1").is_err());
+        assert!(CpuBinding::parse("taskset").is_err());
+    }
+
+    #[test]
+    fn test_cpu_list_round_trip() {
+        let cpus = parse_cpu_list("8, 0-3,2").unwrap();
+        assert_eq!(cpus.iter().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3, 8]);
+        assert_eq!(format_cpu_list(&cpus), "0-3,8");
+        assert_eq!(format_cpu_list(&parse_cpu_list("0,2,4-5").unwrap()), "0,2,4-5");
+        assert!(parse_cpu_list("0-").is_none());
+    }
+
+    #[test]
+    fn test_translate_numactl() {
+        let nodes = |node| match node {
+            0 => parse_cpu_list("0-7"),
+            1 => parse_cpu_list("8-15"),
+            _ => None,
+        };
+        let args: Vec<String> = ["--cpunodebind=1", "-m", "1", "--localalloc"].iter().map(|s| s.to_string()).collect();
+        let (docker, ignored) = translate_numactl(&args, nodes);
+        assert_eq!(docker, vec!["--cpuset-cpus=8-15", "--cpuset-mems=1"]);
+        assert_eq!(ignored, vec!["--localalloc"]);
+
+        let args: Vec<String> = ["-N", "0,1", "--physcpubind=2"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(translate_numactl(&args, nodes).0, vec!["--cpuset-cpus=0-15", "--cpuset-cpus=2"]);
+        // Unknown nodes can't be translated
+        assert_eq!(translate_numactl(&["--cpunodebind=5".to_string()], nodes).1, vec!["--cpunodebind=5"]);
+    }
+}
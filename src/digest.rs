@@ -9,7 +9,10 @@
 
 use serde_json::Value;
 use sha2::{Digest, Sha512};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use crate::manifest::Manifest;
 
@@ -148,12 +151,11 @@ pub fn crate_image_digest(
 // OCI digest resolution
 // ---------------------------------------------------------------------------
 
-/// Parse a docker image reference into (registry, repository, tag).
+/// Parse a docker image reference into (registry, repository, reference),
+/// where the reference is the digest of a pinned image, else the tag.
 fn parse_image_ref(image: &str) -> (String, String, String) {
-    let (name_part, tag) = match image.rfind(':') {
-        Some(idx) => (&image[..idx], &image[idx + 1..]),
-        None => (image, "latest"),
-    };
+    let (name_part, tag, digest) = crate::manifest::split_image_tag_digest(image);
+    let tag = digest.or(tag).unwrap_or("latest");
 
     // Determine registry vs repository
     let (registry, repo) = if let Some(idx) = name_part.find('/') {
@@ -176,47 +178,264 @@ fn parse_image_ref(image: &str) -> (String, String, String) {
     (registry, repo, tag.to_string())
 }
 
+/// Registry requests in flight at once when resolving a manifest's images.
+const DIGEST_WORKERS: usize = 4;
+
+/// Retries of a rate-limited (429) or unavailable (503) digest request.
+const MAX_DIGEST_RETRIES: u32 = 3;
+
+/// Longest wait between retries, whatever `Retry-After` asks for.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How long a resolved digest is reused when `digest_cache_ttl` is unset.
+pub const DEFAULT_DIGEST_CACHE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Reuse window for cached tag → digest lookups (`digest_cache_ttl`), set by
+/// `load_config`. Zero disables the cache.
+static DIGEST_CACHE_TTL: std::sync::RwLock<Duration> = std::sync::RwLock::new(DEFAULT_DIGEST_CACHE_TTL);
+
+pub fn set_digest_cache_ttl(ttl: Duration) {
+    *DIGEST_CACHE_TTL.write().unwrap_or_else(|e| e.into_inner()) = ttl;
+}
+
 /// Attempt to resolve OCI content digests for all images in a manifest.
 /// Returns a map of docker_image tag → sha256:... digest.
-/// Best-effort: returns None for images that can't be resolved.
+/// Best-effort: images that can't be resolved are left out.
 pub fn resolve_oci_digests(manifest: &Manifest) -> HashMap<String, String> {
-    let mut result = HashMap::new();
-
+    let mut images: Vec<String> = Vec::new();
     for cmd in &manifest.manifest.commands {
-        if result.contains_key(&cmd.docker_image) {
-            continue;
+        if !images.contains(&cmd.docker_image) {
+            images.push(cmd.docker_image.clone());
         }
-        match resolve_single_oci_digest(&cmd.docker_image) {
-            Some(digest) => {
-                result.insert(cmd.docker_image.clone(), digest);
-            }
-            None => {
-                log::debug!("Could not resolve OCI digest for: {}", cmd.docker_image);
-            }
+    }
+    resolve_concurrently(&images, resolve_single_oci_digest)
+}
+
+/// Run `resolve` over `images` on up to `DIGEST_WORKERS` threads.
+fn resolve_concurrently(images: &[String], resolve: impl Fn(&str) -> Option<String> + Sync) -> HashMap<String, String> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(HashMap::new());
+    std::thread::scope(|s| {
+        for _ in 0..DIGEST_WORKERS.min(images.len()) {
+            s.spawn(|| {
+                while let Some(image) = images.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    match resolve(image) {
+                        Some(digest) => {
+                            results.lock().unwrap_or_else(|e| e.into_inner()).insert(image.clone(), digest);
+                        }
+                        None => log::debug!("Could not resolve OCI digest for: {}", image),
+                    }
+                }
+            });
         }
+    });
+    results.into_inner().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sidecar holding the last resolved digest of `image`; its mtime is the
+/// resolution time.
+fn digest_cache_path(image: &str) -> std::path::PathBuf {
+    crate::manifest_cache::cache_root_dir().join("oci-digests").join(sha512t24u(image))
+}
+
+fn read_cached_digest(path: &std::path::Path, ttl: Duration) -> Option<String> {
+    let age = std::fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+    if age >= ttl {
+        return None;
     }
+    let digest = std::fs::read_to_string(path).ok()?.trim().to_string();
+    digest.starts_with("sha256:").then_some(digest)
+}
 
-    result
+fn write_cached_digest(path: &std::path::Path, digest: &str) {
+    let written = path
+        .parent()
+        .map(crate::cache_permissions::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| crate::cache_permissions::write(path, digest));
+    if let Err(e) = written {
+        log::debug!("Could not cache digest at {}: {}", path.display(), e);
+    }
 }
 
-/// Resolve a single image tag to its OCI content digest via the registry API.
-fn resolve_single_oci_digest(image: &str) -> Option<String> {
-    let (registry, repo, tag) = parse_image_ref(image);
-    let url = format!("https://{}/v2/{}/manifests/{}", registry, repo, tag);
+/// Delay before retrying a failed registry request, or None if `status` is
+/// not worth retrying. Honors `Retry-After` (seconds), else backs off
+/// exponentially from one second.
+fn retry_delay(status: u16, retry_after: Option<&str>, attempt: u32) -> Option<Duration> {
+    if status != 429 && status != 503 {
+        return None;
+    }
+    let delay = retry_after
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| Duration::from_secs(1 << attempt));
+    Some(delay.min(MAX_RETRY_DELAY))
+}
 
-    let resp = ureq::get(&url)
-        .set(
-            "Accept",
-            "application/vnd.docker.distribution.manifest.v2+json, \
-             application/vnd.oci.image.manifest.v1+json, \
-             application/vnd.oci.image.index.v1+json, \
-             application/vnd.docker.distribution.manifest.list.v2+json",
-        )
+/// Credential references from the `registry_auth` config key, registered by `load_config`.
+static REGISTRY_AUTH: std::sync::RwLock<BTreeMap<String, String>> = std::sync::RwLock::new(BTreeMap::new());
+
+/// Set the registry credential references. Called whenever a config is loaded.
+pub fn set_registry_auth(auth: BTreeMap<String, String>) {
+    *REGISTRY_AUTH.write().unwrap_or_else(|e| e.into_inner()) = auth;
+}
+
+/// Credentials configured for `registry`: the username, if one was recorded,
+/// and the token or password. A reference that cannot be resolved is reported
+/// and the request goes out unauthenticated.
+fn registry_credentials(registry: &str) -> Option<(Option<String>, String)> {
+    let auth = REGISTRY_AUTH.read().unwrap_or_else(|e| e.into_inner());
+    let reference = auth.get(&crate::config::normalize_registry(registry))?;
+    match crate::config::resolve_credential(reference) {
+        Ok(secret) => Some((crate::config::credential_user(reference).map(String::from), secret)),
+        Err(e) => {
+            log::warn!("Ignoring credentials for {}: {:#}", registry, e);
+            None
+        }
+    }
+}
+
+/// Parameters of a `WWW-Authenticate` challenge with the given `scheme`
+/// (`Bearer realm="...",service="...",scope="..."`), lowercased keys.
+fn parse_challenge(header: &str, scheme: &str) -> Option<BTreeMap<String, String>> {
+    let (found, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if !found.eq_ignore_ascii_case(scheme) {
+        return None;
+    }
+    let mut out = BTreeMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, tail) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        out.insert(key.trim().to_ascii_lowercase(), value.to_string());
+        rest = tail.trim_start_matches([',', ' ']);
+    }
+    Some(out)
+}
+
+fn basic_auth(user: &str, secret: &str) -> String {
+    use base64_url::base64::Engine;
+    format!("Basic {}", base64_url::base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, secret)))
+}
+
+/// The token request for a Bearer challenge. With a username the token is
+/// exchanged with basic auth, but only over https (or to localhost): the
+/// realm is whatever the registry's 401 names.
+fn token_request(challenge: &BTreeMap<String, String>, credentials: Option<&(Option<String>, String)>) -> Option<ureq::Request> {
+    let realm = challenge.get("realm")?;
+    let mut req = ureq::get(realm);
+    for key in ["service", "scope"] {
+        if let Some(value) = challenge.get(key) {
+            req = req.query(key, value);
+        }
+    }
+    if let Some((Some(user), secret)) = credentials {
+        if crate::download::is_cleartext(realm) {
+            log::warn!("Not sending registry credentials to {}: it is not https", realm);
+        } else {
+            req = req.set("Authorization", &basic_auth(user, secret));
+        }
+    }
+    Some(req)
+}
+
+/// Fetch a registry token from a Bearer challenge's realm (the Docker registry
+/// token protocol). Without credentials the token is anonymous, which public
+/// images on Docker Hub and ghcr.io still need.
+fn fetch_registry_token(challenge: &BTreeMap<String, String>, credentials: Option<&(Option<String>, String)>) -> Option<String> {
+    let body = token_request(challenge, credentials)?
         .call()
+        .inspect_err(|e| log::debug!("Registry token request failed: {}", e))
+        .ok()?
+        .into_string()
         .ok()?;
+    let body: Value = serde_json::from_str(&body).ok()?;
+    ["token", "access_token"]
+        .iter()
+        .find_map(|k| body.get(*k).and_then(Value::as_str))
+        .map(String::from)
+}
+
+/// The `Authorization` header answering a 401's `WWW-Authenticate` challenge.
+fn answer_challenge(header: &str, credentials: Option<&(Option<String>, String)>) -> Option<String> {
+    if let Some(challenge) = parse_challenge(header, "Bearer") {
+        return fetch_registry_token(&challenge, credentials).map(|token| format!("Bearer {}", token));
+    }
+    match (parse_challenge(header, "Basic"), credentials) {
+        (Some(_), Some((Some(user), secret))) => Some(basic_auth(user, secret)),
+        _ => None,
+    }
+}
+
+/// Resolve a single image tag to its OCI content digest, from the digest
+/// cache if a lookup within `digest_cache_ttl` stored it, else via the
+/// registry API.
+fn resolve_single_oci_digest(image: &str) -> Option<String> {
+    // A pinned image is its own digest; no registry lookup needed
+    if let (_, _, Some(digest)) = crate::manifest::split_image_tag_digest(image) {
+        return Some(digest.to_string());
+    }
+    let ttl = *DIGEST_CACHE_TTL.read().unwrap_or_else(|e| e.into_inner());
+    let cache_path = digest_cache_path(image);
+    // A zero TTL never finds a fresh entry
+    if let Some(digest) = read_cached_digest(&cache_path, ttl) {
+        return Some(digest);
+    }
+    let digest = fetch_oci_digest(image)?;
+    if !ttl.is_zero() {
+        write_cached_digest(&cache_path, &digest);
+    }
+    Some(digest)
+}
+
+/// Ask the image's registry for its content digest, retrying when rate-limited.
+fn fetch_oci_digest(image: &str) -> Option<String> {
+    let (registry, repo, tag) = parse_image_ref(image);
+    let url = format!("https://{}/v2/{}/manifests/{}", registry, repo, tag);
+    fetch_manifest_digest(&url, &registry, registry_credentials(&registry))
+}
 
-    resp.header("Docker-Content-Digest")
-        .map(|s| s.to_string())
+/// Content digest of the manifest at `url`. A token recorded without a
+/// username is sent as a pre-issued bearer token; otherwise the registry's
+/// `WWW-Authenticate` challenge is answered once (token exchange or basic auth).
+fn fetch_manifest_digest(url: &str, registry: &str, credentials: Option<(Option<String>, String)>) -> Option<String> {
+    let mut authorization = match credentials {
+        Some((None, ref token)) => Some(format!("Bearer {}", token)),
+        _ => None,
+    };
+    let mut challenged = false;
+    let mut attempt = 0;
+    loop {
+        let mut req = ureq::get(url);
+        if let Some(ref authorization) = authorization {
+            req = req.set("Authorization", authorization);
+        }
+        let result = req
+            .set(
+                "Accept",
+                "application/vnd.docker.distribution.manifest.v2+json, \
+                 application/vnd.oci.image.manifest.v1+json, \
+                 application/vnd.oci.image.index.v1+json, \
+                 application/vnd.docker.distribution.manifest.list.v2+json",
+            )
+            .call();
+        match result {
+            Ok(resp) => return resp.header("Docker-Content-Digest").map(|s| s.to_string()),
+            Err(ureq::Error::Status(401, resp)) if !challenged => {
+                challenged = true;
+                authorization = Some(answer_challenge(resp.header("WWW-Authenticate")?, credentials.as_ref())?);
+            }
+            Err(ureq::Error::Status(status, resp)) if attempt < MAX_DIGEST_RETRIES => {
+                let delay = retry_delay(status, resp.header("Retry-After"), attempt)?;
+                log::debug!("{} answered {} for {}; retrying in {:?}", registry, status, url, delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(_) => return None,
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -403,7 +622,6 @@ mod tests {
         Manifest {
             manifest: ManifestInner {
                 name: Some("test".to_string()),
-                version: None,
                 commands: commands
                     .into_iter()
                     .map(|(cmd, img)| PackageCommand {
@@ -412,9 +630,9 @@ mod tests {
                         ..Default::default()
                     })
                     .collect(),
-                host_commands: vec![],
-                imports: vec![],
+                ..Default::default()
             },
+            ..Default::default()
         }
     }
 
@@ -525,6 +743,16 @@ mod tests {
         assert_eq!(tag, "latest");
     }
 
+    #[test]
+    fn test_pinned_image_resolves_to_its_digest() {
+        let digest = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let image = format!("quay.io/biocontainers/samtools:1.17@{}", digest);
+        let (reg, repo, reference) = parse_image_ref(&image);
+        assert_eq!((reg.as_str(), repo.as_str(), reference.as_str()), ("quay.io", "biocontainers/samtools", digest));
+        // No registry request for a pinned image
+        assert_eq!(resolve_single_oci_digest(&image).as_deref(), Some(digest));
+    }
+
     #[test]
     fn test_parse_image_ref_org_no_registry() {
         let (reg, repo, tag) = parse_image_ref("nsheff/cowsay:latest");
@@ -532,4 +760,114 @@ mod tests {
         assert_eq!(repo, "nsheff/cowsay");
         assert_eq!(tag, "latest");
     }
+
+    #[test]
+    fn test_resolve_concurrently_resolves_each_image_once() {
+        let images: Vec<String> = (0..10).map(|i| format!("img{}:1", i)).collect();
+        let calls = AtomicUsize::new(0);
+        let result = resolve_concurrently(&images, |image| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            // Odd images are unresolvable
+            let n: usize = image[3..image.len() - 2].parse().unwrap();
+            n.is_multiple_of(2).then(|| format!("sha256:{}", n))
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 10);
+        assert_eq!(result.len(), 5);
+        assert_eq!(result["img4:1"], "sha256:4");
+        assert!(resolve_concurrently(&[], |_| None).is_empty());
+    }
+
+    #[test]
+    fn test_cached_digest_expires_after_ttl() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("oci-digests/abc");
+        let digest = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        write_cached_digest(&path, digest);
+        assert_eq!(read_cached_digest(&path, Duration::from_secs(3600)).as_deref(), Some(digest));
+        assert_eq!(read_cached_digest(&path, Duration::ZERO), None);
+        std::fs::write(&path, "garbage").unwrap();
+        assert_eq!(read_cached_digest(&path, Duration::from_secs(3600)), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(404, None, 0), None);
+        assert_eq!(retry_delay(429, Some("7"), 0), Some(Duration::from_secs(7)));
+        assert_eq!(retry_delay(429, Some("3600"), 0), Some(MAX_RETRY_DELAY));
+        // Without Retry-After (or with an HTTP date), back off exponentially
+        assert_eq!(retry_delay(503, None, 2), Some(Duration::from_secs(4)));
+        assert_eq!(retry_delay(429, Some("Wed, 21 Oct 2026 07:28:00 GMT"), 1), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let header = r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:org/tool:pull""#;
+        let challenge = parse_challenge(header, "Bearer").unwrap();
+        assert_eq!(challenge["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge["service"], "registry.docker.io");
+        assert_eq!(challenge["scope"], "repository:org/tool:pull");
+        assert!(parse_challenge(header, "Basic").is_none());
+        assert_eq!(parse_challenge(r#"Basic realm="Registry""#, "basic").unwrap()["realm"], "Registry");
+    }
+
+    #[test]
+    fn test_token_request_keeps_credentials_off_cleartext_realms() {
+        let credentials = Some((Some("me".to_string()), "secret".to_string()));
+        let challenge = |realm: &str| parse_challenge(&format!(r#"Bearer realm="{}",service="reg""#, realm), "Bearer").unwrap();
+
+        let req = token_request(&challenge("http://auth.example.org/token"), credentials.as_ref()).unwrap();
+        assert_eq!(req.header("Authorization"), None);
+        let req = token_request(&challenge("https://auth.example.org/token"), credentials.as_ref()).unwrap();
+        assert_eq!(req.header("Authorization"), Some("Basic bWU6c2VjcmV0"));
+        let req = token_request(&challenge("http://127.0.0.1:5000/token"), credentials.as_ref()).unwrap();
+        assert!(req.header("Authorization").is_some());
+    }
+
+    #[test]
+    fn test_fetch_manifest_digest_exchanges_token() {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let realm = format!("{}/token", base);
+        // Manifest request -> 401 challenge, token request, manifest request with the token
+        let server = std::thread::spawn(move || {
+            let mut seen = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if line.starts_with("GET ") || line.starts_with("Authorization: ") {
+                        request.push_str(&line);
+                        request.push('\n');
+                    }
+                }
+                let response = if request.starts_with("GET /token") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 17\r\n\r\n{\"token\":\"t0k3n\"}".to_string()
+                } else if request.contains("Authorization: Bearer t0k3n") {
+                    "HTTP/1.1 200 OK\r\nDocker-Content-Digest: sha256:abc\r\nContent-Length: 0\r\n\r\n".to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Bearer realm=\"{}\",service=\"reg\",scope=\"repository:org/tool:pull\"\r\nContent-Length: 0\r\n\r\n",
+                        realm
+                    )
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                seen.push(request);
+            }
+            seen
+        });
+
+        let credentials = Some((Some("me".to_string()), "secret".to_string()));
+        let digest = fetch_manifest_digest(&format!("{}/v2/org/tool/manifests/1", base), "reg", credentials);
+        assert_eq!(digest.as_deref(), Some("sha256:abc"));
+        let seen = server.join().unwrap();
+        // "me:secret" in basic auth, sent only to the token endpoint
+        assert!(seen[0].starts_with("GET /v2/org/tool/manifests/1") && !seen[0].contains("Authorization"), "{:?}", seen);
+        assert!(seen[1].starts_with("GET /token?service=reg&scope=repository%3Aorg%2Ftool%3Apull"), "{:?}", seen);
+        assert!(seen[1].contains("Authorization: Basic bWU6c2VjcmV0"), "{:?}", seen);
+    }
 }
@@ -0,0 +1,423 @@
+//! HTTP downloads shared by registry manifest fetches and larger transfers.
+//!
+//! A download is written to `<cache>/downloads/<key>.part` first. When the
+//! connection drops, the next attempt (in this run or a later one) asks for
+//! the rest with a `Range` request, guarded by `If-Range` so a file that
+//! changed on the server starts over. A server that sent neither `ETag` nor
+//! `Last-Modified` gives nothing to guard with, so its downloads restart. `download_rate_limit` caps the
+//! bandwidth, and a checksum, given by the caller or as a `#sha256=<hex>`
+//! URL fragment, is verified once the file is complete.
+
+use anyhow::{Context, Result, anyhow, bail};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Attempts per download, each resuming where the last one stopped.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Bytes per second downloads may use; None for no limit.
+static RATE_LIMIT: RwLock<Option<u64>> = RwLock::new(None);
+
+/// Set the bandwidth limit, from the loaded config.
+pub fn set_rate_limit(bytes_per_sec: Option<u64>) {
+    *RATE_LIMIT.write().unwrap_or_else(|e| e.into_inner()) = bytes_per_sec.filter(|&r| r > 0);
+}
+
+fn rate_limit() -> Option<u64> {
+    *RATE_LIMIT.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Split a `#sha256=<hex>` fragment off `url`.
+pub fn split_checksum(url: &str) -> (&str, Option<&str>) {
+    match url.split_once("#sha256=") {
+        Some((url, hex)) => (url, Some(hex)),
+        None => (url, None),
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Where a download of `url` collects its bytes until it is complete.
+fn partial_path(url: &str) -> PathBuf {
+    let key = &sha256_hex(url.as_bytes())[..16];
+    crate::manifest_cache::cache_root_dir().join("downloads").join(format!("{}.part", key))
+}
+
+/// `ETag` or `Last-Modified` of the server's copy, kept next to the partial
+/// file for `If-Range`.
+fn validator_path(part: &Path) -> PathBuf {
+    part.with_extension("part.validator")
+}
+
+/// Copy `reader` to `writer`, sleeping as needed to stay under `limit`
+/// bytes per second. Returns the bytes copied.
+fn copy_throttled(reader: &mut impl Read, writer: &mut impl Write, limit: Option<u64>) -> std::io::Result<u64> {
+    let chunk = limit.map_or(64 * 1024, |l| (l / 8).clamp(1024, 64 * 1024) as usize);
+    let mut buf = vec![0u8; chunk];
+    let start = Instant::now();
+    let mut copied = 0u64;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(copied),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+        if let Some(limit) = limit {
+            let due = Duration::from_secs_f64(copied as f64 / limit as f64);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// Whether a request to `url` would cross the network unencrypted: anything
+/// but `https://`, except plain `http://` to a loopback host.
+pub fn is_cleartext(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return !url.starts_with("https://");
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let host = match host_port.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    !(host.eq_ignore_ascii_case("localhost") || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback()))
+}
+
+/// A server's answer other than success.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub url: String,
+    pub status: u16,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} answered HTTP {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Whether `error` means the server doesn't have the file (HTTP 404, or a
+/// missing local file) or couldn't be reached, rather than that it refused
+/// the request or sent something unusable.
+pub fn is_missing_or_unreachable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<HttpStatusError>() {
+            return e.status == 404;
+        }
+        if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+            return matches!(e.kind(), ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io);
+        }
+        cause.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    })
+}
+
+/// A failed attempt, and whether another one can get further.
+struct Failed {
+    error: anyhow::Error,
+    retry: bool,
+}
+
+impl Failed {
+    fn retry(error: anyhow::Error) -> Self {
+        Failed { error, retry: true }
+    }
+}
+
+/// One request for what `part` is still missing of `url`.
+fn attempt(url: &str, part: &Path) -> std::result::Result<(), Failed> {
+    let mut have = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let validator = std::fs::read_to_string(validator_path(part)).unwrap_or_default();
+    if have > 0 && validator.is_empty() {
+        // Without If-Range a changed file would be spliced onto the old bytes
+        log::debug!("No validator for the partial download of {}; starting over", url);
+        let _ = std::fs::remove_file(part);
+        have = 0;
+    }
+    let mut req = ureq::get(url);
+    if have > 0 {
+        req = req.set("Range", &format!("bytes={}-", have)).set("If-Range", &validator);
+    }
+    let resp = match req.call() {
+        Ok(resp) => resp,
+        // What we have is no prefix of the file any more; start over
+        Err(ureq::Error::Status(416, _)) => {
+            let _ = std::fs::remove_file(part);
+            return Err(Failed::retry(anyhow!("{} rejected the resume request", url)));
+        }
+        Err(ureq::Error::Status(code, _)) => {
+            return Err(Failed {
+                error: HttpStatusError { url: url.to_string(), status: code }.into(),
+                retry: code == 429 || code >= 500,
+            });
+        }
+        Err(e) => {
+            // An unreachable host is offline or a typo, not a flaky link
+            let unreachable = matches!(e.kind(), ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed);
+            return Err(Failed {
+                error: anyhow::Error::new(e).context(format!("Failed to fetch {}", url)),
+                retry: !unreachable,
+            });
+        }
+    };
+
+    let resumed = have > 0 && resp.status() == 206;
+    if have > 0 && !resumed {
+        log::debug!("{} sent the whole file; starting over", url);
+    }
+    let new_validator = resp.header("ETag").or_else(|| resp.header("Last-Modified")).unwrap_or("");
+    let _ = std::fs::write(validator_path(part), new_validator);
+    let expected_len = resp
+        .header("Content-Length")
+        .and_then(|l| l.parse::<u64>().ok())
+        .map(|l| if resumed { l + have } else { l });
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part)
+        .map_err(|e| Failed { error: anyhow::Error::new(e).context(format!("Failed to write {}", part.display())), retry: false })?;
+    let copied = copy_throttled(&mut resp.into_reader(), &mut file, rate_limit());
+    file.flush().map_err(|e| Failed { error: e.into(), retry: false })?;
+    let len = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    match (copied, expected_len) {
+        (Err(e), _) => Err(Failed::retry(anyhow::Error::new(e).context(format!("Download of {} broke off", url)))),
+        (Ok(_), Some(expected)) if len < expected => {
+            Err(Failed::retry(anyhow!("Download of {} ended after {} of {} bytes", url, len, expected)))
+        }
+        (Ok(_), _) => Ok(()),
+    }
+}
+
+/// Download `url` to `dest`, resuming after dropped connections and
+/// verifying `sha256` (or the URL's `#sha256=` fragment) when given.
+pub fn download(url: &str, dest: &Path, sha256: Option<&str>) -> Result<()> {
+    let (url, fragment) = split_checksum(url);
+    let expected = sha256.or(fragment).map(|s| s.trim().to_ascii_lowercase());
+    let part = partial_path(url);
+    if let Some(dir) = part.parent() {
+        crate::cache_permissions::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Concurrent downloads of the same URL (e.g. an array job) share the file
+    let mut lock_path = part.clone().into_os_string();
+    lock_path.push(".lock");
+    let _lock = crate::filelock::FileLock::acquire(Path::new(&lock_path))?;
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        match attempt(url, &part) {
+            Ok(()) => break,
+            Err(failed) if failed.retry && tries < MAX_ATTEMPTS => {
+                let delay = Duration::from_millis(250 << (tries - 1));
+                log::info!("{:#}; resuming in {:?}", failed.error, delay);
+                std::thread::sleep(delay);
+            }
+            Err(failed) => {
+                if failed.retry {
+                    log::info!("The partial download is kept in {} for the next try", part.display());
+                }
+                return Err(failed.error);
+            }
+        }
+    }
+
+    if let Some(expected) = expected {
+        let actual = file_sha256(&part)?;
+        if actual != expected {
+            let _ = std::fs::remove_file(&part);
+            bail!("Checksum mismatch for {}: expected sha256 {}, got {}", url, expected, actual);
+        }
+    }
+    let _ = std::fs::remove_file(validator_path(&part));
+    if std::fs::rename(&part, dest).is_err() {
+        // dest on another filesystem
+        std::fs::copy(&part, dest).with_context(|| format!("Failed to write {}", dest.display()))?;
+        let _ = std::fs::remove_file(&part);
+    }
+    crate::cache_permissions::fix(dest);
+    Ok(())
+}
+
+/// Download a manifest's text from `url`, refusing more than
+/// [`MAX_MANIFEST_BYTES`](crate::manifest::MAX_MANIFEST_BYTES).
+pub fn fetch_string(url: &str) -> Result<String> {
+    let dir = crate::manifest_cache::cache_root_dir().join("downloads");
+    let staged = crate::cache_permissions::create_dir_all(&dir).and_then(|_| tempfile::NamedTempFile::new_in(&dir));
+    let dest = match staged {
+        Ok(dest) => dest,
+        Err(e) => {
+            log::debug!("Cannot stage downloads in {} ({}); fetching {} in memory", dir.display(), e, url);
+            return fetch_string_in_memory(url);
+        }
+    };
+    download(url, dest.path(), None)?;
+    crate::manifest::check_manifest_size(std::fs::metadata(dest.path())?.len(), split_checksum(url).0)?;
+    std::fs::read_to_string(dest.path()).with_context(|| format!("Failed to read response from: {}", split_checksum(url).0))
+}
+
+/// [`fetch_string`] without the downloads directory, e.g. on a read-only
+/// cache: one request, no resume.
+fn fetch_string_in_memory(url: &str) -> Result<String> {
+    let (url, fragment) = split_checksum(url);
+    let resp = match ureq::get(url).call() {
+        Ok(resp) => resp,
+        Err(ureq::Error::Status(code, _)) => return Err(HttpStatusError { url: url.to_string(), status: code }.into()),
+        Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to fetch {}", url))),
+    };
+    let contents = crate::manifest::read_manifest_text(resp.into_reader(), url)?;
+    if let Some(expected) = fragment.map(|s| s.trim().to_ascii_lowercase()) {
+        let actual = sha256_hex(contents.as_bytes());
+        if actual != expected {
+            bail!("Checksum mismatch for {}: expected sha256 {}, got {}", url, expected, actual);
+        }
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    /// Serve `body` once cut off after `cut` bytes, then honoring `Range`,
+    /// with `ETag` unless `etag` is false. Returns the URL and the Range
+    /// headers received.
+    fn flaky_server(body: &'static [u8], cut: usize, etag: bool) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/bundle.tar", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut ranges = Vec::new();
+            for (i, stream) in listener.incoming().take(2).enumerate() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(r) = line.strip_prefix("Range: bytes=") {
+                        range = Some(r.trim_end_matches('-').parse::<usize>().unwrap());
+                    }
+                }
+                let etag = if etag { "ETag: \"v1\"\r\n" } else { "" };
+                let head = match range {
+                    Some(from) => {
+                        ranges.push(format!("bytes={}-", from));
+                        format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n{}\r\n", body.len() - from, etag)
+                    }
+                    None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{}\r\n", body.len(), etag),
+                };
+                stream.write_all(head.as_bytes()).unwrap();
+                let rest = &body[range.unwrap_or(0)..];
+                stream.write_all(if i == 0 { &rest[..cut] } else { rest }).unwrap();
+            }
+            ranges
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_download_resumes_and_verifies() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let body: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let (url, server) = flaky_server(body, 10, true);
+        let dest = tmpdir.path().join("bundle.tar");
+        let checksum = sha256_hex(body);
+        download(&format!("{}#sha256={}", url, checksum), &dest, None).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert_eq!(server.join().unwrap(), vec!["bytes=10-"]);
+        assert!(!partial_path(&url).exists());
+    }
+
+    #[test]
+    fn test_download_without_validator_starts_over() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let body: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let (url, server) = flaky_server(body, 10, false);
+        let dest = tmpdir.path().join("bundle.tar");
+        download(&url, &dest, None).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), body);
+        assert!(server.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fetch_string_without_a_writable_cache() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // A file where the cache directory should be
+        let blocked = tmpdir.path().join("blocked");
+        std::fs::write(&blocked, "").unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", &blocked);
+        let body: &[u8] = b"manifest:\n  name: demo\n";
+        let (url, server) = flaky_server(body, body.len(), true);
+        assert_eq!(fetch_string(&format!("{}#sha256={}", url, sha256_hex(body))).unwrap().as_bytes(), body);
+        // The server keeps waiting for a resume request that never comes
+        drop(server);
+    }
+
+    #[test]
+    fn test_is_cleartext() {
+        assert!(is_cleartext("http://registry.example.org/databio/pepatac_1.0.14.yaml"));
+        assert!(is_cleartext("http://user@10.0.0.5:8080/x.yaml"));
+        assert!(is_cleartext("ftp://registry.example.org/x.yaml"));
+        assert!(!is_cleartext("https://registry.example.org/x.yaml"));
+        assert!(!is_cleartext("http://127.0.0.1:8080/x.yaml"));
+        assert!(!is_cleartext("http://localhost/x.yaml"));
+        assert!(!is_cleartext("http://[::1]:8080/x.yaml"));
+    }
+
+    #[test]
+    fn test_missing_or_unreachable_errors() {
+        let status = |status| anyhow::Error::from(HttpStatusError { url: "http://x/a.yaml".to_string(), status });
+        assert!(is_missing_or_unreachable(&status(404).context("Failed to fetch manifest")));
+        assert!(!is_missing_or_unreachable(&status(401)));
+        assert!(!is_missing_or_unreachable(&status(500)));
+        assert!(!is_missing_or_unreachable(&anyhow!("Failed to parse manifest")));
+
+        let refused = ureq::get("http://127.0.0.1:1/a.yaml").call().unwrap_err();
+        assert!(is_missing_or_unreachable(&anyhow::Error::new(refused).context("Failed to fetch")));
+        let no_file = std::fs::File::open("/nonexistent/a.yaml").unwrap_err();
+        assert!(is_missing_or_unreachable(&anyhow::Error::new(no_file)));
+    }
+
+    #[test]
+    fn test_copy_throttled_respects_limit() {
+        let data = vec![7u8; 4096];
+        let mut out = Vec::new();
+        let start = Instant::now();
+        assert_eq!(copy_throttled(&mut data.as_slice(), &mut out, Some(16 * 1024)).unwrap(), 4096);
+        assert!(start.elapsed() >= Duration::from_millis(200), "{:?}", start.elapsed());
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn test_split_checksum() {
+        assert_eq!(split_checksum("https://x/a.yaml#sha256=abc"), ("https://x/a.yaml", Some("abc")));
+        assert_eq!(split_checksum("https://x/a.yaml"), ("https://x/a.yaml", None));
+    }
+}
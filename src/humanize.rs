@@ -0,0 +1,149 @@
+//! Human-readable sizes and durations for CLI output: `1.4 GiB`, `3m12s`,
+//! `2d ago`. Use these instead of printing raw byte counts or seconds.
+
+use anyhow::{Context, Result, bail};
+use std::time::Duration;
+
+/// Binary size with one decimal: `512 B`, `1.4 KiB`, `2.0 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Compact duration: `4.20ms`, `12.3s`, `3m12s`, `2h05m`, `3d04h`. Sub-second
+/// values keep two decimals so short spans stay comparable.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs == 0 {
+        format!("{:.2}ms", d.as_secs_f64() * 1000.0)
+    } else if secs < 60 {
+        format!("{:.1}s", d.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else if secs < 86400 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}d{:02}h", secs / 86400, secs % 86400 / 3600)
+    }
+}
+
+/// Coarse age of a unix timestamp relative to `now` (e.g. "3d ago"), or
+/// "never" when no timestamp is recorded.
+pub fn format_age(now: u64, ts: Option<u64>) -> String {
+    let Some(ts) = ts else {
+        return "never".to_string();
+    };
+    let secs = now.saturating_sub(ts);
+    if secs < 60 {
+        "just now".to_string()
+    } else if secs < 3600 {
+        format!("{}m ago", secs / 60)
+    } else if secs < 86400 {
+        format!("{}h ago", secs / 3600)
+    } else {
+        format!("{}d ago", secs / 86400)
+    }
+}
+
+/// Parse a duration such as `90d`, `12h`, `2w` or `30m`. A bare number is days.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: u64 = num
+        .parse()
+        .with_context(|| format!("Invalid duration '{}': expected e.g. 90d, 12h, 2w", s))?;
+    let unit_secs = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" | "" => 86400,
+        "w" => 7 * 86400,
+        _ => bail!("Invalid duration unit in '{}': use m, h, d or w", s),
+    };
+    Ok(Duration::from_secs(n * unit_secs))
+}
+
+/// Parse a binary size such as `512K`, `10M` or `1G` (`KiB`, `MB` and
+/// lowercase units work too). A bare number is bytes.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: u64 = num
+        .parse()
+        .with_context(|| format!("Invalid size '{}': expected e.g. 512K, 10M, 1G", s))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let prefix = unit.strip_suffix("IB").or_else(|| unit.strip_suffix('B')).unwrap_or(&unit);
+    let shift = match prefix {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => bail!("Invalid size unit in '{}': use K, M or G", s),
+    };
+    n.checked_mul(1 << shift).with_context(|| format!("Size '{}' is too large", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(100 * 1024 * 1024), "100.0 MiB");
+        assert_eq!(format_size(1_503_238_554), "1.4 GiB");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_micros(4200)), "4.20ms");
+        assert_eq!(format_duration(Duration::from_millis(12_340)), "12.3s");
+        assert_eq!(format_duration(Duration::from_secs(192)), "3m12s");
+        assert_eq!(format_duration(Duration::from_secs(2 * 3600 + 5 * 60 + 9)), "2h05m");
+        assert_eq!(format_duration(Duration::from_secs(3 * 86400 + 4 * 3600)), "3d04h");
+    }
+
+    #[test]
+    fn test_format_age() {
+        let now = 1_000_000;
+        assert_eq!(format_age(now, None), "never");
+        assert_eq!(format_age(now, Some(now - 10)), "just now");
+        assert_eq!(format_age(now, Some(now - 300)), "5m ago");
+        assert_eq!(format_age(now, Some(now - 7200)), "2h ago");
+        assert_eq!(format_age(now, Some(now - 3 * 86400)), "3d ago");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90d").unwrap(), Duration::from_secs(90 * 86400));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::from_secs(14 * 86400));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("7").unwrap(), Duration::from_secs(7 * 86400));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("10M").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("2 MiB").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("1gb").unwrap(), 1 << 30);
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("3T").is_err());
+    }
+}
@@ -4,13 +4,105 @@
 // When activating or exec-ing a crate, this module resolves its imports recursively
 // to build the full list of CrateVars, reading from the manifest cache.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use crate::config::BulkerConfig;
-use crate::manifest::{CrateVars, parse_registry_path};
+use crate::manifest::{CrateVars, Manifest, parse_manifest, parse_registry_path};
 use crate::manifest_cache::MAX_IMPORT_DEPTH;
 
+/// Namespace that manifests imported by file path are cached under.
+pub(crate) const LOCAL_IMPORT_NAMESPACE: &str = "local";
+
+/// Whether an import names a sibling file (`./base.yaml`, `../shared/tools.yaml`)
+/// rather than a registry crate.
+pub(crate) fn is_relative_import(import: &str) -> bool {
+    let import = import.trim();
+    import.starts_with("./") || import.starts_with("../")
+}
+
+/// Crate identity for a manifest imported by file path: `local/<file stem>`,
+/// tagged with a digest of its canonical path so same-named files in
+/// different directories don't collide.
+pub(crate) fn local_import_cratevars(path: &Path) -> CrateVars {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let crate_name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    let digest = crate::digest::sha512t24u(path.to_string_lossy().as_bytes());
+    CrateVars {
+        namespace: LOCAL_IMPORT_NAMESPACE.to_string(),
+        crate_name: if crate_name.is_empty() { "import".to_string() } else { crate_name },
+        tag: digest[..8].to_string(),
+    }
+}
+
+/// Cache every manifest that `manifest` (read from a file in `dir`) imports
+/// by relative path, resolving their own file imports against their
+/// locations, and point the imports at the cached crates. `visited` holds
+/// the canonical paths already cached, so file import cycles terminate;
+/// `depth` is how many file imports deep `manifest` is.
+pub(crate) fn cache_relative_imports(manifest: &mut Manifest, dir: &Path, visited: &mut HashSet<PathBuf>, depth: usize) -> Result<()> {
+    if depth >= MAX_IMPORT_DEPTH {
+        anyhow::bail!(
+            "Import depth exceeded {} for file imports in {}. Check for excessively deep import chains.",
+            MAX_IMPORT_DEPTH,
+            dir.display(),
+        );
+    }
+    for import in manifest.manifest.imports.iter_mut() {
+        if !is_relative_import(import) {
+            continue;
+        }
+        let path = std::fs::canonicalize(dir.join(import.trim()))
+            .with_context(|| format!("Imported file '{}' not found (relative to {})", import, dir.display()))?;
+        let cv = local_import_cratevars(&path);
+        if visited.insert(path.clone()) {
+            let contents = crate::manifest::read_manifest_file(&path)
+                .with_context(|| format!("Failed to read imported file: {}", path.display()))?;
+            let mut imported = parse_manifest(&contents)
+                .with_context(|| format!("Failed to parse imported file: {}", path.display()))?;
+            cache_relative_imports(&mut imported, path.parent().unwrap_or(Path::new("/")), visited, depth + 1)?;
+            crate::manifest_cache::save_to_cache(&cv, &imported)?;
+            log::info!("Cached imported file {} as {}", path.display(), cv.display_name());
+        }
+        *import = cv.display_name();
+    }
+    Ok(())
+}
+
+/// Parse an import path and apply the config's `import_remap`. A remap key
+/// with a tag (`bulker/coreutils:1.0`) only matches that tag; a key without one
+/// matches every tag of the crate. A tagged match wins over an untagged one.
+pub fn resolve_import(config: &BulkerConfig, import_path: &str) -> Result<CrateVars> {
+    let default_ns = &config.bulker.default_namespace;
+    let cv = parse_registry_path(import_path, default_ns)?;
+    let mut best: Option<(bool, &str)> = None;
+    for (from, to) in &config.bulker.import_remap {
+        let tagged = from.contains(':');
+        let Ok(from_cv) = parse_registry_path(from, default_ns) else {
+            log::warn!("Ignoring invalid import_remap key '{}'", from);
+            continue;
+        };
+        let matches = from_cv.namespace == cv.namespace
+            && from_cv.crate_name == cv.crate_name
+            && (!tagged || from_cv.tag == cv.tag);
+        if matches && best.is_none_or(|(best_tagged, _)| tagged && !best_tagged) {
+            best = Some((tagged, to));
+        }
+    }
+    match best {
+        Some((_, to)) => {
+            let remapped = parse_registry_path(to, default_ns)?;
+            log::debug!("Remapped import {} -> {}", cv.display_name(), remapped.display_name());
+            Ok(remapped)
+        }
+        None => Ok(cv),
+    }
+}
+
 /// Resolve all CrateVars (including imports) for a list of crates.
 /// Returns a flat list of all CrateVars in dependency order.
 pub fn resolve_cratevars_with_imports(
@@ -21,19 +113,40 @@ pub fn resolve_cratevars_with_imports(
     let mut visited = HashSet::new();
 
     for cv in cratelist {
-        resolve_crate_vars(config, cv, &mut all_vars, &mut visited, 0)?;
+        resolve_crate_vars(config, cv, &mut all_vars, &mut visited, None, 0)?;
     }
 
     Ok(all_vars)
 }
 
+/// Like `resolve_cratevars_with_imports`, but imports that are not cached are
+/// skipped instead of failing, so a partially fetched environment still
+/// works. Returns the resolved crates and the names of skipped imports.
+/// The crates in `cratelist` themselves must be cached.
+pub fn resolve_cratevars_allow_missing_imports(
+    config: &BulkerConfig,
+    cratelist: &[CrateVars],
+) -> Result<(Vec<CrateVars>, Vec<String>)> {
+    let mut all_vars = Vec::new();
+    let mut visited = HashSet::new();
+    let mut missing = Vec::new();
+
+    for cv in cratelist {
+        resolve_crate_vars(config, cv, &mut all_vars, &mut visited, Some(&mut missing), 0)?;
+    }
+
+    Ok((all_vars, missing))
+}
+
 /// Recursively collect CrateVars for a crate and all its imports.
 /// Reads import lists from cached manifests (not from config crates map).
+/// With `missing`, uncached imports are recorded there instead of failing.
 fn resolve_crate_vars(
     config: &BulkerConfig,
     cratevars: &CrateVars,
     vars: &mut Vec<CrateVars>,
     visited: &mut HashSet<String>,
+    mut missing: Option<&mut Vec<String>>,
     depth: usize,
 ) -> Result<()> {
     if depth >= MAX_IMPORT_DEPTH {
@@ -51,11 +164,17 @@ fn resolve_crate_vars(
     visited.insert(key.clone());
 
     // Load imports from the cached manifest (not from config crates map)
-    let manifest = crate::manifest_cache::load_cached(cratevars)?
-        .ok_or_else(|| anyhow::anyhow!(
-            "Crate '{}' is not cached. Run 'bulker activate' to fetch it.",
-            key
-        ))?;
+    let manifest = match crate::manifest_cache::load_cached(cratevars)? {
+        Some(manifest) => manifest,
+        None => match missing {
+            Some(missing) if depth > 0 => {
+                log::debug!("Skipping uncached import: {}", key);
+                missing.push(key);
+                return Ok(());
+            }
+            _ => anyhow::bail!("Crate '{}' is not cached. Run 'bulker activate' to fetch it.", key),
+        },
+    };
 
     vars.push(CrateVars {
         namespace: cratevars.namespace.clone(),
@@ -64,8 +183,8 @@ fn resolve_crate_vars(
     });
 
     for import_path in &manifest.manifest.imports {
-        let import_cv = parse_registry_path(import_path, &config.bulker.default_namespace)?;
-        resolve_crate_vars(config, &import_cv, vars, visited, depth + 1)?;
+        let import_cv = resolve_import(config, import_path)?;
+        resolve_crate_vars(config, &import_cv, vars, visited, missing.as_deref_mut(), depth + 1)?;
     }
     Ok(())
 }
@@ -94,7 +213,7 @@ mod tests {
     fn test_resolve_single_crate_from_cache() {
         // Set up temp XDG dir for test isolation
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
         let cv = CrateVars {
@@ -107,15 +226,14 @@ mod tests {
         let manifest = Manifest {
             manifest: ManifestInner {
                 name: Some("demo".to_string()),
-                version: None,
                 commands: vec![PackageCommand {
                     command: "cowsay".to_string(),
                     docker_image: "nsheff/cowsay:latest".to_string(),
                     ..Default::default()
                 }],
-                host_commands: vec![],
-                imports: vec![],
+                ..Default::default()
             },
+            ..Default::default()
         };
         crate::manifest_cache::save_to_cache(&cv, &manifest).unwrap();
 
@@ -128,11 +246,44 @@ mod tests {
 
     use crate::test_util::make_manifest_with_imports;
 
+    #[test]
+    fn test_relative_imports_cached_under_local_namespace() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path().join("cache"));
+        let dir = tmpdir.path();
+        std::fs::create_dir(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("app.yaml"),
+            "manifest:\n  name: me/app\n  imports:\n  - ./lib/base.yaml\n  - bulker/coreutils\n  commands: []\n",
+        )
+        .unwrap();
+        // base imports its sibling's parent, which imports base back
+        std::fs::write(dir.join("lib/base.yaml"), "manifest:\n  imports:\n  - ../common.yaml\n  commands: []\n").unwrap();
+        std::fs::write(dir.join("common.yaml"), "manifest:\n  imports:\n  - ./lib/base.yaml\n  commands: []\n").unwrap();
+
+        let (_, manifest) = crate::manifest::load_local_manifest(dir.join("app.yaml").to_str().unwrap(), None, "bulker").unwrap();
+        let base = local_import_cratevars(&std::fs::canonicalize(dir.join("lib/base.yaml")).unwrap());
+        let common = local_import_cratevars(&std::fs::canonicalize(dir.join("common.yaml")).unwrap());
+        assert_eq!(manifest.manifest.imports, vec![base.display_name(), "bulker/coreutils".to_string()]);
+        assert_eq!(base.namespace, "local");
+        assert_eq!(base.crate_name, "base");
+
+        let cached_base = crate::manifest_cache::load_cached(&base).unwrap().unwrap();
+        assert_eq!(cached_base.manifest.imports, vec![common.display_name()]);
+        let cached_common = crate::manifest_cache::load_cached(&common).unwrap().unwrap();
+        assert_eq!(cached_common.manifest.imports, vec![base.display_name()]);
+
+        assert!(crate::manifest::load_local_manifest(dir.join("lib/base.yaml").to_str().unwrap(), Some("me/x"), "bulker").is_ok());
+        std::fs::write(dir.join("bad.yaml"), "manifest:\n  name: me/bad\n  imports:\n  - ./missing.yaml\n  commands: []\n").unwrap();
+        let err = crate::manifest::load_local_manifest(dir.join("bad.yaml").to_str().unwrap(), None, "bulker").unwrap_err();
+        assert!(format!("{:#}", err).contains("'./missing.yaml' not found"), "{:#}", err);
+    }
+
     #[test]
     fn test_resolve_cycle_detection() {
         // Set up isolated cache
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
 
@@ -166,11 +317,72 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_resolve_allow_missing_imports_skips_uncached() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+
+        let config = BulkerConfig::test_default();
+        let cv = CrateVars {
+            namespace: "partial_imports".to_string(),
+            crate_name: "top".to_string(),
+            tag: "default".to_string(),
+        };
+        let manifest = make_manifest_with_imports("top", vec!["partial_imports/gone:default".to_string()]);
+        crate::manifest_cache::save_to_cache(&cv, &manifest).unwrap();
+
+        assert!(resolve_cratevars_with_imports(&config, std::slice::from_ref(&cv)).is_err());
+        let (vars, missing) = resolve_cratevars_allow_missing_imports(&config, &[cv]).unwrap();
+        assert_eq!(vars.len(), 1);
+        assert_eq!(missing, vec!["partial_imports/gone:default"]);
+    }
+
+    #[test]
+    fn test_resolve_import_applies_remap() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.import_remap.insert("bulker/coreutils".to_string(), "myorg/coreutils-arm:1.2".to_string());
+        config.bulker.import_remap.insert("bulker/coreutils:0.9".to_string(), "myorg/coreutils-legacy".to_string());
+
+        let remapped = resolve_import(&config, "bulker/coreutils:2.0").unwrap();
+        assert_eq!(remapped.display_name(), "myorg/coreutils-arm:1.2");
+        // Default namespace applies to both the import and the remap key
+        assert_eq!(resolve_import(&config, "coreutils").unwrap().display_name(), "myorg/coreutils-arm:1.2");
+        // A tagged key wins over an untagged one
+        assert_eq!(resolve_import(&config, "bulker/coreutils:0.9").unwrap().display_name(), "myorg/coreutils-legacy:default");
+        assert_eq!(resolve_import(&config, "bulker/other:1.0").unwrap().display_name(), "bulker/other:1.0");
+    }
+
+    #[test]
+    fn test_resolve_uses_remapped_import() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+
+        let mut config = BulkerConfig::test_default();
+        config.bulker.import_remap.insert("remap_imports/upstream".to_string(), "remap_imports/arm:1.0".to_string());
+        let top = CrateVars {
+            namespace: "remap_imports".to_string(),
+            crate_name: "top".to_string(),
+            tag: "default".to_string(),
+        };
+        let arm = CrateVars {
+            namespace: "remap_imports".to_string(),
+            crate_name: "arm".to_string(),
+            tag: "1.0".to_string(),
+        };
+        let manifest = make_manifest_with_imports("top", vec!["remap_imports/upstream:default".to_string()]);
+        crate::manifest_cache::save_to_cache(&top, &manifest).unwrap();
+        crate::manifest_cache::save_to_cache(&arm, &make_manifest_with_imports("arm", vec![])).unwrap();
+
+        let vars = resolve_cratevars_with_imports(&config, &[top]).unwrap();
+        let names: Vec<String> = vars.iter().map(|cv| cv.display_name()).collect();
+        assert_eq!(names, vec!["remap_imports/top:default", "remap_imports/arm:1.0"]);
+    }
+
     #[test]
     fn test_resolve_depth_limit() {
         // Set up isolated cache
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
 
@@ -0,0 +1,139 @@
+//! Multi-container environment manager, as a library.
+//!
+//! The `bulker` binary is built on this crate. Tools that want bulker's
+//! container commands without shelling out to the CLI (workflow engines,
+//! test harnesses) can use its stable API:
+//!
+//! - [`config`]: load a bulker config ([`config::load_config`])
+//! - [`manifest`]: crate paths and manifests ([`manifest::parse_registry_path`])
+//! - [`manifest_cache`]: the local manifest cache ([`manifest_cache::load_cached`])
+//! - [`digest`]: crate manifest digests
+//! - [`shimlink::build_docker_command`] / [`shimlink::build_apptainer_command`]:
+//!   the engine command line for one crate command ([`shimlink::CommandOptions`])
+//! - [`activate::get_new_path`]: a shimdir and PATH for a list of crates
+//!
+//! ```no_run
+//! use bulker::{config, manifest, manifest_cache, shimlink};
+//!
+//! let (config, _path) = config::load_config(None)?;
+//! let cv = manifest::parse_registry_path("bulker/demo", &config.bulker.default_namespace)?;
+//! let crate_manifest = manifest_cache::load_cached(&cv)?.expect("bulker/demo is not cached");
+//! let pkg = &crate_manifest.manifest.commands[0];
+//! let args = ["--help".to_string()];
+//! let mut opts = shimlink::CommandOptions::new(config.engine_path());
+//! opts.volumes = &config.bulker.volumes;
+//! opts.args = &args;
+//! let argv = shimlink::build_docker_command(&config, pkg, &opts);
+//! println!("{}", argv.join(" "));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Everything else, including the `cli` entry point, is internal to the binary.
+
+#[cfg(not(unix))]
+compile_error!("bulker requires a Unix-like operating system (Linux, macOS)");
+
+pub mod activate;
+mod activation_stack;
+mod bundle;
+mod cache_permissions;
+#[doc(hidden)]
+pub mod cli;
+mod commands;
+pub mod config;
+mod containers;
+mod cpu_binding;
+pub mod digest;
+mod download;
+mod filelock;
+mod humanize;
+mod imports;
+mod lockfile;
+pub mod manifest;
+pub mod manifest_cache;
+mod memoize;
+mod metrics;
+mod mock;
+mod mountignore;
+mod ownership;
+mod plugins;
+mod process;
+mod provenance;
+mod registry;
+mod sessions;
+pub mod shimlink;
+mod templates;
+mod timings;
+mod trace;
+#[cfg(test)]
+mod test_util;
+
+use clap::{Arg, ArgAction, Command};
+
+pub mod consts {
+    pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+    pub const BIN_NAME: &str = "bulker";
+    pub const GIT_COMMIT: &str = env!("BULKER_GIT_COMMIT");
+    pub const BUILD_DATE: &str = env!("BULKER_BUILD_DATE");
+    pub const TARGET: &str = env!("BULKER_TARGET");
+    pub const FEATURES: &str = env!("BULKER_FEATURES");
+}
+
+pub fn build_parser() -> Command {
+    Command::new(consts::BIN_NAME)
+        .bin_name(consts::BIN_NAME)
+        .version(consts::VERSION)
+        .about("Multi-container environment manager")
+        .arg_required_else_help(true)
+        .allow_external_subcommands(true)
+        .after_help("\
+PLUGINS:
+  Unknown subcommands run a `bulker-<name>` executable from PATH, so
+  `bulker nf run ...` runs `bulker-nf run ...`. Built-in commands take
+  precedence. List available plugins with `bulker --list-plugins`.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Enable verbose/debug logging"),
+        )
+        .arg(
+            Arg::new("cache-scope")
+                .long("cache-scope")
+                .value_name("SCOPE")
+                .value_parser(["user", "project"])
+                .global(true)
+                .help("Manifest cache to use: user (default) or project (./.bulker/manifests, searched first)"),
+        )
+        .arg(
+            Arg::new("list-plugins")
+                .long("list-plugins")
+                .action(ArgAction::SetTrue)
+                .exclusive(true)
+                .help("List bulker-<name> plugin executables found on PATH"),
+        )
+        .subcommand(commands::activate::create_cli())
+        .subcommand(commands::deactivate::create_cli())
+        .subcommand(commands::attach::create_cli())
+        .subcommand(commands::exec::create_cli())
+        .subcommand(commands::crate_cmd::create_cli())
+        .subcommand(commands::ci_cmd::create_cli())
+        .subcommand(commands::config_cmd::create_cli())
+        .subcommand(commands::env_cmd::create_cli())
+        .subcommand(commands::registry_cmd::create_cli())
+        .subcommand(commands::init_shell::create_cli())
+        .subcommand(commands::mock_cmd::create_cli())
+        .subcommand(commands::ps::create_cli())
+        .subcommand(commands::clean::create_cli())
+        .subcommand(commands::status::create_cli())
+        .subcommand(commands::prefetch::create_cli())
+        .subcommand(commands::selftest::create_cli())
+        .subcommand(commands::demo::create_cli())
+        .subcommand(commands::version::create_cli())
+        .subcommand(commands::bugreport::create_cli())
+        .subcommand(commands::doctor::create_cli())
+        .subcommand(commands::completions::create_cli())
+}
+
@@ -0,0 +1,222 @@
+//! `bulker.lock`: the OCI content digest of every image of a set of crates,
+//! written by `bulker crate lock` and enforced by `activate`/`exec --locked`.
+//!
+//! ```yaml
+//! version: 1
+//! crates:
+//!   databio/pepatac:1.0.13:
+//!     manifest_digest: 3Qd1...
+//!     images:
+//!       quay.io/biocontainers/samtools:1.9: sha256:ab...
+//! ```
+//!
+//! A locked activation refuses crates whose manifest changed since the lock
+//! was written, and its shimlinks run `image@digest` instead of the tag.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::config::BulkerConfig;
+use crate::manifest::{CrateVars, Manifest, PackageCommand, split_image_tag_digest};
+
+/// Default lockfile name, in the current directory.
+pub const LOCKFILE_NAME: &str = "bulker.lock";
+
+/// Set by `--locked` to the absolute path of the lockfile shimlinks pin images from.
+pub(crate) const LOCKFILE_ENV: &str = "BULKER_LOCKFILE";
+
+const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct LockedCrate {
+    pub manifest_digest: String,
+    /// Image reference as written in the manifest → `sha256:...` digest.
+    #[serde(default)]
+    pub images: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Lockfile {
+    pub version: u32,
+    /// Keyed by `namespace/crate:tag`; imports are listed like the crates that import them.
+    #[serde(default)]
+    pub crates: BTreeMap<String, LockedCrate>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile { version: LOCKFILE_VERSION, crates: BTreeMap::new() }
+    }
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lockfile: {}. Run 'bulker crate lock' to create it.", path.display()))?;
+        let lock: Lockfile = serde_yml::from_str(&text).with_context(|| format!("{} is not a bulker lockfile", path.display()))?;
+        if lock.version != LOCKFILE_VERSION {
+            bail!("{}: unsupported lockfile version {} (expected {})", path.display(), lock.version, LOCKFILE_VERSION);
+        }
+        Ok(lock)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let yaml = serde_yml::to_string(self)?;
+        let contents = format!("# Generated by `bulker crate lock`; do not edit.\n{}", yaml);
+        std::fs::write(path, contents).with_context(|| format!("Failed to write lockfile: {}", path.display()))
+    }
+
+    /// Record `manifest` under `cv`, with the digests of its images taken
+    /// from `digests` (image → digest). Fails naming the images with none.
+    pub fn add_crate(&mut self, cv: &CrateVars, manifest: &Manifest, digests: &HashMap<String, String>) -> Result<()> {
+        let mut images = BTreeMap::new();
+        let mut unresolved = Vec::new();
+        for pkg in &manifest.manifest.commands {
+            match digests.get(&pkg.docker_image) {
+                Some(digest) => {
+                    images.insert(pkg.docker_image.clone(), digest.clone());
+                }
+                None if !unresolved.contains(&pkg.docker_image) => unresolved.push(pkg.docker_image.clone()),
+                None => {}
+            }
+        }
+        if !unresolved.is_empty() {
+            bail!(
+                "Could not resolve the digests of {}'s images: {}. Check the registry credentials (registry_auth) and network access.",
+                cv.display_name(),
+                unresolved.join(", ")
+            );
+        }
+        let manifest_digest = crate::digest::crate_manifest_digest(manifest).digest;
+        self.crates.insert(cv.display_name(), LockedCrate { manifest_digest, images });
+        Ok(())
+    }
+
+    /// Check that `manifest` is what was locked for `cv`.
+    pub fn check_crate(&self, cv: &CrateVars, manifest: &Manifest) -> Result<()> {
+        let name = cv.display_name();
+        let Some(locked) = self.crates.get(&name) else {
+            bail!("'{}' is not in the lockfile. Run 'bulker crate lock' to update it.", name);
+        };
+        if crate::digest::crate_manifest_digest(manifest).digest != locked.manifest_digest {
+            bail!("'{}' has changed since it was locked. Run 'bulker crate lock' to update the lockfile.", name);
+        }
+        Ok(())
+    }
+
+    /// The digest locked for `image`, by any crate.
+    pub fn digest_for(&self, image: &str) -> Option<&str> {
+        self.crates.values().find_map(|c| c.images.get(image)).map(|d| d.as_str())
+    }
+
+    /// Pin `pkg`'s image to its locked digest (`image:tag@sha256:...`).
+    /// Images already pinned in the manifest are left alone.
+    pub fn pin(&self, pkg: &mut PackageCommand) -> Result<()> {
+        if split_image_tag_digest(&pkg.docker_image).2.is_some() {
+            return Ok(());
+        }
+        let Some(digest) = self.digest_for(&pkg.docker_image) else {
+            bail!("Image '{}' of '{}' is not in the lockfile. Run 'bulker crate lock' to update it.", pkg.docker_image, pkg.command);
+        };
+        pkg.docker_image = format!("{}@{}", pkg.docker_image, digest);
+        Ok(())
+    }
+}
+
+/// Lock `cratelist` and all of their imports, which must be cached. Images
+/// are resolved to their OCI content digests, which needs network access.
+pub fn lock_crates(config: &BulkerConfig, cratelist: &[CrateVars]) -> Result<Lockfile> {
+    let mut lock = Lockfile::default();
+    for cv in crate::imports::resolve_cratevars_with_imports(config, cratelist)? {
+        let manifest = crate::manifest_cache::load_cached(&cv)?
+            .ok_or_else(|| anyhow::anyhow!("Crate '{}' is not cached", cv.display_name()))?;
+        log::info!("Resolving image digests of {}...", cv.display_name());
+        let digests = crate::digest::resolve_oci_digests(&manifest);
+        lock.add_crate(&cv, &manifest, &digests)?;
+    }
+    Ok(lock)
+}
+
+/// Load the lockfile at `path` and check it covers `cratelist` and their
+/// imports, fetching (or with `force`, re-fetching) them first. Every crate
+/// must be cached and match the lock: one that can't be fetched fails the
+/// check rather than being skipped. Used by `--locked` before activating.
+pub fn check_locked(config: &BulkerConfig, cratelist: &[CrateVars], path: &Path, force: bool) -> Result<()> {
+    let lock = Lockfile::load(path)?;
+    let context = || format!("--locked: {}", path.display());
+    let mut visited = std::collections::HashSet::new();
+    for cv in cratelist {
+        crate::manifest_cache::ensure_cached_with_imports(config, cv, force, false, &mut visited, 0).with_context(context)?;
+    }
+    for cv in crate::imports::resolve_cratevars_with_imports(config, cratelist).with_context(context)? {
+        let Some(manifest) = crate::manifest_cache::load_cached(&cv)? else {
+            return Err(anyhow::anyhow!("'{}' is not cached, so it can't be checked against the lockfile", cv.display_name())).with_context(context);
+        };
+        lock.check_crate(&cv, &manifest).with_context(context)?;
+    }
+    Ok(())
+}
+
+/// Pin `pkg`'s image from the lockfile in `$BULKER_LOCKFILE`, if set.
+pub(crate) fn pin_from_env(pkg: &mut PackageCommand) -> Result<()> {
+    let Some(path) = std::env::var_os(LOCKFILE_ENV).filter(|p| !p.is_empty()) else {
+        return Ok(());
+    };
+    Lockfile::load(Path::new(&path))?.pin(pkg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::parse_manifest;
+
+    const DIGEST: &str = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn cv() -> CrateVars {
+        crate::manifest::parse_registry_path("bulker/demo:1.0", "bulker").unwrap()
+    }
+
+    #[test]
+    fn test_lock_roundtrip_check_and_pin() {
+        let manifest = parse_manifest(
+            "manifest:\n  name: demo\n  commands:\n  - {command: cowsay, docker_image: nsheff/cowsay:1}\n  - {command: say, docker_image: nsheff/cowsay:1}\n",
+        )
+        .unwrap();
+        let mut lock = Lockfile::default();
+        let err = lock.add_crate(&cv(), &manifest, &HashMap::new()).unwrap_err().to_string();
+        assert!(err.contains("bulker/demo:1.0's images: nsheff/cowsay:1."), "{}", err);
+
+        let digests = HashMap::from([("nsheff/cowsay:1".to_string(), DIGEST.to_string())]);
+        lock.add_crate(&cv(), &manifest, &digests).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+        lock.write(&path).unwrap();
+        let loaded = Lockfile::load(&path).unwrap();
+        assert_eq!(loaded, lock);
+        loaded.check_crate(&cv(), &manifest).unwrap();
+
+        let mut pkg = manifest.manifest.commands[0].clone();
+        loaded.pin(&mut pkg).unwrap();
+        assert_eq!(pkg.docker_image, format!("nsheff/cowsay:1@{}", DIGEST));
+        // Already pinned: unchanged
+        loaded.pin(&mut pkg).unwrap();
+        assert_eq!(pkg.docker_image, format!("nsheff/cowsay:1@{}", DIGEST));
+
+        let changed = parse_manifest("manifest:\n  name: demo\n  commands:\n  - {command: cowsay, docker_image: nsheff/cowsay:2}\n").unwrap();
+        assert!(loaded.check_crate(&cv(), &changed).unwrap_err().to_string().contains("has changed"));
+        let mut pkg = changed.manifest.commands[0].clone();
+        assert!(loaded.pin(&mut pkg).is_err());
+        let other = crate::manifest::parse_registry_path("bulker/other", "bulker").unwrap();
+        assert!(loaded.check_crate(&other, &manifest).unwrap_err().to_string().contains("not in the lockfile"));
+    }
+
+    #[test]
+    fn test_load_rejects_unknown_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCKFILE_NAME);
+        std::fs::write(&path, "version: 9\ncrates: {}\n").unwrap();
+        assert!(Lockfile::load(&path).unwrap_err().to_string().contains("version 9"));
+    }
+}
@@ -12,6 +12,30 @@ where
     Option::<Vec<T>>::deserialize(deserializer).map(|v| v.unwrap_or_default())
 }
 
+/// Deserialize an optional scalar as a string, so `cpus: 2` and `cpus: "2"`
+/// read the same.
+pub(crate) fn scalar_as_string<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        Str(String),
+        Int(i64),
+        Float(f64),
+    }
+    Ok(Option::<Scalar>::deserialize(deserializer)?.map(|s| match s {
+        Scalar::Str(s) => s,
+        Scalar::Int(i) => i.to_string(),
+        Scalar::Float(f) => f.to_string(),
+    }))
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
 /// Parsed registry path components.
 #[derive(Debug, Clone)]
 pub struct CrateVars {
@@ -28,24 +52,39 @@ impl CrateVars {
 }
 
 /// Manifest file structure (top-level).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Manifest {
     pub manifest: ManifestInner,
+    /// The text the manifest was parsed from, comments included. Cached next
+    /// to the canonical form so `crate inspect --raw` can show it; digests
+    /// never use it.
+    #[serde(skip)]
+    pub source: Option<String>,
 }
 
 /// Inner manifest data.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ManifestInner {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
     #[serde(default, deserialize_with = "null_as_empty_vec")]
     pub commands: Vec<PackageCommand>,
-    #[serde(default, deserialize_with = "null_as_empty_vec")]
+    #[serde(default, deserialize_with = "null_as_empty_vec", skip_serializing_if = "Vec::is_empty")]
     pub host_commands: Vec<String>,
-    #[serde(default, deserialize_with = "null_as_empty_vec")]
+    #[serde(default, deserialize_with = "null_as_empty_vec", skip_serializing_if = "Vec::is_empty")]
     pub imports: Vec<String>,
+    /// Variables set in every container of this crate, as `KEY=value`, e.g.
+    /// `R_LIBS_USER=$HOME/R/crate-4.3`. `$VAR` and `~` in the value are
+    /// expanded on the host at run time. Explicit `KEY=value` envvars (command,
+    /// config or `BULKER_EXTRA_ENVVARS`) override them.
+    #[serde(default, deserialize_with = "null_as_empty_vec", skip_serializing_if = "Vec::is_empty")]
+    pub env_defaults: Vec<String>,
+    /// Shown once when the crate is activated interactively, e.g. to announce
+    /// breaking changes, required reference data or how to cite the tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activation_message: Option<String>,
 }
 
 /// A single command entry in the manifest.
@@ -58,35 +97,206 @@ pub struct PackageCommand {
     /// apptainer exec command. When unset, both engines fall back to
     /// `pkg.command`. Replaces the deprecated `docker_command`,
     /// `apptainer_command`, `singularity_command` chain.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub entrypoint: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub docker_command: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub docker_args: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dockerargs: Option<String>,
-    #[serde(default, alias = "singularity_args")]
+    #[serde(default, alias = "singularity_args", skip_serializing_if = "Option::is_none")]
     pub apptainer_args: Option<String>,
-    #[serde(default, alias = "singularity_command")]
+    #[serde(default, alias = "singularity_command", skip_serializing_if = "Option::is_none")]
     pub apptainer_command: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub volumes: Vec<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub envvars: Vec<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub no_user: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub no_network: bool,
-    #[serde(default)]
+    /// Run without docker's `--init` (for images that bring their own init
+    /// or need PID 1 semantics).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_init: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
     pub no_default_volumes: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub no_default_envvars: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub workdir: Option<String>,
+    /// Ports to publish for server-type commands, in docker `-p` syntax
+    /// (`8080`, `8080:80`, `127.0.0.1:8080:80/tcp`). Publishing ports
+    /// disables host networking for the command under docker.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+    /// Host devices the command needs (`/dev/fuse`, `/dev/nvidia0`), as
+    /// `HOST[:CONTAINER]`. Docker gets `--device`, apptainer a bind.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<String>,
+    /// Host sockets the command talks to (`/var/run/docker.sock`), as
+    /// `HOST[:CONTAINER]`. Bound into the container by either engine.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sockets: Vec<String>,
+    /// Give the command the host's GPUs: `--gpus=all` under docker and
+    /// podman, `--nv` under apptainer.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub gpu: bool,
+    /// Image platform for multi-arch images (e.g. `linux/arm64`). Docker gets
+    /// `--platform`; apptainer pulls the matching architecture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// When to pull the image: `always`, `missing` (engine default) or `never`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<String>,
+    /// Shell for the interactive `_command` wrapper: `auto` (default: bash if
+    /// the image has it, else sh), a shell command such as `/bin/busybox sh`,
+    /// or `none` for images without any shell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell_in_container: Option<String>,
+    /// Octal umask for the container process (e.g. `0002` for group-writable
+    /// outputs). Overrides the config's `umask`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<String>,
+    /// After a docker run, give files the container created under the
+    /// working directory back to the host user (for root-run images).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub chown_outputs: bool,
+    /// CPUs to bind the command to: `inherit`, a CPU list (`0-3`) or
+    /// `numactl <options>` (see `cpu_binding.rs`). Overrides the config's
+    /// `cpu_binding`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_binding: Option<String>,
+    /// CPU limit (`2`, `0.5`): docker and podman `--cpus`. Overrides the
+    /// config's `cpus`; apptainer ignores it.
+    #[serde(default, deserialize_with = "scalar_as_string", skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<String>,
+    /// Memory limit (`512M`, `8G`): docker and podman `--memory`. Overrides
+    /// the config's `memory`; apptainer ignores it.
+    #[serde(default, deserialize_with = "scalar_as_string", skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Size of the container's /dev/shm (`2G`): docker and podman
+    /// `--shm-size`. Overrides the config's `shm_size`; apptainer shares the
+    /// host's /dev/shm.
+    #[serde(default, deserialize_with = "scalar_as_string", skip_serializing_if = "Option::is_none")]
+    pub shm_size: Option<String>,
+}
+
+/// When the engine pulls a command's image (`pull_policy` manifest field).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullPolicy {
+    /// Check the registry on every run (docker); re-pull on `crate install --build` (apptainer).
+    Always,
+    /// Pull only when the image is not present locally (default).
+    Missing,
+    /// Never pull; fail if the image is not present locally.
+    Never,
+}
+
+impl PullPolicy {
+    pub const NAMES: &'static [&'static str] = &["always", "missing", "never"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "always" => Some(PullPolicy::Always),
+            "missing" => Some(PullPolicy::Missing),
+            "never" => Some(PullPolicy::Never),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PullPolicy::Always => "always",
+            PullPolicy::Missing => "missing",
+            PullPolicy::Never => "never",
+        }
+    }
 }
 
+/// Shell command run inside the container when `shell_in_container` is
+/// `auto`: prefers bash, falls back to sh (busybox and distroless-debug images).
+const AUTO_SHELL_SCRIPT: &str = "if command -v bash >/dev/null 2>&1; then exec bash \"$@\"; fi; exec sh \"$@\"";
+
 impl PackageCommand {
+    /// Argv that starts an interactive shell in the container (the first
+    /// element is the executable). None when `shell_in_container: none`.
+    pub fn container_shell(&self) -> Option<Vec<String>> {
+        match self.shell_in_container.as_deref().map(str::trim) {
+            Some("none") => None,
+            None | Some("") | Some("auto") => Some(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                AUTO_SHELL_SCRIPT.to_string(),
+                "sh".to_string(),
+            ]),
+            Some(shell) => Some(shell.split_whitespace().map(String::from).collect()),
+        }
+    }
+
+    /// Parsed `pull_policy`. Invalid values are warned about and ignored.
+    pub fn pull_policy(&self) -> Option<PullPolicy> {
+        let name = self.pull_policy.as_deref().filter(|p| !p.is_empty())?;
+        let policy = PullPolicy::from_name(name);
+        if policy.is_none() {
+            log::warn!(
+                "'{}': ignoring invalid pull_policy '{}'. Valid values: {}",
+                self.command,
+                name,
+                PullPolicy::NAMES.join(", ")
+            );
+        }
+        policy
+    }
+
+    /// Parsed `cpu_binding`. Invalid values are warned about and ignored.
+    pub fn cpu_binding(&self) -> Option<crate::cpu_binding::CpuBinding> {
+        let value = self.cpu_binding.as_deref().filter(|b| !b.is_empty())?;
+        match crate::cpu_binding::CpuBinding::parse(value) {
+            Ok(binding) => Some(binding),
+            Err(e) => {
+                log::warn!("'{}': ignoring cpu_binding: {:#}", self.command, e);
+                None
+            }
+        }
+    }
+
+    /// Parsed `umask`. Invalid values are warned about and ignored.
+    pub fn umask(&self) -> Option<u32> {
+        let value = self.umask.as_deref().filter(|u| !u.is_empty())?;
+        let mask = crate::ownership::parse_umask(value);
+        if mask.is_none() {
+            log::warn!("'{}': ignoring invalid umask '{}'. Use an octal mask such as 0022.", self.command, value);
+        }
+        mask
+    }
+
+    /// Architecture part of `platform` (`linux/arm64/v8` -> `arm64`), as
+    /// apptainer's `pull --arch` expects.
+    pub fn platform_arch(&self) -> Option<&str> {
+        let platform = self.platform.as_deref().filter(|p| !p.is_empty())?;
+        match platform.split('/').nth(1) {
+            Some(arch) if !arch.is_empty() => Some(arch),
+            _ => Some(platform),
+        }
+    }
+
+    /// Fold the deprecated `dockerargs` into `docker_args` (in the order
+    /// `merged_docker_args` applies them). Returns true if anything changed.
+    pub fn normalize_docker_args(&mut self) -> bool {
+        let Some(old) = self.dockerargs.take() else {
+            return false;
+        };
+        self.docker_args = match self.docker_args.take().filter(|da| !da.is_empty()) {
+            Some(da) if !old.is_empty() => Some(format!("{} {}", old, da)),
+            Some(da) => Some(da),
+            None => Some(old).filter(|o| !o.is_empty()),
+        };
+        true
+    }
+
     /// Merge docker_args from the command's `dockerargs` and `docker_args` fields,
     /// plus any extra args passed in (e.g., host-tool-specific or environment).
     pub(crate) fn merged_docker_args(&self, extra_args: &[&str]) -> String {
@@ -108,9 +318,229 @@ impl PackageCommand {
     }
 }
 
+/// Set from the `strict_manifests` config option whenever a config is loaded.
+static STRICT_MANIFESTS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Turn deprecated manifest fields into errors (`strict_manifests: true`).
+pub fn set_strict_manifests(strict: bool) {
+    STRICT_MANIFESTS.store(strict, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// A deprecated field used by a manifest command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deprecation {
+    pub command: String,
+    pub field: &'static str,
+    pub replacement: &'static str,
+}
+
+impl std::fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}': `{}` is deprecated; use `{}`", self.command, self.field, self.replacement)
+    }
+}
+
+impl Manifest {
+    /// Deprecated fields in use, in command order.
+    pub fn deprecations(&self) -> Vec<Deprecation> {
+        let mut found = Vec::new();
+        for pkg in &self.manifest.commands {
+            let fields = [
+                ("dockerargs", "docker_args", pkg.dockerargs.is_some()),
+                ("docker_command", "entrypoint", pkg.docker_command.is_some()),
+                ("apptainer_command", "entrypoint", pkg.apptainer_command.is_some()),
+            ];
+            for (field, replacement, used) in fields {
+                if used {
+                    found.push(Deprecation { command: pkg.command.clone(), field, replacement });
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Validation pass for a newly loaded manifest: warn about deprecated fields,
+/// or fail in strict mode. `source` names the manifest in messages.
+pub fn check_deprecations(manifest: &Manifest, source: &str) -> Result<()> {
+    check_deprecations_with(manifest, source, STRICT_MANIFESTS.load(std::sync::atomic::Ordering::Relaxed))
+}
+
+fn check_deprecations_with(manifest: &Manifest, source: &str, strict: bool) -> Result<()> {
+    let found = manifest.deprecations();
+    if found.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        bail!(
+            "{} uses deprecated fields (strict_manifests is on):\n  {}\nRun `bulker crate fmt` to fix what can be fixed automatically.",
+            source,
+            found.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n  ")
+        );
+    }
+    for d in &found {
+        log::warn!("{}: {}", source, d);
+    }
+    Ok(())
+}
+
+/// Largest manifest bulker reads. Bigger files are refused before they are
+/// read into memory.
+pub const MAX_MANIFEST_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Most commands a single manifest may define.
+pub const MAX_MANIFEST_COMMANDS: usize = 100_000;
+
+/// Fail for manifest text of `len` bytes over [`MAX_MANIFEST_BYTES`].
+pub(crate) fn check_manifest_size(len: u64, source: &str) -> Result<()> {
+    if len > MAX_MANIFEST_BYTES {
+        bail!(
+            "{} is {}, more than the {} bulker reads as a manifest. Split the crate into smaller crates joined by imports.",
+            source,
+            crate::humanize::format_size(len),
+            crate::humanize::format_size(MAX_MANIFEST_BYTES)
+        );
+    }
+    Ok(())
+}
+
+/// Read manifest text, refusing more than [`MAX_MANIFEST_BYTES`] without
+/// reading past the limit.
+pub(crate) fn read_manifest_text(reader: impl std::io::Read, source: &str) -> Result<String> {
+    use std::io::Read;
+    let mut contents = String::new();
+    reader
+        .take(MAX_MANIFEST_BYTES + 1)
+        .read_to_string(&mut contents)
+        .with_context(|| format!("Failed to read {}", source))?;
+    check_manifest_size(contents.len() as u64, source)?;
+    Ok(contents)
+}
+
+/// [`read_manifest_text`] for a file.
+pub(crate) fn read_manifest_file(path: &std::path::Path) -> Result<String> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    read_manifest_text(file, &path.display().to_string())
+}
+
+/// Parse a manifest from YAML or JSON. JSON is detected by content (a leading
+/// `{`), so `.json` manifests work wherever YAML ones do. For YAML, anchors and
+/// aliases are resolved, and `<<` merge keys are applied, so `<<: *defaults`
+/// can share settings across commands. YAML with merge keys is resolved
+/// through a `serde_yml::Value` first.
+pub fn parse_manifest(contents: &str) -> Result<Manifest> {
+    check_manifest_size(contents.len() as u64, "Manifest")?;
+    let mut manifest: Manifest = if contents.trim_start().starts_with('{') {
+        serde_json::from_str(contents).context("Invalid JSON manifest")?
+    } else if contents.contains("<<") {
+        let mut value: serde_yml::Value = serde_yml::from_str(contents)?;
+        value.apply_merge()?;
+        serde_yml::from_value(value)?
+    } else {
+        serde_yml::from_str(contents)?
+    };
+    if manifest.manifest.commands.len() > MAX_MANIFEST_COMMANDS {
+        bail!(
+            "Manifest defines {} commands, more than the {} bulker accepts. Split the crate into smaller crates joined by imports.",
+            manifest.manifest.commands.len(),
+            MAX_MANIFEST_COMMANDS
+        );
+    }
+    manifest.source = Some(contents.to_string());
+    Ok(manifest)
+}
+
+/// Which commands of a crate an activation links or an install pulls images
+/// for (`--commands-filter`): globs over command names, e.g. `samtools` or
+/// `bcf*`. No globs means every command.
+#[derive(Debug, Clone, Default)]
+pub struct CommandsFilter {
+    globs: Vec<String>,
+}
+
+impl CommandsFilter {
+    /// Build from `--commands-filter` values, each a comma-separated list.
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut globs = Vec::new();
+        for glob in values.into_iter().flat_map(|v| v.split(',')).map(str::trim) {
+            if glob.is_empty() || glob.contains('/') {
+                bail!("Invalid --commands-filter glob '{}'. Use command names with * and ?, e.g. 'samtools,bcf*'.", glob);
+            }
+            globs.push(glob.to_string());
+        }
+        Ok(CommandsFilter { globs })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+    }
+
+    pub fn matches(&self, command: &str) -> bool {
+        self.is_empty() || self.globs.iter().any(|g| crate::mountignore::glob_match(g, command))
+    }
+
+    /// Drop the commands of `manifest` the filter doesn't match.
+    pub fn apply(&self, manifest: &mut Manifest) {
+        if !self.is_empty() {
+            manifest.manifest.commands.retain(|pkg| self.matches(&pkg.command));
+        }
+    }
+}
+
+impl std::fmt::Display for CommandsFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.globs.join(","))
+    }
+}
+
+/// Emit a manifest in canonical form: fixed key order (struct field order),
+/// unset and default-valued fields omitted, aliases expanded. Re-parsing and
+/// re-emitting canonical output yields identical text, so cached manifests can
+/// be diffed reliably. Comments are not preserved.
+pub fn to_canonical_yaml(manifest: &Manifest) -> Result<String> {
+    serde_yml::to_string(manifest).context("Failed to serialize manifest")
+}
+
+/// Check that a command name can name a file: not empty, no `/`, no leading
+/// `.` and no control characters. Names come from manifests, so anything
+/// writing files or scripts named after commands checks them first.
+pub(crate) fn check_command_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') || name.chars().any(char::is_control) {
+        bail!("Command name {:?} is not a plain file name", name);
+    }
+    Ok(())
+}
+
+/// Check a `cpus` limit: a positive number of CPUs such as `2` or `0.5`.
+pub(crate) fn check_cpus(value: &str) -> Result<()> {
+    match value.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(()),
+        _ => bail!("Invalid cpus '{}': expected a positive number such as 2 or 0.5", value),
+    }
+}
+
+/// Check a `memory` or `shm_size` limit such as `512M` or `8G`.
+pub(crate) fn check_memory_size(value: &str) -> Result<()> {
+    if crate::humanize::parse_size(value)? == 0 {
+        bail!("Invalid size '{}': must be more than 0", value);
+    }
+    Ok(())
+}
+
+/// Returns true if a port spec maps a host port to a different container port
+/// (e.g. `8080:80`). Plain `8080` or `8080:8080` are not remaps.
+pub(crate) fn port_is_remapped(spec: &str) -> bool {
+    let spec = spec.split('/').next().unwrap_or(spec);
+    let parts: Vec<&str> = spec.split(':').collect();
+    if parts.len() < 2 {
+        return false;
+    }
+    parts[parts.len() - 2] != parts[parts.len() - 1]
+}
+
 /// Validate that a crate path component contains only safe characters.
 /// Allowed: alphanumeric, hyphen, underscore, dot.
-fn validate_crate_component(s: &str, label: &str) -> Result<()> {
+pub(crate) fn validate_crate_component(s: &str, label: &str) -> Result<()> {
     if s.is_empty() {
         bail!("Empty {} in crate path", label);
     }
@@ -126,6 +556,7 @@ fn validate_crate_component(s: &str, label: &str) -> Result<()> {
 /// Parse a single registry path string like "namespace/crate:tag".
 ///
 /// Defaults: namespace = default_namespace (from config), tag = "default".
+/// Tag aliases are not resolved here; see `manifest_cache::resolve_tag_aliases`.
 pub fn parse_registry_path(path: &str, default_namespace: &str) -> Result<CrateVars> {
     let path = path.trim();
 
@@ -166,13 +597,9 @@ pub(crate) fn is_url(s: &str) -> bool {
     s.starts_with("http://") || s.starts_with("https://")
 }
 
-/// Build the URL for fetching a manifest from the registry.
-fn build_manifest_url(config: &BulkerConfig, cratevars: &CrateVars, filepath: Option<&str>) -> String {
-    if let Some(fp) = filepath {
-        return fp.to_string();
-    }
-
-    let base_url = config.bulker.registry_url.trim_end_matches('/');
+/// Build the URL of a crate's manifest in the registry at `registry_url`.
+pub(crate) fn build_manifest_url(registry_url: &str, cratevars: &CrateVars) -> String {
+    let base_url = registry_url.trim_end_matches('/');
     if cratevars.tag == "default" {
         format!(
             "{}/{}/{}.yaml",
@@ -186,32 +613,56 @@ fn build_manifest_url(config: &BulkerConfig, cratevars: &CrateVars, filepath: Op
     }
 }
 
-/// Load a manifest from a remote URL or local file path.
+/// Load a manifest from a remote URL or local file path. Without
+/// `filepath`, the configured registries are tried in order.
 pub fn load_remote_manifest(
     config: &BulkerConfig,
     registry_path: &str,
     filepath: Option<&str>,
 ) -> Result<(Manifest, CrateVars)> {
     let cratevars = parse_registry_path(registry_path, &config.bulker.default_namespace)?;
-    let url = build_manifest_url(config, &cratevars, filepath);
+    let manifest = match filepath {
+        Some(fp) => load_manifest_url(fp)?,
+        None => fetch_registry_manifest(config, &cratevars)?.0,
+    };
+    Ok((manifest, cratevars))
+}
 
+/// Fetch a crate's manifest from the first of the configured registries
+/// (`BulkerConfig::registry_urls`) that has it. Returns the manifest and
+/// the registry that served it. A registry answering 404 or out of reach
+/// passes the crate on to the next; any other failure is returned.
+pub fn fetch_registry_manifest(config: &BulkerConfig, cratevars: &CrateVars) -> Result<(Manifest, String)> {
+    let registries = config.registry_urls();
+    let mut failures = Vec::new();
+    for registry in &registries {
+        match load_manifest_url(&build_manifest_url(registry, cratevars)) {
+            Ok(manifest) => return Ok((manifest, registry.to_string())),
+            // Only a registry without the crate passes it on; a refused request
+            // or a broken manifest is an answer
+            Err(e) if registries.len() == 1 || !crate::download::is_missing_or_unreachable(&e) => return Err(e),
+            Err(e) => {
+                log::debug!("{} not served by {}: {:#}", cratevars.display_name(), registry, e);
+                failures.push(format!("  {}: {:#}", registry, e));
+            }
+        }
+    }
+    bail!("'{}' was not found in any registry:\n{}", cratevars.display_name(), failures.join("\n"))
+}
+
+/// Load and parse the manifest at `url`, a URL or a file path.
+fn load_manifest_url(url: &str) -> Result<Manifest> {
     log::debug!("Loading manifest from: {}", url);
 
-    let contents = if is_url(&url) {
-        let resp = ureq::get(&url)
-            .call()
-            .with_context(|| format!("Failed to fetch manifest: {}", url))?;
-        resp.into_string()
-            .with_context(|| format!("Failed to read response from: {}", url))?
+    let contents = if is_url(url) {
+        crate::download::fetch_string(url)
+            .with_context(|| format!("Failed to fetch manifest: {}", url))?
     } else {
-        std::fs::read_to_string(&url)
+        read_manifest_file(std::path::Path::new(url))
             .with_context(|| format!("Failed to read manifest file: {}", url))?
     };
 
-    let manifest: Manifest = serde_yml::from_str(&contents)
-        .with_context(|| format!("Failed to parse manifest YAML from: {}", url))?;
-
-    Ok((manifest, cratevars))
+    parse_manifest(&contents).with_context(|| format!("Failed to parse manifest from: {}", url))
 }
 
 /// Detect if a crate argument is a local file path (as opposed to a registry path or URL).
@@ -220,7 +671,8 @@ pub(crate) fn is_local_path(s: &str) -> bool {
         && (s.starts_with('.')
             || s.starts_with('/')
             || s.ends_with(".yaml")
-            || s.ends_with(".yml"))
+            || s.ends_with(".yml")
+            || s.ends_with(".json"))
 }
 
 /// Load a local manifest file, returning the parsed Manifest and derived CrateVars.
@@ -231,16 +683,23 @@ pub(crate) fn is_local_path(s: &str) -> bool {
 /// 3. No name — error
 ///
 /// Tag: from name_override if it includes `:`, else manifest version, else "default".
+///
+/// Imports written as relative file paths (`./base.yaml`) are cached under
+/// the `local` namespace and rewritten to point at those crates.
 pub(crate) fn load_local_manifest(
     path: &str,
     name_override: Option<&str>,
     default_namespace: &str,
 ) -> Result<(CrateVars, Manifest)> {
     let file_path = std::path::Path::new(path);
-    let contents = std::fs::read_to_string(file_path)
-        .map_err(|e| anyhow::anyhow!("Failed to read local manifest '{}': {}", path, e))?;
-    let manifest: Manifest = serde_yml::from_str(&contents)
-        .map_err(|e| anyhow::anyhow!("Failed to parse local manifest '{}': {}", path, e))?;
+    let contents = read_manifest_file(file_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read local manifest '{}': {:#}", path, e))?;
+    let mut manifest = parse_manifest(&contents)
+        .map_err(|e| anyhow::anyhow!("Failed to parse local manifest '{}': {:#}", path, e))?;
+    // Imports written as file paths are resolved against this file's directory
+    let dir = file_path.parent().unwrap_or(std::path::Path::new(""));
+    crate::imports::cache_relative_imports(&mut manifest, dir, &mut std::collections::HashSet::new(), 0)
+        .with_context(|| format!("Failed to resolve file imports of '{}'", path))?;
 
     let cv = if let Some(name) = name_override {
         // --name flag: parse it, and use its tag if present
@@ -272,6 +731,27 @@ pub(crate) fn load_local_manifest(
     Ok((cv, manifest))
 }
 
+/// Returns true if a crate argument means "read the manifest from stdin".
+pub(crate) fn is_stdin_path(s: &str) -> bool {
+    s == "-"
+}
+
+/// Load a manifest from stdin, for generators that pipe manifests into bulker.
+/// The crate identity must come from `name_override` (the `--name` flag);
+/// a `name` field in the piped manifest is not trusted for identity.
+pub(crate) fn load_stdin_manifest(
+    name_override: Option<&str>,
+    default_namespace: &str,
+) -> Result<(CrateVars, Manifest)> {
+    let Some(name) = name_override else {
+        bail!("Reading a manifest from stdin ('-') requires --name (e.g. --name myns/mycrate:1.0)");
+    };
+    let cv = parse_registry_path(name, default_namespace)?;
+    let contents = read_manifest_text(std::io::stdin(), "manifest from stdin")?;
+    let manifest = parse_manifest(&contents).context("Failed to parse manifest from stdin")?;
+    Ok((cv, manifest))
+}
+
 /// Load a manifest from a URL, returning the parsed Manifest and derived CrateVars.
 ///
 /// Identity resolution follows the same logic as `load_local_manifest`:
@@ -283,13 +763,10 @@ pub(crate) fn load_url_manifest(
     name_override: Option<&str>,
     default_namespace: &str,
 ) -> Result<(CrateVars, Manifest)> {
-    let resp = ureq::get(url)
-        .call()
+    let contents = crate::download::fetch_string(url)
         .with_context(|| format!("Failed to fetch manifest from URL: {}", url))?;
-    let contents = resp.into_string()
-        .with_context(|| format!("Failed to read response from: {}", url))?;
-    let manifest: Manifest = serde_yml::from_str(&contents)
-        .with_context(|| format!("Failed to parse manifest YAML from: {}", url))?;
+    let manifest = parse_manifest(&contents)
+        .with_context(|| format!("Failed to parse manifest from: {}", url))?;
 
     let cv = if let Some(name) = name_override {
         parse_registry_path(name, default_namespace)?
@@ -323,24 +800,146 @@ pub(crate) fn merge_lists(primary: &mut Vec<String>, secondary: &[String]) {
     }
 }
 
-/// Compute the apptainer SIF image filename and full path for a docker image.
-/// Returns (image_filename, full_path) where full_path includes the image folder if configured.
+/// Split an image reference into its name, tag and digest:
+/// `quay.io:443/org/tool:1.0@sha256:ab..` -> (`quay.io:443/org/tool`,
+/// Some(`1.0`), Some(`sha256:ab..`)). A colon after the last slash separates
+/// the tag; earlier ones belong to a registry port.
+pub(crate) fn split_image_tag_digest(image: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (rest, digest) = match image.split_once('@') {
+        Some((rest, digest)) => (rest, Some(digest)),
+        None => (image, None),
+    };
+    match rest.rfind(':') {
+        Some(idx) if !rest[idx..].contains('/') => (&rest[..idx], Some(&rest[idx + 1..]), digest),
+        _ => (rest, None, digest),
+    }
+}
+
+/// Check an image digest reference (`sha256:` and 64 hex digits, or
+/// `sha512:` and 128).
+pub(crate) fn check_image_digest(digest: &str) -> Result<()> {
+    let lower_hex = |hex: &str, len: usize| hex.len() == len && hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'));
+    let valid = match digest.split_once(':') {
+        Some(("sha256", hex)) => lower_hex(hex, 64),
+        Some(("sha512", hex)) => lower_hex(hex, 128),
+        _ => false,
+    };
+    if !valid {
+        bail!("Invalid image digest '{}': expected sha256: followed by 64 lowercase hex digits", digest);
+    }
+    Ok(())
+}
+
+/// Validation pass for a newly loaded manifest: every pinned image digest
+/// (`image@sha256:...`) must be well-formed.
+pub fn check_image_references(manifest: &Manifest, source: &str) -> Result<()> {
+    for pkg in &manifest.manifest.commands {
+        if let (_, _, Some(digest)) = split_image_tag_digest(&pkg.docker_image) {
+            check_image_digest(digest).with_context(|| format!("{}: command '{}'", source, pkg.command))?;
+        }
+    }
+    Ok(())
+}
+
+/// Sockets of container engines: a command given one controls the host's
+/// containers, which amounts to root on the host.
+const ENGINE_SOCKETS: &[&str] = &["docker.sock", "podman.sock", "containerd.sock"];
+
+/// Split a `devices`/`sockets` entry into its host and container paths.
+pub(crate) fn split_host_path(spec: &str) -> (&str, &str) {
+    spec.split_once(':').unwrap_or((spec, spec))
+}
+
+/// Validation pass for a newly loaded manifest: warn about commands that get
+/// a container engine socket or host devices, so installing such a crate is
+/// a visible decision.
+pub fn check_host_access(manifest: &Manifest, source: &str) {
+    for pkg in &manifest.manifest.commands {
+        for socket in &pkg.sockets {
+            let (host, _) = split_host_path(socket);
+            if ENGINE_SOCKETS.iter().any(|s| host.ends_with(s)) {
+                log::warn!(
+                    "{}: '{}' gets the container engine socket {}, which gives it control of the host (root-equivalent)",
+                    source, pkg.command, host
+                );
+            }
+        }
+        if !pkg.devices.is_empty() {
+            log::warn!("{}: '{}' gets host devices: {}", source, pkg.command, pkg.devices.join(", "));
+        }
+    }
+}
+
+/// Split a docker image reference into (registry, repository, tag). Docker Hub
+/// images get registry `docker.io` and bare names the `library/` namespace. A
+/// digest (`@sha256:...`) takes the place of the tag, with `:` replaced by `-`.
+fn split_image_ref(docker_image: &str) -> (String, String, String) {
+    let (name, tag, digest) = split_image_tag_digest(docker_image);
+    let tag = match digest {
+        Some(digest) => digest.replace(':', "-"),
+        None => tag.unwrap_or("latest").to_string(),
+    };
+    match name.split_once('/') {
+        Some((first, repo)) if first.contains('.') || first.contains(':') || first == "localhost" => {
+            (first.replace(':', "_"), repo.to_string(), tag)
+        }
+        Some(_) => ("docker.io".to_string(), name.to_string(), tag),
+        None => ("docker.io".to_string(), format!("library/{}", name), tag),
+    }
+}
+
+/// Compute the apptainer SIF image path for a docker image, relative to the image
+/// folder and namespaced by registry and repository, e.g.
+/// `quay.io/biocontainers/samtools-1.9.sif`. Returns (relative_path, full_path)
+/// where full_path includes the image folder if configured.
 pub(crate) fn apptainer_image_paths(docker_image: &str, image_folder: Option<&str>) -> (String, String) {
+    let (registry, repo, tag) = split_image_ref(docker_image);
+    let image_path = format!("{}/{}-{}.sif", registry, repo, tag);
+    let full_path = image_folder
+        .map(|f| format!("{}/{}", f, image_path))
+        .unwrap_or_else(|| image_path.clone());
+    (image_path, full_path)
+}
+
+/// Full path of a SIF in the flat layout used before images were namespaced.
+fn legacy_apptainer_image_path(docker_image: &str, image_folder: Option<&str>) -> String {
     let (img_ns, img_name, img_tag) = parse_docker_image_path(docker_image);
     let image_filename = format!("{}-{}-{}.sif", img_ns, img_name, img_tag);
-    let full_path = image_folder
+    image_folder
         .map(|f| format!("{}/{}", f, image_filename))
-        .unwrap_or_else(|| image_filename.clone());
-    (image_filename, full_path)
+        .unwrap_or(image_filename)
+}
+
+/// Move a SIF from the old flat layout to its namespaced path, so upgrading
+/// doesn't re-pull every image. Returns the expanded namespaced path. Best
+/// effort: if the move fails the image is pulled again.
+pub(crate) fn migrate_legacy_sif(docker_image: &str, image_folder: Option<&str>) -> String {
+    let (_, full_path) = apptainer_image_paths(docker_image, image_folder);
+    let full_path = crate::config::expand_path(&full_path);
+    let legacy = crate::config::expand_path(&legacy_apptainer_image_path(docker_image, image_folder));
+    if std::path::Path::new(&full_path).exists() || !std::path::Path::new(&legacy).is_file() {
+        return full_path;
+    }
+    let moved = std::path::Path::new(&full_path)
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::rename(&legacy, &full_path));
+    match moved {
+        Ok(()) => log::info!("Moved {} to {}", legacy, full_path),
+        Err(e) => log::debug!("Could not move {} to {}: {}", legacy, full_path, e),
+    }
+    full_path
 }
 
 /// Parse a docker image path into (namespace, image_name, tag) for apptainer.
 pub fn parse_docker_image_path(docker_image: &str) -> (String, String, String) {
-    // e.g. "quay.io/biocontainers/samtools:1.9--h91753b0_8"
-    let (name_part, tag) = if let Some(idx) = docker_image.rfind(':') {
-        (&docker_image[..idx], docker_image[idx + 1..].to_string())
-    } else {
-        (docker_image, "latest".to_string())
+    // e.g. "quay.io/biocontainers/samtools:1.9--h91753b0_8". A digest-only
+    // reference uses the digest as its tag (`sha256-...`).
+    let (name_part, tag, digest) = split_image_tag_digest(docker_image);
+    let tag = match (tag, digest) {
+        (Some(tag), _) => tag.to_string(),
+        (None, Some(digest)) => digest.replace(':', "-"),
+        (None, None) => "latest".to_string(),
     };
 
     // Get the last path component as image name, rest as namespace
@@ -438,25 +1037,23 @@ mod tests {
 
     #[test]
     fn test_build_manifest_url_default_tag_omits_suffix() {
-        let config = crate::config::BulkerConfig::test_with_registry("http://hub.bulker.io/");
         let cv = CrateVars {
             namespace: "bulker".to_string(),
             crate_name: "alpine".to_string(),
             tag: "default".to_string(),
         };
-        let url = build_manifest_url(&config, &cv, None);
+        let url = build_manifest_url("http://hub.bulker.io/", &cv);
         assert_eq!(url, "http://hub.bulker.io/bulker/alpine.yaml");
     }
 
     #[test]
     fn test_build_manifest_url_versioned_tag_includes_suffix() {
-        let config = crate::config::BulkerConfig::test_with_registry("http://hub.bulker.io/");
         let cv = CrateVars {
             namespace: "databio".to_string(),
             crate_name: "pepatac".to_string(),
             tag: "1.0.13".to_string(),
         };
-        let url = build_manifest_url(&config, &cv, None);
+        let url = build_manifest_url("http://hub.bulker.io/", &cv);
         assert_eq!(url, "http://hub.bulker.io/databio/pepatac_1.0.13.yaml");
     }
 
@@ -499,6 +1096,28 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_port_is_remapped() {
+        assert!(!port_is_remapped("8080"));
+        assert!(!port_is_remapped("8080:8080"));
+        assert!(!port_is_remapped("127.0.0.1:8080:8080/tcp"));
+        assert!(port_is_remapped("8080:80"));
+        assert!(port_is_remapped("127.0.0.1:8080:80/udp"));
+    }
+
+    #[test]
+    fn test_manifest_ports_parse() {
+        let yaml = r#"manifest:
+  name: test
+  commands:
+  - command: igv-server
+    docker_image: igv/server
+    ports: ["60151:60151", "8080"]
+"#;
+        let manifest: Manifest = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(manifest.manifest.commands[0].ports, vec!["60151:60151", "8080"]);
+    }
+
     #[test]
     fn test_parse_docker_image_path() {
         let (ns, img, tag) = parse_docker_image_path("quay.io/biocontainers/samtools:1.9--h91753b0_8");
@@ -515,6 +1134,45 @@ mod tests {
         assert_eq!(tag, "3.7.4");
     }
 
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_image_references_with_digests() {
+        let pinned = format!("quay.io/biocontainers/samtools@{}", DIGEST);
+        assert_eq!(split_image_tag_digest(&pinned), ("quay.io/biocontainers/samtools", None, Some(DIGEST)));
+        let both = format!("localhost:5000/tool:1.2@{}", DIGEST);
+        assert_eq!(split_image_tag_digest(&both), ("localhost:5000/tool", Some("1.2"), Some(DIGEST)));
+        assert_eq!(split_image_tag_digest("localhost:5000/tool"), ("localhost:5000/tool", None, None));
+
+        let hex = &DIGEST["sha256:".len()..];
+        assert_eq!(
+            parse_docker_image_path(&pinned),
+            ("quay-io-biocontainers".to_string(), "samtools".to_string(), format!("sha256-{}", hex))
+        );
+        assert_eq!(parse_docker_image_path(&both).2, "1.2");
+        assert_eq!(
+            apptainer_image_paths(&pinned, None).0,
+            format!("quay.io/biocontainers/samtools-sha256-{}.sif", hex)
+        );
+    }
+
+    #[test]
+    fn test_check_image_references_rejects_malformed_digests() {
+        let manifest = |image: &str| Manifest {
+            manifest: ManifestInner {
+                commands: vec![PackageCommand { command: "tool".into(), docker_image: image.into(), ..Default::default() }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(check_image_references(&manifest(&format!("tool@{}", DIGEST)), "t").is_ok());
+        assert!(check_image_references(&manifest("tool:1.0"), "t").is_ok());
+        for bad in ["tool@sha256:abc", "tool@md5:0123456789abcdef0123456789abcdef", &format!("tool@{}", DIGEST.to_uppercase())] {
+            let err = check_image_references(&manifest(bad), "t").unwrap_err();
+            assert!(format!("{:#}", err).contains("Invalid image digest"), "{:#}", err);
+        }
+    }
+
     fn write_temp_manifest(yaml: &str) -> tempfile::NamedTempFile {
         use std::io::Write;
         let mut f = tempfile::NamedTempFile::new().unwrap();
@@ -598,13 +1256,41 @@ mod tests {
     #[test]
     fn test_apptainer_image_paths_includes_tag() {
         let (filename, _) = apptainer_image_paths("quay.io/biocontainers/samtools:1.9--h91753b0_8", None);
-        assert_eq!(filename, "quay-io-biocontainers-samtools-1.9--h91753b0_8.sif");
+        assert_eq!(filename, "quay.io/biocontainers/samtools-1.9--h91753b0_8.sif");
     }
 
     #[test]
     fn test_apptainer_image_paths_no_tag_uses_latest() {
         let (filename, _) = apptainer_image_paths("python", None);
-        assert_eq!(filename, "docker-python-latest.sif");
+        assert_eq!(filename, "docker.io/library/python-latest.sif");
+    }
+
+    #[test]
+    fn test_apptainer_image_paths_namespaced_by_registry() {
+        let (hub, _) = apptainer_image_paths("biocontainers/samtools:1.9", None);
+        let (quay, _) = apptainer_image_paths("quay.io/biocontainers/samtools:1.9", None);
+        assert_eq!(hub, "docker.io/biocontainers/samtools-1.9.sif");
+        assert_ne!(hub, quay);
+        let (port, _) = apptainer_image_paths("localhost:5000/tools/bwa", None);
+        assert_eq!(port, "localhost_5000/tools/bwa-latest.sif");
+        let (digest, _) = apptainer_image_paths("org/tool:1.0@sha256:abc123", None);
+        assert_eq!(digest, "docker.io/org/tool-sha256-abc123.sif");
+    }
+
+    #[test]
+    fn test_migrate_legacy_sif() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+        std::fs::write(dir.path().join("quay-io-biocontainers-samtools-1.9.sif"), "sif").unwrap();
+        let path = migrate_legacy_sif("quay.io/biocontainers/samtools:1.9", Some(folder));
+        assert_eq!(path, format!("{}/quay.io/biocontainers/samtools-1.9.sif", folder));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "sif");
+        assert!(!dir.path().join("quay-io-biocontainers-samtools-1.9.sif").exists());
+        // Nothing to migrate: path is still returned
+        assert_eq!(
+            migrate_legacy_sif("python:3.12", Some(folder)),
+            format!("{}/docker.io/library/python-3.12.sif", folder)
+        );
     }
 
     #[test]
@@ -617,6 +1303,116 @@ mod tests {
     #[test]
     fn test_apptainer_image_paths_with_folder() {
         let (_, full_path) = apptainer_image_paths("python:3.12", Some("/images"));
-        assert_eq!(full_path, "/images/docker-python-3.12.sif");
+        assert_eq!(full_path, "/images/docker.io/library/python-3.12.sif");
+    }
+
+    #[test]
+    fn test_parse_manifest_resolves_anchors_and_merge_keys() {
+        let yaml = r#"
+manifest:
+  name: bulker/anchors
+  commands:
+  - &base
+    command: samtools
+    docker_image: quay.io/biocontainers/samtools:1.9
+    no_network: true
+  - <<: *base
+    command: bcftools
+"#;
+        let manifest = parse_manifest(yaml).unwrap();
+        let cmds = &manifest.manifest.commands;
+        assert_eq!(cmds[1].command, "bcftools");
+        assert_eq!(cmds[1].docker_image, "quay.io/biocontainers/samtools:1.9");
+        assert!(cmds[1].no_network);
+    }
+
+    #[test]
+    fn test_to_canonical_yaml_is_stable_and_omits_defaults() {
+        let yaml = "manifest:\n  commands:\n  - docker_image: img:1\n    command: tool\n    workdir: null\n  name: bulker/canon\n";
+        let canonical = to_canonical_yaml(&parse_manifest(yaml).unwrap()).unwrap();
+        assert!(!canonical.contains("null"), "defaults should be omitted: {}", canonical);
+        assert!(!canonical.contains("no_user"), "defaults should be omitted: {}", canonical);
+        assert!(canonical.find("name:").unwrap() < canonical.find("commands:").unwrap());
+        assert!(canonical.find("command: tool").unwrap() < canonical.find("docker_image").unwrap());
+        let again = to_canonical_yaml(&parse_manifest(&canonical).unwrap()).unwrap();
+        assert_eq!(canonical, again);
+    }
+
+    #[test]
+    fn test_parse_manifest_accepts_json() {
+        let json = r#"{"manifest": {"name": "bulker/json", "commands": [
+            {"command": "cowsay", "docker_image": "nsheff/cowsay", "no_user": true}
+        ], "imports": null}}"#;
+        let manifest = parse_manifest(json).unwrap();
+        assert_eq!(manifest.manifest.name.as_deref(), Some("bulker/json"));
+        assert!(manifest.manifest.commands[0].no_user);
+        assert!(manifest.manifest.imports.is_empty());
+        assert!(is_local_path("crate.json"));
+    }
+
+    #[test]
+    fn test_platform_and_pull_policy_fields() {
+        let yaml = "manifest:\n  commands:\n  - command: tool\n    docker_image: img:1\n    platform: linux/arm64/v8\n    pull_policy: never\n";
+        let pkg = &parse_manifest(yaml).unwrap().manifest.commands[0];
+        assert_eq!(pkg.platform.as_deref(), Some("linux/arm64/v8"));
+        assert_eq!(pkg.platform_arch(), Some("arm64"));
+        assert_eq!(pkg.pull_policy(), Some(PullPolicy::Never));
+
+        let bare = PackageCommand { platform: Some("amd64".to_string()), pull_policy: Some("sometimes".to_string()), ..Default::default() };
+        assert_eq!(bare.platform_arch(), Some("amd64"));
+        assert_eq!(bare.pull_policy(), None);
+    }
+
+    #[test]
+    fn test_deprecations_warn_or_fail_in_strict_mode() {
+        let manifest = parse_manifest(
+            "manifest:\n  commands:\n  - command: a\n    docker_image: alpine\n    dockerargs: -p 80\n    docker_command: run\n  - command: b\n    docker_image: alpine\n",
+        )
+        .unwrap();
+        let found = manifest.deprecations();
+        assert_eq!(found.iter().map(|d| d.field).collect::<Vec<_>>(), vec!["dockerargs", "docker_command"]);
+        assert_eq!(found[0].to_string(), "'a': `dockerargs` is deprecated; use `docker_args`");
+
+        assert!(check_deprecations_with(&manifest, "t/a:1", false).is_ok());
+        let err = check_deprecations_with(&manifest, "t/a:1", true).unwrap_err().to_string();
+        assert!(err.contains("t/a:1 uses deprecated fields"), "{}", err);
+        assert!(err.contains("docker_command"), "{}", err);
+    }
+
+    #[test]
+    fn test_manifest_size_limit() {
+        assert!(check_manifest_size(MAX_MANIFEST_BYTES, "m.yaml").is_ok());
+        let err = check_manifest_size(MAX_MANIFEST_BYTES + 1, "m.yaml").unwrap_err().to_string();
+        assert!(err.contains("m.yaml is 64.0 MiB"), "{}", err);
+        let text = read_manifest_text("manifest:\n  commands: []\n".as_bytes(), "m.yaml").unwrap();
+        assert!(parse_manifest(&text).unwrap().manifest.commands.is_empty());
+    }
+
+    #[test]
+    fn test_check_command_name() {
+        for ok in ["samtools", "bowtie2-build", "R", "_private"] {
+            check_command_name(ok).unwrap();
+        }
+        for bad in ["", "../evil", "bin/tool", ".hidden", "tool\nrm -rf ~", "tab\there"] {
+            assert!(check_command_name(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_commands_filter() {
+        let filter = CommandsFilter::parse(["samtools, bcf*", "tabix"]).unwrap();
+        assert!(filter.matches("samtools") && filter.matches("bcftools") && filter.matches("tabix"));
+        assert!(!filter.matches("samtools2") && !filter.matches("bwa"));
+        assert!(CommandsFilter::parse(["samtools,"]).is_err());
+        assert!(CommandsFilter::parse(["bin/samtools"]).is_err());
+        assert!(CommandsFilter::default().matches("anything"));
+
+        let mut manifest = parse_manifest(
+            "manifest:\n  commands:\n  - {command: samtools, docker_image: a}\n  - {command: bwa, docker_image: b}\n",
+        )
+        .unwrap();
+        filter.apply(&mut manifest);
+        let names: Vec<&str> = manifest.manifest.commands.iter().map(|p| p.command.as_str()).collect();
+        assert_eq!(names, vec!["samtools"]);
     }
 }
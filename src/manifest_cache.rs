@@ -1,59 +1,199 @@
 //! Standalone manifest cache. Stores and retrieves crate manifests in a
-//! filesystem-based cache at ~/.config/bulker/manifests/<ns>/<name>/<tag>/manifest.yaml.
+//! filesystem-based cache at `$XDG_CACHE_HOME/bulker/manifests/<ns>/<name>/<tag>/manifest.yaml`
+//! (overridable with the `cache_dir` config key).
 //! Decoupled from the config `crates` map — activate auto-fetches on demand.
+//!
+//! With `--cache-scope project`, manifests are written to `./.bulker/manifests`
+//! instead, and that directory is searched before the user cache.
 
 use anyhow::{Context, Result};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::path::PathBuf;
+use std::sync::RwLock;
 
 use crate::config::BulkerConfig;
+use crate::cache_permissions;
 use crate::digest;
-use crate::manifest::{CrateVars, Manifest, load_remote_manifest, parse_registry_path};
+use crate::manifest::{CrateVars, Manifest, fetch_registry_manifest};
 use crate::templates;
 
 /// Maximum recursion depth for import resolution. Prevents stack overflow
 /// from pathologically deep (but non-cyclic) import chains.
 pub const MAX_IMPORT_DEPTH: usize = 32;
 
-/// Get the base cache directory for manifests.
-pub fn cache_base_dir() -> PathBuf {
+/// Cache directory from the `cache_dir` config key, registered when a bulker
+/// command loads its config.
+static CACHE_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Set (or clear) the configured cache directory. Bulker commands call it
+/// whenever they load a config; library users call it to honour `cache_dir`.
+pub fn set_cache_dir_override(dir: Option<PathBuf>) {
+    *CACHE_DIR_OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = dir;
+}
+
+/// Default cache root: $XDG_CACHE_HOME/bulker (~/.cache/bulker).
+fn default_cache_root() -> PathBuf {
+    let cache_dir = dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.cache"));
+    cache_dir.join("bulker")
+}
+
+/// Manifest cache location used before caches moved out of the config dir.
+fn legacy_cache_base_dir() -> PathBuf {
     let config_dir = dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("~/.config"));
     config_dir.join("bulker").join("manifests")
 }
 
-/// Get the cache path for a specific crate's manifest.
+/// Absolute path of the project manifest cache, exported to child processes
+/// (shimlinks resolve from it after the shell changes directory).
+pub const PROJECT_CACHE_ENV: &str = "BULKER_PROJECT_CACHE";
+
+/// Where `--cache-scope project` keeps manifests, relative to the project root.
+pub const PROJECT_CACHE_DIR: &str = ".bulker/manifests";
+
+/// The project manifest cache, if `--cache-scope project` is in effect.
+pub fn project_cache_dir() -> Option<PathBuf> {
+    std::env::var_os(PROJECT_CACHE_ENV).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+/// Project cache for a command run in `cwd`: the nearest enclosing
+/// `.bulker/manifests`, so subdirectories share it, else one in `cwd`.
+pub fn find_project_cache(cwd: &std::path::Path) -> PathBuf {
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_CACHE_DIR))
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| cwd.join(PROJECT_CACHE_DIR))
+}
+
+/// The user manifest cache under the cache root.
+fn user_cache_dir() -> PathBuf {
+    cache_root_dir().join("manifests")
+}
+
+/// Get the base cache directory for manifests: the directory new manifests
+/// are written to (the project cache when one is in effect).
+pub fn cache_base_dir() -> PathBuf {
+    project_cache_dir().unwrap_or_else(user_cache_dir)
+}
+
+/// Manifest caches in lookup order: project first, then user.
+fn search_dirs() -> Vec<PathBuf> {
+    project_cache_dir().into_iter().chain(std::iter::once(user_cache_dir())).collect()
+}
+
+fn crate_dir(base: &std::path::Path, cv: &CrateVars) -> PathBuf {
+    base.join(&cv.namespace).join(&cv.crate_name).join(&cv.tag)
+}
+
+/// True if the crate resolves from the project cache.
+pub fn is_project_cached(cv: &CrateVars) -> bool {
+    project_cache_dir().is_some_and(|dir| crate_dir(&dir, cv).join("manifest.yaml").exists())
+}
+
+/// Root of bulker's cache (`cache_dir` if configured, else $XDG_CACHE_HOME/bulker).
+/// Holds the manifest cache and small state files such as the last failure.
+pub fn cache_root_dir() -> PathBuf {
+    CACHE_DIR_OVERRIDE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_else(default_cache_root)
+}
+
+/// Move a manifest cache left in the legacy config-dir location to the default
+/// cache location. No-op if there is nothing to move or the new cache exists.
+pub fn migrate_legacy_cache() {
+    migrate_cache_dir(&legacy_cache_base_dir(), &default_cache_root().join("manifests"));
+}
+
+fn migrate_cache_dir(legacy: &std::path::Path, new: &std::path::Path) {
+    if legacy == new || !legacy.is_dir() {
+        return;
+    }
+    if new.exists() {
+        log::debug!(
+            "Legacy manifest cache {} left in place; {} already exists",
+            legacy.display(),
+            new.display()
+        );
+        return;
+    }
+    let moved = new
+        .parent()
+        .map(std::fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| std::fs::rename(legacy, new));
+    match moved {
+        Ok(()) => log::info!("Moved manifest cache from {} to {}", legacy.display(), new.display()),
+        Err(e) => log::warn!(
+            "Could not move manifest cache from {} to {}: {}. Move it manually, or set \
+             `cache_dir` to keep using the old location.",
+            legacy.display(),
+            new.display(),
+            e
+        ),
+    }
+}
+
+/// Get the cache path for a specific crate's manifest: the first cache that
+/// holds it, else where it would be written.
 pub fn manifest_path(cv: &CrateVars) -> PathBuf {
-    cache_base_dir()
-        .join(&cv.namespace)
-        .join(&cv.crate_name)
-        .join(&cv.tag)
-        .join("manifest.yaml")
+    search_dirs()
+        .iter()
+        .map(|dir| crate_dir(dir, cv).join("manifest.yaml"))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| write_path(cv))
+}
+
+/// Where a crate's manifest is written: always the current scope's cache, so
+/// a project install never updates the user cache.
+fn write_path(cv: &CrateVars) -> PathBuf {
+    crate_dir(&cache_base_dir(), cv).join("manifest.yaml")
 }
 
-/// Get the path for a digest sidecar file next to the cached manifest.
-fn digest_sidecar_path(cv: &CrateVars, filename: &str) -> PathBuf {
-    cache_base_dir()
-        .join(&cv.namespace)
-        .join(&cv.crate_name)
-        .join(&cv.tag)
-        .join(filename)
+/// Get the path for a sidecar file (digest, timestamp) next to the cached manifest.
+fn sidecar_path(cv: &CrateVars, filename: &str) -> PathBuf {
+    manifest_path(cv).with_file_name(filename)
 }
 
 /// Read a cached digest sidecar file. Returns None if not present.
 pub fn read_digest_sidecar(cv: &CrateVars, filename: &str) -> Option<String> {
-    let path = digest_sidecar_path(cv, filename);
+    let path = sidecar_path(cv, filename);
     std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
 }
 
 /// Write a digest sidecar file.
 pub fn write_digest_sidecar(cv: &CrateVars, filename: &str, digest: &str) -> Result<()> {
-    let path = digest_sidecar_path(cv, filename);
-    std::fs::write(&path, digest)
+    let path = sidecar_path(cv, filename);
+    cache_permissions::write(&path, digest)
         .with_context(|| format!("Failed to write digest sidecar: {}", path.display()))?;
     Ok(())
 }
 
+/// Current time as unix seconds.
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Read a timestamp sidecar (unix seconds). Returns None if missing or unparseable.
+pub fn read_timestamp_sidecar(cv: &CrateVars, filename: &str) -> Option<u64> {
+    read_digest_sidecar(cv, filename).and_then(|s| s.parse().ok())
+}
+
+/// Record the current time in the `last-used` sidecar of a cached crate.
+/// Called on activate/exec. Skipped silently if the crate is not cached.
+pub fn touch_last_used(cv: &CrateVars) -> Result<()> {
+    // A project cache is often checked in; don't dirty it on every activation
+    if !manifest_path(cv).exists() || is_project_cached(cv) {
+        return Ok(());
+    }
+    write_digest_sidecar(cv, "last-used", &now_secs().to_string())
+}
+
 /// Ensure the crate-manifest-digest sidecar exists. Computes and saves it if missing.
 pub fn ensure_crate_manifest_digest(cv: &CrateVars) -> Result<Option<String>> {
     if let Some(d) = read_digest_sidecar(cv, "crate-manifest-digest") {
@@ -71,35 +211,64 @@ pub fn ensure_crate_manifest_digest(cv: &CrateVars) -> Result<Option<String>> {
 
 /// Load a manifest from the filesystem cache. Returns None if not cached.
 pub fn load_cached(cv: &CrateVars) -> Result<Option<Manifest>> {
+    let _span = crate::timings::span("cache_read");
     let path = manifest_path(cv);
     if !path.exists() {
         return Ok(None);
     }
-    let contents = std::fs::read_to_string(&path)
+    let contents = crate::manifest::read_manifest_file(&path)
         .with_context(|| format!("Failed to read cached manifest: {}", path.display()))?;
-    let manifest: Manifest = serde_yml::from_str(&contents)
+    let mut manifest = crate::manifest::parse_manifest(&contents)
         .with_context(|| format!("Failed to parse cached manifest: {}", path.display()))?;
+    // manifest.yaml is canonical; the authored text is kept beside it
+    manifest.source = std::fs::read_to_string(sidecar_path(cv, SOURCE_SIDECAR)).ok();
     Ok(Some(manifest))
 }
 
 /// Save a manifest to the filesystem cache.
 pub fn save_to_cache(cv: &CrateVars, manifest: &Manifest) -> Result<()> {
-    let path = manifest_path(cv);
+    crate::manifest::check_deprecations(manifest, &cv.display_name())?;
+    crate::manifest::check_image_references(manifest, &cv.display_name())?;
+    crate::manifest::check_host_access(manifest, &cv.display_name());
+    let path = write_manifest(cv, manifest)?;
+
+    // Record install time
+    let _ = cache_permissions::write(&path.parent().unwrap().join("installed-at"), now_secs().to_string());
+
+    Ok(())
+}
+
+/// Sidecar holding the manifest text as authored, comments included.
+pub const SOURCE_SIDECAR: &str = "manifest.source";
+
+/// Write a cached manifest, its crate-manifest-digest sidecar and its
+/// authored text (if known), leaving the timestamp sidecars alone. Returns
+/// the manifest path.
+pub fn write_manifest(cv: &CrateVars, manifest: &Manifest) -> Result<PathBuf> {
+    let path = write_path(cv);
     if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)
+        cache_permissions::create_dir_all(parent)
             .with_context(|| format!("Failed to create cache dir: {}", parent.display()))?;
     }
-    let yaml = serde_yml::to_string(manifest)
-        .context("Failed to serialize manifest")?;
-    std::fs::write(&path, &yaml)
+    let yaml = crate::manifest::to_canonical_yaml(manifest)?;
+    cache_permissions::write(&path, &yaml)
         .with_context(|| format!("Failed to write manifest cache: {}", path.display()))?;
 
     // Compute and store crate-manifest-digest sidecar
     let result = digest::crate_manifest_digest(manifest);
     let sidecar = path.parent().unwrap().join("crate-manifest-digest");
-    let _ = std::fs::write(&sidecar, &result.digest);
-
-    Ok(())
+    let _ = cache_permissions::write(&sidecar, &result.digest);
+
+    let source = path.parent().unwrap().join(SOURCE_SIDECAR);
+    match manifest.source {
+        Some(ref text) => cache_permissions::write(&source, text)
+            .with_context(|| format!("Failed to write manifest source: {}", source.display()))?,
+        // Text from an earlier version would no longer match
+        None => {
+            let _ = std::fs::remove_file(&source);
+        }
+    }
+    Ok(path)
 }
 
 /// Ensure a manifest is cached. Fetches from registry if not present.
@@ -112,7 +281,10 @@ pub fn ensure_cached(config: &BulkerConfig, cv: &CrateVars, force: bool, no_over
         }
     }
     log::info!("Fetching manifest: {}", cv.display_name());
-    let (manifest, _) = load_remote_manifest(config, &cv.display_name(), None)?;
+    let (manifest, registry) = fetch_registry_manifest(config, cv)?;
+    if config.registry_urls().len() > 1 {
+        log::info!("Fetched {} from {}", cv.display_name(), registry);
+    }
 
     // Check if we're about to overwrite a different cached version
     if force {
@@ -167,22 +339,36 @@ pub fn ensure_cached_with_imports(
 
     let manifest = ensure_cached(config, cv, force, no_overwrite)?;
     for import_path in &manifest.manifest.imports {
-        let import_cv = parse_registry_path(import_path, &config.bulker.default_namespace)?;
+        let import_cv = crate::imports::resolve_import(config, import_path)?;
         ensure_cached_with_imports(config, &import_cv, force, no_overwrite, visited, depth + 1)?;
     }
     Ok(manifest)
 }
 
-/// List all cached manifests by walking the cache directory tree.
+/// List all cached manifests by walking the cache directory trees. A crate
+/// in the project cache hides the same crate in the user cache.
 /// Returns Vec<(CrateVars, PathBuf)> sorted by namespace/crate/tag.
 pub fn list_cached() -> Result<Vec<(CrateVars, PathBuf)>> {
-    let base = cache_base_dir();
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+    for base in search_dirs() {
+        for (cv, path) in list_cached_in(&base)? {
+            if seen.insert(cv.display_name()) {
+                results.push((cv, path));
+            }
+        }
+    }
+    results.sort_by(|a, b| a.0.display_name().cmp(&b.0.display_name()));
+    Ok(results)
+}
+
+fn list_cached_in(base: &std::path::Path) -> Result<Vec<(CrateVars, PathBuf)>> {
     let mut results = Vec::new();
     if !base.exists() {
         return Ok(results);
     }
     // Walk: base/<namespace>/<crate_name>/<tag>/manifest.yaml
-    for ns_entry in std::fs::read_dir(&base)? {
+    for ns_entry in std::fs::read_dir(base)? {
         let ns_entry = ns_entry?;
         if !ns_entry.file_type()?.is_dir() { continue; }
         let namespace = ns_entry.file_name().to_string_lossy().to_string();
@@ -204,19 +390,18 @@ pub fn list_cached() -> Result<Vec<(CrateVars, PathBuf)>> {
             }
         }
     }
-    results.sort_by(|a, b| a.0.display_name().cmp(&b.0.display_name()));
     Ok(results)
 }
 
-/// Remove a cached manifest. Cleans up empty parent directories.
+/// Remove a cached manifest and its sidecars. Cleans up empty parent directories.
 pub fn remove_cached(cv: &CrateVars) -> Result<()> {
     let path = manifest_path(cv);
-    if path.exists() {
-        std::fs::remove_file(&path)?;
+    // The tag dir holds the manifest and its sidecars (digests, timestamps)
+    if let Some(tag_dir) = path.parent().filter(|d| d.exists()) {
+        std::fs::remove_dir_all(tag_dir)?;
     }
-    // Clean up empty parent dirs (tag -> crate_name -> namespace)
+    // Clean up empty parent dirs (crate_name -> namespace)
     for ancestor in &[
-        path.parent(),
         path.parent().and_then(|p| p.parent()),
         path.parent().and_then(|p| p.parent()).and_then(|p| p.parent()),
     ] {
@@ -227,6 +412,144 @@ pub fn remove_cached(cv: &CrateVars) -> Result<()> {
     Ok(())
 }
 
+/// Move a cached crate, sidecars included, to another identity. Fails if
+/// the destination is already cached.
+pub fn move_cached(from: &CrateVars, to: &CrateVars) -> Result<()> {
+    let src = manifest_path(from);
+    let dest = write_path(to);
+    if dest.exists() {
+        anyhow::bail!("'{}' is already cached", to.display_name());
+    }
+    // The tag dirs hold the manifest and its sidecars
+    let (src_dir, dest_dir) = (src.parent().unwrap(), dest.parent().unwrap());
+    cache_permissions::create_dir_all(dest_dir.parent().unwrap())
+        .with_context(|| format!("Failed to create cache dir: {}", dest_dir.display()))?;
+    // An empty leftover tag dir would make the rename fail
+    let _ = std::fs::remove_dir(dest_dir);
+    std::fs::rename(src_dir, dest_dir)
+        .with_context(|| format!("Failed to move {} to {}", src_dir.display(), dest_dir.display()))?;
+    for dir in [src_dir.parent(), src_dir.parent().and_then(|p| p.parent())].into_iter().flatten() {
+        let _ = std::fs::remove_dir(dir); // fails silently if not empty
+    }
+    Ok(())
+}
+
+/// Freeform notes on a cached crate (`project: encode-rerun`), kept in its
+/// tag dir so they go wherever the crate's other sidecars go.
+pub const ANNOTATIONS_FILE: &str = "annotations.yaml";
+
+/// Annotations of a cached crate, key -> value.
+pub fn read_annotations(cv: &CrateVars) -> BTreeMap<String, String> {
+    let path = sidecar_path(cv, ANNOTATIONS_FILE);
+    let Ok(contents) = std::fs::read_to_string(&path) else { return BTreeMap::new() };
+    serde_yml::from_str(&contents).unwrap_or_else(|e| {
+        log::warn!("Ignoring malformed annotations {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+/// Replace the annotations of a cached crate.
+pub fn write_annotations(cv: &CrateVars, annotations: &BTreeMap<String, String>) -> Result<()> {
+    let path = sidecar_path(cv, ANNOTATIONS_FILE);
+    if annotations.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    cache_permissions::write(&path, serde_yml::to_string(annotations)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Tag aliases of a crate (`stable` -> `1.0.14`), kept next to its tag dirs.
+const ALIASES_FILE: &str = "aliases.yaml";
+
+fn aliases_path(base: &std::path::Path, namespace: &str, crate_name: &str) -> PathBuf {
+    base.join(namespace).join(crate_name).join(ALIASES_FILE)
+}
+
+fn read_aliases_in(path: &std::path::Path) -> BTreeMap<String, String> {
+    let Ok(contents) = std::fs::read_to_string(path) else { return BTreeMap::new() };
+    serde_yml::from_str(&contents).unwrap_or_else(|e| {
+        log::warn!("Ignoring malformed tag aliases {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+/// Tag aliases of a crate, alias -> tag. A project cache's aliases take
+/// precedence over the user cache's.
+pub fn read_aliases(namespace: &str, crate_name: &str) -> BTreeMap<String, String> {
+    let mut aliases = BTreeMap::new();
+    for base in search_dirs().iter().rev() {
+        aliases.extend(read_aliases_in(&aliases_path(base, namespace, crate_name)));
+    }
+    aliases
+}
+
+/// Replace a crate's tag aliases in the current scope's cache.
+pub fn write_aliases(namespace: &str, crate_name: &str, aliases: &BTreeMap<String, String>) -> Result<()> {
+    let path = aliases_path(&cache_base_dir(), namespace, crate_name);
+    if aliases.is_empty() {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to remove {}", path.display()));
+            }
+            _ => return Ok(()),
+        }
+    }
+    if let Some(dir) = path.parent() {
+        cache_permissions::create_dir_all(dir).with_context(|| format!("Failed to create cache dir: {}", dir.display()))?;
+    }
+    cache_permissions::write(&path, serde_yml::to_string(aliases)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The tag `tag` of a crate is an alias for, if it is one. Aliases point at
+/// tags, never at other aliases.
+pub fn resolve_tag_alias(namespace: &str, crate_name: &str, tag: &str) -> Option<String> {
+    let target = read_aliases(namespace, crate_name).remove(tag)?;
+    log::debug!("{}/{}:{} is an alias for tag {}", namespace, crate_name, tag, target);
+    Some(target)
+}
+
+/// `cratelist` with each tag that is an alias replaced by the tag it points
+/// at. Commands that take crates from the user (activate, exec, install)
+/// call this after parsing.
+pub fn resolve_tag_aliases(cratelist: Vec<CrateVars>) -> Vec<CrateVars> {
+    cratelist
+        .into_iter()
+        .map(|cv| {
+            let tag = resolve_tag_alias(&cv.namespace, &cv.crate_name, &cv.tag)
+                .filter(|target| crate::manifest::validate_crate_component(target, "tag").is_ok())
+                .unwrap_or(cv.tag);
+            CrateVars { tag, ..cv }
+        })
+        .collect()
+}
+
+/// Pull progress goes to stderr, so it can't corrupt output meant for
+/// `eval` (e.g. `activate --echo --prefetch`).
+fn progress_stdio() -> std::process::Stdio {
+    use std::os::fd::AsFd;
+    std::io::stderr()
+        .as_fd()
+        .try_clone_to_owned()
+        .map(std::process::Stdio::from)
+        .unwrap_or_else(|_| std::process::Stdio::inherit())
+}
+
+/// Sidecar recording the container engine a crate's images were last pulled
+/// or built with.
+pub const BUILD_ENGINE_SIDECAR: &str = "build-engine";
+
+/// Pull the images of cached crate `cv` and record the engine they were pulled with.
+pub fn pull_crate_images(config: &BulkerConfig, cv: &CrateVars, manifest: &Manifest) -> Result<()> {
+    pull_images(config, manifest)?;
+    write_digest_sidecar(cv, BUILD_ENGINE_SIDECAR, &config.bulker.container_engine)
+}
+
 /// Pull container images for all commands in a manifest.
 /// For apptainer images, uses file locking to prevent concurrent pulls.
 pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
@@ -235,6 +558,11 @@ pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
 
     for pkg in &manifest.manifest.commands {
         let extra_args = config.host_tool_specific_args(pkg, "docker_args");
+        let pull_policy = pkg.pull_policy();
+        if pull_policy == Some(crate::manifest::PullPolicy::Never) {
+            log::info!("Skipping pull for '{}' (pull_policy: never)", pkg.command);
+            continue;
+        }
 
         if is_apptainer {
             let (apptainer_image, apptainer_fullpath) = crate::manifest::apptainer_image_paths(
@@ -242,10 +570,14 @@ pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
                 config.bulker.apptainer_image_folder.as_deref(),
             );
 
-            let fullpath = crate::config::expand_path(&apptainer_fullpath);
+            let fullpath = crate::manifest::migrate_legacy_sif(
+                &pkg.docker_image,
+                config.bulker.apptainer_image_folder.as_deref(),
+            );
 
-            // Fast path: already exists
-            if std::path::Path::new(&fullpath).exists() {
+            // Fast path: already exists (pull_policy: always re-pulls)
+            let always = pull_policy == Some(crate::manifest::PullPolicy::Always);
+            if !always && std::path::Path::new(&fullpath).exists() {
                 log::info!("Image already exists for '{}': {}", pkg.command, fullpath);
                 continue;
             }
@@ -253,7 +585,7 @@ pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
             // Create parent directory if needed
             if let Some(parent) = std::path::Path::new(&fullpath).parent() {
                 if !parent.exists() {
-                    std::fs::create_dir_all(parent)
+                    cache_permissions::create_dir_all(parent)
                         .with_context(|| format!("Failed to create image directory: {}", parent.display()))?;
                 }
             }
@@ -263,7 +595,7 @@ pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
             let _lock = crate::filelock::FileLock::acquire(std::path::Path::new(&lock_path))?;
 
             // Re-check after acquiring lock
-            if std::path::Path::new(&fullpath).exists() {
+            if !always && std::path::Path::new(&fullpath).exists() {
                 log::info!("Image already exists for '{}': {} (acquired after lock)", pkg.command, fullpath);
                 continue;
             }
@@ -282,11 +614,14 @@ pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
             let status = std::process::Command::new("/bin/sh")
                 .arg("-c")
                 .arg(&build_content)
+                .stdout(progress_stdio())
                 .status()
                 .context("Failed to run build script")?;
             if !status.success() {
                 log::warn!("Build script failed for: {}", pkg.command);
             }
+            cache_permissions::fix(std::path::Path::new(&fullpath));
+            cache_permissions::fix(std::path::Path::new(&lock_path));
             // _lock dropped here, releasing flock
         } else {
             let build_content = templates::render_template(build_template, "build", config, pkg, &extra_args)?;
@@ -295,6 +630,7 @@ pub fn pull_images(config: &BulkerConfig, manifest: &Manifest) -> Result<()> {
             let status = std::process::Command::new("/bin/sh")
                 .arg("-c")
                 .arg(&build_content)
+                .stdout(progress_stdio())
                 .status()
                 .context("Failed to run build script")?;
             if !status.success() {
@@ -317,6 +653,37 @@ mod tests {
         assert!(base.to_string_lossy().ends_with("bulker/manifests"));
     }
 
+    #[test]
+    fn test_cache_dir_override() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // Hold the env lock so no other cache test observes the override
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path().join("xdg"));
+        set_cache_dir_override(Some(tmpdir.path().join("custom")));
+        let base = cache_base_dir();
+        set_cache_dir_override(None);
+        assert_eq!(base, tmpdir.path().join("custom").join("manifests"));
+        assert_eq!(cache_base_dir(), tmpdir.path().join("xdg").join("bulker").join("manifests"));
+    }
+
+    #[test]
+    fn test_migrate_cache_dir_moves_legacy_cache() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let legacy = tmpdir.path().join("config/bulker/manifests");
+        let new = tmpdir.path().join("cache/bulker/manifests");
+        std::fs::create_dir_all(legacy.join("bulker/demo/default")).unwrap();
+        std::fs::write(legacy.join("bulker/demo/default/manifest.yaml"), "manifest: {}\n").unwrap();
+
+        migrate_cache_dir(&legacy, &new);
+        assert!(!legacy.exists());
+        assert!(new.join("bulker/demo/default/manifest.yaml").exists());
+
+        // Existing new cache is never overwritten
+        std::fs::create_dir_all(&legacy).unwrap();
+        migrate_cache_dir(&legacy, &new);
+        assert!(legacy.exists());
+        assert!(new.join("bulker/demo/default/manifest.yaml").exists());
+    }
+
     #[test]
     fn test_manifest_path_structure() {
         let cv = CrateVars {
@@ -331,9 +698,9 @@ mod tests {
 
     #[test]
     fn test_save_and_load_cached_roundtrip() {
-        // Use a temporary directory as cache base by setting XDG_CONFIG_HOME
+        // Use a temporary directory as cache base by setting XDG_CACHE_HOME
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let cv = CrateVars {
             namespace: "test".to_string(),
@@ -351,8 +718,9 @@ mod tests {
                     ..Default::default()
                 }],
                 host_commands: vec!["ls".to_string()],
-                imports: vec![],
+                ..Default::default()
             },
+            ..Default::default()
         };
 
         save_to_cache(&cv, &manifest).unwrap();
@@ -365,6 +733,106 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_cache_keeps_authored_source_but_digests_canonical_form() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        let cv = CrateVars { namespace: "test".to_string(), crate_name: "src".to_string(), tag: "default".to_string() };
+        let plain = "manifest:\n  name: src\n  commands:\n  - command: ls\n    docker_image: alpine\n";
+        let commented = "# Maintained by the core team\nmanifest:\n  name: src\n  commands:\n  - command: ls  # GNU ls\n    docker_image: alpine\n";
+
+        save_to_cache(&cv, &crate::manifest::parse_manifest(commented).unwrap()).unwrap();
+        let loaded = load_cached(&cv).unwrap().unwrap();
+        assert_eq!(loaded.source.as_deref(), Some(commented));
+        assert!(!std::fs::read_to_string(manifest_path(&cv)).unwrap().contains('#'));
+        let digest = read_digest_sidecar(&cv, "crate-manifest-digest").unwrap();
+        assert_eq!(digest, crate::digest::crate_manifest_digest(&crate::manifest::parse_manifest(plain).unwrap()).digest);
+
+        // A manifest without source text drops the stale one
+        save_to_cache(&cv, &make_manifest_with_imports("src", vec![])).unwrap();
+        assert!(load_cached(&cv).unwrap().unwrap().source.is_none());
+    }
+
+    #[test]
+    fn test_project_cache_is_written_and_searched_first() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let project = tmpdir.path().join("project").join(PROJECT_CACHE_DIR);
+        let _cache = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path().join("user"));
+        let cv = CrateVars { namespace: "test".to_string(), crate_name: "scoped".to_string(), tag: "default".to_string() };
+        let other = CrateVars { crate_name: "useronly".to_string(), ..cv.clone() };
+        save_to_cache(&cv, &make_manifest_with_imports("user-copy", vec![])).unwrap();
+        save_to_cache(&other, &make_manifest_with_imports("useronly", vec![])).unwrap();
+
+        let _cache = _cache.and_set(PROJECT_CACHE_ENV, &project);
+        save_to_cache(&cv, &make_manifest_with_imports("project-copy", vec![])).unwrap();
+        assert!(manifest_path(&cv).starts_with(&project));
+        assert_eq!(load_cached(&cv).unwrap().unwrap().manifest.name.as_deref(), Some("project-copy"));
+        // The user cache still serves crates the project doesn't have
+        assert_eq!(load_cached(&other).unwrap().unwrap().manifest.name.as_deref(), Some("useronly"));
+        let listed = list_cached().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(listed[0].1.starts_with(&project));
+
+        touch_last_used(&cv).unwrap();
+        assert!(read_timestamp_sidecar(&cv, "last-used").is_none());
+    }
+
+    #[test]
+    fn test_tag_aliases_resolve_with_project_precedence() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _cache = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path().join("user"));
+        let aliases: BTreeMap<String, String> =
+            [("stable", "1.0.14"), ("latest", "1.0.15")].iter().map(|(a, t)| (a.to_string(), t.to_string())).collect();
+        write_aliases("databio", "pepatac", &aliases).unwrap();
+        assert_eq!(resolve_tag_alias("databio", "pepatac", "stable").as_deref(), Some("1.0.14"));
+        assert_eq!(resolve_tag_alias("databio", "pepatac", "1.0.14"), None);
+        assert_eq!(resolve_tag_alias("databio", "other", "stable"), None);
+        // Parsing leaves aliases alone; the commands resolve them
+        let parsed = crate::manifest::parse_registry_paths("databio/pepatac:stable,databio/pepatac:1.0.13", "bulker").unwrap();
+        assert_eq!(parsed[0].tag, "stable");
+        let tags: Vec<String> = resolve_tag_aliases(parsed).into_iter().map(|cv| cv.tag).collect();
+        assert_eq!(tags, vec!["1.0.14", "1.0.13"]);
+
+        let _cache = _cache.and_set(PROJECT_CACHE_ENV, tmpdir.path().join("project"));
+        write_aliases("databio", "pepatac", &[("stable".to_string(), "1.0.13".to_string())].into()).unwrap();
+        assert_eq!(resolve_tag_alias("databio", "pepatac", "stable").as_deref(), Some("1.0.13"));
+        assert_eq!(resolve_tag_alias("databio", "pepatac", "latest").as_deref(), Some("1.0.15"));
+        // Aliases files are not mistaken for cached tags
+        assert!(list_cached().unwrap().is_empty());
+
+        write_aliases("databio", "pepatac", &BTreeMap::new()).unwrap();
+        assert_eq!(resolve_tag_alias("databio", "pepatac", "stable").as_deref(), Some("1.0.14"));
+    }
+
+    #[test]
+    fn test_find_project_cache_uses_enclosing_project() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let sub = tmpdir.path().join("src").join("deep");
+        std::fs::create_dir_all(&sub).unwrap();
+        assert_eq!(find_project_cache(&sub), sub.join(PROJECT_CACHE_DIR));
+        std::fs::create_dir_all(tmpdir.path().join(PROJECT_CACHE_DIR)).unwrap();
+        assert_eq!(find_project_cache(&sub), tmpdir.path().join(PROJECT_CACHE_DIR));
+    }
+
+    #[test]
+    fn test_save_records_installed_at_and_touch_records_last_used() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+
+        let cv = CrateVars {
+            namespace: "test".to_string(),
+            crate_name: "timestamps".to_string(),
+            tag: "default".to_string(),
+        };
+        save_to_cache(&cv, &make_manifest_with_imports("timestamps", vec![])).unwrap();
+        assert!(read_timestamp_sidecar(&cv, "installed-at").is_some());
+        assert!(read_timestamp_sidecar(&cv, "last-used").is_none());
+
+        touch_last_used(&cv).unwrap();
+        let used = read_timestamp_sidecar(&cv, "last-used").unwrap();
+        assert!(used >= read_timestamp_sidecar(&cv, "installed-at").unwrap());
+    }
+
     #[test]
     fn test_load_cached_returns_none_when_not_cached() {
         let cv = CrateVars {
@@ -403,7 +871,7 @@ mod tests {
     fn test_ensure_cached_with_imports_cycle_detection() {
         // Set up isolated cache
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
 
@@ -438,7 +906,7 @@ mod tests {
     fn test_ensure_cached_with_imports_depth_limit() {
         // Set up isolated cache
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
 
@@ -0,0 +1,210 @@
+//! Opt-in result cache for shimlink runs (`bulker exec --memoize`, or the
+//! `memoize` config key). A successful run is stored under a key built from
+//! the command, the image digest, the resolved arguments, the forwarded
+//! environment, the working directory and the contents of every argument that
+//! names a file. A later run with the same key replays the recorded stdout
+//! and exit code instead of starting a container, so a pipeline restarted
+//! after a crash skips the steps that already finished.
+//!
+//! Only stdout is replayed; files the run wrote are expected to still exist.
+//! Runs that may read piped or redirected stdin are never memoized, since
+//! their input isn't part of the key.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::config::BulkerConfig;
+use crate::manifest::PackageCommand;
+
+/// Set by `bulker exec --memoize`: replay identical successful runs.
+pub(crate) const MEMOIZE_ENV: &str = "BULKER_MEMOIZE";
+
+/// Whether runs are memoized: `$BULKER_MEMOIZE` or the `memoize` config key.
+pub fn enabled(config: &BulkerConfig) -> bool {
+    config.bulker.memoize || std::env::var(MEMOIZE_ENV).is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Whether stdin holds nothing the key misses: a terminal or /dev/null.
+pub fn stdin_is_inert() -> bool {
+    use std::io::IsTerminal;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    if std::io::stdin().is_terminal() {
+        return true;
+    }
+    match (std::fs::metadata("/dev/stdin"), std::fs::metadata("/dev/null")) {
+        (Ok(stdin), Ok(null)) => stdin.file_type().is_char_device() && stdin.rdev() == null.rdev(),
+        _ => false,
+    }
+}
+
+/// A recorded run.
+#[derive(Debug, Serialize, Deserialize)]
+struct MemoMeta {
+    command: String,
+    exit_code: i32,
+    created_at: u64,
+}
+
+/// A run replayed from the cache.
+#[derive(Debug)]
+pub struct Memo {
+    pub stdout: Vec<u8>,
+    pub exit_code: i32,
+}
+
+fn memo_root() -> PathBuf {
+    crate::manifest_cache::cache_root_dir().join("memo")
+}
+
+/// Identity of the image the command runs: its docker digest, or the size
+/// and mtime of the apptainer SIF. None if the image isn't available
+/// locally, in which case the run is not memoized.
+pub fn image_identity(config: &BulkerConfig, pkg: &PackageCommand, engine_path: &str) -> Option<String> {
+    if config.is_apptainer() {
+        let (_, sif) = crate::manifest::apptainer_image_paths(&pkg.docker_image, config.bulker.apptainer_image_folder.as_deref());
+        let meta = std::fs::metadata(crate::config::expand_path(&sif)).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+        return Some(format!("sif:{}:{}", meta.len(), mtime.as_nanos()));
+    }
+    crate::provenance::docker_image_digest(engine_path, &pkg.docker_image)
+}
+
+/// sha512t24u of a file's contents, read in chunks.
+fn file_digest(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha512::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(base64_url::encode(&hasher.finalize()[0..24]))
+}
+
+/// `KEY=value` for every forwarded variable: explicit values as given, bare
+/// names with the host value (unset ones are left out, like the engine does).
+fn env_values(envvars: &[String]) -> Vec<String> {
+    let mut values: Vec<String> = envvars
+        .iter()
+        .filter_map(|e| {
+            if e.contains('=') {
+                Some(e.clone())
+            } else {
+                std::env::var(e).ok().map(|v| format!("{}={}", e, v))
+            }
+        })
+        .collect();
+    values.sort();
+    values.dedup();
+    values
+}
+
+/// Cache key of a run. `args` are the host-side arguments, before path
+/// resolution; those naming regular files contribute their contents, so
+/// changed inputs miss the cache.
+pub fn memo_key(command: &str, image_id: &str, args: &[String], envvars: &[String], cwd: &Path) -> Result<String> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        let path = cwd.join(arg);
+        if path.is_file() {
+            let digest = file_digest(&path).with_context(|| format!("Failed to hash input {}", path.display()))?;
+            inputs.push(serde_json::json!([arg, digest]));
+        }
+    }
+    let key = serde_json::json!({
+        "command": command,
+        "image": image_id,
+        "args": args,
+        "env": env_values(envvars),
+        "cwd": cwd.to_string_lossy(),
+        "inputs": inputs,
+    });
+    Ok(crate::digest::sha512t24u(crate::digest::canonicalize_json(&key)))
+}
+
+/// The recorded run for `key`, if any.
+pub fn lookup(key: &str) -> Option<Memo> {
+    let dir = memo_root().join(key);
+    let meta: MemoMeta = serde_json::from_str(&std::fs::read_to_string(dir.join("meta.json")).ok()?).ok()?;
+    let stdout = std::fs::read(dir.join("stdout")).ok()?;
+    Some(Memo { stdout, exit_code: meta.exit_code })
+}
+
+/// Record a successful run. The entry appears atomically, so a concurrent
+/// lookup never sees a partial one.
+pub fn store(key: &str, command: &str, stdout: &[u8]) -> Result<()> {
+    let root = memo_root();
+    std::fs::create_dir_all(&root).with_context(|| format!("Failed to create {}", root.display()))?;
+    let staging = tempfile::Builder::new().prefix(".memo_").tempdir_in(&root)?;
+    let meta = MemoMeta { command: command.to_string(), exit_code: 0, created_at: crate::manifest_cache::now_secs() };
+    std::fs::write(staging.path().join("meta.json"), serde_json::to_string(&meta)?)?;
+    std::fs::write(staging.path().join("stdout"), stdout)?;
+    let dest = root.join(key);
+    let staged = staging.keep();
+    if std::fs::rename(&staged, &dest).is_err() {
+        // Another run stored the same key first
+        let _ = std::fs::remove_dir_all(&staged);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memo_key_tracks_input_contents() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        std::fs::write(tmpdir.path().join("in.fq"), "ACGT").unwrap();
+        let args = vec!["-t".to_string(), "in.fq".to_string()];
+        let env = vec!["MODE=fast".to_string()];
+        let key = memo_key("align", "sha256:img", &args, &env, tmpdir.path()).unwrap();
+        assert_eq!(key, memo_key("align", "sha256:img", &args, &env, tmpdir.path()).unwrap());
+        // Different image, env value or input contents each miss
+        assert_ne!(key, memo_key("align", "sha256:other", &args, &env, tmpdir.path()).unwrap());
+        assert_ne!(key, memo_key("align", "sha256:img", &args, &["MODE=slow".to_string()], tmpdir.path()).unwrap());
+        std::fs::write(tmpdir.path().join("in.fq"), "ACGTT").unwrap();
+        assert_ne!(key, memo_key("align", "sha256:img", &args, &env, tmpdir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_memo_key_hashes_inputs_of_mapped_paths() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let input = tmpdir.path().join("in.fq");
+        std::fs::write(&input, "ACGT").unwrap();
+        let args = vec![input.to_string_lossy().to_string()];
+        let path_map = [(tmpdir.path().to_string_lossy().to_string(), "/data".to_string())].into();
+        let (resolved, _) = crate::shimlink::resolve_arg_paths(
+            &args,
+            &crate::mountignore::MountIgnore::default(),
+            crate::config::AutoMountMode::Rw,
+            &path_map,
+        );
+        assert_eq!(resolved, vec!["/data/in.fq".to_string()]);
+
+        // The container path names no host file; the host argument does
+        let key = memo_key("align", "sha256:img", &args, &[], Path::new("/")).unwrap();
+        std::fs::write(&input, "ACGTT").unwrap();
+        assert_ne!(key, memo_key("align", "sha256:img", &args, &[], Path::new("/")).unwrap());
+    }
+
+    #[test]
+    fn test_store_and_lookup() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
+        assert!(lookup("k1").is_none());
+        store("k1", "align", b"done\n").unwrap();
+        let memo = lookup("k1").unwrap();
+        assert_eq!(memo.stdout, b"done\n");
+        assert_eq!(memo.exit_code, 0);
+        // A second store of the same key keeps the first entry
+        store("k1", "align", b"other\n").unwrap();
+        assert_eq!(lookup("k1").unwrap().stdout, b"done\n");
+    }
+}
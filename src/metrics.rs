@@ -0,0 +1,357 @@
+//! Per-invocation usage metrics for cluster monitoring.
+//!
+//! With `metrics` set in the config, every shimlink dispatch counts the
+//! invocation and records its wall time per command and image, either into a
+//! Prometheus textfile (for node_exporter's textfile collector) or as StatsD
+//! packets. Metrics are best-effort: failures are logged, never fatal.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The `metrics` config section.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// `textfile` or `statsd`.
+    pub mode: String,
+    /// Prometheus textfile to update (textfile mode).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// `host:port` of the StatsD daemon (statsd mode).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+}
+
+/// Where metrics go, validated from a `MetricsConfig`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricsSink {
+    Textfile(PathBuf),
+    Statsd(String),
+}
+
+impl MetricsConfig {
+    pub const MODES: &'static [&'static str] = &["textfile", "statsd"];
+
+    /// Validate the section and resolve its sink.
+    pub fn sink(&self) -> Result<MetricsSink> {
+        match self.mode.as_str() {
+            "textfile" => match self.path.as_deref().filter(|p| !p.is_empty()) {
+                Some(path) => Ok(MetricsSink::Textfile(PathBuf::from(crate::config::expand_path(path)))),
+                None => bail!("metrics mode 'textfile' needs a `path`"),
+            },
+            "statsd" => match self.addr.as_deref().filter(|a| !a.is_empty()) {
+                Some(addr) if addr.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => {
+                    Ok(MetricsSink::Statsd(addr.to_string()))
+                }
+                Some(addr) => bail!("Invalid metrics addr '{}'. Use HOST:PORT.", addr),
+                None => bail!("metrics mode 'statsd' needs an `addr` (HOST:PORT)"),
+            },
+            other => bail!("Unknown metrics mode '{}'. Valid values: {}", other, Self::MODES.join(", ")),
+        }
+    }
+
+    /// Parse the `config set metrics=` form: `textfile:PATH` or `statsd:HOST:PORT`.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let (mode, target) = spec
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid metrics '{}'. Use textfile:PATH or statsd:HOST:PORT.", spec))?;
+        let config = match mode {
+            "textfile" => MetricsConfig { mode: mode.to_string(), path: Some(target.to_string()), addr: None },
+            "statsd" => MetricsConfig { mode: mode.to_string(), path: None, addr: Some(target.to_string()) },
+            _ => MetricsConfig { mode: mode.to_string(), path: None, addr: None },
+        };
+        config.sink()?;
+        Ok(config)
+    }
+
+    /// The `MODE:TARGET` form shown by `config get metrics`.
+    pub fn spec(&self) -> String {
+        let target = self.path.as_deref().or(self.addr.as_deref()).unwrap_or("");
+        format!("{}:{}", self.mode, target)
+    }
+}
+
+/// One finished shimlink dispatch.
+pub struct Invocation<'a> {
+    pub command: &'a str,
+    pub image: &'a str,
+    pub exit_code: i32,
+    pub duration: Duration,
+}
+
+/// Record an invocation to the configured sink, logging (not returning) errors.
+pub fn record(config: &MetricsConfig, invocation: &Invocation) {
+    let result = config.sink().and_then(|sink| match sink {
+        MetricsSink::Textfile(path) => update_textfile(&path, invocation),
+        MetricsSink::Statsd(addr) => send_statsd(&addr, invocation),
+    });
+    if let Err(e) = result {
+        log::warn!("Failed to record metrics for '{}': {:#}", invocation.command, e);
+    }
+}
+
+// ─── Prometheus textfile ─────────────────────────────────────────────────────
+
+const INVOCATIONS: &str = "bulker_invocations_total";
+const FAILURES: &str = "bulker_invocation_failures_total";
+const DURATION: &str = "bulker_invocation_duration_seconds";
+
+/// Histogram bucket bounds in seconds, from quick tool calls to long jobs.
+const BUCKETS: &[f64] = &[0.5, 1.0, 5.0, 30.0, 60.0, 300.0, 900.0, 3600.0, 14400.0];
+
+/// Accumulated metrics of one command/image pair.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Series {
+    invocations: u64,
+    failures: u64,
+    duration_sum: f64,
+    /// Cumulative counts per `BUCKETS` bound.
+    buckets: Vec<u64>,
+}
+
+impl Series {
+    fn observe(&mut self, invocation: &Invocation) {
+        let secs = invocation.duration.as_secs_f64();
+        self.buckets.resize(BUCKETS.len(), 0);
+        self.invocations += 1;
+        if invocation.exit_code != 0 {
+            self.failures += 1;
+        }
+        self.duration_sum += secs;
+        for (count, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+    }
+}
+
+type SeriesMap = BTreeMap<(String, String), Series>;
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Parse `key="value",...` label pairs.
+fn parse_labels(labels: &str) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    let mut chars = labels.chars().peekable();
+    loop {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.is_empty() || chars.next() != Some('"') {
+            break;
+        }
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some(other) => value.push(other),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        out.insert(key.trim_start_matches(',').trim().to_string(), value);
+    }
+    out
+}
+
+/// Read back the series of a textfile written by `render_textfile`. Lines of
+/// other metrics are ignored.
+fn parse_textfile(text: &str) -> SeriesMap {
+    let mut series = SeriesMap::new();
+    for line in text.lines().filter(|l| !l.starts_with('#')) {
+        let Some((name, rest)) = line.split_once('{') else {
+            continue;
+        };
+        let Some((labels, value)) = rest.rsplit_once("} ") else {
+            continue;
+        };
+        let labels = parse_labels(labels);
+        let (Some(command), Some(image)) = (labels.get("command"), labels.get("image")) else {
+            continue;
+        };
+        let Ok(value) = value.trim().parse::<f64>() else {
+            continue;
+        };
+        let entry = series.entry((command.clone(), image.clone())).or_default();
+        entry.buckets.resize(BUCKETS.len(), 0);
+        match name {
+            INVOCATIONS => entry.invocations = value as u64,
+            FAILURES => entry.failures = value as u64,
+            _ if name == format!("{}_sum", DURATION) => entry.duration_sum = value,
+            _ if name == format!("{}_bucket", DURATION) => {
+                let bound = labels.get("le").and_then(|le| le.parse::<f64>().ok());
+                if let Some(i) = bound.and_then(|b| BUCKETS.iter().position(|x| *x == b)) {
+                    entry.buckets[i] = value as u64;
+                }
+            }
+            _ => {}
+        }
+    }
+    series
+}
+
+fn render_textfile(series: &SeriesMap) -> String {
+    let mut out = String::new();
+    let labels = |(command, image): &(String, String)| {
+        format!("command=\"{}\",image=\"{}\"", escape_label(command), escape_label(image))
+    };
+    out.push_str(&format!("# HELP {} Shimlink invocations by command and image.\n", INVOCATIONS));
+    out.push_str(&format!("# TYPE {} counter\n", INVOCATIONS));
+    for (key, s) in series {
+        out.push_str(&format!("{}{{{}}} {}\n", INVOCATIONS, labels(key), s.invocations));
+    }
+    out.push_str(&format!("# HELP {} Shimlink invocations that exited non-zero.\n", FAILURES));
+    out.push_str(&format!("# TYPE {} counter\n", FAILURES));
+    for (key, s) in series {
+        out.push_str(&format!("{}{{{}}} {}\n", FAILURES, labels(key), s.failures));
+    }
+    out.push_str(&format!("# HELP {} Wall time of shimlink invocations.\n", DURATION));
+    out.push_str(&format!("# TYPE {} histogram\n", DURATION));
+    for (key, s) in series {
+        let l = labels(key);
+        for (bound, count) in BUCKETS.iter().zip(&s.buckets) {
+            out.push_str(&format!("{}_bucket{{{},le=\"{}\"}} {}\n", DURATION, l, bound, count));
+        }
+        out.push_str(&format!("{}_bucket{{{},le=\"+Inf\"}} {}\n", DURATION, l, s.invocations));
+        out.push_str(&format!("{}_sum{{{}}} {}\n", DURATION, l, s.duration_sum));
+        out.push_str(&format!("{}_count{{{}}} {}\n", DURATION, l, s.invocations));
+    }
+    out
+}
+
+/// Add an invocation to the textfile. Concurrent dispatches serialize on a
+/// sidecar lock; the file is replaced atomically so scrapes never see a
+/// partial write.
+fn update_textfile(path: &Path, invocation: &Invocation) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let lock_path = PathBuf::from(format!("{}.lock", path.display()));
+    let _lock = crate::filelock::FileLock::acquire(&lock_path)?;
+
+    let mut series = std::fs::read_to_string(path)
+        .map(|text| parse_textfile(&text))
+        .unwrap_or_default();
+    series
+        .entry((invocation.command.to_string(), invocation.image.to_string()))
+        .or_default()
+        .observe(invocation);
+
+    // node_exporter only reads *.prom, so the temp file is never scraped
+    let tmp = PathBuf::from(format!("{}.{}.tmp", path.display(), std::process::id()));
+    std::fs::write(&tmp, render_textfile(&series))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+// ─── StatsD ──────────────────────────────────────────────────────────────────
+
+fn statsd_tag(value: &str) -> String {
+    value.replace([',', '|', '#', '\n'], "_")
+}
+
+/// StatsD lines for an invocation, with DogStatsD-style tags.
+fn statsd_packet(invocation: &Invocation) -> String {
+    let tags = format!(
+        "|#command:{},image:{}",
+        statsd_tag(invocation.command),
+        statsd_tag(invocation.image)
+    );
+    let mut lines = vec![format!("bulker.invocations:1|c{}", tags)];
+    if invocation.exit_code != 0 {
+        lines.push(format!("bulker.invocation_failures:1|c{}", tags));
+    }
+    lines.push(format!("bulker.invocation_duration:{}|ms{}", invocation.duration.as_millis(), tags));
+    lines.join("\n")
+}
+
+fn send_statsd(addr: &str, invocation: &Invocation) -> Result<()> {
+    use std::net::{ToSocketAddrs, UdpSocket};
+    let target = addr
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", addr))?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} did not resolve to an address", addr))?;
+    let bind = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind).context("Failed to open UDP socket")?;
+    socket
+        .send_to(statsd_packet(invocation).as_bytes(), target)
+        .with_context(|| format!("Failed to send to {}", addr))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation(command: &'static str, exit_code: i32, secs: f64) -> Invocation<'static> {
+        Invocation { command, image: "quay.io/biocontainers/samtools:1.17", exit_code, duration: Duration::from_secs_f64(secs) }
+    }
+
+    #[test]
+    fn test_from_spec() {
+        let config = MetricsConfig::from_spec("textfile:/var/lib/node_exporter/bulker.prom").unwrap();
+        assert_eq!(config.sink().unwrap(), MetricsSink::Textfile("/var/lib/node_exporter/bulker.prom".into()));
+        assert_eq!(config.spec(), "textfile:/var/lib/node_exporter/bulker.prom");
+        let config = MetricsConfig::from_spec("statsd:localhost:8125").unwrap();
+        assert_eq!(config.sink().unwrap(), MetricsSink::Statsd("localhost:8125".into()));
+        assert!(MetricsConfig::from_spec("statsd:localhost").is_err());
+        assert!(MetricsConfig::from_spec("graphite:x:1").is_err());
+        assert!(MetricsConfig::from_spec("textfile:").is_err());
+    }
+
+    #[test]
+    fn test_textfile_accumulates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("textfile/bulker.prom");
+        update_textfile(&path, &invocation("samtools", 0, 0.2)).unwrap();
+        update_textfile(&path, &invocation("samtools", 1, 42.0)).unwrap();
+        update_textfile(&path, &invocation("with \"quote\"", 0, 2.0)).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let l = "command=\"samtools\",image=\"quay.io/biocontainers/samtools:1.17\"";
+        assert!(text.contains(&format!("bulker_invocations_total{{{}}} 2\n", l)), "{}", text);
+        assert!(text.contains(&format!("bulker_invocation_failures_total{{{}}} 1\n", l)), "{}", text);
+        assert!(text.contains(&format!("bulker_invocation_duration_seconds_bucket{{{},le=\"0.5\"}} 1\n", l)), "{}", text);
+        assert!(text.contains(&format!("bulker_invocation_duration_seconds_bucket{{{},le=\"60\"}} 2\n", l)), "{}", text);
+        assert!(text.contains(&format!("bulker_invocation_duration_seconds_count{{{}}} 2\n", l)), "{}", text);
+        assert!(text.contains("command=\"with \\\"quote\\\"\""), "{}", text);
+        // Rendering is a fixed point of parsing
+        assert_eq!(render_textfile(&parse_textfile(&text)), text);
+        assert!(!dir.path().join(format!("textfile/bulker.prom.{}.tmp", std::process::id())).exists());
+    }
+
+    #[test]
+    fn test_statsd_packet() {
+        let packet = statsd_packet(&invocation("samtools", 2, 1.5));
+        assert_eq!(
+            packet,
+            "bulker.invocations:1|c|#command:samtools,image:quay.io/biocontainers/samtools:1.17\n\
+             bulker.invocation_failures:1|c|#command:samtools,image:quay.io/biocontainers/samtools:1.17\n\
+             bulker.invocation_duration:1500|ms|#command:samtools,image:quay.io/biocontainers/samtools:1.17"
+        );
+        assert!(!statsd_packet(&invocation("samtools", 0, 1.0)).contains("failures"));
+    }
+
+    #[test]
+    fn test_send_statsd() {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        record(
+            &MetricsConfig { mode: "statsd".into(), path: None, addr: Some(addr) },
+            &invocation("samtools", 0, 0.01),
+        );
+        let mut buf = [0u8; 1024];
+        let n = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("bulker.invocations:1|c|#command:samtools"));
+    }
+}
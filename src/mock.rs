@@ -1,8 +1,11 @@
 //! Mock mode for CI testing without Docker. `mock record` creates shims that
 //! capture real container outputs as JSON; `mock run` replays them via Python
 //! scripts that return the recorded output. No container runtime needed.
+//! `mock run --inject` adds failure scenarios on top of the recordings.
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use tera::Tera;
@@ -41,6 +44,106 @@ pub fn render_mock_recording_executable(command: &str, real_executable: &str) ->
         .context("Failed to render mock_recording_executable template")
 }
 
+/// An injected failure for `mock run --inject`. Fields mirror outputs.json
+/// records, plus `delay` and `every`, which recorded outputs may use too.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FailureScenario {
+    /// Regex matched against the joined arguments (default: any).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args_pattern: Option<String>,
+    /// Exit code (default 1).
+    #[serde(default = "default_failure_returncode")]
+    pub returncode: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    /// Seconds to wait before failing, e.g. to exercise timeouts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay: Option<f64>,
+    /// Fail only every Nth matching call; other calls replay the recordings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every: Option<u32>,
+}
+
+fn default_failure_returncode() -> i32 {
+    1
+}
+
+/// Read failure scenarios (YAML or JSON): command name -> scenarios, checked in order.
+pub fn load_failures(path: &Path) -> Result<BTreeMap<String, Vec<FailureScenario>>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read failure scenarios: {}", path.display()))?;
+    let failures: BTreeMap<String, Vec<FailureScenario>> = serde_yml::from_str(&content)
+        .with_context(|| format!("Failed to parse failure scenarios: {}", path.display()))?;
+    for (command, scenarios) in &failures {
+        for scenario in scenarios {
+            if scenario.every == Some(0) {
+                bail!("'{}': `every` must be at least 1", command);
+            }
+            if scenario.delay.is_some_and(|d| d < 0.0) {
+                bail!("'{}': `delay` must not be negative", command);
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Reset the call counters of intermittent (`every`) records before a mock
+/// session: `state_dir` starts out empty, and counters that shims run outside
+/// a session left next to the outputs file (`<outputs>.state`) are removed.
+pub fn reset_state(outputs: &Path, state_dir: &Path) -> Result<()> {
+    let mut fallback = outputs.as_os_str().to_owned();
+    fallback.push(".state");
+    for dir in [Path::new(&fallback), state_dir] {
+        match std::fs::remove_dir_all(dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to clear mock state in {}", dir.display())),
+        }
+    }
+    std::fs::create_dir_all(state_dir).with_context(|| format!("Failed to create {}", state_dir.display()))
+}
+
+/// Add a catch-all placeholder record (no output, exit 0, creating the declared
+/// `creates` files) for each of `commands` that has no recordings yet. Returns
+/// the commands that got one.
+pub fn add_placeholder_records(
+    outputs: &mut serde_json::Map<String, serde_json::Value>,
+    commands: &[String],
+    creates: &BTreeMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut added = Vec::new();
+    for command in commands {
+        let recorded = outputs
+            .get(command)
+            .and_then(|r| r.as_array())
+            .is_some_and(|r| !r.is_empty());
+        if recorded || added.contains(command) {
+            continue;
+        }
+        let creates_files: serde_json::Map<String, serde_json::Value> = creates
+            .get(command)
+            .into_iter()
+            .flatten()
+            .map(|path| (path.clone(), serde_json::Value::String(String::new())))
+            .collect();
+        outputs.insert(
+            command.clone(),
+            serde_json::json!([{
+                "args_pattern": ".*",
+                "stdout": "",
+                "stderr": "",
+                "returncode": 0,
+                "creates_files": creates_files,
+            }]),
+        );
+        added.push(command.clone());
+    }
+    added
+}
+
 /// Write a rendered script to a file and make it executable (mode 0755).
 fn write_executable(path: &Path, content: &str) -> Result<()> {
     std::fs::write(path, content)
@@ -185,6 +288,100 @@ mod tests {
         assert!(result.contains("#!/usr/bin/env python3"));
     }
 
+    #[test]
+    fn test_load_failures() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            "samtools:\n- args_pattern: sort\n  stderr: out of memory\n  every: 3\n- returncode: 137\n  delay: 0.5\n",
+        )
+        .unwrap();
+        let failures = load_failures(file.path()).unwrap();
+        assert_eq!(failures["samtools"].len(), 2);
+        assert_eq!(failures["samtools"][0].returncode, 1);
+        assert_eq!(failures["samtools"][0].every, Some(3));
+        assert_eq!(failures["samtools"][1].returncode, 137);
+
+        std::fs::write(file.path(), "samtools:\n- exitcode: 2\n").unwrap();
+        assert!(load_failures(file.path()).is_err(), "unknown fields are rejected");
+        std::fs::write(file.path(), "samtools:\n- every: 0\n").unwrap();
+        assert!(load_failures(file.path()).is_err());
+    }
+
+    #[test]
+    fn test_mock_executable_injects_intermittent_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("samtools");
+        write_executable(&exe, &render_mock_executable("samtools").unwrap()).unwrap();
+        let outputs = dir.path().join("outputs.json");
+        std::fs::write(&outputs, r#"{"samtools": [{"args_pattern": ".*", "stdout": "ok\n", "returncode": 0}]}"#).unwrap();
+        let failures = dir.path().join("failures.json");
+        std::fs::write(&failures, r#"{"samtools": [{"args_pattern": "sort", "stderr": "boom\n", "returncode": 3, "every": 2}]}"#).unwrap();
+
+        let run = |arg: &str| {
+            std::process::Command::new(&exe)
+                .arg(arg)
+                .env("BULKER_MOCK_OUTPUTS", &outputs)
+                .env("BULKER_MOCK_FAILURES", &failures)
+                .env("BULKER_MOCK_STATE", dir.path().join("state"))
+                .output()
+                .unwrap()
+        };
+        let codes: Vec<i32> = (0..4).map(|_| run("sort").status.code().unwrap()).collect();
+        assert_eq!(codes, vec![0, 3, 0, 3]);
+        let failed = run("sort");
+        assert_eq!(String::from_utf8_lossy(&failed.stderr), "");
+        let failed = run("sort");
+        assert_eq!(String::from_utf8_lossy(&failed.stderr), "boom\n");
+        assert_eq!(run("view").status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_reset_state_clears_counters_of_earlier_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = dir.path().join("outputs.json");
+        let state = dir.path().join("session/state");
+        for counters in [dir.path().join("outputs.json.state"), state.clone()] {
+            std::fs::create_dir_all(&counters).unwrap();
+            std::fs::write(counters.join("outputs-samtools-0"), "3").unwrap();
+        }
+        reset_state(&outputs, &state).unwrap();
+        assert!(!dir.path().join("outputs.json.state").exists());
+        assert_eq!(std::fs::read_dir(&state).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_add_placeholder_records_keeps_recordings() {
+        let mut outputs: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(r#"{"samtools": [{"args_pattern": "view", "stdout": "real\n"}]}"#).unwrap();
+        let commands = vec!["samtools".to_string(), "bowtie2".to_string(), "bowtie2".to_string()];
+        let creates = BTreeMap::from([("bowtie2".to_string(), vec!["{-S}".to_string()])]);
+        let added = add_placeholder_records(&mut outputs, &commands, &creates);
+        assert_eq!(added, vec!["bowtie2"]);
+        assert_eq!(outputs["samtools"][0]["stdout"], "real\n");
+        assert_eq!(outputs["bowtie2"][0]["returncode"], 0);
+        assert_eq!(outputs["bowtie2"][0]["creates_files"]["{-S}"], "");
+    }
+
+    #[test]
+    fn test_mock_executable_expands_created_file_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("bowtie2");
+        write_executable(&exe, &render_mock_executable("bowtie2").unwrap()).unwrap();
+        let outputs = dir.path().join("outputs.json");
+        std::fs::write(&outputs, r#"{"bowtie2": [{"creates_files": {"{-S}": "", "logs/{1}.log": "done"}}]}"#).unwrap();
+        let status = std::process::Command::new(&exe)
+            .args(["sample1", "-S", "out/aligned.sam"])
+            .current_dir(dir.path())
+            .env("BULKER_MOCK_OUTPUTS", &outputs)
+            .env_remove("BULKER_MOCK_FAILURES")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(dir.path().join("out/aligned.sam").is_file());
+        assert_eq!(std::fs::read_to_string(dir.path().join("logs/sample1.log")).unwrap(), "done");
+    }
+
     #[test]
     fn test_render_mock_recording_executable_contains_command_and_real() {
         let result = render_mock_recording_executable("samtools", "/tmp/crate/_real_samtools").unwrap();
@@ -0,0 +1,200 @@
+//! Auto-mount exclusions. `~/.config/bulker/mountignore` lists paths (gitignore
+//! syntax) that must never be auto-mounted, even when an argument refers to a
+//! file inside them. Consulted by `shimlink::resolve_arg_paths`.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::expand_path;
+
+/// Location of the mountignore file: ~/.config/bulker/mountignore
+pub fn mountignore_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"));
+    config_dir.join("bulker").join("mountignore")
+}
+
+/// One parsed mountignore line.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: String,
+    negated: bool,
+}
+
+/// A set of auto-mount exclusion rules.
+#[derive(Debug, Clone, Default)]
+pub struct MountIgnore {
+    rules: Vec<Rule>,
+}
+
+impl MountIgnore {
+    /// Load rules from the default mountignore file. Missing file means no rules.
+    pub fn load() -> Self {
+        match std::fs::read_to_string(mountignore_path()) {
+            Ok(contents) => Self::parse(&contents),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Parse gitignore-style contents: `#` comments, blank lines, `!` negation,
+    /// `*`, `?` and `**` wildcards. `~` and `$VAR` are expanded. Patterns without
+    /// a `/` match a path component at any depth; patterns starting with `/` are
+    /// absolute; other patterns with a `/` match at any depth.
+    pub fn parse(contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                let (negated, pat) = match l.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, l),
+                };
+                let pattern = expand_path(pat);
+                let pattern = pattern.trim_end_matches('/').to_string();
+                Rule { pattern, negated }
+            })
+            .filter(|r| !r.pattern.is_empty())
+            .collect();
+        MountIgnore { rules }
+    }
+
+    /// Returns true if `path` (absolute) or any of its ancestors is excluded.
+    /// As in gitignore, a path inside an excluded directory cannot be re-included.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let mut ancestors: Vec<&Path> = path.ancestors().collect();
+        ancestors.reverse();
+        ancestors.iter().any(|p| self.matches_exactly(p))
+    }
+
+    /// The excluded path that mounting directory `dir` would expose: `dir`
+    /// itself (or an ancestor), one of its entries, or a deeper path named by
+    /// an absolute rule. Auto-mounts share the whole directory, so an ignored
+    /// sibling of an argument is as exposed as the argument.
+    pub fn exposed_by(&self, dir: &Path) -> Option<PathBuf> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        if self.is_ignored(dir) {
+            return Some(dir.to_path_buf());
+        }
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                if self.matches_exactly(&entry.path()) {
+                    return Some(entry.path());
+                }
+            }
+        }
+        let prefix = format!("{}/", dir.to_string_lossy().trim_end_matches('/'));
+        self.rules
+            .iter()
+            .filter(|r| !r.negated && r.pattern.starts_with('/'))
+            .map(|r| r.pattern.split(['*', '?']).next().unwrap_or_default())
+            .find(|literal| literal.starts_with(&prefix))
+            .map(PathBuf::from)
+    }
+
+    /// Verdict of the last rule matching this exact path.
+    fn matches_exactly(&self, path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+        let basename = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+        let mut ignored = false;
+        for rule in &self.rules {
+            let matched = if rule.pattern.starts_with('/') {
+                glob_match(&rule.pattern, &path_str)
+            } else if rule.pattern.contains('/') {
+                glob_match(&format!("**/{}", rule.pattern), &path_str)
+            } else {
+                glob_match(&rule.pattern, &basename)
+            };
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Match `text` against a glob. `*` and `?` do not cross `/`; `**` does.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    glob_match_chars(&p, &t)
+}
+
+fn glob_match_chars(p: &[char], t: &[char]) -> bool {
+    match p.first() {
+        None => t.is_empty(),
+        Some('*') if p.get(1) == Some(&'*') => {
+            let rest = &p[2..];
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=t.len()).any(|i| (i == 0 || t[i - 1] == '/') && glob_match_chars(rest, &t[i..]))
+        }
+        Some('*') => {
+            let max = t.iter().position(|c| *c == '/').unwrap_or(t.len());
+            (0..=max).any(|i| glob_match_chars(&p[1..], &t[i..]))
+        }
+        Some('?') => !t.is_empty() && t[0] != '/' && glob_match_chars(&p[1..], &t[1..]),
+        Some(c) => !t.is_empty() && t[0] == *c && glob_match_chars(&p[1..], &t[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match_wildcards() {
+        assert!(glob_match("*.pem", "key.pem"));
+        assert!(!glob_match("*.pem", "dir/key.pem"));
+        assert!(glob_match("/home/**/secrets", "/home/a/b/secrets"));
+        assert!(glob_match("**/secrets", "secrets"));
+        assert!(glob_match("/data/run?", "/data/run1"));
+        assert!(!glob_match("/data/run?", "/data/run10"));
+    }
+
+    #[test]
+    fn test_mountignore_excludes_dir_and_contents() {
+        let ignore = MountIgnore::parse("# comment\n/home/u/.ssh/\n.aws\n");
+        assert!(ignore.is_ignored(Path::new("/home/u/.ssh")));
+        assert!(ignore.is_ignored(Path::new("/home/u/.ssh/keys")));
+        assert!(ignore.is_ignored(Path::new("/home/u/.aws")));
+        assert!(ignore.is_ignored(Path::new("/other/.aws/config")));
+        assert!(!ignore.is_ignored(Path::new("/home/u/data")));
+    }
+
+    #[test]
+    fn test_mountignore_negation_and_tilde() {
+        let home = std::env::var("HOME").unwrap();
+        let ignore = MountIgnore::parse("~/private-*\n!~/private-ok\n");
+        assert!(ignore.is_ignored(&Path::new(&home).join("private-x")));
+        assert!(!ignore.is_ignored(&Path::new(&home).join("private-ok")));
+    }
+
+    #[test]
+    fn test_exposed_by_checks_the_mounted_directory() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path();
+        std::fs::create_dir(dir.join(".aws")).unwrap();
+        std::fs::write(dir.join("reads.fq"), "x").unwrap();
+        let ignore = MountIgnore::parse(".aws\n");
+        assert_eq!(ignore.exposed_by(dir), Some(dir.join(".aws")));
+        assert_eq!(ignore.exposed_by(&dir.join(".aws")), Some(dir.join(".aws")));
+        assert_eq!(MountIgnore::parse(".ssh\n").exposed_by(dir), None);
+
+        let deep = MountIgnore::parse(&format!("{}/a/b/secret*\n", dir.display()));
+        assert_eq!(deep.exposed_by(dir), Some(dir.join("a/b/secret")));
+        assert_eq!(deep.exposed_by(&dir.join("other")), None);
+    }
+
+    #[test]
+    fn test_mountignore_empty_ignores_nothing() {
+        let ignore = MountIgnore::parse("\n# only comments\n");
+        assert!(!ignore.is_ignored(Path::new("/home/u/.ssh")));
+    }
+}
@@ -0,0 +1,159 @@
+//! Output permissions: `umask` and `chown_outputs`.
+//!
+//! Containers create files with the image's umask, and a docker container
+//! running as root (`no_user`, or an image that switches user) leaves
+//! root-owned outputs the host user cannot delete. `umask` sets the mask for
+//! the container process; `chown_outputs` hands new files under the working
+//! directory back to the host user after the run.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Paths per `chown` container, to stay well below ARG_MAX.
+const CHOWN_BATCH: usize = 1000;
+
+/// Parse an octal umask (`022`, `0027`). None if invalid.
+pub fn parse_umask(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if s.is_empty() || s.len() > 4 || !s.chars().all(|c| ('0'..='7').contains(&c)) {
+        return None;
+    }
+    u32::from_str_radix(s, 8).ok().filter(|m| *m <= 0o777)
+}
+
+/// Four-digit octal form used on command lines (`0022`).
+pub fn format_umask(mask: u32) -> String {
+    format!("{:04o}", mask)
+}
+
+/// Script run by the shell that applies the umask before exec'ing the command.
+pub fn umask_script(mask: u32) -> String {
+    format!("umask {} && exec \"$@\"", format_umask(mask))
+}
+
+/// Files and directories under `dir` changed at or after `since` that are
+/// not owned by `uid`. Symlinks are not followed.
+pub fn foreign_outputs(dir: &Path, uid: u32, since: SystemTime) -> Vec<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let since_secs = since
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut found = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                stack.push(entry.path());
+            }
+            if meta.uid() != uid && meta.ctime() >= since_secs {
+                found.push(entry.path());
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Engine commands that chown `paths` (all under `dir`) to `uid:gid`, run
+/// as root in `image`.
+pub fn chown_commands(engine_path: &str, image: &str, uid: u32, gid: u32, dir: &Path, paths: &[PathBuf]) -> Vec<Vec<String>> {
+    let dir = dir.to_string_lossy();
+    paths
+        .chunks(CHOWN_BATCH)
+        .map(|batch| {
+            let mut cmd = vec![
+                engine_path.to_string(),
+                "run".to_string(),
+                "--rm".to_string(),
+                "--user=0:0".to_string(),
+                "--entrypoint=chown".to_string(),
+                "--volume".to_string(),
+                format!("{}:{}", dir, dir),
+                image.to_string(),
+                "-h".to_string(),
+                format!("{}:{}", uid, gid),
+                "--".to_string(),
+            ];
+            cmd.extend(batch.iter().map(|p| p.to_string_lossy().to_string()));
+            cmd
+        })
+        .collect()
+}
+
+/// Give files a container created under `dir` since `since` back to the
+/// current user. Best effort: failures are warned about, never fatal.
+pub fn fix_output_ownership(engine_path: &str, image: &str, dir: &Path, since: SystemTime) {
+    let uid = nix::unistd::getuid().as_raw();
+    if uid == 0 {
+        return;
+    }
+    let gid = nix::unistd::getgid().as_raw();
+    let paths = foreign_outputs(dir, uid, since);
+    if paths.is_empty() {
+        return;
+    }
+    log::debug!("Fixing ownership of {} outputs under {}", paths.len(), dir.display());
+    for cmd in chown_commands(engine_path, image, uid, gid, dir, &paths) {
+        let status = std::process::Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .status();
+        match status {
+            Ok(s) if s.success() => {}
+            Ok(s) => log::warn!("chown_outputs: failed to fix ownership under {} ({})", dir.display(), s),
+            Err(e) => log::warn!("chown_outputs: failed to run {}: {}", engine_path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_umask() {
+        assert_eq!(parse_umask("022"), Some(0o022));
+        assert_eq!(parse_umask("0027"), Some(0o027));
+        assert_eq!(parse_umask(" 7 "), Some(0o007));
+        assert_eq!(parse_umask("0777"), Some(0o777));
+        assert_eq!(parse_umask("1777"), None);
+        assert_eq!(parse_umask("089"), None);
+        assert_eq!(parse_umask(""), None);
+        assert_eq!(format_umask(0o22), "0022");
+    }
+
+    #[test]
+    fn test_foreign_outputs_skips_own_and_old_files() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmpdir.path().join("sub")).unwrap();
+        std::fs::write(tmpdir.path().join("sub/out.txt"), "").unwrap();
+        let uid = nix::unistd::getuid().as_raw();
+        let past = SystemTime::now() - std::time::Duration::from_secs(60);
+        // Everything here is ours
+        assert!(foreign_outputs(tmpdir.path(), uid, past).is_empty());
+        // Seen as another user, new files are foreign; before `since` they are not
+        let found = foreign_outputs(tmpdir.path(), uid + 1, past);
+        assert_eq!(found, vec![tmpdir.path().join("sub"), tmpdir.path().join("sub/out.txt")]);
+        let future = SystemTime::now() + std::time::Duration::from_secs(60);
+        assert!(foreign_outputs(tmpdir.path(), uid + 1, future).is_empty());
+    }
+
+    #[test]
+    fn test_chown_commands_batches_paths() {
+        let paths: Vec<PathBuf> = (0..CHOWN_BATCH + 1).map(|i| PathBuf::from(format!("/w/f{}", i))).collect();
+        let cmds = chown_commands("docker", "img:1", 1000, 100, Path::new("/w"), &paths);
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(
+            cmds[1],
+            vec!["docker", "run", "--rm", "--user=0:0", "--entrypoint=chown", "--volume", "/w:/w", "img:1", "-h", "1000:100", "--", "/w/f1000"]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! External subcommands: `bulker foo ...` runs a `bulker-foo` executable found
+//! on PATH (git-style), so integrations can add commands without changes to
+//! the core CLI. Built-in subcommands always take precedence.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+
+/// Executable name prefix for plugins.
+pub const PLUGIN_PREFIX: &str = "bulker-";
+
+/// Environment variable carrying the path of the dispatching bulker binary,
+/// so plugins can call back into the same bulker that ran them.
+pub const BULKER_BIN_ENV: &str = "BULKER_BIN";
+
+fn is_executable_file(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Find the `bulker-<name>` plugin on `path` (first match wins, like a shell lookup).
+pub fn find_plugin(name: &str, path: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    path.split(':')
+        .filter(|d| !d.is_empty())
+        .map(|dir| Path::new(dir).join(format!("{}{}", PLUGIN_PREFIX, name)))
+        .find(|candidate| is_executable_file(candidate))
+}
+
+/// All plugins on `path`, keyed by subcommand name. A plugin shadowed by an
+/// earlier PATH entry of the same name is not listed.
+pub fn list_plugins(path: &str) -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    for dir in path.split(':').filter(|d| !d.is_empty()) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some(name) = file_name.strip_prefix(PLUGIN_PREFIX) else {
+                continue;
+            };
+            if name.is_empty() || plugins.contains_key(name) || !is_executable_file(&entry.path()) {
+                continue;
+            }
+            plugins.insert(name.to_string(), entry.path());
+        }
+    }
+    plugins
+}
+
+/// Print the plugins found on `$PATH`, marking any shadowed by a built-in subcommand.
+pub fn print_plugins(builtins: &[&str]) {
+    let path = std::env::var("PATH").unwrap_or_default();
+    let plugins = list_plugins(&path);
+    if plugins.is_empty() {
+        println!("No plugins found on PATH (executables named {}<name>).", PLUGIN_PREFIX);
+        return;
+    }
+    let width = plugins.keys().map(|k| k.len()).max().unwrap_or(0);
+    for (name, plugin_path) in &plugins {
+        let note = if builtins.contains(&name.as_str()) { "  (shadowed by built-in command)" } else { "" };
+        println!("  {:<w$}  {}{}", name, plugin_path.display(), note, w = width);
+    }
+}
+
+/// Replace the current process with the plugin. Only returns on failure.
+pub fn exec_plugin(plugin: &Path, args: &[OsString]) -> std::io::Error {
+    let mut cmd = std::process::Command::new(plugin);
+    cmd.args(args);
+    if let Ok(bulker) = std::env::current_exe() {
+        cmd.env(BULKER_BIN_ENV, bulker);
+    }
+    log::debug!("Dispatching to plugin {}", plugin.display());
+    cmd.exec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_exe(dir: &Path, name: &str, mode: u32) {
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_find_plugin_first_path_entry_wins() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        write_exe(a.path(), "bulker-nf", 0o755);
+        write_exe(b.path(), "bulker-nf", 0o755);
+        let path = format!("{}:{}", a.path().display(), b.path().display());
+        assert_eq!(find_plugin("nf", &path), Some(a.path().join("bulker-nf")));
+        assert_eq!(find_plugin("missing", &path), None);
+        assert_eq!(find_plugin("../nf", &path), None);
+    }
+
+    #[test]
+    fn test_list_plugins_skips_non_executables_and_shadowed() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        write_exe(a.path(), "bulker-nf", 0o755);
+        write_exe(a.path(), "bulker-notes", 0o644);
+        write_exe(a.path(), "bulker-", 0o755);
+        write_exe(b.path(), "bulker-nf", 0o755);
+        write_exe(b.path(), "bulker-lint", 0o755);
+        write_exe(b.path(), "other", 0o755);
+        let path = format!("{}:{}", a.path().display(), b.path().display());
+        let plugins = list_plugins(&path);
+        assert_eq!(plugins.keys().collect::<Vec<_>>(), vec!["lint", "nf"]);
+        assert_eq!(plugins["nf"], a.path().join("bulker-nf"));
+    }
+}
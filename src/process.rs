@@ -4,6 +4,21 @@ use std::sync::atomic::{AtomicI32, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 
+/// Error ending the process with exit status `.0` once the command has
+/// returned; whatever explains it has already been reported. Lets library
+/// code signal a status (e.g. a partial activation) without exiting itself.
+/// `cli::run` turns it into the exit.
+#[derive(Debug)]
+pub(crate) struct ExitWith(pub i32);
+
+impl std::fmt::Display for ExitWith {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitWith {}
+
 /// Global child PID for signal handler access.
 pub static CHILD_PID: AtomicI32 = AtomicI32::new(-1);
 
@@ -40,52 +55,396 @@ pub fn graceful_kill_group(pgid: Pid) {
     }
 }
 
+/// How a signal bulker receives is passed on to the child's process group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Forward {
+    /// SIGINT/SIGTERM: stop the child with escalating signals.
+    Terminate,
+    /// Ctrl-Z: stop the child, then bulker itself, so the shell sees the job
+    /// as stopped. The child runs in its own (orphaned) session, where the
+    /// kernel discards SIGTSTP, so it gets SIGSTOP instead.
+    Suspend,
+    /// SIGCONT (`fg`/`bg`) and SIGWINCH (terminal resize): pass it on as is.
+    Relay(Signal),
+}
+
+fn forward_action(signal: i32) -> Option<Forward> {
+    match Signal::try_from(signal).ok()? {
+        Signal::SIGINT | Signal::SIGTERM => Some(Forward::Terminate),
+        Signal::SIGTSTP => Some(Forward::Suspend),
+        sig @ (Signal::SIGCONT | Signal::SIGWINCH) => Some(Forward::Relay(sig)),
+        _ => None,
+    }
+}
+
 /// Set up signal handler thread that forwards signals to the child process group.
 pub fn setup_signal_forwarding() {
-    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::consts::{SIGCONT, SIGINT, SIGTERM, SIGTSTP, SIGWINCH};
     use signal_hook::iterator::Signals;
 
-    let mut signals = Signals::new([SIGINT, SIGTERM]).expect("Failed to register signal handlers");
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGTSTP, SIGCONT, SIGWINCH])
+        .expect("Failed to register signal handlers");
 
     thread::spawn(move || {
-        for _sig in signals.forever() {
+        for sig in signals.forever() {
             let pid = CHILD_PID.load(Ordering::SeqCst);
-            if pid > 0 {
-                graceful_kill_group(Pid::from_raw(pid));
+            let pgid = Pid::from_raw(pid);
+            match forward_action(sig) {
+                Some(Forward::Terminate) if pid > 0 => graceful_kill_group(pgid),
+                Some(Forward::Suspend) => {
+                    if pid > 0 {
+                        let _ = killpg(pgid, Signal::SIGSTOP);
+                    }
+                    // Handling SIGTSTP replaced its default action, so stop explicitly
+                    let _ = nix::sys::signal::raise(Signal::SIGSTOP);
+                }
+                Some(Forward::Relay(signal)) if pid > 0 => {
+                    let _ = killpg(pgid, signal);
+                    log::debug!("Forwarded {:?} to process group {}", signal, pgid);
+                }
+                _ => {}
             }
         }
     });
 }
 
 /// Spawn a child process in a new session with signal forwarding and wait for it.
-/// Returns the child's exit code (or 1 if unavailable).
-pub fn spawn_and_wait(program: &str, args: &[impl AsRef<std::ffi::OsStr>]) -> anyhow::Result<i32> {
+/// The child's exit code (or 1 if unavailable) is classified as one of
+/// `engine`'s (`""` for a program that is not a container engine) and
+/// remapped if the engine reserves it.
+pub fn spawn_and_wait(engine: &str, program: &str, args: &[impl AsRef<std::ffi::OsStr>]) -> anyhow::Result<EngineStatus> {
+    use anyhow::Context;
+
+    let mut child = spawn_in_session(std::process::Command::new(program).args(args), program)?;
+    let status = child.wait().context("Failed to wait on child process")?;
+    Ok(EngineStatus::new(engine, status.code().unwrap_or(1)))
+}
+
+/// Like `spawn_and_wait`, but also returns everything the child wrote to
+/// stdout, which is still passed through as it arrives. With `prefix`, both
+/// streams are relayed line by line as in `spawn_and_wait_prefixed`; the
+/// returned stdout is the child's own, without prefixes. None if the child
+/// wrote more than `MAX_CAPTURE` bytes, which are not kept.
+pub fn spawn_and_wait_capture(
+    engine: &str,
+    program: &str,
+    args: &[impl AsRef<std::ffi::OsStr>],
+    prefix: Option<&str>,
+) -> anyhow::Result<(EngineStatus, Option<Vec<u8>>)> {
+    spawn_and_relay(program, args, prefix, true).map(|(code, stdout)| (EngineStatus::new(engine, code), stdout))
+}
+
+/// Like `spawn_and_wait`, but every line the child writes to stdout or stderr
+/// is passed through with `prefix` in front, so the output of tools running
+/// side by side can be told apart. Each line goes out in a single write.
+pub fn spawn_and_wait_prefixed(
+    engine: &str,
+    program: &str,
+    args: &[impl AsRef<std::ffi::OsStr>],
+    prefix: &str,
+) -> anyhow::Result<EngineStatus> {
+    spawn_and_relay(program, args, Some(prefix), false).map(|(code, _)| EngineStatus::new(engine, code))
+}
+
+fn spawn_and_relay(
+    program: &str,
+    args: &[impl AsRef<std::ffi::OsStr>],
+    prefix: Option<&str>,
+    capture: bool,
+) -> anyhow::Result<(i32, Option<Vec<u8>>)> {
+    use anyhow::Context;
+    use std::process::Stdio;
+
+    let mut cmd = std::process::Command::new(program);
+    cmd.args(args).stdout(Stdio::piped());
+    if prefix.is_some() {
+        cmd.stderr(Stdio::piped());
+    }
+    let mut child = spawn_in_session(&mut cmd, program)?;
+    let stderr_relay = match (child.stderr.take(), prefix) {
+        (Some(pipe), Some(prefix)) => {
+            let prefix = prefix.to_string();
+            Some(thread::spawn(move || relay(pipe, &mut std::io::stderr(), Some(&prefix), None)))
+        }
+        _ => None,
+    };
+    let mut captured = Vec::new();
+    let mut complete = true;
+    if let Some(pipe) = child.stdout.take() {
+        complete = relay(pipe, &mut std::io::stdout(), prefix, capture.then_some(&mut captured))
+            .context("Failed to read child stdout")?;
+    }
+    if let Some(Ok(Err(e))) = stderr_relay.map(|handle| handle.join()) {
+        log::debug!("Failed to relay child stderr: {}", e);
+    }
+    let status = child.wait().context("Failed to wait on child process")?;
+    Ok((status.code().unwrap_or(1), complete.then_some(captured)))
+}
+
+/// Most stdout `relay` keeps a copy of; past it the copy is dropped.
+pub const MAX_CAPTURE: usize = 64 * 1024 * 1024;
+
+/// Longest partial line `relay` holds back waiting for its newline; a longer
+/// one goes out in pieces, each with the prefix.
+const MAX_PENDING_LINE: usize = 64 * 1024;
+
+/// `text` with `prefix` in front of every line. A final line without a
+/// newline gets one, so the next prefixed line starts on its own.
+pub fn prefix_lines(prefix: &str, text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    for line in text.split_inclusive(|&b| b == b'\n') {
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(line);
+        if !line.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// Copy `pipe` to `sink` until EOF, optionally prefixing each line and
+/// keeping a copy of the raw bytes in `captured`. Without a prefix, bytes are
+/// passed on as they arrive; with one, whole lines are. Returns false if the
+/// copy outgrew `MAX_CAPTURE` and was dropped.
+fn relay(
+    mut pipe: impl std::io::Read,
+    sink: &mut impl std::io::Write,
+    prefix: Option<&str>,
+    mut captured: Option<&mut Vec<u8>>,
+) -> std::io::Result<bool> {
+    let mut buf = [0u8; 8192];
+    let mut pending = Vec::new();
+    let mut complete = true;
+    loop {
+        let n = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(copy) = captured.as_deref_mut() {
+            if copy.len() + n > MAX_CAPTURE {
+                *copy = Vec::new();
+                captured = None;
+                complete = false;
+            } else {
+                copy.extend_from_slice(&buf[..n]);
+            }
+        }
+        let out = match prefix {
+            Some(prefix) => {
+                pending.extend_from_slice(&buf[..n]);
+                let end = match pending.iter().rposition(|&b| b == b'\n') {
+                    Some(end) => end + 1,
+                    None if pending.len() > MAX_PENDING_LINE => pending.len(),
+                    None => continue,
+                };
+                let lines: Vec<u8> = pending.drain(..end).collect();
+                prefix_lines(prefix, &lines)
+            }
+            None => buf[..n].to_vec(),
+        };
+        // A closed stdout (e.g. `| head`) must not stop the capture
+        let _ = sink.write_all(&out).and_then(|_| sink.flush());
+    }
+    if let (Some(prefix), false) = (prefix, pending.is_empty()) {
+        let _ = sink.write_all(&prefix_lines(prefix, &pending)).and_then(|_| sink.flush());
+    }
+    Ok(complete)
+}
+
+/// Start `cmd` as the leader of a new session and register it for signal forwarding.
+fn spawn_in_session(cmd: &mut std::process::Command, program: &str) -> anyhow::Result<std::process::Child> {
     use anyhow::Context;
     use std::os::unix::process::CommandExt;
 
     setup_signal_forwarding();
 
     let child = unsafe {
-        std::process::Command::new(program)
-            .args(args)
-            .pre_exec(|| {
-                nix::unistd::setsid()
-                    .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
-                Ok(())
-            })
-            .spawn()
-            .with_context(|| format!("Failed to spawn: {}", program))?
+        cmd.pre_exec(|| {
+            nix::unistd::setsid()
+                .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+            Ok(())
+        })
+        .spawn()
+        .with_context(|| format!("Failed to spawn: {}", program))?
     };
+    CHILD_PID.store(child.id() as i32, Ordering::SeqCst);
+    Ok(child)
+}
 
-    let child_pid = child.id() as i32;
-    CHILD_PID.store(child_pid, Ordering::SeqCst);
+/// Exit status bulker reports when the container engine itself failed
+/// (docker/podman 125), so it is not taken for a status of the tool. 69 is
+/// sysexits' EX_UNAVAILABLE. 126 and 127 keep their shell meaning.
+pub const EXIT_ENGINE_ERROR: i32 = 69;
 
-    let mut child = child;
-    let status = child.wait().context("Failed to wait on child process")?;
-    Ok(status.code().unwrap_or(1))
+/// How a child started by `spawn_and_wait` (and friends) exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineStatus {
+    /// Status to exit with: the child's own, remapped for engine errors.
+    pub code: i32,
+    /// Status the child exited with.
+    pub raw: i32,
+    pub kind: EngineExit,
+}
+
+impl EngineStatus {
+    /// Classify and remap `raw`, the exit status of `engine`.
+    pub fn new(engine: &str, raw: i32) -> Self {
+        let kind = EngineExit::classify(engine, raw);
+        let code = match kind {
+            EngineExit::EngineError => EXIT_ENGINE_ERROR,
+            _ => raw,
+        };
+        EngineStatus { code, raw, kind }
+    }
+}
+
+/// Where a container run failed, from the engine's exit code. Docker and
+/// podman reserve 125-127 for their own errors, so without this a tool
+/// failure and an engine failure look the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineExit {
+    Success,
+    /// The tool ran and exited non-zero.
+    Tool,
+    /// 125: the engine failed before the container ran (bad flag, daemon
+    /// unreachable, image not pullable).
+    EngineError,
+    /// 126: the command exists in the image but could not be invoked.
+    NotExecutable,
+    /// 127: the command was not found in the image.
+    NotFound,
+}
+
+impl EngineExit {
+    /// Classify `code` from `engine`. Apptainer has no reserved codes.
+    pub fn classify(engine: &str, code: i32) -> Self {
+        match (engine, code) {
+            (_, 0) => EngineExit::Success,
+            ("docker" | "podman", 125) => EngineExit::EngineError,
+            ("docker" | "podman", 126) => EngineExit::NotExecutable,
+            ("docker" | "podman", 127) => EngineExit::NotFound,
+            _ => EngineExit::Tool,
+        }
+    }
+
+    /// Name recorded in traces and provenance bundles.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineExit::Success => "success",
+            EngineExit::Tool => "tool",
+            EngineExit::EngineError => "engine_error",
+            EngineExit::NotExecutable => "not_executable",
+            EngineExit::NotFound => "not_found",
+        }
+    }
+
+    /// Explanation for a failed run of `command` (executing `entry` in
+    /// `image`), or None if the exit code is the tool's own.
+    pub fn explain(&self, engine: &str, command: &str, entry: &str, image: &str) -> Option<String> {
+        match self {
+            EngineExit::Success | EngineExit::Tool => None,
+            EngineExit::EngineError => Some(format!(
+                "{} could not run the container for '{}' (exit 125, reported as {}). This is an \
+                 engine error, not a failure of '{}'; see the {} message above, or run `bulker doctor`.",
+                engine, command, EXIT_ENGINE_ERROR, command, engine
+            )),
+            EngineExit::NotExecutable => Some(format!(
+                "'{}' exists in {} but could not be executed (exit 126). Check that it is \
+                 executable in the image, or set `entrypoint` for '{}'.",
+                entry, image, command
+            )),
+            EngineExit::NotFound => Some(format!(
+                "'{}' was not found in {} (exit 127), unless '{}' itself exited 127. Check the \
+                 image, or set `entrypoint` for '{}' to the right binary.",
+                entry, image, command, command
+            )),
+        }
+    }
 }
 
 /// Like `spawn_and_wait` but runs via `/bin/sh -c`.
 pub fn spawn_shell_and_wait(shell_command: &str) -> anyhow::Result<i32> {
-    spawn_and_wait("/bin/sh", &["-c", shell_command])
+    spawn_and_wait("", "/bin/sh", &["-c", shell_command]).map(|status| status.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_exit_classify() {
+        assert_eq!(EngineExit::classify("docker", 0), EngineExit::Success);
+        assert_eq!(EngineExit::classify("docker", 1), EngineExit::Tool);
+        assert_eq!(EngineExit::classify("docker", 125), EngineExit::EngineError);
+        assert_eq!(EngineExit::classify("podman", 126), EngineExit::NotExecutable);
+        assert_eq!(EngineExit::classify("docker", 127), EngineExit::NotFound);
+        assert_eq!(EngineExit::classify("apptainer", 127), EngineExit::Tool);
+        assert_eq!(EngineExit::NotFound.name(), "not_found");
+        assert!(EngineExit::Tool.explain("docker", "samtools", "samtools", "img").is_none());
+        let msg = EngineExit::NotFound.explain("docker", "samtools", "/opt/samtools", "img").unwrap();
+        assert!(msg.starts_with("'/opt/samtools' was not found in img"), "{}", msg);
+    }
+
+    #[test]
+    fn test_spawn_and_wait_remaps_engine_errors() {
+        let exit = |engine: &str, code: i32| spawn_and_wait(engine, "/bin/sh", &["-c", &format!("exit {}", code)]).unwrap();
+        let status = exit("docker", 125);
+        assert_eq!((status.code, status.raw, status.kind), (EXIT_ENGINE_ERROR, 125, EngineExit::EngineError));
+        assert_eq!(exit("podman", 127).code, 127);
+        assert_eq!(exit("docker", 3).kind, EngineExit::Tool);
+        // Not an engine: its own status
+        assert_eq!(exit("", 125).code, 125);
+        assert_eq!(exit("apptainer", 125).kind, EngineExit::Tool);
+    }
+
+    #[test]
+    fn test_prefix_lines() {
+        assert_eq!(prefix_lines("bwa | ", b"one\ntwo\n"), b"bwa | one\nbwa | two\n");
+        assert_eq!(prefix_lines("bwa | ", b"one\npartial"), b"bwa | one\nbwa | partial\n");
+        assert!(prefix_lines("bwa | ", b"").is_empty());
+    }
+
+    #[test]
+    fn test_relay_prefixes_lines_split_across_reads() {
+        // A reader that hands out one byte at a time
+        struct Trickle(std::io::Cursor<Vec<u8>>);
+        impl std::io::Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(1);
+                self.0.read(&mut buf[..len])
+            }
+        }
+        let mut out = Vec::new();
+        let mut captured = Vec::new();
+        assert!(relay(Trickle(std::io::Cursor::new(b"ab\ncd".to_vec())), &mut out, Some("x | "), Some(&mut captured)).unwrap());
+        assert_eq!(out, b"x | ab\nx | cd\n");
+        assert_eq!(captured, b"ab\ncd");
+    }
+
+    #[test]
+    fn test_relay_bounds_what_it_holds() {
+        // A line that never ends is passed on in pieces
+        let endless = vec![b'a'; MAX_PENDING_LINE * 3];
+        let mut out = Vec::new();
+        relay(std::io::Cursor::new(endless.clone()), &mut out, Some("x | "), None).unwrap();
+        assert_eq!(out.iter().filter(|&&b| b == b'\n').count(), 3);
+        assert_eq!(out.len(), endless.len() + 3 * "x | \n".len());
+
+        // Output past MAX_CAPTURE is relayed but not kept
+        let big = std::io::Read::take(std::io::repeat(b'b'), MAX_CAPTURE as u64 + 1);
+        let mut captured = Vec::new();
+        assert!(!relay(big, &mut std::io::sink(), None, Some(&mut captured)).unwrap());
+        assert!(captured.is_empty());
+    }
+
+    #[test]
+    fn test_forward_action() {
+        assert_eq!(forward_action(Signal::SIGINT as i32), Some(Forward::Terminate));
+        assert_eq!(forward_action(Signal::SIGTSTP as i32), Some(Forward::Suspend));
+        assert_eq!(forward_action(Signal::SIGCONT as i32), Some(Forward::Relay(Signal::SIGCONT)));
+        assert_eq!(forward_action(Signal::SIGWINCH as i32), Some(Forward::Relay(Signal::SIGWINCH)));
+        assert_eq!(forward_action(Signal::SIGUSR1 as i32), None);
+    }
 }
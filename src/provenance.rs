@@ -0,0 +1,234 @@
+//! Provenance bundles for `bulker exec --record`. The top-level invocation is
+//! written to `<bundle>/invocation.json`; every container command run through a
+//! shimlink while `$BULKER_RECORD_DIR` is set appends one line to
+//! `<bundle>/commands.jsonl`. Unlike the mock recorder, this captures what ran
+//! (images, digests, exit codes, wall times), not outputs for replay.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+use crate::filelock::FileLock;
+
+/// Environment variable naming the bundle directory shims record into.
+pub const RECORD_ENV: &str = "BULKER_RECORD_DIR";
+
+/// One container command run by a shimlink.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub command: String,
+    pub image: String,
+    pub image_digest: Option<String>,
+    pub engine: String,
+    pub env_mode: String,
+    pub args: Vec<String>,
+    pub container_command: Vec<String>,
+    pub cwd: String,
+    pub pid: u32,
+    pub parent_pid: u32,
+    pub started_at: u64,
+    pub wall_time_secs: f64,
+    pub exit_code: i32,
+    /// Where a failure came from: `tool`, or an engine-reserved exit code
+    /// (`engine_error`, `not_executable`, `not_found`). See `EngineExit`.
+    pub exit_kind: String,
+}
+
+/// The top-level `bulker exec` invocation.
+#[derive(Debug, Serialize)]
+pub struct InvocationRecord {
+    pub bulker_version: String,
+    pub crates: Vec<String>,
+    pub command: Vec<String>,
+    pub strict: bool,
+    pub env_mode: String,
+    pub cwd: String,
+    pub pid: u32,
+    pub started_at: u64,
+    pub wall_time_secs: f64,
+    pub exit_code: i32,
+}
+
+/// Name of the environment mode recorded in bundles.
+pub fn env_mode(host_env: bool) -> &'static str {
+    if host_env { "host-env" } else { "allowlist" }
+}
+
+/// Create a fresh bundle directory. Refuses to reuse a directory that already
+/// holds a recording, so earlier provenance is never mixed with new runs.
+pub fn init_bundle(dir: &Path) -> Result<()> {
+    if dir.join("invocation.json").exists() || dir.join("commands.jsonl").exists() {
+        anyhow::bail!("Record bundle already exists: {}", dir.display());
+    }
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create record bundle: {}", dir.display()))
+}
+
+/// Append a command record to `<dir>/commands.jsonl`. Concurrent shims are
+/// serialized with an advisory lock.
+pub fn append_command_record(dir: &Path, record: &CommandRecord) -> Result<()> {
+    let _lock = FileLock::acquire(&dir.join("commands.jsonl.lock"))?;
+    let path = dir.join("commands.jsonl");
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open: {}", path.display()))?;
+    let line = serde_json::to_string(record)?;
+    writeln!(file, "{}", line).with_context(|| format!("Failed to write: {}", path.display()))
+}
+
+/// The command records of a bundle, in the order they finished. Empty if no
+/// command ran.
+pub fn read_command_records(dir: &Path) -> Result<Vec<CommandRecord>> {
+    let path = dir.join("commands.jsonl");
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read: {}", path.display())),
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).with_context(|| format!("Invalid record in {}", path.display())))
+        .collect()
+}
+
+/// End-of-run summary for `bulker exec --summary`: container commands run,
+/// failures and time spent, overall and per command.
+pub fn summarize(records: &[CommandRecord]) -> String {
+    let secs = |r: &CommandRecord| std::time::Duration::from_secs_f64(r.wall_time_secs.max(0.0));
+    let failed = records.iter().filter(|r| r.exit_code != 0).count();
+    let total: std::time::Duration = records.iter().map(secs).sum();
+    let mut out = format!(
+        "bulker: {} container command(s), {} failed, {} in containers\n",
+        records.len(),
+        failed,
+        crate::humanize::format_duration(total)
+    );
+    // Per command, in order of first use
+    let mut names: Vec<&str> = Vec::new();
+    for r in records {
+        if !names.contains(&r.command.as_str()) {
+            names.push(&r.command);
+        }
+    }
+    let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+    for name in names {
+        let runs: Vec<&CommandRecord> = records.iter().filter(|r| r.command == name).collect();
+        let failures: Vec<String> = runs
+            .iter()
+            .filter(|r| r.exit_code != 0)
+            .map(|r| format!("exit {} ({})", r.exit_code, r.exit_kind))
+            .collect();
+        let time: std::time::Duration = runs.iter().map(|r| secs(r)).sum();
+        out.push_str(&format!(
+            "  {:<width$}  {} run(s)  {:>8}",
+            name,
+            runs.len(),
+            crate::humanize::format_duration(time),
+            width = width
+        ));
+        if !failures.is_empty() {
+            out.push_str(&format!("  failed: {}", failures.join(", ")));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Write `<dir>/invocation.json`.
+pub fn write_invocation_record(dir: &Path, record: &InvocationRecord) -> Result<()> {
+    let path = dir.join("invocation.json");
+    let json = serde_json::to_string_pretty(record)?;
+    std::fs::write(&path, json + "\n").with_context(|| format!("Failed to write: {}", path.display()))
+}
+
+/// Best-effort local image digest for docker-compatible engines: the first
+/// repo digest if the image was pulled from a registry, else the image ID.
+pub fn docker_image_digest(engine_path: &str, image: &str) -> Option<String> {
+    for format in ["{{index .RepoDigests 0}}", "{{.Id}}"] {
+        let output = std::process::Command::new(engine_path)
+            .args(["image", "inspect", "--format", format, image])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()?;
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !value.is_empty() {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_command(exit_code: i32) -> CommandRecord {
+        CommandRecord {
+            command: "cowsay".to_string(),
+            image: "nsheff/cowsay".to_string(),
+            image_digest: None,
+            engine: "docker".to_string(),
+            env_mode: env_mode(false).to_string(),
+            args: vec!["hi".to_string()],
+            container_command: vec!["docker".to_string(), "run".to_string()],
+            cwd: "/work".to_string(),
+            pid: 2,
+            parent_pid: 1,
+            started_at: 0,
+            wall_time_secs: 0.5,
+            exit_code,
+            exit_kind: crate::process::EngineExit::classify("docker", exit_code).name().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_append_command_record_writes_jsonl() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        append_command_record(tmpdir.path(), &sample_command(0)).unwrap();
+        append_command_record(tmpdir.path(), &sample_command(3)).unwrap();
+        let contents = std::fs::read_to_string(tmpdir.path().join("commands.jsonl")).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["command"], "cowsay");
+        assert_eq!(lines[1]["exit_code"], 3);
+        assert_eq!(lines[1]["exit_kind"], "tool");
+        assert_eq!(lines[0]["env_mode"], "allowlist");
+    }
+
+    #[test]
+    fn test_read_command_records_and_summarize() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        assert!(read_command_records(tmpdir.path()).unwrap().is_empty());
+        append_command_record(tmpdir.path(), &sample_command(0)).unwrap();
+        let mut other = sample_command(127);
+        other.command = "fortune".to_string();
+        other.exit_kind = "not_found".to_string();
+        append_command_record(tmpdir.path(), &other).unwrap();
+        append_command_record(tmpdir.path(), &sample_command(0)).unwrap();
+
+        let records = read_command_records(tmpdir.path()).unwrap();
+        assert_eq!(records.len(), 3);
+        let summary = summarize(&records);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], "bulker: 3 container command(s), 1 failed, 1.5s in containers");
+        assert!(lines[1].starts_with("  cowsay   2 run(s)"), "{}", summary);
+        assert!(lines[2].starts_with("  fortune  1 run(s)"), "{}", summary);
+        assert!(lines[2].ends_with("failed: exit 127 (not_found)"), "{}", summary);
+    }
+
+    #[test]
+    fn test_init_bundle_refuses_existing_recording() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let bundle = tmpdir.path().join("bundle");
+        init_bundle(&bundle).unwrap();
+        append_command_record(&bundle, &sample_command(0)).unwrap();
+        assert!(init_bundle(&bundle).is_err());
+    }
+}
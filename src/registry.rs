@@ -0,0 +1,111 @@
+//! Client for the crate registry's index. Next to the manifests it serves
+//! (`<registry_url>/<namespace>/<crate>_<tag>.yaml`), a registry publishes
+//! `<registry_url>/index.yaml` listing every crate it has:
+//!
+//! ```yaml
+//! crates:
+//!   - namespace: databio
+//!     crate: pepatac
+//!     description: PEPATAC ATAC-seq pipeline tools
+//!     tags: [1.0.13, 1.0.14]
+//! ```
+//!
+//! JSON with the same fields is read as well.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::config::BulkerConfig;
+use crate::manifest::is_url;
+
+pub const INDEX_FILE: &str = "index.yaml";
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct IndexEntry {
+    pub namespace: String,
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl IndexEntry {
+    /// `namespace/crate`, as the crate is named on the command line.
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.namespace, self.crate_name)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RegistryIndex {
+    #[serde(default)]
+    pub crates: Vec<IndexEntry>,
+}
+
+impl RegistryIndex {
+    pub fn parse(text: &str, source: &str) -> Result<Self> {
+        serde_yml::from_str(text).with_context(|| format!("{} is not a registry index", source))
+    }
+
+    /// Crates whose `namespace/crate` contains `term`, ignoring case, sorted
+    /// by path. A term with a '/' matches across the separator.
+    pub fn search(&self, term: &str) -> Vec<&IndexEntry> {
+        let term = term.to_lowercase();
+        let mut found: Vec<&IndexEntry> = self
+            .crates
+            .iter()
+            .filter(|entry| entry.path().to_lowercase().contains(&term))
+            .collect();
+        found.sort_by_key(|entry| entry.path());
+        found
+    }
+}
+
+/// Where the index of the configured registry (the first of
+/// `registry_urls`) lives: a URL, or a file when the registry is a local
+/// directory.
+pub fn index_url(config: &BulkerConfig) -> String {
+    format!("{}/{}", config.registry_urls()[0].trim_end_matches('/'), INDEX_FILE)
+}
+
+/// Fetch and parse the configured registry's index.
+pub fn fetch_index(config: &BulkerConfig) -> Result<RegistryIndex> {
+    let url = index_url(config);
+    log::debug!("Loading registry index from: {}", url);
+    let text = if is_url(&url) {
+        crate::download::fetch_string(&url)
+            .with_context(|| format!("Failed to fetch the registry index: {}. Does {} publish one?", url, config.registry_urls()[0]))?
+    } else {
+        std::fs::read_to_string(&url).with_context(|| format!("Failed to read the registry index: {}", url))?
+    };
+    RegistryIndex::parse(&text, &url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_parse_and_search() {
+        let index = RegistryIndex::parse(
+            "crates:\n\
+             - {namespace: databio, crate: pepatac, description: ATAC-seq tools, tags: [1.0.13]}\n\
+             - {namespace: bulker, crate: demo}\n\
+             - {namespace: databio, crate: peppro}\n",
+            "index.yaml",
+        )
+        .unwrap();
+        let paths = |term| index.search(term).iter().map(|e| e.path()).collect::<Vec<_>>();
+        assert_eq!(paths("pep"), ["databio/pepatac", "databio/peppro"]);
+        assert_eq!(paths("DATABIO/PEPA"), ["databio/pepatac"]);
+        assert_eq!(paths("bulker"), ["bulker/demo"]);
+        assert!(paths("atac-seq").is_empty());
+        assert_eq!(index.search("demo")[0].description, "");
+
+        let json = RegistryIndex::parse(r#"{"crates": [{"namespace": "bulker", "crate": "demo", "tags": ["default"]}]}"#, "index.json").unwrap();
+        assert_eq!(json.crates[0].tags, ["default"]);
+        assert!(RegistryIndex::parse("crates: 3", "index.yaml").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Named activation sessions (`bulker activate --detach NAME`, `bulker
+//! attach NAME`). A session is an activation persisted to
+//! `sessions/<name>.env` next to the config file, so shells started later
+//! (tmux or screen windows, batch job steps) can source it without running
+//! the activation again. Its shimdir is a shared shimdir under
+//! `sessions/<name>.shims`, which `deactivate` in one of those shells leaves
+//! in place for the others.
+
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+
+/// Directory holding the sessions of the config at `config_path` (the
+/// default config when None).
+pub fn sessions_dir(config_path: Option<&Path>) -> PathBuf {
+    let default_cfg = crate::config::default_config_path();
+    crate::activate::config_templates_dir(config_path.unwrap_or(&default_cfg)).join("sessions")
+}
+
+/// A named session's files.
+#[derive(Debug, PartialEq)]
+pub struct Session {
+    pub name: String,
+    /// Sourceable activation.
+    pub env_file: PathBuf,
+    /// Shared shimdir the activation puts on PATH, unless `--shimdir` chose
+    /// another.
+    pub shimdir: PathBuf,
+}
+
+impl Session {
+    /// The session `name` in `dir`. Names are used as file names, so they are
+    /// limited to letters, digits, `-`, `_` and `.`.
+    pub fn new(dir: &Path, name: &str) -> Result<Self> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            bail!("Invalid session name '{}'. Use letters, digits, '-', '_' and '.'.", name);
+        }
+        Ok(Session {
+            name: name.to_string(),
+            env_file: dir.join(format!("{}.env", name)),
+            shimdir: dir.join(format!("{}.shims", name)),
+        })
+    }
+
+    /// The existing session `name` in `dir`; the error lists the sessions
+    /// there.
+    pub fn find(dir: &Path, name: &str) -> Result<Self> {
+        let session = Session::new(dir, name)?;
+        if !session.env_file.is_file() {
+            let names = list(dir);
+            if names.is_empty() {
+                bail!("No session '{}'. Create one with `bulker activate --detach {} <crate>`.", name, name);
+            }
+            bail!("No session '{}'. Sessions: {}", name, names.join(", "));
+        }
+        Ok(session)
+    }
+
+    /// Remove the session's activation and its shimdir.
+    pub fn remove(&self) -> Result<()> {
+        std::fs::remove_file(&self.env_file)
+            .with_context(|| format!("Failed to remove {}", self.env_file.display()))?;
+        // Only a directory bulker made a shimdir of; never a --shimdir elsewhere
+        if self.shimdir.join(crate::activate::SHARED_SHIMDIR_MARKER).exists() {
+            std::fs::remove_dir_all(&self.shimdir)
+                .with_context(|| format!("Failed to remove {}", self.shimdir.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Names of the sessions in `dir`, sorted.
+pub fn list(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".env").map(str::to_string))
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Value of an `export NAME="value"` line in a session file.
+pub fn exported(contents: &str, name: &str) -> Option<String> {
+    let prefix = format!("export {}=\"", name);
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix)?.strip_suffix('"').map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_names() {
+        let dir = Path::new("/cfg/sessions");
+        let session = Session::new(dir, "rna-seq_2.1").unwrap();
+        assert_eq!(session.env_file, dir.join("rna-seq_2.1.env"));
+        assert_eq!(session.shimdir, dir.join("rna-seq_2.1.shims"));
+        for bad in ["", ".hidden", "a/b", "../x", "with space"] {
+            assert!(Session::new(dir, bad).is_err(), "{} accepted", bad);
+        }
+    }
+
+    #[test]
+    fn test_list_find_and_remove() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path();
+        assert!(Session::find(dir, "work").unwrap_err().to_string().contains("--detach work"));
+        let work = Session::new(dir, "work").unwrap();
+        std::fs::write(&work.env_file, "").unwrap();
+        std::fs::create_dir(&work.shimdir).unwrap();
+        std::fs::write(work.shimdir.join(crate::activate::SHARED_SHIMDIR_MARKER), "").unwrap();
+        std::fs::write(dir.join("alpha.env"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(list(dir), vec!["alpha", "work"]);
+        assert!(Session::find(dir, "nope").unwrap_err().to_string().contains("alpha, work"));
+
+        Session::find(dir, "work").unwrap().remove().unwrap();
+        assert!(!work.env_file.exists());
+        assert!(!work.shimdir.exists());
+        assert_eq!(list(dir), vec!["alpha"]);
+    }
+
+    #[test]
+    fn test_exported() {
+        let contents = "export BULKERCRATE=\"bulker/demo:default\"\nexport BULKER_SHIMDIR=\"/s/work.shims\"\n";
+        assert_eq!(exported(contents, "BULKER_SHIMDIR").as_deref(), Some("/s/work.shims"));
+        assert_eq!(exported(contents, "BULKERCFG"), None);
+    }
+}
@@ -1,5 +1,5 @@
 //! Busybox-pattern executable dispatch. When bulker is invoked via a symlink
-//! (e.g., as "samtools"), argv[0] tells us which command to run. We look up the
+//! (e.g., as "samtools"), `argv[0]` tells us which command to run. We look up the
 //! command in the crate manifest, build the docker/apptainer command dynamically,
 //! and exec it. No generated shell scripts needed.
 
@@ -7,30 +7,66 @@ use anyhow::{Context, Result, bail};
 use std::io::IsTerminal;
 use std::path::Path;
 
-use crate::config::{BulkerConfig, expand_path, load_config};
+use crate::config::{AutoMountMode, BulkerConfig, LocalePolicy, expand_path, load_config_for_dispatch};
 use crate::manifest::{CrateVars, Manifest, PackageCommand, parse_registry_paths};
 use crate::process;
 
 // ─── argv[0] detection ───────────────────────────────────────────────────────
 
+/// Escape hatch for argv[0] detection: `1` always dispatches on the argv[0]
+/// name, `0` never does (bulker then parses its arguments as a CLI).
+pub(crate) const SHIMLINK_ENV: &str = "BULKER_SHIMLINK";
+
 /// Check if we were invoked as a shimlink (argv[0] != "bulker").
 /// Returns Some(command_name) if so, None if normal CLI invocation.
-pub fn detect_shimlink_invocation() -> Option<String> {
-    let argv0 = std::env::args().next()?;
-    let filename = std::path::Path::new(&argv0).file_name()?.to_str()?;
-    if filename == "bulker" {
-        None
+pub(crate) fn detect_shimlink_invocation() -> Option<String> {
+    let argv0 = std::env::args_os().next()?;
+    shimlink_command_name(&argv0.to_string_lossy())
+}
+
+/// The command a shimlink invocation with this argv[0] runs, if any.
+///
+/// The name alone is not trusted: `exec -a`, login shells (`-name`) and
+/// renamed copies (`bulker-0.1`) all hand bulker an argv[0] that is not a
+/// shimlink. Dispatch only happens inside an activation (`$BULKERCRATE` or
+/// `$BULKER_SHIMDIR` set) or when argv[0] points into a shimdir; see
+/// [`SHIMLINK_ENV`] to override.
+pub(crate) fn shimlink_command_name(argv0: &str) -> Option<String> {
+    let path = Path::new(argv0);
+    let name = path.file_name()?.to_str()?;
+    match std::env::var(SHIMLINK_ENV).as_deref() {
+        Ok("0") => return None,
+        Ok("1") => return Some(name.to_string()),
+        _ => {}
+    }
+    // `-name` is a login shell's argv[0], never a shimlink
+    if name == "bulker" || name.starts_with('-') {
+        return None;
+    }
+    let activated = ["BULKERCRATE", "BULKER_SHIMDIR"].iter().any(|v| std::env::var_os(v).is_some_and(|s| !s.is_empty()));
+    let in_shimdir = path.parent().is_some_and(is_shimdir);
+    if activated || in_shimdir {
+        Some(name.to_string())
     } else {
-        Some(filename.to_string())
+        log::debug!("argv[0] '{}' is not in a shimdir and no crate is active; running as bulker", argv0);
+        None
     }
 }
 
+/// Whether `dir` was made by an activation: it holds a config snapshot or the
+/// shared shimdir marker, or has the `bulker_` prefix of per-activation shimdirs.
+fn is_shimdir(dir: &Path) -> bool {
+    dir.join(crate::config::CONFIG_SNAPSHOT_FILE).is_file()
+        || dir.join(crate::activate::SHARED_SHIMDIR_MARKER).is_file()
+        || dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("bulker_"))
+}
+
 // ─── shimlink execution ──────────────────────────────────────────────────────
 
 /// Execute a command via shimlink dispatch.
 /// Reads $BULKERCRATE and $BULKERCFG, looks up the command in the manifest,
 /// constructs the docker/apptainer command, and exec()s it.
-pub fn shimlink_exec(command_name: &str, args: &[String]) -> Result<()> {
+pub(crate) fn shimlink_exec(command_name: &str, args: &[String]) -> Result<()> {
     // Handle _command prefix for shell/interactive wrappers
     let (actual_command, interactive) = if command_name.starts_with('_') {
         (&command_name[1..], true)
@@ -38,36 +74,61 @@ pub fn shimlink_exec(command_name: &str, args: &[String]) -> Result<()> {
         (command_name, false)
     };
 
+    let trace = crate::trace::TraceBundle::from_env(actual_command);
+
     // 1. Read environment
     let crate_id = std::env::var("BULKERCRATE")
         .context("$BULKERCRATE not set. Are you in an activated bulker environment?")?;
-    let (config, _config_path) = load_config(None)?;
+    let (config, config_path) = load_config_for_dispatch()?;
+    let mut phases = crate::timings::Phases::start();
 
     // 2. Find command across all activated crates and their imports
     let cratevars = parse_registry_paths(&crate_id, &config.bulker.default_namespace)?;
-    let pkg = find_command_in_crates_with_imports(&config, &cratevars, actual_command)?;
+    let (mut pkg, env_defaults) = match find_command_in_crates_with_imports(&config, &cratevars, actual_command) {
+        Ok(found) => found,
+        Err(e) if e.is::<CommandNotFound>() => {
+            // Like a shell, so pipelines can tell a missing command from a tool failure
+            eprintln!("Error: {}", e);
+            return Err(process::ExitWith(COMMAND_NOT_FOUND_EXIT).into());
+        }
+        Err(e) => return Err(e),
+    };
+    phases.end("command_lookup");
+    expand_command_vars(&mut pkg)?;
+    crate::lockfile::pin_from_env(&mut pkg)?;
+    if gpus_requested() {
+        pkg.gpu = true;
+    }
+    if let Some(ref t) = trace {
+        t.write_pkg(&pkg);
+    }
 
     // 3. Resolve argument paths and auto-mount directories
-    let (resolved_args, auto_mount_dirs) = resolve_arg_paths(args);
+    let (resolved_args, auto_mount_dirs) = resolve_arg_paths(
+        args,
+        &crate::mountignore::MountIgnore::load(),
+        config.auto_mount_mode(),
+        &config.bulker.path_map,
+    );
 
     // 4. Merge volumes: config + command + auto-mount
-    let mut volumes = if pkg.no_default_volumes {
-        Vec::new()
-    } else {
-        config.bulker.volumes.clone()
-    };
-    crate::manifest::merge_lists(&mut volumes, &pkg.volumes);
-    crate::manifest::merge_lists(&mut volumes, &auto_mount_dirs);
+    let mut volumes = configured_volumes(&config, &pkg);
+    merge_auto_mounts(&mut volumes, &auto_mount_dirs);
+    if let (Ok(dir), Ok(cwd)) = (std::env::var(EXEC_CWD_ENV), std::env::current_dir()) {
+        volumes.extend(exec_cwd_mount(&dir, &cwd, &volumes));
+    }
 
     // Auto-mount temp directory ($TMPDIR or /tmp)
     let tmpdir = tmpdir_volume();
+    warn_if_scratch_low(&tmpdir);
     if !volumes.contains(&tmpdir) {
+        volumes.retain(|v| split_volume_mode(v) != (tmpdir.as_str(), Some("ro")));
         volumes.push(tmpdir);
     }
 
     // 5. Collect env vars
     let host_env = std::env::var("BULKER_HOST_ENV").is_ok();
-    let envvars = if host_env {
+    let mut envvars = if host_env {
         // --host-env: forward all host vars except bulker internals
         std::env::vars()
             .map(|(k, _)| k)
@@ -76,106 +137,392 @@ pub fn shimlink_exec(command_name: &str, args: &[String]) -> Result<()> {
             .collect()
     } else {
         // Allowlist mode (default)
-        let mut patterns: Vec<String> = if pkg.no_default_envvars || config.bulker.no_default_envvars {
-            Vec::new()
-        } else {
-            DEFAULT_ENVVARS.iter().map(|s| s.to_string()).collect()
-        };
-        crate::manifest::merge_lists(&mut patterns, &pkg.envvars);
-        crate::manifest::merge_lists(&mut patterns, &config.bulker.envvars);
-        if let Ok(extra) = std::env::var("BULKER_EXTRA_ENVVARS") {
-            let extras: Vec<String> = extra.split(',')
-                .map(|e| e.trim().to_string())
-                .filter(|e| !e.is_empty())
-                .collect();
-            crate::manifest::merge_lists(&mut patterns, &extras);
-        }
-        expand_envvar_patterns(&patterns)
+        expand_envvar_patterns(&envvar_allowlist(&config, &pkg))
+    };
+    apply_env_defaults(&mut envvars, &env_defaults);
+    if config.locale_policy() == LocalePolicy::CUtf8 {
+        use_c_utf8_locale(&mut envvars);
+    }
+
+    // Throwaway HOME for reproducibility-sensitive runs (exec --isolate-home)
+    let isolated_home = if std::env::var(ISOLATE_HOME_ENV).is_ok() || config.bulker.isolate_home {
+        let home = tempfile::Builder::new()
+            .prefix("bulker_home_")
+            .tempdir_in(tmpdir_volume())
+            .context("Failed to create isolated home directory")?;
+        Some(home)
+    } else {
+        None
     };
+    let isolated_home_path = isolated_home.as_ref().map(|home| home.path().to_string_lossy().to_string());
+    if let Some(ref home) = isolated_home_path {
+        isolate_home(&mut volumes, &mut envvars, home, config.is_apptainer());
+    }
 
     // 6. Merge docker_args from multiple sources
+    if interactive && pkg.container_shell().is_none() {
+        bail!(
+            "'{}' has no shell in its image (shell_in_container: none); run '{}' directly instead of '_{}'",
+            actual_command, actual_command, actual_command
+        );
+    }
+
     let tool_extra = config.host_tool_specific_args(&pkg, "docker_args");
-    let env_extra = std::env::var("BULKER_EXTRA_DOCKER_ARGS").unwrap_or_default();
+    let env_extra = extra_engine_args(EXTRA_DOCKER_ARGS_ENV, actual_command);
     let docker_args = pkg.merged_docker_args(&[&tool_extra, &env_extra]);
+    let apptainer_extra = extra_engine_args(EXTRA_APPTAINER_ARGS_ENV, actual_command);
+    if !apptainer_extra.is_empty() {
+        let manifest_args = pkg.apptainer_args.take().unwrap_or_default();
+        pkg.apptainer_args = Some(format!("{} {}", manifest_args, apptainer_extra).trim().to_string());
+    }
 
     // 7. Build and exec the container command
+    check_volume_paths(&volumes)?;
     let is_apptainer = config.is_apptainer();
 
     let engine_path = config.engine_path();
 
+    if is_apptainer {
+        check_apptainer_ports(&pkg)?;
+    }
+
     // Auto-pull missing apptainer SIF images (skip in print-command mode)
     if is_apptainer && std::env::var("BULKER_PRINT_COMMAND").is_err() {
         ensure_apptainer_image(&config, &pkg, engine_path)?;
     }
 
-    let cmd_vec = if is_apptainer {
-        build_apptainer_command(
-            &config,
-            &pkg,
-            &volumes,
-            &envvars,
-            &resolved_args,
-            interactive,
-            engine_path,
-        )
+    // Reap containers of earlier runs that were killed before docker could
+    // remove them, then register this run's container for the next one
+    let cidfile = if !is_apptainer && config.bulker.reap_containers && std::env::var("BULKER_PRINT_COMMAND").is_err() {
+        crate::containers::reap_registered(engine_path);
+        crate::containers::register()
+            .inspect_err(|e| log::warn!("Not registering the '{}' container: {:#}", actual_command, e))
+            .ok()
+    } else {
+        None
+    };
+    let memo_key = if interactive || !crate::memoize::enabled(&config) || std::env::var("BULKER_PRINT_COMMAND").is_ok() {
+        None
+    } else if !crate::memoize::stdin_is_inert() {
+        log::debug!("Not memoizing '{}': it may read piped input", actual_command);
+        None
+    } else {
+        match crate::memoize::image_identity(&config, &pkg, engine_path) {
+            Some(image_id) => {
+                // Host-side arguments: the resolved ones may be path_map'ed
+                let cwd = std::env::current_dir()?;
+                // An isolated home is a new directory every run; only its use counts
+                let isolated = isolated_home_path.as_ref().map(|home| format!("HOME={}", home));
+                let key_envvars: Vec<String> = envvars
+                    .iter()
+                    .map(|e| if Some(e) == isolated.as_ref() { "HOME=<isolated>".to_string() } else { e.clone() })
+                    .collect();
+                Some(crate::memoize::memo_key(actual_command, &image_id, args, &key_envvars, &cwd)?)
+            }
+            None => {
+                log::debug!("Not memoizing '{}': image {} is not available locally", actual_command, pkg.docker_image);
+                None
+            }
+        }
+    };
+    let opts = CommandOptions {
+        engine_path,
+        volumes: &volumes,
+        envvars: &envvars,
+        docker_args: &docker_args,
+        args: &resolved_args,
+        interactive,
+        home: isolated_home_path.as_deref(),
+        cidfile: cidfile.as_deref(),
+        captured: memo_key.is_some(),
+    };
+    let mut cmd_vec = if is_apptainer {
+        build_apptainer_command(&config, &pkg, &opts)
     } else {
-        build_docker_command(
-            &config,
-            &pkg,
-            &volumes,
-            &envvars,
-            &docker_args,
-            &resolved_args,
-            interactive,
-            engine_path,
-        )
+        build_docker_command(&config, &pkg, &opts)
     };
 
     if cmd_vec.is_empty() {
         bail!("Failed to build container command");
     }
+    if is_apptainer {
+        // apptainer runs as a host process, so taskset/numactl bind it directly
+        let wrapper = crate::cpu_binding::apptainer_wrapper(&effective_cpu_binding(&config, &pkg), actual_command);
+        cmd_vec.splice(0..0, wrapper);
+    }
+    phases.end("build_command");
+
+    let trace_json = |status: Option<process::EngineStatus>| {
+        serde_json::json!({
+            "command": actual_command,
+            "interactive": interactive,
+            "crates": crate_id,
+            "config": config_path.as_ref().map(|p| p.display().to_string()),
+            "engine": config.bulker.container_engine,
+            "args": args,
+            "resolved_args": resolved_args,
+            "volumes": volumes,
+            "envvars": envvars,
+            "docker_args": docker_args,
+            "argv": cmd_vec,
+            "timings_ms": crate::timings::summary()
+                .iter()
+                .map(|(name, total, _)| (name.to_string(), serde_json::json!(total.as_secs_f64() * 1000.0)))
+                .collect::<serde_json::Map<_, _>>(),
+            "exit_code": status.map(|s| s.code),
+            "engine_exit_code": status.map(|s| s.raw),
+            "exit_kind": status.map(|s| s.kind.name()),
+        })
+    };
+    if let Some(ref t) = trace {
+        t.write_trace(&trace_json(None));
+    }
 
     // Print command instead of executing if BULKER_PRINT_COMMAND is set
     if std::env::var("BULKER_PRINT_COMMAND").is_ok() {
-        println!("{}", cmd_vec.join(" "));
+        println!("{}", cmd_vec.iter().map(|a| display_arg(a)).collect::<Vec<_>>().join(" "));
         return Ok(());
     }
 
     log::debug!("Shimlink exec: {:?}", cmd_vec);
 
-    let exit_code = process::spawn_and_wait(&cmd_vec[0], &cmd_vec[1..])?;
+    let record_dir = std::env::var(crate::provenance::RECORD_ENV).ok();
+    let started_at = crate::manifest_cache::now_secs();
+    let start = std::time::Instant::now();
+    let start_time = std::time::SystemTime::now();
+
+    // apptainer runs the command as this process's user and inherits its umask
+    if let Some(mask) = effective_umask(&config, &pkg).filter(|_| is_apptainer) {
+        nix::sys::stat::umask(nix::sys::stat::Mode::from_bits_truncate(mask as nix::libc::mode_t));
+    }
+
+    let prefix = output_prefix(actual_command, interactive);
+    let engine = config.bulker.container_engine.as_str();
+    let memo = memo_key.as_deref().and_then(crate::memoize::lookup);
+    let replayed = memo.is_some();
+    let status = match (memo, memo_key, prefix) {
+        // Replayed runs go through the same bookkeeping as real ones below
+        (Some(memo), _, prefix) => {
+            use std::io::Write;
+            log::info!("Replaying recorded output of an identical '{}' run", actual_command);
+            let replay = match prefix {
+                Some(ref prefix) => process::prefix_lines(prefix, &memo.stdout),
+                None => memo.stdout,
+            };
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(&replay).and_then(|_| stdout.flush());
+            process::EngineStatus::new(engine, memo.exit_code)
+        }
+        (None, Some(ref key), prefix) => {
+            let (status, stdout) = process::spawn_and_wait_capture(engine, &cmd_vec[0], &cmd_vec[1..], prefix.as_deref())?;
+            // Only successful runs are worth replaying
+            let stored = match stdout {
+                Some(ref stdout) if status.code == 0 => crate::memoize::store(key, actual_command, stdout),
+                None => {
+                    log::debug!("Not recording '{}' run: stdout exceeds {} bytes", actual_command, process::MAX_CAPTURE);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = stored {
+                log::warn!("Could not record '{}' run: {:#}", actual_command, e);
+            }
+            status
+        }
+        (None, None, Some(ref prefix)) => process::spawn_and_wait_prefixed(engine, &cmd_vec[0], &cmd_vec[1..], prefix)?,
+        (None, None, None) => process::spawn_and_wait(engine, &cmd_vec[0], &cmd_vec[1..])?,
+    };
+    let (exit_code, exit_kind) = (status.code, status.kind);
+    phases.end("engine_run");
+    if let Some(ref path) = cidfile {
+        crate::containers::unregister(path);
+    }
+
+    // Only rootful docker leaves outputs the host user does not own
+    if !replayed && (pkg.chown_outputs || config.bulker.chown_outputs) && config.bulker.container_engine == "docker" {
+        crate::ownership::fix_output_ownership(engine_path, &pkg.docker_image, &output_dir(&pkg), start_time);
+        phases.end("chown_outputs");
+    }
+
+    if let Some(ref metrics) = config.bulker.metrics {
+        crate::metrics::record(
+            metrics,
+            &crate::metrics::Invocation {
+                command: actual_command,
+                image: &pkg.docker_image,
+                exit_code,
+                duration: start.elapsed(),
+            },
+        );
+    }
+
+    if interactive && matches!(exit_kind, process::EngineExit::NotExecutable | process::EngineExit::NotFound) {
+        log::warn!(
+            "Could not start a shell in '{}'. If the image has no bash or sh, set \
+             `shell_in_container` for '{}' (e.g. `/bin/busybox sh`, or `none`).",
+            pkg.docker_image, actual_command
+        );
+    } else if let Some(explanation) = exit_kind.explain(
+        &config.bulker.container_engine,
+        actual_command,
+        pkg.entrypoint.as_deref().filter(|e| !e.is_empty()).unwrap_or(&pkg.command),
+        &pkg.docker_image,
+    ) {
+        log::warn!("{}", explanation);
+    }
+
+    if let Some(ref t) = trace {
+        t.write_trace(&trace_json(Some(status)));
+    }
+    if exit_code != 0 {
+        crate::trace::write_last_failure(&trace_json(Some(status)));
+    }
+    if crate::timings::enabled_by_env() {
+        crate::timings::print_report();
+    }
+
+    if let Some(dir) = record_dir {
+        let image_digest = if is_apptainer {
+            None
+        } else {
+            crate::provenance::docker_image_digest(engine_path, &pkg.docker_image)
+        };
+        let record = crate::provenance::CommandRecord {
+            command: actual_command.to_string(),
+            image: pkg.docker_image.clone(),
+            image_digest,
+            engine: config.bulker.container_engine.clone(),
+            env_mode: crate::provenance::env_mode(host_env).to_string(),
+            args: resolved_args.clone(),
+            container_command: cmd_vec.clone(),
+            cwd: std::env::current_dir()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            pid: std::process::id(),
+            parent_pid: std::os::unix::process::parent_id(),
+            started_at,
+            wall_time_secs: start.elapsed().as_secs_f64(),
+            exit_code,
+            exit_kind: exit_kind.name().to_string(),
+        };
+        if let Err(e) = crate::provenance::append_command_record(Path::new(&dir), &record) {
+            log::warn!("Failed to record provenance for '{}': {:#}", actual_command, e);
+        }
+    }
+
+    if let Some(Err(e)) = isolated_home.map(|home| home.close()) {
+        log::debug!("Failed to remove isolated home directory: {}", e);
+    }
+
+    if exit_code != 0 && !interactive {
+        stop_run_on_failure(actual_command, exit_code);
+    }
 
     std::process::exit(exit_code);
 }
 
 // ─── command construction ────────────────────────────────────────────────────
 
+/// Inputs of [`build_docker_command`] and [`build_apptainer_command`] besides
+/// the config and the command. Start from [`CommandOptions::new`] and set the
+/// fields that apply; more may be added in later versions.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct CommandOptions<'a> {
+    /// Engine executable (`config.engine_path()` unless overridden).
+    pub engine_path: &'a str,
+    /// Host paths to bind-mount, optionally with a `:ro`/`:rw` mode.
+    pub volumes: &'a [String],
+    /// `NAME` (passed through from the host) or `NAME=value` entries.
+    pub envvars: &'a [String],
+    /// Extra `docker run` arguments, as one shell-quoted string (docker only).
+    pub docker_args: &'a str,
+    /// Arguments for the command.
+    pub args: &'a [String],
+    /// Start the container's shell instead of the command (`_command`).
+    pub interactive: bool,
+    /// Host directory apptainer binds as the container's home (`--home`).
+    /// Docker gets an isolated home through `volumes` and `envvars` instead.
+    pub home: Option<&'a str>,
+    /// File docker writes the container id to (`--cidfile`), for reaping the
+    /// container if bulker is killed.
+    pub cidfile: Option<&'a Path>,
+    /// The command's stdout is captured (memoized runs), so it gets no TTY:
+    /// one would merge stderr into it and end its lines with CRLF.
+    pub captured: bool,
+}
+
+impl<'a> CommandOptions<'a> {
+    /// Options for running a command with `engine_path` and nothing else.
+    pub fn new(engine_path: &'a str) -> Self {
+        CommandOptions { engine_path, ..Default::default() }
+    }
+}
+
 /// Build a docker run command from resolved command config.
-pub fn build_docker_command(
-    config: &BulkerConfig,
-    pkg: &PackageCommand,
-    volumes: &[String],
-    envvars: &[String],
-    docker_args: &str,
-    args: &[String],
-    interactive: bool,
-    engine_path: &str,
-) -> Vec<String> {
-    let mut cmd = vec![engine_path.to_string(), "run".to_string(), "--rm".to_string(), "--init".to_string()];
+pub fn build_docker_command(config: &BulkerConfig, pkg: &PackageCommand, opts: &CommandOptions) -> Vec<String> {
+    let CommandOptions { engine_path, volumes, envvars, docker_args, args, interactive, cidfile, captured, .. } = *opts;
+    let mut cmd = vec![engine_path.to_string(), "run".to_string(), "--rm".to_string()];
+    if let Some(cidfile) = cidfile {
+        cmd.push(format!("--cidfile={}", cidfile.display()));
+    }
+
+    // Tiny init as PID 1 reaps zombies and forwards signals, unless the image
+    // needs to be PID 1 itself
+    if !pkg.no_init && !config.bulker.no_init {
+        cmd.push("--init".to_string());
+    }
+    if let Some(platform) = pkg.platform.as_deref().filter(|p| !p.is_empty()) {
+        cmd.push(format!("--platform={}", platform));
+    }
+    if let Some(policy) = pkg.pull_policy() {
+        cmd.push(format!("--pull={}", policy.name()));
+    }
+
+    // Label containers so `bulker ps` can find them, and (with
+    // `reap_containers`) so `bulker clean --containers` can tell which ones
+    // outlived their bulker process. Detached services outlive it on
+    // purpose, so they carry no pid.
+    cmd.push(format!("--label=bulker.command={}", pkg.command));
+    if config.bulker.reap_containers && !is_detached(&shell_split(docker_args)) {
+        cmd.push(format!("--label={}={}", crate::containers::PID_LABEL, std::process::id()));
+        cmd.push(format!("--label={}={}", crate::containers::HOST_LABEL, crate::containers::hostname()));
+    }
 
     // Always keep stdin open (-i) and auto-detect TTY (-t)
-    if stdin_is_tty() {
+    if wants_tty(stdin_is_tty(), captured, output_prefix(&pkg.command, interactive).is_some()) {
         cmd.push("-it".to_string());
     } else {
         cmd.push("-i".to_string());
     }
 
     // Emit --entrypoint flag when the manifest specifies one (non-interactive only).
-    // In interactive mode, we want bash, not the pinned entrypoint.
+    // In interactive mode, the entrypoint is the container shell instead.
     let use_entrypoint = !interactive
         && pkg.entrypoint.as_deref().map(|s| !s.is_empty()).unwrap_or(false);
-    if use_entrypoint {
+    let shell = if interactive { pkg.container_shell().unwrap_or_default() } else { Vec::new() };
+    // podman sets the umask itself; under docker a shell applies it and execs the command
+    let umask = effective_umask(config, pkg);
+    let podman = config.bulker.container_engine == "podman";
+    let umask_shell = match umask {
+        Some(mask) if !podman && !interactive => {
+            let wrapper = umask_wrapper(pkg, mask);
+            if wrapper.is_none() {
+                log::warn!("'{}': cannot apply umask without a shell in the image (shell_in_container: none)", pkg.command);
+            }
+            wrapper
+        }
+        _ => None,
+    };
+    if let Some(ref wrapper) = umask_shell {
+        cmd.push(format!("--entrypoint={}", wrapper[0]));
+    } else if use_entrypoint {
         cmd.push(format!("--entrypoint={}", pkg.entrypoint.as_ref().unwrap()));
+    } else if let Some(shell_exe) = shell.first() {
+        cmd.push(format!("--entrypoint={}", shell_exe));
+    }
+    if let Some(mask) = umask.filter(|_| podman) {
+        cmd.push(format!("--umask={}", crate::ownership::format_umask(mask)));
     }
 
     // Deprecation warnings
@@ -217,10 +564,14 @@ pub fn build_docker_command(
         cmd.push(format!("--user={}:{}", uid, gid));
     }
 
-    // Network (unless no_network or config disables host networking)
-    if !pkg.no_network && config.bulker.host_network {
+    // Network (unless no_network or config disables host networking).
+    // Published ports need a bridge network; docker ignores -p under host networking.
+    if !pkg.no_network && config.bulker.host_network && pkg.ports.is_empty() {
         cmd.push("--network=host".to_string());
     }
+    for port in &pkg.ports {
+        cmd.push(format!("--publish={}", port));
+    }
 
     // Environment variables
     for envvar in envvars {
@@ -230,10 +581,28 @@ pub fn build_docker_command(
 
     // Volume mounts
     for volume in volumes {
-        let expanded = expand_path(volume);
-        cmd.push("--volume".to_string());
-        cmd.push(format!("{}:{}", expanded, expanded));
+        let (path, mode) = split_volume_mode(volume);
+        let host = expand_path(path);
+        cmd.extend(bind_mount_args(&host, &map_path(&host, &config.bulker.path_map), mode, false));
+    }
+    cmd.extend(host_access_args(pkg, false));
+    if pkg.gpu {
+        // podman reaches the GPUs through the NVIDIA CDI spec, not a --gpus hook
+        if podman {
+            cmd.extend(["--device".to_string(), "nvidia.com/gpu=all".to_string()]);
+        } else {
+            cmd.push("--gpus=all".to_string());
+        }
     }
+    // An explicit `cpus` limit replaces the CPU count a SLURM job would give
+    let limits = resource_limit_args(config, pkg);
+    let cpus_limited = limits.iter().any(|a| a.starts_with("--cpus="));
+    cmd.extend(
+        crate::cpu_binding::docker_args(&effective_cpu_binding(config, pkg), &pkg.command)
+            .into_iter()
+            .filter(|a| !(cpus_limited && a.starts_with("--cpus="))),
+    );
+    cmd.extend(limits);
 
     // System volumes for user mapping (skipped on macOS via config)
     if !pkg.no_user && config.bulker.system_volumes {
@@ -253,7 +622,7 @@ pub fn build_docker_command(
     let workdir = match &pkg.workdir {
         Some(w) if !w.is_empty() => w.clone(),
         _ => std::env::current_dir()
-            .map(|p| p.to_string_lossy().to_string())
+            .map(|p| map_path(&p.to_string_lossy(), &config.bulker.path_map))
             .unwrap_or_else(|_| "/".to_string()),
     };
     cmd.push(format!("--workdir={}", workdir));
@@ -263,8 +632,16 @@ pub fn build_docker_command(
 
     // Command to run inside container
     if interactive {
-        // Shell wrapper: launch bash
-        cmd.push("bash".to_string());
+        // Shell wrapper: the shell is the entrypoint; push its arguments
+        cmd.extend(shell.iter().skip(1).cloned());
+    } else if let Some(wrapper) = umask_shell {
+        // The umask shell is the entrypoint; it execs the command with the args
+        cmd.extend(wrapper.into_iter().skip(1));
+        if use_entrypoint {
+            cmd.push(pkg.entrypoint.clone().unwrap());
+        } else {
+            cmd.push(pkg.docker_command.clone().filter(|dc| !dc.is_empty()).unwrap_or_else(|| pkg.command.clone()));
+        }
     } else if use_entrypoint {
         // --entrypoint already emitted; args go straight to the overridden entrypoint.
     } else if let Some(ref dc) = pkg.docker_command {
@@ -283,6 +660,97 @@ pub fn build_docker_command(
     cmd
 }
 
+/// Host directory whose new files `chown_outputs` fixes: the command's
+/// `workdir` if it exists on the host, else the current directory.
+fn output_dir(pkg: &PackageCommand) -> std::path::PathBuf {
+    match pkg.workdir.as_deref().filter(|w| !w.is_empty() && Path::new(w).is_dir()) {
+        Some(w) => std::path::PathBuf::from(w),
+        None => std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from(".")),
+    }
+}
+
+/// Volumes of `pkg` from the config and the manifest, before the
+/// auto-mounts of a particular run.
+pub(crate) fn configured_volumes(config: &BulkerConfig, pkg: &PackageCommand) -> Vec<String> {
+    let mut volumes = if pkg.no_default_volumes {
+        Vec::new()
+    } else {
+        config.bulker.volumes.clone()
+    };
+    crate::manifest::merge_lists(&mut volumes, &pkg.volumes);
+    volumes
+}
+
+/// Patterns of the host variables forwarded to `pkg` in allowlist mode:
+/// the defaults, then the command's, the config's and `BULKER_EXTRA_ENVVARS`.
+pub(crate) fn envvar_allowlist(config: &BulkerConfig, pkg: &PackageCommand) -> Vec<String> {
+    let mut patterns: Vec<String> = if pkg.no_default_envvars || config.bulker.no_default_envvars {
+        Vec::new()
+    } else {
+        DEFAULT_ENVVARS.iter().map(|s| s.to_string()).collect()
+    };
+    if config.locale_policy() == LocalePolicy::Forward {
+        crate::manifest::merge_lists(&mut patterns, &LOCALE_ENVVARS.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+    }
+    crate::manifest::merge_lists(&mut patterns, &pkg.envvars);
+    crate::manifest::merge_lists(&mut patterns, &config.bulker.envvars);
+    if let Ok(extra) = std::env::var("BULKER_EXTRA_ENVVARS") {
+        let extras: Vec<String> = extra.split(',')
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty())
+            .collect();
+        crate::manifest::merge_lists(&mut patterns, &extras);
+    }
+    patterns
+}
+
+/// The command's `cpu_binding`, else the config's.
+fn effective_cpu_binding(config: &BulkerConfig, pkg: &PackageCommand) -> crate::cpu_binding::CpuBinding {
+    pkg.cpu_binding().unwrap_or_else(|| config.cpu_binding())
+}
+
+/// The command's `umask`, else the config's.
+pub(crate) fn effective_umask(config: &BulkerConfig, pkg: &PackageCommand) -> Option<u32> {
+    pkg.umask()
+        .or_else(|| config.bulker.umask.as_deref().and_then(crate::ownership::parse_umask))
+}
+
+/// Docker/podman `--cpus`, `--memory` and `--shm-size` for the command's
+/// resource limits, each falling back to the config's. Invalid values are
+/// warned about and ignored.
+fn resource_limit_args(config: &BulkerConfig, pkg: &PackageCommand) -> Vec<String> {
+    let limits = [
+        ("cpus", "--cpus", &pkg.cpus, &config.bulker.cpus),
+        ("memory", "--memory", &pkg.memory, &config.bulker.memory),
+        ("shm_size", "--shm-size", &pkg.shm_size, &config.bulker.shm_size),
+    ];
+    let mut args = Vec::new();
+    for (field, flag, command_value, config_value) in limits {
+        let Some(value) = command_value.as_deref().or(config_value.as_deref()).map(str::trim).filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        let check = if field == "cpus" { crate::manifest::check_cpus(value) } else { crate::manifest::check_memory_size(value) };
+        match check {
+            Ok(()) => args.push(format!("{}={}", flag, value)),
+            Err(e) => log::warn!("'{}': ignoring {}: {:#}", pkg.command, field, e),
+        }
+    }
+    args
+}
+
+/// Shell argv that applies `mask` and execs the rest of the arguments, using
+/// the command's `shell_in_container` (sh when `auto`). None for images
+/// without a shell.
+fn umask_wrapper(pkg: &PackageCommand, mask: u32) -> Option<Vec<String>> {
+    let mut argv = match pkg.shell_in_container.as_deref().map(str::trim) {
+        Some("none") => return None,
+        None | Some("") | Some("auto") => vec!["/bin/sh".to_string()],
+        Some(shell) => shell.split_whitespace().map(String::from).collect(),
+    };
+    argv.extend(["-c".to_string(), crate::ownership::umask_script(mask), "sh".to_string()]);
+    Some(argv)
+}
+
 /// Ensure the apptainer SIF image exists, pulling from docker:// if missing.
 /// Uses file locking to prevent concurrent pulls from corrupting the image.
 fn ensure_apptainer_image(
@@ -290,16 +758,22 @@ fn ensure_apptainer_image(
     pkg: &PackageCommand,
     engine_path: &str,
 ) -> Result<()> {
-    let (_apptainer_image, apptainer_fullpath) = crate::manifest::apptainer_image_paths(
+    let fullpath = crate::manifest::migrate_legacy_sif(
         &pkg.docker_image,
         config.bulker.apptainer_image_folder.as_deref(),
     );
-    let fullpath = expand_path(&apptainer_fullpath);
 
     // Fast path: already exists
     if Path::new(&fullpath).exists() {
         return Ok(());
     }
+    if pkg.pull_policy() == Some(crate::manifest::PullPolicy::Never) {
+        bail!(
+            "Image for '{}' is not present at {} and its pull_policy is 'never'",
+            pkg.command,
+            fullpath
+        );
+    }
 
     // Create parent directory if needed
     if let Some(parent) = Path::new(&fullpath).parent() {
@@ -322,8 +796,11 @@ fn ensure_apptainer_image(
     let tmp_path = format!("{}.{}.tmp", fullpath, std::process::id());
     log::info!("Pulling apptainer image for '{}': docker://{}", pkg.command, pkg.docker_image);
     let mut cmd = std::process::Command::new(engine_path);
-    cmd.arg("pull")
-        .arg(&tmp_path)
+    cmd.arg("pull");
+    if let Some(arch) = pkg.platform_arch() {
+        cmd.arg("--arch").arg(arch);
+    }
+    cmd.arg(&tmp_path)
         .arg(format!("docker://{}", pkg.docker_image));
     if let Some(parent) = Path::new(&fullpath).parent() {
         cmd.env("APPTAINER_TMPDIR", parent);
@@ -346,22 +823,42 @@ fn ensure_apptainer_image(
     // _lock dropped here, releasing flock
 }
 
+/// Apptainer always shares the host network, so ports cannot be remapped.
+/// Unremapped ports need no action (the server listens on the host directly).
+fn check_apptainer_ports(pkg: &PackageCommand) -> Result<()> {
+    let remapped: Vec<&str> = pkg.ports.iter()
+        .filter(|p| crate::manifest::port_is_remapped(p))
+        .map(|p| p.as_str())
+        .collect();
+    if !remapped.is_empty() {
+        bail!(
+            "'{}' maps ports {} to different container ports, but apptainer shares the host \
+             network and cannot remap ports. Use identical host and container ports, or docker.",
+            pkg.command,
+            remapped.join(", ")
+        );
+    }
+    if !pkg.ports.is_empty() {
+        log::debug!("'{}': apptainer uses host networking; ports {:?} are served directly", pkg.command, pkg.ports);
+    }
+    Ok(())
+}
+
 /// Build an apptainer exec command from resolved command config.
-pub fn build_apptainer_command(
-    config: &BulkerConfig,
-    pkg: &PackageCommand,
-    volumes: &[String],
-    envvars: &[String],
-    args: &[String],
-    interactive: bool,
-    engine_path: &str,
-) -> Vec<String> {
+/// `opts.docker_args` is ignored.
+pub fn build_apptainer_command(config: &BulkerConfig, pkg: &PackageCommand, opts: &CommandOptions) -> Vec<String> {
+    let CommandOptions { engine_path, volumes, envvars, args, interactive, home, .. } = *opts;
     let (_, apptainer_fullpath) = crate::manifest::apptainer_image_paths(
         &pkg.docker_image,
         config.bulker.apptainer_image_folder.as_deref(),
     );
 
     let mut cmd = vec![engine_path.to_string(), "exec".to_string()];
+    // apptainer binds the home directory itself and refuses HOME overrides
+    if let Some(home) = home {
+        cmd.push("--home".to_string());
+        cmd.push(format!("{}:{}", home, home));
+    }
 
     // Always use --cleanenv + explicit --env for each allowed var
     cmd.push("--cleanenv".to_string());
@@ -387,10 +884,23 @@ pub fn build_apptainer_command(
 
     // Volume binds (apptainer skips $HOME since it's auto-bound)
     for volume in volumes {
-        let expanded = expand_path(volume);
+        let (path, mode) = split_volume_mode(volume);
+        let expanded = expand_path(path);
         if expanded != expand_path("$HOME") && expanded != expand_path("${HOME}") {
-            cmd.push("-B".to_string());
-            cmd.push(format!("{}:{}", expanded, expanded));
+            cmd.extend(bind_mount_args(&expanded, &map_path(&expanded, &config.bulker.path_map), mode, true));
+        }
+    }
+    cmd.extend(host_access_args(pkg, true));
+    if pkg.gpu {
+        cmd.push("--nv".to_string());
+    }
+    // apptainer starts in the host working directory, which a path_map moves
+    if let Ok(cwd) = std::env::current_dir() {
+        let cwd = cwd.to_string_lossy();
+        let mapped = map_path(&cwd, &config.bulker.path_map);
+        if mapped != cwd {
+            cmd.push("--pwd".to_string());
+            cmd.push(mapped);
         }
     }
 
@@ -399,7 +909,7 @@ pub fn build_apptainer_command(
 
     // Command to run
     if interactive {
-        cmd.push("bash".to_string());
+        cmd.extend(pkg.container_shell().unwrap_or_default());
     } else if let Some(ref ep) = pkg.entrypoint {
         if !ep.is_empty() {
             cmd.push(ep.clone());
@@ -434,11 +944,160 @@ pub fn build_apptainer_command(
 
 // ─── argument path resolution ────────────────────────────────────────────────
 
+/// Quote a `--mount` field if it contains CSV syntax (commas or quotes).
+fn csv_field(field: String) -> String {
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Engine arguments binding host `path` at `target` in the container.
+/// The short `--volume`/`-B` form separates fields with `:` (and apptainer
+/// also splits binds on `,`), so paths containing either use the `--mount`
+/// form, whose CSV fields can be quoted. Spaces and unicode need no special
+/// handling since arguments are passed without a shell.
+fn bind_mount_args(path: &str, target: &str, mode: Option<&str>, is_apptainer: bool) -> Vec<String> {
+    if !path.contains([':', ',']) && !target.contains([':', ',']) {
+        let flag = if is_apptainer { "-B" } else { "--volume" };
+        let spec = match mode {
+            Some(mode) => format!("{}:{}:{}", path, target, mode),
+            None => format!("{}:{}", path, target),
+        };
+        return vec![flag.to_string(), spec];
+    }
+    let (src, dst, ro) = if is_apptainer { ("src", "dst", "ro") } else { ("source", "target", "readonly") };
+    let mut spec = format!(
+        "type=bind,{},{}",
+        csv_field(format!("{}={}", src, path)),
+        csv_field(format!("{}={}", dst, target))
+    );
+    if mode == Some("ro") {
+        spec.push(',');
+        spec.push_str(ro);
+    }
+    vec!["--mount".to_string(), spec]
+}
+
+/// Engine arguments for the command's `devices` and `sockets`. Docker takes
+/// devices with `--device` (which also grants cgroup access); apptainer sees
+/// host devices through a bind. Missing host paths are skipped with a warning,
+/// since docker would create an empty directory in place of a missing socket.
+fn host_access_args(pkg: &PackageCommand, is_apptainer: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    for (spec, is_device) in pkg.devices.iter().map(|d| (d, true)).chain(pkg.sockets.iter().map(|s| (s, false))) {
+        let expanded = expand_path(spec);
+        let (host, container) = crate::manifest::split_host_path(&expanded);
+        if !Path::new(host).exists() {
+            log::warn!("'{}': {} {} does not exist on this host; not passing it", pkg.command, if is_device { "device" } else { "socket" }, host);
+            continue;
+        }
+        if is_device && !is_apptainer {
+            args.push(format!("--device={}:{}", host, container));
+        } else {
+            args.push(if is_apptainer { "-B" } else { "--volume" }.to_string());
+            args.push(format!("{}:{}", host, container));
+        }
+    }
+    args
+}
+
+/// Reject volume paths no engine can bind: a newline breaks both the short
+/// and the CSV `--mount` syntax.
+pub(crate) fn check_volume_paths(volumes: &[String]) -> Result<()> {
+    for volume in volumes {
+        let path = expand_path(split_volume_mode(volume).0);
+        if path.contains(['\n', '\r']) {
+            bail!(
+                "Cannot mount {:?}: paths containing a newline can't be passed to the container engine. \
+                 Rename the directory or pass files from elsewhere.",
+                path
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Quote an argument for display only if the shell would split or expand it.
+fn display_arg(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        shell_escape::escape(std::borrow::Cow::Borrowed(arg)).to_string()
+    }
+}
+
+/// Where host `path` appears in the container under the `path_map` config:
+/// the longest matching host prefix (whole path components) is replaced by
+/// its container prefix. Unmapped paths are mounted as is.
+pub(crate) fn map_path(path: &str, path_map: &std::collections::BTreeMap<String, String>) -> String {
+    path_map
+        .iter()
+        .filter_map(|(host, container)| {
+            let host = host.trim_end_matches('/');
+            let rest = path.strip_prefix(host)?;
+            (rest.is_empty() || rest.starts_with('/')).then(|| (host.len(), format!("{}{}", container.trim_end_matches('/'), rest)))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, mapped)| if mapped.is_empty() { "/".to_string() } else { mapped })
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Split an optional `:ro`/`:rw` suffix off a volume entry.
+pub(crate) fn split_volume_mode(volume: &str) -> (&str, Option<&str>) {
+    for mode in ["ro", "rw"] {
+        if let Some(path) = volume.strip_suffix(&format!(":{}", mode)) {
+            return (path, Some(mode));
+        }
+    }
+    (volume, None)
+}
+
+/// Append auto-mount entries to `volumes`, skipping directories that are
+/// already mounted (an explicit volume keeps its own mode).
+pub(crate) fn merge_auto_mounts(volumes: &mut Vec<String>, auto_mount_dirs: &[String]) {
+    for dir in auto_mount_dirs {
+        let (path, _) = split_volume_mode(dir);
+        let already = volumes.iter().any(|v| expand_path(split_volume_mode(v).0) == path);
+        if !already {
+            volumes.push(dir.clone());
+        }
+    }
+}
+
+/// Add an auto-mount directory. A writable mount of a directory wins over a
+/// read-only one, so a directory holding both inputs and outputs stays writable.
+fn push_auto_mount(auto_mount_dirs: &mut Vec<String>, dir: String, read_only: bool) {
+    let ro_entry = format!("{}:ro", dir);
+    if read_only {
+        if !auto_mount_dirs.contains(&dir) && !auto_mount_dirs.contains(&ro_entry) {
+            auto_mount_dirs.push(ro_entry);
+        }
+    } else if let Some(existing) = auto_mount_dirs.iter_mut().find(|d| **d == ro_entry) {
+        *existing = dir;
+    } else if !auto_mount_dirs.contains(&dir) {
+        auto_mount_dirs.push(dir);
+    }
+}
+
 /// Resolve file-like arguments to absolute paths and collect parent directories for auto-mounting.
+/// Directories excluded by the mountignore file, or holding excluded entries, are never auto-mounted. In `ro-inputs` mode,
+/// parents of existing files (other than the working directory) are mounted read-only.
+/// Resolved paths are given as the container sees them under `path_map`;
+/// auto-mount directories stay host paths.
 /// Returns (resolved_args, auto_mount_dirs).
-pub fn resolve_arg_paths(args: &[String]) -> (Vec<String>, Vec<String>) {
+pub(crate) fn resolve_arg_paths(
+    args: &[String],
+    ignore: &crate::mountignore::MountIgnore,
+    mode: AutoMountMode,
+    path_map: &std::collections::BTreeMap<String, String>,
+) -> (Vec<String>, Vec<String>) {
     let mut resolved_args = Vec::with_capacity(args.len());
     let mut auto_mount_dirs = Vec::new();
+    let cwd = std::env::current_dir().ok().and_then(|d| std::fs::canonicalize(d).ok());
 
     for arg in args {
         // Skip flags (start with -)
@@ -450,40 +1109,49 @@ pub fn resolve_arg_paths(args: &[String]) -> (Vec<String>, Vec<String>) {
         let path = Path::new(arg);
 
         // If the path exists on the filesystem, resolve it to absolute
-        if path.exists() {
-            if let Ok(abs) = std::fs::canonicalize(path) {
-                let abs_str = abs.to_string_lossy().to_string();
-
-                // Add parent directory as auto-mount
-                if let Some(parent) = abs.parent() {
-                    let parent_str = parent.to_string_lossy().to_string();
-                    if !auto_mount_dirs.contains(&parent_str) {
-                        auto_mount_dirs.push(parent_str);
-                    }
+        if path.exists()
+            && let Ok(abs) = std::fs::canonicalize(path)
+        {
+            let abs_str = abs.to_string_lossy().to_string();
+
+            // Add parent directory as auto-mount
+            if let Some(parent) = abs.parent() {
+                let parent_str = parent.to_string_lossy().to_string();
+                if ignore.is_ignored(&abs) {
+                    log::debug!("Not auto-mounting {} (excluded by mountignore)", parent_str);
+                } else if let Some(excluded) = ignore.exposed_by(parent) {
+                    log::debug!("Not auto-mounting {} (would expose {}, excluded by mountignore)", parent_str, excluded.display());
+                } else {
+                    let read_only = mode == AutoMountMode::RoInputs
+                        && cwd.as_deref() != Some(parent);
+                    push_auto_mount(&mut auto_mount_dirs, parent_str, read_only);
                 }
-
-                resolved_args.push(abs_str);
-                continue;
             }
+
+            resolved_args.push(map_path(&abs_str, path_map));
+            continue;
         }
 
         // Check if it looks like a path (contains / or .) and its parent exists
-        if (arg.contains('/') || arg.contains('.')) && !arg.starts_with('-') {
-            if let Some(parent) = path.parent() {
-                if parent.exists() && !parent.as_os_str().is_empty() {
-                    if let Ok(abs_parent) = std::fs::canonicalize(parent) {
-                        let parent_str = abs_parent.to_string_lossy().to_string();
-                        if !auto_mount_dirs.contains(&parent_str) {
-                            auto_mount_dirs.push(parent_str);
-                        }
-                        // Resolve the arg with absolute parent + filename
-                        if let Some(filename) = path.file_name() {
-                            let abs_path = abs_parent.join(filename);
-                            resolved_args.push(abs_path.to_string_lossy().to_string());
-                            continue;
-                        }
-                    }
-                }
+        if (arg.contains('/') || arg.contains('.'))
+            && !arg.starts_with('-')
+            && let Some(parent) = path.parent()
+            && parent.exists()
+            && !parent.as_os_str().is_empty()
+            && let Ok(abs_parent) = std::fs::canonicalize(parent)
+        {
+            let parent_str = abs_parent.to_string_lossy().to_string();
+            if let Some(excluded) = ignore.exposed_by(&abs_parent) {
+                log::debug!("Not auto-mounting {} (would expose {}, excluded by mountignore)", parent_str, excluded.display());
+            } else {
+                // Output path (does not exist yet): always writable
+                push_auto_mount(&mut auto_mount_dirs, parent_str, false);
+            }
+            // Resolve the arg with absolute parent + filename
+            if let Some(filename) = path.file_name() {
+                let abs_path = abs_parent.join(filename);
+                resolved_args.push(map_path(&abs_path.to_string_lossy(), path_map));
+                continue;
             }
         }
 
@@ -496,38 +1164,111 @@ pub fn resolve_arg_paths(args: &[String]) -> (Vec<String>, Vec<String>) {
 
 // ─── command lookup with imports ─────────────────────────────────────────────
 
+/// Exit status of a shimlink whose command is in none of the activated crates,
+/// the shell's "command not found".
+pub(crate) const COMMAND_NOT_FOUND_EXIT: i32 = 127;
+
+/// A command that none of the activated crates (or their imports) provide.
+#[derive(Debug)]
+pub(crate) struct CommandNotFound {
+    pub command: String,
+    pub activated: Vec<String>,
+    /// Every crate that was searched, imports included.
+    pub searched: Vec<String>,
+    /// Imports that were skipped because they are not cached.
+    pub missing: Vec<String>,
+    /// Similar command names from the searched crates.
+    pub suggestions: Vec<String>,
+}
+
+impl std::fmt::Display for CommandNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Command '{}' not found in activated crates '{}' or their imports",
+            self.command,
+            self.activated.join(", ")
+        )?;
+        write!(f, "\n  searched: {}", self.searched.join(", "))?;
+        if !self.missing.is_empty() {
+            write!(f, "\n  not cached (skipped): {}", self.missing.join(", "))?;
+        }
+        if !self.suggestions.is_empty() {
+            write!(f, "\n  did you mean: {}?", self.suggestions.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CommandNotFound {}
+
+/// Levenshtein distance between two strings, by characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            cur.push(substitution.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Up to three names close to `command`: within a third of its length in
+/// edit distance (at least 1), or extending it as a prefix.
+fn similar_commands<'a>(command: &str, names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let max = (command.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &str)> = names
+        .into_iter()
+        .filter(|name| *name != command)
+        .filter_map(|name| {
+            let distance = edit_distance(command, name);
+            (distance <= max || name.starts_with(command)).then_some((distance, name))
+        })
+        .collect();
+    scored.sort();
+    scored.dedup();
+    scored.into_iter().take(3).map(|(_, name)| name.to_string()).collect()
+}
+
 /// Find a command by searching all activated crates and their imports.
+/// Returns the command and the `env_defaults` of the crate that defines it.
 fn find_command_in_crates_with_imports(
     config: &BulkerConfig,
     primary_cvs: &[CrateVars],
     command_name: &str,
-) -> Result<PackageCommand> {
-    let all_crates = crate::imports::resolve_cratevars_with_imports(config, primary_cvs)?;
+) -> Result<(PackageCommand, Vec<String>)> {
+    // Imports that failed to fetch at activation are skipped (partial activation)
+    let (all_crates, missing) = crate::imports::resolve_cratevars_allow_missing_imports(config, primary_cvs)?;
 
+    let mut known: Vec<String> = Vec::new();
     for cv in &all_crates {
         if let Some(manifest) = crate::manifest_cache::load_cached(cv)? {
             if let Some(pkg) = manifest.manifest.commands.iter().find(|c| c.command == command_name) {
-                return Ok(pkg.clone());
+                return Ok((pkg.clone(), manifest.manifest.env_defaults.clone()));
             }
+            known.extend(manifest.manifest.commands.iter().map(|c| c.command.clone()));
+            known.extend(manifest.manifest.host_commands.iter().cloned());
         }
     }
 
-    let names = primary_cvs
-        .iter()
-        .map(|c| c.display_name())
-        .collect::<Vec<_>>()
-        .join(", ");
-    bail!(
-        "Command '{}' not found in activated crates '{}' or their imports",
-        command_name,
-        names
-    )
+    Err(CommandNotFound {
+        command: command_name.to_string(),
+        activated: primary_cvs.iter().map(|c| c.display_name()).collect(),
+        searched: all_crates.iter().map(|c| c.display_name()).collect(),
+        missing,
+        suggestions: similar_commands(command_name, known.iter().map(|s| s.as_str())),
+    }
+    .into())
 }
 
 // ─── manifest caching ────────────────────────────────────────────────────────
 
 /// Load a cached manifest from the manifest cache.
-pub fn load_cached_manifest(_config: &BulkerConfig, cratevars: &CrateVars) -> Result<Manifest> {
+pub(crate) fn load_cached_manifest(_config: &BulkerConfig, cratevars: &CrateVars) -> Result<Manifest> {
     crate::manifest_cache::load_cached(cratevars)?
         .ok_or_else(|| anyhow::anyhow!(
             "Crate '{}' is not cached. Run 'bulker activate {}' to fetch it.",
@@ -541,7 +1282,7 @@ pub fn load_cached_manifest(_config: &BulkerConfig, cratevars: &CrateVars) -> Re
 /// Create a directory of symlinks pointing to the bulker binary, one per command.
 /// Also creates symlinks for host_commands pointing to the actual host binary.
 /// Returns the path to the created directory.
-pub fn create_shimlink_dir(manifest: &Manifest, dir: &Path) -> Result<()> {
+pub(crate) fn create_shimlink_dir(manifest: &Manifest, dir: &Path) -> Result<()> {
     std::fs::create_dir_all(dir)
         .with_context(|| format!("Failed to create shimlink dir: {}", dir.display()))?;
 
@@ -551,8 +1292,7 @@ pub fn create_shimlink_dir(manifest: &Manifest, dir: &Path) -> Result<()> {
     // Create symlinks for containerized commands
     for pkg in &manifest.manifest.commands {
         let link_path = dir.join(&pkg.command);
-        let _ = std::fs::remove_file(&link_path);
-        std::os::unix::fs::symlink(&bulker_path, &link_path).with_context(|| {
+        replace_symlink(&bulker_path, &link_path).with_context(|| {
             format!(
                 "Failed to create shimlink: {} -> {}",
                 link_path.display(),
@@ -562,8 +1302,7 @@ pub fn create_shimlink_dir(manifest: &Manifest, dir: &Path) -> Result<()> {
 
         // Also create _command shell wrapper symlink
         let shell_link_path = dir.join(format!("_{}", pkg.command));
-        let _ = std::fs::remove_file(&shell_link_path);
-        std::os::unix::fs::symlink(&bulker_path, &shell_link_path).with_context(|| {
+        replace_symlink(&bulker_path, &shell_link_path).with_context(|| {
             format!(
                 "Failed to create shell shimlink: {} -> {}",
                 shell_link_path.display(),
@@ -582,6 +1321,174 @@ pub fn create_shimlink_dir(manifest: &Manifest, dir: &Path) -> Result<()> {
 
 // ─── helpers ─────────────────────────────────────────────────────────────────
 
+/// Prefix of the variables that fill `{name}` placeholders in `workdir` and
+/// `volumes` (`bulker exec --var name=value` sets `BULKER_VAR_name`).
+pub(crate) const VAR_ENV_PREFIX: &str = "BULKER_VAR_";
+
+/// Replace `{name}` placeholders in `s` using `lookup`. `${VAR}` is left for
+/// `expand_path`, and braces around anything but an identifier are literal.
+pub(crate) fn expand_template_vars(s: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(open) = rest.find('{') {
+        let after = &rest[open + 1..];
+        let name_len = after.find('}').filter(|&n| {
+            let name = &after[..n];
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        let is_env_ref = rest[..open].ends_with('$');
+        match name_len {
+            Some(n) if !is_env_ref => {
+                let name = &after[..n];
+                let Some(value) = lookup(name) else {
+                    bail!(
+                        "'{}' uses {{{}}} but no value was given: pass `bulker exec --var {}=...` or set {}{}",
+                        s, name, name, VAR_ENV_PREFIX, name
+                    );
+                };
+                out.push_str(&rest[..open]);
+                out.push_str(&value);
+                rest = &after[n + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..=open]);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Fill `{name}` placeholders in the command's `workdir` and `volumes` from
+/// the `BULKER_VAR_*` environment.
+fn expand_command_vars(pkg: &mut PackageCommand) -> Result<()> {
+    let lookup = |name: &str| std::env::var(format!("{}{}", VAR_ENV_PREFIX, name)).ok();
+    if let Some(ref workdir) = pkg.workdir {
+        pkg.workdir = Some(expand_template_vars(workdir, lookup)?);
+    }
+    for volume in pkg.volumes.iter_mut() {
+        *volume = expand_template_vars(volume, lookup)?;
+    }
+    Ok(())
+}
+
+/// Extra `docker run` arguments for every command; `<name>_<COMMAND>` adds
+/// arguments for one command only (see [`extra_engine_args`]).
+pub(crate) const EXTRA_DOCKER_ARGS_ENV: &str = "BULKER_EXTRA_DOCKER_ARGS";
+
+/// Extra `apptainer exec` arguments, scoped like [`EXTRA_DOCKER_ARGS_ENV`].
+pub(crate) const EXTRA_APPTAINER_ARGS_ENV: &str = "BULKER_EXTRA_APPTAINER_ARGS";
+
+/// Suffix naming `command` in a scoped variable: uppercased, with anything
+/// but letters and digits turned into `_` (`bowtie2-build` -> `BOWTIE2_BUILD`).
+pub(crate) fn command_env_suffix(command: &str) -> String {
+    command
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// `$<var>` followed by `$<var>_<COMMAND>`, so per-command arguments come
+/// last and win where the engine takes the last value.
+fn extra_engine_args(var: &str, command: &str) -> String {
+    let scoped = format!("{}_{}", var, command_env_suffix(command));
+    [var, scoped.as_str()]
+        .iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .filter(|args| !args.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Set by `bulker exec --isolate-home`: give containers a throwaway HOME.
+pub(crate) const ISOLATE_HOME_ENV: &str = "BULKER_ISOLATE_HOME";
+
+/// Set by `bulker exec --gpus`: give every container command the host's GPUs.
+pub(crate) const GPUS_ENV: &str = "BULKER_GPUS";
+
+/// Whether [`GPUS_ENV`] asks for GPUs: set, and not empty or `0`.
+fn gpus_requested() -> bool {
+    std::env::var(GPUS_ENV).is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Set by `bulker exec --cwd` to the directory it runs the command in, which
+/// shimlinks mount so the container's working directory exists.
+pub(crate) const EXEC_CWD_ENV: &str = "BULKER_EXEC_CWD";
+
+/// The `bulker exec --cwd` directory `dir` as a volume, unless the command
+/// runs outside it (the script changed directory) or `volumes` already
+/// mount it or a directory above it.
+fn exec_cwd_mount(dir: &str, cwd: &Path, volumes: &[String]) -> Option<String> {
+    if dir.is_empty() || !cwd.starts_with(dir) {
+        return None;
+    }
+    let mounted = volumes
+        .iter()
+        .map(|v| expand_path(split_volume_mode(v).0))
+        .any(|host| !host.is_empty() && Path::new(dir).starts_with(host));
+    (!mounted).then(|| dir.to_string())
+}
+
+/// Set by `bulker exec --prefix-output`: prefix each output line with the command name.
+pub(crate) const PREFIX_OUTPUT_ENV: &str = "BULKER_PREFIX_OUTPUT";
+
+/// Set by `bulker exec --fail-fast` to the PID of the wrapped shell, which
+/// leads its own process group. A failing container command terminates that
+/// group, so the rest of the run stops.
+pub(crate) const FAIL_FAST_ENV: &str = "BULKER_FAIL_FAST";
+
+/// Send SIGTERM to the process group named by `$BULKER_FAIL_FAST`, if set.
+/// Other running shimlinks in the group pass it on to their containers.
+fn stop_run_on_failure(command: &str, exit_code: i32) {
+    let Some(pgid) = std::env::var(FAIL_FAST_ENV).ok().and_then(|v| v.parse::<i32>().ok()).filter(|&p| p > 1) else {
+        return;
+    };
+    log::error!("'{}' failed (exit {}); stopping the run ({})", command, exit_code, FAIL_FAST_ENV);
+    let _ = nix::sys::signal::killpg(nix::unistd::Pid::from_raw(pgid), nix::sys::signal::Signal::SIGTERM);
+}
+
+/// Prefix for the output lines of `command`, docker-compose style, if
+/// `$BULKER_PREFIX_OUTPUT` is set. Interactive runs keep their terminal.
+fn output_prefix(command: &str, interactive: bool) -> Option<String> {
+    let enabled = std::env::var(PREFIX_OUTPUT_ENV).is_ok_and(|v| !v.is_empty() && v != "0");
+    (enabled && !interactive).then(|| format!("{} | ", command))
+}
+
+/// Swap the real `$HOME` for the throwaway directory `home`: drop volumes that
+/// mount the real home, keep the working directory mounted if it lies inside
+/// it, and point HOME at `home` (apptainer gets `--home` instead).
+pub(crate) fn isolate_home(volumes: &mut Vec<String>, envvars: &mut Vec<String>, home: &str, is_apptainer: bool) {
+    let real_home = expand_path("$HOME");
+    volumes.retain(|v| expand_path(split_volume_mode(v).0) != real_home);
+    if let Ok(cwd) = std::env::current_dir() {
+        if cwd != Path::new(&real_home) && cwd.starts_with(&real_home) {
+            merge_auto_mounts(volumes, &[cwd.to_string_lossy().to_string()]);
+        } else if cwd == Path::new(&real_home) {
+            log::warn!("Working directory is $HOME, which is not mounted with an isolated home");
+        }
+    }
+    envvars.retain(|e| e != "HOME" && !e.starts_with("HOME="));
+    if !is_apptainer {
+        volumes.push(home.to_string());
+        envvars.push(format!("HOME={}", home));
+    }
+}
+
+/// Point `link` at `target`, atomically replacing any existing entry, so a
+/// shell using a shared shimdir never sees the command missing mid-update.
+fn replace_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    let name = link.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let tmp = link.with_file_name(format!(".{}.tmp-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(target, &tmp)?;
+    std::fs::rename(&tmp, link).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
 /// Returns the host temp directory path to auto-mount.
 /// Uses $TMPDIR if set, otherwise falls back to "/tmp".
 pub(crate) fn tmpdir_volume() -> String {
@@ -589,6 +1496,22 @@ pub(crate) fn tmpdir_volume() -> String {
         .unwrap_or_else(|_| "/tmp".to_string())
 }
 
+/// Warn when the scratch directory mounted into containers is nearly full,
+/// since tools then fail with confusing "no space left" errors.
+fn warn_if_scratch_low(dir: &str) {
+    const MIN_SCRATCH_FREE_BYTES: u64 = 100 * 1024 * 1024;
+    if let Ok(stat) = nix::sys::statvfs::statvfs(dir) {
+        let free = crate::activate::available_bytes(&stat);
+        if free < MIN_SCRATCH_FREE_BYTES {
+            log::warn!(
+                "Scratch directory {} has only {} free; set TMPDIR to a larger directory",
+                dir,
+                crate::humanize::format_size(free)
+            );
+        }
+    }
+}
+
 /// Default environment variable patterns forwarded into containers.
 /// Prefix patterns (e.g., "SLURM_*") match any host var with that prefix.
 /// Cloud credentials and path-based vars are intentionally excluded —
@@ -636,6 +1559,20 @@ pub(crate) const DEFAULT_ENVVARS: &[&str] = &[
     "VISUAL",
 ];
 
+/// Host locale variables, as envvar patterns (`locale_policy: forward`).
+const LOCALE_ENVVARS: &[&str] = &["LANG", "LANGUAGE", "LC_*"];
+
+/// `locale_policy: c.utf8`: drop locale variables forwarded from the host and
+/// set `LANG=C.UTF-8`. Explicit `KEY=value` entries are kept, and an explicit
+/// LANG wins.
+pub(crate) fn use_c_utf8_locale(envvars: &mut Vec<String>) {
+    let is_locale = |key: &str| key == "LANG" || key == "LANGUAGE" || key.starts_with("LC_");
+    envvars.retain(|e| e.contains('=') || !is_locale(e));
+    if !envvars.iter().any(|e| e.starts_with("LANG=")) {
+        envvars.push("LANG=C.UTF-8".to_string());
+    }
+}
+
 /// Expand envvar patterns against the current host environment.
 /// - Exact names (e.g. "TERM"): included if set on host, as the name only
 /// - Prefix globs (e.g. "SLURM_*"): expand to all matching host vars
@@ -671,6 +1608,30 @@ pub(crate) fn expand_envvar_patterns(patterns: &[String]) -> Vec<String> {
     result
 }
 
+/// Add a crate's `env_defaults` to the container environment. A default
+/// replaces a variable forwarded from the host by name, but not an explicit
+/// `KEY=value` entry. Values are expanded against the host environment.
+/// Returns the entries that were applied.
+pub(crate) fn apply_env_defaults(envvars: &mut Vec<String>, defaults: &[String]) -> Vec<String> {
+    let mut applied = Vec::new();
+    for default in defaults {
+        let Some((key, value)) = default.split_once('=').filter(|(k, _)| !k.is_empty()) else {
+            log::warn!("Ignoring env_defaults entry '{}': expected KEY=value", default);
+            continue;
+        };
+        let prefix = format!("{}=", key);
+        if envvars.iter().any(|e| e.starts_with(&prefix)) {
+            log::debug!("env_defaults: {} is set explicitly, keeping it", key);
+            continue;
+        }
+        remove_key(envvars, key);
+        let entry = format!("{}={}", key, expand_path(value));
+        envvars.push(entry.clone());
+        applied.push(entry);
+    }
+    applied
+}
+
 /// Remove any entry for a given key from the envvar list.
 /// Handles both name-only ("KEY") and KEY=VALUE ("KEY=...") forms.
 fn remove_key(list: &mut Vec<String>, key: &str) {
@@ -685,6 +1646,25 @@ fn stdin_is_tty() -> bool {
     std::io::stdin().is_terminal()
 }
 
+/// Whether the container gets a TTY: only for a terminal stdin, and not when
+/// the output is relayed with a prefix or captured, which need separate
+/// stdout/stderr and no carriage returns.
+fn wants_tty(stdin_tty: bool, captured: bool, prefixed: bool) -> bool {
+    stdin_tty && !captured && !prefixed
+}
+
+/// Whether docker args detach the container (`-d`, `--detach`, or a short
+/// flag group such as `-dit`).
+fn is_detached(args: &[String]) -> bool {
+    args.iter().any(|arg| {
+        arg == "--detach"
+            || arg == "--detach=true"
+            || arg.strip_prefix('-').is_some_and(|flags| {
+                !flags.starts_with('-') && flags.contains('d') && flags.chars().all(|c| matches!(c, 'd' | 'i' | 't'))
+            })
+    })
+}
+
 /// Strip the `-t` / `--tty` flag from a docker_args string when stdin is not a TTY.
 /// Converts `-it` to `-i`, `-ti` to `-i`, removes standalone `-t` and `--tty`,
 /// and strips `t` from compound short flags like `-dit` → `-di`.
@@ -762,23 +1742,49 @@ mod tests {
     use crate::manifest::{ManifestInner, Manifest};
 
     #[test]
-    fn test_detect_shimlink_invocation_returns_none_for_bulker() {
-        // We cannot easily test argv[0] detection since it depends on the actual binary name.
-        // Instead, test the logic directly.
-        let filename = "bulker";
-        assert_eq!(filename == "bulker", true);
+    fn test_shimlink_command_name_outside_activation() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let shimdir = tmpdir.path().join("bulker_abc123");
+        std::fs::create_dir(&shimdir).unwrap();
+        let _guard = crate::test_util::EnvGuard::remove("BULKERCRATE").and_remove("BULKER_SHIMDIR").and_remove(SHIMLINK_ENV);
+
+        // Only links in a shimdir dispatch
+        assert_eq!(shimlink_command_name(&format!("{}/samtools", shimdir.display())).as_deref(), Some("samtools"));
+        assert_eq!(shimlink_command_name(&format!("{}/_samtools", shimdir.display())).as_deref(), Some("_samtools"));
+        assert_eq!(shimlink_command_name("samtools"), None);
+        assert_eq!(shimlink_command_name("/usr/local/bin/bk"), None);
+        assert_eq!(shimlink_command_name("/opt/bin/bulker-0.0.16"), None);
+        let custom = tmpdir.path().join("shims");
+        std::fs::create_dir(&custom).unwrap();
+        assert_eq!(shimlink_command_name(&format!("{}/samtools", custom.display())), None);
+        std::fs::write(custom.join(crate::config::CONFIG_SNAPSHOT_FILE), "{}").unwrap();
+        assert_eq!(shimlink_command_name(&format!("{}/samtools", custom.display())).as_deref(), Some("samtools"));
+        let shared = tmpdir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::write(shared.join(crate::activate::SHARED_SHIMDIR_MARKER), "").unwrap();
+        assert_eq!(shimlink_command_name(&format!("{}/samtools", shared.display())).as_deref(), Some("samtools"));
     }
 
     #[test]
-    fn test_detect_shimlink_name_extraction() {
-        // Test the file_name extraction logic
-        let path = std::path::Path::new("/usr/local/bin/samtools");
-        let filename = path.file_name().unwrap().to_str().unwrap();
-        assert_eq!(filename, "samtools");
+    fn test_shimlink_command_name_odd_argv0() {
+        let _guard = crate::test_util::EnvGuard::set("BULKERCRATE", "bulker/demo:default").and_remove(SHIMLINK_ENV);
+        assert_eq!(shimlink_command_name("/usr/local/bin/samtools").as_deref(), Some("samtools"));
+        assert_eq!(shimlink_command_name("samtools").as_deref(), Some("samtools"));
+        // bulker itself, login-style `-name`s and paths without a file name
+        for argv0 in ["bulker", "./bulker", "/opt/bin/bulker", "-bulker", "-samtools", "", "/", ".."] {
+            assert_eq!(shimlink_command_name(argv0), None, "{:?}", argv0);
+        }
+        // Commands may start with "bulker" (selftest's does)
+        assert_eq!(shimlink_command_name("bulker-selftest-echo").as_deref(), Some("bulker-selftest-echo"));
+    }
 
-        let path2 = std::path::Path::new("/usr/local/bin/bulker");
-        let filename2 = path2.file_name().unwrap().to_str().unwrap();
-        assert_eq!(filename2, "bulker");
+    #[test]
+    fn test_shimlink_command_name_escape_hatch() {
+        let _guard = crate::test_util::EnvGuard::set(SHIMLINK_ENV, "0").and_set("BULKERCRATE", "bulker/demo:default");
+        assert_eq!(shimlink_command_name("/tmp/bulker_x/samtools"), None);
+        drop(_guard);
+        let _guard = crate::test_util::EnvGuard::set(SHIMLINK_ENV, "1").and_remove("BULKERCRATE");
+        assert_eq!(shimlink_command_name("samtools").as_deref(), Some("samtools"));
     }
 
     #[test]
@@ -805,7 +1811,7 @@ mod tests {
     #[test]
     fn test_resolve_arg_paths_flags_pass_through() {
         let args = vec!["--verbose".to_string(), "-n".to_string(), "5".to_string()];
-        let (resolved, auto_mounts) = resolve_arg_paths(&args);
+        let (resolved, auto_mounts) = resolve_arg_paths(&args, &crate::mountignore::MountIgnore::default(), AutoMountMode::Rw, &Default::default());
         assert_eq!(resolved, args);
         assert!(auto_mounts.is_empty());
     }
@@ -814,10 +1820,185 @@ mod tests {
     fn test_resolve_arg_paths_existing_file() {
         // /tmp always exists
         let args = vec!["/tmp".to_string()];
-        let (resolved, _auto_mounts) = resolve_arg_paths(&args);
+        let (resolved, _auto_mounts) = resolve_arg_paths(&args, &crate::mountignore::MountIgnore::default(), AutoMountMode::Rw, &Default::default());
         assert_eq!(resolved[0], "/tmp");
     }
 
+    #[test]
+    fn test_resolve_arg_paths_skips_mountignored_parent() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let secret_dir = std::fs::canonicalize(tmpdir.path()).unwrap().join(".ssh");
+        std::fs::create_dir_all(&secret_dir).unwrap();
+        let key = secret_dir.join("id_rsa");
+        std::fs::write(&key, "x").unwrap();
+
+        let args = vec![key.to_string_lossy().to_string()];
+        let (_, mounts) = resolve_arg_paths(&args, &crate::mountignore::MountIgnore::default(), AutoMountMode::Rw, &Default::default());
+        assert_eq!(mounts, vec![secret_dir.to_string_lossy().to_string()]);
+
+        let ignore = crate::mountignore::MountIgnore::parse(".ssh/\n");
+        let (resolved, mounts) = resolve_arg_paths(&args, &ignore, AutoMountMode::Rw, &Default::default());
+        assert!(mounts.is_empty(), "ignored dir was auto-mounted: {:?}", mounts);
+        assert_eq!(resolved, args);
+
+        // The parent of a harmless file is not mounted when it holds an ignored entry
+        let notes = secret_dir.parent().unwrap().join("notes.txt");
+        std::fs::write(&notes, "x").unwrap();
+        let args = vec![notes.to_string_lossy().to_string()];
+        let (_, mounts) = resolve_arg_paths(&args, &ignore, AutoMountMode::Rw, &Default::default());
+        assert!(mounts.is_empty(), "dir holding an ignored entry was auto-mounted: {:?}", mounts);
+    }
+
+    #[test]
+    fn test_resolve_arg_paths_ro_inputs() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let base = std::fs::canonicalize(tmpdir.path()).unwrap();
+        let inputs = base.join("inputs");
+        let outputs = base.join("outputs");
+        std::fs::create_dir_all(&inputs).unwrap();
+        std::fs::create_dir_all(&outputs).unwrap();
+        std::fs::write(inputs.join("reads.fq"), "x").unwrap();
+
+        let args = vec![
+            inputs.join("reads.fq").to_string_lossy().to_string(),
+            outputs.join("out.bam").to_string_lossy().to_string(),
+        ];
+        let ignore = crate::mountignore::MountIgnore::default();
+        let (_, mounts) = resolve_arg_paths(&args, &ignore, AutoMountMode::RoInputs, &Default::default());
+        assert_eq!(mounts, vec![
+            format!("{}:ro", inputs.display()),
+            outputs.to_string_lossy().to_string(),
+        ]);
+
+        // A directory holding both an input and an output stays writable
+        let args = vec![
+            inputs.join("reads.fq").to_string_lossy().to_string(),
+            inputs.join("reads.out").to_string_lossy().to_string(),
+        ];
+        let (_, mounts) = resolve_arg_paths(&args, &ignore, AutoMountMode::RoInputs, &Default::default());
+        assert_eq!(mounts, vec![inputs.to_string_lossy().to_string()]);
+    }
+
+    #[test]
+    fn test_map_path_longest_prefix() {
+        let map: std::collections::BTreeMap<String, String> = [("/mnt/gpfs", "/gpfs"), ("/mnt/gpfs/scratch", "/scratch")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(map_path("/mnt/gpfs/proj/a.bam", &map), "/gpfs/proj/a.bam");
+        assert_eq!(map_path("/mnt/gpfs", &map), "/gpfs");
+        assert_eq!(map_path("/mnt/gpfs/scratch/tmp", &map), "/scratch/tmp");
+        // Only whole path components match
+        assert_eq!(map_path("/mnt/gpfs2/x", &map), "/mnt/gpfs2/x");
+        assert_eq!(map_path("/home/u", &map), "/home/u");
+
+        let config = BulkerConfig { bulker: crate::config::BulkerSettings { path_map: map, ..BulkerConfig::test_default().bulker } };
+        let pkg = PackageCommand { command: "tool".to_string(), docker_image: "img".to_string(), ..Default::default() };
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { volumes: &["/mnt/gpfs/proj:ro".to_string()], engine_path: "docker", ..Default::default() });
+        assert!(cmd.windows(2).any(|w| w[0] == "--volume" && w[1] == "/mnt/gpfs/proj:/gpfs/proj:ro"), "{:?}", cmd);
+    }
+
+    #[test]
+    fn test_merge_auto_mounts_skips_already_mounted() {
+        let mut volumes = vec!["/data".to_string()];
+        merge_auto_mounts(&mut volumes, &["/data:ro".to_string(), "/ref:ro".to_string()]);
+        assert_eq!(volumes, vec!["/data".to_string(), "/ref:ro".to_string()]);
+    }
+
+    #[test]
+    fn test_build_docker_command_read_only_volume() {
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand {
+            command: "samtools".to_string(),
+            docker_image: "img".to_string(),
+            ..Default::default()
+        };
+        let volumes = vec!["/ref:ro".to_string()];
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { volumes: &volumes, engine_path: "docker", ..Default::default() });
+        let cmd_str = cmd.join(" ");
+        assert!(cmd_str.contains("--volume /ref:/ref:ro"), "got: {}", cmd_str);
+    }
+
+    #[test]
+    fn test_volume_paths_with_spaces_colons_and_unicode() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.apptainer_image_folder = Some("/tmp/sif".to_string());
+        let pkg = PackageCommand {
+            command: "samtools".to_string(),
+            docker_image: "img".to_string(),
+            ..Default::default()
+        };
+        let volumes = vec![
+            "/data/my runs".to_string(),
+            "/data/run:2024-01-01:ro".to_string(),
+            "/data/a,b \"c\"".to_string(),
+            "/données/échantillons".to_string(),
+        ];
+
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { volumes: &volumes, engine_path: "docker", ..Default::default() });
+        let after = |flag: &str, value: &str| cmd.windows(2).any(|w| w[0] == flag && w[1] == value);
+        assert!(after("--volume", "/data/my runs:/data/my runs"), "{:?}", cmd);
+        assert!(after("--mount", "type=bind,source=/data/run:2024-01-01,target=/data/run:2024-01-01,readonly"), "{:?}", cmd);
+        assert!(after("--mount", "type=bind,\"source=/data/a,b \"\"c\"\"\",\"target=/data/a,b \"\"c\"\"\""), "{:?}", cmd);
+        assert!(after("--volume", "/données/échantillons:/données/échantillons"), "{:?}", cmd);
+
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { volumes: &volumes, engine_path: "apptainer", ..Default::default() });
+        let after = |flag: &str, value: &str| cmd.windows(2).any(|w| w[0] == flag && w[1] == value);
+        assert!(after("-B", "/data/my runs:/data/my runs"), "{:?}", cmd);
+        assert!(after("--mount", "type=bind,src=/data/run:2024-01-01,dst=/data/run:2024-01-01,ro"), "{:?}", cmd);
+        assert!(after("-B", "/données/échantillons:/données/échantillons"), "{:?}", cmd);
+    }
+
+    #[test]
+    fn test_build_docker_command_umask() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.umask = Some("0022".to_string());
+        let pkg = PackageCommand {
+            command: "samtools".to_string(),
+            docker_image: "quay.io/samtools:1.17".to_string(),
+            umask: Some("0002".to_string()),
+            ..Default::default()
+        };
+        let args = vec!["view".to_string()];
+        // docker: a shell applies the command's umask (which wins) and execs the command
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { args: &args, engine_path: "docker", ..Default::default() });
+        assert!(cmd.contains(&"--entrypoint=/bin/sh".to_string()), "{:?}", cmd);
+        let image = cmd.iter().position(|a| a == "quay.io/samtools:1.17").unwrap();
+        assert_eq!(cmd[image + 1..], ["-c", "umask 0002 && exec \"$@\"", "sh", "samtools", "view"]);
+
+        // An explicit entrypoint becomes the first exec'd argument
+        let with_entrypoint = PackageCommand { entrypoint: Some("/opt/bin/tool".to_string()), ..pkg.clone() };
+        let cmd = build_docker_command(&config, &with_entrypoint, &CommandOptions { args: &args, engine_path: "docker", ..Default::default() });
+        assert_eq!(cmd[cmd.len() - 2..], ["/opt/bin/tool", "view"]);
+
+        // podman takes the umask as a flag and keeps the command as is
+        config.bulker.container_engine = "podman".to_string();
+        let cmd = build_docker_command(&config, &PackageCommand { umask: None, ..pkg.clone() }, &CommandOptions { args: &args, engine_path: "podman", ..Default::default() });
+        assert!(cmd.contains(&"--umask=0022".to_string()), "{:?}", cmd);
+        assert!(!cmd.iter().any(|a| a.starts_with("--entrypoint")), "{:?}", cmd);
+
+        // No shell in the image: the umask cannot be applied under docker
+        config.bulker.container_engine = "docker".to_string();
+        let shell_less = PackageCommand { shell_in_container: Some("none".to_string()), ..pkg };
+        let cmd = build_docker_command(&config, &shell_less, &CommandOptions { args: &args, engine_path: "docker", ..Default::default() });
+        assert_eq!(cmd[cmd.len() - 2..], ["samtools", "view"]);
+    }
+
+    #[test]
+    fn test_check_volume_paths_rejects_newlines() {
+        assert!(check_volume_paths(&["/data/my runs:ro".to_string(), "/data/x:y".to_string()]).is_ok());
+        let err = check_volume_paths(&["/data/bad\nname".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("newline"), "{}", err);
+    }
+
+    #[test]
+    fn test_display_arg_quotes_only_when_needed() {
+        assert_eq!(display_arg("--volume"), "--volume");
+        assert_eq!(display_arg("/data:/data:ro"), "/data:/data:ro");
+        assert_eq!(display_arg("/data/my runs:/data/my runs"), "'/data/my runs:/data/my runs'");
+        assert_eq!(display_arg(""), "''");
+    }
+
     #[test]
     fn test_shell_split_simple() {
         let result = shell_split("--gpus all --shm-size 8g");
@@ -848,7 +2029,7 @@ mod tests {
         let envvars = vec!["DISPLAY".to_string()];
         let args = vec!["view".to_string(), "test.bam".to_string()];
 
-        let cmd = build_docker_command(&config, &pkg, &volumes, &envvars, "", &args, false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { volumes: &volumes, envvars: &envvars, args: &args, engine_path: "docker", ..Default::default() });
 
         assert_eq!(cmd[0], "docker");
         assert_eq!(cmd[1], "run");
@@ -867,6 +2048,28 @@ mod tests {
         assert!(cmd.contains(&"--network=host".to_string()));
     }
 
+    #[test]
+    fn test_extra_engine_args_scoped_to_command() {
+        assert_eq!(command_env_suffix("bowtie2-build"), "BOWTIE2_BUILD");
+        assert_eq!(command_env_suffix("multiqc.py"), "MULTIQC_PY");
+        let _guard = crate::test_util::EnvGuard::set(EXTRA_DOCKER_ARGS_ENV, "--shm-size=1g")
+            .and_set("BULKER_EXTRA_DOCKER_ARGS_BOWTIE2_BUILD", "--memory=8g");
+        assert_eq!(extra_engine_args(EXTRA_DOCKER_ARGS_ENV, "bowtie2-build"), "--shm-size=1g --memory=8g");
+        assert_eq!(extra_engine_args(EXTRA_DOCKER_ARGS_ENV, "samtools"), "--shm-size=1g");
+        assert_eq!(extra_engine_args(EXTRA_APPTAINER_ARGS_ENV, "bowtie2-build"), "");
+    }
+
+    #[test]
+    fn test_use_c_utf8_locale() {
+        let mut envvars = vec!["TERM".to_string(), "LANG".to_string(), "LC_ALL".to_string(), "LC_NUMERIC=C".to_string()];
+        use_c_utf8_locale(&mut envvars);
+        assert_eq!(envvars, vec!["TERM", "LC_NUMERIC=C", "LANG=C.UTF-8"]);
+        // An explicit LANG wins
+        let mut envvars = vec!["LANG=en_US.UTF-8".to_string()];
+        use_c_utf8_locale(&mut envvars);
+        assert_eq!(envvars, vec!["LANG=en_US.UTF-8"]);
+    }
+
     #[test]
     fn test_build_docker_command_interactive() {
         let config = BulkerConfig::test_default();
@@ -875,13 +2078,53 @@ mod tests {
             docker_image: "quay.io/biocontainers/samtools:1.9".to_string(),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], true, "docker");
-        // Interactive flag controls bash launch, not -it (TTY is auto-detected)
-        assert!(cmd.contains(&"bash".to_string()));
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { interactive: true, engine_path: "docker", ..Default::default() });
+        // Interactive flag controls the shell launch, not -it (TTY is auto-detected)
+        assert!(cmd.contains(&"--entrypoint=/bin/sh".to_string()));
+        assert!(cmd.iter().any(|a| a.contains("exec bash")));
         // -i or -it depending on TTY state
         assert!(cmd.contains(&"-i".to_string()) || cmd.contains(&"-it".to_string()));
     }
 
+    #[test]
+    fn test_build_docker_command_no_init() {
+        let mut config = BulkerConfig::test_default();
+        let mut pkg = PackageCommand {
+            command: "tool".to_string(),
+            docker_image: "myimage:latest".to_string(),
+            ..Default::default()
+        };
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        assert!(cmd.contains(&"--init".to_string()));
+
+        pkg.no_init = true;
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        assert!(!cmd.contains(&"--init".to_string()));
+
+        // Config-level default applies to every command
+        pkg.no_init = false;
+        config.bulker.no_init = true;
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        assert!(!cmd.contains(&"--init".to_string()));
+    }
+
+    #[test]
+    fn test_build_docker_command_platform_and_pull() {
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand {
+            command: "tool".to_string(),
+            docker_image: "myimage:latest".to_string(),
+            platform: Some("linux/amd64".to_string()),
+            pull_policy: Some("always".to_string()),
+            ..Default::default()
+        };
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        let image_idx = cmd.iter().position(|a| a == "myimage:latest").unwrap();
+        let platform_idx = cmd.iter().position(|a| a == "--platform=linux/amd64").unwrap();
+        assert!(platform_idx < image_idx);
+        assert!(cmd.contains(&"--pull=always".to_string()));
+    }
+
     #[test]
     fn test_build_docker_command_no_user() {
         let config = BulkerConfig::test_default();
@@ -891,7 +2134,7 @@ mod tests {
             no_user: true,
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
         // Should NOT contain --user= or system volumes
         let cmd_str = cmd.join(" ");
         assert!(!cmd_str.contains("--user="));
@@ -907,7 +2150,7 @@ mod tests {
             docker_image: "myimage:latest".to_string(),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
         assert!(!cmd.contains(&"--network=host".to_string()));
     }
 
@@ -920,11 +2163,66 @@ mod tests {
             docker_image: "myimage:latest".to_string(),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
         let cmd_str = cmd.join(" ");
         assert!(!cmd_str.contains("/etc/passwd"));
     }
 
+    #[test]
+    fn test_build_docker_command_publishes_ports_without_host_network() {
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand {
+            command: "igv-server".to_string(),
+            docker_image: "igv/server:latest".to_string(),
+            ports: vec!["60151:60151".to_string()],
+            ..Default::default()
+        };
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        assert!(cmd.contains(&"--publish=60151:60151".to_string()));
+        assert!(!cmd.contains(&"--network=host".to_string()));
+        assert!(cmd.contains(&"--label=bulker.command=igv-server".to_string()));
+    }
+
+    #[test]
+    fn test_host_access_args_per_engine() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let socket = tmpdir.path().join("engine.sock");
+        std::fs::write(&socket, "").unwrap();
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand {
+            command: "mounter".to_string(),
+            docker_image: "org/mounter:1".to_string(),
+            devices: vec!["/dev/null:/dev/fuse".to_string(), "/dev/does-not-exist".to_string()],
+            sockets: vec![format!("{}:/var/run/docker.sock", socket.display())],
+            ..Default::default()
+        };
+        let socket_bind = format!("{}:/var/run/docker.sock", socket.display());
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        assert!(cmd.contains(&"--device=/dev/null:/dev/fuse".to_string()), "{:?}", cmd);
+        assert!(cmd.windows(2).any(|w| w[0] == "--volume" && w[1] == socket_bind), "{:?}", cmd);
+        // Missing host paths are left out
+        assert!(!cmd.iter().any(|a| a.contains("does-not-exist")), "{:?}", cmd);
+
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "apptainer", ..Default::default() });
+        assert!(cmd.windows(2).any(|w| w[0] == "-B" && w[1] == "/dev/null:/dev/fuse"), "{:?}", cmd);
+        assert!(cmd.windows(2).any(|w| w[0] == "-B" && w[1] == socket_bind), "{:?}", cmd);
+    }
+
+    #[test]
+    fn test_check_apptainer_ports_rejects_remap() {
+        let mut pkg = PackageCommand {
+            command: "igv-server".to_string(),
+            docker_image: "igv/server:latest".to_string(),
+            ports: vec!["8080:80".to_string()],
+            ..Default::default()
+        };
+        let err = check_apptainer_ports(&pkg).unwrap_err().to_string();
+        assert!(err.contains("8080:80"), "{}", err);
+
+        pkg.ports = vec!["60151:60151".to_string()];
+        assert!(check_apptainer_ports(&pkg).is_ok());
+    }
+
     #[test]
     fn test_build_docker_command_no_network() {
         let config = BulkerConfig::test_default();
@@ -934,10 +2232,72 @@ mod tests {
             no_network: true,
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
         assert!(!cmd.contains(&"--network=host".to_string()));
     }
 
+    #[test]
+    fn test_gpu_flag_per_engine() {
+        let config = BulkerConfig::test_default();
+        let mut pkg = PackageCommand {
+            command: "train".to_string(),
+            docker_image: "pytorch/pytorch:2.3".to_string(),
+            ..Default::default()
+        };
+        assert!(!build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() }).contains(&"--gpus=all".to_string()));
+        pkg.gpu = true;
+        let docker = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        let image = docker.iter().position(|a| a == "pytorch/pytorch:2.3").unwrap();
+        assert!(docker[..image].contains(&"--gpus=all".to_string()), "{:?}", docker);
+        let apptainer = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "apptainer", ..Default::default() });
+        assert!(apptainer.contains(&"--nv".to_string()), "{:?}", apptainer);
+        assert!(!apptainer.contains(&"--gpus=all".to_string()));
+    }
+
+    #[test]
+    fn test_gpu_flag_under_podman_uses_cdi() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.container_engine = "podman".to_string();
+        let pkg = PackageCommand {
+            command: "train".to_string(),
+            docker_image: "pytorch/pytorch:2.3".to_string(),
+            gpu: true,
+            ..Default::default()
+        };
+        let podman = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "podman", ..Default::default() });
+        let image = podman.iter().position(|a| a == "pytorch/pytorch:2.3").unwrap();
+        assert!(podman[..image].windows(2).any(|w| w == ["--device", "nvidia.com/gpu=all"]), "{:?}", podman);
+        assert!(!podman.contains(&"--gpus=all".to_string()), "{:?}", podman);
+    }
+
+    #[test]
+    fn test_gpus_requested_checks_value() {
+        let _guard = crate::test_util::EnvGuard::remove(GPUS_ENV);
+        assert!(!gpus_requested());
+        drop(_guard);
+        for (value, expected) in [("1", true), ("yes", true), ("0", false), ("", false)] {
+            let _guard = crate::test_util::EnvGuard::set(GPUS_ENV, value);
+            assert_eq!(gpus_requested(), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_resource_limits_fall_back_to_config() {
+        let mut config = BulkerConfig::test_default();
+        config.bulker.memory = Some("16G".to_string());
+        config.bulker.shm_size = Some("lots".to_string());
+        let pkg: PackageCommand = serde_yml::from_str("{command: train, docker_image: img, cpus: 2, memory: 8g}").unwrap();
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
+        assert!(cmd.contains(&"--cpus=2".to_string()) && cmd.contains(&"--memory=8g".to_string()), "{:?}", cmd);
+        assert!(!cmd.iter().any(|a| a.starts_with("--shm-size") || a == "--memory=16G"), "{:?}", cmd);
+
+        config.bulker.shm_size = Some("2G".to_string());
+        let pkg = PackageCommand { command: "train".to_string(), docker_image: "img".to_string(), cpus: Some("0".to_string()), ..Default::default() };
+        assert_eq!(resource_limit_args(&config, &pkg), ["--memory=16G", "--shm-size=2G"]);
+        let apptainer = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "apptainer", ..Default::default() });
+        assert!(!apptainer.iter().any(|a| a.starts_with("--memory")));
+    }
+
     #[test]
     fn test_build_docker_command_with_docker_command() {
         let config = BulkerConfig::test_default();
@@ -947,7 +2307,7 @@ mod tests {
             docker_command: Some("python3".to_string()),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &["--version".to_string()], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { args: &["--version".to_string()], engine_path: "docker", ..Default::default() });
         // Should use docker_command instead of command
         assert!(cmd.contains(&"python3".to_string()));
     }
@@ -962,7 +2322,7 @@ mod tests {
         };
         let home = std::env::var("HOME").unwrap();
         let docker_args = "--volume=${HOME}/R/4.0:/usr/local/lib/R/host-site-library";
-        let cmd = build_docker_command(&config, &pkg, &[], &[], docker_args, &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { docker_args, engine_path: "docker", ..Default::default() });
         let cmd_str = cmd.join(" ");
         // ${HOME} should be expanded, not passed literally
         assert!(!cmd_str.contains("${HOME}"), "env var not expanded: {}", cmd_str);
@@ -979,7 +2339,7 @@ mod tests {
             ..Default::default()
         };
 
-        let cmd = build_apptainer_command(&config, &pkg, &[], &[], &[], false, "apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "apptainer", ..Default::default() });
         assert_eq!(cmd[0], "apptainer");
         assert_eq!(cmd[1], "exec");
         // Should contain the SIF path
@@ -993,7 +2353,6 @@ mod tests {
         let manifest = Manifest {
             manifest: ManifestInner {
                 name: Some("test".to_string()),
-                version: None,
                 commands: vec![
                     PackageCommand {
                         command: "samtools".to_string(),
@@ -1006,9 +2365,9 @@ mod tests {
                         ..Default::default()
                     },
                 ],
-                host_commands: vec![],
-                imports: vec![],
+                ..Default::default()
             },
+            ..Default::default()
         };
 
         let tmpdir = tempfile::tempdir().unwrap();
@@ -1087,7 +2446,7 @@ mod tests {
             docker_image: "quay.io/biocontainers/samtools:1.9".to_string(),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], false, "/usr/bin/docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "/usr/bin/docker", ..Default::default() });
         assert_eq!(cmd[0], "/usr/bin/docker");
     }
 
@@ -1100,7 +2459,7 @@ mod tests {
             docker_image: "quay.io/biocontainers/samtools:1.9".to_string(),
             ..Default::default()
         };
-        let cmd = build_apptainer_command(&config, &pkg, &[], &[], &[], false, "/usr/local/bin/apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "/usr/local/bin/apptainer", ..Default::default() });
         assert_eq!(cmd[0], "/usr/local/bin/apptainer");
     }
 
@@ -1112,7 +2471,7 @@ mod tests {
     #[test]
     fn test_find_command_in_imported_crate() {
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
 
@@ -1125,15 +2484,15 @@ mod tests {
         let child_manifest = Manifest {
             manifest: ManifestInner {
                 name: Some("coreutils".to_string()),
-                version: None,
                 commands: vec![PackageCommand {
                     command: "cat".to_string(),
                     docker_image: "alpine:latest".to_string(),
                     ..make_empty_pkg()
                 }],
-                host_commands: vec![],
-                imports: vec![],
+                env_defaults: vec!["COREUTILS_DEFAULT=1".to_string()],
+                ..Default::default()
             },
+            ..Default::default()
         };
         crate::manifest_cache::save_to_cache(&child_cv, &child_manifest).unwrap();
 
@@ -1146,33 +2505,34 @@ mod tests {
         let parent_manifest = Manifest {
             manifest: ManifestInner {
                 name: Some("parent".to_string()),
-                version: None,
                 commands: vec![PackageCommand {
                     command: "samtools".to_string(),
                     docker_image: "samtools:latest".to_string(),
                     ..make_empty_pkg()
                 }],
-                host_commands: vec![],
                 imports: vec!["bulker/coreutils_shimtest:default".to_string()],
+                ..Default::default()
             },
+            ..Default::default()
         };
         crate::manifest_cache::save_to_cache(&parent_cv, &parent_manifest).unwrap();
 
         // Look up "cat" starting from the parent crate — should find it in the import
-        let pkg = find_command_in_crates_with_imports(&config, &[parent_cv.clone()], "cat").unwrap();
+        let (pkg, env_defaults) = find_command_in_crates_with_imports(&config, &[parent_cv.clone()], "cat").unwrap();
         assert_eq!(pkg.command, "cat");
+        assert_eq!(env_defaults, vec!["COREUTILS_DEFAULT=1"]);
 
         // Also verify "samtools" is found in the primary crate
-        let pkg2 = find_command_in_crates_with_imports(&config, &[parent_cv], "samtools").unwrap();
+        let (pkg2, _) = find_command_in_crates_with_imports(&config, &[parent_cv], "samtools").unwrap();
         assert_eq!(pkg2.command, "samtools");
 
-        // EnvGuard restores XDG_CONFIG_HOME on drop
+        // EnvGuard restores XDG_CACHE_HOME on drop
     }
 
     #[test]
     fn test_find_command_across_multiple_activated_crates() {
         let tmpdir = tempfile::tempdir().unwrap();
-        let _guard = crate::test_util::EnvGuard::set("XDG_CONFIG_HOME", tmpdir.path());
+        let _guard = crate::test_util::EnvGuard::set("XDG_CACHE_HOME", tmpdir.path());
 
         let config = BulkerConfig::test_default();
 
@@ -1185,15 +2545,14 @@ mod tests {
         let manifest_a = Manifest {
             manifest: ManifestInner {
                 name: Some("multi_a".to_string()),
-                version: None,
                 commands: vec![PackageCommand {
                     command: "mkdir".to_string(),
                     docker_image: "alpine:latest".to_string(),
                     ..make_empty_pkg()
                 }],
-                host_commands: vec![],
-                imports: vec![],
+                ..Default::default()
             },
+            ..Default::default()
         };
         crate::manifest_cache::save_to_cache(&crate_a_cv, &manifest_a).unwrap();
 
@@ -1206,26 +2565,25 @@ mod tests {
         let manifest_b = Manifest {
             manifest: ManifestInner {
                 name: Some("multi_b".to_string()),
-                version: None,
                 commands: vec![PackageCommand {
                     command: "bowtie2-build".to_string(),
                     docker_image: "bowtie2:latest".to_string(),
                     ..make_empty_pkg()
                 }],
-                host_commands: vec![],
-                imports: vec![],
+                ..Default::default()
             },
+            ..Default::default()
         };
         crate::manifest_cache::save_to_cache(&crate_b_cv, &manifest_b).unwrap();
 
         // Both crates' unique commands must resolve when both are activated
         let cvs = vec![crate_a_cv.clone(), crate_b_cv.clone()];
         assert_eq!(
-            find_command_in_crates_with_imports(&config, &cvs, "mkdir").unwrap().command,
+            find_command_in_crates_with_imports(&config, &cvs, "mkdir").unwrap().0.command,
             "mkdir"
         );
         assert_eq!(
-            find_command_in_crates_with_imports(&config, &cvs, "bowtie2-build").unwrap().command,
+            find_command_in_crates_with_imports(&config, &cvs, "bowtie2-build").unwrap().0.command,
             "bowtie2-build"
         );
 
@@ -1233,10 +2591,36 @@ mod tests {
         let cvs_rev = vec![crate_b_cv, crate_a_cv];
         assert!(find_command_in_crates_with_imports(&config, &cvs_rev, "mkdir").is_ok());
         assert!(find_command_in_crates_with_imports(&config, &cvs_rev, "bowtie2-build").is_ok());
+
+        // A typo is a CommandNotFound listing the searched crates and near misses
+        let err = find_command_in_crates_with_imports(&config, &cvs_rev, "bowtie-build").unwrap_err();
+        let not_found = err.downcast_ref::<CommandNotFound>().expect("CommandNotFound");
+        assert_eq!(not_found.searched, vec!["test/multi_b_shimtest:default", "test/multi_a_shimtest:default"]);
+        assert_eq!(not_found.suggestions, vec!["bowtie2-build"]);
+        assert!(err.to_string().contains("did you mean: bowtie2-build?"), "{}", err);
+    }
+
+    #[test]
+    fn test_similar_commands() {
+        let names = ["samtools", "bcftools", "bowtie2", "bowtie2-build", "ls"];
+        assert_eq!(similar_commands("samtols", names), vec!["samtools"]);
+        assert_eq!(similar_commands("bowtie", names), vec!["bowtie2", "bowtie2-build"]);
+        assert_eq!(similar_commands("l", names), vec!["ls"]);
+        assert!(similar_commands("python", names).is_empty());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
     }
 
     // ─── strip_tty_flag tests ────────────────────────────────────────────────
 
+    #[test]
+    fn test_wants_tty_not_for_captured_or_prefixed_output() {
+        assert!(wants_tty(true, false, false));
+        assert!(!wants_tty(false, false, false));
+        // A memoized run's output goes to the cache, not a terminal
+        assert!(!wants_tty(true, true, false));
+        assert!(!wants_tty(true, false, true));
+    }
+
     #[test]
     fn test_build_docker_command_strips_tty_from_docker_args() {
         let config = BulkerConfig::test_default();
@@ -1246,13 +2630,38 @@ mod tests {
             ..Default::default()
         };
         // docker_args has -it; the -t should be stripped (TTY is auto-detected)
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "-it", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { docker_args: "-it", engine_path: "docker", ..Default::default() });
         // -t from docker_args should be stripped; verify -i from docker_args is present
         let docker_args_idx = cmd.iter().position(|a| a == "--init").unwrap() + 2; // skip auto-detected -i/-it
         assert!(cmd[docker_args_idx..].contains(&"-i".to_string()),
             "docker_args -i should be present after stripping -t: {:?}", cmd);
     }
 
+    #[test]
+    fn test_detached_containers_carry_no_pid_label() {
+        let mut config = BulkerConfig::test_default();
+        let pkg = PackageCommand { command: "postgres".to_string(), docker_image: "postgres:16".to_string(), ..Default::default() };
+        // Only runs that reap their containers are labelled with a pid
+        assert!(!build_docker_command(&config, &pkg, &CommandOptions::new("docker"))
+            .iter()
+            .any(|a| a.starts_with("--label=bulker.pid=")));
+        config.bulker.reap_containers = true;
+        let has_pid = |docker_args: &str| {
+            build_docker_command(&config, &pkg, &CommandOptions { docker_args, engine_path: "docker", ..Default::default() })
+                .iter()
+                .any(|a| a.starts_with("--label=bulker.pid="))
+        };
+        assert!(has_pid(""));
+        assert!(has_pid("--device-read-bps /dev/sda:1mb"));
+        assert!(!has_pid("-d --name pg"));
+        assert!(!has_pid("-dit"));
+        assert!(!has_pid("--detach"));
+
+        let cidfile = Path::new("/cache/containers/42.cid");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { cidfile: Some(cidfile), ..CommandOptions::new("docker") });
+        assert_eq!(&cmd[..4], &["docker", "run", "--rm", "--cidfile=/cache/containers/42.cid"]);
+    }
+
     #[test]
     fn test_strip_tty_flag_it() {
         assert_eq!(strip_tty_flag("-it"), "-i");
@@ -1371,6 +2780,22 @@ mod tests {
         assert_eq!(result.iter().filter(|v| v.as_str() == "TERM").count(), 1);
     }
 
+    #[test]
+    fn test_apply_env_defaults() {
+        let _guard = crate::test_util::EnvGuard::set("BULKER_TEST_RLIB_HOME", "/home/u");
+        let mut envvars = vec!["R_LIBS_USER".to_string(), "PYTHONPATH=/opt/py".to_string()];
+        let defaults = vec![
+            "R_LIBS_USER=$BULKER_TEST_RLIB_HOME/R/4.3".to_string(),
+            "PYTHONPATH=/crate/py".to_string(),
+            "PYTHONNOUSERSITE=1".to_string(),
+            "malformed".to_string(),
+        ];
+        let applied = apply_env_defaults(&mut envvars, &defaults);
+        assert_eq!(applied, vec!["R_LIBS_USER=/home/u/R/4.3", "PYTHONNOUSERSITE=1"]);
+        // The host's R_LIBS_USER is replaced; the explicit PYTHONPATH is kept
+        assert_eq!(envvars, vec!["PYTHONPATH=/opt/py", "R_LIBS_USER=/home/u/R/4.3", "PYTHONNOUSERSITE=1"]);
+    }
+
     #[test]
     fn test_docker_passes_allowlisted_vars() {
         let allowlist = vec!["DISPLAY".to_string(), "LANG".to_string()];
@@ -1380,7 +2805,7 @@ mod tests {
             docker_image: "img:latest".to_string(),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &allowlist, "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { envvars: &allowlist, engine_path: "docker", ..Default::default() });
         let cmd_str = cmd.join(" ");
         assert!(cmd_str.contains("--env DISPLAY"), "allowlisted var should be present: {}", cmd_str);
         assert!(cmd_str.contains("--env LANG"), "allowlisted var should be present: {}", cmd_str);
@@ -1398,7 +2823,7 @@ mod tests {
             ..Default::default()
         };
         let envvars = vec!["DISPLAY".to_string()];
-        let cmd = build_apptainer_command(&config, &pkg, &[], &envvars, &[], false, "apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { envvars: &envvars, engine_path: "apptainer", ..Default::default() });
         assert!(cmd.contains(&"--cleanenv".to_string()), "apptainer should always have --cleanenv: {:?}", cmd);
     }
 
@@ -1426,7 +2851,7 @@ mod tests {
             ..Default::default()
         };
         let volumes = vec!["/tmp".to_string()];
-        let cmd = build_docker_command(&config, &pkg, &volumes, &[], "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { volumes: &volumes, engine_path: "docker", ..Default::default() });
         let cmd_str = cmd.join(" ");
         assert!(cmd_str.contains("--volume /tmp:/tmp"), "tmpdir should be mounted: {}", cmd_str);
     }
@@ -1442,7 +2867,7 @@ mod tests {
             ..Default::default()
         };
         let volumes = vec!["/tmp".to_string()];
-        let cmd = build_apptainer_command(&config, &pkg, &volumes, &[], &[], false, "apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { volumes: &volumes, engine_path: "apptainer", ..Default::default() });
         let cmd_str = cmd.join(" ");
         assert!(cmd_str.contains("-B /tmp:/tmp"), "tmpdir should be bound in apptainer: {}", cmd_str);
     }
@@ -1458,7 +2883,7 @@ mod tests {
             ..Default::default()
         };
         let envvars = vec!["MY_TEST_VAR_APT".to_string()];
-        let cmd = build_apptainer_command(&config, &pkg, &[], &envvars, &[], false, "apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { envvars: &envvars, engine_path: "apptainer", ..Default::default() });
         assert!(cmd.contains(&"--env".to_string()), "should pass allowlisted vars: {:?}", cmd);
         assert!(cmd.contains(&"MY_TEST_VAR_APT=testval".to_string()), "should pass var=value: {:?}", cmd);
     }
@@ -1475,7 +2900,7 @@ mod tests {
             docker_image: "bioconductor/bioconductor_docker:latest".to_string(),
             ..Default::default()
         };
-        let cmd = build_apptainer_command(&config, &pkg, &[], &[], &[], false, "singularity");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "singularity", ..Default::default() });
         assert_eq!(cmd[0], "singularity");
         assert_eq!(cmd[1], "exec");
         let sif_arg = cmd.iter().find(|a| a.contains(".sif")).unwrap();
@@ -1494,7 +2919,7 @@ mod tests {
             entrypoint: Some("jq".to_string()),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &["--version".to_string()], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { args: &["--version".to_string()], engine_path: "docker", ..Default::default() });
         assert!(
             cmd.contains(&"--entrypoint=jq".to_string()),
             "expected --entrypoint=jq flag, got {:?}", cmd
@@ -1512,7 +2937,7 @@ mod tests {
             docker_image: "samtools:1.9".to_string(),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], false, "docker");
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { engine_path: "docker", ..Default::default() });
         assert!(!cmd.iter().any(|a| a.starts_with("--entrypoint")));
         let image_idx = cmd.iter().position(|a| a == "samtools:1.9").unwrap();
         assert_eq!(cmd[image_idx + 1], "samtools");
@@ -1531,7 +2956,7 @@ mod tests {
             docker_command: Some("yq".to_string()),
             ..Default::default()
         };
-        let cmd = build_apptainer_command(&config, &pkg, &[], &[], &["--version".to_string()], false, "apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { args: &["--version".to_string()], engine_path: "apptainer", ..Default::default() });
         let sif_idx = cmd.iter().position(|a| a.ends_with(".sif")).unwrap();
         assert_eq!(cmd[sif_idx + 1], "jq");
         assert_eq!(cmd[sif_idx + 2], "--version");
@@ -1546,7 +2971,7 @@ mod tests {
             docker_image: "samtools:1.9".to_string(),
             ..Default::default()
         };
-        let cmd = build_apptainer_command(&config, &pkg, &[], &[], &[], false, "apptainer");
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { engine_path: "apptainer", ..Default::default() });
         let sif_idx = cmd.iter().position(|a| a.ends_with(".sif")).unwrap();
         assert_eq!(cmd[sif_idx + 1], "samtools");
     }
@@ -1560,10 +2985,32 @@ mod tests {
             entrypoint: Some("jq".to_string()),
             ..Default::default()
         };
-        let cmd = build_docker_command(&config, &pkg, &[], &[], "", &[], true, "docker");
-        assert!(!cmd.iter().any(|a| a.starts_with("--entrypoint")),
-            "interactive mode should not emit --entrypoint: {:?}", cmd);
-        assert!(cmd.contains(&"bash".to_string()));
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { interactive: true, engine_path: "docker", ..Default::default() });
+        assert!(!cmd.contains(&"--entrypoint=jq".to_string()),
+            "interactive mode should not emit the manifest entrypoint: {:?}", cmd);
+        assert!(cmd.contains(&"--entrypoint=/bin/sh".to_string()));
+    }
+
+    #[test]
+    fn test_interactive_shell_in_container() {
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand {
+            command: "tool".to_string(),
+            docker_image: "busybox-based:1".to_string(),
+            shell_in_container: Some("/bin/busybox sh".to_string()),
+            ..Default::default()
+        };
+        let cmd = build_docker_command(&config, &pkg, &CommandOptions { args: &["-x".to_string()], interactive: true, engine_path: "docker", ..Default::default() });
+        let image_idx = cmd.iter().position(|a| a == "busybox-based:1").unwrap();
+        assert!(cmd.contains(&"--entrypoint=/bin/busybox".to_string()));
+        assert_eq!(&cmd[image_idx + 1..], &["sh", "-x"]);
+
+        let cmd = build_apptainer_command(&config, &pkg, &CommandOptions { interactive: true, engine_path: "apptainer", ..Default::default() });
+        let sif_idx = cmd.iter().position(|a| a.ends_with(".sif")).unwrap();
+        assert_eq!(&cmd[sif_idx + 1..], &["/bin/busybox", "sh"]);
+
+        let none = PackageCommand { shell_in_container: Some("none".to_string()), ..pkg };
+        assert!(none.container_shell().is_none());
     }
 
     #[test]
@@ -1587,10 +3034,68 @@ mod tests {
             &pkg.docker_image,
             config.bulker.apptainer_image_folder.as_deref(),
         );
+        std::fs::create_dir_all(Path::new(&fullpath).parent().unwrap()).unwrap();
         std::fs::write(&fullpath, "fake sif").unwrap();
 
         let result = ensure_apptainer_image(&config, &pkg, "apptainer");
         assert!(result.is_ok());
     }
 
+
+    #[test]
+    fn test_exec_cwd_mount() {
+        let cwd = Path::new("/data/run/sub");
+        assert_eq!(exec_cwd_mount("/data/run", cwd, &[]).as_deref(), Some("/data/run"));
+        assert_eq!(exec_cwd_mount("/data/run", cwd, &["/data:ro".to_string()]), None);
+        assert_eq!(exec_cwd_mount("/data/run", cwd, &["/data/run".to_string()]), None);
+        assert_eq!(exec_cwd_mount("/data/run", cwd, &["/data/run/sub".to_string()]).as_deref(), Some("/data/run"));
+        // A script that left the directory gets no extra mount
+        assert_eq!(exec_cwd_mount("/data/run", Path::new("/tmp"), &[]), None);
+        assert_eq!(exec_cwd_mount("/data/run", Path::new("/data/runs"), &[]), None);
+    }
+
+    #[test]
+    fn test_isolate_home_replaces_home_mount() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let home = tmpdir.path().join("home");
+        let _guard = crate::test_util::EnvGuard::set("HOME", &home);
+        let home = home.to_string_lossy().to_string();
+
+        let mut volumes = vec!["$HOME".to_string(), "/data".to_string(), format!("{}:ro", home)];
+        let mut envvars = vec!["DISPLAY".to_string(), "HOME".to_string()];
+        isolate_home(&mut volumes, &mut envvars, "/tmp/bulker_home_x", false);
+        assert_eq!(volumes, vec!["/data", "/tmp/bulker_home_x"]);
+        assert_eq!(envvars, vec!["DISPLAY", "HOME=/tmp/bulker_home_x"]);
+
+        // apptainer gets --home instead of a bind and HOME override
+        let mut volumes = vec!["$HOME".to_string()];
+        let mut envvars = vec![];
+        isolate_home(&mut volumes, &mut envvars, "/tmp/bulker_home_x", true);
+        assert!(volumes.is_empty());
+        assert!(envvars.is_empty());
+
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand { command: "tool".to_string(), docker_image: "org/tool:1".to_string(), ..Default::default() };
+        let opts = CommandOptions { home: Some("/tmp/bulker_home_x"), ..CommandOptions::new("apptainer") };
+        let cmd = build_apptainer_command(&config, &pkg, &opts);
+        let at = cmd.iter().position(|a| a == "--home").unwrap();
+        assert_eq!(cmd[at + 1], "/tmp/bulker_home_x:/tmp/bulker_home_x");
+        assert!(at < cmd.iter().position(|a| a.ends_with(".sif")).unwrap());
+    }
+
+    #[test]
+    fn test_expand_template_vars() {
+        let lookup = |name: &str| match name {
+            "sample" => Some("NA12878".to_string()),
+            "run_2" => Some("r2".to_string()),
+            _ => None,
+        };
+        assert_eq!(expand_template_vars("/out/{sample}/{run_2}", lookup).unwrap(), "/out/NA12878/r2");
+        // Env references, empty braces and non-identifiers are left alone
+        assert_eq!(expand_template_vars("${HOME}/{sample}:ro", lookup).unwrap(), "${HOME}/NA12878:ro");
+        assert_eq!(expand_template_vars("/a/{}/{1x}/{a b}", lookup).unwrap(), "/a/{}/{1x}/{a b}");
+        assert_eq!(expand_template_vars("/plain", lookup).unwrap(), "/plain");
+        let err = expand_template_vars("/out/{lane}", lookup).unwrap_err().to_string();
+        assert!(err.contains("--var lane=") && err.contains("BULKER_VAR_lane"), "{}", err);
+    }
 }
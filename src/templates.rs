@@ -21,36 +21,36 @@ pub const BASH_RC: &str = include_str!("../templates/start.sh");
 pub const BASH_RC_STRICT: &str = include_str!("../templates/start_strict.sh");
 pub const ZSH_RC: &str = include_str!("../templates/zsh_start/.zshrc");
 pub const ZSH_RC_STRICT: &str = include_str!("../templates/zsh_start_strict/.zshrc");
+
+/// Every embedded template, by its path relative to the templates dir.
+pub const TEMPLATE_FILES: &[(&str, &str)] = &[
+    ("docker_executable.tera", DOCKER_EXE_TEMPLATE),
+    ("docker_shell.tera", DOCKER_SHELL_TEMPLATE),
+    ("docker_build.tera", DOCKER_BUILD_TEMPLATE),
+    ("apptainer_executable.tera", APPTAINER_EXE_TEMPLATE),
+    ("apptainer_shell.tera", APPTAINER_SHELL_TEMPLATE),
+    ("apptainer_build.tera", APPTAINER_BUILD_TEMPLATE),
+    ("start.sh", BASH_RC),
+    ("start_strict.sh", BASH_RC_STRICT),
+    // Zsh rcfiles need subdirectories
+    ("zsh_start/.zshrc", ZSH_RC),
+    ("zsh_start_strict/.zshrc", ZSH_RC_STRICT),
+];
+
 /// Write all embedded templates to a directory on disk (for rcfile references).
 pub fn write_templates_to_dir(dir: &Path) -> Result<()> {
-    std::fs::create_dir_all(dir)
+    crate::cache_permissions::create_dir_all(dir)
         .with_context(|| format!("Failed to create templates dir: {}", dir.display()))?;
 
-    let files = [
-        ("docker_executable.tera", DOCKER_EXE_TEMPLATE),
-        ("docker_shell.tera", DOCKER_SHELL_TEMPLATE),
-        ("docker_build.tera", DOCKER_BUILD_TEMPLATE),
-        ("apptainer_executable.tera", APPTAINER_EXE_TEMPLATE),
-        ("apptainer_shell.tera", APPTAINER_SHELL_TEMPLATE),
-        ("apptainer_build.tera", APPTAINER_BUILD_TEMPLATE),
-        ("start.sh", BASH_RC),
-        ("start_strict.sh", BASH_RC_STRICT),
-    ];
-
-    for (name, content) in &files {
-        std::fs::write(dir.join(name), content)
+    for (name, content) in TEMPLATE_FILES {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent().filter(|p| *p != dir) {
+            crate::cache_permissions::create_dir_all(parent)?;
+        }
+        crate::cache_permissions::write(&path, content)
             .with_context(|| format!("Failed to write template: {}", name))?;
     }
 
-    // Zsh rcfiles need subdirectories
-    let zsh_dir = dir.join("zsh_start");
-    std::fs::create_dir_all(&zsh_dir)?;
-    std::fs::write(zsh_dir.join(".zshrc"), ZSH_RC)?;
-
-    let zsh_strict_dir = dir.join("zsh_start_strict");
-    std::fs::create_dir_all(&zsh_strict_dir)?;
-    std::fs::write(zsh_strict_dir.join(".zshrc"), ZSH_RC_STRICT)?;
-
     Ok(())
 }
 
@@ -97,6 +97,25 @@ fn build_context(
     ctx.insert("command", &pkg.command);
     ctx.insert("no_user", &pkg.no_user);
     ctx.insert("no_network", &pkg.no_network);
+    ctx.insert("no_init", &(pkg.no_init || config.bulker.no_init));
+    ctx.insert("ports", &pkg.ports);
+    ctx.insert("platform", &pkg.platform.as_deref().unwrap_or(""));
+    ctx.insert("platform_arch", &pkg.platform_arch().unwrap_or(""));
+    ctx.insert("pull_policy", &pkg.pull_policy().map(|p| p.name()).unwrap_or(""));
+
+    // Interactive shell (`_command` wrappers)
+    let shell: Vec<String> = pkg
+        .container_shell()
+        .unwrap_or_default()
+        .iter()
+        .map(|a| shell_escape::escape(a.into()).to_string())
+        .collect();
+    ctx.insert("shell_entrypoint", &shell.first().map(String::as_str).unwrap_or("/bin/sh"));
+    ctx.insert("shell_args", &shell.get(1..).unwrap_or_default().join(" "));
+    // `apptainer shell --shell` takes no arguments, so an explicit shell is
+    // run with `apptainer exec` as a full (escaped) command line instead.
+    let explicit_shell = matches!(pkg.shell_in_container.as_deref(), Some(s) if !s.is_empty() && s != "auto" && s != "none");
+    ctx.insert("apptainer_shell", &if explicit_shell { shell.join(" ") } else { String::new() });
 
     // Docker-specific
     ctx.insert("docker_command", &pkg.docker_command.as_deref().unwrap_or(""));
@@ -175,3 +194,37 @@ pub fn get_build_template(config: &BulkerConfig) -> &'static str {
         DOCKER_BUILD_TEMPLATE
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_templates_to_dir_writes_every_template() {
+        let dir = tempfile::tempdir().unwrap();
+        write_templates_to_dir(dir.path()).unwrap();
+        for (name, content) in TEMPLATE_FILES {
+            assert_eq!(std::fs::read_to_string(dir.path().join(name)).unwrap(), *content, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_shell_templates_render_the_whole_shell_command() {
+        let config = BulkerConfig::test_default();
+        let pkg = PackageCommand {
+            command: "tool".to_string(),
+            docker_image: "img:1".to_string(),
+            shell_in_container: Some("bash -l".to_string()),
+            ..Default::default()
+        };
+        let docker = render_template(DOCKER_SHELL_TEMPLATE, "shell", &config, &pkg, "").unwrap();
+        assert!(docker.contains("--entrypoint=bash \\\n  img:1 -l"), "{}", docker);
+
+        let ctx = build_context(&config, &pkg, "");
+        assert_eq!(ctx.get("apptainer_shell").unwrap(), "bash -l");
+
+        let auto = PackageCommand { shell_in_container: None, ..pkg };
+        let ctx = build_context(&config, &auto, "");
+        assert_eq!(ctx.get("apptainer_shell").unwrap(), "");
+    }
+}
@@ -0,0 +1,122 @@
+//! Lightweight timing spans for the dispatch path.
+//!
+//! Spans are recorded into a process-wide list and logged at debug level as
+//! they finish. `--timings` on `activate`/`exec` (or `BULKER_TIMINGS=1` for a
+//! shimlink dispatch) prints the aggregated report to stderr.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Environment variable that makes shimlink dispatches print their timings.
+pub const TIMINGS_ENV: &str = "BULKER_TIMINGS";
+
+static SPANS: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Record a finished span.
+pub fn record(name: &'static str, elapsed: Duration) {
+    log::debug!("timing: {} {:.2}ms", name, elapsed.as_secs_f64() * 1000.0);
+    if let Ok(mut spans) = SPANS.lock() {
+        spans.push((name, elapsed));
+    }
+}
+
+/// A span that records its duration when dropped.
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        record(self.name, self.start.elapsed());
+    }
+}
+
+/// Start a span that ends when the returned guard goes out of scope.
+pub fn span(name: &'static str) -> Span {
+    Span { name, start: Instant::now() }
+}
+
+/// Sequential phases: each `end` records the time since the previous one.
+pub struct Phases {
+    start: Instant,
+}
+
+impl Phases {
+    pub fn start() -> Self {
+        Phases { start: Instant::now() }
+    }
+
+    pub fn end(&mut self, name: &'static str) {
+        record(name, self.start.elapsed());
+        self.start = Instant::now();
+    }
+}
+
+/// Recorded spans aggregated by name, in first-seen order: (name, total, count).
+pub fn summary() -> Vec<(&'static str, Duration, usize)> {
+    let spans = match SPANS.lock() {
+        Ok(spans) => spans.clone(),
+        Err(_) => return Vec::new(),
+    };
+    aggregate(&spans)
+}
+
+fn aggregate(spans: &[(&'static str, Duration)]) -> Vec<(&'static str, Duration, usize)> {
+    let mut out: Vec<(&'static str, Duration, usize)> = Vec::new();
+    for (name, elapsed) in spans {
+        match out.iter_mut().find(|(n, _, _)| n == name) {
+            Some(entry) => {
+                entry.1 += *elapsed;
+                entry.2 += 1;
+            }
+            None => out.push((name, *elapsed, 1)),
+        }
+    }
+    out
+}
+
+fn format_report(summary: &[(&'static str, Duration, usize)]) -> String {
+    let width = summary.iter().map(|(n, _, _)| n.len()).max().unwrap_or(0);
+    let mut out = String::from("bulker timings:\n");
+    for (name, total, count) in summary {
+        out.push_str(&format!("  {:<w$}  {:>11}", name, crate::humanize::format_duration(*total), w = width));
+        if *count > 1 {
+            out.push_str(&format!("  ({}x)", count));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Print the aggregated timing report to stderr.
+pub fn print_report() {
+    eprint!("{}", format_report(&summary()));
+}
+
+/// Whether `BULKER_TIMINGS` asks for a report.
+pub fn enabled_by_env() -> bool {
+    std::env::var(TIMINGS_ENV).map(|v| !v.is_empty() && v != "0").unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_and_format() {
+        let spans = vec![
+            ("config_load", Duration::from_millis(2)),
+            ("cache_read", Duration::from_millis(1)),
+            ("cache_read", Duration::from_millis(3)),
+        ];
+        let summary = aggregate(&spans);
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].0, "config_load");
+        assert_eq!(summary[1], ("cache_read", Duration::from_millis(4), 2));
+
+        let report = format_report(&summary);
+        assert!(report.contains("config_load       2.00ms"));
+        assert!(report.contains("cache_read        4.00ms  (2x)"));
+    }
+}
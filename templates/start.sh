@@ -1,3 +1,12 @@
 source "${BULKERSHELLRC}"
 if [ -z ${BULKERPROMPT+x} ]; then echo "No prompt change"; else PS1="${BULKERPROMPT}"; fi
 export PATH="${BULKERPATH}:${PATH}"
+
+# Warn once if a hook re-prepends entries to PATH, burying the crate commands
+_bulker_check_path() {
+  case "$PATH" in
+    "${BULKER_SHIMDIR}"|"${BULKER_SHIMDIR}":*) ;;
+    *) if [ -z "${_BULKER_PATH_WARNED}" ]; then _BULKER_PATH_WARNED=1; command bulker doctor --active >&2; fi ;;
+  esac
+}
+PROMPT_COMMAND="${PROMPT_COMMAND:+${PROMPT_COMMAND};}_bulker_check_path"
@@ -0,0 +1,84 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Global mutex to serialize all tests that modify environment variables.
+/// Since `std::env::set_var` affects the entire process, concurrent tests
+/// that modify the same env vars (e.g. XDG_CONFIG_HOME) will race.
+/// This mutex ensures only one such test runs at a time.
+static ENV_MUTEX: Mutex<()> = Mutex::new(());
+
+/// RAII guard that restores an environment variable on drop.
+/// Handles both "was set to X" and "was not set" cases.
+/// Holds a global mutex lock to prevent concurrent env var modifications.
+pub struct EnvGuard {
+    /// Variables to restore, with their original values, in the order set.
+    saved: Vec<(String, Option<String>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl EnvGuard {
+    /// Save the current value of `key`, set it to `value`, and hold a global
+    /// lock until this guard is dropped. This serializes all tests that modify
+    /// environment variables.
+    pub fn set(key: &str, value: impl AsRef<std::ffi::OsStr>) -> Self {
+        let lock = ENV_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let original = std::env::var(key).ok();
+        // SAFETY: We hold ENV_MUTEX so no other EnvGuard-using test is running.
+        unsafe {
+            std::env::set_var(key, value);
+        }
+        EnvGuard {
+            saved: vec![(key.to_string(), original)],
+            _lock: lock,
+        }
+    }
+
+    /// Also set `key` under the same lock (a second guard would deadlock).
+    /// Restored together with the first variable.
+    pub fn and_set(mut self, key: &str, value: impl AsRef<std::ffi::OsStr>) -> Self {
+        self.saved.push((key.to_string(), std::env::var(key).ok()));
+        // SAFETY: We hold ENV_MUTEX so no other EnvGuard-using test is running.
+        unsafe {
+            std::env::set_var(key, value);
+        }
+        self
+    }
+
+    /// Save the current value of `key`, remove it from the environment, and hold
+    /// a global lock until this guard is dropped. Restores the original value on drop.
+    pub fn remove(key: &str) -> Self {
+        let lock = ENV_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let original = std::env::var(key).ok();
+        // SAFETY: We hold ENV_MUTEX so no other EnvGuard-using test is running.
+        unsafe {
+            std::env::remove_var(key);
+        }
+        EnvGuard {
+            saved: vec![(key.to_string(), original)],
+            _lock: lock,
+        }
+    }
+
+    /// Also remove `key` under the same lock. Restored together with the
+    /// first variable.
+    pub fn and_remove(mut self, key: &str) -> Self {
+        self.saved.push((key.to_string(), std::env::var(key).ok()));
+        // SAFETY: We hold ENV_MUTEX so no other EnvGuard-using test is running.
+        unsafe {
+            std::env::remove_var(key);
+        }
+        self
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (key, original) in self.saved.iter().rev() {
+            unsafe {
+                match original {
+                    Some(v) => std::env::set_var(key, v),
+                    None => std::env::remove_var(key),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::path::Path;
+
+/// The crate bulker's integration tests install: `cowsay` and `fortune`
+/// images plus `ls` as a host command. Install it as `bulker/test-crate:1.0.0`.
+pub const TEST_CRATE_MANIFEST: &str = r#"manifest:
+  name: test-crate
+  version: 1.0.0
+  commands:
+  - command: cowsay
+    docker_image: nsheff/cowsay
+    docker_command: cowsay
+    docker_args: "-i"
+  - command: fortune
+    docker_image: nsheff/fortune
+    docker_command: fortune
+  host_commands:
+  - ls
+"#;
+
+/// Builder for crate manifest YAML.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestFixture {
+    name: String,
+    version: Option<String>,
+    commands: Vec<(String, String)>,
+    host_commands: Vec<String>,
+    imports: Vec<String>,
+}
+
+impl ManifestFixture {
+    pub fn new(name: &str) -> Self {
+        ManifestFixture { name: name.to_string(), ..Default::default() }
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Add a command run in `image`.
+    pub fn command(mut self, command: &str, image: &str) -> Self {
+        self.commands.push((command.to_string(), image.to_string()));
+        self
+    }
+
+    pub fn host_command(mut self, command: &str) -> Self {
+        self.host_commands.push(command.to_string());
+        self
+    }
+
+    /// Import another crate by registry path (e.g. `bulker/base:1.0`).
+    pub fn import(mut self, registry_path: &str) -> Self {
+        self.imports.push(registry_path.to_string());
+        self
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut yaml = format!("manifest:\n  name: {}\n", quote(&self.name));
+        if let Some(ref version) = self.version {
+            yaml.push_str(&format!("  version: {}\n", quote(version)));
+        }
+        push_list(&mut yaml, "imports", &self.imports);
+        yaml.push_str("  commands:\n");
+        for (command, image) in &self.commands {
+            yaml.push_str(&format!("  - command: {}\n    docker_image: {}\n", quote(command), quote(image)));
+        }
+        push_list(&mut yaml, "host_commands", &self.host_commands);
+        yaml
+    }
+}
+
+/// Builder for a bulker config file.
+#[derive(Debug, Clone)]
+pub struct ConfigFixture {
+    engine: String,
+    engine_path: Option<String>,
+    registry_url: Option<String>,
+    registry_urls: Vec<String>,
+    volumes: Vec<String>,
+    envvars: Vec<String>,
+}
+
+impl Default for ConfigFixture {
+    fn default() -> Self {
+        ConfigFixture {
+            engine: "docker".to_string(),
+            engine_path: None,
+            registry_url: None,
+            registry_urls: Vec::new(),
+            volumes: Vec::new(),
+            envvars: Vec::new(),
+        }
+    }
+}
+
+impl ConfigFixture {
+    /// A docker config with no volumes or forwarded variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Container engine name (`docker`, `podman` or `apptainer`).
+    pub fn engine(mut self, engine: &str) -> Self {
+        self.engine = engine.to_string();
+        self
+    }
+
+    /// Run this executable instead of the engine, e.g. [`TestEnv::fake_engine`](crate::TestEnv::fake_engine).
+    pub fn engine_path(mut self, path: &Path) -> Self {
+        self.engine_path = Some(path.display().to_string());
+        self
+    }
+
+    /// Fetch manifests from this registry, e.g. [`FakeRegistry::url`](crate::FakeRegistry::url).
+    pub fn registry_url(mut self, url: &str) -> Self {
+        self.registry_url = Some(url.to_string());
+        self
+    }
+
+    /// Fetch manifests from these registries, tried in order.
+    pub fn registry_urls(mut self, urls: &[&str]) -> Self {
+        self.registry_urls = urls.iter().map(|u| u.to_string()).collect();
+        self
+    }
+
+    pub fn volume(mut self, volume: &str) -> Self {
+        self.volumes.push(volume.to_string());
+        self
+    }
+
+    pub fn envvar(mut self, name: &str) -> Self {
+        self.envvars.push(name.to_string());
+        self
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut yaml = format!("bulker:\n  container_engine: {}\n", quote(&self.engine));
+        if let Some(ref path) = self.engine_path {
+            yaml.push_str(&format!("  engine_path: {}\n", quote(path)));
+        }
+        if let Some(ref url) = self.registry_url {
+            yaml.push_str(&format!("  registry_url: {}\n", quote(url)));
+        }
+        push_list(&mut yaml, "registry_urls", &self.registry_urls);
+        // Always written, so bulker's default volumes and variables stay out
+        yaml.push_str(&format!("  volumes: [{}]\n", self.volumes.iter().map(|v| quote(v)).collect::<Vec<_>>().join(", ")));
+        yaml.push_str(&format!("  envvars: [{}]\n", self.envvars.iter().map(|v| quote(v)).collect::<Vec<_>>().join(", ")));
+        yaml
+    }
+}
+
+/// Double-quote a YAML scalar.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn push_list(yaml: &mut String, key: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    yaml.push_str(&format!("  {}:\n", key));
+    for item in items {
+        yaml.push_str(&format!("  - {}\n", quote(item)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_fixture_yaml() {
+        let yaml = ManifestFixture::new("demo")
+            .version("1.0")
+            .command("samtools", "quay.io/biocontainers/samtools:1.17")
+            .import("bulker/base")
+            .host_command("ls")
+            .to_yaml();
+        assert_eq!(
+            yaml,
+            "manifest:\n  name: \"demo\"\n  version: \"1.0\"\n  imports:\n  - \"bulker/base\"\n  commands:\n  \
+             - command: \"samtools\"\n    docker_image: \"quay.io/biocontainers/samtools:1.17\"\n  host_commands:\n  - \"ls\"\n"
+        );
+    }
+
+    #[test]
+    fn test_config_fixture_yaml() {
+        let yaml = ConfigFixture::new().engine_path(Path::new("/tmp/fake docker")).volume("/data").to_yaml();
+        assert!(yaml.contains("  engine_path: \"/tmp/fake docker\"\n"), "{}", yaml);
+        assert!(yaml.contains("  volumes: [\"/data\"]\n"), "{}", yaml);
+        assert!(yaml.contains("  envvars: []\n"), "{}", yaml);
+    }
+}
@@ -0,0 +1,34 @@
+//! Test helpers for bulker and for tools that drive it.
+//!
+//! These are the helpers bulker's own tests use: an environment-variable
+//! guard, a sandbox with its own config and cache directories, a fake
+//! container engine, a fake (writable) manifest registry, and manifest fixtures.
+//!
+//! ```no_run
+//! use bulker_test_utils::{ConfigFixture, FakeRegistry, ManifestFixture, TestEnv};
+//!
+//! let registry = FakeRegistry::start();
+//! registry.add_manifest(
+//!     "bulker/demo:1.0",
+//!     &ManifestFixture::new("demo").command("cowsay", "nsheff/cowsay").to_yaml(),
+//! );
+//! let env = TestEnv::new();
+//! let engine = env.fake_engine("echo \"$@\"");
+//! env.write_config(&ConfigFixture::new().engine_path(&engine).registry_url(&registry.url()));
+//! let output = env
+//!     .command("bulker")
+//!     .args(["exec", "bulker/demo:1.0", "--", "cowsay", "hi"])
+//!     .output()
+//!     .unwrap();
+//! assert!(output.status.success());
+//! ```
+
+mod env;
+mod fixtures;
+mod registry;
+mod sandbox;
+
+pub use env::EnvGuard;
+pub use fixtures::{ConfigFixture, ManifestFixture, TEST_CRATE_MANIFEST};
+pub use registry::{FakeRegistry, Upload};
+pub use sandbox::TestEnv;
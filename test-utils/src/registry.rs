@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// A manifest registry on a local port, serving manifests the test adds.
+/// Point bulker at it with `registry_url` (see [`ConfigFixture::registry_url`](crate::ConfigFixture::registry_url)).
+/// Unknown paths get a 404. It is writable: a POST is recorded (see
+/// [`FakeRegistry::uploads`]) and its body served at that path from then on.
+/// The server stops when this is dropped.
+pub struct FakeRegistry {
+    addr: std::net::SocketAddr,
+    files: Arc<Mutex<HashMap<String, String>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+    uploads: Arc<Mutex<Vec<Upload>>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// A POST a [`FakeRegistry`] accepted.
+#[derive(Debug, Clone)]
+pub struct Upload {
+    pub path: String,
+    /// Request headers, names lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl FakeRegistry {
+    /// Start serving on an unused port of 127.0.0.1.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind fake registry");
+        let addr = listener.local_addr().expect("fake registry has no address");
+        let files: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+        let requests: Arc<Mutex<Vec<String>>> = Arc::default();
+        let uploads: Arc<Mutex<Vec<Upload>>> = Arc::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (files, requests, uploads, stop) = (files.clone(), requests.clone(), uploads.clone(), stop.clone());
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        serve(stream, &files, &requests, &uploads);
+                    }
+                }
+            })
+        };
+        FakeRegistry { addr, files, requests, uploads, stop, handle: Some(handle) }
+    }
+
+    /// Base URL to use as bulker's `registry_url`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// Serve `yaml` as the manifest of `registry_path` (`namespace/crate:tag`;
+    /// the namespace defaults to `bulker` and the tag to `default`), at the
+    /// URL bulker builds for it.
+    pub fn add_manifest(&self, registry_path: &str, yaml: &str) {
+        self.add_file(&manifest_url_path(registry_path), yaml);
+    }
+
+    /// Serve `body` at `path` (e.g. `/bulker/demo_1.0.yaml`).
+    pub fn add_file(&self, path: &str, body: &str) {
+        self.files.lock().unwrap().insert(path.to_string(), body.to_string());
+    }
+
+    /// Paths requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// POSTs received so far, in order.
+    pub fn uploads(&self) -> Vec<Upload> {
+        self.uploads.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FakeRegistry {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the stop flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// URL path bulker requests for a registry path.
+fn manifest_url_path(registry_path: &str) -> String {
+    let (namespace, rest) = registry_path.split_once('/').unwrap_or(("bulker", registry_path));
+    match rest.split_once(':') {
+        Some((name, tag)) if tag != "default" => format!("/{}/{}_{}.yaml", namespace, name, tag),
+        Some((name, _)) => format!("/{}/{}.yaml", namespace, name),
+        None => format!("/{}/{}.yaml", namespace, rest),
+    }
+}
+
+fn serve(stream: TcpStream, files: &Mutex<HashMap<String, String>>, requests: &Mutex<Vec<String>>, uploads: &Mutex<Vec<Upload>>) {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut headers = HashMap::new();
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+        line.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("GET").to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+    requests.lock().unwrap().push(path.clone());
+    if method == "POST" {
+        let length = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        let body = String::from_utf8_lossy(&body).into_owned();
+        files.lock().unwrap().insert(path.clone(), body.clone());
+        uploads.lock().unwrap().push(Upload { path, headers, body });
+        let _ = (&stream).write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        return;
+    }
+    let response = match files.lock().unwrap().get(&path) {
+        Some(body) => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body),
+        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    let _ = (&stream).write_all(response.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(url: &str) -> String {
+        let rest = url.strip_prefix("http://").unwrap();
+        let (host, path) = rest.split_once('/').unwrap();
+        let mut stream = TcpStream::connect(host).unwrap();
+        write!(stream, "GET /{} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host).unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_manifest_url_path() {
+        assert_eq!(manifest_url_path("bulker/demo:1.0"), "/bulker/demo_1.0.yaml");
+        assert_eq!(manifest_url_path("databio/pepatac"), "/databio/pepatac.yaml");
+        assert_eq!(manifest_url_path("demo:default"), "/bulker/demo.yaml");
+    }
+
+    #[test]
+    fn test_fake_registry_serves_manifests() {
+        let registry = FakeRegistry::start();
+        registry.add_manifest("bulker/demo:1.0", "manifest:\n  name: demo\n");
+        let ok = get(&format!("{}bulker/demo_1.0.yaml", registry.url()));
+        assert!(ok.starts_with("HTTP/1.1 200 OK"), "{}", ok);
+        assert!(ok.ends_with("manifest:\n  name: demo\n"), "{}", ok);
+        let missing = get(&format!("{}bulker/other.yaml", registry.url()));
+        assert!(missing.starts_with("HTTP/1.1 404"), "{}", missing);
+        assert_eq!(registry.requests(), vec!["/bulker/demo_1.0.yaml", "/bulker/other.yaml"]);
+    }
+
+    #[test]
+    fn test_fake_registry_accepts_uploads() {
+        let registry = FakeRegistry::start();
+        let mut stream = TcpStream::connect(registry.addr).unwrap();
+        let body = "manifest:\n  name: demo\n";
+        write!(stream, "POST /bulker/demo_1.0.yaml HTTP/1.1\r\nAuthorization: Bearer t\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
+
+        let uploads = registry.uploads();
+        assert_eq!(uploads[0].path, "/bulker/demo_1.0.yaml");
+        assert_eq!(uploads[0].headers["authorization"], "Bearer t");
+        assert_eq!(uploads[0].body, body);
+        assert!(get(&format!("{}bulker/demo_1.0.yaml", registry.url())).ends_with(body));
+    }
+}
@@ -0,0 +1,80 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::ConfigFixture;
+
+/// A temporary directory that serves as bulker's config and cache home.
+/// Commands built with [`TestEnv::command`] never touch the user's real
+/// config, manifest cache or shimdirs.
+pub struct TestEnv {
+    dir: tempfile::TempDir,
+}
+
+impl Default for TestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        TestEnv { dir: tempfile::tempdir().expect("failed to create test directory") }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Where [`TestEnv::write_config`] writes the config; passed to bulker as `$BULKERCFG`.
+    pub fn config_path(&self) -> PathBuf {
+        self.path().join("bulker_config.yaml")
+    }
+
+    pub fn write_config(&self, config: &ConfigFixture) -> PathBuf {
+        let path = self.config_path();
+        std::fs::write(&path, config.to_yaml()).expect("failed to write test config");
+        path
+    }
+
+    /// Write an executable `fake-docker` shell script whose body is `script`,
+    /// for use as `engine_path`. It gets the engine arguments as `"$@"`.
+    pub fn fake_engine(&self, script: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = self.path().join("fake-docker");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).expect("failed to write fake engine");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("failed to chmod fake engine");
+        path
+    }
+
+    /// A command for `program` (usually the bulker binary) with
+    /// `XDG_CONFIG_HOME` and `XDG_CACHE_HOME` in the sandbox, the engine
+    /// health check off, and `$BULKERCFG` set once a config has been written.
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut cmd = Command::new(program);
+        cmd.env("XDG_CONFIG_HOME", self.path());
+        cmd.env("XDG_CACHE_HOME", self.path());
+        cmd.env("BULKER_SKIP_ENGINE_CHECK", "1");
+        if self.config_path().exists() {
+            cmd.env("BULKERCFG", self.config_path());
+        }
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_engine_runs_script() {
+        let env = TestEnv::new();
+        let engine = env.fake_engine("echo engine \"$@\"");
+        let output = env.command(&engine).args(["run", "alpine"]).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "engine run alpine\n");
+
+        let config = env.write_config(&ConfigFixture::new().engine_path(&engine));
+        let output = env.command("sh").args(["-c", "echo $BULKERCFG"]).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), config.to_str().unwrap());
+    }
+}
@@ -22,6 +22,7 @@ FAILURES=""
 setup() {
     TMPDIR="$(mktemp -d)"
     export XDG_CONFIG_HOME="$TMPDIR/xdg"
+    export XDG_CACHE_HOME="$TMPDIR/xdg-cache"
     CONFIG="$TMPDIR/bulker_config.yaml"
 
     "$BULKERS" config init -c "$CONFIG" >/dev/null 2>&1
@@ -33,12 +34,13 @@ setup() {
 setup_no_load() {
     TMPDIR="$(mktemp -d)"
     export XDG_CONFIG_HOME="$TMPDIR/xdg"
+    export XDG_CACHE_HOME="$TMPDIR/xdg-cache"
     CONFIG="$TMPDIR/bulker_config.yaml"
     "$BULKERS" config init -c "$CONFIG" >/dev/null 2>&1
 }
 
 teardown() {
-    unset XDG_CONFIG_HOME 2>/dev/null || true
+    unset XDG_CONFIG_HOME XDG_CACHE_HOME 2>/dev/null || true
     if [ -n "${TMPDIR:-}" ] && [ -d "$TMPDIR" ]; then
         rm -rf "$TMPDIR"
     fi
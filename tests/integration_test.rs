@@ -3,6 +3,8 @@ use std::path::PathBuf;
 use std::process::Command;
 use tempfile::TempDir;
 
+use bulker_test_utils::{ConfigFixture, FakeRegistry, ManifestFixture, TEST_CRATE_MANIFEST, TestEnv};
+
 fn bulker_bin() -> PathBuf {
     // Find the built binary
     let mut path = std::env::current_exe().unwrap();
@@ -13,29 +15,18 @@ fn bulker_bin() -> PathBuf {
 }
 
 fn create_test_manifest(dir: &std::path::Path) -> PathBuf {
-    let manifest = r#"manifest:
-  name: test-crate
-  version: 1.0.0
-  commands:
-  - command: cowsay
-    docker_image: nsheff/cowsay
-    docker_command: cowsay
-    docker_args: "-i"
-  - command: fortune
-    docker_image: nsheff/fortune
-    docker_command: fortune
-  host_commands:
-  - ls
-"#;
     let path = dir.join("test_manifest.yaml");
-    fs::write(&path, manifest).unwrap();
+    fs::write(&path, TEST_CRATE_MANIFEST).unwrap();
     path
 }
 
-/// Helper: run bulker with XDG_CONFIG_HOME set to isolate manifest cache.
+/// Helper: run bulker with XDG_CONFIG_HOME and XDG_CACHE_HOME set to isolate config and manifest cache.
 fn bulker_cmd(xdg_home: &std::path::Path) -> Command {
     let mut cmd = Command::new(bulker_bin());
     cmd.env("XDG_CONFIG_HOME", xdg_home);
+    cmd.env("XDG_CACHE_HOME", xdg_home);
+    // No engine daemon in the test sandbox
+    cmd.env("BULKER_SKIP_ENGINE_CHECK", "1");
     cmd
 }
 
@@ -176,6 +167,38 @@ fn test_activate_echo_mode() {
     assert!(stdout.contains("bulker_"), "PATH doesn't contain shimlink dir: {}", stdout);
 }
 
+#[test]
+fn test_activate_echo_for_nu_and_xonsh() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let activate = |extra: &[&str]| {
+        bulker_cmd(tmp.path())
+            .env_remove("BULKERCRATE")
+            .args(["activate", "-c", config_path.to_str().unwrap()])
+            .args(extra)
+            .arg("bulker/test-crate:1.0.0")
+            .output()
+            .unwrap()
+    };
+
+    let output = activate(&["--echo", "--shell", "nu"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.starts_with("{\n  BULKERCRATE: \"bulker/test-crate:1.0.0\",\n"), "{}", stdout);
+    assert!(stdout.contains("  PATH: [\""), "{}", stdout);
+    assert!(!stdout.contains("export "), "{}", stdout);
+
+    let output = activate(&["--echo", "--shell", "xonsh"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("$BULKERCRATE = \"bulker/test-crate:1.0.0\"\n"), "{}", stdout);
+    assert!(stdout.contains("$PATH.insert(0, \""), "{}", stdout);
+
+    // --shell only changes what --echo prints
+    assert!(!activate(&["--shell", "nu"]).status.success());
+}
+
 #[test]
 fn test_activate_local_manifest() {
     let tmp = TempDir::new().unwrap();
@@ -465,7 +488,7 @@ fn test_host_exec_passthrough() {
 }
 
 #[test]
-fn test_activate_double_activation_rejected() {
+fn test_activate_inside_activation_stacks_unless_written_elsewhere() {
     let tmp = TempDir::new().unwrap();
     let config_path = init_config(&tmp);
     install_test_crate(&tmp, &config_path);
@@ -473,6 +496,7 @@ fn test_activate_double_activation_rejected() {
     // Simulate an already-active crate by setting BULKERCRATE
     let output = bulker_cmd(tmp.path())
         .env("BULKERCRATE", "bulker/some-crate:1.0.0")
+        .env("BULKER_HOST_ENV", "1")
         .args([
             "activate",
             "-c", config_path.to_str().unwrap(),
@@ -481,8 +505,20 @@ fn test_activate_double_activation_rejected() {
         ])
         .output()
         .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("export BULKERCRATE=\"bulker/test-crate:1.0.0,bulker/some-crate:1.0.0\""), "{}", stdout);
+    assert!(stdout.contains("export BULKER_STACK="), "{}", stdout);
+    // The outer activation's settings don't leak into the inner one
+    assert!(stdout.lines().any(|l| l.starts_with("unset ") && l.contains("BULKER_HOST_ENV")), "{}", stdout);
 
-    assert!(!output.status.success(), "double activation should fail");
+    // A modulefile is not for this shell, so there is nothing to stack on
+    let output = bulker_cmd(tmp.path())
+        .env("BULKERCRATE", "bulker/some-crate:1.0.0")
+        .args(["activate", "-c", config_path.to_str().unwrap(), "--format", "modulefile", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "modulefile inside an activation should fail");
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(stderr.contains("already activated"), "should warn about existing activation: {}", stderr);
     assert!(stderr.contains("bulker/some-crate:1.0.0"), "should show active crate name: {}", stderr);
@@ -589,3 +625,2625 @@ fn test_activate_strict_echo_no_host_path() {
     let path_line = stdout.lines().find(|l| l.starts_with("export PATH=")).unwrap();
     assert!(!path_line.contains(":/"), "strict PATH should not contain original PATH segments: {}", path_line);
 }
+
+#[test]
+fn test_activate_strict_keeps_host_commands_of_crate_and_config() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "add", "-c", config_path.to_str().unwrap(), "host_commands", "cat"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", config_path.to_str().unwrap(), "--echo", "--strict", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let shimdir = stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("export PATH=\""))
+        .and_then(|l| l.strip_suffix('"'))
+        .unwrap()
+        .to_string();
+
+    // `ls` comes from the crate's host_commands, `cat` from the config
+    fs::write(tmp.path().join("note.txt"), "hello").unwrap();
+    let output = std::process::Command::new("/bin/sh")
+        .args(["-c", "ls \"$1\" && cat \"$1/note.txt\"", "sh", tmp.path().to_str().unwrap()])
+        .env("PATH", &shimdir)
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(listing.contains("note.txt") && listing.ends_with("hello"), "{}", listing);
+    assert!(!std::path::Path::new(&shimdir).join("echo").exists());
+
+    let output = bulker_cmd(tmp.path())
+        .args(["status", "--json", "-c", config_path.to_str().unwrap()])
+        .env("BULKERCRATE", "bulker/test-crate:1.0.0")
+        .env("BULKER_SHIMDIR", &shimdir)
+        .env("BULKERPATH", &shimdir)
+        .output()
+        .unwrap();
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["host_commands"], serde_json::json!(["ls", "cat"]));
+}
+
+#[test]
+fn test_exec_script_runs_in_crate_environment() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    // Not executable: should be run via /bin/sh, with trailing args passed through
+    let script = tmp.path().join("steps.sh");
+    fs::write(&script, "echo \"crate=$BULKERCRATE arg=$1\"\ncowsay hello\n").unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "exec",
+            "-c", config_path.to_str().unwrap(),
+            "-p",
+            "bulker/test-crate:1.0.0",
+            "--script", script.to_str().unwrap(),
+            "--",
+            "sample1",
+        ])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "exec --script failed: {}\n{}", stderr, stdout);
+    assert!(stdout.contains("crate=bulker/test-crate:1.0.0 arg=sample1"), "unexpected output: {}", stdout);
+    // cowsay resolved through the shimdir (print-command mode shows the container command)
+    assert!(stdout.contains("nsheff/cowsay"), "cowsay not dispatched via shim: {}", stdout);
+}
+
+#[test]
+fn test_exec_var_fills_workdir_and_volumes() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    let data = tmp.path().join("data");
+    fs::create_dir_all(data.join("NA12878")).unwrap();
+    let manifest = tmp.path().join("pipeline.yaml");
+    fs::write(&manifest, format!(
+        "manifest:\n  name: pipeline\n  commands:\n  - command: align\n    docker_image: biocontainers/bwa\n    \
+         workdir: /out/{{sample}}\n    volumes:\n    - {}/{{sample}}:ro\n",
+        data.display()
+    )).unwrap();
+
+    let exec = |vars: &[&str]| {
+        let mut cmd = bulker_cmd(tmp.path());
+        cmd.args(["exec", "-c", config_path.to_str().unwrap(), "-p"]);
+        for var in vars {
+            cmd.args(["--var", var]);
+        }
+        cmd.args([manifest.to_str().unwrap(), "--", "align", "in.fq"]).output().unwrap()
+    };
+
+    let output = exec(&["sample=NA12878"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("--workdir=/out/NA12878"), "{}", stdout);
+    assert!(stdout.contains(&format!("{}/NA12878", data.display())), "{}", stdout);
+
+    // A placeholder without a value fails the command instead of mounting a literal {sample}
+    let output = exec(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--var sample="));
+    let output = exec(&["1bad=x"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid --var name"));
+}
+
+#[test]
+fn test_ci_setup_writes_github_env_files() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let github_env = tmp.path().join("github_env");
+    let github_path = tmp.path().join("github_path");
+    let output = bulker_cmd(tmp.path())
+        .env("GITHUB_ENV", &github_env)
+        .env("GITHUB_PATH", &github_path)
+        .args(["ci", "setup", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "ci setup failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("::group::"), "output should be grouped: {}", stdout);
+
+    let env_contents = fs::read_to_string(&github_env).unwrap();
+    assert!(env_contents.contains("BULKERCRATE=bulker/test-crate:1.0.0\n"), "{}", env_contents);
+    let path_contents = fs::read_to_string(&github_path).unwrap();
+    let shimdir = path_contents.trim();
+    assert!(std::path::Path::new(shimdir).join("cowsay").exists(), "shimdir missing cowsay: {}", shimdir);
+    let _ = fs::remove_dir_all(shimdir);
+}
+
+#[test]
+fn test_ci_setup_reports_error_annotation() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+
+    let output = bulker_cmd(tmp.path())
+        .env("GITHUB_ENV", tmp.path().join("github_env"))
+        .env("GITHUB_PATH", tmp.path().join("github_path"))
+        .args(["ci", "setup", "-c", config_path.to_str().unwrap(), "./does-not-exist.yaml"])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("::error title=bulker ci setup::"), "missing error annotation: {}", stdout);
+}
+
+#[test]
+fn test_exec_record_writes_provenance_bundle() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    // Fake engine: every container command exits 3
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\nexit 3\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let bundle = tmp.path().join("bundle");
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "exec",
+            "-c", config_path.to_str().unwrap(),
+            "--record", bundle.to_str().unwrap(),
+            "bulker/test-crate:1.0.0",
+            "--",
+            "cowsay", "hello",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let invocation: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(bundle.join("invocation.json")).unwrap()).unwrap();
+    assert_eq!(invocation["exit_code"], 3);
+    assert_eq!(invocation["crates"][0], "bulker/test-crate:1.0.0");
+    assert_eq!(invocation["command"][0], "cowsay");
+
+    let commands = fs::read_to_string(bundle.join("commands.jsonl")).unwrap();
+    let first: serde_json::Value = serde_json::from_str(commands.lines().next().unwrap()).unwrap();
+    assert_eq!(first["command"], "cowsay");
+    assert_eq!(first["image"], "nsheff/cowsay");
+    assert_eq!(first["exit_code"], 3);
+    assert_eq!(first["exit_kind"], "tool");
+    assert_eq!(first["env_mode"], "allowlist");
+    assert_eq!(first["args"][0], "hello");
+}
+
+#[test]
+fn test_version_json_reports_build_metadata() {
+    let tmp = TempDir::new().unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["version", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "version --json failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["git_commit"].is_string());
+    assert_eq!(info["build_date"].as_str().unwrap().len(), 10);
+    assert!(info["target"].is_string());
+    assert!(info["features"].is_array());
+    assert!(info["engines"].as_object().unwrap().contains_key("docker"));
+}
+
+#[test]
+fn test_selftest_runs_embedded_crate_through_shimlink() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+
+    // Fake engine that echoes its arguments, so the token reaches stdout
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\necho \"$@\"\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["selftest", "-c", config_path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "selftest failed: {}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("All checks passed."), "{}", stdout);
+    // The selftest crate is removed afterwards
+    assert!(!tmp.path().join("bulker/manifests/bulker/selftest/embedded/manifest.yaml").exists());
+}
+
+#[test]
+fn test_crate_install_from_stdin_requires_name() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    let manifest = "manifest:\n  commands:\n  - command: cowsay\n    docker_image: nsheff/cowsay\n";
+
+    let run = |extra: &[&str]| {
+        let mut child = bulker_cmd(tmp.path())
+            .args(["crate", "install", "-c", config_path.to_str().unwrap()])
+            .args(extra)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(manifest.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("requires --name"));
+
+    let output = run(&["--name", "gen/tools:2.0"]);
+    assert!(output.status.success(), "stdin install failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Cached: gen/tools:2.0"));
+    assert!(tmp.path().join("bulker/manifests/gen/tools/2.0/manifest.yaml").exists());
+}
+
+#[test]
+fn test_exec_timings_reports_bulker_and_shimlink_spans() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "exec",
+            "-c", config_path.to_str().unwrap(),
+            "--timings",
+            "bulker/test-crate:1.0.0",
+            "--",
+            "cowsay", "hello",
+        ])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {}", stderr);
+    // One report from the shimlink dispatch, one from exec itself
+    assert_eq!(stderr.matches("bulker timings:").count(), 2, "stderr: {}", stderr);
+    for span in ["config_load", "shimdir_create", "import_resolution", "command_lookup", "engine_run", "command_run"] {
+        assert!(stderr.contains(span), "missing {} span: {}", span, stderr);
+    }
+}
+
+#[test]
+fn test_activate_write_rc_is_sourceable() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let rc = tmp.path().join("activate.sh");
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "activate",
+            "-c", config_path.to_str().unwrap(),
+            "--write-rc", rc.to_str().unwrap(),
+            "bulker/test-crate:1.0.0",
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout.trim(), format!("source {}", rc.display()));
+
+    // Sourcing the file in a plain shell puts the crate commands on PATH
+    let sourced = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(format!(". {} && command -v cowsay && echo \"$BULKERCRATE\"", rc.display()))
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&sourced.stdout);
+    assert!(sourced.status.success(), "stderr: {}", String::from_utf8_lossy(&sourced.stderr));
+    assert!(out.contains("bulker_"), "cowsay not from shimdir: {}", out);
+    assert!(out.contains("bulker/test-crate:1.0.0"), "BULKERCRATE not set: {}", out);
+}
+
+#[test]
+fn test_activate_write_rc_then_deactivate_restores_path() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let rc = tmp.path().join("activate.sh");
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", config_path.to_str().unwrap(), "--write-rc", rc.to_str().unwrap(), "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    // The file's own `bulker deactivate`, without the init-shell function
+    for shell in ["/bin/sh", "bash"] {
+        let script = format!(
+            "orig=\"$PATH\"; . {} || exit 1; shimdir=\"$BULKER_SHIMDIR\"; bulker deactivate; \
+             [ \"$PATH\" = \"$orig\" ] && [ -z \"$BULKERCRATE$BULKER_ORIG_PATH\" ] && [ ! -d \"$shimdir\" ] && echo restored",
+            rc.display()
+        );
+        let sourced = Command::new(shell).arg("-c").arg(script).env_remove("BULKERCRATE").output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&sourced.stdout).trim(), "restored", "{}: {}", shell, String::from_utf8_lossy(&sourced.stderr));
+        // Re-create the shimdir the first shell removed
+        let output = bulker_cmd(tmp.path())
+            .args(["activate", "-c", config_path.to_str().unwrap(), "--write-rc", rc.to_str().unwrap(), "bulker/test-crate:1.0.0"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+    }
+}
+
+#[test]
+fn test_activate_exec_shell_needs_no_rcfile() {
+    use std::os::unix::fs::PermissionsExt;
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    // Stands in for the user's shell: reports how it was started
+    let shell = tmp.path().join("fake-shell");
+    fs::write(&shell, "#!/bin/sh\necho \"args:$#\"\necho \"path:$PATH\"\necho \"crate:$BULKERCRATE\"\n").unwrap();
+    fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "set", "-c", config_path.to_str().unwrap(), &format!("shell_path={}", shell.display())])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", config_path.to_str().unwrap(), "--exec-shell", "bulker/test-crate:1.0.0"])
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("args:0\n"), "the shell got a bulker rcfile: {}", stdout);
+    let path = stdout.lines().find_map(|l| l.strip_prefix("path:")).unwrap();
+    let shimdir = path.split(':').next().unwrap();
+    assert!(std::path::Path::new(shimdir).join("cowsay").exists(), "shimdir not first on PATH: {}", path);
+    assert!(stdout.contains("crate:bulker/test-crate:1.0.0"), "{}", stdout);
+}
+
+#[test]
+fn test_activate_detach_then_attach_from_another_shell() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let cfg = config_path.to_str().unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", cfg, "--detach", "work", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let sessions = config_path.parent().unwrap().join("sessions");
+    assert!(sessions.join("work.shims/cowsay").exists());
+
+    let output = bulker_cmd(tmp.path()).args(["attach", "-c", cfg, "work"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout.trim(), format!("source {}", sessions.join("work.env").display()));
+
+    // A later shell with its own PATH gets the shims in front of it, and
+    // deactivating leaves the session's shimdir for other shells
+    let sourced = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(format!(
+            "PATH=/opt/late:$PATH; {} && command -v cowsay && echo \"$PATH\" && echo \"$BULKER_SHIMDIR_SHARED\"",
+            stdout.trim().replacen("source", ".", 1)
+        ))
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    let out = String::from_utf8_lossy(&sourced.stdout);
+    assert!(sourced.status.success(), "stderr: {}", String::from_utf8_lossy(&sourced.stderr));
+    let shims = sessions.join("work.shims");
+    assert!(out.contains(&format!("{}/cowsay", shims.display())), "{}", out);
+    assert!(out.contains(&format!("{}:/opt/late:", shims.display())), "{}", out);
+    assert!(out.lines().last() == Some("1"), "{}", out);
+
+    let output = bulker_cmd(tmp.path()).args(["attach", "-c", cfg, "--list"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "work\tbulker/test-crate:1.0.0\n");
+    let output = bulker_cmd(tmp.path()).args(["attach", "-c", cfg, "--remove", "work"]).output().unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(!shims.exists());
+    let output = bulker_cmd(tmp.path()).args(["attach", "-c", cfg, "work"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No session 'work'"));
+}
+
+#[test]
+fn test_crate_scan_aggregates_findings_per_command() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    // A fake trivy: one critical finding in the cowsay image, none in fortune
+    let bin = tmp.path().join("bin");
+    fs::create_dir(&bin).unwrap();
+    let trivy = bin.join("trivy");
+    fs::write(
+        &trivy,
+        r#"#!/bin/sh
+case "$5" in
+  nsheff/cowsay) echo '{"Results":[{"Vulnerabilities":[{"VulnerabilityID":"CVE-1","PkgName":"perl","InstalledVersion":"5.1","Severity":"CRITICAL"}]}]}' ;;
+  *) echo '{"Results":[]}' ;;
+esac
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&trivy, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default());
+    let scan = |extra: &[&str]| {
+        bulker_cmd(tmp.path())
+            .env("PATH", &path)
+            .args(["crate", "scan", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0"])
+            .args(extra)
+            .output()
+            .unwrap()
+    };
+
+    let output = scan(&["--json"]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["scanner"], "trivy");
+    assert_eq!(report["commands"][0]["command"], "cowsay");
+    assert_eq!(report["commands"][0]["counts"]["critical"], 1);
+    assert_eq!(report["commands"][1]["command"], "fortune");
+    assert_eq!(report["commands"][1]["counts"]["critical"], 0);
+
+    let output = scan(&["--fail-on", "high"]);
+    assert_eq!(output.status.code(), Some(3), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).lines().any(|l| l.starts_with("cowsay") && l.contains("nsheff/cowsay")));
+}
+
+#[test]
+fn test_crate_alias_resolves_tag_and_shows_in_list() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let cfg = config_path.to_str().unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "alias", "-c", cfg, "bulker/test-crate", "stable=1.0.0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", cfg, "--echo", "bulker/test-crate:stable"])
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("export BULKERCRATE=\"bulker/test-crate:1.0.0\""), "{}", stdout);
+
+    let output = bulker_cmd(tmp.path()).args(["crate", "list", "-c", cfg]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("aliases: stable -> 1.0.0"), "{}", stdout);
+
+    // A real tag can't be shadowed
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "alias", "-c", cfg, "bulker/test-crate", "1.0.0=2.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_nested_activation_stacks_and_deactivate_pops() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let cfg = config_path.to_str().unwrap();
+    let other = tmp.path().join("other.yaml");
+    fs::write(&other, ManifestFixture::new("other").version("1").command("othertool", "org/other:1").to_yaml()).unwrap();
+    let output = bulker_cmd(tmp.path()).args(["crate", "install", "-c", cfg, other.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let script = format!(
+        r#"eval "$(bulker init-shell bash)"
+bulker activate -c {cfg} bulker/test-crate:1.0.0
+outer_path="$PATH"
+bulker activate -c {cfg} bulker/other:1
+echo "inner=$BULKERCRATE"
+command -v othertool >/dev/null && command -v cowsay >/dev/null && echo both-on-path
+inner_shimdir="$BULKER_SHIMDIR"
+bulker deactivate
+echo "popped=$BULKERCRATE"
+[ "$PATH" = "$outer_path" ] && echo path-restored
+[ -d "$inner_shimdir" ] || echo inner-removed
+bulker activate -c {cfg} --replace bulker/other:1
+echo "replaced=$BULKERCRATE stack=${{BULKER_STACK:-none}}"
+bulker deactivate
+echo "done=${{BULKERCRATE:-none}}"
+"#,
+        cfg = cfg
+    );
+    let path = format!("{}:/usr/bin:/bin", bulker_bin().parent().unwrap().display());
+    let output = Command::new("/bin/bash")
+        .args(["-c", &script])
+        .env("XDG_CONFIG_HOME", tmp.path())
+        .env("XDG_CACHE_HOME", tmp.path())
+        .env("BULKER_SKIP_ENGINE_CHECK", "1")
+        .env("PATH", &path)
+        .env_remove("BULKERCRATE")
+        .env_remove("BULKER_STACK")
+        .env_remove("BULKER_ORIG_PATH")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("inner=bulker/other:1,bulker/test-crate:1.0.0\n"), "{}\n{}", stdout, stderr);
+    assert!(stdout.contains("both-on-path"), "{}\n{}", stdout, stderr);
+    assert!(stdout.contains("popped=bulker/test-crate:1.0.0\n"), "{}\n{}", stdout, stderr);
+    assert!(stdout.contains("path-restored"), "{}\n{}", stdout, stderr);
+    assert!(stdout.contains("inner-removed"), "{}\n{}", stdout, stderr);
+    assert!(stdout.contains("replaced=bulker/other:1 stack=none\n"), "{}\n{}", stdout, stderr);
+    assert!(stdout.contains("done=none"), "{}\n{}", stdout, stderr);
+}
+
+#[test]
+fn test_shared_shimdir_keeps_shimlinks_of_overlapping_activations() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let cfg = config_path.to_str().unwrap();
+    let other = tmp.path().join("other.yaml");
+    fs::write(&other, ManifestFixture::new("other").version("1").command("othertool", "org/other:1").to_yaml()).unwrap();
+    let output = bulker_cmd(tmp.path()).args(["crate", "install", "-c", cfg, other.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let shared = tmp.path().join("shared-shims");
+    let shimdir = shared.to_str().unwrap();
+    let activate = |cmd: &mut Command| {
+        let output = cmd.env_remove("BULKERCRATE").env_remove("BULKER_STACK").output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    };
+
+    // First activation is owned by this (still running) test process
+    activate(bulker_cmd(tmp.path()).args(["activate", "-c", cfg, "--echo", "--shimdir", shimdir, "bulker/test-crate:1.0.0"]));
+    // A second, overlapping one from another shell doesn't prune its shimlinks
+    let second = format!(
+        "{} activate -c {} --echo --shimdir {} bulker/other:1 >/dev/null; true",
+        bulker_bin().display(), cfg, shimdir
+    );
+    activate(
+        Command::new("/bin/sh")
+            .args(["-c", &second])
+            .env("XDG_CONFIG_HOME", tmp.path())
+            .env("XDG_CACHE_HOME", tmp.path())
+            .env("BULKER_SKIP_ENGINE_CHECK", "1"),
+    );
+    assert!(shared.join("cowsay").is_symlink());
+    assert!(shared.join("othertool").is_symlink());
+
+    // Once that shell has exited, re-activating here drops both the second
+    // shell's shimlinks and the ones this process no longer uses
+    activate(bulker_cmd(tmp.path()).args(["activate", "-c", cfg, "--echo", "--shimdir", shimdir, "bulker/test-crate:1.0.0", "--commands-filter", "cowsay"]));
+    assert!(shared.join("cowsay").is_symlink());
+    assert!(!shared.join("othertool").exists());
+    assert!(!shared.join("fortune").exists());
+}
+
+#[test]
+fn test_activate_commands_filter_links_only_matching_commands() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let cfg = config_path.to_str().unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", cfg, "--echo", "--strict", "--commands-filter", "cow*", "bulker/test-crate:1.0.0"])
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let shimdir = stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("export PATH=\""))
+        .and_then(|l| l.strip_suffix('"'))
+        .unwrap()
+        .to_string();
+    assert!(std::path::Path::new(&shimdir).join("cowsay").exists());
+    assert!(!std::path::Path::new(&shimdir).join("fortune").exists());
+    let _ = fs::remove_dir_all(&shimdir);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", cfg, "--echo", "--commands-filter", "samtools", "bulker/test-crate:1.0.0"])
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("matches no command"));
+
+    // On install the filter picks images to pull, so it needs --build
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "install", "-c", cfg, "--commands-filter", "cow*", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_crate_annotate_shows_in_inspect_and_filters_list() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let cfg = config_path.to_str().unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "annotate", "-c", cfg, "bulker/test-crate:1.0.0", "project=encode-rerun"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "inspect", "-c", cfg, "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Annotations:\n  project=encode-rerun"), "{}", stdout);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "list", "-c", cfg, "--filter", "project=encode-rerun"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("bulker/test-crate"), "{}", stdout);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "list", "-c", cfg, "--filter", "project=other"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("bulker/test-crate"), "{}", stdout);
+
+    // Only cached crates can be annotated
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "annotate", "-c", cfg, "bulker/missing:1.0", "project=x"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_activate_no_rc_uses_minimal_rcfile() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    // A fake shell that reports how bulker launched it
+    let shell = tmp.path().join("bash");
+    std::fs::write(&shell, "#!/bin/sh\necho \"args: $*\"\necho \"path: $BULKERPATH\"\n").unwrap();
+    std::fs::set_permissions(&shell, std::fs::Permissions::from_mode(0o755)).unwrap();
+    bulker_cmd(tmp.path())
+        .args(["config", "set", "-c", config_path.to_str().unwrap(), &format!("shell_path={}", shell.display())])
+        .output()
+        .unwrap();
+
+    let launch = |extra: &[&str]| {
+        let output = bulker_cmd(tmp.path())
+            .env_remove("BULKERCRATE")
+            .args(["activate", "-c", config_path.to_str().unwrap()])
+            .args(extra)
+            .arg("bulker/test-crate:1.0.0")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let default = launch(&[]);
+    assert!(default.contains("--rcfile") && default.contains("/start.sh"), "{}", default);
+    let no_rc = launch(&["--no-rc"]);
+    assert!(no_rc.contains("/start_strict.sh"), "{}", no_rc);
+    // Unlike strict mode, the host PATH is kept
+    let path_line = no_rc.lines().find(|l| l.starts_with("path: ")).unwrap();
+    assert!(path_line.contains(':'), "{}", no_rc);
+
+    // There is no rc file to skip when printing exports
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", config_path.to_str().unwrap(), "--echo", "--no-rc", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_bugreport_includes_last_failure() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\nexit 4\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["exec", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0", "--", "cowsay", "moo"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["bugreport", "-c", config_path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.starts_with("# bulker bug report"), "{}", stdout);
+    assert!(stdout.contains("- bulker/test-crate:1.0.0"), "cache listing missing: {}", stdout);
+    assert!(stdout.contains("\"exit_code\": 4"), "last failure missing: {}", stdout);
+    assert!(stdout.contains("\"command\": \"cowsay\""), "last failure missing: {}", stdout);
+}
+
+#[test]
+fn test_crate_clean_unused_removes_stale_crates() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let tag_dir = tmp.path().join("bulker/manifests/bulker/test-crate/1.0.0");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    // Recently used: kept
+    fs::write(tag_dir.join("last-used"), (now - 10 * 86400).to_string()).unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "clean", "-c", config_path.to_str().unwrap(), "--unused", "90d", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(tag_dir.join("manifest.yaml").exists());
+
+    // Stale: needs confirmation, which a non-terminal cannot give
+    fs::write(tag_dir.join("last-used"), (now - 200 * 86400).to_string()).unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "clean", "-c", config_path.to_str().unwrap(), "--unused", "90d"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+    assert!(tag_dir.join("manifest.yaml").exists());
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "clean", "-c", config_path.to_str().unwrap(), "--unused", "90d", "--yes"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Removed: bulker/test-crate:1.0.0"), "{}", stdout);
+    assert!(!tag_dir.exists(), "tag dir with sidecars should be gone");
+}
+
+#[test]
+fn test_activate_quiet_partial_imports_exit_code() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = tmp.path().join("bulker_config.yaml");
+    // Unreachable registry: imports cannot be fetched
+    fs::write(&config_path, "bulker:\n  container_engine: docker\n  registry_url: http://127.0.0.1:9/\n").unwrap();
+    let manifest = tmp.path().join("partial.yaml");
+    fs::write(
+        &manifest,
+        "manifest:\n  name: partial\n  commands:\n  - command: cowsay\n    docker_image: nsheff/cowsay\n  imports:\n  - bulker/unreachable-import:1.0\n",
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "activate",
+            "-c", config_path.to_str().unwrap(),
+            "--echo", "--quiet",
+            "-n", "me/partial:1.0",
+            manifest.to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "stderr: {}", stderr);
+    assert!(stdout.lines().all(|l| l.starts_with("export ")), "stdout must carry only exports: {}", stdout);
+    assert!(stdout.contains("export PATH="), "{}", stdout);
+    assert!(!stderr.contains("WARN"), "--quiet should silence warnings: {}", stderr);
+    assert!(stderr.contains("bulker/unreachable-import:1.0"), "{}", stderr);
+}
+
+#[test]
+fn test_unknown_subcommand_dispatches_to_plugin() {
+    use std::os::unix::fs::PermissionsExt;
+    let tmp = TempDir::new().unwrap();
+    let plugin_dir = tmp.path().join("plugins");
+    fs::create_dir_all(&plugin_dir).unwrap();
+    let plugin = plugin_dir.join("bulker-hello");
+    fs::write(&plugin, "#!/bin/sh\necho \"hello from plugin: $*\"\necho \"bin=$BULKER_BIN\"\nexit 7\n").unwrap();
+    fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", plugin_dir.display(), std::env::var("PATH").unwrap_or_default());
+
+    let output = bulker_cmd(tmp.path())
+        .env("PATH", &path)
+        .args(["hello", "--flag", "arg"])
+        .output()
+        .expect("failed to run plugin");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(7), "plugin exit code should pass through");
+    assert!(stdout.contains("hello from plugin: --flag arg"), "stdout: {}", stdout);
+    assert!(stdout.contains(&format!("bin={}", bulker_bin().display())), "stdout: {}", stdout);
+
+    let output = bulker_cmd(tmp.path())
+        .env("PATH", &path)
+        .arg("--list-plugins")
+        .output()
+        .expect("failed to list plugins");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.contains("hello") && stdout.contains(&plugin.display().to_string()), "stdout: {}", stdout);
+
+    // No plugin: the usual clap error
+    let output = bulker_cmd(tmp.path())
+        .env("PATH", &path)
+        .arg("nosuchplugin")
+        .output()
+        .expect("failed to run bulker");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unrecognized subcommand 'nosuchplugin'"));
+}
+
+#[test]
+fn test_exec_isolate_home_uses_throwaway_home() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\necho \"$@\"\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n  volumes: ['$HOME']\n", engine.display()),
+    )
+    .unwrap();
+    let home = tmp.path().join("home");
+    let scratch = tmp.path().join("scratch");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&scratch).unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .env("HOME", &home)
+        .env("TMPDIR", &scratch)
+        .args([
+            "exec",
+            "-c", config_path.to_str().unwrap(),
+            "--isolate-home",
+            "bulker/test-crate:1.0.0",
+            "--",
+            "cowsay", "hello",
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let real_home_mount = format!("--volume {}:{}", home.display(), home.display());
+    assert!(!stdout.contains(&real_home_mount), "real HOME mounted: {}", stdout);
+    assert!(stdout.contains(&format!("HOME={}/bulker_home_", scratch.display())), "stdout: {}", stdout);
+    // The throwaway home is removed after the run
+    let leftovers: Vec<_> = fs::read_dir(&scratch)
+        .unwrap()
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("bulker_home_"))
+        .collect();
+    assert!(leftovers.is_empty(), "isolated home left behind");
+}
+
+#[test]
+fn test_doctor_active_reports_buried_shimdir() {
+    let tmp = TempDir::new().unwrap();
+    let shimdir = tmp.path().join("shims");
+    let early = tmp.path().join("early");
+    fs::create_dir_all(&shimdir).unwrap();
+    fs::create_dir_all(&early).unwrap();
+    std::os::unix::fs::symlink(bulker_bin(), shimdir.join("cowsay")).unwrap();
+    fs::write(early.join("cowsay"), "").unwrap();
+    let host_path = std::env::var("PATH").unwrap_or_default();
+
+    let doctor = |path: String| {
+        bulker_cmd(tmp.path())
+            .env("BULKERCRATE", "bulker/test-crate:1.0.0")
+            .env("BULKER_SHIMDIR", &shimdir)
+            .env("PATH", path)
+            .args(["doctor", "--active"])
+            .output()
+            .unwrap()
+    };
+
+    let output = doctor(format!("{}:{}", shimdir.display(), host_path));
+    assert!(output.status.success(), "stdout: {}", String::from_utf8_lossy(&output.stdout));
+
+    let output = doctor(format!("{}:{}:{}", early.display(), shimdir.display(), host_path));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains(&format!("{}  (shadows: cowsay)", early.display())), "stdout: {}", stdout);
+}
+
+#[test]
+fn test_demo_runs_embedded_crate_without_shell() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\necho \"engine: $@\"\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["demo", "--no-shell", "-c", config_path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "demo failed: {}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("nsheff/cowsay"), "{}", stdout);
+    assert!(stdout.contains("Next: bulker activate bulker/demo:embedded"), "{}", stdout);
+    // The demo crate stays installed
+    assert!(tmp.path().join("bulker/manifests/bulker/demo/embedded/manifest.yaml").exists());
+}
+
+#[test]
+fn test_activate_prefetch_pulls_images() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    // Fake engine that records pulls and prints progress on stdout
+    let pulls = tmp.path().join("pulls.log");
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, format!("#!/bin/sh\necho \"progress $*\"\necho \"$*\" >> {}\n", pulls.display())).unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let activate = |mode: &str| {
+        bulker_cmd(tmp.path())
+            .args(["activate", "-c", config_path.to_str().unwrap(), "--echo", mode, "bulker/test-crate:1.0.0"])
+            .output()
+            .unwrap()
+    };
+
+    // Foreground: images pulled before the exports print; progress stays off stdout
+    let output = activate("--prefetch");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("prefetching [2/2]"), "{}", stderr);
+    assert!(stderr.contains("prefetched 2 image(s) in "), "{}", stderr);
+    assert!(!stdout.contains("progress"), "{}", stdout);
+    let pulled = fs::read_to_string(&pulls).unwrap();
+    assert!(pulled.contains("pull nsheff/cowsay") && pulled.contains("pull nsheff/fortune"), "{}", pulled);
+    fs::remove_file(&pulls).unwrap();
+
+    // Background: a detached worker pulls and reports to the status file
+    let output = activate("--prefetch=background");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let status_line = stdout
+        .lines()
+        .find(|l| l.starts_with("export BULKER_PREFETCH_STATUS="))
+        .unwrap_or_else(|| panic!("no status export: {}", stdout));
+    let status_path = status_line.trim_start_matches("export BULKER_PREFETCH_STATUS=").trim_matches(|c| c == '"' || c == '\'');
+    let mut status = String::new();
+    for _ in 0..100 {
+        status = fs::read_to_string(status_path).unwrap_or_default();
+        if status.contains("prefetch done") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(status.contains("prefetch done"), "{}", status);
+    assert!(fs::read_to_string(&pulls).unwrap().contains("pull nsheff/cowsay"));
+}
+
+#[test]
+fn test_status_summarizes_activation() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    // Not activated
+    let output = bulker_cmd(tmp.path())
+        .args(["status", "-c", config_path.to_str().unwrap()])
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No crate is active"));
+
+    let shimdir = tmp.path().join("shims");
+    fs::create_dir_all(&shimdir).unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["status", "--json", "-c", config_path.to_str().unwrap()])
+        .env("BULKERCRATE", "bulker/test-crate:1.0.0")
+        .env("BULKER_SHIMDIR", &shimdir)
+        .env("BULKERPATH", &shimdir)
+        .env("PATH", &shimdir)
+        .env_remove("BULKER_HOST_ENV")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["crates"][0]["name"], "bulker/test-crate:1.0.0");
+    assert_eq!(info["crates"][0]["commands"], 2);
+    assert!(info["crates"][0]["manifest_digest"].is_string());
+    assert_eq!(info["commands"], 2);
+    assert_eq!(info["strict"], true);
+    assert_eq!(info["host_env"], false);
+    assert_eq!(info["shimdir_health"], "ok");
+}
+
+#[test]
+fn test_registry_login_records_reference_not_token() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+
+    // Fake engine that records its arguments and stdin
+    let calls = tmp.path().join("calls.log");
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, format!("#!/bin/sh\necho \"$* stdin=$(cat)\" >> {}\n", calls.display())).unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["registry", "login", "-c", config_path.to_str().unwrap(), "ghcr.io", "-u", "me", "--token-env", "BULKER_IT_TOKEN"])
+        .env("BULKER_IT_TOKEN", "tok123")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read_to_string(&calls).unwrap().trim(),
+        "login --username me --password-stdin ghcr.io stdin=tok123"
+    );
+    let config = fs::read_to_string(&config_path).unwrap();
+    assert!(config.contains("ghcr.io: me@env:BULKER_IT_TOKEN"), "{}", config);
+    assert!(!config.contains("tok123"));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["registry", "list", "-c", config_path.to_str().unwrap()])
+        .env_remove("BULKER_IT_TOKEN")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("ghcr.io") && stdout.contains("is not set"), "{}", stdout);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["registry", "logout", "-c", config_path.to_str().unwrap(), "ghcr.io"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(fs::read_to_string(&calls).unwrap().contains("logout ghcr.io"));
+    assert!(!fs::read_to_string(&config_path).unwrap().contains("registry_auth"));
+}
+
+#[test]
+fn test_mock_generate_placeholders() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let out = tmp.path().join("ci/outputs.json");
+    fs::create_dir_all(out.parent().unwrap()).unwrap();
+    fs::write(&out, r#"{"cowsay": [{"args_pattern": "hi", "stdout": "recorded\n"}]}"#).unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "mock", "generate", "-c", config_path.to_str().unwrap(),
+            "bulker/test-crate:1.0.0", "-o", out.to_str().unwrap(),
+            "--creates", "fortune={-o}",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let outputs: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+    assert_eq!(outputs["cowsay"][0]["stdout"], "recorded\n");
+    assert_eq!(outputs["fortune"][0]["args_pattern"], ".*");
+    assert_eq!(outputs["fortune"][0]["returncode"], 0);
+    assert_eq!(outputs["fortune"][0]["creates_files"]["{-o}"], "");
+
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "mock", "generate", "-c", config_path.to_str().unwrap(),
+            "bulker/test-crate:1.0.0", "-o", out.to_str().unwrap(), "--force",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let outputs: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+    assert_eq!(outputs["cowsay"][0]["stdout"], "");
+}
+
+#[test]
+fn test_shimlink_metrics_textfile() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\nexit 3\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    let prom = tmp.path().join("textfile/bulker.prom");
+    fs::write(
+        &config_path,
+        format!(
+            "bulker:\n  container_engine: docker\n  engine_path: {}\n  metrics:\n    mode: textfile\n    path: {}\n",
+            engine.display(),
+            prom.display()
+        ),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["exec", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0", "--", "cowsay", "hello"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+
+    let text = fs::read_to_string(&prom).unwrap();
+    let labels = "command=\"cowsay\",image=\"nsheff/cowsay\"";
+    assert!(text.contains(&format!("bulker_invocations_total{{{}}} 1", labels)), "{}", text);
+    assert!(text.contains(&format!("bulker_invocation_failures_total{{{}}} 1", labels)), "{}", text);
+    assert!(text.contains("# TYPE bulker_invocation_duration_seconds histogram"), "{}", text);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "get", "-c", config_path.to_str().unwrap(), "metrics"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), format!("textfile:{}", prom.display()));
+}
+
+#[test]
+fn test_env_defaults_set_in_container() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    let manifest = tmp.path().join("rtools.yaml");
+    fs::write(
+        &manifest,
+        "manifest:\n  name: rtools\n  version: '1.0'\n  env_defaults:\n  - R_LIBS_USER=$HOME/R/rtools\n  - PYTHONNOUSERSITE=1\n  commands:\n  - command: Rscript\n    docker_image: r-base:4.3.1\n",
+    )
+    .unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "install", "-c", config_path.to_str().unwrap(), manifest.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(&engine, "#!/bin/sh\necho \"engine: $@\"\n").unwrap();
+    fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n  envvars: ['PYTHONNOUSERSITE=0']\n", engine.display()),
+    )
+    .unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args(["exec", "-c", config_path.to_str().unwrap(), "bulker/rtools:1.0", "--", "Rscript", "-e", "1"])
+        .env("R_LIBS_USER", "/host/R")
+        .env("HOME", tmp.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains(&format!("--env R_LIBS_USER={}/R/rtools", tmp.path().display())), "{}", stdout);
+    assert!(stdout.contains("--env PYTHONNOUSERSITE=0"), "{}", stdout);
+    assert!(!stdout.contains("PYTHONNOUSERSITE=1"), "{}", stdout);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["env", "-c", config_path.to_str().unwrap(), "--crate", "bulker/rtools:1.0"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("env_defaults of bulker/rtools:1.0"), "{}", stdout);
+    assert!(stdout.contains("PYTHONNOUSERSITE=1  (overridden by config envvars)"), "{}", stdout);
+}
+
+#[test]
+fn test_engine_reserved_exit_codes_are_explained() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let engine = tmp.path().join("fake-docker");
+    fs::write(
+        &config_path,
+        format!("bulker:\n  container_engine: docker\n  engine_path: {}\n", engine.display()),
+    )
+    .unwrap();
+
+    // Engine errors are remapped so they can't be mistaken for the tool's status
+    for (code, exit, kind, message) in [
+        (125, 69, "engine_error", "This is an engine error, not a failure of 'cowsay'"),
+        (127, 127, "not_found", "'cowsay' was not found in nsheff/cowsay (exit 127)"),
+    ] {
+        fs::write(&engine, format!("#!/bin/sh\nexit {}\n", code)).unwrap();
+        fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+        let bundle = tmp.path().join(format!("bundle-{}", code));
+        let output = bulker_cmd(tmp.path())
+            .args([
+                "exec", "-c", config_path.to_str().unwrap(), "--record", bundle.to_str().unwrap(),
+                "bulker/test-crate:1.0.0", "--", "cowsay", "hello",
+            ])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(exit), "stderr: {}", stderr);
+        assert!(stderr.contains(message), "stderr: {}", stderr);
+        let commands = fs::read_to_string(bundle.join("commands.jsonl")).unwrap();
+        let first: serde_json::Value = serde_json::from_str(commands.lines().next().unwrap()).unwrap();
+        assert_eq!(first["exit_kind"], kind);
+    }
+}
+
+#[test]
+fn test_crate_inspect_effective_merges_config() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    for setting in ["volumes+=/data", "envvars=MYVAR", "host_commands+=git"] {
+        let output = bulker_cmd(tmp.path())
+            .args(["config", "set", "-c", config_path.to_str().unwrap(), setting])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "inspect", "-c", config_path.to_str().unwrap(), "--effective", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("  cowsay  nsheff/cowsay  [bulker/test-crate:1.0.0]"), "{}", stdout);
+    assert!(stdout.contains("    volumes:      $HOME, /data"), "{}", stdout);
+    assert!(stdout.contains("    envvars:      defaults + MYVAR"), "{}", stdout);
+    assert!(stdout.contains("Host commands (strict mode): ls, git"), "{}", stdout);
+}
+
+#[test]
+fn test_crate_graph_dot_and_json() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "graph", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    let dot = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dot.starts_with("digraph bulker {"), "{}", dot);
+    assert!(dot.contains("\"cmd:bulker/test-crate:1.0.0:cowsay\" -> \"image:nsheff/cowsay\";"), "{}", dot);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "graph", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0", "--format", "json"])
+        .output()
+        .unwrap();
+    let graph: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(graph["roots"][0], "bulker/test-crate:1.0.0");
+    assert_eq!(graph["images"], serde_json::json!(["nsheff/cowsay", "nsheff/fortune"]));
+    assert_eq!(graph["crates"][0]["host_commands"][0], "ls");
+}
+
+#[test]
+fn test_print_command_mounts_paths_with_spaces_and_colons() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let spaced = tmp.path().join("my runs");
+    let coloned = tmp.path().join("run:2");
+    fs::create_dir_all(&spaced).unwrap();
+    fs::create_dir_all(&coloned).unwrap();
+    fs::write(spaced.join("in.txt"), "").unwrap();
+    fs::write(coloned.join("in.txt"), "").unwrap();
+
+    let output = bulker_cmd(tmp.path())
+        .args([
+            "exec", "-c", config_path.to_str().unwrap(), "-p", "bulker/test-crate:1.0.0", "--",
+            "cowsay", spaced.join("in.txt").to_str().unwrap(), coloned.join("in.txt").to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let spaced = fs::canonicalize(&spaced).unwrap();
+    let coloned = fs::canonicalize(&coloned).unwrap();
+    assert!(stdout.contains(&format!("--volume '{}:{}'", spaced.display(), spaced.display())), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("--mount type=bind,source={},target={}", coloned.display(), coloned.display())),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_activation_message_shown_once() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    // A "shell" that exits at once, so the interactive activation returns
+    let shell = tmp.path().join("fake-shell");
+    fs::write(&shell, "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(&shell, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "set", "-c", config_path.to_str().unwrap(), &format!("shell_path={}", shell.display())])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let manifest = tmp.path().join("noted.yaml");
+    fs::write(
+        &manifest,
+        "manifest:\n  name: noted\n  activation_message: |\n    Please cite us.\n  commands:\n  - command: tool\n    docker_image: org/tool:1\n",
+    )
+    .unwrap();
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "install", "-c", config_path.to_str().unwrap(), "-n", "bulker/noted:1", manifest.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let activate = |extra: &[&str]| {
+        let output = bulker_cmd(tmp.path())
+            .env_remove("BULKERCRATE")
+            .args(["activate", "-c", config_path.to_str().unwrap()])
+            .args(extra)
+            .arg("bulker/noted:1")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stderr).to_string()
+    };
+    assert!(!activate(&["--quiet"]).contains("Please cite us."));
+    let stderr = activate(&[]);
+    assert!(stderr.contains("bulker: message from bulker/noted:1:\n  Please cite us.\n"), "{}", stderr);
+    assert!(!activate(&[]).contains("Please cite us."));
+
+    let output = bulker_cmd(tmp.path())
+        .args(["crate", "inspect", "-c", config_path.to_str().unwrap(), "bulker/noted:1"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Activation message:\n  Please cite us."));
+}
+
+#[test]
+fn test_config_doctor_fix() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    let contents = fs::read_to_string(&config_path).unwrap();
+    fs::write(&config_path, contents.replace("  - $HOME\n", "  - $HOME/\n  - $HOME\n").replace("shell_prompt: null", "shell_prompt: 'null'")).unwrap();
+    fs::remove_file(tmp.path().join("templates/start.sh")).unwrap();
+    let doctor = |extra: &[&str]| {
+        bulker_cmd(tmp.path()).args(["config", "doctor", "-c", config_path.to_str().unwrap()]).args(extra).output().unwrap()
+    };
+
+    let output = doctor(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("templates: missing start.sh"), "{}", stdout);
+    assert!(stdout.contains("-   - $HOME/\n"), "{}", stdout);
+    assert!(stdout.contains("-   shell_prompt: 'null'\n"), "{}", stdout);
+    // Without --fix nothing changes
+    assert!(!tmp.path().join("templates/start.sh").exists());
+
+    let output = doctor(&["--fix"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(tmp.path().join("templates/start.sh").is_file());
+    let output = doctor(&[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No problems found"));
+}
+
+#[test]
+fn test_extra_docker_args_scoped_to_one_command() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let print = |command: &str| {
+        let output = bulker_cmd(tmp.path())
+            .env("BULKER_EXTRA_DOCKER_ARGS", "--shm-size=1g")
+            .env("BULKER_EXTRA_DOCKER_ARGS_COWSAY", "--memory=2g")
+            .args(["exec", "-c", config_path.to_str().unwrap(), "-p", "bulker/test-crate:1.0.0", "--", command])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let stdout = print("cowsay");
+    assert!(stdout.contains("--shm-size=1g --memory=2g"), "{}", stdout);
+    let stdout = print("fortune");
+    assert!(stdout.contains("--shm-size=1g") && !stdout.contains("--memory=2g"), "{}", stdout);
+}
+
+#[test]
+fn test_locale_policy_c_utf8_sets_lang() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "set", "-c", config_path.to_str().unwrap(), "locale_policy=c.utf8"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = bulker_cmd(tmp.path())
+        .env("LANG", "de_DE.UTF-8")
+        .env("LC_ALL", "de_DE.UTF-8")
+        .args(["exec", "-c", config_path.to_str().unwrap(), "-p", "bulker/test-crate:1.0.0", "--", "cowsay"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("--env LANG=C.UTF-8"), "{}", stdout);
+    assert!(!stdout.contains("--env LANG ") && !stdout.contains("LC_ALL"), "{}", stdout);
+}
+
+#[test]
+fn test_shimlink_unknown_command_exits_127_with_suggestions() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let shimdir = tmp.path().join("shims");
+    fs::create_dir_all(&shimdir).unwrap();
+    std::os::unix::fs::symlink(bulker_bin(), shimdir.join("cowsy")).unwrap();
+
+    let output = Command::new(shimdir.join("cowsy"))
+        .env("XDG_CONFIG_HOME", tmp.path())
+        .env("XDG_CACHE_HOME", tmp.path())
+        .env("BULKERCFG", &config_path)
+        .env("BULKERCRATE", "bulker/test-crate:1.0.0")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(127), "stderr: {}", stderr);
+    assert!(stderr.contains("searched: bulker/test-crate:1.0.0"), "stderr: {}", stderr);
+    assert!(stderr.contains("did you mean: cowsay?"), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_argv0_outside_shimdir_runs_bulker_cli() {
+    let tmp = TempDir::new().unwrap();
+    let bin = tmp.path().join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    std::os::unix::fs::symlink(bulker_bin(), bin.join("samtools")).unwrap();
+
+    // No active crate and not in a shimdir: the link is just another name for bulker
+    let output = Command::new(bin.join("samtools"))
+        .env_remove("BULKERCRATE")
+        .env_remove("BULKER_SHIMDIR")
+        .env_remove("BULKER_SHIMLINK")
+        .arg("--version")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(env!("CARGO_PKG_VERSION")));
+
+    // The escape hatch forces dispatch on the name
+    let output = Command::new(bin.join("samtools"))
+        .env_remove("BULKERCRATE")
+        .env_remove("BULKER_SHIMDIR")
+        .env("BULKER_SHIMLINK", "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("$BULKERCRATE not set"));
+}
+
+#[test]
+fn test_shimlink_uses_config_snapshot_until_config_changes() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+    let output = bulker_cmd(tmp.path())
+        .args(["activate", "-c", config_path.to_str().unwrap(), "--echo", "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let shimdir = stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("export BULKER_SHIMDIR="))
+        .map(|v| v.trim_matches(|c| c == '"' || c == '\''))
+        .unwrap_or_else(|| panic!("no BULKER_SHIMDIR export: {}", stdout))
+        .to_string();
+    assert!(std::path::Path::new(&shimdir).join(".bulker_config.json").is_file());
+
+    let run_cowsay = || {
+        let output = Command::new(std::path::Path::new(&shimdir).join("cowsay"))
+            .env("XDG_CONFIG_HOME", tmp.path())
+            .env("XDG_CACHE_HOME", tmp.path())
+            .env("BULKERCFG", &config_path)
+            .env("BULKERCRATE", "bulker/test-crate:1.0.0")
+            .env("BULKER_SHIMDIR", &shimdir)
+            .env("BULKER_PRINT_COMMAND", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    assert!(!run_cowsay().contains("/snapshot-test"));
+
+    // Config edits after activation take effect without re-activating
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "add", "-c", config_path.to_str().unwrap(), "volumes", "/snapshot-test"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(run_cowsay().contains("/snapshot-test"));
+}
+
+#[test]
+fn test_crate_install_from_fake_registry_with_import() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest(
+        "bulker/top:1.0",
+        &ManifestFixture::new("top").command("cowsay", "nsheff/cowsay").import("bulker/base:2").to_yaml(),
+    );
+    registry.add_manifest("bulker/base:2", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"engine $*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine).registry_url(&registry.url()));
+
+    let output = env.command(bulker_bin()).args(["crate", "install", "bulker/top:1.0"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let requests = registry.requests();
+    assert!(requests.contains(&"/bulker/top_1.0.yaml".to_string()), "{:?}", requests);
+    assert!(requests.contains(&"/bulker/base_2.yaml".to_string()), "{:?}", requests);
+
+    // The imported command runs through the fake engine
+    let output = env
+        .command(bulker_bin())
+        .args(["exec", "bulker/top:1.0", "--", "fortune"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("engine run") && stdout.contains("nsheff/fortune"), "{}", stdout);
+}
+
+#[test]
+fn test_crate_lock_and_exec_locked() {
+    let digest = format!("sha256:{}", "a".repeat(64));
+    let registry = FakeRegistry::start();
+    registry.add_manifest(
+        "bulker/pinned:1",
+        &ManifestFixture::new("pinned").command("cowsay", &format!("nsheff/cowsay@{}", digest)).to_yaml(),
+    );
+    registry.add_manifest("bulker/other:1", &ManifestFixture::new("other").command("fortune", "nsheff/fortune").to_yaml());
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"engine $*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine).registry_url(&registry.url()));
+
+    let output = env.command(bulker_bin()).current_dir(env.path()).args(["crate", "lock", "bulker/pinned:1"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Locked 1 crate(s), 1 image(s)"));
+    let lock = fs::read_to_string(env.path().join("bulker.lock")).unwrap();
+    assert!(lock.contains("bulker/pinned:1") && lock.contains(&digest), "{}", lock);
+
+    let output = env
+        .command(bulker_bin())
+        .current_dir(env.path())
+        .args(["exec", "--locked", "bulker/pinned:1", "--", "cowsay"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("nsheff/cowsay@{}", digest)));
+
+    // A crate that was never locked is refused
+    let output = env
+        .command(bulker_bin())
+        .current_dir(env.path())
+        .args(["exec", "--locked", "bulker/other:1", "--", "fortune"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'bulker/other:1' is not in the lockfile"));
+}
+
+#[test]
+fn test_exec_locked_refuses_uncached_imports_without_leaking_shimdirs() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest("bulker/app:1", &ManifestFixture::new("app").import("bulker/base:1").command("cowsay", "nsheff/cowsay").to_yaml());
+    registry.add_manifest("bulker/base:1", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"engine $*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine).registry_url(&registry.url()));
+    let output = env.command(bulker_bin()).current_dir(env.path()).args(["crate", "install", "bulker/app:1"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // The lockfile names the app only; its import can no longer be fetched
+    let lock = "version: 1\ncrates:\n  bulker/app:1:\n    manifest_digest: x\n";
+    fs::write(env.path().join("bulker.lock"), lock).unwrap();
+    fs::remove_dir_all(env.path().join("bulker/manifests/bulker/base")).unwrap();
+    env.write_config(&ConfigFixture::new().engine_path(&engine).registry_url("http://127.0.0.1:1/"));
+
+    let runtime = env.path().join("runtime");
+    fs::create_dir(&runtime).unwrap();
+    let output = env
+        .command(bulker_bin())
+        .current_dir(env.path())
+        .env("XDG_RUNTIME_DIR", &runtime)
+        .args(["exec", "--locked", "bulker/app:1", "--", "cowsay"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("--locked") && stderr.contains("bulker/base:1"), "{}", stderr);
+    assert_eq!(fs::read_dir(&runtime).unwrap().count(), 0, "a refused --locked run left a shimdir behind");
+}
+
+#[test]
+fn test_exec_gpus_flag_reaches_engine() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest("bulker/ml:1", &ManifestFixture::new("ml").command("train", "pytorch/pytorch").to_yaml());
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"engine $*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine).registry_url(&registry.url()));
+
+    let run = |extra: &[&str]| {
+        let output = env.command(bulker_bin()).arg("exec").args(extra).args(["bulker/ml:1", "--", "train"]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    assert!(!run(&[]).contains("--gpus"));
+    assert!(run(&["--gpus"]).contains("--gpus=all"));
+}
+
+#[test]
+fn test_crate_search_queries_registry_index() {
+    let registry = FakeRegistry::start();
+    registry.add_file(
+        "/index.yaml",
+        "crates:\n\
+         - {namespace: databio, crate: pepatac, description: ATAC-seq pipeline tools, tags: [1.0.13, 1.0.14]}\n\
+         - {namespace: databio, crate: peppro}\n\
+         - {namespace: bulker, crate: demo, tags: [default]}\n",
+    );
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()));
+
+    let output = env.command(bulker_bin()).args(["crate", "search", "PEP"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout, "databio/pepatac  ATAC-seq pipeline tools\n  tags: 1.0.14, 1.0.13\ndatabio/peppro\n");
+    assert!(registry.requests().contains(&"/index.yaml".to_string()), "{:?}", registry.requests());
+
+    let output = env.command(bulker_bin()).args(["crate", "search", "--simple", "databio/"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "databio/pepatac\ndatabio/peppro\n");
+
+    let output = env.command(bulker_bin()).args(["crate", "search", "nothing"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No crates matching 'nothing'"));
+}
+
+#[test]
+fn test_crate_install_url_verifies_sha256_fragment() {
+    let registry = FakeRegistry::start();
+    let yaml = ManifestFixture::new("big").version("1").command("cowsay", "nsheff/cowsay").to_yaml();
+    registry.add_file("/manifests/big.yaml", &yaml);
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()));
+    let yaml_path = env.path().join("big.yaml");
+    fs::write(&yaml_path, &yaml).unwrap();
+    let sha256sum = Command::new("sha256sum").arg(&yaml_path).output().unwrap();
+    let checksum = String::from_utf8_lossy(&sha256sum.stdout).split_whitespace().next().unwrap().to_string();
+    let url = format!("{}manifests/big.yaml", registry.url());
+
+    let output = env
+        .command(bulker_bin())
+        .args(["crate", "install", &format!("{}#sha256={}", url, "0".repeat(64))])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Checksum mismatch"), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = env
+        .command(bulker_bin())
+        .args(["crate", "install", &format!("{}#sha256={}", url, checksum)])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("bulker/big:1"), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn test_crate_sync_installs_updates_and_prunes() {
+    let registry = FakeRegistry::start();
+    let top = ManifestFixture::new("top").command("cowsay", "nsheff/cowsay").import("bulker/base:2");
+    registry.add_manifest("bulker/top:1.0", &top.to_yaml());
+    registry.add_manifest("bulker/base:2", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    registry.add_manifest("bulker/other:1", &ManifestFixture::new("other").command("ls", "alpine").to_yaml());
+    registry.add_manifest("bulker/stale:1", &ManifestFixture::new("stale").command("ls", "alpine").to_yaml());
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()));
+    let sync = |crate_set: &str, extra: &[&str]| {
+        let path = env.path().join("crates.yaml");
+        fs::write(&path, crate_set).unwrap();
+        env.command(bulker_bin()).args(["crate", "sync", path.to_str().unwrap()]).args(extra).output().unwrap()
+    };
+    let output = env.command(bulker_bin()).args(["crate", "install", "bulker/stale:1"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A pin the registry does not serve fails that crate only
+    let output = sync("crates:\n- bulker/top:1.0\n- crate: bulker/other:1\n  digest: wrong\n", &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stdout.contains("Installed: bulker/top:1.0"), "{}", stdout);
+    assert!(stderr.contains("but the crate set pins wrong"), "{}", stderr);
+    let output = sync("crates:\n- bulker/top:1.0\n- bulker/other:1\n", &["--dry-run"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Unchanged: bulker/top:1.0") && stdout.contains("Would install: bulker/other:1"), "{}", stdout);
+
+    // update: always picks up registry changes; prune keeps imports
+    registry.add_manifest("bulker/top:1.0", &top.command("fortune", "nsheff/fortune").to_yaml());
+    let output = sync("update: always\nprune: true\ncrates:\n- bulker/top:1.0\n", &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Updated: bulker/top:1.0 ("), "{}", stdout);
+    assert!(stdout.contains("Removed: bulker/stale:1"), "{}", stdout);
+    assert!(!stdout.contains("Removed: bulker/base:2"), "{}", stdout);
+    let output = env.command(bulker_bin()).args(["crate", "list"]).output().unwrap();
+    let list = String::from_utf8_lossy(&output.stdout);
+    assert!(list.contains("base") && !list.contains("stale"), "{}", list);
+}
+
+#[test]
+fn test_apptainer_inherits_configured_umask() {
+    let env = TestEnv::new();
+    let sif_dir = env.path().join("sif");
+    fs::create_dir_all(sif_dir.join("docker.io/nsheff")).unwrap();
+    fs::write(sif_dir.join("docker.io/nsheff/cowsay-latest.sif"), "fake").unwrap();
+    let engine = env.fake_engine("umask");
+    let config = ConfigFixture::new().engine("apptainer").engine_path(&engine).to_yaml();
+    fs::write(env.config_path(), format!("{}  apptainer_image_folder: {}\n  umask: \"0027\"\n", config, sif_dir.display())).unwrap();
+    let manifest = env.path().join("test_manifest.yaml");
+    fs::write(&manifest, TEST_CRATE_MANIFEST).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = env
+        .command(bulker_bin())
+        .args(["exec", "bulker/test-crate:1.0.0", "--", "cowsay"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "0027");
+}
+
+#[test]
+fn test_activate_prewarm_starts_throwaway_container() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\" >> \"$(dirname \"$0\")/prewarm\"");
+    let config = ConfigFixture::new().engine_path(&engine).to_yaml() + "  prewarm: true\n";
+    fs::write(env.config_path(), config).unwrap();
+    let manifest = env.path().join("warm.yaml");
+    fs::write(&manifest, ManifestFixture::new("warm").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = env
+        .command(bulker_bin())
+        .args(["activate", "--echo", "bulker/warm:1"])
+        .env_remove("BULKERCRATE")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // The container runs detached, so give it a moment
+    let log = engine.parent().unwrap().join("prewarm");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while fs::read_to_string(&log).map_or(true, |s| !s.ends_with('\n')) && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let recorded = fs::read_to_string(&log).unwrap_or_default();
+    assert!(recorded.contains("run --rm --pull=never --entrypoint true org/tool:1"), "{}", recorded);
+}
+
+#[test]
+fn test_pinned_image_digests_run_and_malformed_ones_are_rejected() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$@\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let digest = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+    let install = |image: &str| {
+        let manifest = env.path().join("pinned.yaml");
+        fs::write(&manifest, ManifestFixture::new("pinned").version("1").command("tool", image).to_yaml()).unwrap();
+        env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap()
+    };
+
+    let output = install("quay.io/org/tool@sha256:abc");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid image digest"), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let image = format!("quay.io/org/tool@{}", digest);
+    let output = install(&image);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = env.command(bulker_bin()).args(["exec", "bulker/pinned:1", "--", "tool"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("{} tool", image)));
+}
+
+#[test]
+fn test_exec_memoize_replays_identical_runs() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine(
+        "case \"$1\" in image) echo sha256:feed; exit 0;; esac\necho run >> \"$(dirname \"$0\")/runs\"\necho \"ran $*\"",
+    );
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let manifest = env.path().join("memo.yaml");
+    fs::write(&manifest, ManifestFixture::new("memo").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    fs::write(env.path().join("in.txt"), "v1").unwrap();
+    let run = || {
+        let output = env
+            .command(bulker_bin())
+            .current_dir(env.path())
+            .args(["exec", "--memoize", "bulker/memo:1", "--", "tool", "in.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let runs = || fs::read_to_string(env.path().join("runs")).unwrap().lines().count();
+
+    let first = run();
+    assert!(first.contains("ran "), "{}", first);
+    assert_eq!(run(), first);
+    assert_eq!(runs(), 1);
+
+    // Changed input contents run again
+    fs::write(env.path().join("in.txt"), "v2").unwrap();
+    run();
+    assert_eq!(runs(), 2);
+
+    // A replay cleans up after itself like a real run
+    let tmp = env.path().join("tmp");
+    fs::create_dir(&tmp).unwrap();
+    for _ in 0..2 {
+        let output = env
+            .command(bulker_bin())
+            .current_dir(env.path())
+            .env("TMPDIR", &tmp)
+            .args(["exec", "--memoize", "--isolate-home", "bulker/memo:1", "--", "tool", "in.txt"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    assert_eq!(runs(), 3);
+    let leftovers: Vec<_> = fs::read_dir(&tmp).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
+
+#[test]
+fn test_reap_containers_removes_containers_of_killed_runs() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\" >> \"$(dirname \"$0\")/calls\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let output = env.command(bulker_bin()).args(["config", "set", "reap_containers=true"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let manifest = env.path().join("reap.yaml");
+    fs::write(&manifest, ManifestFixture::new("reap").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A registration left by a run that was killed (no process has this pid)
+    let registrations = env.path().join("bulker").join("containers");
+    fs::create_dir_all(&registrations).unwrap();
+    fs::write(registrations.join(format!("{}.cid", i32::MAX)), "c0ffee\n").unwrap();
+
+    let output = env.command(bulker_bin()).args(["exec", "bulker/reap:1", "--", "tool"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let calls = fs::read_to_string(env.path().join("calls")).unwrap();
+    let mut lines = calls.lines();
+    assert_eq!(lines.next(), Some("rm --force c0ffee"), "{}", calls);
+    let run = lines.next().unwrap();
+    assert!(run.starts_with("run --rm --cidfile="), "{}", run);
+    assert!(run.contains("--label=bulker.pid="), "{}", run);
+    // Both the stale registration and the finished run's are gone
+    assert_eq!(fs::read_dir(&registrations).unwrap().count(), 0);
+}
+
+#[test]
+fn test_clean_containers_removes_orphans_from_this_host() {
+    let env = TestEnv::new();
+    let host = Command::new("uname").arg("-n").output().unwrap();
+    let host = String::from_utf8_lossy(&host.stdout).trim().to_string();
+    let engine = env.fake_engine(&format!(
+        "case \"$1\" in ps) printf 'dead1\\ttool\\t{max}\\t{host}\\nlive1\\ttool\\t1\\t{host}\\nelse1\\ttool\\t{max}\\tother-host\\n';; \
+         *) echo \"$*\" >> \"$(dirname \"$0\")/calls\";; esac",
+        max = i32::MAX,
+        host = host
+    ));
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+
+    let output = env.command(bulker_bin()).args(["clean", "--containers", "--dry-run"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Would remove dead1 (tool"), "{}", stdout);
+    assert!(!stdout.contains("live1") && !stdout.contains("else1"), "{}", stdout);
+    assert!(!env.path().join("calls").exists());
+
+    let output = env.command(bulker_bin()).args(["clean", "--containers"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read_to_string(env.path().join("calls")).unwrap(), "rm --force dead1\n");
+}
+
+#[test]
+fn test_cpu_binding_reaches_docker() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let manifest = env.path().join("cpus.yaml");
+    fs::write(
+        &manifest,
+        "manifest:\n  name: cpus\n  version: '1'\n  commands:\n  - command: tool\n    docker_image: org/tool:1\n  \
+         - command: pinned\n    docker_image: org/tool:1\n    cpu_binding: '4-5'\n",
+    )
+    .unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let run = |command: &str, slurm: bool| {
+        let mut cmd = env.command(bulker_bin());
+        cmd.env_remove("SLURM_CPUS_ON_NODE");
+        if slurm {
+            cmd.env("SLURM_CPUS_ON_NODE", "2");
+        }
+        let output = cmd.args(["exec", "bulker/cpus:1", "--", command]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    // Unset: no binding, even inside SLURM
+    assert!(!run("tool", true).contains("--cpu"), "{}", run("tool", true));
+
+    // auto: nothing outside SLURM, the job's CPUs inside it
+    let output = env.command(bulker_bin()).args(["config", "set", "cpu_binding=auto"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!run("tool", false).contains("--cpus"), "{}", run("tool", false));
+    let stdout = run("tool", true);
+    assert!(stdout.contains("--cpus=2") || stdout.contains("--cpuset-cpus="), "{}", stdout);
+
+    let output = env.command(bulker_bin()).args(["config", "set", "cpu_binding=0-1"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(run("tool", true).contains("--cpuset-cpus=0-1"));
+    // The command's own binding wins
+    assert!(run("pinned", false).contains("--cpuset-cpus=4-5"));
+
+    let output = env.command(bulker_bin()).args(["config", "set", "cpu_binding=cores"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid cpu_binding"));
+}
+
+#[test]
+fn test_path_map_translates_mounts_args_and_workdir() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let host = fs::canonicalize(env.path()).unwrap();
+    let host = host.to_str().unwrap();
+    let output = env.command(bulker_bin()).args(["config", "set", &format!("path_map+={}->/data", host)]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let manifest = env.path().join("map.yaml");
+    fs::write(&manifest, ManifestFixture::new("map").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    fs::write(env.path().join("in.txt"), "x").unwrap();
+    let output = env
+        .command(bulker_bin())
+        .current_dir(env.path())
+        .args(["exec", "bulker/map:1", "--", "tool", "in.txt"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains(&format!("--volume {}:/data ", host)), "{}", stdout);
+    assert!(stdout.contains("--workdir=/data "), "{}", stdout);
+    assert!(stdout.trim_end().ends_with("tool /data/in.txt"), "{}", stdout);
+}
+
+#[test]
+fn test_exec_cwd_runs_in_and_mounts_directory() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\"");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let manifest = env.path().join("cwd.yaml");
+    fs::write(&manifest, ManifestFixture::new("cwd").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let run = fs::canonicalize(env.path()).unwrap().join("run dir");
+    fs::create_dir(&run).unwrap();
+    fs::write(run.join("in.txt"), "x").unwrap();
+    let output = env
+        .command(bulker_bin())
+        .current_dir(env.path())
+        .args(["exec", "--cwd", "run dir", "bulker/cwd:1", "--", "tool", "in.txt"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains(&format!("--volume {}:{} ", run.display(), run.display())), "{}", stdout);
+    assert!(stdout.contains(&format!("--workdir={} ", run.display())), "{}", stdout);
+    assert!(stdout.trim_end().ends_with(&format!("tool {}/in.txt", run.display())), "{}", stdout);
+
+    let output = env.command(bulker_bin()).args(["exec", "--cwd", "/no/such/dir", "bulker/cwd:1", "--", "tool"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Working directory not found"));
+}
+
+#[test]
+fn test_cache_permissions_make_cache_group_writable() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    let gid = fs::metadata(tmp.path()).unwrap().gid();
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "set", "-c", config_path.to_str().unwrap(), &format!("cache_permissions=0664:{}", gid)])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    install_test_crate(&tmp, &config_path);
+
+    let tag_dir = tmp.path().join("bulker/manifests/bulker/test-crate/1.0.0");
+    let mode = |p: &std::path::Path| fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+    assert_eq!(mode(&tag_dir.join("manifest.yaml")), 0o664);
+    assert_eq!(mode(&tag_dir.join("installed-at")), 0o664);
+    assert_eq!(mode(&tag_dir), 0o2775);
+    assert_eq!(mode(&tmp.path().join("bulker/manifests")), 0o2775);
+    assert_eq!(fs::metadata(tag_dir.join("manifest.yaml")).unwrap().gid(), gid);
+
+    let output = bulker_cmd(tmp.path())
+        .args(["config", "get", "-c", config_path.to_str().unwrap(), "cache_permissions"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), format!("0664:{}", gid));
+}
+
+#[test]
+fn test_crate_rename_namespace_moves_crates_and_rewrites_imports() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest("oldorg/base:1", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    let app = ManifestFixture::new("app").command("cowsay", "nsheff/cowsay").import("oldorg/base:1");
+    registry.add_manifest("bulker/app:1", &app.to_yaml());
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()));
+    let output = env.command(bulker_bin()).args(["crate", "install", "bulker/app:1"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let rename = |extra: &[&str]| {
+        let output = env.command(bulker_bin()).args(["crate", "rename-namespace", "oldorg", "neworg"]).args(extra).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = rename(&["--dry-run"]);
+    assert!(stdout.contains("Would move: oldorg/base:1 -> neworg/base:1"), "{}", stdout);
+    assert!(stdout.contains("Would rewrite imports of bulker/app:1: oldorg/base:1 -> neworg/base:1"), "{}", stdout);
+    let stdout = rename(&[]);
+    assert!(stdout.contains("1 crate(s) moved, 1 manifest(s) rewritten"), "{}", stdout);
+
+    let output = env.command(bulker_bin()).args(["crate", "list"]).output().unwrap();
+    let list = String::from_utf8_lossy(&output.stdout);
+    assert!(list.contains("neworg/base") && !list.contains("oldorg"), "{}", list);
+    // The rewritten import resolves from the cache without the registry
+    drop(registry);
+    let output = env.command(bulker_bin()).args(["exec", "-p", "bulker/app:1", "--", "fortune"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("nsheff/fortune"));
+}
+
+#[test]
+fn test_cache_scope_project_keeps_manifests_in_the_checkout() {
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new());
+    let project = env.path().join("project");
+    fs::create_dir_all(project.join("sub")).unwrap();
+    let manifest = project.join("env.yaml");
+    fs::write(&manifest, ManifestFixture::new("projenv").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+
+    let output = env
+        .command(bulker_bin())
+        .current_dir(&project)
+        .args(["--cache-scope", "project", "crate", "install", "env.yaml"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(project.join(".bulker/manifests/bulker/projenv/1/manifest.yaml").is_file());
+
+    let list = |dir: &std::path::Path, scope: &[&str]| {
+        let output = env.command(bulker_bin()).current_dir(dir).args(scope).args(["crate", "list"]).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    assert!(!list(&project, &[]).contains("projenv"), "the user cache must not see project crates");
+    // Found from a subdirectory of the project too
+    assert!(list(&project.join("sub"), &["--cache-scope", "project"]).contains("projenv"));
+}
+
+#[test]
+fn test_crate_export_spack_wraps_commands_and_imports() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest("bulker/base:1", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    let app = ManifestFixture::new("app").command("cowsay", "nsheff/cowsay").import("bulker/base:1");
+    registry.add_manifest("bulker/app:1", &app.to_yaml());
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()));
+
+    // Fetched on demand, imports included
+    let output = env.command(bulker_bin()).args(["crate", "export-spack", "bulker/app:1"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let recipe = String::from_utf8_lossy(&output.stdout);
+    assert!(recipe.contains("class BulkerApp(Package):"), "{}", recipe);
+    assert!(recipe.contains("version(\"1\")"), "{}", recipe);
+    assert!(recipe.contains("\"cowsay\",") && recipe.contains("\"fortune\","), "{}", recipe);
+    assert!(recipe.contains("exec bulker exec bulker/app:1 --"), "{}", recipe);
+
+    let eb = env.path().join("bulker-app-1.eb");
+    let output = env
+        .command(bulker_bin())
+        .args(["crate", "export-spack", "bulker/app:1", "--format", "easybuild", "-o"])
+        .arg(&eb)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let easyconfig = fs::read_to_string(&eb).unwrap();
+    assert!(easyconfig.contains("'files': [\"bin/cowsay\", \"bin/fortune\"]"), "{}", easyconfig);
+}
+
+#[test]
+fn test_crate_wrappers_run_without_path_or_config_env() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo ran");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let manifest = env.path().join("wrapped.yaml");
+    fs::write(&manifest, ManifestFixture::new("wrapped").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let out = env.path().join("wrappers");
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("tool"), "mine").unwrap();
+    let wrappers = |extra: &[&str]| {
+        env.command(bulker_bin()).args(["crate", "wrappers", "bulker/wrapped:1", "--out"]).arg(&out).args(extra).output().unwrap()
+    };
+    // A file that is not a wrapper is kept unless --force
+    assert!(!wrappers(&[]).status.success());
+    assert_eq!(fs::read_to_string(out.join("tool")).unwrap(), "mine");
+    let output = wrappers(&["--force"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // Rewriting existing wrappers needs no --force
+    assert!(wrappers(&[]).status.success());
+
+    // The wrapper carries the config and the bulker path itself
+    let output = Command::new(out.join("tool"))
+        .env_clear()
+        .env("XDG_CACHE_HOME", env.path())
+        .env("BULKER_SKIP_ENGINE_CHECK", "1")
+        .env("PATH", "/usr/bin:/bin")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ran\n");
+}
+
+#[test]
+fn test_exec_prefix_output_labels_each_line() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo one; echo two; echo oops >&2; printf partial");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let manifest = env.path().join("labels.yaml");
+    fs::write(&manifest, ManifestFixture::new("labels").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = env
+        .command(bulker_bin())
+        .args(["exec", "--prefix-output", "bulker/labels:1", "--", "tool"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "tool | one\ntool | two\ntool | partial\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("tool | oops\n"));
+
+    // Without the flag output passes through untouched
+    let output = env.command(bulker_bin()).args(["exec", "bulker/labels:1", "--", "tool"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\npartial");
+}
+
+#[test]
+fn test_crate_install_images_only_pulls_without_fetching() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest("bulker/base:1", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    let app = ManifestFixture::new("app").command("cowsay", "nsheff/cowsay").import("bulker/base:1");
+    registry.add_manifest("bulker/app:1", &app.to_yaml());
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\" >> \"$(dirname \"$0\")/pulls\"");
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()).engine_path(&engine));
+    let install = |args: &[&str]| env.command(bulker_bin()).args(["crate", "install"]).args(args).output().unwrap();
+
+    // Not cached yet: nothing is fetched
+    let output = install(&["--images-only", "bulker/app:1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not cached"));
+
+    let output = install(&["bulker/app:1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let fetches = registry.requests().len();
+    let output = install(&["--images-only", "bulker/app:1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(registry.requests().len(), fetches, "manifests must come from the cache");
+    let pulls = fs::read_to_string(env.path().join("pulls")).unwrap();
+    assert!(pulls.contains("pull nsheff/cowsay") && pulls.contains("pull nsheff/fortune"), "{}", pulls);
+}
+
+#[test]
+fn test_activate_warns_when_engine_differs_from_build() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("exit 0");
+    let manifest = env.path().join("built.yaml");
+    fs::write(&manifest, ManifestFixture::new("built").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    env.write_config(&ConfigFixture::new().engine("apptainer").engine_path(&engine));
+    // Without an apptainer_image_folder, SIF files and their locks land in the working directory
+    let output = env
+        .command(bulker_bin())
+        .current_dir(env.path())
+        .args(["crate", "install", "-b", manifest.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let exec = || env.command(bulker_bin()).current_dir(env.path()).args(["exec", "-p", "bulker/built:1", "--", "tool"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&exec().stderr).to_string();
+    assert!(!stderr.contains("were pulled with"), "{}", stderr);
+
+    env.write_config(&ConfigFixture::new().engine("docker").engine_path(&engine));
+    let stderr = String::from_utf8_lossy(&exec().stderr).to_string();
+    assert!(stderr.contains("Images of 'bulker/built:1' were pulled with apptainer, but the configured engine is docker"), "{}", stderr);
+}
+
+#[test]
+fn test_config_restore_from_backup() {
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new());
+    let config = |args: &[&str]| env.command(bulker_bin()).arg("config").args(args).output().unwrap();
+    let output = config(&["set", "default_namespace=first"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = config(&["set", "default_namespace=second"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A corrupted config can still be restored
+    fs::write(env.config_path(), "bulker: [not, a, map\n").unwrap();
+    let output = config(&["restore", "--list"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1  "), "{}", String::from_utf8_lossy(&output.stdout));
+    let output = config(&["restore", "--from-backup"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = config(&["get", "default_namespace"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "first");
+    // The corrupted version became backup 1; backup 3 is the original
+    let output = config(&["restore", "--from-backup", "1"]);
+    assert!(!output.status.success(), "an invalid backup must not be restored");
+    let output = config(&["restore", "--from-backup", "3"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = config(&["get", "default_namespace"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "bulker");
+
+    let output = config(&["restore", "--from-backup", "9"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_exec_summary_and_fail_fast() {
+    let env = TestEnv::new();
+    let engine = env.fake_engine("echo \"$*\" >> \"$(dirname \"$0\")/runs\"\ncase \"$*\" in *fail*) exit 3;; esac");
+    env.write_config(&ConfigFixture::new().engine_path(&engine));
+    let manifest = env.path().join("ff.yaml");
+    fs::write(&manifest, ManifestFixture::new("ff").version("1").command("tool", "org/tool:1").to_yaml()).unwrap();
+    let output = env.command(bulker_bin()).args(["crate", "install", manifest.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let script = env.path().join("steps.sh");
+    fs::write(&script, "tool one\ntool fail\ntool two\n").unwrap();
+    // The engine is also asked for image digests; count container runs only
+    let runs = || fs::read_to_string(env.path().join("runs")).unwrap().lines().filter(|l| l.starts_with("run ")).count();
+
+    let output = env
+        .command(bulker_bin())
+        .args(["exec", "--summary", "bulker/ff:1", "--script", script.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stderr.contains("bulker: 3 container command(s), 1 failed"), "{}", stderr);
+    assert!(stderr.contains("failed: exit 3 (tool)"), "{}", stderr);
+    assert_eq!(runs(), 3);
+
+    let output = env
+        .command(bulker_bin())
+        .args(["exec", "--summary", "--fail-fast", "bulker/ff:1", "--script", script.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("bulker: 2 container command(s), 1 failed"), "{}", stderr);
+    assert_eq!(runs(), 5, "the step after the failure must not run");
+}
+
+#[test]
+fn test_completions_offer_cached_crates_and_exec_commands() {
+    let tmp = TempDir::new().unwrap();
+    let config_path = init_config(&tmp);
+    install_test_crate(&tmp, &config_path);
+
+    let output = bulker_cmd(tmp.path()).args(["completions", "--crates"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "bulker/test-crate:1.0.0\n");
+
+    let output = bulker_cmd(tmp.path())
+        .args(["completions", "--exec-commands", "-c", config_path.to_str().unwrap(), "bulker/test-crate:1.0.0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // host_commands complete too
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "cowsay\nfortune\nls\n");
+
+    // Drive the bash completion function the way readline would
+    let script = bulker_cmd(tmp.path()).args(["completions", "bash"]).output().unwrap();
+    let script_path = tmp.path().join("bulker.bash");
+    fs::write(&script_path, &script.stdout).unwrap();
+    let probe = format!(
+        "source {}\n\
+         t() {{ COMP_LINE=\"$1\"; COMP_POINT=${{#1}}; _bulker_dynamic bulker x y; echo \"${{COMPREPLY[*]}}\"; }}\n\
+         t 'bulker activate '\n\
+         t 'bulker crate inspect bulker/test-crate:'\n\
+         t 'bulker exec bulker/test-crate:1.0.0 -- co'\n",
+        script_path.display()
+    );
+    let bin_dir = bulker_bin().parent().unwrap().to_path_buf();
+    let output = Command::new("/bin/bash")
+        .args(["--norc", "-c", &probe])
+        .env("XDG_CONFIG_HOME", tmp.path())
+        .env("XDG_CACHE_HOME", tmp.path())
+        .env("PATH", format!("{}:{}", bin_dir.display(), std::env::var("PATH").unwrap_or_default()))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "bulker/test-crate:1.0.0\n1.0.0\ncowsay\n");
+}
+
+#[test]
+fn test_crate_export_import_bundle_offline() {
+    let registry = FakeRegistry::start();
+    registry.add_manifest(
+        "bulker/top:1.0",
+        &ManifestFixture::new("top").command("cowsay", "nsheff/cowsay").import("bulker/base:2").to_yaml(),
+    );
+    registry.add_manifest("bulker/base:2", &ManifestFixture::new("base").command("fortune", "nsheff/fortune").to_yaml());
+    let online = TestEnv::new();
+    // `save -o FILE IMAGE` writes the image name as the archive
+    let engine = online.fake_engine("if [ \"$1\" = save ]; then echo \"$4\" > \"$3\"; fi");
+    online.write_config(&ConfigFixture::new().engine_path(&engine).registry_url(&registry.url()));
+    let bundle = online.path().join("top.tar.gz");
+
+    let output = online
+        .command(bulker_bin())
+        .args(["crate", "export", "--images", "bulker/top:1.0", "-o", bundle.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Exported 2 crate(s), 2 image(s)"), "{}", stdout);
+
+    // The offline system has no registry
+    let offline = TestEnv::new();
+    let engine = offline.fake_engine("if [ \"$1\" = load ]; then cat \"$3\" >> \"$(dirname \"$0\")/loaded\"; fi");
+    offline.write_config(&ConfigFixture::new().engine_path(&engine).registry_url("http://127.0.0.1:1"));
+    let output = offline.command(bulker_bin()).args(["crate", "import", bundle.to_str().unwrap()]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Imported: bulker/top:1.0") && stdout.contains("Imported: bulker/base:2"), "{}", stdout);
+    assert!(stdout.contains("Loaded 2 image(s)"), "{}", stdout);
+    let loaded = fs::read_to_string(offline.path().join("loaded")).unwrap();
+    assert_eq!(loaded, "nsheff/cowsay\nnsheff/fortune\n");
+
+    let output = offline.command(bulker_bin()).args(["crate", "inspect", "bulker/top:1.0"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("cowsay"));
+
+    // Importing again changes nothing; a differing cached crate needs --force
+    let output = offline.command(bulker_bin()).args(["crate", "import", "--no-images", bundle.to_str().unwrap()]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Already cached: bulker/top:1.0"));
+    let changed = offline.path().join("changed.yaml");
+    fs::write(&changed, ManifestFixture::new("bulker/base").version("2").command("fortune", "nsheff/fortune:2").to_yaml()).unwrap();
+    let output = offline.command(bulker_bin()).args(["crate", "install", changed.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = offline.command(bulker_bin()).args(["crate", "import", "--no-images", bundle.to_str().unwrap()]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Use --force"), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = offline.command(bulker_bin()).args(["crate", "import", "--no-images", "--force", bundle.to_str().unwrap()]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Imported: bulker/base:2"));
+
+    // A bundle whose manifest lost its digest is refused
+    let unpacked = offline.path().join("unpacked");
+    fs::create_dir(&unpacked).unwrap();
+    assert!(Command::new("tar").arg("-xzf").arg(&bundle).arg("-C").arg(&unpacked).status().unwrap().success());
+    fs::remove_file(unpacked.join("manifests/bulker/base/2/crate-manifest-digest")).unwrap();
+    let stripped = offline.path().join("stripped.tar.gz");
+    assert!(Command::new("tar").arg("-czf").arg(&stripped).arg("-C").arg(&unpacked).arg(".").status().unwrap().success());
+    let output = offline.command(bulker_bin()).args(["crate", "import", "--force", stripped.to_str().unwrap()]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("missing the manifest digest of bulker/base:2"), "{}", stderr);
+}
+
+#[test]
+fn test_registry_urls_fall_back_in_order() {
+    let mirror = FakeRegistry::start();
+    mirror.add_manifest("bulker/mirrored:1", &ManifestFixture::new("mirrored").command("cowsay", "mirror/cowsay").to_yaml());
+    let hub = FakeRegistry::start();
+    hub.add_manifest("bulker/mirrored:1", &ManifestFixture::new("mirrored").command("cowsay", "hub/cowsay").to_yaml());
+    hub.add_manifest("bulker/hubonly:1", &ManifestFixture::new("hubonly").command("fortune", "hub/fortune").to_yaml());
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_urls(&[&mirror.url(), &hub.url()]));
+
+    let output = env.command(bulker_bin()).args(["crate", "install", "bulker/mirrored:1,bulker/hubonly:1"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains(&format!("Fetched bulker/mirrored:1 from {}", mirror.url())), "{}", stderr);
+    assert!(stderr.contains(&format!("Fetched bulker/hubonly:1 from {}", hub.url())), "{}", stderr);
+    // The first registry that has a crate wins
+    let output = env.command(bulker_bin()).args(["crate", "inspect", "--raw", "bulker/mirrored:1"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("mirror/cowsay"));
+    assert!(!hub.requests().contains(&"/bulker/mirrored_1.yaml".to_string()), "{:?}", hub.requests());
+
+    let output = env.command(bulker_bin()).args(["crate", "install", "bulker/nowhere:1"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("'bulker/nowhere:1' was not found in any registry"), "{}", stderr);
+    assert!(stderr.contains(&mirror.url()) && stderr.contains(&hub.url()), "{}", stderr);
+
+    // A broken manifest is the mirror's answer, not a reason to ask the hub
+    mirror.add_manifest("bulker/broken:1", "manifest: [not, a, crate");
+    hub.add_manifest("bulker/broken:1", &ManifestFixture::new("broken").command("cowsay", "hub/cowsay").to_yaml());
+    let output = env.command(bulker_bin()).args(["crate", "install", "bulker/broken:1"]).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Failed to parse manifest"), "{}", stderr);
+    assert!(!hub.requests().contains(&"/bulker/broken_1.yaml".to_string()), "{:?}", hub.requests());
+}
+
+#[test]
+fn test_crate_publish_uploads_manifest_with_digest_and_token() {
+    let registry = FakeRegistry::start();
+    let env = TestEnv::new();
+    env.write_config(&ConfigFixture::new().registry_url(&registry.url()));
+    for setting in [format!("publish_url={}", registry.url()), "publish_token=env:TEST_PUBLISH_TOKEN".to_string()] {
+        let output = env.command(bulker_bin()).args(["config", "set", &setting]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let cratefile = env.path().join("demo.yaml");
+    let yaml = ManifestFixture::new("databio/demo").version("2").command("cowsay", "nsheff/cowsay:1").to_yaml();
+    std::fs::write(&cratefile, &yaml).unwrap();
+
+    let output = env.command(bulker_bin())
+        .args(["crate", "publish", "--dry-run", cratefile.to_str().unwrap(), "databio/demo:2"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Would publish to") && registry.uploads().is_empty(), "{}", stdout);
+
+    let output = env.command(bulker_bin())
+        .args(["crate", "publish", cratefile.to_str().unwrap(), "databio/demo:2"])
+        .env("TEST_PUBLISH_TOKEN", "s3cret")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Published databio/demo:2 to"), "{}", stdout);
+    let uploads = registry.uploads();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0].path, "/databio/demo_2.yaml");
+    assert_eq!(uploads[0].body, yaml);
+    assert_eq!(uploads[0].headers.get("authorization").map(|s| s.as_str()), Some("Bearer s3cret"));
+    let digest = uploads[0].headers.get("x-bulker-crate-manifest-digest").expect("no digest header");
+    assert!(stdout.contains(digest.as_str()), "{}", stdout);
+
+    // What was published installs like any registry crate
+    let output = env.command(bulker_bin()).args(["crate", "install", "databio/demo:2"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Refused before anything is sent
+    let output = env.command(bulker_bin())
+        .args(["crate", "publish", cratefile.to_str().unwrap(), "databio/other:2"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is named 'databio/demo'"));
+    let local = env.path().join("local.yaml");
+    std::fs::write(&local, ManifestFixture::new("local").import("./demo.yaml").command("ls", "ubuntu").to_yaml()).unwrap();
+    let output = env.command(bulker_bin())
+        .args(["crate", "publish", local.to_str().unwrap(), "databio/local:1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is a file"));
+    assert_eq!(registry.uploads().len(), 1);
+}